@@ -170,14 +170,14 @@ pub static NUM_BLOCKS_IN_TREE: Lazy<IntGauge> = Lazy::new(|| {
 //////////////////////
 // PERFORMANCE COUNTERS
 //////////////////////
-// TODO Consider reintroducing this counter
-// pub static UNWRAPPED_PROPOSAL_SIZE_BYTES: Lazy<Histogram> = Lazy::new(|| {
-//     register_histogram!(
-//         "libra_consensus_unwrapped_proposal_size_bytes",
-//         "Histogram of proposal size after LCS but before wrapping with GRPC and libra net."
-//     )
-//     .unwrap()
-// });
+/// Histogram of proposal message size after LCS but before wrapping with libra net.
+pub static PROPOSAL_SIZE_BYTES: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "libra_consensus_proposal_size_bytes",
+        "Histogram of proposal size after LCS but before wrapping with libra net."
+    )
+    .unwrap()
+});
 
 /// Histogram for the number of txns per (committed) blocks.
 pub static NUM_TXNS_PER_BLOCK: Lazy<Histogram> = Lazy::new(|| {
@@ -121,11 +121,13 @@ impl NetworkSender {
     /// as well as there is no indication about the network failures.
     pub async fn broadcast_proposal(&mut self, proposal: ProposalMsg) {
         let msg = ConsensusMsg::ProposalMsg(Box::new(proposal));
-        // counters::UNWRAPPED_PROPOSAL_SIZE_BYTES.observe(msg.message.len() as f64);
-        self.broadcast(msg).await
+        if let Some(size) = self.broadcast(msg).await {
+            counters::PROPOSAL_SIZE_BYTES.observe(size as f64);
+        }
     }
 
-    async fn broadcast(&mut self, msg: ConsensusMsg) {
+    /// Returns the size of the message serialized for other validators, None if it was not sent
+    async fn broadcast(&mut self, msg: ConsensusMsg) -> Option<usize> {
         // Directly send the message to ourself without going through network.
         let self_msg = Event::Message((self.author, msg.clone()));
         if let Err(err) = self.self_sender.send(Ok(self_msg)).await {
@@ -141,8 +143,12 @@ impl NetworkSender {
             .filter(|author| author != &self_author);
 
         // Broadcast message over direct-send to all other validators.
-        if let Err(err) = self.network_sender.send_to_many(other_validators, msg) {
-            error!("Error broadcasting message: {:?}", err);
+        match self.network_sender.send_to_many(other_validators, msg) {
+            Ok(size) => Some(size),
+            Err(err) => {
+                error!("Error broadcasting message: {:?}", err);
+                None
+            }
         }
     }
 
@@ -175,7 +181,7 @@ impl NetworkSender {
     /// Broadcasts vote message to all validators
     pub async fn broadcast_vote(&mut self, vote_msg: VoteMsg) {
         let msg = ConsensusMsg::VoteMsg(Box::new(vote_msg));
-        self.broadcast(msg).await
+        self.broadcast(msg).await;
     }
 
     /// Sends the given sync info to the given author.
@@ -196,7 +202,7 @@ impl NetworkSender {
     /// when we commit the reconfiguration block
     pub async fn broadcast_epoch_change(&mut self, proof: EpochChangeProof) {
         let msg = ConsensusMsg::EpochChangeProof(Box::new(proof));
-        self.broadcast(msg).await
+        self.broadcast(msg).await;
     }
 
     pub async fn notify_epoch_change(&mut self, proof: EpochChangeProof) {
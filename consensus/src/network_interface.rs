@@ -115,13 +115,13 @@ impl ConsensusNetworkSender {
     }
 
     /// Send a single message to the destination peers using the `CONSENSUS_DIRECT_SEND_PROTOCOL`
-    /// ProtocolId.
+    /// ProtocolId. Returns the size of the serialized message.
     #[inject_error(probability = 0.05)]
     pub fn send_to_many(
         &mut self,
         recipients: impl Iterator<Item = PeerId>,
         message: ConsensusMsg,
-    ) -> Result<(), NetworkError> {
+    ) -> Result<usize, NetworkError> {
         let protocol = ProtocolId::ConsensusDirectSend;
         self.network_sender
             .send_to_many(recipients, protocol, message)
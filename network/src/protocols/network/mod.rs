@@ -244,18 +244,20 @@ impl<TMessage: Message> NetworkSender<TMessage> {
     }
 
     /// Send a protobuf message to a many recipients. Provides a wrapper over
-    /// `[peer_manager::PeerManagerRequestSender::send_to_many]`.
+    /// `[peer_manager::PeerManagerRequestSender::send_to_many]`. Returns the size of the
+    /// serialized message.
     pub fn send_to_many(
         &mut self,
         recipients: impl Iterator<Item = PeerId>,
         protocol: ProtocolId,
         message: TMessage,
-    ) -> Result<(), NetworkError> {
+    ) -> Result<usize, NetworkError> {
         // Serialize message.
-        let mdata = lcs::to_bytes(&message)?.into();
+        let mdata = lcs::to_bytes(&message)?;
+        let size = mdata.len();
         self.peer_mgr_reqs_tx
-            .send_to_many(recipients, protocol, mdata)?;
-        Ok(())
+            .send_to_many(recipients, protocol, mdata.into())?;
+        Ok(size)
     }
 
     /// Send a protobuf rpc request to a single recipient while handling
@@ -0,0 +1,203 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Submission rate of an endpoint adapted to back-pressure of its mempool. Mempool-full and
+/// too-many-transactions rejections halve the rate, while submissions it accepts raise it by a
+/// fixed step, like TCP congestion control. Rate the endpoint oscillates around once it pushed
+/// back is the capacity it can take, rather than the number of rejections of an unpaced emitter
+use libra_json_rpc_types::errors::{JsonRpcError, ServerCode};
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Rate of each endpoint at the start of a job, doubled until the endpoint first pushes back
+const INITIAL_TPS: f64 = 100.0;
+const MIN_TPS: f64 = 10.0;
+const INCREASE_TPS: f64 = 20.0;
+const DECREASE_FACTOR: f64 = 0.5;
+/// Rate is changed at most once per interval, so that a burst of rejections of submissions
+/// which were in flight together counts as one signal
+const ADJUST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Mempool rejections which mean that the endpoint takes transactions slower than they come,
+/// and the same transactions would be accepted later
+pub fn is_backpressure(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<JsonRpcError>().map_or(false, |e| {
+        e.code == ServerCode::MempoolIsFull as i16
+            || e.code == ServerCode::MempoolTooManyTransactions as i16
+    })
+}
+
+/// Shared by all workers submitting to the endpoint
+pub struct AimdRate {
+    state: Mutex<State>,
+}
+
+struct State {
+    tps: f64,
+    /// Earliest time next submission may be sent
+    next_slot: Instant,
+    /// Set once a submission had to wait, rate is only raised while it limits submissions
+    throttled: bool,
+    slow_start: bool,
+    last_adjust: Instant,
+    decreases: u64,
+    /// Time of the first back-pressure, and integral of rate over time since then up to
+    /// `tps_since`
+    converging_since: Option<Instant>,
+    tps_since: Instant,
+    tps_integral: f64,
+}
+
+/// Controller state at the time it was taken
+#[derive(Clone, Copy, Debug)]
+pub struct AimdSnapshot {
+    pub tps: f64,
+    pub decreases: u64,
+    /// Time-weighted average rate since the endpoint first pushed back, None if it never did,
+    /// in which case its capacity was not reached
+    pub converged_tps: Option<f64>,
+}
+
+impl AimdRate {
+    pub fn new() -> Self {
+        Self::new_at(Instant::now())
+    }
+
+    fn new_at(now: Instant) -> Self {
+        Self {
+            state: Mutex::new(State {
+                tps: INITIAL_TPS,
+                next_slot: now,
+                throttled: false,
+                slow_start: true,
+                last_adjust: now,
+                decreases: 0,
+                converging_since: None,
+                tps_since: now,
+                tps_integral: 0.0,
+            }),
+        }
+    }
+
+    /// Reserves slot for `txns` transactions and returns how long to wait until it
+    pub fn delay(&self, txns: usize) -> Duration {
+        self.delay_at(txns, Instant::now())
+    }
+
+    fn delay_at(&self, txns: usize, now: Instant) -> Duration {
+        let mut state = self.state.lock().expect("aimd lock poisoned");
+        let slot = state.next_slot.max(now);
+        state.next_slot = slot + Duration::from_secs_f64(txns as f64 / state.tps);
+        if slot > now {
+            state.throttled = true;
+        }
+        slot - now
+    }
+
+    /// Adjusts rate to the response of the endpoint to a submission
+    pub fn on_response(&self, accepted: u64, backpressured: u64) {
+        self.on_response_at(accepted, backpressured, Instant::now())
+    }
+
+    fn on_response_at(&self, accepted: u64, backpressured: u64, now: Instant) {
+        let mut state = self.state.lock().expect("aimd lock poisoned");
+        if now.saturating_duration_since(state.last_adjust) < ADJUST_INTERVAL {
+            return;
+        }
+        let tps = if backpressured > 0 {
+            state.slow_start = false;
+            state.decreases += 1;
+            state.converging_since.get_or_insert(now);
+            (state.tps * DECREASE_FACTOR).max(MIN_TPS)
+        } else if accepted > 0 && state.throttled {
+            state.throttled = false;
+            if state.slow_start {
+                state.tps * 2.0
+            } else {
+                state.tps + INCREASE_TPS
+            }
+        } else {
+            return;
+        };
+        state.set_tps(tps, now);
+        state.last_adjust = now;
+    }
+
+    pub fn snapshot(&self) -> AimdSnapshot {
+        self.snapshot_at(Instant::now())
+    }
+
+    fn snapshot_at(&self, now: Instant) -> AimdSnapshot {
+        let state = self.state.lock().expect("aimd lock poisoned");
+        let converged_tps = state.converging_since.and_then(|since| {
+            let window = now.saturating_duration_since(since).as_secs_f64();
+            if window > 0.0 {
+                let current =
+                    state.tps * now.saturating_duration_since(state.tps_since).as_secs_f64();
+                Some((state.tps_integral + current) / window)
+            } else {
+                None
+            }
+        });
+        AimdSnapshot {
+            tps: state.tps,
+            decreases: state.decreases,
+            converged_tps,
+        }
+    }
+}
+
+impl State {
+    fn set_tps(&mut self, tps: f64, now: Instant) {
+        if let Some(converging_since) = self.converging_since {
+            let since = self.tps_since.max(converging_since);
+            self.tps_integral += self.tps * now.saturating_duration_since(since).as_secs_f64();
+        }
+        self.tps = tps;
+        self.tps_since = now;
+    }
+}
+
+impl Default for AimdRate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_aimd_rate() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let rate = AimdRate::new_at(start);
+        // Rate is only raised while it holds submissions back
+        rate.on_response_at(50, 0, at(1));
+        assert!((rate.snapshot_at(at(1)).tps - INITIAL_TPS).abs() < 1e-9);
+        assert_eq!(rate.delay_at(100, at(1)), Duration::from_secs(0));
+        assert_eq!(rate.delay_at(100, at(1)), Duration::from_secs(1));
+        rate.on_response_at(100, 0, at(2));
+        assert!((rate.snapshot_at(at(2)).tps - 2.0 * INITIAL_TPS).abs() < 1e-9);
+        assert!(rate.snapshot_at(at(2)).converged_tps.is_none());
+
+        // Back-pressure halves the rate, after which it grows additively
+        rate.on_response_at(0, 10, at(3));
+        assert!((rate.snapshot_at(at(3)).tps - INITIAL_TPS).abs() < 1e-9);
+        // Rejections of submissions in flight together are one signal
+        rate.on_response_at(0, 10, at(3));
+        assert_eq!(rate.snapshot_at(at(3)).decreases, 1);
+        rate.delay_at(1000, at(4));
+        rate.delay_at(1, at(4));
+        rate.on_response_at(1, 0, at(5));
+        let snapshot = rate.snapshot_at(at(7));
+        assert!((snapshot.tps - (INITIAL_TPS + INCREASE_TPS)).abs() < 1e-9);
+        // 100 TPS for 2 secs and 120 TPS for 2 secs since first back-pressure
+        assert!((snapshot.converged_tps.unwrap() - 110.0).abs() < 1e-9);
+    }
+}
@@ -100,6 +100,10 @@ impl Sub for &AtomicHistogramSnapshot {
 }
 
 impl AtomicHistogramSnapshot {
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
     pub fn percentile(&self, numerator: u64, denominator: u64) -> u64 {
         let committed: u64 = self.buckets.iter().sum();
         let p_count = committed * numerator / denominator;
@@ -174,6 +178,34 @@ mod test {
             expired: 0,
             latency: 0,
             latency_buckets: histogram.snapshot(),
+            ack_latency: 0,
+            ack_latency_samples: 0,
+            ack_latency_buckets: Default::default(),
+            ack_to_commit_latency: 0,
+            ack_to_commit_latency_buckets: Default::default(),
+            minute_latencies: vec![],
+            commit_latency: 0,
+            commit_latency_samples: 0,
+            admin_submitted: 0,
+            admin_committed: 0,
+            gas: Default::default(),
+            clock_offsets: Default::default(),
+            groups: Default::default(),
+            retries: 0,
+            failovers: 0,
+            unreachable_target_tps: None,
+            setup_duration: Default::default(),
+            submit_errors: 0,
+            local_submit_errors: 0,
+            scheduling_lag: 0,
+            scheduling_lag_samples: 0,
+            emitter_cpu_time: Default::default(),
+            emitter_wall_time: Default::default(),
+            topups: 0,
+            endpoints: Default::default(),
+            storm_resubmitted: 0,
+            storm_duplicates_rejected: 0,
+            accounts: Default::default(),
         };
         let res = stat.latency_buckets.percentile(9, 10);
         assert_eq!(res, 900);
@@ -0,0 +1,58 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Audit log of everything cluster test does to the cluster: commands run on instances,
+/// effects applied and cluster swarm calls, one timestamped line each. When a run damages a
+/// shared cluster, the log shows what was done to it and when
+use anyhow::{format_err, Result};
+use chrono::Utc;
+use libra_logger::warn;
+use once_cell::sync::Lazy;
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+static AUDIT_LOG: Lazy<Mutex<Option<(PathBuf, File)>>> = Lazy::new(|| Mutex::new(None));
+
+/// Actions are not recorded anywhere until this is called
+pub fn init(path: &Path) -> Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format_err!("Failed to open audit log {:?}: {}", path, e))?;
+    *AUDIT_LOG.lock().expect("audit log lock poisoned") = Some((path.to_path_buf(), file));
+    Ok(())
+}
+
+pub fn path() -> Option<PathBuf> {
+    AUDIT_LOG
+        .lock()
+        .expect("audit log lock poisoned")
+        .as_ref()
+        .map(|(path, _)| path.clone())
+}
+
+/// Subject is what is acted on, usually peer name of an instance
+pub fn record<A: fmt::Display>(kind: &str, subject: &str, action: A) {
+    let mut log = AUDIT_LOG.lock().expect("audit log lock poisoned");
+    if let Some((_, file)) = log.as_mut() {
+        let action = action.to_string().replace('\n', "\\n");
+        let line = format!(
+            "{} {} {}: {}",
+            Utc::now().to_rfc3339(),
+            kind,
+            subject,
+            action
+        );
+        if let Err(e) = writeln!(file, "{}", line) {
+            warn!("Failed to write audit log: {}", e);
+        }
+    }
+}
@@ -7,6 +7,7 @@ use anyhow::{bail, format_err, Result};
 use libra_logger::{info, warn};
 use rusoto_autoscaling::{
     AutoScalingGroupNamesType, Autoscaling, AutoscalingClient, SetDesiredCapacityType,
+    TerminateInstanceInAutoScalingGroupType,
 };
 use rusoto_core::Region;
 use rusoto_sts::WebIdentityProvider;
@@ -126,3 +127,35 @@ pub async fn set_asg_size(
     })
     .await
 }
+
+/// Terminates given instances of an autoscaling group and lowers its desired capacity by the
+/// same amount, so that the group does not replace them
+pub async fn terminate_asg_instances(instance_ids: &[String]) -> Result<()> {
+    let credentials_provider = WebIdentityProvider::from_k8s_env();
+    let dispatcher = rusoto_core::HttpClient::new().expect("failed to create request dispatcher");
+    let asc = AutoscalingClient::new_with(dispatcher, credentials_provider, Region::UsWest2);
+    for instance_id in instance_ids {
+        info!("Terminating {}", instance_id);
+        let request = TerminateInstanceInAutoScalingGroupType {
+            instance_id: instance_id.clone(),
+            should_decrement_desired_capacity: true,
+        };
+        libra_retrier::retry_async(libra_retrier::fixed_retry_strategy(10_000, 6), || {
+            let asc = asc.clone();
+            let request = request.clone();
+            Box::pin(async move {
+                asc.terminate_instance_in_auto_scaling_group(request)
+                    .await
+                    .map_err(|e| {
+                        warn!(
+                            "terminate_instance_in_auto_scaling_group failed: {}, retrying",
+                            e
+                        );
+                        format_err!("terminate_instance_in_auto_scaling_group failed: {}", e)
+                    })
+            })
+        })
+        .await?;
+    }
+    Ok(())
+}
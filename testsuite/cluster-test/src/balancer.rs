@@ -0,0 +1,277 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Policies distributing submissions of emitter workers over endpoints of their target group.
+/// With the static policy every worker submits to the endpoint it was assigned, so each node
+/// gets a fixed share of load however fast it takes it. Other policies pick an endpoint for
+/// every submission, which lets faster nodes take more and shows how much each of them can take
+use crate::retrying_client::{Endpoint, RetryStats, RetryingClient};
+use anyhow::{bail, Result};
+use libra_types::{account_address::AccountAddress, transaction::SignedTransaction};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use tokio::task::JoinHandle;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BalancingPolicy {
+    /// Each worker submits to its own endpoint, failing over to others only while it is down
+    Static,
+    RoundRobin,
+    /// Endpoint with fewest submissions in flight, which is the one answering fastest
+    LeastInflight,
+    /// Round robin proportional to capacity of targets
+    Weighted,
+    /// Transactions of an account always go to the same endpoint, so that its sequence numbers
+    /// do not race through different mempools
+    Sticky,
+}
+
+impl FromStr for BalancingPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "static" => Ok(BalancingPolicy::Static),
+            "round-robin" => Ok(BalancingPolicy::RoundRobin),
+            "least-inflight" => Ok(BalancingPolicy::LeastInflight),
+            "weighted" => Ok(BalancingPolicy::Weighted),
+            "sticky" => Ok(BalancingPolicy::Sticky),
+            _ => bail!(
+                "Unknown balancing policy {}, expected static, round-robin, least-inflight, weighted or sticky",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for BalancingPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BalancingPolicy::Static => write!(f, "static"),
+            BalancingPolicy::RoundRobin => write!(f, "round-robin"),
+            BalancingPolicy::LeastInflight => write!(f, "least-inflight"),
+            BalancingPolicy::Weighted => write!(f, "weighted"),
+            BalancingPolicy::Sticky => write!(f, "sticky"),
+        }
+    }
+}
+
+struct BalancedEndpoint {
+    name: String,
+    endpoint: Arc<Endpoint>,
+    /// Capacity of the target, weighted policy sends load proportional to it
+    weight: u64,
+    inflight: Arc<AtomicU64>,
+}
+
+/// Shared by all workers of a target group
+pub struct Balancer {
+    policy: BalancingPolicy,
+    endpoints: Vec<BalancedEndpoint>,
+    total_weight: u64,
+    /// Number of submissions routed so far, round robin policies cycle by it
+    next: AtomicU64,
+}
+
+/// Transactions of a submission which go to one endpoint
+pub struct Route {
+    pub name: String,
+    pub client: RetryingClient,
+    pub txns: Vec<SignedTransaction>,
+    /// Submissions in flight to the endpoint, not tracked without a balancer
+    inflight: Option<Arc<AtomicU64>>,
+}
+
+impl Balancer {
+    /// Endpoints are given with name and capacity of their targets
+    pub fn new(policy: BalancingPolicy, endpoints: Vec<(String, Arc<Endpoint>, u64)>) -> Self {
+        assert!(!endpoints.is_empty(), "Balancer needs an endpoint");
+        let endpoints: Vec<_> = endpoints
+            .into_iter()
+            .map(|(name, endpoint, weight)| BalancedEndpoint {
+                name,
+                endpoint,
+                weight: weight.max(1),
+                inflight: Arc::new(AtomicU64::new(0)),
+            })
+            .collect();
+        let total_weight = endpoints.iter().map(|e| e.weight).sum();
+        Self {
+            policy,
+            endpoints,
+            total_weight,
+            next: AtomicU64::new(0),
+        }
+    }
+
+    /// Splits `txns` by endpoint they are submitted to. Only sticky policy splits a submission,
+    /// the others send all of it to one endpoint, so that batch requests stay whole
+    pub fn route(&self, txns: &[SignedTransaction], retry_stats: &Arc<RetryStats>) -> Vec<Route> {
+        let mut routes: BTreeMap<usize, Vec<SignedTransaction>> = BTreeMap::new();
+        if self.policy == BalancingPolicy::Sticky {
+            for txn in txns {
+                routes
+                    .entry(self.pick(&txn.sender()))
+                    .or_default()
+                    .push(txn.clone());
+            }
+        } else if let Some(txn) = txns.first() {
+            routes.insert(self.pick(&txn.sender()), txns.to_vec());
+        }
+        routes
+            .into_iter()
+            .map(|(index, txns)| {
+                let endpoint = &self.endpoints[index];
+                Route {
+                    name: endpoint.name.clone(),
+                    client: RetryingClient::new(
+                        vec![endpoint.endpoint.clone()],
+                        retry_stats.clone(),
+                    ),
+                    txns,
+                    inflight: Some(endpoint.inflight.clone()),
+                }
+            })
+            .collect()
+    }
+
+    /// Index of endpoint preferred by the policy, or the next available one if circuit breaker
+    /// of the preferred one is open
+    fn pick(&self, sender: &AccountAddress) -> usize {
+        let count = self.endpoints.len();
+        let next = self.next.fetch_add(1, Ordering::Relaxed);
+        let preferred = match self.policy {
+            BalancingPolicy::Static | BalancingPolicy::RoundRobin => (next % count as u64) as usize,
+            BalancingPolicy::LeastInflight => {
+                // Ties go round robin, so that idle endpoints all get load
+                let start = (next % count as u64) as usize;
+                (0..count)
+                    .map(|i| (start + i) % count)
+                    .filter(|i| self.endpoints[*i].endpoint.is_available())
+                    .min_by_key(|i| self.endpoints[*i].inflight.load(Ordering::Relaxed))
+                    .unwrap_or(start)
+            }
+            BalancingPolicy::Weighted => {
+                let mut slot = next % self.total_weight;
+                self.endpoints
+                    .iter()
+                    .position(|e| {
+                        if slot < e.weight {
+                            true
+                        } else {
+                            slot -= e.weight;
+                            false
+                        }
+                    })
+                    .expect("Slot is below total weight")
+            }
+            BalancingPolicy::Sticky => {
+                let mut hasher = DefaultHasher::new();
+                sender.hash(&mut hasher);
+                (hasher.finish() % count as u64) as usize
+            }
+        };
+        (0..count)
+            .map(|i| (preferred + i) % count)
+            .find(|i| self.endpoints[*i].endpoint.is_available())
+            .unwrap_or(preferred)
+    }
+}
+
+impl Route {
+    /// Route to own endpoint of a worker which does not balance load
+    pub fn new(name: String, client: RetryingClient, txns: Vec<SignedTransaction>) -> Self {
+        Self {
+            name,
+            client,
+            txns,
+            inflight: None,
+        }
+    }
+
+    /// Submission stops being in flight when it completes or is dropped, e.g. on timeout
+    pub async fn submit(&self) -> Result<Vec<Result<()>>> {
+        let _inflight = self.inflight.clone().map(InflightGuard::new);
+        self.client.submit_transactions(self.txns.clone()).await
+    }
+
+    /// Submission runs to completion even if the returned handle is dropped, like a request
+    /// whose client stopped waiting for the response
+    pub fn spawn_submit(&self) -> JoinHandle<Result<Vec<Result<()>>>> {
+        let inflight = self.inflight.clone().map(InflightGuard::new);
+        let client = self.client.clone();
+        let txns = self.txns.clone();
+        tokio::spawn(async move {
+            let _inflight = inflight;
+            client.submit_transactions(txns).await
+        })
+    }
+}
+
+struct InflightGuard(Arc<AtomicU64>);
+
+impl InflightGuard {
+    fn new(inflight: Arc<AtomicU64>) -> Self {
+        inflight.fetch_add(1, Ordering::Relaxed);
+        Self(inflight)
+    }
+}
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libra_json_rpc_client::JsonRpcAsyncClient;
+    use reqwest::Url;
+
+    fn balancer(policy: BalancingPolicy, weights: &[u64]) -> Balancer {
+        let url = Url::parse("http://localhost:8080").unwrap();
+        let endpoints = weights
+            .iter()
+            .enumerate()
+            .map(|(i, weight)| {
+                let endpoint = Endpoint::new(JsonRpcAsyncClient::new(url.clone()));
+                (format!("val-{}", i), endpoint, *weight)
+            })
+            .collect();
+        Balancer::new(policy, endpoints)
+    }
+
+    #[test]
+    fn test_pick() {
+        let sender = AccountAddress::random();
+        let round_robin = balancer(BalancingPolicy::RoundRobin, &[1, 1, 1]);
+        let picks: Vec<_> = (0..4).map(|_| round_robin.pick(&sender)).collect();
+        assert_eq!(picks, vec![0, 1, 2, 0]);
+
+        let weighted = balancer(BalancingPolicy::Weighted, &[1, 3]);
+        let picks: Vec<_> = (0..8).map(|_| weighted.pick(&sender)).collect();
+        assert_eq!(picks.iter().filter(|i| **i == 1).count(), 6);
+
+        let least_inflight = balancer(BalancingPolicy::LeastInflight, &[1, 1]);
+        least_inflight.endpoints[0]
+            .inflight
+            .store(2, Ordering::Relaxed);
+        assert_eq!(least_inflight.pick(&sender), 1);
+        assert_eq!(least_inflight.pick(&sender), 1);
+
+        let sticky = balancer(BalancingPolicy::Sticky, &[1, 1, 1]);
+        let first = sticky.pick(&sender);
+        assert!((0..10).all(|_| sticky.pick(&sender) == first));
+    }
+}
@@ -0,0 +1,223 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Bisection of builds between a good and a bad image tag, to find the first build which
+/// regressed a metric. Tags are expected to be `<prefix><commit>`, e.g. `dev_1a2b3c4d`, so that
+/// tags of builds in between can be derived from commit history
+use crate::{
+    github::GitHub,
+    significance::{Comparison, Test},
+};
+use anyhow::{bail, format_err, Result};
+use std::collections::HashSet;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct BisectParams {
+    #[structopt(
+        long,
+        group = "action",
+        requires_all = &["bisect-bad", "bisect-threshold"],
+        help = "Image tag of a build without the regression, bisection runs from it to --bisect-bad"
+    )]
+    pub bisect_good: Option<String>,
+    #[structopt(long, help = "Image tag of a build with the regression")]
+    pub bisect_bad: Option<String>,
+    #[structopt(
+        long,
+        default_value = "bench",
+        help = "Experiment run on every build, its params can be passed after --"
+    )]
+    pub bisect_experiment: String,
+    #[structopt(
+        long,
+        default_value = "avg_tps",
+        help = "Metric of the experiment to compare"
+    )]
+    pub bisect_metric: String,
+    #[structopt(long, help = "Builds where metric is worse than this are bad")]
+    pub bisect_threshold: Option<f64>,
+    #[structopt(long, help = "Metric is worse when it is higher, e.g. for latency")]
+    pub bisect_lower_is_better: bool,
+    #[structopt(
+        long,
+        default_value = "1",
+        help = "Runs of the experiment on every build. If more than 1, the good build is measured as well and a build is only bad if it also differs from it significantly"
+    )]
+    pub bisect_repeats: usize,
+    #[structopt(
+        long,
+        default_value = "0.95",
+        help = "Confidence at which a difference from the good build is significant"
+    )]
+    pub bisect_confidence: f64,
+    #[structopt(
+        long,
+        default_value = "welch",
+        help = "Significance test, welch or mann-whitney"
+    )]
+    pub bisect_test: Test,
+}
+
+impl BisectParams {
+    /// Mean of `samples` is worse than the threshold, and if both builds were measured
+    /// repeatedly, the difference from `good` is not noise
+    pub fn is_regression(&self, samples: &[f64], threshold: f64, good: &[f64]) -> bool {
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        let worse = if self.bisect_lower_is_better {
+            mean > threshold
+        } else {
+            mean < threshold
+        };
+        match Comparison::new(good, samples, self.bisect_test, self.bisect_confidence) {
+            Some(comparison) if worse => comparison.significant(),
+            _ => worse,
+        }
+    }
+}
+
+pub struct Bisection {
+    /// Ordered from good to bad
+    tags: Vec<String>,
+    /// Index of last known good tag
+    good: usize,
+    /// Index of first known bad tag
+    bad: usize,
+    /// Tags which could not be measured, e.g. because their image was not built
+    skipped: HashSet<usize>,
+}
+
+impl Bisection {
+    pub fn new(tags: Vec<String>) -> Self {
+        assert!(tags.len() >= 2, "Bisection needs a good and a bad tag");
+        Self {
+            good: 0,
+            bad: tags.len() - 1,
+            tags,
+            skipped: HashSet::new(),
+        }
+    }
+
+    /// Index of untested tag closest to the middle of the remaining range, none once the range
+    /// can not be narrowed further
+    pub fn next(&self) -> Option<usize> {
+        let middle = (self.good + self.bad) / 2;
+        (self.good + 1..self.bad)
+            .filter(|i| !self.skipped.contains(i))
+            .min_by_key(|i| (*i as isize - middle as isize).abs())
+    }
+
+    pub fn tag(&self, index: usize) -> &str {
+        &self.tags[index]
+    }
+
+    pub fn record(&mut self, index: usize, regressed: bool) {
+        if regressed {
+            self.bad = index;
+        } else {
+            self.good = index;
+        }
+    }
+
+    pub fn skip(&mut self, index: usize) {
+        self.skipped.insert(index);
+    }
+
+    /// First bad tag, and tags between it and the last good one which could not be tested, any
+    /// of them could have introduced the regression as well
+    pub fn result(&self) -> (&str, Vec<&str>) {
+        let untested = (self.good + 1..self.bad).map(|i| self.tag(i)).collect();
+        (self.tag(self.bad), untested)
+    }
+
+    pub fn remaining(&self) -> usize {
+        (self.good + 1..self.bad)
+            .filter(|i| !self.skipped.contains(i))
+            .count()
+    }
+}
+
+/// Tags of all commits from `good` to `bad`, both included. Only first page of commit history
+/// is available, so `good` has to be among recent ancestors of `bad`
+pub fn candidate_tags(github: &GitHub, good: &str, bad: &str) -> Result<Vec<String>> {
+    let (prefix, good_commit) = split_tag(good)?;
+    let (bad_prefix, bad_commit) = split_tag(bad)?;
+    if prefix != bad_prefix {
+        bail!("Tags {} and {} have different prefixes", good, bad);
+    }
+    let commits = github.get_commits("libra/libra", bad_commit)?;
+    let good_position = commits
+        .iter()
+        .position(|c| c.sha.starts_with(good_commit))
+        .ok_or_else(|| {
+            format_err!(
+                "{} is not among last {} commits before {}",
+                good,
+                commits.len(),
+                bad
+            )
+        })?;
+    if good_position == 0 {
+        bail!("Tags {} and {} are of the same commit", good, bad);
+    }
+    let mut tags: Vec<_> = commits[1..good_position]
+        .iter()
+        .rev()
+        .map(|c| format!("{}{}", prefix, &c.sha[..good_commit.len()]))
+        .collect();
+    tags.insert(0, good.to_string());
+    tags.push(bad.to_string());
+    Ok(tags)
+}
+
+fn split_tag(tag: &str) -> Result<(&str, &str)> {
+    let pos = tag
+        .rfind('_')
+        .ok_or_else(|| format_err!("Tag {} is not in <prefix>_<commit> format", tag))?;
+    Ok((&tag[..=pos], &tag[pos + 1..]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bisection() {
+        let tags: Vec<_> = (0..10).map(|i| format!("dev_{}", i)).collect();
+        let mut bisection = Bisection::new(tags);
+        // Regression was introduced by dev_3, dev_5 could not be deployed
+        bisection.skip(5);
+        while let Some(index) = bisection.next() {
+            assert_ne!(index, 5);
+            bisection.record(index, index >= 3);
+        }
+        assert_eq!(bisection.result(), ("dev_3", vec![]));
+
+        let tags: Vec<_> = (0..10).map(|i| format!("dev_{}", i)).collect();
+        let mut bisection = Bisection::new(tags);
+        bisection.skip(5);
+        while let Some(index) = bisection.next() {
+            bisection.record(index, index >= 5);
+        }
+        assert_eq!(bisection.result(), ("dev_6", vec!["dev_5"]));
+    }
+
+    #[test]
+    fn test_is_regression() {
+        let params = BisectParams::from_iter(vec!["bisect", "--bisect-repeats", "3"]);
+        let good = [1000.0, 980.0, 1010.0];
+        assert!(params.is_regression(&[890.0], 900.0, &[]));
+        // Below threshold, but within noise of the good build
+        assert!(!params.is_regression(&[800.0, 1000.0, 880.0], 900.0, &good));
+        assert!(params.is_regression(&[870.0, 880.0, 860.0], 900.0, &good));
+        assert!(!params.is_regression(&[990.0, 1000.0, 1005.0], 900.0, &good));
+    }
+
+    #[test]
+    fn test_split_tag() {
+        assert_eq!(split_tag("dev_1a2b3c4d").unwrap(), ("dev_", "1a2b3c4d"));
+        assert!(split_tag("master").is_err());
+    }
+}
@@ -0,0 +1,70 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Progress of a suite is saved after every completed experiment, so that a runner which crashed
+/// or was preempted can resume the suite from the next experiment with `--resume <run-id>`
+/// instead of running a multi-hour suite from scratch
+use crate::{report::SuiteReport, suite::Outcome};
+use anyhow::{format_err, Result};
+use libra_logger::info;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+#[derive(Deserialize, Serialize)]
+pub struct Checkpoint {
+    pub run_id: String,
+    pub suite: String,
+    /// Ids of suite entries which completed or were skipped, in order
+    pub completed: Vec<String>,
+    /// Outcomes by suite entry id, later entries may depend on them
+    #[serde(default)]
+    pub outcomes: BTreeMap<String, Outcome>,
+    pub report: SuiteReport,
+}
+
+/// Checkpoints are kept as `<run-id>.json` in a directory, which can be a mounted volume or
+/// bucket to survive loss of the runner
+#[derive(Clone)]
+pub struct CheckpointStore {
+    dir: PathBuf,
+}
+
+impl CheckpointStore {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Written to a temporary file first, so that a crash while saving leaves previous
+    /// checkpoint intact
+    pub fn save(&self, checkpoint: &Checkpoint) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format_err!("Failed to create {:?}: {}", self.dir, e))?;
+        let path = self.path(&checkpoint.run_id);
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string(checkpoint)?)
+            .map_err(|e| format_err!("Failed to write {:?}: {}", tmp_path, e))?;
+        fs::rename(&tmp_path, &path)
+            .map_err(|e| format_err!("Failed to rename {:?}: {}", tmp_path, e))?;
+        info!(
+            "Saved checkpoint of run {} after {} experiments to {:?}",
+            checkpoint.run_id,
+            checkpoint.completed.len(),
+            path
+        );
+        Ok(())
+    }
+
+    pub fn load(&self, run_id: &str) -> Result<Checkpoint> {
+        let path = self.path(run_id);
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format_err!("Failed to read checkpoint {:?}: {}", path, e))?;
+        serde_json::from_str(&content)
+            .map_err(|e| format_err!("Failed to parse checkpoint {:?}: {}", path, e))
+    }
+
+    fn path(&self, run_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", run_id))
+    }
+}
@@ -3,7 +3,8 @@
 
 #![forbid(unsafe_code)]
 
-use crate::instance::{Instance, ValidatorGroup};
+use crate::instance::{Instance, JsonRpcEndpointConfig, ValidatorGroup};
+use anyhow::Result;
 use config_builder::ValidatorConfig;
 use libra_crypto::{
     ed25519::{Ed25519PrivateKey, Ed25519PublicKey},
@@ -11,7 +12,7 @@ use libra_crypto::{
 };
 use rand::prelude::*;
 use reqwest::Client;
-use std::convert::TryInto;
+use std::{collections::BTreeMap, convert::TryInto};
 
 #[derive(Clone)]
 pub struct Cluster {
@@ -49,6 +50,30 @@ impl Cluster {
         }
     }
 
+    /// Uses mint key from given file instead of the one derived from default genesis seed
+    pub fn with_mint_file(self, mint_file: &str) -> Self {
+        let mint_key: Ed25519PrivateKey = generate_key::load_key(mint_file);
+        Self {
+            mint_key_pair: KeyPair::from(mint_key),
+            ..self
+        }
+    }
+
+    /// Reaches JSON-RPC endpoints of validators and fullnodes as described by `config`
+    pub fn with_json_rpc_endpoint(self, config: &JsonRpcEndpointConfig) -> Result<Self> {
+        let map = |instances: Vec<Instance>| -> Result<Vec<Instance>> {
+            instances
+                .into_iter()
+                .map(|i| i.with_json_rpc_endpoint(config))
+                .collect()
+        };
+        Ok(Self {
+            validator_instances: map(self.validator_instances)?,
+            fullnode_instances: map(self.fullnode_instances)?,
+            ..self
+        })
+    }
+
     fn get_mint_key_pair() -> KeyPair<Ed25519PrivateKey, Ed25519PublicKey> {
         let seed = "1337133713371337133713371337133713371337133713371337133713371337";
         let seed = hex::decode(seed).expect("Invalid hex in seed.");
@@ -74,6 +99,21 @@ impl Cluster {
         }
     }
 
+    /// Cluster after validators, fullnodes and LSRs were redeployed with image `tag`, vaults do
+    /// not have tags and are kept as they are
+    pub fn with_image_tag(&self, tag: &str) -> Result<Self> {
+        let retag = |instances: &[Instance]| -> Result<Vec<Instance>> {
+            instances.iter().map(|i| i.with_image_tag(tag)).collect()
+        };
+        Ok(Self {
+            validator_instances: retag(&self.validator_instances)?,
+            fullnode_instances: retag(&self.fullnode_instances)?,
+            lsr_instances: retag(&self.lsr_instances)?,
+            vault_instances: self.vault_instances.clone(),
+            mint_key_pair: self.mint_key_pair.clone(),
+        })
+    }
+
     pub fn random_validator_instance(&self) -> Instance {
         let mut rnd = rand::thread_rng();
         self.validator_instances
@@ -151,7 +191,42 @@ impl Cluster {
     /// Returns tuple of two clusters:
     /// First element in tuple contains cluster with c random instances from self
     /// Second element in tuple contains cluster with remaining instances from self
+    ///
+    /// Instances are spread across zones: a second validator of a zone is only picked once
+    /// every zone has one picked, so that taking down the first cluster is not a zone outage
+    /// in disguise. Validators with unknown zone are each a zone of their own
     pub fn split_n_validators_random(&self, c: usize) -> (Self, Self) {
+        assert!(c <= self.validator_instances.len());
+        let mut rng = ThreadRng::default();
+        let mut zones: Vec<Vec<&Instance>> = failure_domains(&self.validator_instances)
+            .into_iter()
+            .map(|(_, mut instances)| {
+                instances.shuffle(&mut rng);
+                instances
+            })
+            .collect();
+        zones.shuffle(&mut rng);
+        let mut sub = vec![];
+        while sub.len() < c {
+            for zone in zones.iter_mut() {
+                if sub.len() == c {
+                    break;
+                }
+                if let Some(instance) = zone.pop() {
+                    sub.push(instance.clone());
+                }
+            }
+        }
+        let rem = self.remaining_validators(&sub);
+        (
+            self.new_validator_sub_cluster(sub),
+            self.new_validator_sub_cluster(rem),
+        )
+    }
+
+    /// Same as `split_n_validators_random`, but zones are ignored, so that several validators
+    /// of the same zone may be picked even if other zones have none picked
+    pub fn split_n_validators_random_any_zone(&self, c: usize) -> (Self, Self) {
         assert!(c <= self.validator_instances.len());
         let mut rng = ThreadRng::default();
         let mut sub = vec![];
@@ -167,6 +242,47 @@ impl Cluster {
         )
     }
 
+    /// Splits validators and fullnodes into those running in `zone` and the rest
+    pub fn split_zone(&self, zone: &str) -> (Self, Self) {
+        let in_zone = |instances: &[Instance], in_zone: bool| -> Vec<Instance> {
+            instances
+                .iter()
+                .filter(|i| (i.zone() == Some(zone)) == in_zone)
+                .cloned()
+                .collect()
+        };
+        (
+            self.new_sub_cluster(
+                in_zone(&self.validator_instances, true),
+                in_zone(&self.fullnode_instances, true),
+            ),
+            self.new_sub_cluster(
+                in_zone(&self.validator_instances, false),
+                in_zone(&self.fullnode_instances, false),
+            ),
+        )
+    }
+
+    /// Zones validators run in, sorted, empty if zones of validators are unknown
+    pub fn zones(&self) -> Vec<String> {
+        let mut zones: Vec<_> = self
+            .validator_instances
+            .iter()
+            .filter_map(|i| i.zone().map(str::to_string))
+            .collect();
+        zones.sort();
+        zones.dedup();
+        zones
+    }
+
+    fn remaining_validators(&self, picked: &[Instance]) -> Vec<Instance> {
+        self.validator_instances
+            .iter()
+            .filter(|i| !picked.iter().any(|p| p.peer_name() == i.peer_name()))
+            .cloned()
+            .collect()
+    }
+
     pub fn split_n_fullnodes_random(&self, c: usize) -> (Self, Self) {
         assert!(c <= self.fullnode_instances.len());
         let mut rng = ThreadRng::default();
@@ -193,6 +309,16 @@ impl Cluster {
         }
     }
 
+    fn new_sub_cluster(&self, validators: Vec<Instance>, fullnodes: Vec<Instance>) -> Self {
+        Cluster {
+            validator_instances: validators,
+            fullnode_instances: fullnodes,
+            lsr_instances: vec![],
+            vault_instances: vec![],
+            mint_key_pair: self.mint_key_pair.clone(),
+        }
+    }
+
     fn new_fullnode_sub_cluster(&self, instances: Vec<Instance>) -> Self {
         Cluster {
             validator_instances: vec![],
@@ -253,3 +379,60 @@ impl Cluster {
             .collect()
     }
 }
+
+/// Instances grouped by zone, instances with unknown zone are keyed by their peer name
+fn failure_domains(instances: &[Instance]) -> BTreeMap<String, Vec<&Instance>> {
+    let mut domains: BTreeMap<String, Vec<&Instance>> = BTreeMap::new();
+    for instance in instances {
+        let domain = match instance.zone() {
+            Some(zone) => format!("zone:{}", zone),
+            None => format!("peer:{}", instance.peer_name()),
+        };
+        domains.entry(domain).or_default().push(instance);
+    }
+    domains
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validator(index: usize, zone: &str) -> Instance {
+        Instance::new(
+            format!("val-{}", index),
+            format!("10.0.0.{}", index),
+            8080,
+            None,
+            Client::new(),
+        )
+        .with_zone(Some(zone.to_string()))
+    }
+
+    #[test]
+    fn test_split_spreads_across_zones() {
+        let validators: Vec<_> = (0..9)
+            .map(|i| validator(i, ["a", "b", "c"][i % 3]))
+            .collect();
+        let cluster = Cluster::new(validators, vec![], vec![], vec![]);
+        for count in 1..=9 {
+            let (picked, rest) = cluster.split_n_validators_random(count);
+            assert_eq!(picked.validator_instances().len(), count);
+            assert_eq!(rest.validator_instances().len(), 9 - count);
+            let per_zone = failure_domains(picked.validator_instances());
+            let max = per_zone.values().map(Vec::len).max().unwrap();
+            let min = if per_zone.len() < 3 {
+                0
+            } else {
+                per_zone.values().map(Vec::len).min().unwrap()
+            };
+            assert!(max - min <= 1, "{} validators are not spread", count);
+        }
+        let (zone, rest) = cluster.split_zone("b");
+        assert_eq!(zone.validator_instances().len(), 3);
+        assert!(zone
+            .validator_instances()
+            .iter()
+            .all(|i| i.zone() == Some("b")));
+        assert_eq!(rest.validator_instances().len(), 6);
+    }
+}
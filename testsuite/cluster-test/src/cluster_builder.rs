@@ -198,6 +198,7 @@ impl ClusterBuilder {
                 config_overrides: config_overrides.to_vec(),
                 seed_peer_ip,
                 safety_rules_addr,
+                malloc_conf: None,
             };
             self.cluster_swarm.spawn_new_instance(
                 InstanceConfig {
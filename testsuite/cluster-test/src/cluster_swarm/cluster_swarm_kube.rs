@@ -3,13 +3,24 @@
 
 #![forbid(unsafe_code)]
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::{bail, format_err, Result};
 use async_trait::async_trait;
 
+use chrono::Utc;
 use futures::{future::try_join_all, lock::Mutex};
-use k8s_openapi::api::core::v1::{ConfigMap, Node, Pod, Service};
+use k8s_openapi::{
+    api::{
+        coordination::v1::{Lease, LeaseSpec},
+        core::v1::{ConfigMap, Node, Pod, Service},
+    },
+    apimachinery::pkg::apis::meta::v1::{MicroTime, ObjectMeta},
+};
 use kube::{
     api::{Api, PostParams},
     client::Client,
@@ -17,7 +28,7 @@ use kube::{
 };
 use libra_logger::*;
 
-use crate::{cluster_swarm::ClusterSwarm, instance::Instance};
+use crate::{audit, aws, cluster_swarm::ClusterSwarm, instance::Instance};
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 
 use crate::instance::{
@@ -27,9 +38,10 @@ use crate::instance::{
 use itertools::Itertools;
 use k8s_openapi::api::batch::v1::Job;
 use kube::api::ListParams;
-use libra_config::config::DEFAULT_JSON_RPC_PORT;
+use libra_config::config::{NodeConfig, DEFAULT_JSON_RPC_PORT};
 use reqwest::Client as HttpClient;
 use std::{collections::HashSet, convert::TryFrom, process::Command};
+use tokio::time::delay_for;
 
 const DEFAULT_NAMESPACE: &str = "default";
 
@@ -37,6 +49,17 @@ const CFG_SEED: &str = "13371337133713371337133713371337133713371337133713371337
 const CFG_FULLNODE_SEED: &str = "2674267426742674267426742674267426742674267426742674267426742674";
 
 const ERROR_NOT_FOUND: u16 = 404;
+const ERROR_CONFLICT: u16 = 409;
+
+const CLUSTER_LOCK_NAME: &str = "cluster-test-lock";
+/// Lock which was not renewed for this long is considered abandoned
+pub const CLUSTER_LOCK_DURATION_SECS: i32 = 300;
+const CLUSTER_LOCK_POLL_INTERVAL: Duration = Duration::from_secs(10);
+const ZONE_LABEL: &str = "topology.kubernetes.io/zone";
+/// Set instead of `ZONE_LABEL` by kubernetes before 1.17
+const LEGACY_ZONE_LABEL: &str = "failure-domain.beta.kubernetes.io/zone";
+/// Images deployed with the image tag of instances
+const LIBRA_IMAGES: [&str; 3] = ["libra_init", "libra_validator", "libra_safety_rules"];
 
 #[derive(Clone)]
 pub struct ClusterSwarmKube {
@@ -153,6 +176,7 @@ impl ClusterSwarmKube {
         seed_peer_ip: &str,
         safety_rules_addr: &str,
         cfg_overrides: &str,
+        malloc_conf: &str,
         delete_data: bool,
     ) -> Result<Pod> {
         let cfg_fullnode_seed = if num_fullnodes > 0 {
@@ -171,6 +195,7 @@ impl ClusterSwarmKube {
             image_tag = image_tag,
             node_name = node_name,
             cfg_overrides = cfg_overrides,
+            malloc_conf = malloc_conf,
             delete_data = delete_data,
             cfg_seed = CFG_SEED,
             cfg_seed_peer_ip = seed_peer_ip,
@@ -376,6 +401,48 @@ impl ClusterSwarmKube {
         self.run_jobs(jobs, back_off_limit).await
     }
 
+    /// One job per node and image, which only pulls the image and exits
+    async fn prepull_images_helper(&self, image_tag: &str) -> Result<()> {
+        let back_off_limit = 2;
+        let nodes = self.list_nodes().await?;
+        let mut jobs: Vec<Job> = vec![];
+        for node in &nodes {
+            for image in LIBRA_IMAGES.iter() {
+                let suffix = thread_rng()
+                    .sample_iter(&Alphanumeric)
+                    .take(10)
+                    .collect::<String>()
+                    .to_ascii_lowercase();
+                let job_name = format!("prepull-images-{}", suffix);
+                let job_yaml = format!(
+                    include_str!("job_template.yaml"),
+                    name = &job_name,
+                    label = "prepull-images",
+                    image = format!(
+                        "853397791086.dkr.ecr.us-west-2.amazonaws.com/{}:{}",
+                        image, image_tag
+                    ),
+                    node_name = node.name,
+                    command = "true",
+                    back_off_limit = back_off_limit,
+                );
+                let job_spec: serde_yaml::Value = serde_yaml::from_str(&job_yaml)?;
+                let job_spec = serde_json::value::to_value(job_spec)?;
+                jobs.push(
+                    serde_json::from_value(job_spec)
+                        .map_err(|e| format_err!("serde_json::from_value failed: {}", e))?,
+                );
+            }
+        }
+        debug!(
+            "Pulling {} images of {} on {} nodes",
+            LIBRA_IMAGES.len(),
+            image_tag,
+            nodes.len()
+        );
+        self.run_jobs(jobs, back_off_limit).await
+    }
+
     pub async fn get_workspace(&self) -> Result<String> {
         let cm_api: Api<ConfigMap> = Api::namespaced(self.client.clone(), DEFAULT_NAMESPACE);
         let data = cm_api
@@ -389,6 +456,137 @@ impl ClusterSwarmKube {
         Ok(workspace.clone())
     }
 
+    /// Acquires lock on the cluster, so that concurrent cluster test runs do not run conflicting
+    /// experiments. Waits up to `wait` for the lock to be released by current holder.
+    /// If `steal` is set, lock is taken over from current holder without waiting
+    pub async fn acquire_lock(&self, holder: &str, wait: Duration, steal: bool) -> Result<()> {
+        audit::record("acquire_lock", holder, format!("steal={}", steal));
+        let deadline = Instant::now() + wait;
+        loop {
+            match self.try_acquire_lock(holder, steal).await? {
+                None => {
+                    info!("Acquired cluster lock as {}", holder);
+                    return Ok(());
+                }
+                Some(current_holder) => {
+                    if Instant::now() > deadline {
+                        bail!(
+                            "Cluster is locked by {}, use --wait-for-lock or --steal-lock",
+                            current_holder
+                        );
+                    }
+                    info!("Cluster is locked by {}, waiting", current_holder);
+                    delay_for(CLUSTER_LOCK_POLL_INTERVAL).await;
+                }
+            }
+        }
+    }
+
+    /// Returns holder of the lock if it is currently held by someone else
+    async fn try_acquire_lock(&self, holder: &str, steal: bool) -> Result<Option<String>> {
+        let lease_api: Api<Lease> = Api::namespaced(self.client.clone(), DEFAULT_NAMESPACE);
+        let now = MicroTime(Utc::now());
+        let mut lease = match lease_api.get(CLUSTER_LOCK_NAME).await {
+            Ok(lease) => lease,
+            Err(kube::Error::Api(ae)) if ae.code == ERROR_NOT_FOUND => {
+                let lease = Lease {
+                    metadata: Some(ObjectMeta {
+                        name: Some(CLUSTER_LOCK_NAME.to_string()),
+                        ..Default::default()
+                    }),
+                    spec: Some(LeaseSpec {
+                        holder_identity: Some(holder.to_string()),
+                        acquire_time: Some(now.clone()),
+                        renew_time: Some(now),
+                        lease_duration_seconds: Some(CLUSTER_LOCK_DURATION_SECS),
+                        ..Default::default()
+                    }),
+                };
+                return match lease_api.create(&PostParams::default(), &lease).await {
+                    Ok(_) => Ok(None),
+                    // Someone else created lock between get and create
+                    Err(kube::Error::Api(ae)) if ae.code == ERROR_CONFLICT => {
+                        Ok(Some("unknown".to_string()))
+                    }
+                    Err(e) => bail!("Failed to create cluster lock: {}", e),
+                };
+            }
+            Err(e) => bail!("Failed to get cluster lock: {}", e),
+        };
+        let spec = lease.spec.get_or_insert_with(Default::default);
+        if let Some(current_holder) = spec.holder_identity.clone() {
+            if current_holder != holder {
+                let expired = match (&spec.renew_time, spec.lease_duration_seconds) {
+                    (Some(renew_time), Some(duration)) => {
+                        renew_time.0 + chrono::Duration::seconds(duration.into()) < now.0
+                    }
+                    _ => true,
+                };
+                if !expired && !steal {
+                    return Ok(Some(current_holder));
+                }
+                warn!(
+                    "Taking over cluster lock from {} (expired: {})",
+                    current_holder, expired
+                );
+            }
+        }
+        spec.holder_identity = Some(holder.to_string());
+        spec.acquire_time = Some(now.clone());
+        spec.renew_time = Some(now);
+        spec.lease_duration_seconds = Some(CLUSTER_LOCK_DURATION_SECS);
+        // Lease carries resource version it was read with, so replace fails with conflict
+        // if lock was modified by someone else after get
+        match lease_api
+            .replace(CLUSTER_LOCK_NAME, &PostParams::default(), &lease)
+            .await
+        {
+            Ok(_) => Ok(None),
+            Err(kube::Error::Api(ae)) if ae.code == ERROR_CONFLICT => {
+                Ok(Some("unknown".to_string()))
+            }
+            Err(e) => bail!("Failed to update cluster lock: {}", e),
+        }
+    }
+
+    /// Extends cluster lock held by `holder`, fails if lock was taken over by someone else
+    pub async fn renew_lock(&self, holder: &str) -> Result<()> {
+        let lease_api: Api<Lease> = Api::namespaced(self.client.clone(), DEFAULT_NAMESPACE);
+        let mut lease = lease_api.get(CLUSTER_LOCK_NAME).await?;
+        let spec = lease
+            .spec
+            .as_mut()
+            .ok_or_else(|| format_err!("spec not found for cluster lock"))?;
+        if spec.holder_identity.as_deref() != Some(holder) {
+            bail!("Cluster lock was taken over by {:?}", spec.holder_identity);
+        }
+        spec.renew_time = Some(MicroTime(Utc::now()));
+        lease_api
+            .replace(CLUSTER_LOCK_NAME, &PostParams::default(), &lease)
+            .await?;
+        Ok(())
+    }
+
+    /// Releases cluster lock if it is still held by `holder`
+    pub async fn release_lock(&self, holder: &str) -> Result<()> {
+        audit::record("release_lock", holder, "");
+        let lease_api: Api<Lease> = Api::namespaced(self.client.clone(), DEFAULT_NAMESPACE);
+        let lease = lease_api.get(CLUSTER_LOCK_NAME).await?;
+        let current_holder = lease.spec.and_then(|spec| spec.holder_identity);
+        if current_holder.as_deref() != Some(holder) {
+            warn!(
+                "Not releasing cluster lock, it is held by {:?}",
+                current_holder
+            );
+            return Ok(());
+        }
+        lease_api
+            .delete(CLUSTER_LOCK_NAME, &Default::default())
+            .await?;
+        info!("Released cluster lock");
+        Ok(())
+    }
+
     pub async fn run(
         &self,
         k8s_node: &str,
@@ -448,6 +646,33 @@ impl ClusterSwarmKube {
         ))
     }
 
+    /// Pods are pinned to nodes by `nodeName`, which bypasses the scheduler, so nodes are only
+    /// picked among the ones matching `nodeSelector` of the specs, and cordoned ones are skipped
+    async fn reallocate_node(&self, pod_name: &str, k8s_node: Option<String>) -> Result<KubeNode> {
+        let nodes = self.list_nodes().await?;
+        let mut node_map = self.node_map.lock().await;
+        let used_nodes: HashSet<_> = node_map.values().map(|node| node.name.clone()).collect();
+        let node = match k8s_node {
+            Some(k8s_node) => nodes
+                .into_iter()
+                .find(|node| node.name == k8s_node)
+                .ok_or_else(|| format_err!("Can not find node {}", k8s_node))?,
+            None => nodes
+                .into_iter()
+                .find(|node| !node.unschedulable && !used_nodes.contains(&node.name))
+                .ok_or_else(|| format_err!("Can not find free node to move {}", pod_name))?,
+        };
+        if let Some(owner) = node_map
+            .iter()
+            .find(|(pod, n)| n.name == node.name && pod.as_str() != pod_name)
+            .map(|(pod, _)| pod)
+        {
+            bail!("Node {} is already used by {}", node.name, owner);
+        }
+        node_map.insert(pod_name.to_string(), node.clone());
+        Ok(node)
+    }
+
     pub async fn upsert_node(
         &self,
         instance_config: InstanceConfig,
@@ -479,6 +704,7 @@ impl ClusterSwarmKube {
                         .as_ref()
                         .unwrap_or(&"".to_string()),
                     &validator_config.config_overrides.iter().join(","),
+                    validator_config.malloc_conf.as_deref().unwrap_or(""),
                     delete_data,
                 )?,
                 self.service_spec(pod_name.clone()),
@@ -555,10 +781,60 @@ impl ClusterSwarmKube {
             instance_config.clone(),
             self.http_client.clone(),
             self.clone(),
-        );
+        )
+        .with_zone(node.zone.clone());
         Ok(instance)
     }
 
+    /// Validators and fullnodes of pods which are running now, found without changing anything
+    /// in the cluster
+    pub async fn running_instances(&self) -> Result<(Vec<Instance>, Vec<Instance>)> {
+        let pod_api: Api<Pod> = Api::namespaced(self.client.clone(), DEFAULT_NAMESPACE);
+        let pods = pod_api
+            .list(&ListParams {
+                label_selector: Some("libra-node=true".to_string()),
+                ..Default::default()
+            })
+            .await?
+            .items;
+        let debug_port = NodeConfig::default()
+            .debug_interface
+            .admission_control_node_debug_port as u32;
+        let (mut validators, mut fullnodes) = (vec![], vec![]);
+        for pod in pods {
+            let name = pod
+                .metadata
+                .as_ref()
+                .and_then(|metadata| metadata.name.clone())
+                .ok_or_else(|| format_err!("name not found for pod"))?;
+            let instances = if name.starts_with("val-") {
+                &mut validators
+            } else if name.starts_with("fn-") {
+                &mut fullnodes
+            } else {
+                continue;
+            };
+            // Pods run in host network, so they are reached at the ip of their node
+            let ip = match pod.status.and_then(|status| status.host_ip) {
+                Some(ip) => ip,
+                None => {
+                    warn!("Pod {} is not scheduled", name);
+                    continue;
+                }
+            };
+            instances.push(Instance::new(
+                name,
+                ip,
+                DEFAULT_JSON_RPC_PORT as u32,
+                Some(debug_port),
+                self.http_client.clone(),
+            ));
+        }
+        validators.sort_by(|a, b| a.peer_name().cmp(b.peer_name()));
+        fullnodes.sort_by(|a, b| a.peer_name().cmp(b.peer_name()));
+        Ok((validators, fullnodes))
+    }
+
     pub async fn delete_node(&self, instance_config: &InstanceConfig) -> Result<()> {
         let pod_name = instance_config.pod_name();
         let service_name = pod_name.clone();
@@ -574,6 +850,7 @@ impl ClusterSwarmKube {
     }
 
     pub async fn cleanup(&self) -> Result<()> {
+        audit::record("cleanup", "cluster", "delete all pods, services and jobs");
         self.delete_all()
             .await
             .map_err(|e| format_err!("delete_all failed: {}", e))?;
@@ -663,9 +940,77 @@ impl ClusterSwarm for ClusterSwarmKube {
         instance_config: InstanceConfig,
         delete_data: bool,
     ) -> Result<Instance> {
+        audit::record(
+            "spawn_new_instance",
+            &instance_config.pod_name(),
+            format!(
+                "delete_data={} image_tag={:?} overrides={:?} malloc_conf={:?}",
+                delete_data,
+                instance_config.image_tag(),
+                instance_config.config_overrides(),
+                instance_config.malloc_conf()
+            ),
+        );
         self.upsert_node(instance_config, delete_data).await
     }
 
+    async fn move_instance(
+        &self,
+        instance_config: InstanceConfig,
+        host: Option<String>,
+    ) -> Result<Instance> {
+        let pod_name = instance_config.pod_name();
+        audit::record("move_instance", &pod_name, format!("host={:?}", host));
+        let pod_api: Api<Pod> = Api::namespaced(self.client.clone(), DEFAULT_NAMESPACE);
+        if pod_api.get(&pod_name).await.is_ok() {
+            self.delete_resource::<Pod>(&pod_name).await?;
+        }
+        let node = self.reallocate_node(&pod_name, host).await?;
+        info!("Moving {} to node {}", pod_name, node.name);
+        self.upsert_node(instance_config, true).await
+    }
+
+    async fn ensure_host_count(&self, count: u32) -> Result<()> {
+        if self.list_nodes().await?.len() >= count as usize {
+            return Ok(());
+        }
+        let asg_name = format!("{}-k8s-testnet-validators", self.get_workspace().await?);
+        audit::record("ensure_host_count", &asg_name, format!("count={}", count));
+        aws::set_asg_size(count as i64, 5.0, &asg_name, true, false)
+            .await
+            .map_err(|err| format_err!("{} scale up failed: {}", asg_name, err))
+    }
+
+    async fn host_count(&self) -> Result<u32> {
+        Ok(self.list_nodes().await?.len() as u32)
+    }
+
+    async fn release_hosts(&self, count: u32, stopped: &[Instance]) -> Result<()> {
+        let nodes = self.list_nodes().await?;
+        // Holding lock so that no instance is placed on nodes being released
+        let mut node_map = self.node_map.lock().await;
+        for instance in stopped {
+            node_map.remove(&instance.instance_config().pod_name());
+        }
+        let used_nodes: HashSet<_> = node_map.values().map(|node| node.name.clone()).collect();
+        let released = nodes_to_release(nodes, &used_nodes, count as usize);
+        if released.is_empty() {
+            return Ok(());
+        }
+        let instance_ids: Vec<_> = released
+            .iter()
+            .map(|node| ec2_instance_id(&node.provider_id).to_string())
+            .collect();
+        audit::record(
+            "release_hosts",
+            "cluster",
+            format!("count={} instances={:?}", count, instance_ids),
+        );
+        aws::terminate_asg_instances(&instance_ids)
+            .await
+            .map_err(|err| format_err!("Releasing {:?} failed: {}", instance_ids, err))
+    }
+
     async fn get_grafana_baseurl(&self) -> Result<String> {
         let workspace = self.get_workspace().await?;
         Ok(format!(
@@ -673,6 +1018,35 @@ impl ClusterSwarm for ClusterSwarmKube {
             workspace
         ))
     }
+
+    async fn prepull_images(&self, image_tag: &str) -> Result<()> {
+        audit::record(
+            "prepull_images",
+            "cluster",
+            format!("image_tag={}", image_tag),
+        );
+        self.prepull_images_helper(image_tag).await
+    }
+}
+
+/// Nodes beyond the first `count` which no instance is placed on, nodes in use are never
+/// released even if more than `count` of them are left
+fn nodes_to_release(
+    nodes: Vec<KubeNode>,
+    used_nodes: &HashSet<String>,
+    count: usize,
+) -> Vec<KubeNode> {
+    let excess = nodes.len().saturating_sub(count);
+    nodes
+        .into_iter()
+        .filter(|node| !used_nodes.contains(&node.name))
+        .take(excess)
+        .collect()
+}
+
+/// EC2 instance id of a node from its provider id, e.g. `aws:///us-west-2a/i-0123456789`
+fn ec2_instance_id(provider_id: &str) -> &str {
+    provider_id.rsplit('/').next().unwrap_or(provider_id)
 }
 
 #[derive(Clone, Debug)]
@@ -680,6 +1054,10 @@ pub struct KubeNode {
     pub name: String,
     pub provider_id: String,
     pub internal_ip: String,
+    /// Availability zone from node labels
+    pub zone: Option<String>,
+    /// Set for cordoned nodes
+    pub unschedulable: bool,
 }
 
 impl TryFrom<Node> for KubeNode {
@@ -692,6 +1070,7 @@ impl TryFrom<Node> for KubeNode {
         let spec = node
             .spec
             .ok_or_else(|| format_err!("spec not found for node"))?;
+        let unschedulable = spec.unschedulable.unwrap_or(false);
         let provider_id = spec
             .provider_id
             .ok_or_else(|| format_err!("provider_id not found for node"))?;
@@ -709,10 +1088,51 @@ impl TryFrom<Node> for KubeNode {
             .find(|a| a.type_ == "InternalIP")
             .ok_or_else(|| format_err!("internal address not found"))?;
         let internal_ip = internal_address.address.clone();
+        let zone = metadata.labels.and_then(|labels| {
+            labels
+                .get(ZONE_LABEL)
+                .or_else(|| labels.get(LEGACY_ZONE_LABEL))
+                .cloned()
+        });
         Ok(Self {
             name,
             provider_id,
             internal_ip,
+            zone,
+            unschedulable,
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn node(name: &str) -> KubeNode {
+        KubeNode {
+            name: name.to_string(),
+            provider_id: format!("aws:///us-west-2a/i-{}", name),
+            internal_ip: "10.0.0.1".to_string(),
+            zone: None,
+            unschedulable: false,
+        }
+    }
+
+    #[test]
+    fn test_nodes_to_release_skips_used_nodes() {
+        let nodes = vec![node("a"), node("b"), node("c"), node("d")];
+        let used: HashSet<_> = vec!["a".to_string(), "c".to_string()].into_iter().collect();
+        let names = |nodes: Vec<KubeNode>| nodes.into_iter().map(|n| n.name).collect::<Vec<_>>();
+        assert_eq!(names(nodes_to_release(nodes.clone(), &used, 3)), vec!["b"]);
+        assert_eq!(
+            names(nodes_to_release(nodes.clone(), &used, 1)),
+            vec!["b", "d"]
+        );
+        assert!(nodes_to_release(nodes, &used, 4).is_empty());
+    }
+
+    #[test]
+    fn test_ec2_instance_id() {
+        assert_eq!(ec2_instance_id("aws:///us-west-2a/i-0abc"), "i-0abc");
+    }
+}
@@ -0,0 +1,371 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// ClusterSwarm for clusters which are not managed by cluster test, for example partner operated
+/// or bare metal testnets. Instances are defined by static inventory file and libra-node is
+/// controlled over ssh as systemd service. Instances can only be restarted in place, so
+/// experiments which move instances or add new hosts are not supported
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+};
+
+use anyhow::{bail, format_err, Result};
+use async_trait::async_trait;
+use libra_config::config::{NodeConfig, DEFAULT_JSON_RPC_PORT};
+use reqwest::Client as HttpClient;
+use serde::Deserialize;
+
+use crate::{
+    audit,
+    cluster::Cluster,
+    cluster_swarm::ClusterSwarm,
+    instance::{
+        ApplicationConfig, FullnodeConfig, Instance, InstanceConfig, JsonRpcEndpointConfig,
+        SshInstanceInfo, ValidatorConfig, ValidatorGroup,
+    },
+};
+
+/// Example inventory:
+/// ```yaml
+/// prometheus: 10.0.0.100
+/// grafana_base_url: http://10.0.0.100:3000/
+/// ssh_user: libra
+/// instances:
+///   - host: 10.0.0.1
+///     role: validator
+///     zone: us-west-2a
+///   - host: 10.0.0.2
+///     role: validator
+///     ssh_user: admin
+///     zone: us-west-2b
+///     capacity: 2
+///   - host: 10.0.0.3
+///     role: fullnode
+///     validator_index: 0
+///     json_rpc:
+///       url: https://fullnode.example.com/v1
+///       api_key: secret
+///     prometheus_labels:
+///       peer_id: fn-0-0
+///       job: libra-fullnode
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct Inventory {
+    /// Address of prometheus server, which is expected to listen on port 80
+    pub prometheus: String,
+    pub grafana_base_url: String,
+    /// Default ssh user, can be overridden per instance
+    pub ssh_user: String,
+    #[serde(default = "default_service")]
+    pub service: String,
+    #[serde(default = "default_data_dir")]
+    pub data_dir: String,
+    /// Version of libra-node deployed on the cluster, instances can not be restarted with another one
+    #[serde(default)]
+    pub image_tag: String,
+    pub instances: Vec<InventoryEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InventoryEntry {
+    pub host: String,
+    pub role: Role,
+    pub ssh_user: Option<String>,
+    /// Index of validator this fullnode is connected to, required for fullnodes
+    pub validator_index: Option<u32>,
+    pub json_rpc_port: Option<u32>,
+    pub debug_interface_port: Option<u32>,
+    /// Set when JSON-RPC endpoint needs https or authentication, e.g. behind ingress
+    pub json_rpc: Option<JsonRpcEndpointConfig>,
+    /// Labels Prometheus scrapes metrics of this instance with, defaults to `peer_id` set to
+    /// peer name of the instance, e.g. `val-0` or `fn-0-0`
+    #[serde(default)]
+    pub prometheus_labels: BTreeMap<String, String>,
+    /// Failure domain of the instance, e.g. availability zone or rack
+    pub zone: Option<String>,
+    /// Capacity relative to other instances, e.g. 2 for a host with twice the cores, emitter
+    /// sends proportionally more load to it with weighted balancing
+    pub capacity: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Validator,
+    Fullnode,
+}
+
+fn default_service() -> String {
+    "libra-node".to_string()
+}
+
+fn default_data_dir() -> String {
+    "/opt/libra/data".to_string()
+}
+
+/// Contents of data dir are deleted as root when instances are reset, so it has to be a plain
+/// absolute path which can not resolve to the root
+fn validate_data_dir(data_dir: &str) -> Result<()> {
+    if !data_dir.starts_with('/')
+        || data_dir.ends_with('/')
+        || data_dir.split('/').any(|part| part == "..")
+    {
+        bail!(
+            "data_dir {:?} has to be an absolute path other than / without trailing / or ..",
+            data_dir
+        );
+    }
+    Ok(())
+}
+
+pub struct ClusterSwarmSsh {
+    inventory: Inventory,
+    validators: Vec<Instance>,
+    fullnodes: Vec<Instance>,
+    /// Labels set in inventory, by peer name
+    prometheus_labels: HashMap<String, BTreeMap<String, String>>,
+}
+
+impl ClusterSwarmSsh {
+    pub fn from_inventory_file(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format_err!("Failed to read inventory file {}: {}", path, e))?;
+        let inventory: Inventory = serde_yaml::from_str(&content)
+            .map_err(|e| format_err!("Failed to parse inventory file {}: {}", path, e))?;
+        Self::new(inventory)
+    }
+
+    pub fn new(inventory: Inventory) -> Result<Self> {
+        let validator_hosts: Vec<_> = inventory
+            .instances
+            .iter()
+            .filter(|e| e.role == Role::Validator)
+            .map(|e| e.host.clone())
+            .collect();
+        if validator_hosts.is_empty() {
+            bail!("Inventory does not contain any validators");
+        }
+        validate_data_dir(&inventory.data_dir)?;
+        let num_validators = validator_hosts.len() as u32;
+        let mut fullnodes_per_validator = HashMap::new();
+        for entry in inventory.instances.iter() {
+            if entry.role == Role::Fullnode {
+                let index = entry
+                    .validator_index
+                    .ok_or_else(|| format_err!("validator_index is not set for {}", entry.host))?;
+                if index >= num_validators {
+                    bail!(
+                        "validator_index {} of {} is out of range",
+                        index,
+                        entry.host
+                    );
+                }
+                *fullnodes_per_validator.entry(index).or_insert(0u32) += 1;
+            }
+        }
+        let num_fullnodes_per_validator =
+            fullnodes_per_validator.values().max().cloned().unwrap_or(0);
+
+        let http_client = HttpClient::new();
+        let mut validators = vec![];
+        let mut fullnodes = vec![];
+        let mut fullnode_indices = HashMap::new();
+        let mut prometheus_labels = HashMap::new();
+        for entry in inventory.instances.iter() {
+            let application_config = match entry.role {
+                Role::Validator => ApplicationConfig::Validator(ValidatorConfig {
+                    num_validators,
+                    num_fullnodes: num_fullnodes_per_validator,
+                    enable_lsr: false,
+                    image_tag: inventory.image_tag.clone(),
+                    config_overrides: vec![],
+                    seed_peer_ip: validator_hosts[0].clone(),
+                    safety_rules_addr: None,
+                    malloc_conf: None,
+                }),
+                Role::Fullnode => {
+                    let validator_index = entry.validator_index.expect("Checked above");
+                    let fullnode_index = fullnode_indices.entry(validator_index).or_insert(0);
+                    let config = FullnodeConfig {
+                        fullnode_index: *fullnode_index,
+                        num_fullnodes_per_validator,
+                        num_validators,
+                        image_tag: inventory.image_tag.clone(),
+                        config_overrides: vec![],
+                        seed_peer_ip: validator_hosts[validator_index as usize].clone(),
+                    };
+                    *fullnode_index += 1;
+                    ApplicationConfig::Fullnode(config)
+                }
+            };
+            let validator_index = match entry.role {
+                Role::Validator => validators.len() as u32,
+                Role::Fullnode => entry.validator_index.expect("Checked above"),
+            };
+            let ssh_info = SshInstanceInfo {
+                ssh_user: entry
+                    .ssh_user
+                    .clone()
+                    .unwrap_or_else(|| inventory.ssh_user.clone()),
+                service: inventory.service.clone(),
+                data_dir: inventory.data_dir.clone(),
+                instance_config: InstanceConfig {
+                    validator_group: ValidatorGroup::new_for_index(validator_index),
+                    application_config,
+                },
+            };
+            let instance = Instance::new_ssh(
+                entry.host.clone(),
+                entry.json_rpc_port.unwrap_or(DEFAULT_JSON_RPC_PORT as u32),
+                entry.debug_interface_port.unwrap_or(
+                    NodeConfig::default()
+                        .debug_interface
+                        .admission_control_node_debug_port as u32,
+                ),
+                ssh_info,
+                http_client.clone(),
+            );
+            let instance = match &entry.json_rpc {
+                Some(config) => instance.with_json_rpc_endpoint(config)?,
+                None => instance,
+            }
+            .with_zone(entry.zone.clone())
+            .with_capacity(entry.capacity);
+            if !entry.prometheus_labels.is_empty() {
+                prometheus_labels.insert(
+                    instance.peer_name().clone(),
+                    entry.prometheus_labels.clone(),
+                );
+            }
+            match entry.role {
+                Role::Validator => validators.push(instance),
+                Role::Fullnode => fullnodes.push(instance),
+            }
+        }
+        Ok(Self {
+            inventory,
+            validators,
+            fullnodes,
+            prometheus_labels,
+        })
+    }
+
+    pub fn cluster(&self, mint_file: &str) -> Cluster {
+        Cluster::new(
+            self.validators.clone(),
+            self.fullnodes.clone(),
+            vec![],
+            vec![],
+        )
+        .with_mint_file(mint_file)
+    }
+
+    pub fn prometheus_ip(&self) -> &str {
+        &self.inventory.prometheus
+    }
+}
+
+#[async_trait]
+impl ClusterSwarm for ClusterSwarmSsh {
+    /// Restarts existing instance from inventory, new instances can not be created
+    async fn spawn_new_instance(
+        &self,
+        instance_config: InstanceConfig,
+        delete_data: bool,
+    ) -> Result<Instance> {
+        let pod_name = instance_config.pod_name();
+        let instance = self
+            .validators
+            .iter()
+            .chain(self.fullnodes.iter())
+            .find(|i| i.peer_name() == &pod_name)
+            .ok_or_else(|| format_err!("{} is not defined in inventory", pod_name))?;
+        if instance_config.image_tag() != Some(self.inventory.image_tag.as_str()) {
+            bail!("Changing image tag is not supported for inventory instances");
+        }
+        if instance_config.config_overrides() != instance.instance_config().config_overrides() {
+            bail!("Config overrides are not supported for inventory instances");
+        }
+        if instance_config.malloc_conf().is_some() {
+            bail!("jemalloc options are not supported for inventory instances");
+        }
+        audit::record(
+            "spawn_new_instance",
+            &pod_name,
+            format!("delete_data={}", delete_data),
+        );
+        instance.stop().await?;
+        instance.start(delete_data).await?;
+        Ok(instance.clone())
+    }
+
+    async fn move_instance(
+        &self,
+        _instance_config: InstanceConfig,
+        _host: Option<String>,
+    ) -> Result<Instance> {
+        bail!("Moving instances is not supported for inventory instances")
+    }
+
+    async fn ensure_host_count(&self, count: u32) -> Result<()> {
+        if count as usize > self.inventory.instances.len() {
+            bail!(
+                "Inventory has {} hosts, {} required",
+                self.inventory.instances.len(),
+                count
+            );
+        }
+        Ok(())
+    }
+
+    async fn host_count(&self) -> Result<u32> {
+        Ok(self.inventory.instances.len() as u32)
+    }
+
+    async fn release_hosts(&self, _count: u32, _stopped: &[Instance]) -> Result<()> {
+        // Inventory hosts are never added, so there is nothing to give back
+        Ok(())
+    }
+
+    async fn get_grafana_baseurl(&self) -> Result<String> {
+        Ok(self.inventory.grafana_base_url.clone())
+    }
+
+    fn supports_config_overrides(&self) -> bool {
+        false
+    }
+
+    fn supports_moving_instances(&self) -> bool {
+        false
+    }
+
+    fn supports_malloc_conf(&self) -> bool {
+        false
+    }
+
+    fn prometheus_labels(&self, instance: &Instance) -> Vec<(String, String)> {
+        match self.prometheus_labels.get(instance.peer_name()) {
+            Some(labels) => labels
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+            None => vec![("peer_id".to_string(), instance.peer_name().clone())],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate_data_dir() {
+        assert!(validate_data_dir("/opt/libra/data").is_ok());
+        for data_dir in &["", "/", "opt/libra", "/opt/libra/", "/opt/../"] {
+            assert!(validate_data_dir(data_dir).is_err(), "{}", data_dir);
+        }
+    }
+}
@@ -2,10 +2,20 @@
 // SPDX-License-Identifier: Apache-2.0
 
 pub mod cluster_swarm_kube;
+pub mod cluster_swarm_ssh;
 
-use crate::instance::{Instance, InstanceConfig};
-use anyhow::Result;
+use crate::{
+    cluster::Cluster,
+    instance::{
+        ApplicationConfig::{Fullnode, Validator},
+        FullnodeConfig, Instance, InstanceConfig, ValidatorGroup,
+    },
+};
+use anyhow::{bail, format_err, Result};
 use async_trait::async_trait;
+use futures::future::{join_all, try_join_all};
+use itertools::zip;
+use libra_logger::{info, warn};
 
 #[async_trait]
 pub trait ClusterSwarm: Send + Sync {
@@ -16,5 +26,172 @@ pub trait ClusterSwarm: Send + Sync {
         delete_data: bool,
     ) -> Result<Instance>;
 
+    /// Respawns instance on another host, which changes its ip address.
+    /// If `host` is not set, any host not used by other instances is picked.
+    /// Data is not moved along, so instance starts with empty db
+    async fn move_instance(
+        &self,
+        instance_config: InstanceConfig,
+        host: Option<String>,
+    ) -> Result<Instance>;
+
+    /// Makes sure at least `count` hosts are available for instances.
+    /// Existing hosts are kept, so instances already running are not affected
+    async fn ensure_host_count(&self, count: u32) -> Result<()>;
+
+    /// Number of hosts available for instances
+    async fn host_count(&self) -> Result<u32>;
+
+    /// Gives back hosts added by `ensure_host_count` until at most `count` are left. Only hosts
+    /// no instance is placed on are removed, `stopped` instances must not be started again
+    async fn release_hosts(&self, count: u32, stopped: &[Instance]) -> Result<()>;
+
     async fn get_grafana_baseurl(&self) -> Result<String>;
+
+    /// Pulls images of `image_tag` on every host ahead of deploying it, so that deploys which
+    /// follow do not include download time. Nothing to do where the tag can not be changed
+    async fn prepull_images(&self, _image_tag: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Labels which select metrics of `instance` in Prometheus. Queries of experiments and
+    /// health checks select instances by `peer_id`, which pods of the cluster are labeled with
+    fn prometheus_labels(&self, instance: &Instance) -> Vec<(String, String)> {
+        vec![("peer_id".to_string(), instance.peer_name().clone())]
+    }
+
+    /// Whether instances can be respawned with config overrides
+    fn supports_config_overrides(&self) -> bool {
+        true
+    }
+
+    /// Whether instances can be moved to another host, see `move_instance`
+    fn supports_moving_instances(&self) -> bool {
+        true
+    }
+
+    /// Whether instances can be respawned with jemalloc options, see `set_malloc_conf`
+    fn supports_malloc_conf(&self) -> bool {
+        true
+    }
+
+    /// Restarts given instances with node config overrides (e.g. `capacity=10000`) added to
+    /// their configs, data is kept. Returned instances carry the new configs, original configs
+    /// can be restored by spawning instances from configs of `instances`. Overrides are checked
+    /// before any instance is stopped
+    async fn apply_config_overrides(
+        &self,
+        instances: &[Instance],
+        overrides: &[String],
+    ) -> Result<Vec<Instance>> {
+        if !self.supports_config_overrides() {
+            bail!("Config overrides are not supported by this cluster");
+        }
+        let instance_configs = instances
+            .iter()
+            .map(|instance| {
+                let mut instance_config = instance.instance_config().clone();
+                instance_config.add_config_overrides(overrides)?;
+                Ok(instance_config)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let futures =
+            zip(instances, instance_configs).map(|(instance, instance_config)| async move {
+                instance.stop().await?;
+                self.spawn_new_instance(instance_config, false).await
+            });
+        try_join_all(futures).await
+    }
+}
+
+/// Fullnodes an experiment spawns on top of the base deployment, e.g. an observer with tiny
+/// caches. They take fullnode indices after the ones of the deployment, so base instances are
+/// never replaced, and are torn down when the experiment ends however it ended, together with
+/// hosts added for them
+#[derive(Default)]
+pub struct TemporaryFullnodes {
+    instances: Vec<Instance>,
+    /// Hosts of the cluster before the first fullnode was spawned
+    host_count: Option<u32>,
+}
+
+impl TemporaryFullnodes {
+    /// Spawns a fullnode of validator `validator_index` with empty db, configured like the
+    /// validator plus `overrides` (e.g. `max_open_files=64`). Needs a free host,
+    /// which is added if the cluster can grow
+    pub async fn spawn(
+        &mut self,
+        cluster_swarm: &dyn ClusterSwarm,
+        cluster: &Cluster,
+        validator_index: u32,
+        overrides: &[String],
+    ) -> Result<Instance> {
+        let validator = cluster
+            .validator_instances()
+            .iter()
+            .find(|v| v.validator_group().index == validator_index)
+            .ok_or_else(|| format_err!("No validator with index {}", validator_index))?;
+        let validator_config = match &validator.instance_config().application_config {
+            Validator(c) => c,
+            _ => bail!("{} has no validator config", validator),
+        };
+        let siblings = cluster
+            .fullnode_instances()
+            .iter()
+            .chain(self.instances.iter())
+            .filter(|f| f.validator_group().index == validator_index)
+            .count() as u32;
+        let mut instance_config = InstanceConfig {
+            validator_group: ValidatorGroup::new_for_index(validator_index),
+            application_config: Fullnode(FullnodeConfig {
+                fullnode_index: siblings,
+                num_fullnodes_per_validator: siblings + 1,
+                num_validators: validator_config.num_validators,
+                image_tag: validator_config.image_tag.clone(),
+                config_overrides: validator_config.config_overrides.clone(),
+                seed_peer_ip: validator.ip().clone(),
+            }),
+        };
+        instance_config.add_config_overrides(overrides)?;
+        if self.host_count.is_none() {
+            self.host_count = Some(cluster_swarm.host_count().await?);
+        }
+        let host_count = cluster.all_instances().count() + self.instances.len() + 1;
+        cluster_swarm.ensure_host_count(host_count as u32).await?;
+        info!(
+            "Spawning temporary fullnode {} with overrides {:?}",
+            instance_config.pod_name(),
+            overrides
+        );
+        let instance = cluster_swarm
+            .spawn_new_instance(instance_config, true)
+            .await?;
+        self.instances.push(instance.clone());
+        Ok(instance)
+    }
+
+    pub fn instances(&self) -> &[Instance] {
+        &self.instances
+    }
+
+    /// Stops all temporary fullnodes and scales hosts back to the count before they were
+    /// spawned. Fullnodes which fail to stop, and their hosts, are left to cluster cleanup
+    pub async fn teardown(&mut self, cluster_swarm: &dyn ClusterSwarm) {
+        let results = join_all(self.instances.iter().map(Instance::stop)).await;
+        let mut stopped = vec![];
+        for (instance, result) in self.instances.drain(..).zip(results) {
+            match result {
+                Ok(()) => {
+                    info!("Stopped temporary fullnode {}", instance);
+                    stopped.push(instance);
+                }
+                Err(e) => warn!("Failed to stop temporary fullnode {}: {}", instance, e),
+            }
+        }
+        if let Some(host_count) = self.host_count.take() {
+            if let Err(e) = cluster_swarm.release_hosts(host_count, &stopped).await {
+                warn!("Failed to release hosts of temporary fullnodes: {}", e);
+            }
+        }
+    }
 }
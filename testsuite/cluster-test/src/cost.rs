@@ -0,0 +1,96 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Estimates cloud cost of experiments as instance-hours consumed times hourly rates read
+/// from config file. Instances created by experiments on top of the cluster are accounted by
+/// experiments themselves by reporting `extra_instance_hours` metric
+use crate::{cluster::Cluster, report::SuiteReport};
+use anyhow::{format_err, Result};
+use serde::Deserialize;
+use std::{fs, time::Duration};
+
+pub const EXTRA_INSTANCE_HOURS: &str = "extra_instance_hours";
+
+/// Example config:
+/// ```yaml
+/// validator: 0.68
+/// fullnode: 0.34
+/// extra_instance: 0.68
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct CostRates {
+    /// USD per instance hour
+    pub validator: f64,
+    #[serde(default)]
+    pub fullnode: f64,
+    #[serde(default)]
+    pub lsr: f64,
+    #[serde(default)]
+    pub vault: f64,
+    #[serde(default)]
+    pub extra_instance: f64,
+}
+
+impl CostRates {
+    pub fn from_file(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format_err!("Failed to read cost rates file {}: {}", path, e))?;
+        serde_yaml::from_str(&content)
+            .map_err(|e| format_err!("Failed to parse cost rates file {}: {}", path, e))
+    }
+
+    /// Cost of running the cluster for an hour, in USD
+    pub fn cluster_hourly_cost(&self, cluster: &Cluster) -> f64 {
+        cluster.validator_instances().len() as f64 * self.validator
+            + cluster.fullnode_instances().len() as f64 * self.fullnode
+            + cluster.lsr_instances().len() as f64 * self.lsr
+            + cluster.vault_instances().len() as f64 * self.vault
+    }
+
+    /// Reports estimated cost of experiment which ran for `duration` on the cluster,
+    /// including extra instances reported by experiment itself
+    pub fn report_experiment_cost(
+        &self,
+        report: &mut SuiteReport,
+        cluster: &Cluster,
+        experiment: &str,
+        duration: Duration,
+    ) {
+        let extra_instance_hours: f64 = report
+            .metrics()
+            .iter()
+            .filter(|m| m.experiment == experiment && m.metric == EXTRA_INSTANCE_HOURS)
+            .map(|m| m.value)
+            .sum();
+        let cost = self.cluster_hourly_cost(cluster) * duration.as_secs_f64() / 3600.0
+            + extra_instance_hours * self.extra_instance;
+        report.report_metric(experiment, "cost_usd", cost);
+    }
+}
+
+/// Reports total cost of the suite and cost per million committed transactions
+pub fn report_suite_cost(report: &mut SuiteReport) {
+    let sum = |report: &SuiteReport, name: &str| -> f64 {
+        report
+            .metrics()
+            .iter()
+            .filter(|m| m.experiment != "suite" && m.metric == name)
+            .map(|m| m.value)
+            .sum()
+    };
+    let cost = sum(report, "cost_usd");
+    if cost == 0.0 {
+        return;
+    }
+    let committed = sum(report, "committed_txn");
+    report.report_metric("suite", "cost_usd", cost);
+    let mut text = format!("Estimated cost: ${:.2}", cost);
+    if committed > 0.0 {
+        let cost_per_million = cost * 1_000_000.0 / committed;
+        report.report_metric("suite", "cost_per_million_txn", cost_per_million);
+        text.push_str(&format!(", ${:.2} per million txns", cost_per_million));
+    }
+    report.report_text(text);
+}
@@ -0,0 +1,112 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Transactions the emitter failed to submit or which expired, one JSON object per line. Signed
+/// transaction is hex of its LCS bytes, the same as JSON-RPC `submit` takes, so a single failure
+/// seen only at scale can be replayed against a local node
+use crate::util::unix_timestamp_now;
+use anyhow::{format_err, Result};
+use libra_logger::warn;
+use libra_types::transaction::SignedTransaction;
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+pub struct DeadLetters {
+    path: PathBuf,
+    file: Mutex<File>,
+    max_entries: usize,
+    /// Entries recorded so far, including ones dropped over the cap
+    entries: AtomicUsize,
+}
+
+#[derive(Serialize)]
+struct DeadLetter<'a> {
+    /// `rejected` by the endpoint, `submit_error` if it did not respond, or `expired`
+    kind: &'a str,
+    sender: String,
+    sequence_number: u64,
+    endpoint: &'a str,
+    error: &'a str,
+    submitted_at_ms: u128,
+    failed_at_ms: u128,
+    signed_txn: String,
+}
+
+impl DeadLetters {
+    /// Existing file is truncated, so that it only holds failures of this run
+    pub fn create(path: &Path, max_entries: usize) -> Result<Self> {
+        let file = File::create(path)
+            .map_err(|e| format_err!("Failed to create dead letter file {:?}: {}", path, e))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+            max_entries,
+            entries: AtomicUsize::new(0),
+        })
+    }
+
+    /// `submitted_at` is unix time of submission, entries over `max_entries` are dropped
+    pub fn record(
+        &self,
+        kind: &str,
+        txn: &SignedTransaction,
+        endpoint: &str,
+        error: &str,
+        submitted_at: Duration,
+    ) {
+        let entry = self.entries.fetch_add(1, Ordering::Relaxed);
+        if entry >= self.max_entries {
+            if entry == self.max_entries {
+                warn!(
+                    "Dead letter file {:?} reached {} entries, further failures are not recorded",
+                    self.path, self.max_entries
+                );
+            }
+            return;
+        }
+        let signed_txn = match lcs::to_bytes(txn) {
+            Ok(bytes) => hex::encode(bytes),
+            Err(e) => {
+                warn!("Failed to serialize dead letter transaction: {}", e);
+                return;
+            }
+        };
+        let letter = DeadLetter {
+            kind,
+            sender: txn.sender().to_string(),
+            sequence_number: txn.sequence_number(),
+            endpoint,
+            error,
+            submitted_at_ms: submitted_at.as_millis(),
+            failed_at_ms: unix_timestamp_now().as_millis(),
+            signed_txn,
+        };
+        let mut file = self.file.lock().expect("dead letter lock poisoned");
+        let result = serde_json::to_string(&letter)
+            .map_err(Into::into)
+            .and_then(|line| writeln!(file, "{}", line));
+        if let Err(e) = result {
+            warn!("Failed to write dead letter to {:?}: {}", self.path, e);
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of failures seen, recorded or not
+    pub fn entries(&self) -> usize {
+        self.entries.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,298 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Snapshot of the cluster state for quick triage: versions, mempool size and peer counts of
+/// every validator and fullnode, and consensus state of validators when an experiment fails on
+/// liveness. Metrics are read from the debug interface of each instance, which is the same
+/// source prometheus scrapes, except for safety rules metrics which are only pushed to the
+/// pushgateway
+use crate::{
+    cluster::Cluster, health::UnhealthyValidators, instance::Instance, prometheus::Prometheus,
+    report::SuiteReport, util::unix_timestamp_now,
+};
+use chrono::Utc;
+use futures::future::join_all;
+use libra_logger::info;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, time::Duration};
+
+/// Health checks which fail when validators stop committing
+const LIVENESS_CHECKS: [&str; 2] = ["liveness_check", "progress_check"];
+/// Safety rules push their metrics every 15 secs, latest push within this window is taken
+const PUSHED_METRICS_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Serialize)]
+pub struct InstanceState {
+    pub instance: String,
+    pub uptime_secs: Option<i64>,
+    /// Latest version reported by json rpc
+    pub latest_version: Option<u64>,
+    pub committed_version: Option<i64>,
+    pub synced_version: Option<i64>,
+    pub mempool_size: Option<i64>,
+    pub connected_peers: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClusterState {
+    pub instances: Vec<InstanceState>,
+}
+
+impl ClusterState {
+    pub async fn collect(cluster: &Cluster) -> Self {
+        let futures = cluster
+            .validator_and_fullnode_instances()
+            .map(InstanceState::collect);
+        Self {
+            instances: join_all(futures).await,
+        }
+    }
+}
+
+impl InstanceState {
+    async fn collect(instance: &Instance) -> Self {
+        let uptime_secs = instance
+            .start_time()
+            .await
+            .ok()
+            .map(|start_time| (Utc::now() - start_time).num_seconds());
+        let latest_version = instance.latest_version().await.ok();
+        let metrics = if instance.debug_interface_port().is_some() {
+            instance
+                .debug_interface_client()
+                .get_node_metrics()
+                .await
+                .unwrap_or_default()
+        } else {
+            HashMap::new()
+        };
+        let connected_peers = metrics
+            .iter()
+            .filter(|(k, _)| {
+                k.starts_with("libra_network_peers{") && k.ends_with("state=connected}")
+            })
+            .map(|(_, v)| *v)
+            .fold(None, |acc, v| Some(acc.unwrap_or(0) + v));
+        Self {
+            instance: instance.to_string(),
+            uptime_secs,
+            latest_version,
+            committed_version: metrics
+                .get("libra_state_sync_version{type=committed}")
+                .cloned(),
+            synced_version: metrics
+                .get("libra_state_sync_version{type=synced}")
+                .cloned(),
+            mempool_size: metrics
+                .get("libra_core_mempool_index_size{index=system_ttl}")
+                .cloned(),
+            connected_peers,
+        }
+    }
+}
+
+impl fmt::Display for ClusterState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<30} {:>10} {:>12} {:>12} {:>12} {:>10} {:>6}",
+            "instance", "uptime", "latest", "committed", "synced", "mempool", "peers"
+        )?;
+        for state in &self.instances {
+            writeln!(
+                f,
+                "{:<30} {:>10} {:>12} {:>12} {:>12} {:>10} {:>6}",
+                state.instance,
+                or_dash(state.uptime_secs),
+                or_dash(state.latest_version),
+                or_dash(state.committed_version),
+                or_dash(state.synced_version),
+                or_dash(state.mempool_size),
+                or_dash(state.connected_peers),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Consensus state of every validator, attached to the report of an experiment which failed on
+/// liveness so that it can be triaged without reproducing it
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ConsensusSnapshot {
+    pub title: String,
+    pub validators: Vec<ValidatorConsensusState>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ValidatorConsensusState {
+    pub instance: String,
+    pub epoch: Option<i64>,
+    pub current_round: Option<i64>,
+    pub last_voted_round: Option<i64>,
+    /// Round of the highest 2-chain head, closest to the highest QC among exported metrics
+    pub preferred_round: Option<i64>,
+    pub last_committed_round: Option<i64>,
+    pub timeouts: Option<i64>,
+    pub committed_version: Option<i64>,
+    pub synced_version: Option<i64>,
+    pub upstream_peers: Option<i64>,
+}
+
+impl ConsensusSnapshot {
+    pub async fn collect(cluster: &Cluster, prometheus: &Prometheus, title: String) -> Self {
+        let last_voted_rounds =
+            safety_rules_gauge(prometheus, "libra_safety_rules_last_voted_round");
+        let preferred_rounds = safety_rules_gauge(prometheus, "libra_safety_rules_preferred_round");
+        let futures = cluster.validator_instances().iter().map(|instance| {
+            ValidatorConsensusState::collect(instance, &last_voted_rounds, &preferred_rounds)
+        });
+        Self {
+            title,
+            validators: join_all(futures).await,
+        }
+    }
+}
+
+impl ValidatorConsensusState {
+    /// Fields are left out for validators whose debug interface is not reachable, which is a
+    /// finding of its own
+    async fn collect(
+        instance: &Instance,
+        last_voted_rounds: &HashMap<String, i64>,
+        preferred_rounds: &HashMap<String, i64>,
+    ) -> Self {
+        let metrics = if instance.debug_interface_port().is_some() {
+            instance
+                .debug_interface_client()
+                .get_node_metrics()
+                .await
+                .unwrap_or_default()
+        } else {
+            HashMap::new()
+        };
+        let get = |name: &str| metrics.get(name).cloned();
+        Self {
+            instance: instance.to_string(),
+            epoch: get("libra_consensus_epoch{}"),
+            current_round: get("libra_consensus_current_round{}"),
+            last_voted_round: last_voted_rounds.get(instance.peer_name()).cloned(),
+            preferred_round: preferred_rounds.get(instance.peer_name()).cloned(),
+            last_committed_round: get("libra_consensus_last_committed_round{}"),
+            timeouts: get("libra_consensus_timeout_count{}"),
+            committed_version: get("libra_state_sync_version{type=committed}"),
+            synced_version: get("libra_state_sync_version{type=synced}"),
+            upstream_peers: get("libra_state_sync_active_upstream_peers{}"),
+        }
+    }
+}
+
+impl fmt::Display for ConsensusSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.title)?;
+        writeln!(
+            f,
+            "{:<30} {:>6} {:>8} {:>8} {:>10} {:>10} {:>9} {:>12} {:>12} {:>6}",
+            "validator",
+            "epoch",
+            "round",
+            "voted",
+            "preferred",
+            "committed",
+            "timeouts",
+            "committed_v",
+            "synced_v",
+            "peers"
+        )?;
+        for state in &self.validators {
+            writeln!(
+                f,
+                "{:<30} {:>6} {:>8} {:>8} {:>10} {:>10} {:>9} {:>12} {:>12} {:>6}",
+                state.instance,
+                or_dash(state.epoch),
+                or_dash(state.current_round),
+                or_dash(state.last_voted_round),
+                or_dash(state.preferred_round),
+                or_dash(state.last_committed_round),
+                or_dash(state.timeouts),
+                or_dash(state.committed_version),
+                or_dash(state.synced_version),
+                or_dash(state.upstream_peers),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Latest pushed value of safety rules `gauge` by validator. Safety rules only push metrics when
+/// they run as LSR instance `lsr-<i>`, which serves validator `val-<i>`
+fn safety_rules_gauge(prometheus: &Prometheus, gauge: &str) -> HashMap<String, i64> {
+    let end = unix_timestamp_now();
+    let start = end.checked_sub(PUSHED_METRICS_WINDOW).unwrap_or_default();
+    let data = match prometheus.query_range_raw(gauge.to_string(), &start, &end, 15) {
+        Ok(data) => data,
+        Err(e) => {
+            info!("Failed to query {}: {}", gauge, e);
+            return HashMap::new();
+        }
+    };
+    let mut values = HashMap::new();
+    for series in data["result"].as_array().into_iter().flatten() {
+        let metric = &series["metric"];
+        // Pushgateway keeps the pushed instance label as exported_instance unless prometheus
+        // honors labels of scraped series
+        let lsr = metric["exported_instance"]
+            .as_str()
+            .or_else(|| metric["instance"].as_str())
+            .and_then(|instance| instance.strip_prefix("lsr-"));
+        let value = series["values"]
+            .as_array()
+            .and_then(|values| values.last())
+            .and_then(|value| value[1].as_str())
+            .and_then(|value| value.parse::<f64>().ok());
+        if let (Some(index), Some(value)) = (lsr, value) {
+            values.insert(format!("val-{}", index), value as i64);
+        }
+    }
+    values
+}
+
+/// Whether validators stopped committing, as found by liveness or progress health check
+pub fn is_liveness_failure(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<UnhealthyValidators>()
+            .map_or(false, |unhealthy| {
+                LIVENESS_CHECKS
+                    .iter()
+                    .any(|check| unhealthy.checks.contains(check))
+            })
+    })
+}
+
+/// Attaches consensus snapshot of validators to `report` if `error` of `experiment` is a
+/// liveness failure
+pub async fn attach_on_liveness_failure(
+    cluster: &Cluster,
+    prometheus: &Prometheus,
+    report: &mut SuiteReport,
+    experiment: &str,
+    error: &anyhow::Error,
+) {
+    if !is_liveness_failure(error) {
+        return;
+    }
+    let snapshot = ConsensusSnapshot::collect(
+        cluster,
+        prometheus,
+        format!("Liveness failure of {}", experiment),
+    )
+    .await;
+    info!("{}", snapshot);
+    report.report_consensus_snapshot(snapshot);
+}
+
+fn or_dash<T: ToString>(v: Option<T>) -> String {
+    v.map_or_else(|| "-".to_string(), |v| v.to_string())
+}
@@ -0,0 +1,64 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// ClockSkew moves system clock of the host of given instance by given number of seconds,
+/// clock is moved back by the same amount on deactivation
+use crate::{effects::Effect, instance::Instance, util::unix_timestamp_now};
+use anyhow::{bail, format_err, Result};
+
+use async_trait::async_trait;
+use libra_logger::info;
+use std::fmt;
+
+/// Allowed difference of observed clock offset from the skew, covers latency of exec
+const MAX_OFFSET_ERROR_SECS: i64 = 5;
+
+pub struct ClockSkew {
+    instance: Instance,
+    offset_secs: i64,
+}
+
+impl ClockSkew {
+    pub fn new(instance: Instance, offset_secs: i64) -> Self {
+        Self {
+            instance,
+            offset_secs,
+        }
+    }
+}
+
+#[async_trait]
+impl Effect for ClockSkew {
+    async fn activate(&mut self) -> Result<()> {
+        info!("{}", self);
+        let cmd = format!("date -s @$(( $(date +%s) + {} ))", self.offset_secs);
+        self.instance.util_cmd(cmd, "clock-skew").await
+    }
+
+    async fn deactivate(&mut self) -> Result<()> {
+        info!("Removing clock skew for {}", self.instance);
+        let cmd = format!("date -s @$(( $(date +%s) - {} ))", self.offset_secs);
+        self.instance.util_cmd(cmd, "de-clock-skew").await
+    }
+
+    async fn verify(&mut self) -> Result<()> {
+        let output = self.instance.exec_output("date +%s").await?;
+        let remote: i64 = output
+            .trim()
+            .parse()
+            .map_err(|e| format_err!("failed to parse time {:?}: {}", output, e))?;
+        let offset = remote - unix_timestamp_now().as_secs() as i64;
+        if (offset - self.offset_secs).abs() > MAX_OFFSET_ERROR_SECS {
+            bail!("clock is {}s off", offset);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ClockSkew {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ClockSkew {}s for {}", self.offset_secs, self.instance)
+    }
+}
@@ -0,0 +1,62 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// CpuBurn runs given number of busy loops inside the container of given instance
+use crate::{effects::Effect, instance::Instance};
+use anyhow::{bail, Result};
+
+use async_trait::async_trait;
+use libra_logger::info;
+use std::fmt;
+
+pub const PID_FILE: &str = "/tmp/cluster-test-cpu-burn.pids";
+
+pub struct CpuBurn {
+    instance: Instance,
+    threads: usize,
+}
+
+impl CpuBurn {
+    pub fn new(instance: Instance, threads: usize) -> Self {
+        Self { instance, threads }
+    }
+}
+
+#[async_trait]
+impl Effect for CpuBurn {
+    async fn activate(&mut self) -> Result<()> {
+        info!("{}", self);
+        let cmd = format!(
+            "for i in $(seq 1 {}); do nohup sh -c 'while :; do :; done' > /dev/null 2>&1 & echo $! >> {}; done",
+            self.threads, PID_FILE
+        );
+        self.instance.exec(&cmd, true).await
+    }
+
+    async fn deactivate(&mut self) -> Result<()> {
+        info!("Stopping cpu burn for {}", self.instance);
+        let cmd = format!("kill $(cat {0}); rm -f {0}", PID_FILE);
+        self.instance.exec(&cmd, true).await
+    }
+
+    /// Counts burning processes which are still alive
+    async fn verify(&mut self) -> Result<()> {
+        let cmd = format!(
+            "for p in $(cat {}); do kill -0 $p 2>/dev/null && echo $p; done | wc -l",
+            PID_FILE
+        );
+        let running: usize = self.instance.exec_output(&cmd).await?.trim().parse()?;
+        if running < self.threads {
+            bail!("{} of {} threads are running", running, self.threads);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for CpuBurn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CpuBurn {} threads for {}", self.threads, self.instance)
+    }
+}
@@ -0,0 +1,92 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// CpuQuota lowers CFS quota of the cgroup of libra-node on the host, so that the node gets at
+/// most given share of a core. Execution has no threads of its own, so the whole node is slowed
+/// down. Original quota is saved on the host and restored on deactivation
+use crate::{effects::Effect, instance::Instance};
+use anyhow::{bail, format_err, Result};
+
+use async_trait::async_trait;
+use libra_logger::info;
+use std::fmt;
+
+const SAVED_QUOTA_FILE: &str = "/tmp/cluster-test-cpu-quota.orig";
+/// Cgroup directory of libra-node in host mount namespace, commands run on the host with
+/// host pids visible
+const CGROUP_DIR: &str = "dir=/sys/fs/cgroup/cpu$(grep -E ':cpu(,cpuacct)?:|:cpuacct,cpu:' /proc/$(pgrep -x libra-node | head -1)/cgroup | head -1 | cut -d: -f3)";
+
+/// Restores saved quota, if any
+pub fn restore_cmd() -> String {
+    format!(
+        "{dir}; if nsenter -t 1 -m test -f {saved}; then nsenter -t 1 -m cat {saved} | nsenter -t 1 -m tee $dir/cpu.cfs_quota_us > /dev/null; nsenter -t 1 -m rm -f {saved}; fi",
+        dir = CGROUP_DIR,
+        saved = SAVED_QUOTA_FILE
+    )
+}
+
+pub struct CpuQuota {
+    instance: Instance,
+    millicores: u64,
+}
+
+impl CpuQuota {
+    pub fn new(instance: Instance, millicores: u64) -> Self {
+        Self {
+            instance,
+            millicores,
+        }
+    }
+}
+
+#[async_trait]
+impl Effect for CpuQuota {
+    async fn activate(&mut self) -> Result<()> {
+        info!("{}", self);
+        // Quota saved by an earlier activation is kept, it is the original one
+        let cmd = format!(
+            "set -e; {dir}; nsenter -t 1 -m test -f {saved} || nsenter -t 1 -m cat $dir/cpu.cfs_quota_us | nsenter -t 1 -m tee {saved} > /dev/null; period=$(nsenter -t 1 -m cat $dir/cpu.cfs_period_us); echo $((period * {millicores} / 1000)) | nsenter -t 1 -m tee $dir/cpu.cfs_quota_us > /dev/null",
+            dir = CGROUP_DIR,
+            saved = SAVED_QUOTA_FILE,
+            millicores = self.millicores
+        );
+        self.instance.util_cmd(cmd, "set-cpu-quota").await
+    }
+
+    async fn deactivate(&mut self) -> Result<()> {
+        info!("Restoring cpu quota for {}", self.instance);
+        self.instance
+            .util_cmd(format!("set -e; {}", restore_cmd()), "restore-cpu-quota")
+            .await
+    }
+
+    /// Quota is read through exec, which sees the cgroup of the node as its own in a container
+    async fn verify(&mut self) -> Result<()> {
+        let cmd = format!(
+            "{}; [ -f $dir/cpu.cfs_quota_us ] || dir=/sys/fs/cgroup/cpu; cat $dir/cpu.cfs_quota_us $dir/cpu.cfs_period_us",
+            CGROUP_DIR
+        );
+        let output = self.instance.exec_output(&cmd).await?;
+        let values = output
+            .split_whitespace()
+            .map(str::parse::<i64>)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format_err!("failed to parse cgroup values {:?}: {}", output, e))?;
+        if let [quota, period] = values[..] {
+            if quota != period * self.millicores as i64 / 1000 {
+                bail!("cpu quota is {} with period {}", quota, period);
+            }
+            Ok(())
+        } else {
+            bail!("unexpected cgroup values {:?}", output)
+        }
+    }
+}
+
+impl fmt::Display for CpuQuota {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CpuQuota {}m for {}", self.millicores, self.instance)
+    }
+}
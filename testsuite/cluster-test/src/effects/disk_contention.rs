@@ -0,0 +1,72 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// DiskContention runs a helper process next to libra-node which contends for its disk over and
+/// over: every SST file of the node is read and written again with direct I/O and fsync to a
+/// scratch directory on the same volume. This is I/O of the size of a full compaction, but the
+/// node itself does not compact, it competes with the helper for its own flushes and compactions
+use crate::{effects::Effect, instance::Instance};
+use anyhow::{bail, Result};
+
+use async_trait::async_trait;
+use libra_logger::info;
+use std::fmt;
+
+pub const PID_FILE: &str = "/tmp/cluster-test-disk-contention.pid";
+/// On the data volume, so that rewrites go to the same disk as the DB
+const SCRATCH_DIR: &str = "/opt/libra/data/cluster-test-disk-contention";
+
+pub struct DiskContention {
+    instance: Instance,
+}
+
+impl DiskContention {
+    pub fn new(instance: Instance) -> Self {
+        Self { instance }
+    }
+}
+
+/// Removes files rewritten by a helper which was killed without deactivation
+pub fn cleanup_cmd() -> String {
+    format!("rm -rf {}", SCRATCH_DIR)
+}
+
+#[async_trait]
+impl Effect for DiskContention {
+    async fn activate(&mut self) -> Result<()> {
+        info!("{}", self);
+        let cmd = format!(
+            "mkdir -p {dir}; nohup bash -c 'while true; do for f in $(find /opt/libra/data -name \"*.sst\" -not -path \"{dir}/*\"); do dd if=$f of={dir}/$(basename $f) bs=1M oflag=direct conv=fsync 2>/dev/null; done; rm -f {dir}/*; done' > /dev/null 2>&1 & echo $! > {pid_file}",
+            dir = SCRATCH_DIR,
+            pid_file = PID_FILE
+        );
+        self.instance.exec(&cmd, true).await
+    }
+
+    async fn deactivate(&mut self) -> Result<()> {
+        info!("Stopping disk contention on {}", self.instance);
+        let cmd = format!(
+            "pkill -P $(cat {0}); kill $(cat {0}); rm -f {0}; {1}",
+            PID_FILE,
+            cleanup_cmd()
+        );
+        self.instance.exec(&cmd, true).await
+    }
+
+    /// Helper exits right away if there are no SST files to rewrite
+    async fn verify(&mut self) -> Result<()> {
+        let cmd = format!("kill -0 $(cat {}) && echo running; true", PID_FILE);
+        if self.instance.exec_output(&cmd).await?.trim() != "running" {
+            bail!("helper is not running");
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DiskContention {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DiskContention on {}", self.instance)
+    }
+}
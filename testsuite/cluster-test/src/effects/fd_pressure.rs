@@ -0,0 +1,121 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// FdPressure runs a helper process next to libra-node which opens given number of idle TCP
+/// connections to a port of the node. Each connection holds a file descriptor in the node and
+/// an ephemeral port on the host
+use crate::{effects::Effect, instance::Instance};
+use anyhow::{bail, format_err, Result};
+
+use async_trait::async_trait;
+use libra_logger::info;
+use std::fmt;
+
+pub const PID_FILE: &str = "/tmp/cluster-test-fd-pressure.pid";
+const LOG_FILE: &str = "/tmp/cluster-test-fd-pressure.log";
+/// Node using this percent of its descriptor limit is considered out of descriptors
+const NODE_FD_LIMIT_PERCENT: usize = 95;
+
+pub struct FdPressure {
+    instance: Instance,
+    port: u32,
+    connections: usize,
+    /// Number of connections helper held when verified
+    held_connections: Option<usize>,
+}
+
+impl FdPressure {
+    pub fn new(instance: Instance, port: u32, connections: usize) -> Self {
+        Self {
+            instance,
+            port,
+            connections,
+            held_connections: None,
+        }
+    }
+
+    pub fn held_connections(&self) -> Option<usize> {
+        self.held_connections
+    }
+}
+
+#[async_trait]
+impl Effect for FdPressure {
+    async fn activate(&mut self) -> Result<()> {
+        info!("{}", self);
+        // Helper stops opening connections at first failure, e.g. when ports run out. It exits
+        // if its own descriptor limit can not be raised, which `verify` reports from the log
+        let cmd = format!(
+            "nohup bash -c 'ulimit -n {limit} || exit 1; for i in $(seq 1 {connections}); do exec {{fd}}<>/dev/tcp/{ip}/{port} || break; done; sleep 86400' > {log_file} 2>&1 & echo $! > {pid_file}",
+            limit = self.connections + 64,
+            connections = self.connections,
+            ip = self.instance.ip(),
+            port = self.port,
+            log_file = LOG_FILE,
+            pid_file = PID_FILE
+        );
+        self.instance.exec(&cmd, true).await
+    }
+
+    async fn deactivate(&mut self) -> Result<()> {
+        info!("Stopping fd pressure for {}", self.instance);
+        // Helper which already exited, e.g. on failed ulimit, is stopped too
+        let cmd = format!(
+            "if [ -f {pid_file} ]; then pid=$(cat {pid_file}); kill $pid 2>/dev/null || ! kill -0 $pid 2>/dev/null; fi && rm -f {pid_file} {log_file}",
+            pid_file = PID_FILE,
+            log_file = LOG_FILE
+        );
+        self.instance.exec(&cmd, true).await
+    }
+
+    /// Helper may stop short of `connections` only once node runs out of descriptors
+    async fn verify(&mut self) -> Result<()> {
+        let cmd = format!(
+            "node=$(pgrep -x libra-node | head -1); echo $(ls /proc/$(cat {})/fd 2>/dev/null | wc -l) $(ls /proc/$node/fd | wc -l) $(awk '/Max open files/ {{print $4}}' /proc/$node/limits)",
+            PID_FILE
+        );
+        let output = self.instance.exec_output(&cmd).await?;
+        let counts = output
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<std::result::Result<Vec<usize>, _>>()
+            .map_err(|e| format_err!("Failed to parse descriptor counts {:?}: {}", output, e))?;
+        let (helper_fds, node_fds, node_limit) = match counts.as_slice() {
+            [helper_fds, node_fds, node_limit] => (*helper_fds, *node_fds, *node_limit),
+            _ => bail!("Unexpected descriptor counts {:?}", output),
+        };
+        // stdin, stdout and stderr
+        let held = helper_fds.saturating_sub(3);
+        if held == 0 {
+            let log = self
+                .instance
+                .exec_output(&format!("cat {}", LOG_FILE))
+                .await
+                .unwrap_or_default();
+            bail!("helper holds no connections: {}", log.trim());
+        }
+        self.held_connections = Some(held);
+        if held < self.connections && node_fds * 100 < node_limit * NODE_FD_LIMIT_PERCENT {
+            bail!(
+                "helper holds {} of {} connections while node uses {} of {} descriptors",
+                held,
+                self.connections,
+                node_fds,
+                node_limit
+            );
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for FdPressure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "FdPressure {} connections to port {} of {}",
+            self.connections, self.port, self.instance
+        )
+    }
+}
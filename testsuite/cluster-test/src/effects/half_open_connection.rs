@@ -0,0 +1,89 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// HalfOpenConnection silently drops all packets between an instance and a peer. Unlike a
+/// rejected connection no RST is sent, so both sides keep connections which look open until
+/// they notice that nothing comes through
+use crate::{
+    effects::{self, ConnectProbe, Effect},
+    instance::Instance,
+};
+use anyhow::{bail, Result};
+
+use async_trait::async_trait;
+use libra_logger::{info, warn};
+use std::fmt;
+
+/// Comment of inserted iptables rules, so that they can be found and removed by `revert_all`
+pub const RULE_COMMENT: &str = "cluster-test-half-open";
+
+pub struct HalfOpenConnection {
+    instance: Instance,
+    peer: Instance,
+}
+
+impl HalfOpenConnection {
+    pub fn new(instance: Instance, peer: Instance) -> Self {
+        Self { instance, peer }
+    }
+
+    fn rules(&self) -> [String; 2] {
+        [
+            format!(
+                "INPUT -s {} -m comment --comment {} -j DROP",
+                self.peer.ip(),
+                RULE_COMMENT
+            ),
+            format!(
+                "OUTPUT -d {} -m comment --comment {} -j DROP",
+                self.peer.ip(),
+                RULE_COMMENT
+            ),
+        ]
+    }
+}
+
+#[async_trait]
+impl Effect for HalfOpenConnection {
+    async fn activate(&mut self) -> Result<()> {
+        info!("{}", self);
+        let [input, output] = self.rules();
+        let cmd = format!("iptables -I {} && iptables -I {}", input, output);
+        self.instance.util_cmd(cmd, "ac-half-open").await
+    }
+
+    async fn deactivate(&mut self) -> Result<()> {
+        info!("Removing {}", self);
+        let [input, output] = self.rules();
+        let cmd = format!("iptables -D {}; iptables -D {}; true", input, output);
+        self.instance.util_cmd(cmd, "de-half-open").await
+    }
+
+    async fn verify(&mut self) -> Result<()> {
+        match effects::probe_connect(&self.instance, &self.peer).await? {
+            ConnectProbe::Connected(ms) => {
+                bail!("connect to {} still succeeds in {} ms", self.peer, ms)
+            }
+            ConnectProbe::TimedOut => Ok(()),
+            ConnectProbe::Unavailable => {
+                warn!(
+                    "Can not probe connects from {}, {} is not verified",
+                    self.instance, self
+                );
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for HalfOpenConnection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "HalfOpenConnection between {} and {}",
+            self.instance, self.peer
+        )
+    }
+}
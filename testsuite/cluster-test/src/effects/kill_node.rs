@@ -0,0 +1,59 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// KillNode stops given instance and starts it again with existing data on deactivation
+use crate::{effects::Effect, instance::Instance};
+use anyhow::{bail, Result};
+
+use async_trait::async_trait;
+use libra_logger::info;
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+use tokio::time;
+
+/// Time for metrics endpoint of a killed instance to go away
+const STOP_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct KillNode {
+    instance: Instance,
+}
+
+impl KillNode {
+    pub fn new(instance: Instance) -> Self {
+        Self { instance }
+    }
+}
+
+#[async_trait]
+impl Effect for KillNode {
+    async fn activate(&mut self) -> Result<()> {
+        info!("Killing {}", self.instance);
+        self.instance.stop().await
+    }
+
+    async fn deactivate(&mut self) -> Result<()> {
+        info!("Starting {}", self.instance);
+        self.instance.start(false).await
+    }
+
+    async fn verify(&mut self) -> Result<()> {
+        let deadline = Instant::now() + STOP_TIMEOUT;
+        while self.instance.try_metrics().await.is_ok() {
+            if Instant::now() > deadline {
+                bail!("metrics are still served");
+            }
+            time::delay_for(Duration::from_secs(1)).await;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for KillNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Kill {}", self.instance)
+    }
+}
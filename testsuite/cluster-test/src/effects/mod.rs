@@ -3,26 +3,124 @@
 
 #![forbid(unsafe_code)]
 
-use anyhow::Result;
+use crate::{audit, instance::Instance, topology};
+use anyhow::{bail, Result};
 use async_trait::async_trait;
-use futures::future::try_join_all;
+use futures::future::{join_all, try_join_all};
 use std::fmt::Display;
 
+pub mod clock_skew;
+pub mod cpu_burn;
+pub mod cpu_quota;
+pub mod disk_contention;
+pub mod fd_pressure;
+pub mod half_open_connection;
+pub mod kill_node;
+pub mod network_bandwidth;
 pub mod network_delay;
 pub mod packet_loss;
+pub mod process_pause;
+pub mod storage_delay;
+
+/// Number of TCP connects of a probe, the fastest one is taken
+const CONNECT_PROBES: usize = 3;
+const CONNECT_TIMEOUT_SECS: u64 = 2;
+/// Printed by the probe instead of connect times when the instance lacks tools it needs
+const PROBE_UNAVAILABLE: &str = "probe-unavailable";
 
 #[async_trait]
-pub trait Effect: Display {
+pub trait Effect: Display + Send {
     async fn activate(&mut self) -> Result<()>;
     async fn deactivate(&mut self) -> Result<()>;
+    /// Checks after `activate` that the fault is observable, since experiment passing without
+    /// its fault gives false confidence. Effects which can not be observed cheaply, e.g. packet
+    /// loss or bandwidth limits, are not checked
+    async fn verify(&mut self) -> Result<()> {
+        Ok(())
+    }
 }
 
+/// Effects are deactivated again if any of them is not observable after activation
 pub async fn activate_all<T: Effect>(effects: &mut Vec<T>) -> Result<()> {
+    for effect in effects.iter() {
+        audit::record("activate", "effect", effect);
+        topology::fault_applied(effect);
+    }
     try_join_all(effects.iter_mut().map(Effect::activate)).await?;
+    let results = join_all(effects.iter_mut().map(Effect::verify)).await;
+    let failures: Vec<_> = effects
+        .iter()
+        .zip(results)
+        .filter_map(|(effect, result)| result.err().map(|e| format!("{}: {}", effect, e)))
+        .collect();
+    if !failures.is_empty() {
+        deactivate_all(effects).await?;
+        bail!("Faults were not applied: {}", failures.join(", "));
+    }
+    topology::faults_activated().await;
     Ok(())
 }
 
 pub async fn deactivate_all<T: Effect>(effects: &mut Vec<T>) -> Result<()> {
+    for effect in effects.iter() {
+        audit::record("deactivate", "effect", effect);
+        topology::fault_removed(effect);
+    }
     try_join_all(effects.iter_mut().map(Effect::deactivate)).await?;
     Ok(())
 }
+
+/// Reverts network, cgroup and process effects which may have been left active by an interrupted
+/// experiment. Safe to run on instance without active effects. Clock skew can not be reverted
+/// without knowing the offset, so it is left to ntp
+pub async fn revert_all(instance: &Instance) -> Result<()> {
+    audit::record("revert_effects", instance.peer_name(), "");
+    topology::faults_reverted(instance);
+    let cmd = format!(
+        "tc qdisc delete dev eth0 root; iptables-save | grep -v {} | iptables-restore; {}; {}; true",
+        half_open_connection::RULE_COMMENT,
+        cpu_quota::restore_cmd(),
+        storage_delay::STOP_CMD
+    );
+    instance.util_cmd(cmd, "revert-net").await?;
+    let cmd = format!(
+        "for f in {} {} {} {}; do [ -f $f ] && kill $(cat $f); rm -f $f; done; {}; {}; true",
+        cpu_burn::PID_FILE,
+        fd_pressure::PID_FILE,
+        process_pause::PID_FILE,
+        disk_contention::PID_FILE,
+        process_pause::RESUME_CMD,
+        disk_contention::cleanup_cmd()
+    );
+    instance.exec(&cmd, true).await
+}
+
+/// Outcome of `probe_connect`
+pub enum ConnectProbe {
+    /// Fastest of `CONNECT_PROBES` connects in ms
+    Connected(u64),
+    TimedOut,
+    /// Instance lacks bash, timeout or ns resolution of date, so the effect is left unverified
+    Unavailable,
+}
+
+/// Probes TCP connects from `from` to JSON-RPC port of `to`
+pub async fn probe_connect(from: &Instance, to: &Instance) -> Result<ConnectProbe> {
+    let cmd = format!(
+        "if ! command -v bash >/dev/null || ! command -v timeout >/dev/null || ! date +%N | grep -qE '^[0-9]+$'; then echo {unavailable}; exit 0; fi; bash -c 'for i in $(seq 1 {probes}); do s=$(date +%s%N); timeout {timeout} bash -c \"exec 3<>/dev/tcp/{ip}/{port}\" 2>/dev/null && echo $(( ($(date +%s%N) - s) / 1000000 )); done; true'",
+        probes = CONNECT_PROBES,
+        timeout = CONNECT_TIMEOUT_SECS,
+        ip = to.ip(),
+        port = to.ac_port(),
+        unavailable = PROBE_UNAVAILABLE
+    );
+    let output = from.exec_output(&cmd).await?;
+    if output.trim() == PROBE_UNAVAILABLE {
+        return Ok(ConnectProbe::Unavailable);
+    }
+    Ok(output
+        .lines()
+        .filter_map(|line| line.trim().parse::<u64>().ok())
+        .min()
+        .map_or(ConnectProbe::TimedOut, ConnectProbe::Connected))
+}
@@ -0,0 +1,71 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+use crate::effects::Effect;
+/// NetworkBandwidth limits egress bandwidth from a given instance to a provided list of instances
+/// Traffic to any other destination is not affected
+use crate::instance::Instance;
+use anyhow::Result;
+
+use async_trait::async_trait;
+use libra_logger::debug;
+use std::fmt;
+
+pub struct NetworkBandwidth {
+    instance: Instance,
+    targets: Vec<Instance>,
+    rate_mbit: u64,
+}
+
+impl NetworkBandwidth {
+    pub fn new(instance: Instance, targets: Vec<Instance>, rate_mbit: u64) -> Self {
+        Self {
+            instance,
+            targets,
+            rate_mbit,
+        }
+    }
+}
+
+#[async_trait]
+impl Effect for NetworkBandwidth {
+    async fn activate(&mut self) -> Result<()> {
+        debug!("Limiting bandwidth for {}", self);
+        let mut command = "".to_string();
+        // Unclassified traffic is not shaped by HTB https://linux.die.net/man/8/tc-htb
+        command += "tc qdisc add dev eth0 root handle 1: htb; ";
+        command += format!(
+            "tc class add dev eth0 parent 1: classid 1:1 htb rate {}mbit; ",
+            self.rate_mbit
+        )
+        .as_str();
+        for target_instance in &self.targets {
+            command += format!(
+                "tc filter add dev eth0 parent 1: protocol ip prio 1 u32 flowid 1:1 match ip dst {}; ",
+                target_instance.ip()
+            )
+            .as_str();
+        }
+        self.instance.util_cmd(command, "ac-net-bandwidth").await
+    }
+
+    async fn deactivate(&mut self) -> Result<()> {
+        self.instance
+            .util_cmd("tc qdisc delete dev eth0 root; true", "de-net-bandwidth")
+            .await
+    }
+}
+
+impl fmt::Display for NetworkBandwidth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "NetworkBandwidth {}mbit from {} to {} instances",
+            self.rate_mbit,
+            self.instance,
+            self.targets.len()
+        )
+    }
+}
@@ -3,16 +3,19 @@
 
 #![forbid(unsafe_code)]
 
-use crate::effects::Effect;
+use crate::effects::{self, ConnectProbe, Effect};
 /// NetworkDelay introduces network delay from a given instance to a provided list of instances
 /// If no instances are provided, network delay is introduced on all outgoing packets
 use crate::instance::Instance;
-use anyhow::Result;
+use anyhow::{bail, Result};
 
 use async_trait::async_trait;
-use libra_logger::debug;
+use libra_logger::{debug, warn};
 use std::{fmt, time::Duration};
 
+/// Connect to a delayed instance has to take at least this percent of the delay
+const MIN_OBSERVED_DELAY_PERCENT: u128 = 80;
+
 pub struct NetworkDelay {
     instance: Instance,
     // A vector of a pair of (delay, instance list)
@@ -69,6 +72,35 @@ impl Effect for NetworkDelay {
             .util_cmd("tc qdisc delete dev eth0 root; true", "de-net-delay")
             .await
     }
+
+    /// One instance of each delayed group is probed
+    async fn verify(&mut self) -> Result<()> {
+        for (instances, delay) in &self.configuration {
+            let target = match instances.first() {
+                Some(target) => target,
+                None => continue,
+            };
+            let min_ms = delay.as_millis() * MIN_OBSERVED_DELAY_PERCENT / 100;
+            match effects::probe_connect(&self.instance, target).await? {
+                ConnectProbe::Connected(ms) if ms as u128 >= min_ms => {}
+                ConnectProbe::Connected(ms) => bail!(
+                    "connect to {} took {} ms with {} ms delay",
+                    target,
+                    ms,
+                    delay.as_millis()
+                ),
+                ConnectProbe::TimedOut => bail!("can not connect to {}", target),
+                ConnectProbe::Unavailable => {
+                    warn!(
+                        "Can not probe connects from {}, {} is not verified",
+                        self.instance, self
+                    );
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 impl fmt::Display for NetworkDelay {
@@ -0,0 +1,76 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// ProcessPause runs a helper process next to libra-node which repeatedly freezes the node with
+/// SIGSTOP for given pause and resumes it with SIGCONT, like VM live migration or hypervisor
+/// stalls would
+use crate::{effects::Effect, instance::Instance};
+use anyhow::{format_err, Result};
+
+use async_trait::async_trait;
+use libra_logger::info;
+use std::{fmt, time::Duration};
+
+pub const PID_FILE: &str = "/tmp/cluster-test-process-pause.pid";
+/// Resumes libra-node, in case helper was killed while the node was stopped
+pub const RESUME_CMD: &str = "pkill -CONT -x libra-node";
+
+pub struct ProcessPause {
+    instance: Instance,
+    pause: Duration,
+    interval: Duration,
+}
+
+impl ProcessPause {
+    /// Node runs for `interval` between pauses
+    pub fn new(instance: Instance, pause: Duration, interval: Duration) -> Self {
+        Self {
+            instance,
+            pause,
+            interval,
+        }
+    }
+}
+
+#[async_trait]
+impl Effect for ProcessPause {
+    async fn activate(&mut self) -> Result<()> {
+        info!("{}", self);
+        let cmd = format!(
+            "nohup sh -c 'while :; do sleep {interval}; pkill -STOP -x libra-node; sleep {pause}; {resume}; done' > /dev/null 2>&1 & echo $! > {pid_file}",
+            interval = self.interval.as_secs_f64(),
+            pause = self.pause.as_secs_f64(),
+            resume = RESUME_CMD,
+            pid_file = PID_FILE
+        );
+        self.instance.exec(&cmd, true).await
+    }
+
+    async fn deactivate(&mut self) -> Result<()> {
+        info!("Stopping process pause for {}", self.instance);
+        let cmd = format!("kill $(cat {0}); rm -f {0}; {1}", PID_FILE, RESUME_CMD);
+        self.instance.exec(&cmd, true).await
+    }
+
+    /// Pauses start after the first interval, so only the helper pausing node is checked
+    async fn verify(&mut self) -> Result<()> {
+        self.instance
+            .exec(&format!("kill -0 $(cat {})", PID_FILE), true)
+            .await
+            .map_err(|_| format_err!("pausing helper is not running"))
+    }
+}
+
+impl fmt::Display for ProcessPause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ProcessPause {:.1}s every {:.1}s for {}",
+            self.pause.as_secs_f64(),
+            self.interval.as_secs_f64(),
+            self.instance
+        )
+    }
+}
@@ -0,0 +1,100 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// StorageDelay attaches strace to libra-node and delays return of its sync syscalls, so that
+/// every fsync of RocksDB takes at least given time, like on a slow disk. Syscalls are traced
+/// per process rather than per directory, but the DB is the only thing the node syncs
+use crate::{effects::Effect, instance::Instance};
+use anyhow::{bail, Result};
+
+use async_trait::async_trait;
+use libra_logger::{debug, info};
+use std::{fmt, time::Duration};
+use tokio::time;
+
+/// Detaches tracers left by an interrupted experiment, run by a util job
+pub const STOP_CMD: &str = "pkill -x strace";
+const NODE_PID: &str = "$(pgrep -x libra-node | head -1)";
+/// Number of checks with util job, which take a few secs each, until tracer is attached
+const ATTACH_CHECKS: usize = 10;
+
+pub struct StorageDelay {
+    instance: Instance,
+    syscalls: String,
+    delay: Duration,
+}
+
+impl StorageDelay {
+    /// `syscalls` is comma separated, e.g. fsync,fdatasync. Zero delay only traces them, which
+    /// tells overhead of tracing apart from the delay
+    pub fn new(instance: Instance, syscalls: String, delay: Duration) -> Self {
+        Self {
+            instance,
+            syscalls,
+            delay,
+        }
+    }
+}
+
+#[async_trait]
+impl Effect for StorageDelay {
+    async fn activate(&mut self) -> Result<()> {
+        info!("{}", self);
+        let cmd = format!(
+            "strace -f -qq -o /dev/null -e trace={syscalls} -e inject={syscalls}:delay_exit={delay} -p {pid}; true",
+            syscalls = self.syscalls,
+            delay = self.delay.as_micros(),
+            pid = NODE_PID
+        );
+        // Tracer runs until deactivation, longer than util_cmd waits for a job, so its job
+        // is left running and attachment is checked by verify
+        let instance = self.instance.clone();
+        tokio::spawn(async move {
+            if let Err(e) = instance.util_cmd(cmd, "ac-storage-delay").await {
+                debug!("Tracer job of {} did not complete: {}", instance, e);
+            }
+        });
+        Ok(())
+    }
+
+    async fn deactivate(&mut self) -> Result<()> {
+        info!("Stopping storage delay for {}", self.instance);
+        self.instance
+            .util_cmd(format!("{}; true", STOP_CMD), "de-storage-delay")
+            .await
+    }
+
+    /// Status of the node in its own pid namespace hides the tracer, so it is checked on host
+    async fn verify(&mut self) -> Result<()> {
+        let cmd = format!(
+            "grep -q 'TracerPid:[[:space:]]*[1-9]' /proc/{}/status",
+            NODE_PID
+        );
+        for _ in 0..ATTACH_CHECKS {
+            if self
+                .instance
+                .util_cmd(&cmd, "verify-storage-delay")
+                .await
+                .is_ok()
+            {
+                return Ok(());
+            }
+            time::delay_for(Duration::from_secs(2)).await;
+        }
+        bail!("strace is not attached to libra-node")
+    }
+}
+
+impl fmt::Display for StorageDelay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "StorageDelay {} ms on {} for {}",
+            self.delay.as_secs_f64() * 1000.0,
+            self.syscalls,
+            self.instance
+        )
+    }
+}
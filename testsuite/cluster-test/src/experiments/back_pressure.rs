@@ -0,0 +1,269 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which slows down validators by lowering their CPU quota
+/// while the cluster is under load, and verifies that sustained overload is absorbed by
+/// back-pressure: the cluster keeps committing, mempool stays within its capacity and no
+/// validator is restarted, e.g. after running out of memory
+use crate::{
+    cluster::Cluster,
+    effects::{self, cpu_quota::CpuQuota},
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::{self, Instance},
+    stats::PrometheusRangeView,
+    tx_emitter::EmitJobRequest,
+    util::unix_timestamp_now,
+};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use futures::future::{join_all, try_join_all};
+use libra_logger::{info, warn};
+use std::{collections::HashSet, fmt, time::Duration};
+use structopt::StructOpt;
+use tokio::time;
+
+#[derive(StructOpt, Debug)]
+pub struct BackPressureParams {
+    #[structopt(
+        long,
+        help = "Number of validators to slow down, all of them by default"
+    )]
+    pub count: Option<usize>,
+    #[structopt(
+        long,
+        default_value = "1000",
+        help = "CPU quota of slowed down validators in millicores"
+    )]
+    pub cpu_millicores: u64,
+    #[structopt(
+        long,
+        default_value = "120",
+        help = "Duration in secs of load before validators are slowed down"
+    )]
+    pub baseline_secs: u64,
+    #[structopt(
+        long,
+        default_value = "300",
+        help = "Duration in secs of load while validators are slowed down"
+    )]
+    pub duration_secs: u64,
+}
+
+pub struct BackPressure {
+    instances: Vec<Instance>,
+    validators: Vec<Instance>,
+    cpu_millicores: u64,
+    baseline: Duration,
+    duration: Duration,
+}
+
+impl ExperimentParam for BackPressureParams {
+    type E = BackPressure;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        let validators = cluster.validator_instances().to_vec();
+        let count = self.count.unwrap_or_else(|| validators.len());
+        let (slowed, _) = cluster.split_n_validators_random(count);
+        Self::E {
+            instances: slowed.into_validator_instances(),
+            validators,
+            cpu_millicores: self.cpu_millicores,
+            baseline: Duration::from_secs(self.baseline_secs),
+            duration: Duration::from_secs(self.duration_secs),
+        }
+    }
+}
+
+/// Committed throughput and block shape over a window
+struct Window {
+    committed_tps: u64,
+    txns_per_block: Option<f64>,
+    block_interval: Option<f64>,
+}
+
+#[async_trait]
+impl Experiment for BackPressure {
+    fn tags(&self) -> &'static [&'static str] {
+        &["process", "long"]
+    }
+
+    fn affected_validators(&self) -> HashSet<String> {
+        instance::instancelist_to_set(&self.instances)
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        // Mempool of any validator may not grow over the largest capacity deployed
+        let capacity = try_join_all(self.validators.iter().map(Instance::mempool_capacity))
+            .await?
+            .into_iter()
+            .max()
+            .unwrap_or(0) as f64;
+        let restarts_before = self.restart_counts().await;
+        let job = context
+            .tx_emitter
+            .start_job(EmitJobRequest::for_instances(
+                self.validators.clone(),
+                context.global_emit_job_request,
+            ))
+            .await?;
+        let baseline_start = unix_timestamp_now();
+        let start_stats = context.tx_emitter.peek_job_stats(&job);
+        time::delay_for(self.baseline).await;
+        let baseline_end = unix_timestamp_now();
+        let baseline_stats = context.tx_emitter.peek_job_stats(&job);
+
+        let mut effects: Vec<_> = self
+            .instances
+            .iter()
+            .map(|instance| CpuQuota::new(instance.clone(), self.cpu_millicores))
+            .collect();
+        let result = effects::activate_all(&mut effects).await;
+        if result.is_ok() {
+            time::delay_for(self.duration).await;
+        }
+        let overload_end = unix_timestamp_now();
+        let overload_stats = context.tx_emitter.peek_job_stats(&job);
+        let deactivated = effects::deactivate_all(&mut effects).await;
+        context.tx_emitter.stop_job(job).await;
+        result.and(deactivated)?;
+
+        let window = |start, end, committed| {
+            let pv = PrometheusRangeView::new(context.prometheus, start, end);
+            Window {
+                committed_tps: committed / (end - start).as_secs().max(1),
+                txns_per_block: pv.avg_txns_per_block(),
+                block_interval: pv.avg_block_interval(),
+            }
+        };
+        let baseline = window(
+            baseline_start,
+            baseline_end,
+            baseline_stats.committed - start_stats.committed,
+        );
+        let overload = window(
+            baseline_end,
+            overload_end,
+            overload_stats.committed - baseline_stats.committed,
+        );
+        let max_mempool_size = context
+            .prometheus
+            .query_range_max(
+                "libra_core_mempool_index_size{index=\"system_ttl\",peer_id=~\"val-.*\"}"
+                    .to_string(),
+                &baseline_end,
+                &overload_end,
+                10,
+            )
+            .ok();
+        let restarts_after = self.restart_counts().await;
+
+        for (metric, value) in [
+            (
+                "baseline_committed_tps",
+                Some(baseline.committed_tps as f64),
+            ),
+            (
+                "overload_committed_tps",
+                Some(overload.committed_tps as f64),
+            ),
+            ("baseline_txns_per_block", baseline.txns_per_block),
+            ("overload_txns_per_block", overload.txns_per_block),
+            (
+                "baseline_block_interval_ms",
+                baseline.block_interval.map(|i| i * 1000.0),
+            ),
+            (
+                "overload_block_interval_ms",
+                overload.block_interval.map(|i| i * 1000.0),
+            ),
+            ("max_mempool_size", max_mempool_size),
+        ]
+        .iter()
+        {
+            if let Some(value) = value {
+                context.report.report_metric(&self, *metric, *value);
+            }
+        }
+        let text = format!(
+            "{}: committed {} -> {} txn/s, txns per block {:?} -> {:?}, block interval {:?} -> {:?} secs, max mempool size {:?}",
+            self,
+            baseline.committed_tps,
+            overload.committed_tps,
+            baseline.txns_per_block,
+            overload.txns_per_block,
+            baseline.block_interval,
+            overload.block_interval,
+            max_mempool_size
+        );
+        info!("{}", text);
+        context.report.report_text(text);
+
+        // Slower execution has to show up as smaller or less frequent blocks
+        if let (Some(base_size), Some(size), Some(base_interval), Some(interval)) = (
+            baseline.txns_per_block,
+            overload.txns_per_block,
+            baseline.block_interval,
+            overload.block_interval,
+        ) {
+            if size >= base_size && interval <= base_interval {
+                warn!("Blocks neither shrank nor slowed down under overload");
+                context.report.report_text(format!(
+                    "(!) {} : no sign of back-pressure, blocks neither shrank nor slowed down",
+                    self
+                ));
+            }
+        }
+        if overload.committed_tps == 0 {
+            bail!("Cluster stopped committing while validators were slowed down");
+        }
+        if let Some(size) = max_mempool_size {
+            if size > capacity {
+                bail!("Mempool grew to {} over its capacity of {}", size, capacity);
+            }
+        }
+        for ((instance, before), after) in self
+            .instances
+            .iter()
+            .zip(restarts_before)
+            .zip(restarts_after)
+        {
+            if let (Some(before), Some(after)) = (before, after) {
+                if after > before {
+                    bail!(
+                        "{} was restarted {} times under overload",
+                        instance,
+                        after - before
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        self.baseline + self.duration + Duration::from_secs(5 * 60)
+    }
+}
+
+impl BackPressure {
+    /// Not available for ssh instances
+    async fn restart_counts(&self) -> Vec<Option<u32>> {
+        join_all(self.instances.iter().map(Instance::restart_count))
+            .await
+            .into_iter()
+            .map(Result::ok)
+            .collect()
+    }
+}
+
+impl fmt::Display for BackPressure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Back-pressure with {} validators limited to {}m cpu",
+            self.instances.len(),
+            self.cpu_millicores
+        )
+    }
+}
@@ -0,0 +1,289 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which upgrades a single validator to a candidate image
+/// while the rest of the cluster stays on the current one, and compares per validator metrics
+/// of the canary with the fleet under load. A canary which is slower as leader, votes late or
+/// uses more resources than its peers fails the experiment before the image is rolled out to
+/// the whole cluster
+use crate::{
+    cluster::Cluster,
+    experiments::{
+        compatibility_test::update_batch_instance, Context, Experiment, ExperimentParam,
+    },
+    instance::{self, Instance},
+    stats::PrometheusRangeView,
+    tx_emitter::EmitJobRequest,
+    util::unix_timestamp_now,
+};
+use anyhow::{bail, format_err, Result};
+use async_trait::async_trait;
+use futures::future::join_all;
+use libra_logger::{info, warn};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use tokio::time;
+
+/// Load before the measured window, so that the canary catches up after its restart
+const WARMUP: Duration = Duration::from_secs(60);
+/// Differences of small counts around zero are not regressions
+const MIN_DELTA: f64 = 2.0;
+/// Cumulative CPU time of the container in ns and RSS of libra-node in kB, read in the container
+const RESOURCE_CMD: &str = "cat /sys/fs/cgroup/cpuacct/cpuacct.usage; grep VmRSS /proc/$(pgrep -x libra-node | head -1)/status | awk '{print $2}'";
+
+#[derive(StructOpt, Debug)]
+pub struct CanaryParams {
+    #[structopt(long, help = "Image tag the canary validator is upgraded to")]
+    pub candidate_image_tag: String,
+    #[structopt(
+        long,
+        default_value = "300",
+        help = "Duration in secs of the measured window, after the canary warmed up"
+    )]
+    pub duration_secs: u64,
+    #[structopt(
+        long,
+        default_value = "20",
+        help = "Canary fails when a metric is worse than fleet median by more than given percent"
+    )]
+    pub max_regression_percent: f64,
+}
+
+pub struct Canary {
+    canary: Instance,
+    canary_lsr: Vec<Instance>,
+    validators: Vec<Instance>,
+    candidate_image_tag: String,
+    duration: Duration,
+    max_regression_percent: f64,
+}
+
+impl ExperimentParam for CanaryParams {
+    type E = Canary;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        let canary = cluster.random_validator_instance();
+        let canary_lsr = if cluster.lsr_instances().is_empty() {
+            vec![]
+        } else {
+            cluster.lsr_instances_for_validators(&[canary.clone()])
+        };
+        Self::E {
+            canary,
+            canary_lsr,
+            validators: cluster.validator_instances().to_vec(),
+            candidate_image_tag: self.candidate_image_tag,
+            duration: Duration::from_secs(self.duration_secs),
+            max_regression_percent: self.max_regression_percent,
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for Canary {
+    fn tags(&self) -> &'static [&'static str] {
+        &["performance"]
+    }
+
+    fn affected_validators(&self) -> HashSet<String> {
+        instance::instancelist_to_set(&[self.canary.clone()])
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        info!(
+            "Upgrading canary {} to {}",
+            self.canary, self.candidate_image_tag
+        );
+        update_batch_instance(
+            context,
+            &[self.canary.clone()],
+            &self.canary_lsr,
+            self.candidate_image_tag.clone(),
+        )
+        .await?;
+        let result = self.benchmark(context).await;
+        let current_tag = context.current_tag.to_string();
+        update_batch_instance(
+            context,
+            &[self.canary.clone()],
+            &self.canary_lsr,
+            current_tag,
+        )
+        .await?;
+        let regressions = result?;
+        if !regressions.is_empty() {
+            bail!(
+                "Canary {} on {} regressed against the fleet: {}",
+                self.canary,
+                self.candidate_image_tag,
+                regressions.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(2 * 480 + 5 * 60) + WARMUP + self.duration
+    }
+}
+
+impl Canary {
+    /// Runs load on all validators and returns metrics on which the canary regressed
+    async fn benchmark(&self, context: &mut Context<'_>) -> Result<Vec<String>> {
+        let started = Instant::now();
+        let job = context
+            .tx_emitter
+            .start_job(EmitJobRequest::for_instances(
+                self.validators.clone(),
+                context.global_emit_job_request,
+            ))
+            .await?;
+        time::delay_for(WARMUP).await;
+        let resources_start = join_all(self.validators.iter().map(resource_sample)).await;
+        let start = unix_timestamp_now();
+        time::delay_for(self.duration).await;
+        let end = unix_timestamp_now();
+        let resources_end = join_all(self.validators.iter().map(resource_sample)).await;
+        let stats = context.tx_emitter.stop_job(job).await;
+        context
+            .report
+            .report_txn_stats(self.to_string(), stats, started.elapsed());
+
+        let pv = PrometheusRangeView::new(context.prometheus, start, end);
+        let mut metrics = pv.validator_breakdown();
+        metrics.extend(pv.validator_latencies());
+        let (mut cpu_millicores, mut rss_mb) = (HashMap::new(), HashMap::new());
+        for ((instance, first), last) in self
+            .validators
+            .iter()
+            .zip(resources_start)
+            .zip(resources_end)
+        {
+            if let (Some((cpu_start, _)), Some((cpu_end, rss_kb))) = (first, last) {
+                // CPU ms per sec of the window is millicores
+                let cpu_ms = cpu_end.saturating_sub(cpu_start) as f64 / 1e6;
+                cpu_millicores.insert(
+                    instance.peer_name().clone(),
+                    cpu_ms / (end - start).as_secs_f64(),
+                );
+                rss_mb.insert(instance.peer_name().clone(), rss_kb as f64 / 1024.0);
+            }
+        }
+        metrics.push(("cpu_millicores", cpu_millicores));
+        metrics.push(("rss_mb", rss_mb));
+
+        let mut regressions = vec![];
+        let mut text = format!(
+            "{}: canary against fleet median, (!) marks regressions",
+            self
+        );
+        for (metric, values) in metrics {
+            let canary = match values.get(self.canary.peer_name()) {
+                Some(value) => *value,
+                None => {
+                    warn!("No {} data of canary {}", metric, self.canary);
+                    continue;
+                }
+            };
+            let fleet = match median(
+                values
+                    .iter()
+                    .filter(|(peer_id, _)| *peer_id != self.canary.peer_name())
+                    .map(|(_, value)| *value)
+                    .collect(),
+            ) {
+                Some(value) => value,
+                None => {
+                    warn!("No {} data of the fleet", metric);
+                    continue;
+                }
+            };
+            context
+                .report
+                .report_metric(&self, format!("canary_{}", metric), canary);
+            context
+                .report
+                .report_metric(&self, format!("fleet_{}", metric), fleet);
+            let regressed = worse_direction(metric).map_or(false, |sign| {
+                sign * (canary - fleet)
+                    > (self.max_regression_percent / 100.0 * fleet.abs()).max(MIN_DELTA)
+            });
+            text.push_str(&format!(
+                "\n  {}: canary {:.1}, fleet {:.1}",
+                metric, canary, fleet
+            ));
+            if fleet.abs() > f64::EPSILON {
+                text.push_str(&format!(" ({:+.1}%)", (canary - fleet) * 100.0 / fleet));
+            }
+            if regressed {
+                text.push_str(" (!)");
+                regressions.push(metric.to_string());
+            }
+        }
+        context
+            .report
+            .report_metric(&self, "regressions", regressions.len() as f64);
+        info!("{}", text);
+        context.report.report_text(text);
+        Ok(regressions)
+    }
+}
+
+/// Sign of the change which is a regression, None for metrics which only describe the load
+fn worse_direction(metric: &str) -> Option<f64> {
+    match metric {
+        // Leader reputation elects slow validators less often
+        "proposals" => Some(-1.0),
+        "nil_votes"
+        | "timeouts"
+        | "qc_latency_ms"
+        | "proposal_receival_ms"
+        | "cpu_millicores"
+        | "rss_mb" => Some(1.0),
+        _ => None,
+    }
+}
+
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).expect("Values are finite"));
+    Some(values[values.len() / 2])
+}
+
+/// CPU ns and RSS kB of `instance`, None if they could not be read
+async fn resource_sample(instance: &Instance) -> Option<(u64, u64)> {
+    let sample = async {
+        let output = instance.exec_output(RESOURCE_CMD).await?;
+        let values = output
+            .split_whitespace()
+            .map(str::parse::<u64>)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format_err!("failed to parse {:?}: {}", output, e))?;
+        match values[..] {
+            [cpu_ns, rss_kb] => Ok::<_, anyhow::Error>((cpu_ns, rss_kb)),
+            _ => bail!("unexpected output {:?}", output),
+        }
+    };
+    sample
+        .await
+        .map_err(|e| warn!("No resource usage of {}: {}", instance, e))
+        .ok()
+}
+
+impl fmt::Display for Canary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Canary {} on {}",
+            self.canary.peer_name(),
+            self.candidate_image_tag
+        )
+    }
+}
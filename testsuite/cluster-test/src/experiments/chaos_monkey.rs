@@ -0,0 +1,388 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides a long running experiment which injects faults picked at random from a
+/// weighted catalog at random times, while continuous load is emitted and health checks verify
+/// invariants on the validators which are never faulted. Fault schedule is derived from a seed,
+/// so any failure can be replayed by running the experiment with the same seed on a cluster of
+/// the same size
+use crate::{
+    audit,
+    cluster::Cluster,
+    effects::{
+        clock_skew::ClockSkew, cpu_burn::CpuBurn, kill_node::KillNode, packet_loss::PacketLoss,
+        Effect,
+    },
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::{self, Instance},
+    topology,
+    tx_emitter::EmitJobRequest,
+};
+use anyhow::{format_err, Result};
+use async_trait::async_trait;
+use libra_logger::{info, warn};
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    rngs::StdRng,
+    seq::SliceRandom,
+    Rng, SeedableRng,
+};
+use std::{
+    collections::HashSet,
+    fmt, mem,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use tokio::time::{delay_until, Instant as TokioInstant};
+
+/// Relative weights of faults in the catalog
+const KILL_WEIGHT: u32 = 3;
+const PACKET_LOSS_WEIGHT: u32 = 3;
+const CLOCK_SKEW_WEIGHT: u32 = 2;
+const CPU_BURN_WEIGHT: u32 = 2;
+
+#[derive(StructOpt, Debug)]
+pub struct ChaosMonkeyParams {
+    #[structopt(long, default_value = "3600", help = "Duration of the run in secs")]
+    pub duration_secs: u64,
+    #[structopt(long, help = "Seed of the fault schedule, random if not set")]
+    pub seed: Option<u64>,
+    #[structopt(
+        long,
+        default_value = "30",
+        help = "Min time in secs between starts of two faults"
+    )]
+    pub min_interval_secs: u64,
+    #[structopt(
+        long,
+        default_value = "120",
+        help = "Max time in secs between starts of two faults"
+    )]
+    pub max_interval_secs: u64,
+    #[structopt(long, default_value = "30", help = "Min duration of a fault in secs")]
+    pub min_fault_secs: u64,
+    #[structopt(long, default_value = "120", help = "Max duration of a fault in secs")]
+    pub max_fault_secs: u64,
+}
+
+pub struct ChaosMonkey {
+    params: ChaosMonkeyParams,
+    seed: u64,
+    /// At most f validators are faulted, so that cluster is expected to stay live
+    faulty: Vec<Instance>,
+    healthy: Vec<Instance>,
+    healthy_fullnodes: Vec<Instance>,
+    schedule: Vec<ScheduledFault>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Fault {
+    Kill,
+    PacketLoss(f32),
+    ClockSkew(i64),
+    CpuBurn(usize),
+}
+
+struct ScheduledFault {
+    start: Duration,
+    duration: Duration,
+    fault: Fault,
+    instance: Instance,
+}
+
+impl ExperimentParam for ChaosMonkeyParams {
+    type E = ChaosMonkey;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        if self.min_interval_secs == 0 || self.min_interval_secs > self.max_interval_secs {
+            panic!(
+                "Fault interval must be positive and min interval at most max interval, got {}..{}",
+                self.min_interval_secs, self.max_interval_secs
+            );
+        }
+        if self.min_fault_secs == 0 || self.min_fault_secs > self.max_fault_secs {
+            panic!(
+                "Fault duration must be positive and min duration at most max duration, got {}..{}",
+                self.min_fault_secs, self.max_fault_secs
+            );
+        }
+        if cluster.validator_instances().len() < 4 {
+            panic!("Chaos monkey needs at least 4 validators");
+        }
+        let seed = self.seed.unwrap_or_else(rand::random);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut validators = cluster.validator_instances().to_vec();
+        validators.shuffle(&mut rng);
+        let healthy = validators.split_off((validators.len() - 1) / 3);
+        let faulty = validators;
+        let healthy_fullnodes = cluster
+            .fullnode_instances()
+            .iter()
+            .filter(|f| {
+                healthy
+                    .iter()
+                    .any(|v| v.validator_group() == f.validator_group())
+            })
+            .cloned()
+            .collect();
+        let schedule = generate_schedule(&mut rng, &faulty, &self);
+        Self::E {
+            params: self,
+            seed,
+            faulty,
+            healthy,
+            healthy_fullnodes,
+            schedule,
+        }
+    }
+}
+
+/// Faults start at random intervals, each instance has at most one active fault at a time
+fn generate_schedule(
+    rng: &mut StdRng,
+    instances: &[Instance],
+    params: &ChaosMonkeyParams,
+) -> Vec<ScheduledFault> {
+    let weights = WeightedIndex::new(&[
+        KILL_WEIGHT,
+        PACKET_LOSS_WEIGHT,
+        CLOCK_SKEW_WEIGHT,
+        CPU_BURN_WEIGHT,
+    ])
+    .expect("Invalid fault weights");
+    let total = Duration::from_secs(params.duration_secs);
+    let mut busy_until = vec![Duration::from_secs(0); instances.len()];
+    let mut start = Duration::from_secs(0);
+    let mut schedule = vec![];
+    loop {
+        start += Duration::from_secs(
+            rng.gen_range(params.min_interval_secs, params.max_interval_secs + 1),
+        );
+        let duration =
+            Duration::from_secs(rng.gen_range(params.min_fault_secs, params.max_fault_secs + 1));
+        if start + duration > total {
+            break;
+        }
+        let free: Vec<_> = (0..instances.len())
+            .filter(|i| busy_until[*i] <= start)
+            .collect();
+        let index = match free.choose(rng) {
+            Some(index) => *index,
+            None => continue,
+        };
+        busy_until[index] = start + duration;
+        let fault = match weights.sample(rng) {
+            0 => Fault::Kill,
+            1 => Fault::PacketLoss(rng.gen_range(5.0, 30.0)),
+            2 => Fault::ClockSkew(rng.gen_range(-30, 31)),
+            _ => Fault::CpuBurn(rng.gen_range(1, 9)),
+        };
+        schedule.push(ScheduledFault {
+            start,
+            duration,
+            fault,
+            instance: instances[index].clone(),
+        });
+    }
+    schedule
+}
+
+#[async_trait]
+impl Experiment for ChaosMonkey {
+    fn tags(&self) -> &'static [&'static str] {
+        &["consensus", "network", "long"]
+    }
+
+    fn affected_validators(&self) -> HashSet<String> {
+        instance::instancelist_to_set(&self.faulty)
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        let schedule_text = self.schedule_text();
+        info!("{}", schedule_text);
+        context.report.report_text(schedule_text);
+
+        let instances = if context.emit_to_validator || self.healthy_fullnodes.is_empty() {
+            self.healthy.clone()
+        } else {
+            self.healthy_fullnodes.clone()
+        };
+        let job = context
+            .tx_emitter
+            .start_job(EmitJobRequest::for_instances(
+                instances,
+                context.global_emit_job_request,
+            ))
+            .await?;
+
+        let mut faults = Faults {
+            effects: self.schedule.iter().map(ScheduledFault::effect).collect(),
+            active: HashSet::new(),
+        };
+        let mut events: Vec<_> = self
+            .schedule
+            .iter()
+            .enumerate()
+            .flat_map(|(i, f)| vec![(f.start, true, i), (f.start + f.duration, false, i)])
+            .collect();
+        events.sort_by_key(|(at, activate, _)| (*at, *activate));
+
+        let started = Instant::now();
+        let mut result = Ok(());
+        for (at, activate, i) in events {
+            delay_until(TokioInstant::from_std(started + at)).await;
+            let effect_result = if activate {
+                faults.activate(i).await
+            } else {
+                faults.deactivate(i).await
+            };
+            if let Err(e) = effect_result {
+                result = Err(format_err!(
+                    "Fault {} failed: {}. {}",
+                    self.schedule[i],
+                    e,
+                    self.replay_hint()
+                ));
+                break;
+            }
+        }
+        faults.deactivate_active().await;
+        if result.is_ok() {
+            delay_until(TokioInstant::from_std(
+                started + Duration::from_secs(self.params.duration_secs),
+            ))
+            .await;
+        }
+
+        let stats = context.tx_emitter.stop_job(job).await;
+        context
+            .report
+            .report_txn_stats(self.to_string(), stats, started.elapsed());
+        result
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(self.params.duration_secs + 10 * 60)
+    }
+}
+
+impl ChaosMonkey {
+    fn schedule_text(&self) -> String {
+        let mut text = format!(
+            "{}: {} faults on {} validators. {}",
+            self,
+            self.schedule.len(),
+            self.faulty.len(),
+            self.replay_hint()
+        );
+        for fault in &self.schedule {
+            text.push_str(&format!("\n  {}", fault));
+        }
+        text
+    }
+
+    fn replay_hint(&self) -> String {
+        format!(
+            "Replay with: --run chaos_monkey -- --seed {} --duration-secs {} --min-interval-secs {} --max-interval-secs {} --min-fault-secs {} --max-fault-secs {}",
+            self.seed,
+            self.params.duration_secs,
+            self.params.min_interval_secs,
+            self.params.max_interval_secs,
+            self.params.min_fault_secs,
+            self.params.max_fault_secs
+        )
+    }
+}
+
+/// Effects of the schedule, those which are active are deactivated when the run ends however it
+/// ended. When the run is dropped on deadline they are deactivated by a spawned task
+struct Faults {
+    effects: Vec<Box<dyn Effect + Send>>,
+    active: HashSet<usize>,
+}
+
+impl Faults {
+    async fn activate(&mut self, i: usize) -> Result<()> {
+        let effect = &mut self.effects[i];
+        self.active.insert(i);
+        audit::record("activate", "effect", &*effect);
+        topology::fault_applied(&*effect);
+        effect.activate().await?;
+        effect.verify().await?;
+        topology::faults_activated().await;
+        Ok(())
+    }
+
+    async fn deactivate(&mut self, i: usize) -> Result<()> {
+        let effect = &mut self.effects[i];
+        self.active.remove(&i);
+        audit::record("deactivate", "effect", &*effect);
+        topology::fault_removed(&*effect);
+        effect.deactivate().await
+    }
+
+    async fn deactivate_active(&mut self) {
+        let active: Vec<_> = self.active.iter().cloned().collect();
+        for i in active {
+            if let Err(e) = self.deactivate(i).await {
+                warn!("Failed to deactivate {}: {}", self.effects[i], e);
+            }
+        }
+    }
+}
+
+impl Drop for Faults {
+    fn drop(&mut self) {
+        if self.active.is_empty() {
+            return;
+        }
+        let active = mem::take(&mut self.active);
+        let effects: Vec<_> = mem::take(&mut self.effects)
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| active.contains(i))
+            .map(|(_, effect)| effect)
+            .collect();
+        tokio::spawn(async move {
+            for mut effect in effects {
+                audit::record("deactivate", "effect", &effect);
+                topology::fault_removed(&effect);
+                if let Err(e) = effect.deactivate().await {
+                    warn!("Failed to deactivate {}: {}", effect, e);
+                }
+            }
+        });
+    }
+}
+
+impl ScheduledFault {
+    fn effect(&self) -> Box<dyn Effect + Send> {
+        let instance = self.instance.clone();
+        match self.fault {
+            Fault::Kill => Box::new(KillNode::new(instance)),
+            Fault::PacketLoss(percent) => Box::new(PacketLoss::new(instance, percent)),
+            Fault::ClockSkew(offset_secs) => Box::new(ClockSkew::new(instance, offset_secs)),
+            Fault::CpuBurn(threads) => Box::new(CpuBurn::new(instance, threads)),
+        }
+    }
+}
+
+impl fmt::Display for ScheduledFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "+{}s {:?} on {} for {}s",
+            self.start.as_secs(),
+            self.fault,
+            self.instance,
+            self.duration.as_secs()
+        )
+    }
+}
+
+impl fmt::Display for ChaosMonkey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Chaos monkey with seed {}", self.seed)
+    }
+}
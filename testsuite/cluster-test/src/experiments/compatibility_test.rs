@@ -8,7 +8,8 @@ use crate::{
     experiments::{Context, Experiment, ExperimentParam},
     instance,
     instance::Instance,
-    tx_emitter::EmitJobRequest,
+    report::ReportSection,
+    tx_emitter::{EmitJob, EmitJobRequest, TxStats},
 };
 use async_trait::async_trait;
 use futures::future::try_join_all;
@@ -22,13 +23,14 @@ use std::{
 use structopt::StructOpt;
 use tokio::time;
 
-/// Reboot `updated_instance` with newer image tag
+/// Reboot `updated_instance` with newer image tag. Returns time each of them took from deploy
+/// until its JSON-RPC responded
 pub async fn update_batch_instance(
     context: &mut Context<'_>,
     updated_instance: &[Instance],
     updated_lsr: &[Instance],
     updated_tag: String,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<Vec<Duration>> {
     let deadline = Instant::now() + Duration::from_secs(2 * 60);
 
     info!("Stop Existing instances.");
@@ -55,31 +57,90 @@ pub async fn update_batch_instance(
         time::delay_for(Duration::from_secs(20)).await;
     }
 
-    info!("Reinstantiate a set of new nodes.");
+    info!("Reinstantiate a set of new nodes and wait for them to recover.");
+    let cluster_swarm = context.cluster_swarm;
     let futures: Vec<_> = updated_instance
         .iter()
         .map(|instance| {
             let mut newer_config = instance.instance_config().clone();
             newer_config.replace_tag(updated_tag.clone()).unwrap();
-            context
-                .cluster_swarm
-                .spawn_new_instance(newer_config, false)
+            async move {
+                let started = Instant::now();
+                let instance = cluster_swarm
+                    .spawn_new_instance(newer_config, false)
+                    .await?;
+                instance.wait_json_rpc(deadline).await?;
+                Ok::<_, anyhow::Error>(started.elapsed())
+            }
         })
         .collect();
-    let instances = try_join_all(futures).await?;
-
-    info!("Wait for the instances to recover.");
-    let futures: Vec<_> = instances
-        .iter()
-        .map(|instance| instance.wait_json_rpc(deadline))
-        .collect();
-    try_join_all(futures).await?;
+    let rollout_times = try_join_all(futures).await?;
 
     // Add a timeout to have wait for validators back to healthy mode.
     // TODO: Replace this with a blocking health check.
     info!("Wait for the instance to sync up with peers");
     time::delay_for(Duration::from_secs(20)).await;
-    Ok(())
+    Ok(rollout_times)
+}
+
+/// Pulls images of `image_tag` on all hosts ahead of the upgrade, so that rollout times do not
+/// depend on how fast each host downloads them. Upgrade works without it, only slower
+pub async fn prepull_images(context: &mut Context<'_>, experiment: &str, image_tag: &str) {
+    info!("Pulling images of {} on all hosts", image_tag);
+    let started = Instant::now();
+    match context.cluster_swarm.prepull_images(image_tag).await {
+        Ok(()) => context.report.report_metric(
+            experiment,
+            "prepull_secs",
+            started.elapsed().as_secs_f64(),
+        ),
+        Err(e) => warn!("Failed to pull images of {}: {}", image_tag, e),
+    }
+}
+
+/// Reports rollout time of each instance of a batch, and max and average over the batch
+pub fn report_rollout_times(
+    context: &mut Context<'_>,
+    experiment: &str,
+    batch: &str,
+    instances: &[Instance],
+    rollout_times: &[Duration],
+) {
+    if rollout_times.is_empty() {
+        return;
+    }
+    let mut text = format!("   rollout of {}:", batch);
+    for (instance, rollout) in instances.iter().zip(rollout_times) {
+        context.report.report_metric(
+            experiment,
+            format!("rollout_secs_{}", instance.peer_name()),
+            rollout.as_secs_f64(),
+        );
+        text.push_str(&format!(
+            " {} {:.1}s,",
+            instance.peer_name(),
+            rollout.as_secs_f64()
+        ));
+    }
+    let max = rollout_times.iter().max().copied().unwrap_or_default();
+    let avg = rollout_times.iter().sum::<Duration>() / rollout_times.len() as u32;
+    context.report.report_metric(
+        experiment,
+        format!("{}_max_rollout_secs", batch),
+        max.as_secs_f64(),
+    );
+    context.report.report_metric(
+        experiment,
+        format!("{}_avg_rollout_secs", batch),
+        avg.as_secs_f64(),
+    );
+    text.push_str(&format!(
+        " max {:.1}s, avg {:.1}s",
+        max.as_secs_f64(),
+        avg.as_secs_f64()
+    ));
+    info!("{}", text);
+    context.report.report_text(text);
 }
 
 pub fn get_instance_list_str(batch: &[Instance]) -> String {
@@ -101,6 +162,11 @@ pub struct CompatiblityTestParams {
     pub count: usize,
     #[structopt(long, help = "Image tag of newer validator software")]
     pub updated_image_tag: String,
+    #[structopt(
+        long,
+        help = "Pull images of the newer tag on all hosts before upgrading, so that rollout times do not include download"
+    )]
+    pub prepull_images: bool,
 }
 
 pub struct CompatibilityTest {
@@ -112,6 +178,7 @@ pub struct CompatibilityTest {
     second_batch_lsr: Vec<Instance>,
     full_nodes: Vec<Instance>,
     updated_image_tag: String,
+    prepull_images: bool,
 }
 
 impl ExperimentParam for CompatiblityTestParams {
@@ -148,12 +215,21 @@ impl ExperimentParam for CompatiblityTestParams {
             second_batch_lsr,
             full_nodes: cluster.fullnode_instances().to_vec(),
             updated_image_tag: self.updated_image_tag,
+            prepull_images: self.prepull_images,
         }
     }
 }
 
 #[async_trait]
 impl Experiment for CompatibilityTest {
+    fn tags(&self) -> &'static [&'static str] {
+        &["upgrade", "long"]
+    }
+
+    fn report_section(&self) -> ReportSection {
+        ReportSection::Critical
+    }
+
     fn affected_validators(&self) -> HashSet<String> {
         instance::instancelist_to_set(&self.first_batch)
             .union(&instance::instancelist_to_set(&self.second_batch))
@@ -163,6 +239,102 @@ impl Experiment for CompatibilityTest {
     }
 
     async fn run(&mut self, context: &mut Context<'_>) -> anyhow::Result<()> {
+        if self.prepull_images {
+            prepull_images(context, &self.to_string(), &self.updated_image_tag).await;
+        }
+        // Background load keeps running through all phases of the upgrade, so that client
+        // visible continuity is verified and not only that the cluster comes back
+        let background_instances = if self.full_nodes.is_empty() {
+            context.cluster.validator_instances().to_vec()
+        } else {
+            self.full_nodes.clone()
+        };
+        // Transactions on chain are counted from sequence numbers of emitter accounts, so that
+        // transactions dropped by restarted nodes are not taken for committed
+        let sequence_numbers_before = context
+            .tx_emitter
+            .sync_sequence_numbers(&background_instances[0])
+            .await;
+        let background_job = context
+            .tx_emitter
+            .start_job(EmitJobRequest::for_instances(
+                background_instances.clone(),
+                context.global_emit_job_request,
+            ))
+            .await?;
+        let mut phase_stats = PhaseStats::new();
+        let result = self
+            .upgrade(context, &background_job, &mut phase_stats)
+            .await;
+        let stats = context.tx_emitter.stop_job(background_job).await;
+        result?;
+
+        let on_chain = context
+            .tx_emitter
+            .sync_sequence_numbers(&background_instances[0])
+            .await
+            .saturating_sub(sequence_numbers_before);
+        let lost = stats.submitted.saturating_sub(on_chain + stats.expired);
+        context.report.report_metric(&self, "lost_txn", lost as f64);
+        context.report.report_text(format!(
+            "Background load during upgrade: {} submitted, {} on chain, {} expired, {} lost",
+            stats.submitted, on_chain, stats.expired, lost
+        ));
+        if lost > 0 {
+            anyhow::bail!(
+                "{} transactions submitted during upgrade are neither on chain nor expired",
+                lost
+            );
+        }
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(20 * 60)
+    }
+}
+
+/// Throughput of the background load between phases of the upgrade
+struct PhaseStats {
+    started: Instant,
+    stats: TxStats,
+}
+
+impl PhaseStats {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            stats: TxStats::default(),
+        }
+    }
+
+    fn report(
+        &mut self,
+        context: &mut Context<'_>,
+        experiment: &CompatibilityTest,
+        job: &EmitJob,
+        phase: &str,
+    ) {
+        let stats = context.tx_emitter.peek_job_stats(job);
+        let rate = (&stats - &self.stats).rate(self.started.elapsed());
+        context
+            .report
+            .report_metric(experiment, format!("{}_tps", phase), rate.committed as f64);
+        context
+            .report
+            .report_text(format!("   background load during {}: {}", phase, rate));
+        self.stats = stats;
+        self.started = Instant::now();
+    }
+}
+
+impl CompatibilityTest {
+    async fn upgrade(
+        &self,
+        context: &mut Context<'_>,
+        background_job: &EmitJob,
+        phase_stats: &mut PhaseStats,
+    ) -> anyhow::Result<()> {
         let validator_txn_job = EmitJobRequest::for_instances(
             context.cluster.validator_instances().to_vec(),
             context.global_emit_job_request,
@@ -189,6 +361,7 @@ impl Experiment for CompatibilityTest {
             .emit_txn_for(job_duration, fullnode_txn_job.clone())
             .await
             .map_err(|e| anyhow::format_err!("Failed to generate traffic: {}", e))?;
+        phase_stats.report(context, self, background_job, "before_upgrade");
 
         let msg = format!(
             "2. First validator {} ==> {}, to validate storage",
@@ -198,13 +371,20 @@ impl Experiment for CompatibilityTest {
         info!("Upgrading validator: {}", self.first_node);
         context.report.report_text(msg);
         let first_node = vec![self.first_node.clone()];
-        update_batch_instance(
+        let rollout_times = update_batch_instance(
             context,
             &first_node,
             &self.first_lsr,
             self.updated_image_tag.clone(),
         )
         .await?;
+        report_rollout_times(
+            context,
+            &self.to_string(),
+            "first_node",
+            &first_node,
+            &rollout_times,
+        );
         context
             .tx_emitter
             .emit_txn_for(
@@ -213,6 +393,7 @@ impl Experiment for CompatibilityTest {
             )
             .await
             .map_err(|e| anyhow::format_err!("Storage backwards compat broken: {}", e))?;
+        phase_stats.report(context, self, background_job, "first_node");
 
         let msg = format!(
             "3. First batch validators ({}) {} ==> {}, to test consensus",
@@ -226,18 +407,26 @@ impl Experiment for CompatibilityTest {
             get_instance_list_str(&self.first_batch)
         );
         context.report.report_text(msg);
-        update_batch_instance(
+        let rollout_times = update_batch_instance(
             context,
             &self.first_batch,
             &self.first_batch_lsr,
             self.updated_image_tag.clone(),
         )
         .await?;
+        report_rollout_times(
+            context,
+            &self.to_string(),
+            "first_batch",
+            &self.first_batch,
+            &rollout_times,
+        );
         context
             .tx_emitter
             .emit_txn_for(job_duration, validator_txn_job.clone())
             .await
             .map_err(|e| anyhow::format_err!("Consensus backwards compat broken: {}", e))?;
+        phase_stats.report(context, self, background_job, "first_batch");
 
         let msg = format!(
             "4. Second batch validators ({}) {} ==> {}, to upgrade rest of the validators",
@@ -251,13 +440,20 @@ impl Experiment for CompatibilityTest {
             get_instance_list_str(&self.second_batch)
         );
         context.report.report_text(msg);
-        update_batch_instance(
+        let rollout_times = update_batch_instance(
             context,
             &self.second_batch,
             &self.second_batch_lsr,
             self.updated_image_tag.clone(),
         )
         .await?;
+        report_rollout_times(
+            context,
+            &self.to_string(),
+            "second_batch",
+            &self.second_batch,
+            &rollout_times,
+        );
         context
             .tx_emitter
             .emit_txn_for(job_duration, validator_txn_job)
@@ -265,6 +461,7 @@ impl Experiment for CompatibilityTest {
             .map_err(|e| {
                 anyhow::format_err!("Failed to upgrade rest of validator images: {}", e)
             })?;
+        phase_stats.report(context, self, background_job, "second_batch");
 
         let msg = format!(
             "5. All full nodes ({}) {} ==> {}, to finish the network upgrade",
@@ -278,24 +475,28 @@ impl Experiment for CompatibilityTest {
             get_instance_list_str(&self.full_nodes)
         );
         context.report.report_text(msg);
-        update_batch_instance(
+        let rollout_times = update_batch_instance(
             context,
             &self.full_nodes,
             &[],
             self.updated_image_tag.clone(),
         )
         .await?;
+        report_rollout_times(
+            context,
+            &self.to_string(),
+            "full_nodes",
+            &self.full_nodes,
+            &rollout_times,
+        );
         context
             .tx_emitter
             .emit_txn_for(job_duration, fullnode_txn_job)
             .await
             .map_err(|e| anyhow::format_err!("Failed to upgrade full node images: {}", e))?;
+        phase_stats.report(context, self, background_job, "full_nodes");
         Ok(())
     }
-
-    fn deadline(&self) -> Duration {
-        Duration::from_secs(16 * 60)
-    }
 }
 
 impl fmt::Display for CompatibilityTest {
@@ -0,0 +1,303 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which runs the same workload twice on the same cluster,
+/// first with baseline config and then with node config overrides applied, and reports metrics
+/// of both runs side by side. With repeated runs differences are tested for significance.
+/// Baseline config is restored at the end
+use crate::{
+    cluster::Cluster,
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::{validate_config_overrides, Instance},
+    significance::{Comparison, Test},
+    stats::PrometheusRangeView,
+    tx_emitter::EmitJobRequest,
+    util::unix_timestamp_now,
+};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use futures::future::try_join_all;
+use libra_logger::{info, warn};
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use tokio::time;
+
+#[derive(StructOpt, Debug)]
+pub struct ConfigAbTestParams {
+    #[structopt(
+        long = "override",
+        help = "Node config override in key=value format, e.g. capacity=10000. Keys are plain field names of node config sections, paths like mempool.capacity are not supported"
+    )]
+    pub overrides: Vec<String>,
+    #[structopt(long, help = "Apply overrides to fullnodes as well")]
+    pub include_fullnodes: bool,
+    #[structopt(
+        long,
+        default_value = "120",
+        help = "Duration of each workload run in secs"
+    )]
+    pub duration_secs: u64,
+    #[structopt(
+        long,
+        default_value = "30",
+        help = "Time in secs for restarted nodes to catch up before workload starts"
+    )]
+    pub warmup_secs: u64,
+    #[structopt(
+        long,
+        default_value = "1",
+        help = "Number of workload runs with each config, differences are tested for significance if more than 1"
+    )]
+    pub repeats: usize,
+    #[structopt(
+        long,
+        default_value = "0.95",
+        help = "Confidence at which a difference is significant"
+    )]
+    pub confidence: f64,
+    #[structopt(
+        long,
+        default_value = "welch",
+        help = "Significance test, welch or mann-whitney"
+    )]
+    pub test: Test,
+}
+
+pub struct ConfigAbTest {
+    overrides: Vec<String>,
+    include_fullnodes: bool,
+    validators: Vec<Instance>,
+    fullnodes: Vec<Instance>,
+    duration: Duration,
+    warmup: Duration,
+    repeats: usize,
+    confidence: f64,
+    test: Test,
+}
+
+/// Metrics of a single workload run, `None` if metric could not be collected
+type PhaseMetrics = Vec<(&'static str, Option<f64>)>;
+
+impl ExperimentParam for ConfigAbTestParams {
+    type E = ConfigAbTest;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        if self.overrides.is_empty() {
+            panic!("At least one --override is required");
+        }
+        if let Err(e) = validate_config_overrides(&self.overrides) {
+            panic!("{}", e);
+        }
+        Self::E {
+            overrides: self.overrides,
+            include_fullnodes: self.include_fullnodes,
+            validators: cluster.validator_instances().to_vec(),
+            fullnodes: cluster.fullnode_instances().to_vec(),
+            duration: Duration::from_secs(self.duration_secs),
+            warmup: Duration::from_secs(self.warmup_secs),
+            repeats: self.repeats.max(1),
+            confidence: self.confidence,
+            test: self.test,
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for ConfigAbTest {
+    fn tags(&self) -> &'static [&'static str] {
+        &["performance"]
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        let baseline = self
+            .run_phases(context, &self.validators, &self.fullnodes)
+            .await?;
+
+        info!("Applying config overrides: {}", self.overrides.join(", "));
+        let mut targets = self.validators.clone();
+        if self.include_fullnodes {
+            targets.extend(self.fullnodes.iter().cloned());
+        }
+        // Some instances may already run with overrides when applying them fails, so baseline
+        // config is restored however the variant phase ended
+        let variant = match context
+            .cluster_swarm
+            .apply_config_overrides(&targets, &self.overrides)
+            .await
+        {
+            Ok(updated) => {
+                let (validators, updated_fullnodes) = updated.split_at(self.validators.len());
+                let fullnodes = if self.include_fullnodes {
+                    updated_fullnodes.to_vec()
+                } else {
+                    self.fullnodes.clone()
+                };
+                self.run_phases(context, validators, &fullnodes).await
+            }
+            Err(e) => Err(e),
+        };
+
+        info!("Restoring baseline config");
+        let cluster_swarm = context.cluster_swarm;
+        let futures = targets.iter().map(|instance| async move {
+            instance.stop().await?;
+            cluster_swarm
+                .spawn_new_instance(instance.instance_config().clone(), false)
+                .await
+        });
+        let restored = try_join_all(futures).await;
+        let variant = variant?;
+        let restored = restored?;
+        let deadline = Instant::now() + Duration::from_secs(120);
+        try_join_all(restored.iter().map(|i| i.wait_json_rpc(deadline))).await?;
+
+        self.report(context, &baseline, &variant);
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(15 * 60) + (self.duration + self.warmup) * 2 * self.repeats as u32
+    }
+}
+
+impl ConfigAbTest {
+    async fn run_phases(
+        &self,
+        context: &mut Context<'_>,
+        validators: &[Instance],
+        fullnodes: &[Instance],
+    ) -> Result<Vec<PhaseMetrics>> {
+        let mut runs = vec![];
+        for run in 0..self.repeats {
+            if self.repeats > 1 {
+                info!("Workload run {} of {}", run + 1, self.repeats);
+            }
+            runs.push(self.run_phase(context, validators, fullnodes).await?);
+        }
+        Ok(runs)
+    }
+
+    async fn run_phase(
+        &self,
+        context: &mut Context<'_>,
+        validators: &[Instance],
+        fullnodes: &[Instance],
+    ) -> Result<PhaseMetrics> {
+        let deadline = Instant::now() + Duration::from_secs(120);
+        try_join_all(validators.iter().map(|i| i.wait_json_rpc(deadline))).await?;
+        time::delay_for(self.warmup).await;
+
+        let instances = if context.emit_to_validator || fullnodes.is_empty() {
+            validators.to_vec()
+        } else {
+            fullnodes.to_vec()
+        };
+        let start = unix_timestamp_now();
+        let stats = context
+            .tx_emitter
+            .emit_txn_for(
+                self.duration,
+                EmitJobRequest::for_instances(instances, context.global_emit_job_request),
+            )
+            .await?;
+        let end = unix_timestamp_now();
+        if stats.committed == 0 {
+            bail!("No transactions were committed during the run");
+        }
+
+        let rate = stats.rate(self.duration);
+        let pv = PrometheusRangeView::new(&context.prometheus, start, end);
+        Ok(vec![
+            ("tps", Some(rate.committed as f64)),
+            ("avg_latency_ms", Some(rate.latency as f64)),
+            ("p99_latency_ms", Some(rate.p99_latency as f64)),
+            ("expired_per_sec", Some(rate.expired as f64)),
+            ("avg_txns_per_block", pv.avg_txns_per_block()),
+            (
+                "avg_round_duration_ms",
+                pv.avg_round_duration().map(|d| d * 1000.0),
+            ),
+        ])
+    }
+
+    fn report(
+        &self,
+        context: &mut Context<'_>,
+        baseline: &[PhaseMetrics],
+        variant: &[PhaseMetrics],
+    ) {
+        if self.repeats > 1 {
+            self.report_comparison(context, baseline, variant);
+            return;
+        }
+        let mut metrics = vec![];
+        for ((metric, a), (_, b)) in baseline[0].iter().zip(variant[0].iter()) {
+            match (a, b) {
+                (Some(a), Some(b)) => metrics.push((*metric, *a, *b)),
+                _ => warn!("{} is not available for both runs", metric),
+            }
+        }
+        let text = format!(
+            "{}:{}",
+            self,
+            context.report.report_comparison(&self, "variant", &metrics)
+        );
+        info!("{}", text);
+        context.report.report_text(text);
+    }
+
+    /// Means of repeated runs with confidence intervals, and whether they differ significantly
+    fn report_comparison(
+        &self,
+        context: &mut Context<'_>,
+        baseline: &[PhaseMetrics],
+        variant: &[PhaseMetrics],
+    ) {
+        let samples = |runs: &[PhaseMetrics], i: usize| -> Vec<f64> {
+            runs.iter().filter_map(|run| run[i].1).collect()
+        };
+        let mut text = format!("{}, {} runs of each:", self, self.repeats);
+        for (i, (metric, _)) in baseline[0].iter().enumerate() {
+            let comparison = match Comparison::new(
+                &samples(baseline, i),
+                &samples(variant, i),
+                self.test,
+                self.confidence,
+            ) {
+                Some(comparison) => comparison,
+                None => {
+                    warn!("{} is not available for enough runs", metric);
+                    continue;
+                }
+            };
+            let mut metrics = vec![
+                (format!("baseline_{}", metric), comparison.baseline.mean),
+                (format!("variant_{}", metric), comparison.candidate.mean),
+                (format!("p_value_{}", metric), comparison.p_value),
+                (
+                    format!("significant_{}", metric),
+                    if comparison.significant() { 1.0 } else { 0.0 },
+                ),
+            ];
+            if let Some(delta) = comparison.delta_pct() {
+                metrics.push((format!("variant_delta_pct_{}", metric), delta));
+            }
+            for (name, value) in metrics {
+                context.report.report_metric(&self, name, value);
+            }
+            text.push_str(&format!("\n  {}: {}", metric, comparison));
+        }
+        info!("{}", text);
+        context.report.report_text(text);
+    }
+}
+
+impl fmt::Display for ConfigAbTest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Config A/B test of [{}]", self.overrides.join(", "))
+    }
+}
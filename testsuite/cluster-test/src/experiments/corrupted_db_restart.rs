@@ -0,0 +1,267 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which corrupts storage of one stopped validator, restarts
+/// it and verifies that corruption is detected instead of silently accepted: the validator must
+/// crash, and must not serve transactions differing from the rest of the cluster meanwhile.
+/// Afterwards the validator is wiped and re-synced to verify it can be recovered
+use crate::{
+    cluster::Cluster,
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::Instance,
+    report::ReportSection,
+};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use libra_json_rpc_client::{JsonRpcBatch, JsonRpcResponse};
+use libra_logger::{info, warn};
+use std::{
+    collections::HashSet,
+    fmt,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use tokio::time;
+
+#[derive(StructOpt, Debug)]
+pub struct CorruptedDbRestartParams {
+    #[structopt(long, default_value = "3", help = "Number of db files to corrupt")]
+    pub num_files: usize,
+    #[structopt(
+        long,
+        default_value = "4096",
+        help = "Number of bytes overwritten with random data in each file"
+    )]
+    pub corrupted_bytes: usize,
+    #[structopt(
+        long,
+        default_value = "180",
+        help = "Time in secs for validator to detect corruption after restart"
+    )]
+    pub detection_timeout_secs: u64,
+    #[structopt(
+        long,
+        default_value = "600",
+        help = "Time in secs for wiped validator to re-sync with the cluster"
+    )]
+    pub resync_timeout_secs: u64,
+}
+
+pub struct CorruptedDbRestart {
+    instance: Instance,
+    healthy_instance: Instance,
+    num_files: usize,
+    corrupted_bytes: usize,
+    detection_timeout: Duration,
+    resync_timeout: Duration,
+}
+
+impl ExperimentParam for CorruptedDbRestartParams {
+    type E = CorruptedDbRestart;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        let (corrupted, healthy) = cluster.split_n_validators_random(1);
+        Self::E {
+            instance: corrupted.into_validator_instances().remove(0),
+            healthy_instance: healthy.random_validator_instance(),
+            num_files: self.num_files,
+            corrupted_bytes: self.corrupted_bytes,
+            detection_timeout: Duration::from_secs(self.detection_timeout_secs),
+            resync_timeout: Duration::from_secs(self.resync_timeout_secs),
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for CorruptedDbRestart {
+    fn tags(&self) -> &'static [&'static str] {
+        &["storage"]
+    }
+
+    fn report_section(&self) -> ReportSection {
+        ReportSection::Critical
+    }
+
+    fn affected_validators(&self) -> HashSet<String> {
+        let mut result = HashSet::new();
+        result.insert(self.instance.peer_name().clone());
+        result
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        // Stopping deletes the pod, and the pod started afterwards counts restarts from zero.
+        // Still read it first, so that instances without restart count fail before any damage
+        self.instance.restart_count().await?;
+        info!("Stopping {}", self.instance);
+        self.instance.stop().await?;
+        info!("Corrupting db files of {}", self.instance);
+        self.instance
+            .util_cmd(
+                generate_corrupt_db_command(self.num_files, self.corrupted_bytes),
+                "corrupt-db",
+            )
+            .await?;
+        info!("Restarting {} with corrupted db", self.instance);
+        self.instance.start(false).await?;
+
+        let detection_time = self.wait_for_detection().await?;
+        let detection_msg = format!(
+            "{}: corruption detected in {} secs",
+            self,
+            detection_time.as_secs()
+        );
+        info!("{}", detection_msg);
+        context.report.report_text(detection_msg);
+        context.report.report_metric(
+            &self,
+            "corruption_detection_time",
+            detection_time.as_secs_f64(),
+        );
+
+        info!("Wiping db and restarting {}", self.instance);
+        self.instance.stop().await?;
+        let recovery_started = Instant::now();
+        self.instance.start(true).await?;
+        self.instance
+            .wait_json_rpc(recovery_started + Duration::from_secs(120))
+            .await?;
+        let target_version = self.healthy_instance.latest_version().await?;
+        let resync_deadline = recovery_started + self.resync_timeout;
+        while self.instance.latest_version().await.unwrap_or(0) < target_version {
+            if Instant::now() > resync_deadline {
+                bail!(
+                    "{} did not re-sync to version {} within {} secs",
+                    self.instance,
+                    target_version,
+                    self.resync_timeout.as_secs()
+                );
+            }
+            time::delay_for(Duration::from_secs(1)).await;
+        }
+        let recovery_time = recovery_started.elapsed();
+        let recovery_msg = format!(
+            "{}: re-synced to version {} in {} secs",
+            self,
+            target_version,
+            recovery_time.as_secs()
+        );
+        info!("{}", recovery_msg);
+        context.report.report_text(recovery_msg);
+        context
+            .report
+            .report_metric(&self, "recovery_time", recovery_time.as_secs_f64());
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(20 * 60)
+    }
+}
+
+impl CorruptedDbRestart {
+    /// Corruption is considered detected once the node crashes and is restarted by k8s.
+    /// A node which serves transactions differing from the healthy instance, or keeps serving
+    /// json rpc with corrupted db until timeout fails the experiment
+    async fn wait_for_detection(&self) -> Result<Duration> {
+        let started = Instant::now();
+        let deadline = started + self.detection_timeout;
+        let mut served_json_rpc = false;
+        while Instant::now() < deadline {
+            match self.instance.restart_count().await {
+                Ok(restart_count) if restart_count > 0 => {
+                    return Ok(started.elapsed());
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Failed to get restart count for {}: {}", self.instance, e),
+            }
+            if let Ok(version) = self.instance.latest_version().await {
+                served_json_rpc = true;
+                self.check_served_transactions(version).await?;
+            }
+            time::delay_for(Duration::from_secs(1)).await;
+        }
+        if served_json_rpc {
+            bail!(
+                "{} kept serving json rpc with corrupted db for {} secs",
+                self.instance,
+                self.detection_timeout.as_secs()
+            );
+        }
+        bail!(
+            "{} did not crash nor serve json rpc with corrupted db within {} secs",
+            self.instance,
+            self.detection_timeout.as_secs()
+        )
+    }
+
+    /// Fails if the last transactions served by the corrupted instance up to `version` differ
+    /// from the ones of the healthy instance. Errors of either instance are not conclusive
+    async fn check_served_transactions(&self, version: u64) -> Result<()> {
+        let start = version.saturating_sub(CHECKED_TRANSACTIONS - 1);
+        let served = match transaction_hashes(&self.instance, start, version).await {
+            Ok(hashes) => hashes,
+            Err(_) => return Ok(()),
+        };
+        let expected = match transaction_hashes(&self.healthy_instance, start, version).await {
+            Ok(hashes) => hashes,
+            Err(e) => {
+                warn!(
+                    "Failed to get transactions from {}: {}",
+                    self.healthy_instance, e
+                );
+                return Ok(());
+            }
+        };
+        if let Some((index, _)) = served
+            .iter()
+            .zip(expected.iter())
+            .enumerate()
+            .find(|(_, (served, expected))| served != expected)
+        {
+            bail!(
+                "{} served transaction {} differing from {} with corrupted db",
+                self.instance,
+                start + index as u64,
+                self.healthy_instance
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Number of latest transactions compared while corrupted instance serves json rpc
+const CHECKED_TRANSACTIONS: u64 = 100;
+
+async fn transaction_hashes(instance: &Instance, start: u64, end: u64) -> Result<Vec<String>> {
+    let mut batch = JsonRpcBatch::new();
+    batch.add_get_transactions_request(start, end - start + 1, false);
+    match instance.json_rpc_client().execute(batch).await?.remove(0)? {
+        JsonRpcResponse::TransactionsResponse(txns) => {
+            Ok(txns.into_iter().map(|t| t.hash).collect())
+        }
+        other => bail!(
+            "Unexpected response for get_transactions from {}: {:?}",
+            instance,
+            other
+        ),
+    }
+}
+
+impl fmt::Display for CorruptedDbRestart {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Corrupted db restart of {}", self.instance)
+    }
+}
+
+fn generate_corrupt_db_command(num_files: usize, corrupted_bytes: usize) -> String {
+    format!(
+        "set -e; \
+        for f in $(find /opt/libra/data -name '*.sst' | shuf -n {num_files}); do \
+        size=$(stat -c %s $f); \
+        dd if=/dev/urandom of=$f bs=1 count={corrupted_bytes} seek=$((size / 2)) conv=notrunc; \
+        done",
+        num_files = num_files,
+        corrupted_bytes = corrupted_bytes,
+    )
+}
@@ -15,14 +15,21 @@ use structopt::StructOpt;
 
 use async_trait::async_trait;
 
+use libra_logger::info;
+
 use crate::{
     cluster::Cluster,
-    experiments::{Context, Experiment, ExperimentParam},
+    experiments::{
+        compatibility_test::update_batch_instance, Context, Experiment, ExperimentParam,
+    },
     instance,
     instance::Instance,
+    report::ReportSection,
     tx_emitter::EmitJobRequest,
 };
 
+const FLAMEGRAPH_URL: &str = "https://toro-cluster-test-flamegraphs.s3-us-west-2.amazonaws.com";
+
 #[derive(StructOpt, Debug)]
 pub struct CpuFlamegraphParams {
     #[structopt(
@@ -31,49 +38,154 @@ pub struct CpuFlamegraphParams {
         help = "Number of seconds for which perf should be run"
     )]
     pub duration_secs: usize,
+    #[structopt(
+        long,
+        help = "Profile the same validator again on this image tag under identical workload and generate differential flamegraph against current tag"
+    )]
+    pub candidate_image_tag: Option<String>,
+    #[structopt(
+        long,
+        help = "Emit at fixed TPS instead of global workload, so that faster binary does not get more load in comparison"
+    )]
+    pub tps: Option<u64>,
 }
 
 pub struct CpuFlamegraph {
     duration_secs: usize,
     perf_instance: Instance,
+    perf_lsr: Vec<Instance>,
+    candidate_image_tag: Option<String>,
+    tps: Option<u64>,
 }
 
 impl ExperimentParam for CpuFlamegraphParams {
     type E = CpuFlamegraph;
     fn build(self, cluster: &Cluster) -> Self::E {
         let perf_instance = cluster.random_validator_instance();
+        let perf_lsr = if cluster.lsr_instances().is_empty() {
+            vec![]
+        } else {
+            cluster.lsr_instances_for_validators(&[perf_instance.clone()])
+        };
         Self::E {
             duration_secs: self.duration_secs,
             perf_instance,
+            perf_lsr,
+            candidate_image_tag: self.candidate_image_tag,
+            tps: self.tps,
         }
     }
 }
 
 #[async_trait]
 impl Experiment for CpuFlamegraph {
+    fn tags(&self) -> &'static [&'static str] {
+        &["performance"]
+    }
+
+    fn report_section(&self) -> ReportSection {
+        ReportSection::Informational
+    }
+
     fn affected_validators(&self) -> HashSet<String> {
         instance::instancelist_to_set(&[self.perf_instance.clone()])
     }
 
     async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        let prefix = format!(
+            "{}-libra-node-perf",
+            thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(30)
+                .collect::<String>()
+        );
+        let candidate_image_tag = match &self.candidate_image_tag {
+            Some(tag) => tag.clone(),
+            None => {
+                self.profile(context, &prefix).await?;
+                context.report.report_text(format!(
+                    "perf flamegraph : {}/{}.svg",
+                    FLAMEGRAPH_URL, prefix
+                ));
+                return Ok(());
+            }
+        };
+
+        let baseline = format!("{}-baseline", prefix);
+        let candidate = format!("{}-candidate", prefix);
+        let diff = format!("{}-diff", prefix);
+        info!(
+            "Profiling {} on {}",
+            self.perf_instance, context.current_tag
+        );
+        self.profile(context, &baseline).await?;
+        info!(
+            "Profiling {} on {}",
+            self.perf_instance, candidate_image_tag
+        );
+        update_batch_instance(
+            context,
+            &[self.perf_instance.clone()],
+            &self.perf_lsr,
+            candidate_image_tag.clone(),
+        )
+        .await?;
+        let result = self.profile(context, &candidate).await;
+        let current_tag = context.current_tag.to_string();
+        update_batch_instance(
+            context,
+            &[self.perf_instance.clone()],
+            &self.perf_lsr,
+            current_tag,
+        )
+        .await?;
+        result?;
+        self.perf_instance
+            .util_cmd(
+                generate_diff_flamegraph_command(&baseline, &candidate, &diff),
+                "generate-diff-flamegraph",
+            )
+            .await
+            .map_err(|e| format_err!("Failed to generate differential flamegraph: {:?}", e))?;
+        context.report.report_text(format!(
+            "perf flamegraph {} : {}/{}.svg\nperf flamegraph {} : {}/{}.svg\ndifferential flamegraph, red is more CPU on {} : {}/{}.svg",
+            context.current_tag,
+            FLAMEGRAPH_URL,
+            baseline,
+            candidate_image_tag,
+            FLAMEGRAPH_URL,
+            candidate,
+            candidate_image_tag,
+            FLAMEGRAPH_URL,
+            diff
+        ));
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        if self.candidate_image_tag.is_some() {
+            Duration::from_secs(2 * 480 + 2 * 300)
+        } else {
+            Duration::from_secs(480)
+        }
+    }
+}
+
+impl CpuFlamegraph {
+    /// Uploads `<name>.svg` and folded stacks `<name>.perf-folded` of perf instance under load
+    async fn profile(&self, context: &mut Context<'_>, name: &str) -> Result<()> {
         let buffer = Duration::from_secs(60);
         let tx_emitter_duration = 2 * buffer + Duration::from_secs(self.duration_secs as u64);
-        let emit_job_request = EmitJobRequest::for_instances(
-            context.cluster.validator_instances().to_vec(),
-            context.global_emit_job_request,
-        );
+        let instances = context.cluster.validator_instances().to_vec();
+        let emit_job_request = match self.tps {
+            Some(tps) => EmitJobRequest::fixed_tps(instances, tps),
+            None => EmitJobRequest::for_instances(instances, context.global_emit_job_request),
+        };
         let emit_future = context
             .tx_emitter
             .emit_txn_for(tx_emitter_duration, emit_job_request)
             .boxed();
-        let filename = format!(
-            "{}-libra-node-perf.svg",
-            thread_rng()
-                .sample_iter(&Alphanumeric)
-                .take(30)
-                .collect::<String>()
-        );
-        let command = generate_perf_flamegraph_command(&filename, self.duration_secs);
+        let command = generate_perf_flamegraph_command(name, self.duration_secs);
         let flame_graph = self.perf_instance.util_cmd(command, "generate-flamegraph");
         let flame_graph_future = tokio::time::delay_for(buffer)
             .then(|_| async move { flame_graph.await })
@@ -81,25 +193,24 @@ impl Experiment for CpuFlamegraph {
         let (emit_result, flame_graph_result) = join!(emit_future, flame_graph_future);
         emit_result.map_err(|e| format_err!("Emiting tx failed: {:?}", e))?;
         flame_graph_result.map_err(|e| format_err!("Failed to generate flamegraph: {:?}", e))?;
-        context.report.report_text(format!(
-            "perf flamegraph : https://toro-cluster-test-flamegraphs.s3-us-west-2.amazonaws.com/{}",
-            filename
-        ));
         Ok(())
     }
-
-    fn deadline(&self) -> Duration {
-        Duration::from_secs(480)
-    }
 }
 
 impl Display for CpuFlamegraph {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        write!(f, "Generating CpuFlamegraph on {}", self.perf_instance)
+        match &self.candidate_image_tag {
+            Some(tag) => write!(
+                f,
+                "Comparing CpuFlamegraph with {} on {}",
+                tag, self.perf_instance
+            ),
+            None => write!(f, "Generating CpuFlamegraph on {}", self.perf_instance),
+        }
     }
 }
 
-fn generate_perf_flamegraph_command(filename: &str, duration_secs: usize) -> String {
+fn generate_perf_flamegraph_command(name: &str, duration_secs: usize) -> String {
     format!(
         r#"
         set -xe;
@@ -107,10 +218,30 @@ fn generate_perf_flamegraph_command(filename: &str, duration_secs: usize) -> Str
         mkdir /tmp/perf-data;
         cd /tmp/perf-data;
         perf record -F 99 -p $(ps aux | grep libra-node | grep -v grep | awk '{{print $2}}') --output=perf.data --call-graph dwarf -- sleep {duration_secs};
-        perf script --input=perf.data | /usr/local/etc/FlameGraph/stackcollapse-perf.pl > out.perf-folded;
-        /usr/local/etc/FlameGraph/flamegraph.pl out.perf-folded > {filename};
-        aws s3 cp {filename} s3://toro-cluster-test-flamegraphs/{filename};"#,
+        perf script --input=perf.data | /usr/local/etc/FlameGraph/stackcollapse-perf.pl > {name}.perf-folded;
+        /usr/local/etc/FlameGraph/flamegraph.pl {name}.perf-folded > {name}.svg;
+        aws s3 cp {name}.perf-folded s3://toro-cluster-test-flamegraphs/{name}.perf-folded;
+        aws s3 cp {name}.svg s3://toro-cluster-test-flamegraphs/{name}.svg;"#,
         duration_secs = duration_secs,
-        filename = filename,
+        name = name,
+    )
+}
+
+/// Sample counts are normalized with -n, since runs of both images may collect different number
+/// of samples
+fn generate_diff_flamegraph_command(baseline: &str, candidate: &str, diff: &str) -> String {
+    format!(
+        r#"
+        set -xe;
+        rm -rf /tmp/perf-diff;
+        mkdir /tmp/perf-diff;
+        cd /tmp/perf-diff;
+        aws s3 cp s3://toro-cluster-test-flamegraphs/{baseline}.perf-folded .;
+        aws s3 cp s3://toro-cluster-test-flamegraphs/{candidate}.perf-folded .;
+        /usr/local/etc/FlameGraph/difffolded.pl -n {baseline}.perf-folded {candidate}.perf-folded | /usr/local/etc/FlameGraph/flamegraph.pl > {diff}.svg;
+        aws s3 cp {diff}.svg s3://toro-cluster-test-flamegraphs/{diff}.svg;"#,
+        baseline = baseline,
+        candidate = candidate,
+        diff = diff,
     )
 }
@@ -0,0 +1,253 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which runs the disaster recovery drill: the whole
+/// validator set is halted at once, an optional intervention such as applying a writeset with
+/// db-bootstrapper is run on the host of every validator, and the validator set is restarted
+/// from its halted state. Time until consensus commits again is reported
+use crate::{
+    cluster::Cluster,
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::{self, Instance},
+    prometheus::TimeSeries,
+    report::ReportSection,
+    tx_emitter::EmitJobRequest,
+    util::unix_timestamp_now,
+};
+use anyhow::{bail, format_err, Result};
+use async_trait::async_trait;
+use futures::future::{join_all, try_join_all};
+use libra_logger::info;
+use std::{
+    collections::HashSet,
+    fmt,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use tokio::time;
+
+/// Last scrape of a validator is at most a scrape interval before it halted
+const HALTED_VERSION_LOOKBACK: Duration = Duration::from_secs(60);
+
+#[derive(StructOpt, Debug)]
+pub struct DisasterRecoveryParams {
+    #[structopt(
+        long,
+        default_value = "30",
+        help = "Time in secs to keep the validator set halted"
+    )]
+    pub hold_secs: u64,
+    #[structopt(
+        long,
+        help = "Command run on host of every validator while the validator set is halted, e.g. to apply a writeset with db-bootstrapper"
+    )]
+    pub intervention_cmd: Option<String>,
+    #[structopt(
+        long,
+        use_delimiter = true,
+        help = "Config overrides validators are restarted with, e.g. new waypoint after a writeset. They are kept after the experiment"
+    )]
+    pub restart_overrides: Vec<String>,
+    #[structopt(
+        long,
+        default_value = "600",
+        help = "Time in secs for consensus to resume after restart"
+    )]
+    pub resume_timeout_secs: u64,
+}
+
+pub struct DisasterRecovery {
+    validators: Vec<Instance>,
+    hold: Duration,
+    intervention_cmd: Option<String>,
+    restart_overrides: Vec<String>,
+    resume_timeout: Duration,
+}
+
+impl ExperimentParam for DisasterRecoveryParams {
+    type E = DisasterRecovery;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        Self::E {
+            validators: cluster.validator_instances().to_vec(),
+            hold: Duration::from_secs(self.hold_secs),
+            intervention_cmd: self.intervention_cmd,
+            restart_overrides: self.restart_overrides,
+            resume_timeout: Duration::from_secs(self.resume_timeout_secs),
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for DisasterRecovery {
+    fn tags(&self) -> &'static [&'static str] {
+        &["consensus", "storage"]
+    }
+
+    fn report_section(&self) -> ReportSection {
+        ReportSection::Critical
+    }
+
+    fn affected_validators(&self) -> HashSet<String> {
+        instance::instancelist_to_set(&self.validators)
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        info!("Halting {} validators", self.validators.len());
+        let halt_started = unix_timestamp_now();
+        let halt_timer = Instant::now();
+        // All stops are issued together and each is waited for, so that no validator keeps
+        // running when another one fails to stop
+        let halted = join_all(self.validators.iter().map(Instance::stop))
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>>>();
+        let halt_time = halt_timer.elapsed();
+        let halted = match halted {
+            Ok(_) => self.hold(context, halt_started).await,
+            Err(e) => Err(e),
+        };
+
+        // Validator set is restarted whatever failed before, with overrides only if the
+        // intervention they are meant for ran
+        info!("Restarting validator set");
+        let restart_started = Instant::now();
+        let cluster_swarm = context.cluster_swarm;
+        let overrides = match &halted {
+            Ok(_) => &self.restart_overrides[..],
+            Err(_) => &[],
+        };
+        let futures = self.validators.iter().map(|instance| async move {
+            let mut instance_config = instance.instance_config().clone();
+            instance_config.add_config_overrides(overrides)?;
+            cluster_swarm
+                .spawn_new_instance(instance_config, false)
+                .await
+        });
+        let restarted = join_all(futures)
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>>>();
+        let (halted_version, version_spread) = halted?;
+        let restarted = restarted?;
+        let deadline = restart_started + self.resume_timeout;
+        try_join_all(restarted.iter().map(|i| i.wait_json_rpc(deadline))).await?;
+        let restart_time = restart_started.elapsed();
+
+        // Validators which did not see the last commits before the halt catch up with state sync,
+        // only consensus can move the cluster past the highest halted version
+        loop {
+            let versions = try_join_all(restarted.iter().map(Instance::latest_version)).await?;
+            if versions.into_iter().any(|v| v > halted_version) {
+                break;
+            }
+            if Instant::now() > deadline {
+                bail!(
+                    "Consensus did not resume within {} secs after restart",
+                    self.resume_timeout.as_secs()
+                );
+            }
+            time::delay_for(Duration::from_secs(1)).await;
+        }
+        let resume_time = restart_started.elapsed();
+
+        let window = Duration::from_secs(30);
+        let stats = context
+            .tx_emitter
+            .emit_txn_for(
+                window,
+                EmitJobRequest::for_instances(restarted, context.global_emit_job_request),
+            )
+            .await?;
+
+        let msg = format!(
+            "{}: halted in {} secs at version {} (spread {}), json-rpc up {} secs and consensus resumed {} secs after restart, {:.0} txn/s after resume",
+            self,
+            halt_time.as_secs(),
+            halted_version,
+            version_spread,
+            restart_time.as_secs(),
+            resume_time.as_secs(),
+            stats.committed as f64 / window.as_secs_f64()
+        );
+        info!("{}", msg);
+        context.report.report_text(msg);
+        let metrics = [
+            ("halt_time", halt_time.as_secs_f64()),
+            ("halted_version_spread", version_spread as f64),
+            ("restart_time", restart_time.as_secs_f64()),
+            ("time_to_resume", resume_time.as_secs_f64()),
+        ];
+        for (metric, value) in metrics.iter() {
+            context.report.report_metric(&self, *metric, *value);
+        }
+        if stats.committed == 0 {
+            bail!("No transactions were committed after consensus resumed");
+        }
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(15 * 60) + self.hold + self.resume_timeout
+    }
+}
+
+impl DisasterRecovery {
+    /// Keeps the validator set halted and runs the intervention. Returns the highest version
+    /// validators committed before the halt and the spread of their versions, read from
+    /// Prometheus while they are down, so that commits after the restart are not included
+    async fn hold(&self, context: &mut Context<'_>, halt_started: Duration) -> Result<(u64, u64)> {
+        time::delay_for(self.hold).await;
+        let versions = context
+            .prometheus
+            .query_range(
+                format!(
+                    "libra_state_sync_version{{type=\"committed\",{}}}",
+                    context.prometheus.validator_selector()
+                ),
+                &(halt_started - HALTED_VERSION_LOOKBACK),
+                &unix_timestamp_now(),
+                1,
+            )?
+            .time_series()
+            .values()
+            .filter_map(TimeSeries::max)
+            .map(|v| v as u64)
+            .collect::<Vec<_>>();
+        let halted_version = versions
+            .iter()
+            .max()
+            .cloned()
+            .ok_or_else(|| format_err!("No committed versions of halted validators"))?;
+        let version_spread = halted_version - versions.iter().min().cloned().unwrap_or(0);
+
+        if let Some(cmd) = &self.intervention_cmd {
+            info!("Running intervention on {} hosts", self.validators.len());
+            let futures = self
+                .validators
+                .iter()
+                .map(|instance| instance.util_cmd(cmd, "dr-intervention"));
+            join_all(futures)
+                .await
+                .into_iter()
+                .collect::<Result<Vec<_>>>()?;
+        }
+        Ok((halted_version, version_spread))
+    }
+}
+
+impl fmt::Display for DisasterRecovery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Disaster recovery drill of {} validators{}",
+            self.validators.len(),
+            if self.intervention_cmd.is_some() {
+                " with intervention"
+            } else {
+                ""
+            }
+        )
+    }
+}
@@ -0,0 +1,209 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which exhausts file descriptors and ephemeral ports on
+/// selected validators while load is emitted to the whole cluster. Pressured validators are
+/// expected to keep running and catch up after pressure is released. Error rates before,
+/// during and after the pressure window are reported
+use crate::{
+    cluster::Cluster,
+    effects::{self, fd_pressure::FdPressure},
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::{self, Instance},
+    tx_emitter::{EmitJobRequest, TxStats},
+};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use futures::future::{join_all, try_join_all};
+use libra_logger::info;
+use std::{
+    collections::HashSet,
+    fmt,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use tokio::time;
+
+#[derive(StructOpt, Debug)]
+pub struct FdPressureParams {
+    #[structopt(
+        long,
+        default_value = "1",
+        help = "Number of validators to put under pressure"
+    )]
+    pub count: usize,
+    #[structopt(
+        long,
+        default_value = "25000",
+        help = "Number of connections opened to each validator"
+    )]
+    pub connections: usize,
+    #[structopt(long, help = "Port to open connections to, JSON-RPC port if not set")]
+    pub port: Option<u32>,
+    #[structopt(
+        long,
+        default_value = "120",
+        help = "Duration of the pressure window in secs"
+    )]
+    pub duration_secs: u64,
+    #[structopt(
+        long,
+        default_value = "60",
+        help = "Time in secs for validators to recover after pressure is released"
+    )]
+    pub recovery_secs: u64,
+}
+
+pub struct FdPressureExperiment {
+    instances: Vec<Instance>,
+    validators: Vec<Instance>,
+    fullnodes: Vec<Instance>,
+    connections: usize,
+    port: Option<u32>,
+    duration: Duration,
+    recovery: Duration,
+}
+
+impl ExperimentParam for FdPressureParams {
+    type E = FdPressureExperiment;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        let (test_cluster, _) = cluster.split_n_validators_random(self.count);
+        Self::E {
+            instances: test_cluster.into_validator_instances(),
+            validators: cluster.validator_instances().to_vec(),
+            fullnodes: cluster.fullnode_instances().to_vec(),
+            connections: self.connections,
+            port: self.port,
+            duration: Duration::from_secs(self.duration_secs),
+            recovery: Duration::from_secs(self.recovery_secs),
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for FdPressureExperiment {
+    fn tags(&self) -> &'static [&'static str] {
+        &["network"]
+    }
+
+    fn affected_validators(&self) -> HashSet<String> {
+        instance::instancelist_to_set(&self.instances)
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        let instances = if context.emit_to_validator || self.fullnodes.is_empty() {
+            self.validators.clone()
+        } else {
+            self.fullnodes.clone()
+        };
+        let job = context
+            .tx_emitter
+            .start_job(EmitJobRequest::for_instances(
+                instances,
+                context.global_emit_job_request,
+            ))
+            .await?;
+        time::delay_for(self.recovery).await;
+        let before = context.tx_emitter.peek_job_stats(&job);
+
+        let mut effects: Vec<_> = self
+            .instances
+            .iter()
+            .map(|instance| {
+                let port = self.port.unwrap_or_else(|| instance.ac_port());
+                FdPressure::new(instance.clone(), port, self.connections)
+            })
+            .collect();
+        let activated = effects::activate_all(&mut effects).await;
+        if activated.is_ok() {
+            time::delay_for(self.duration).await;
+        }
+        let during = context.tx_emitter.peek_job_stats(&job);
+        let held_connections = effects
+            .iter()
+            .filter_map(FdPressure::held_connections)
+            .min()
+            .unwrap_or(0);
+        // Helpers which started before activation of another one failed are still running
+        let deactivated = effects::deactivate_all(&mut effects).await;
+        if let Err(e) = activated.and(deactivated) {
+            context.tx_emitter.stop_job(job).await;
+            return Err(e);
+        }
+        // Node may not serve JSON-RPC right after pressure, any later version is progress then
+        let versions: Vec<_> = join_all(self.instances.iter().map(Instance::latest_version))
+            .await
+            .into_iter()
+            .map(|v| v.unwrap_or(0))
+            .collect();
+
+        time::delay_for(self.recovery).await;
+        let after = context.tx_emitter.stop_job(job).await;
+
+        let phases = [
+            ("before", &before - &TxStats::default()),
+            ("during", &during - &before),
+            ("after", &after - &during),
+        ];
+        context
+            .report
+            .report_metric(&self, "min_held_connections", held_connections as f64);
+        let mut text = format!(
+            "{}: at least {} connections held per validator,",
+            self, held_connections
+        );
+        for (phase, stats) in phases.iter() {
+            let error_rate = error_rate(stats);
+            context
+                .report
+                .report_metric(&self, format!("{}_error_rate", phase), error_rate);
+            text.push_str(&format!(
+                " {} pressure {:.1}% errors,",
+                phase,
+                error_rate * 100.0
+            ));
+        }
+        info!("{}", text);
+        context.report.report_text(text);
+
+        let deadline = Instant::now() + Duration::from_secs(60);
+        try_join_all(self.instances.iter().map(|i| i.wait_json_rpc(deadline))).await?;
+        let versions_after =
+            try_join_all(self.instances.iter().map(Instance::latest_version)).await?;
+        for ((instance, before), after) in self.instances.iter().zip(versions).zip(versions_after) {
+            if after <= before {
+                bail!(
+                    "{} did not make progress after pressure was released",
+                    instance
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(10 * 60) + self.duration + self.recovery * 2
+    }
+}
+
+/// Share of submitted transactions which did not commit
+fn error_rate(stats: &TxStats) -> f64 {
+    if stats.submitted == 0 {
+        0.0
+    } else {
+        stats.expired as f64 / stats.submitted as f64
+    }
+}
+
+impl fmt::Display for FdPressureExperiment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Fd pressure of {} connections on {} validators",
+            self.connections,
+            self.instances.len()
+        )
+    }
+}
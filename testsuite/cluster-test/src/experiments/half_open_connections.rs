@@ -0,0 +1,182 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which blackholes traffic between pairs of validators
+/// without resetting their connections, and verifies that health checks of the network notice
+/// the stuck connections and drop them, and that the connections are replaced once traffic
+/// flows again, both within bounded time
+use crate::{
+    cluster::Cluster,
+    effects::{self, half_open_connection::HalfOpenConnection},
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::{self, Instance},
+};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use libra_logger::info;
+use std::{
+    cmp::min,
+    collections::HashSet,
+    fmt,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use tokio::time;
+
+#[derive(StructOpt, Debug)]
+pub struct HalfOpenConnectionsParams {
+    #[structopt(
+        long,
+        default_value = "1",
+        help = "Number of disjoint validator pairs whose connection is blackholed"
+    )]
+    pub pairs: usize,
+    #[structopt(
+        long,
+        default_value = "60",
+        help = "Time in secs for validators to drop stuck connection"
+    )]
+    pub detect_timeout_secs: u64,
+    #[structopt(
+        long,
+        default_value = "120",
+        help = "Time in secs for validators to reconnect after traffic is restored"
+    )]
+    pub reconnect_timeout_secs: u64,
+}
+
+pub struct HalfOpenConnections {
+    pairs: Vec<(Instance, Instance)>,
+    num_validators: usize,
+    detect_timeout: Duration,
+    reconnect_timeout: Duration,
+}
+
+impl ExperimentParam for HalfOpenConnectionsParams {
+    type E = HalfOpenConnections;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        let num_validators = cluster.validator_instances().len();
+        let pairs = min(self.pairs, num_validators / 2);
+        let (affected, _) = cluster.split_n_validators_random(pairs * 2);
+        let affected = affected.into_validator_instances();
+        let pairs = affected
+            .chunks(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect();
+        Self::E {
+            pairs,
+            num_validators,
+            detect_timeout: Duration::from_secs(self.detect_timeout_secs),
+            reconnect_timeout: Duration::from_secs(self.reconnect_timeout_secs),
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for HalfOpenConnections {
+    fn tags(&self) -> &'static [&'static str] {
+        &["network"]
+    }
+
+    fn affected_validators(&self) -> HashSet<String> {
+        instance::instancelist_to_set(&self.instances())
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        let mut effects: Vec<_> = self
+            .pairs
+            .iter()
+            .map(|(instance, peer)| HalfOpenConnection::new(instance.clone(), peer.clone()))
+            .collect();
+        effects::activate_all(&mut effects).await?;
+        // Each validator of a pair loses exactly one peer, since pairs are disjoint
+        let all_peers = (self.num_validators - 1) as f64;
+        let detect_time = self
+            .wait_for_peers("drop stuck connection", self.detect_timeout, |peers| {
+                peers < all_peers
+            })
+            .await;
+        effects::deactivate_all(&mut effects).await?;
+        let detect_time = detect_time?;
+        let reconnect_time = self
+            .wait_for_peers("reconnect", self.reconnect_timeout, |peers| {
+                peers >= all_peers
+            })
+            .await?;
+
+        let msg = format!(
+            "{}: stuck connections dropped in {} secs, replaced in {} secs after traffic was restored",
+            self,
+            detect_time.as_secs(),
+            reconnect_time.as_secs()
+        );
+        info!("{}", msg);
+        context.report.report_text(msg);
+        context
+            .report
+            .report_metric(&self, "detect_time", detect_time.as_secs_f64());
+        context
+            .report
+            .report_metric(&self, "reconnect_time", reconnect_time.as_secs_f64());
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(5 * 60) + self.detect_timeout + self.reconnect_timeout
+    }
+}
+
+impl HalfOpenConnections {
+    fn instances(&self) -> Vec<Instance> {
+        self.pairs
+            .iter()
+            .flat_map(|(instance, peer)| vec![instance.clone(), peer.clone()])
+            .collect()
+    }
+
+    /// Time until number of connected peers of every affected validator satisfies `condition`
+    async fn wait_for_peers(
+        &self,
+        what: &str,
+        timeout: Duration,
+        condition: impl Fn(f64) -> bool,
+    ) -> Result<Duration> {
+        let started = Instant::now();
+        let instances = self.instances();
+        loop {
+            let pending: Vec<_> = instances
+                .iter()
+                .filter(|instance| {
+                    instance
+                        .counter("libra_network_peers.validator.connected")
+                        .map_or(true, |peers| !condition(peers))
+                })
+                .collect();
+            if pending.is_empty() {
+                return Ok(started.elapsed());
+            }
+            if started.elapsed() > timeout {
+                let pending: Vec<_> = pending.iter().map(|i| i.to_string()).collect();
+                bail!(
+                    "{} did not {} within {} secs",
+                    pending.join(", "),
+                    what,
+                    timeout.as_secs()
+                );
+            }
+            time::delay_for(Duration::from_secs(1)).await;
+        }
+    }
+}
+
+impl fmt::Display for HalfOpenConnections {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Half-open connections between [")?;
+        for (instance, peer) in &self.pairs {
+            write!(f, "{} <-> {}, ", instance, peer)?;
+        }
+        write!(f, "]")
+    }
+}
@@ -0,0 +1,305 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which restarts one validator with jemalloc heap
+/// profiling, takes the heap profiles dumped at the start and at the end of a long soak, and
+/// reports allocation sites which grew in between. Growth which does not level off over the
+/// soak points to a leak, and unlike RSS the diff tells where it is
+use crate::{
+    cluster::Cluster,
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::{self, Instance},
+    report::ReportSection,
+    tx_emitter::EmitJobRequest,
+};
+use anyhow::{bail, format_err, Result};
+use async_trait::async_trait;
+use libra_logger::info;
+use std::{
+    collections::HashSet,
+    fmt,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use tokio::time;
+
+/// On the data volume, so that the util job sees dumps of the node
+const PROF_PREFIX: &str = "/opt/libra/data/jeprof";
+const DIFF_FILE: &str = "/opt/libra/data/heap-diff.txt";
+
+#[derive(StructOpt, Debug)]
+pub struct HeapProfileDiffParams {
+    #[structopt(
+        long,
+        default_value = "3600",
+        help = "Duration of the soak between the two profiles in secs"
+    )]
+    pub duration_secs: u64,
+    #[structopt(
+        long,
+        default_value = "300",
+        help = "Time in secs under load before the first profile, so that caches are filled"
+    )]
+    pub warmup_secs: u64,
+    #[structopt(
+        long,
+        default_value = "30",
+        help = "Profile is dumped every 2^N bytes allocated, lower it if no profiles are dumped"
+    )]
+    pub lg_prof_interval: u32,
+    #[structopt(
+        long,
+        default_value = "15",
+        help = "Number of top growth sites to report"
+    )]
+    pub top: usize,
+}
+
+pub struct HeapProfileDiff {
+    instance: Instance,
+    instances: Vec<Instance>,
+    duration: Duration,
+    warmup: Duration,
+    lg_prof_interval: u32,
+    top: usize,
+}
+
+/// Line of `jeprof --text` output
+struct GrowthSite {
+    flat_mb: f64,
+    cum_mb: f64,
+    symbol: String,
+}
+
+impl ExperimentParam for HeapProfileDiffParams {
+    type E = HeapProfileDiff;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        Self::E {
+            instance: cluster.random_validator_instance(),
+            instances: cluster.validator_instances().to_vec(),
+            duration: Duration::from_secs(self.duration_secs),
+            warmup: Duration::from_secs(self.warmup_secs),
+            lg_prof_interval: self.lg_prof_interval,
+            top: self.top,
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for HeapProfileDiff {
+    fn tags(&self) -> &'static [&'static str] {
+        &["performance"]
+    }
+
+    fn report_section(&self) -> ReportSection {
+        ReportSection::Informational
+    }
+
+    fn affected_validators(&self) -> HashSet<String> {
+        instance::instancelist_to_set(&[self.instance.clone()])
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        if !context.cluster_swarm.supports_malloc_conf() {
+            bail!(
+                "Heap profiling is not supported by this cluster, jemalloc options can not be set"
+            );
+        }
+        info!("Restarting {} with heap profiling", self.instance);
+        // Dumps of earlier runs would be taken for the ones of this run
+        self.instance
+            .exec(&format!("rm -f {}.*.heap {}", PROF_PREFIX, DIFF_FILE), true)
+            .await?;
+        let mut config = self.instance.instance_config().clone();
+        config.set_malloc_conf(Some(format!(
+            "prof:true,prof_prefix:{},lg_prof_interval:{}",
+            PROF_PREFIX, self.lg_prof_interval
+        )))?;
+        self.instance.stop().await?;
+        let profiled = match context
+            .cluster_swarm
+            .spawn_new_instance(config, false)
+            .await
+        {
+            Ok(profiled) => profiled,
+            Err(e) => {
+                self.restore(context).await?;
+                return Err(e);
+            }
+        };
+        let result = self.soak(context, &profiled).await;
+
+        profiled.stop().await?;
+        self.restore(context).await?;
+        let diff = result?;
+
+        let (total_mb, sites) = parse_jeprof_text(&diff);
+        context
+            .report
+            .report_metric(&self, "heap_growth_mb", total_mb);
+        let mut text = format!(
+            "{}: heap grew by {:.1} MB in {} secs, top growth sites (flat MB, cumulative MB):",
+            self,
+            total_mb,
+            self.duration.as_secs()
+        );
+        for site in sites.iter().take(self.top) {
+            text.push_str(&format!(
+                "\n  {:>8.1} {:>8.1} {}",
+                site.flat_mb, site.cum_mb, site.symbol
+            ));
+        }
+        info!("{}", text);
+        context.report.report_text(text);
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(10 * 60) + self.warmup + self.duration
+    }
+}
+
+impl HeapProfileDiff {
+    /// Starts the validator again with its original config
+    async fn restore(&self, context: &Context<'_>) -> Result<()> {
+        info!("Restarting {} without heap profiling", self.instance);
+        let restored = context
+            .cluster_swarm
+            .spawn_new_instance(self.instance.instance_config().clone(), false)
+            .await?;
+        restored
+            .wait_json_rpc(Instant::now() + Duration::from_secs(120))
+            .await
+    }
+
+    /// Runs load with profiled validator in place of the original one, returns symbolized diff
+    /// of the profiles taken after warmup and at the end of the soak
+    async fn soak(&self, context: &mut Context<'_>, profiled: &Instance) -> Result<String> {
+        profiled
+            .wait_json_rpc(Instant::now() + Duration::from_secs(120))
+            .await?;
+        let instances = self
+            .instances
+            .iter()
+            .map(|i| {
+                if i.peer_name() == profiled.peer_name() {
+                    profiled.clone()
+                } else {
+                    i.clone()
+                }
+            })
+            .collect();
+        let job = context
+            .tx_emitter
+            .start_job(EmitJobRequest::for_instances(
+                instances,
+                context.global_emit_job_request,
+            ))
+            .await?;
+        time::delay_for(self.warmup).await;
+        let start = latest_profile(profiled).await;
+        time::delay_for(self.duration).await;
+        let end = latest_profile(profiled).await;
+        context.tx_emitter.stop_job(job).await;
+        let (start, end) = (start?, end?);
+        if start == end {
+            bail!(
+                "No profile was dumped during the soak, lower --lg-prof-interval than {}",
+                self.lg_prof_interval
+            );
+        }
+
+        info!("Symbolizing growth from {} to {}", start, end);
+        // Binary is taken from the node process, util job shares the host pid namespace
+        let command = format!(
+            "jeprof --text --base={start} /proc/$(pgrep -n -x libra-node)/exe {end} > {diff}",
+            start = start,
+            end = end,
+            diff = DIFF_FILE
+        );
+        profiled
+            .util_cmd(command, "heap-profile-diff")
+            .await
+            .map_err(|e| format_err!("Failed to symbolize heap profiles: {:?}", e))?;
+        profiled.exec_output(&format!("cat {}", DIFF_FILE)).await
+    }
+}
+
+/// Last profile dumped by jemalloc on `instance`
+async fn latest_profile(instance: &Instance) -> Result<String> {
+    let output = instance
+        .exec_output(&format!(
+            "ls -t {}.*.heap 2>/dev/null | head -1",
+            PROF_PREFIX
+        ))
+        .await?;
+    match output.trim() {
+        "" => bail!(
+            "No heap profile on {}, is libra-node built with profiling?",
+            instance
+        ),
+        path => Ok(path.to_string()),
+    }
+}
+
+/// Total growth and sites of `jeprof --text` output, which come sorted by flat growth
+fn parse_jeprof_text(text: &str) -> (f64, Vec<GrowthSite>) {
+    let mut total_mb = 0.0;
+    let mut sites = vec![];
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(total) = line.strip_prefix("Total:") {
+            total_mb = total
+                .trim()
+                .trim_end_matches("MB")
+                .trim()
+                .parse()
+                .unwrap_or(0.0);
+            continue;
+        }
+        let fields: Vec<_> = line.split_whitespace().collect();
+        if fields.len() < 6 || !fields[1].ends_with('%') {
+            continue;
+        }
+        if let (Ok(flat_mb), Ok(cum_mb)) = (fields[0].parse(), fields[3].parse()) {
+            sites.push(GrowthSite {
+                flat_mb,
+                cum_mb,
+                symbol: fields[5..].join(" "),
+            });
+        }
+    }
+    (total_mb, sites)
+}
+
+impl fmt::Display for HeapProfileDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Heap profile diff on {}", self.instance)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_jeprof_text() {
+        let text = "Using local file /proc/1/exe.
+Using local file /opt/libra/data/jeprof.1.9.i9.heap.
+Total: 12.5 MB
+     5.0  40.0%  40.0%      6.0  48.0% prof_backtrace_impl
+     3.0  24.0%  64.0%      3.0  24.0% <T as core::clone::Clone>::clone
+    -0.5  -4.0%  60.0%      0.0   0.0% alloc::raw_vec::finish_grow
+";
+        let (total_mb, sites) = parse_jeprof_text(text);
+        assert_eq!(total_mb, 12.5);
+        assert_eq!(sites.len(), 3);
+        assert_eq!(sites[0].flat_mb, 5.0);
+        assert_eq!(sites[0].cum_mb, 6.0);
+        assert_eq!(sites[0].symbol, "prof_backtrace_impl");
+        assert_eq!(sites[1].symbol, "<T as core::clone::Clone>::clone");
+        assert_eq!(sites[2].flat_mb, -0.5);
+    }
+}
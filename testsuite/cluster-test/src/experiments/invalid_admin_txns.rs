@@ -0,0 +1,395 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides a negative test which submits unauthorized or malformed admin and
+/// governance transactions to a real cluster and checks that each of them is rejected with the
+/// expected status, and that admin accounts are left unchanged
+use crate::{
+    cluster::Cluster,
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::Instance,
+    report::ReportSection,
+    tx_emitter::{execute_and_wait_transactions, AccountData},
+    util::unix_timestamp_now,
+};
+use anyhow::{bail, format_err, Result};
+use async_trait::async_trait;
+use libra_crypto::{
+    ed25519::{Ed25519PrivateKey, Ed25519PublicKey},
+    test_utils::KeyPair,
+    traits::{SigningKey, Uniform},
+};
+use libra_json_rpc_client::{
+    views::{AccountView, VMStatusView},
+    JsonRpcAsyncClient, JsonRpcBatch, JsonRpcResponse,
+};
+use libra_logger::info;
+use libra_types::{
+    account_address::AccountAddress,
+    account_config::{coin1_tag, COIN1_NAME},
+    chain_id::ChainId,
+    transaction::{
+        helpers::create_user_txn, RawTransaction, Script, SignedTransaction, TransactionPayload,
+    },
+};
+use rand::{
+    rngs::{OsRng, StdRng},
+    Rng, SeedableRng,
+};
+use std::{collections::HashMap, fmt, time::Duration};
+use structopt::StructOpt;
+use transaction_builder::{encode_tiered_mint_script, encode_update_libra_version_script};
+
+const MAX_GAS_AMOUNT: u64 = 1_000_000;
+const TXN_EXPIRATION_SECONDS: i64 = 50;
+
+/// Abort codes of Move modules, see LibraConfig.move, LibraVersion.move and DesignatedDealer.move
+const EMODIFY_CAPABILITY_NOT_HELD: u64 = 4;
+const EINVALID_MAJOR_VERSION_NUMBER: u64 = 2;
+const EACCOUNT_NOT_TREASURY_COMPLIANCE: u64 = 0;
+const EINVALID_TIER_INDEX: u64 = 3;
+
+#[derive(StructOpt, Debug)]
+pub struct InvalidAdminTxnsParams {
+    #[structopt(
+        long,
+        default_value = "1",
+        help = "Number of times each invalid transaction is submitted"
+    )]
+    pub rounds: usize,
+}
+
+pub struct InvalidAdminTxns {
+    rounds: usize,
+    instance: Instance,
+}
+
+/// How a transaction is expected to fail
+#[derive(Debug)]
+enum Expected {
+    /// Rejected on submission, error contains given status code
+    Rejected(&'static str),
+    /// Committed with given Move abort code, only sequence number of sender changes
+    Aborted(u64),
+}
+
+struct Case {
+    name: &'static str,
+    sender: AccountAddress,
+    txn: SignedTransaction,
+    expected: Expected,
+}
+
+impl ExperimentParam for InvalidAdminTxnsParams {
+    type E = InvalidAdminTxns;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        Self::E {
+            rounds: self.rounds,
+            instance: cluster.random_validator_instance(),
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for InvalidAdminTxns {
+    fn tags(&self) -> &'static [&'static str] {
+        &["negative"]
+    }
+
+    fn report_section(&self) -> ReportSection {
+        ReportSection::Critical
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        let mut client = self.instance.json_rpc_client();
+        let mut root = context
+            .tx_emitter
+            .load_libra_root_account(&self.instance)
+            .await?;
+        let mut tc = context
+            .tx_emitter
+            .load_treasury_compliance_account(&self.instance)
+            .await?;
+        let mut dd = context
+            .tx_emitter
+            .load_faucet_account(&self.instance)
+            .await?;
+        let addresses = [root.address, tc.address, dd.address];
+        let before = query_accounts(&client, &addresses).await?;
+
+        let mut failures = vec![];
+        let mut rejected = 0;
+        let mut aborted = HashMap::new();
+        for round in 0..self.rounds {
+            info!("{} round {}", self, round);
+            for case in gen_cases(&mut root, &mut tc, &mut dd) {
+                let result = match case.expected {
+                    Expected::Rejected(status) => {
+                        let result = check_rejected(&client, case.txn, status).await;
+                        if result.is_ok() {
+                            rejected += 1;
+                        }
+                        result
+                    }
+                    Expected::Aborted(abort_code) => {
+                        let sender = [&root, &tc, &dd]
+                            .iter()
+                            .find(|a| a.address == case.sender)
+                            .map(|a| (*a).clone())
+                            .expect("Sender is one of admin accounts");
+                        let result = check_aborted(&mut client, sender, case.txn, abort_code).await;
+                        *aborted.entry(case.sender).or_insert(0u64) += 1;
+                        result
+                    }
+                };
+                if let Err(e) = result {
+                    failures.push(format!("{}: {}", case.name, e));
+                }
+            }
+        }
+
+        let after = query_accounts(&client, &addresses).await?;
+        for ((address, before), mut after) in addresses.iter().zip(before).zip(after) {
+            let expected_sequence_number =
+                before.sequence_number + aborted.get(address).cloned().unwrap_or(0);
+            if after.sequence_number != expected_sequence_number {
+                failures.push(format!(
+                    "sequence number of {} is {}, expected {}",
+                    address, after.sequence_number, expected_sequence_number
+                ));
+            }
+            after.sequence_number = before.sequence_number;
+            if after != before {
+                failures.push(format!(
+                    "state of {} changed: {:?} -> {:?}",
+                    address, before, after
+                ));
+            }
+        }
+
+        let aborted: u64 = aborted.values().sum();
+        context
+            .report
+            .report_metric(&self, "rejected", rejected as f64);
+        context
+            .report
+            .report_metric(&self, "aborted", aborted as f64);
+        context
+            .report
+            .report_metric(&self, "failures", failures.len() as f64);
+        if !failures.is_empty() {
+            bail!(
+                "Invalid admin transactions check failed:\n{}",
+                failures.join("\n")
+            );
+        }
+        context.report.report_text(format!(
+            "{}: {} transactions rejected, {} aborted",
+            self, rejected, aborted
+        ));
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(300) * self.rounds as u32
+    }
+}
+
+/// Each round covers wrong signer, bad params and malformed envelope of admin transactions
+fn gen_cases(root: &mut AccountData, tc: &mut AccountData, dd: &mut AccountData) -> Vec<Case> {
+    let seed: [u8; 32] = OsRng.gen();
+    let mut rng = StdRng::from_seed(seed);
+    let stranger = KeyPair::<Ed25519PrivateKey, Ed25519PublicKey>::generate(&mut rng);
+    let wrong_chain_id = ChainId::new(ChainId::test().id().wrapping_add(1));
+    let expiration_secs = unix_timestamp_now().as_secs() + TXN_EXPIRATION_SECONDS as u64;
+
+    let mut cases = vec![
+        Case {
+            name: "update_libra_version_by_dd",
+            sender: dd.address,
+            txn: gen_txn(dd, encode_update_libra_version_script(u64::max_value())),
+            expected: Expected::Aborted(EMODIFY_CAPABILITY_NOT_HELD),
+        },
+        Case {
+            name: "update_libra_version_downgrade",
+            sender: root.address,
+            txn: gen_txn(root, encode_update_libra_version_script(0)),
+            expected: Expected::Aborted(EINVALID_MAJOR_VERSION_NUMBER),
+        },
+        Case {
+            name: "tiered_mint_by_dd",
+            sender: dd.address,
+            txn: gen_txn(
+                dd,
+                encode_tiered_mint_script(coin1_tag(), 0, dd.address, 1_000, 0),
+            ),
+            expected: Expected::Aborted(EACCOUNT_NOT_TREASURY_COMPLIANCE),
+        },
+        Case {
+            name: "tiered_mint_invalid_tier",
+            sender: tc.address,
+            txn: gen_txn(
+                tc,
+                encode_tiered_mint_script(coin1_tag(), 0, dd.address, 1_000, 1_000),
+            ),
+            expected: Expected::Aborted(EINVALID_TIER_INDEX),
+        },
+    ];
+
+    // Payload would abort even if envelope was accepted, so that no case can change libra version
+    let raw_txn = |sequence_number, expiration_secs, chain_id| {
+        RawTransaction::new_script(
+            root.address,
+            sequence_number,
+            encode_update_libra_version_script(0),
+            MAX_GAS_AMOUNT,
+            0,
+            COIN1_NAME.to_owned(),
+            expiration_secs,
+            chain_id,
+        )
+    };
+    let sign = |raw: RawTransaction| {
+        raw.sign(&root.key_pair.private_key, root.key_pair.public_key.clone())
+            .expect("Failed to sign transaction")
+            .into_inner()
+    };
+    cases.push(Case {
+        name: "wrong_chain_id",
+        sender: root.address,
+        txn: sign(raw_txn(
+            root.sequence_number,
+            expiration_secs,
+            wrong_chain_id,
+        )),
+        expected: Expected::Rejected("BAD_CHAIN_ID"),
+    });
+    cases.push(Case {
+        name: "expired",
+        sender: root.address,
+        txn: sign(raw_txn(
+            root.sequence_number,
+            expiration_secs - 3600,
+            ChainId::test(),
+        )),
+        expected: Expected::Rejected("TRANSACTION_EXPIRED"),
+    });
+    // Sequence number of update_libra_version_downgrade, which is committed by now
+    cases.push(Case {
+        name: "stale_sequence_number",
+        sender: root.address,
+        txn: sign(raw_txn(
+            root.sequence_number - 1,
+            expiration_secs,
+            ChainId::test(),
+        )),
+        expected: Expected::Rejected("SEQUENCE_NUMBER_TOO_OLD"),
+    });
+    cases.push(Case {
+        name: "wrong_signer_key",
+        sender: root.address,
+        txn: raw_txn(root.sequence_number, expiration_secs, ChainId::test())
+            .sign(&stranger.private_key, stranger.public_key.clone())
+            .expect("Failed to sign transaction")
+            .into_inner(),
+        expected: Expected::Rejected("INVALID_AUTH_KEY"),
+    });
+    let raw = raw_txn(root.sequence_number, expiration_secs, ChainId::test());
+    let signature = stranger.private_key.sign(&raw);
+    cases.push(Case {
+        name: "bad_signature",
+        sender: root.address,
+        txn: SignedTransaction::new(raw, root.key_pair.public_key.clone(), signature),
+        expected: Expected::Rejected("INVALID_SIGNATURE"),
+    });
+    cases
+}
+
+fn gen_txn(sender: &mut AccountData, script: Script) -> SignedTransaction {
+    let txn = create_user_txn(
+        &sender.key_pair,
+        TransactionPayload::Script(script),
+        sender.address,
+        sender.sequence_number,
+        MAX_GAS_AMOUNT,
+        0,
+        COIN1_NAME.to_owned(),
+        TXN_EXPIRATION_SECONDS,
+        ChainId::test(),
+    )
+    .expect("Failed to create signed transaction");
+    sender.sequence_number += 1;
+    txn
+}
+
+async fn check_rejected(
+    client: &JsonRpcAsyncClient,
+    txn: SignedTransaction,
+    status: &str,
+) -> Result<()> {
+    match client.submit_transaction(txn).await {
+        Ok(()) => bail!("transaction was accepted, expected {}", status),
+        Err(e) => {
+            let error = format!("{:?}", e);
+            // Stale sequence numbers may be caught by mempool before VM validation
+            let mempool_rejected =
+                status == "SEQUENCE_NUMBER_TOO_OLD" && error.contains("Mempool submission error");
+            if !error.contains(status) && !mempool_rejected {
+                bail!("expected {}, got {}", status, error);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// `sender` is only used to wait for the transaction, later cases of the round are already
+/// generated with further sequence numbers
+async fn check_aborted(
+    client: &mut JsonRpcAsyncClient,
+    mut sender: AccountData,
+    txn: SignedTransaction,
+    abort_code: u64,
+) -> Result<()> {
+    let sequence_number = txn.sequence_number();
+    sender.sequence_number = sequence_number + 1;
+    execute_and_wait_transactions(client, &mut sender, vec![txn]).await?;
+    let mut batch = JsonRpcBatch::new();
+    batch.add_get_account_transaction_request(sender.address, sequence_number, false);
+    let vm_status = match client.execute(batch).await?.remove(0)? {
+        JsonRpcResponse::AccountTransactionResponse(Some(txn)) => txn.vm_status,
+        other => bail!(
+            "unexpected response for get_account_transaction: {:?}",
+            other
+        ),
+    };
+    match vm_status {
+        VMStatusView::MoveAbort {
+            abort_code: code, ..
+        } if code == abort_code => Ok(()),
+        other => bail!("expected abort code {}, got {:?}", abort_code, other),
+    }
+}
+
+async fn query_accounts(
+    client: &JsonRpcAsyncClient,
+    addresses: &[AccountAddress],
+) -> Result<Vec<AccountView>> {
+    client
+        .get_accounts(addresses)
+        .await
+        .map_err(|e| format_err!("[{:?}] get_accounts failed: {:?}", client, e))?
+        .into_iter()
+        .zip(addresses)
+        .map(|(account, address)| {
+            account.ok_or_else(|| format_err!("account {} does not exist", address))
+        })
+        .collect()
+}
+
+impl fmt::Display for InvalidAdminTxns {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid admin transactions against {}", self.instance)
+    }
+}
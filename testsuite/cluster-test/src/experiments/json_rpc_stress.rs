@@ -0,0 +1,221 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which issues worst-case read queries, i.e. full pages of
+/// transactions with events and long event streams, against fullnodes under write load, and
+/// reports their latency and how much they slow down regular traffic
+use crate::{
+    cluster::Cluster,
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::Instance,
+    query_emitter::{
+        query_latest_version, query_sent_events_key, QueryGenerator, QueryJob, QueryStats,
+    },
+    tx_emitter::{EmitJobRequest, TxStatsRate},
+};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use libra_json_rpc_client::{JsonRpcAsyncClient, JsonRpcBatch};
+use libra_logger::info;
+use libra_types::account_config::testnet_dd_account_address;
+use rand::Rng;
+use std::{fmt, sync::Arc, time::Duration};
+use structopt::StructOpt;
+use tokio::time;
+
+/// Max page size of get_transactions
+const MAX_TRANSACTIONS_LIMIT: u64 = 1000;
+
+#[derive(StructOpt, Debug)]
+pub struct JsonRpcStressParams {
+    #[structopt(
+        long,
+        default_value = "4",
+        help = "Number of concurrent readers per endpoint"
+    )]
+    pub readers: usize,
+    #[structopt(
+        long,
+        default_value = "10000",
+        help = "Limit of get_events queries, which is not capped by server"
+    )]
+    pub events_limit: u64,
+    #[structopt(
+        long,
+        default_value = "120",
+        help = "Duration of each workload run in secs"
+    )]
+    pub duration_secs: u64,
+}
+
+pub struct JsonRpcStress {
+    readers: usize,
+    events_limit: u64,
+    /// Read queries are sent to these, write load to validators
+    read_instances: Vec<Instance>,
+    validators: Vec<Instance>,
+    duration: Duration,
+}
+
+impl ExperimentParam for JsonRpcStressParams {
+    type E = JsonRpcStress;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        let read_instances = if cluster.fullnode_instances().is_empty() {
+            cluster.validator_instances().to_vec()
+        } else {
+            cluster.fullnode_instances().to_vec()
+        };
+        Self::E {
+            readers: self.readers,
+            events_limit: self.events_limit,
+            read_instances,
+            validators: cluster.validator_instances().to_vec(),
+            duration: Duration::from_secs(self.duration_secs),
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for JsonRpcStress {
+    fn tags(&self) -> &'static [&'static str] {
+        &["performance"]
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        let request =
+            EmitJobRequest::for_instances(self.validators.clone(), context.global_emit_job_request);
+        let baseline = context
+            .tx_emitter
+            .emit_txn_for(self.duration, request.clone())
+            .await?
+            .rate(self.duration);
+
+        let clients: Vec<_> = self
+            .read_instances
+            .iter()
+            .map(Instance::json_rpc_client)
+            .collect();
+        // Faucet account sends a payment for every mint, so it has the longest event stream
+        let events_key = query_sent_events_key(&clients[0], testnet_dd_account_address()).await?;
+        let generator = Arc::new(WorstCaseQueries {
+            events_key,
+            events_limit: self.events_limit,
+        });
+        let job = context.tx_emitter.start_job(request).await?;
+        let queries = QueryJob::start(&clients, self.readers, generator, None);
+        time::delay_for(self.duration).await;
+        let mut query_stats = queries.stop().await;
+        let under_reads = context.tx_emitter.stop_job(job).await.rate(self.duration);
+
+        self.report(context, &baseline, &under_reads, &mut query_stats);
+        if query_stats.latencies.values().all(Vec::is_empty) {
+            bail!("All read queries failed");
+        }
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(5 * 60) + self.duration * 2
+    }
+}
+
+impl JsonRpcStress {
+    fn report(
+        &self,
+        context: &mut Context<'_>,
+        baseline: &TxStatsRate,
+        under_reads: &TxStatsRate,
+        query_stats: &mut QueryStats,
+    ) {
+        let mut text = format!("{}:", self);
+        for (kind, latencies) in query_stats.latencies.iter_mut() {
+            let errors = query_stats.errors.get(kind).cloned().unwrap_or(0);
+            context
+                .report
+                .report_metric(&self, format!("{}_errors", kind), errors as f64);
+            if latencies.is_empty() {
+                continue;
+            }
+            latencies.sort_unstable();
+            let avg = latencies.iter().sum::<u64>() / latencies.len() as u64;
+            let p99 = latencies[(latencies.len() - 1) * 99 / 100];
+            context
+                .report
+                .report_metric(&self, format!("{}_avg_latency_ms", kind), avg as f64);
+            context
+                .report
+                .report_metric(&self, format!("{}_p99_latency_ms", kind), p99 as f64);
+            text.push_str(&format!(
+                "\n  {}: {} queries, {} errors, avg {} ms, p99 {} ms",
+                kind,
+                latencies.len(),
+                errors,
+                avg,
+                p99
+            ));
+        }
+        let metrics = [
+            (
+                "tps",
+                baseline.committed as f64,
+                under_reads.committed as f64,
+            ),
+            (
+                "avg_latency_ms",
+                baseline.latency as f64,
+                under_reads.latency as f64,
+            ),
+            (
+                "p99_latency_ms",
+                baseline.p99_latency as f64,
+                under_reads.p99_latency as f64,
+            ),
+        ];
+        text.push_str(
+            &context
+                .report
+                .report_comparison(&self, "under_reads", &metrics),
+        );
+        info!("{}", text);
+        context.report.report_text(text);
+    }
+}
+
+/// Alternates full pages of transactions at random versions and the faucet event stream
+struct WorstCaseQueries {
+    events_key: String,
+    events_limit: u64,
+}
+
+#[async_trait]
+impl QueryGenerator for WorstCaseQueries {
+    async fn add_query(
+        &self,
+        client: &JsonRpcAsyncClient,
+        batch: &mut JsonRpcBatch,
+    ) -> Result<&'static str> {
+        if rand::thread_rng().gen_bool(0.5) {
+            let latest = query_latest_version(client).await?;
+            let start = rand::thread_rng()
+                .gen_range(0, latest.saturating_sub(MAX_TRANSACTIONS_LIMIT).max(1));
+            batch.add_get_transactions_request(start, MAX_TRANSACTIONS_LIMIT, true);
+            Ok("get_transactions")
+        } else {
+            batch.add_get_events_request(self.events_key.clone(), 0, self.events_limit);
+            Ok("get_events")
+        }
+    }
+}
+
+impl fmt::Display for JsonRpcStress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "JSON-RPC stress with {} readers on {} endpoints",
+            self.readers,
+            self.read_instances.len()
+        )
+    }
+}
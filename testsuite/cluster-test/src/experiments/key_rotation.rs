@@ -0,0 +1,357 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment where accounts of the emitter rotate their authentication
+/// keys on-chain while the cluster is under load and keep transacting with the new keys. Every
+/// endpoint has to accept transactions signed with the new key and reject ones signed with the
+/// old key right after the rotation, which catches stale authentication keys cached by nodes
+use crate::{
+    cluster::Cluster,
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::Instance,
+    tx_emitter::{execute_and_wait_transactions, AccountData, EmitJobRequest},
+};
+use anyhow::{bail, format_err, Result};
+use async_trait::async_trait;
+use futures::future::{join_all, try_join_all};
+use libra_crypto::{
+    ed25519::{Ed25519PrivateKey, Ed25519PublicKey},
+    test_utils::KeyPair,
+    traits::Uniform,
+};
+use libra_json_rpc_client::JsonRpcAsyncClient;
+use libra_logger::{info, warn};
+use libra_types::{
+    account_address::AccountAddress,
+    account_config::{coin1_tag, COIN1_NAME},
+    chain_id::ChainId,
+    transaction::{
+        authenticator::AuthenticationKey, helpers::create_user_txn, Script, SignedTransaction,
+        TransactionPayload,
+    },
+};
+use rand::{
+    rngs::{OsRng, StdRng},
+    Rng, SeedableRng,
+};
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use transaction_builder::{
+    encode_peer_to_peer_with_metadata_script, encode_rotate_authentication_key_script,
+};
+
+const MAX_GAS_AMOUNT: u64 = 1_000_000;
+/// Transactions signed with old keys expire soon, in case some node wrongly accepts them
+const TXN_EXPIRATION_SECONDS: i64 = 20;
+
+#[derive(StructOpt, Debug)]
+pub struct KeyRotationParams {
+    #[structopt(
+        long,
+        default_value = "20",
+        help = "Number of accounts which rotate their keys"
+    )]
+    pub accounts: usize,
+    #[structopt(
+        long,
+        default_value = "3",
+        help = "Number of rotations of each account"
+    )]
+    pub rounds: usize,
+    #[structopt(
+        long,
+        default_value = "5",
+        help = "Transactions each account sends with its new key after every rotation"
+    )]
+    pub txns_per_round: usize,
+}
+
+pub struct KeyRotation {
+    accounts: usize,
+    rounds: usize,
+    txns_per_round: usize,
+    /// Transactions are sent to all of them in turn, validators and fullnodes alike
+    instances: Vec<Instance>,
+    /// Background load is sent to these
+    load_instances: Vec<Instance>,
+}
+
+#[derive(Default)]
+struct RotationStats {
+    rotations: u64,
+    rotation_secs: f64,
+    auth_key_mismatches: u64,
+    txns_with_new_key: u64,
+    old_key_accepted: u64,
+}
+
+impl ExperimentParam for KeyRotationParams {
+    type E = KeyRotation;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        Self::E {
+            accounts: self.accounts.max(2),
+            rounds: self.rounds,
+            txns_per_round: self.txns_per_round,
+            instances: cluster
+                .validator_and_fullnode_instances()
+                .cloned()
+                .collect(),
+            load_instances: cluster.validator_instances().to_vec(),
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for KeyRotation {
+    fn tags(&self) -> &'static [&'static str] {
+        &["mempool", "correctness"]
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        let request = EmitJobRequest::for_instances(
+            self.load_instances.clone(),
+            context.global_emit_job_request,
+        );
+        let job = context.tx_emitter.start_job(request.clone()).await?;
+        let result = self.rotate(context, &request).await;
+        context.tx_emitter.stop_job(job).await;
+        let stats = result?;
+
+        let avg_rotation_secs = if stats.rotations > 0 {
+            stats.rotation_secs / self.rounds as f64
+        } else {
+            0.0
+        };
+        let metrics = [
+            ("rotations", stats.rotations as f64),
+            ("avg_rotation_secs", avg_rotation_secs),
+            ("auth_key_mismatches", stats.auth_key_mismatches as f64),
+            ("txns_with_new_key", stats.txns_with_new_key as f64),
+            ("old_key_accepted", stats.old_key_accepted as f64),
+        ];
+        for (metric, value) in metrics.iter() {
+            context.report.report_metric(&self, *metric, *value);
+        }
+        let text = format!(
+            "{}: {} rotations committed in {:.1} secs per round, {} transactions committed with new keys, {} transactions with old keys accepted, {} stale authentication keys",
+            self,
+            stats.rotations,
+            avg_rotation_secs,
+            stats.txns_with_new_key,
+            stats.old_key_accepted,
+            stats.auth_key_mismatches
+        );
+        info!("{}", text);
+        context.report.report_text(text);
+        if stats.old_key_accepted > 0 {
+            bail!(
+                "{} transactions signed with rotated out keys were accepted",
+                stats.old_key_accepted
+            );
+        }
+        if stats.auth_key_mismatches > 0 {
+            bail!(
+                "{} accounts do not have the authentication key they rotated to",
+                stats.auth_key_mismatches
+            );
+        }
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(5 * 60) + Duration::from_secs(3 * 60) * self.rounds as u32
+    }
+}
+
+impl KeyRotation {
+    async fn rotate(
+        &self,
+        context: &mut Context<'_>,
+        request: &EmitJobRequest,
+    ) -> Result<RotationStats> {
+        context
+            .tx_emitter
+            .mint_accounts(request, self.accounts)
+            .await?;
+        let clients: Vec<_> = self
+            .instances
+            .iter()
+            .map(Instance::json_rpc_client)
+            .collect();
+        let mut accounts: Vec<_> = (0..self.accounts)
+            .map(|_| context.tx_emitter.take_account())
+            .collect();
+        let seed: [u8; 32] = OsRng.gen();
+        let mut rng = StdRng::from_seed(seed);
+        let mut stats = RotationStats::default();
+        for round in 0..self.rounds {
+            let new_keys: Vec<_> = accounts
+                .iter()
+                .map(|_| KeyPair::<Ed25519PrivateKey, Ed25519PublicKey>::generate(&mut rng))
+                .collect();
+            let rotations = accounts
+                .iter_mut()
+                .zip(&new_keys)
+                .map(|(account, new_key)| {
+                    sign(
+                        account,
+                        encode_rotate_authentication_key_script(
+                            AuthenticationKey::ed25519(&new_key.public_key).to_vec(),
+                        ),
+                    )
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let started = Instant::now();
+            try_join_all(accounts.iter_mut().zip(rotations).enumerate().map(
+                |(i, (account, txn))| {
+                    let mut client = clients[(i + round) % clients.len()].clone();
+                    async move {
+                        execute_and_wait_transactions(&mut client, account, vec![txn])
+                            .await
+                            .map_err(|e| {
+                                format_err!("Rotation of {} failed: {}", account.address, e)
+                            })
+                    }
+                },
+            ))
+            .await?;
+            stats.rotation_secs += started.elapsed().as_secs_f64();
+            stats.rotations += accounts.len() as u64;
+            let old_keys: Vec<_> = accounts
+                .iter_mut()
+                .zip(new_keys)
+                .map(|(account, new_key)| std::mem::replace(&mut account.key_pair, new_key))
+                .collect();
+            stats.auth_key_mismatches += count_auth_key_mismatches(&clients[0], &accounts).await?;
+
+            // Endpoints other than the one which took the rotation have to pick up the new key
+            let receivers: Vec<_> = accounts.iter().map(|a| a.address).collect();
+            let payments = accounts
+                .iter_mut()
+                .enumerate()
+                .map(|(i, account)| {
+                    let receiver = receivers[(i + 1) % receivers.len()];
+                    (0..self.txns_per_round)
+                        .map(|_| sign(account, payment_script(receiver)))
+                        .collect::<Result<Vec<_>>>()
+                })
+                .collect::<Result<Vec<_>>>()?;
+            try_join_all(accounts.iter_mut().zip(payments).enumerate().map(
+                |(i, (account, txns))| {
+                    let mut client = clients[(i + round + 1) % clients.len()].clone();
+                    async move {
+                        execute_and_wait_transactions(&mut client, account, txns)
+                            .await
+                            .map_err(|e| {
+                                format_err!(
+                                    "Transactions of {} signed with its new key failed: {}",
+                                    account.address,
+                                    e
+                                )
+                            })
+                    }
+                },
+            ))
+            .await?;
+            stats.txns_with_new_key += (accounts.len() * self.txns_per_round) as u64;
+
+            // Sequence numbers of accounts are not advanced, these must not get anywhere
+            let stale = accounts
+                .iter()
+                .zip(&old_keys)
+                .enumerate()
+                .map(|(i, (account, old_key))| {
+                    let receiver = receivers[(i + 1) % receivers.len()];
+                    let mut stale_account = AccountData {
+                        address: account.address,
+                        key_pair: old_key.clone(),
+                        sequence_number: account.sequence_number,
+                    };
+                    sign(&mut stale_account, payment_script(receiver))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let submissions = stale
+                .iter()
+                .flat_map(|txn| clients.iter().map(move |client| submit(client, txn)));
+            for client in join_all(submissions).await.into_iter().flatten() {
+                warn!("{} accepted transaction signed with old key", client);
+                stats.old_key_accepted += 1;
+            }
+            info!("Key rotation round {} of {} done", round + 1, self.rounds);
+        }
+        Ok(stats)
+    }
+}
+
+fn payment_script(receiver: AccountAddress) -> Script {
+    encode_peer_to_peer_with_metadata_script(coin1_tag(), receiver, 1, vec![], vec![])
+}
+
+fn sign(account: &mut AccountData, script: Script) -> Result<SignedTransaction> {
+    let txn = create_user_txn(
+        &account.key_pair,
+        TransactionPayload::Script(script),
+        account.address,
+        account.sequence_number,
+        MAX_GAS_AMOUNT,
+        0,
+        COIN1_NAME.to_owned(),
+        TXN_EXPIRATION_SECONDS,
+        ChainId::test(),
+    )
+    .map_err(|e| format_err!("Failed to create signed transaction: {}", e))?;
+    account.sequence_number += 1;
+    Ok(txn)
+}
+
+/// Returns name of the client if it accepted `txn`
+async fn submit(client: &JsonRpcAsyncClient, txn: &SignedTransaction) -> Result<String> {
+    client
+        .submit_transaction(txn.clone())
+        .await
+        .map(|_| format!("{:?}", client))
+        .map_err(|e| format_err!("[{:?}] {:?}", client, e))
+}
+
+async fn count_auth_key_mismatches(
+    client: &JsonRpcAsyncClient,
+    accounts: &[AccountData],
+) -> Result<u64> {
+    let mut mismatches = 0;
+    for chunk in accounts.chunks(100) {
+        let addresses: Vec<_> = chunk.iter().map(|a| a.address).collect();
+        let views = client
+            .get_accounts(&addresses)
+            .await
+            .map_err(|e| format_err!("[{:?}] get_accounts failed: {:?}", client, e))?;
+        for (account, view) in chunk.iter().zip(views) {
+            let view =
+                view.ok_or_else(|| format_err!("Account {} does not exist", account.address))?;
+            let expected =
+                hex::encode(AuthenticationKey::ed25519(&account.key_pair.public_key).to_vec());
+            if view.authentication_key.0 != expected {
+                warn!(
+                    "Account {} has authentication key {}, rotated to {}",
+                    account.address, view.authentication_key.0, expected
+                );
+                mismatches += 1;
+            }
+        }
+    }
+    Ok(mismatches)
+}
+
+impl fmt::Display for KeyRotation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Key rotation of {} accounts, {} rounds",
+            self.accounts, self.rounds
+        )
+    }
+}
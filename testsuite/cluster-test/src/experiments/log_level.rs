@@ -0,0 +1,184 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which restarts selected validators with verbose logging
+/// under load and reports the throughput cost of it compared to the default log level
+use crate::{
+    cluster::Cluster,
+    experiments::{Context, Experiment, ExperimentParam},
+    instance,
+    instance::Instance,
+    tx_emitter::{EmitJobRequest, TxStatsRate},
+};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use futures::future::try_join_all;
+use libra_logger::info;
+use std::{
+    collections::HashSet,
+    fmt,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use tokio::time;
+
+#[derive(StructOpt, Debug)]
+pub struct LogLevelParams {
+    #[structopt(
+        long,
+        default_value = "1",
+        help = "Number of validators to switch to verbose logging"
+    )]
+    pub count: usize,
+    #[structopt(long, default_value = "DEBUG", help = "Log level, e.g. DEBUG or TRACE")]
+    pub level: String,
+    #[structopt(
+        long,
+        default_value = "120",
+        help = "Duration of each workload run in secs"
+    )]
+    pub duration_secs: u64,
+    #[structopt(
+        long,
+        default_value = "30",
+        help = "Time in secs for restarted validators to catch up before workload starts"
+    )]
+    pub warmup_secs: u64,
+}
+
+pub struct LogLevel {
+    level: String,
+    verbose: Vec<Instance>,
+    instances: Vec<Instance>,
+    duration: Duration,
+    warmup: Duration,
+}
+
+impl ExperimentParam for LogLevelParams {
+    type E = LogLevel;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        let (verbose, _) = cluster.split_n_validators_random(self.count);
+        Self::E {
+            level: self.level.to_uppercase(),
+            verbose: verbose.into_validator_instances(),
+            instances: cluster.validator_instances().to_vec(),
+            duration: Duration::from_secs(self.duration_secs),
+            warmup: Duration::from_secs(self.warmup_secs),
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for LogLevel {
+    fn tags(&self) -> &'static [&'static str] {
+        &["performance"]
+    }
+
+    fn affected_validators(&self) -> HashSet<String> {
+        instance::instancelist_to_set(&self.verbose)
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        let baseline = self.run_phase(context, self.instances.clone()).await?;
+
+        info!(
+            "Switching {} validators to {} logs",
+            self.verbose.len(),
+            self.level
+        );
+        let overrides = vec![format!("level={}", self.level)];
+        let verbose = context
+            .cluster_swarm
+            .apply_config_overrides(&self.verbose, &overrides)
+            .await?;
+        let deadline = Instant::now() + Duration::from_secs(120);
+        try_join_all(verbose.iter().map(|i| i.wait_json_rpc(deadline))).await?;
+        time::delay_for(self.warmup).await;
+        // Restarted validators may have new addresses
+        let instances = self
+            .instances
+            .iter()
+            .map(|i| {
+                verbose
+                    .iter()
+                    .find(|v| v.peer_name() == i.peer_name())
+                    .unwrap_or(i)
+                    .clone()
+            })
+            .collect();
+        let result = self.run_phase(context, instances).await;
+
+        info!("Restoring default log level");
+        let cluster_swarm = context.cluster_swarm;
+        let futures = self.verbose.iter().map(|instance| async move {
+            instance.stop().await?;
+            cluster_swarm
+                .spawn_new_instance(instance.instance_config().clone(), false)
+                .await
+        });
+        let restored = try_join_all(futures).await?;
+        let deadline = Instant::now() + Duration::from_secs(120);
+        try_join_all(restored.iter().map(|i| i.wait_json_rpc(deadline))).await?;
+        let verbose = result?;
+
+        let metrics = [
+            ("tps", baseline.committed as f64, verbose.committed as f64),
+            (
+                "avg_latency_ms",
+                baseline.latency as f64,
+                verbose.latency as f64,
+            ),
+            (
+                "p99_latency_ms",
+                baseline.p99_latency as f64,
+                verbose.p99_latency as f64,
+            ),
+        ];
+        let text = format!(
+            "{}:{}",
+            self,
+            context.report.report_comparison(&self, "verbose", &metrics)
+        );
+        info!("{}", text);
+        context.report.report_text(text);
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(10 * 60) + self.duration * 2 + self.warmup
+    }
+}
+
+impl LogLevel {
+    /// Load is sent to all validators, so that verbose ones are on the submission path too
+    async fn run_phase(
+        &self,
+        context: &mut Context<'_>,
+        instances: Vec<Instance>,
+    ) -> Result<TxStatsRate> {
+        let stats = context
+            .tx_emitter
+            .emit_txn_for(
+                self.duration,
+                EmitJobRequest::for_instances(instances, context.global_emit_job_request),
+            )
+            .await?;
+        if stats.committed == 0 {
+            bail!("No transactions were committed during the run");
+        }
+        Ok(stats.rate(self.duration))
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} logs on {} validators",
+            self.level,
+            self.verbose.len()
+        )
+    }
+}
@@ -0,0 +1,282 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which submits probe transactions with a range of
+/// expiration times while validators are under load, and reports how likely transactions are
+/// to be committed depending on their TTL, and how fast mempool sweeps expired ones
+use crate::{
+    cluster::Cluster,
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::Instance,
+    tx_emitter::{AccountData, EmitJobRequest},
+    util::unix_timestamp_now,
+};
+use anyhow::{bail, format_err, Result};
+use async_trait::async_trait;
+use futures::future::join_all;
+use libra_json_rpc_client::JsonRpcAsyncClient;
+use libra_logger::{info, warn};
+use libra_types::{
+    account_address::AccountAddress,
+    account_config::{coin1_tag, COIN1_NAME},
+    chain_id::ChainId,
+    transaction::{helpers::create_user_txn, TransactionPayload},
+};
+use std::{fmt, time::Duration};
+use structopt::StructOpt;
+use tokio::time;
+use transaction_builder::encode_peer_to_peer_with_metadata_script;
+
+const MAX_GAS_AMOUNT: u64 = 1_000_000;
+/// Time after the longest TTL of a round before probes are checked, so that commits of
+/// transactions which made it just before expiration are visible
+const SETTLE_TIME: Duration = Duration::from_secs(10);
+
+#[derive(StructOpt, Debug)]
+pub struct MempoolTtlParams {
+    #[structopt(
+        long,
+        use_delimiter = true,
+        default_value = "2,5,10,20,40",
+        help = "Expiration times in secs of probe transactions"
+    )]
+    pub ttls_secs: Vec<u64>,
+    #[structopt(
+        long,
+        default_value = "20",
+        help = "Number of probe accounts per expiration time, each submits one transaction per round"
+    )]
+    pub probes_per_ttl: usize,
+    #[structopt(long, default_value = "5", help = "Number of probe rounds")]
+    pub rounds: usize,
+}
+
+pub struct MempoolTtl {
+    ttls: Vec<u64>,
+    probes_per_ttl: usize,
+    rounds: usize,
+    instances: Vec<Instance>,
+}
+
+/// Outcome of probes of one TTL
+#[derive(Default)]
+struct TtlStats {
+    submitted: u64,
+    rejected: u64,
+    committed: u64,
+}
+
+impl ExperimentParam for MempoolTtlParams {
+    type E = MempoolTtl;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        let mut ttls = self.ttls_secs;
+        ttls.sort_unstable();
+        ttls.dedup();
+        Self::E {
+            ttls,
+            probes_per_ttl: self.probes_per_ttl,
+            rounds: self.rounds,
+            instances: cluster.validator_instances().to_vec(),
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for MempoolTtl {
+    fn tags(&self) -> &'static [&'static str] {
+        &["mempool"]
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        if self.ttls.is_empty() {
+            bail!("No expiration times to probe");
+        }
+        let request =
+            EmitJobRequest::for_instances(self.instances.clone(), context.global_emit_job_request);
+        let job = context.tx_emitter.start_job(request.clone()).await?;
+        let start = unix_timestamp_now();
+        let result = self.probe(context, &request).await;
+        let end = unix_timestamp_now();
+        let load = context.tx_emitter.stop_job(job).await;
+        let stats = result?;
+        let window = end - start;
+
+        let mut text = format!(
+            "{}: background load {} txn/s",
+            self,
+            load.rate(window).committed
+        );
+        for (ttl, stats) in self.ttls.iter().zip(&stats) {
+            let probability = if stats.submitted > 0 {
+                stats.committed as f64 / stats.submitted as f64
+            } else {
+                0.0
+            };
+            context.report.report_metric(
+                &self,
+                format!("commit_probability_ttl_{}s", ttl),
+                probability,
+            );
+            context.report.report_metric(
+                &self,
+                format!("rejected_ttl_{}s", ttl),
+                stats.rejected as f64,
+            );
+            text.push_str(&format!(
+                "\n  ttl {}s: {} of {} committed ({:.0}%), {} rejected",
+                ttl,
+                stats.committed,
+                stats.submitted,
+                probability * 100.0,
+                stats.rejected
+            ));
+        }
+        let sweeps = [
+            (
+                "expired_per_sec",
+                "sum(rate(libra_core_mempool_gc_latency_count{type=\"client_expiration\"}[1m]))",
+            ),
+            (
+                "avg_age_at_expiration_sweep_secs",
+                "sum(rate(libra_core_mempool_gc_latency_sum{type=\"client_expiration\"}[1m]))/sum(rate(libra_core_mempool_gc_latency_count{type=\"client_expiration\"}[1m]))",
+            ),
+        ];
+        for (metric, query) in sweeps.iter() {
+            match context
+                .prometheus
+                .query_range_avg(query.to_string(), &start, &end, 10)
+            {
+                Ok(value) => {
+                    context.report.report_metric(&self, *metric, value);
+                    text.push_str(&format!("\n  {}: {:.2}", metric, value));
+                }
+                Err(e) => warn!("Failed to query {}: {}", metric, e),
+            }
+        }
+        info!("{}", text);
+        context.report.report_text(text);
+        if stats.iter().all(|s| s.committed == 0) {
+            bail!("No probe transaction was committed");
+        }
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        let max_ttl = self.ttls.iter().max().cloned().unwrap_or(0);
+        Duration::from_secs(5 * 60)
+            + (Duration::from_secs(max_ttl) + SETTLE_TIME) * self.rounds as u32
+    }
+}
+
+impl MempoolTtl {
+    /// Each round every probe account submits one transaction, so that expiration of one
+    /// does not park later transactions of the same account
+    async fn probe(
+        &self,
+        context: &mut Context<'_>,
+        request: &EmitJobRequest,
+    ) -> Result<Vec<TtlStats>> {
+        let num_probes = self.ttls.len() * self.probes_per_ttl;
+        context
+            .tx_emitter
+            .mint_accounts(request, num_probes)
+            .await?;
+        let mut accounts: Vec<_> = (0..num_probes)
+            .map(|_| context.tx_emitter.take_account())
+            .collect();
+        let clients: Vec<_> = self
+            .instances
+            .iter()
+            .map(Instance::json_rpc_client)
+            .collect();
+        let max_ttl = Duration::from_secs(*self.ttls.last().expect("ttls can not be empty"));
+        let mut stats: Vec<_> = self.ttls.iter().map(|_| TtlStats::default()).collect();
+        for round in 0..self.rounds {
+            let submissions = accounts.iter().enumerate().map(|(i, account)| {
+                let ttl = self.ttls[i / self.probes_per_ttl];
+                let receiver = accounts[(i + 1) % accounts.len()].address;
+                let client = &clients[i % clients.len()];
+                submit_probe(client, account, receiver, ttl)
+            });
+            let results = join_all(submissions).await;
+            time::delay_for(max_ttl + SETTLE_TIME).await;
+            let committed = query_committed(&clients[0], &mut accounts).await?;
+            for (i, (result, committed)) in results.iter().zip(committed).enumerate() {
+                let stats = &mut stats[i / self.probes_per_ttl];
+                stats.submitted += 1;
+                if let Err(e) = result {
+                    warn!("Probe of {} was rejected: {}", accounts[i].address, e);
+                    stats.rejected += 1;
+                } else if committed {
+                    stats.committed += 1;
+                }
+            }
+            info!("Probe round {} of {} done", round + 1, self.rounds);
+        }
+        Ok(stats)
+    }
+}
+
+async fn submit_probe(
+    client: &JsonRpcAsyncClient,
+    account: &AccountData,
+    receiver: AccountAddress,
+    ttl: u64,
+) -> Result<()> {
+    let txn = create_user_txn(
+        &account.key_pair,
+        TransactionPayload::Script(encode_peer_to_peer_with_metadata_script(
+            coin1_tag(),
+            receiver,
+            1,
+            vec![],
+            vec![],
+        )),
+        account.address,
+        account.sequence_number,
+        MAX_GAS_AMOUNT,
+        0,
+        COIN1_NAME.to_owned(),
+        ttl as i64,
+        ChainId::test(),
+    )?;
+    client
+        .submit_transaction(txn)
+        .await
+        .map_err(|e| format_err!("[{:?}] Failed to submit probe: {:?}", client, e))
+}
+
+/// Whether probe of each account was committed, sequence numbers are updated to the chain
+async fn query_committed(
+    client: &JsonRpcAsyncClient,
+    accounts: &mut [AccountData],
+) -> Result<Vec<bool>> {
+    let mut committed = vec![];
+    for chunk in accounts.chunks_mut(100) {
+        let addresses: Vec<_> = chunk.iter().map(|a| a.address).collect();
+        let views = client
+            .get_accounts(&addresses)
+            .await
+            .map_err(|e| format_err!("[{:?}] get_accounts failed: {:?}", client, e))?;
+        for (account, view) in chunk.iter_mut().zip(views) {
+            let sequence_number = view
+                .ok_or_else(|| format_err!("Probe account {} does not exist", account.address))?
+                .sequence_number;
+            committed.push(sequence_number > account.sequence_number);
+            account.sequence_number = sequence_number;
+        }
+    }
+    Ok(committed)
+}
+
+impl fmt::Display for MempoolTtl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Mempool TTL sweep of {:?} secs with {} probes each",
+            self.ttls, self.probes_per_ttl
+        )
+    }
+}
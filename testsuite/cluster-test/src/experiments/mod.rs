@@ -3,15 +3,44 @@
 
 #![forbid(unsafe_code)]
 
+mod back_pressure;
+mod canary;
+mod chaos_monkey;
 mod compatibility_test;
+mod config_ab_test;
+mod corrupted_db_restart;
 mod cpu_flamegraph;
+mod disaster_recovery;
+mod fd_pressure;
+mod half_open_connections;
+mod heap_profile_diff;
+mod invalid_admin_txns;
+mod json_rpc_stress;
+mod key_rotation;
+mod log_level;
+mod mempool_ttl;
 mod packet_loss_random_validators;
+mod pause_sweep;
 mod performance_benchmark;
 mod performance_benchmark_three_region_simulation;
+mod prepopulate;
+mod quorum_loss;
+mod read_write_ratio;
 mod reboot_random_validators;
 mod recovery_time;
+mod replay_protection;
+mod retry_storm;
+mod scenario_replay;
+mod slow_network_fullnode_sync;
+mod snapshot_benchmark;
+mod storage_contention;
+mod storage_latency_sweep;
 mod twin_validator;
+mod validator_ip_change;
+mod validator_removal;
+mod validator_set_scaling;
 mod versioning_test;
+mod zone_outage;
 
 use std::{
     collections::{HashMap, HashSet},
@@ -19,28 +48,58 @@ use std::{
     time::Duration,
 };
 
-pub use compatibility_test::{CompatibilityTest, CompatiblityTestParams};
+pub use back_pressure::{BackPressure, BackPressureParams};
+pub use canary::{Canary, CanaryParams};
+pub use chaos_monkey::{ChaosMonkey, ChaosMonkeyParams};
+pub use compatibility_test::{update_batch_instance, CompatibilityTest, CompatiblityTestParams};
+pub use config_ab_test::{ConfigAbTest, ConfigAbTestParams};
+pub use corrupted_db_restart::{CorruptedDbRestart, CorruptedDbRestartParams};
+pub use disaster_recovery::{DisasterRecovery, DisasterRecoveryParams};
+pub use fd_pressure::{FdPressureExperiment, FdPressureParams};
+pub use half_open_connections::{HalfOpenConnections, HalfOpenConnectionsParams};
+pub use heap_profile_diff::{HeapProfileDiff, HeapProfileDiffParams};
+pub use invalid_admin_txns::{InvalidAdminTxns, InvalidAdminTxnsParams};
+pub use json_rpc_stress::{JsonRpcStress, JsonRpcStressParams};
+pub use key_rotation::{KeyRotation, KeyRotationParams};
+pub use log_level::{LogLevel, LogLevelParams};
+pub use mempool_ttl::{MempoolTtl, MempoolTtlParams};
 pub use packet_loss_random_validators::{
     PacketLossRandomValidators, PacketLossRandomValidatorsParams,
 };
+pub use pause_sweep::{PauseSweep, PauseSweepParams};
 pub use performance_benchmark::{PerformanceBenchmark, PerformanceBenchmarkParams};
 pub use performance_benchmark_three_region_simulation::{
     PerformanceBenchmarkThreeRegionSimulation, PerformanceBenchmarkThreeRegionSimulationParams,
 };
+pub use prepopulate::{Prepopulate, PrepopulateParams};
+pub use quorum_loss::{QuorumLoss, QuorumLossParams};
+pub use read_write_ratio::{ReadWriteRatio, ReadWriteRatioParams};
 pub use reboot_random_validators::{RebootRandomValidators, RebootRandomValidatorsParams};
 pub use recovery_time::{RecoveryTime, RecoveryTimeParams};
+pub use replay_protection::{ReplayProtection, ReplayProtectionParams};
+pub use retry_storm::{RetryStorm, RetryStormParams};
+pub use scenario_replay::{ScenarioReplay, ScenarioReplayParams};
+pub use slow_network_fullnode_sync::{SlowNetworkFullnodeSync, SlowNetworkFullnodeSyncParams};
+pub use snapshot_benchmark::{SnapshotBenchmark, SnapshotBenchmarkParams};
+pub use storage_contention::{StorageContention, StorageContentionParams};
+pub use storage_latency_sweep::{StorageLatencySweep, StorageLatencySweepParams};
 pub use twin_validator::{TwinValidators, TwinValidatorsParams};
+pub use validator_ip_change::{ValidatorIpChange, ValidatorIpChangeParams};
+pub use validator_removal::{ValidatorRemoval, ValidatorRemovalParams};
+pub use validator_set_scaling::{ValidatorSetScaling, ValidatorSetScalingParams};
 pub use versioning_test::{ValidatorVersioning, ValidatorVersioningParams};
+pub use zone_outage::{ZoneOutage, ZoneOutageParams};
 
 use crate::{
     cluster::Cluster,
     prometheus::Prometheus,
-    report::SuiteReport,
+    report::{ReportSection, SuiteReport},
     tx_emitter::{EmitJobRequest, TxEmitter},
+    warm_state::WarmState,
 };
 
 use crate::{
-    cluster_swarm::{cluster_swarm_kube::ClusterSwarmKube, ClusterSwarm},
+    cluster_swarm::{ClusterSwarm, TemporaryFullnodes},
     health::TraceTail,
 };
 use async_trait::async_trait;
@@ -49,11 +108,36 @@ use structopt::{clap::AppSettings, StructOpt};
 
 #[async_trait]
 pub trait Experiment: Display + Send {
+    /// Tags describing area this experiment covers (e.g. "network", "storage", "long"),
+    /// used to run only a subset of a suite with --include-tags / --exclude-tags
+    fn tags(&self) -> &'static [&'static str] {
+        &[]
+    }
     fn affected_validators(&self) -> HashSet<String> {
         HashSet::new()
     }
+    /// Section of the report metrics and text of this experiment belong to
+    fn report_section(&self) -> ReportSection {
+        if self.tags().contains(&"performance") {
+            ReportSection::Performance
+        } else {
+            ReportSection::Informational
+        }
+    }
+    /// Whether cluster may run mixed versions when suite with this experiment starts, e.g. left
+    /// over from an upgrade test
+    fn expects_version_skew(&self) -> bool {
+        self.tags().contains(&"upgrade")
+    }
     async fn run(&mut self, context: &mut Context<'_>) -> anyhow::Result<()>;
     fn deadline(&self) -> Duration;
+    /// How long the experiment usually runs, used to fit suites into their time budget
+    fn expected_duration(&self) -> Duration {
+        self.deadline()
+    }
+    /// Shortens the experiment to run for about `duration` where it can, experiments of fixed
+    /// length ignore it
+    fn trim(&mut self, _duration: Duration) {}
 }
 
 pub trait ExperimentParam {
@@ -72,6 +156,10 @@ pub struct Context<'a> {
     pub cluster_swarm: &'a dyn ClusterSwarm,
     /// Current docker image tag used by this run
     pub current_tag: &'a str,
+    /// Setup earlier experiments of the suite did, which this one can reuse
+    pub warm_state: &'a mut WarmState,
+    /// Extra fullnodes of this experiment, stopped when it ends
+    pub temporary_fullnodes: TemporaryFullnodes,
 }
 
 impl<'a> Context<'a> {
@@ -83,8 +171,9 @@ impl<'a> Context<'a> {
         report: &'a mut SuiteReport,
         emit_job_request: &'a mut Option<EmitJobRequest>,
         emit_to_validator: bool,
-        cluster_swarm: &'a ClusterSwarmKube,
+        cluster_swarm: &'a dyn ClusterSwarm,
         current_tag: &'a str,
+        warm_state: &'a mut WarmState,
     ) -> Self {
         Context {
             tx_emitter,
@@ -96,6 +185,8 @@ impl<'a> Context<'a> {
             emit_to_validator,
             cluster_swarm,
             current_tag,
+            warm_state,
+            temporary_fullnodes: TemporaryFullnodes::default(),
         }
     }
 }
@@ -140,6 +231,38 @@ pub fn get_experiment(name: &str, args: &[String], cluster: &Cluster) -> Box<dyn
     known_experiments.insert("generate_cpu_flamegraph", f::<CpuFlamegraphParams>());
     known_experiments.insert("versioning_testing", f::<ValidatorVersioningParams>());
     known_experiments.insert("compatibility_test", f::<CompatiblityTestParams>());
+    known_experiments.insert(
+        "slow_network_fullnode_sync",
+        f::<SlowNetworkFullnodeSyncParams>(),
+    );
+    known_experiments.insert("corrupted_db_restart", f::<CorruptedDbRestartParams>());
+    known_experiments.insert("validator_ip_change", f::<ValidatorIpChangeParams>());
+    known_experiments.insert("validator_set_scaling", f::<ValidatorSetScalingParams>());
+    known_experiments.insert("quorum_loss", f::<QuorumLossParams>());
+    known_experiments.insert("chaos_monkey", f::<ChaosMonkeyParams>());
+    known_experiments.insert("config_ab_test", f::<ConfigAbTestParams>());
+    known_experiments.insert("fd_pressure", f::<FdPressureParams>());
+    known_experiments.insert("invalid_admin_txns", f::<InvalidAdminTxnsParams>());
+    known_experiments.insert("log_level", f::<LogLevelParams>());
+    known_experiments.insert("json_rpc_stress", f::<JsonRpcStressParams>());
+    known_experiments.insert("half_open_connections", f::<HalfOpenConnectionsParams>());
+    known_experiments.insert("pause_sweep", f::<PauseSweepParams>());
+    known_experiments.insert("disaster_recovery", f::<DisasterRecoveryParams>());
+    known_experiments.insert("mempool_ttl", f::<MempoolTtlParams>());
+    known_experiments.insert("prepopulate", f::<PrepopulateParams>());
+    known_experiments.insert("back_pressure", f::<BackPressureParams>());
+    known_experiments.insert("snapshot_benchmark", f::<SnapshotBenchmarkParams>());
+    known_experiments.insert("read_write_ratio", f::<ReadWriteRatioParams>());
+    known_experiments.insert("validator_removal", f::<ValidatorRemovalParams>());
+    known_experiments.insert("replay_protection", f::<ReplayProtectionParams>());
+    known_experiments.insert("zone_outage", f::<ZoneOutageParams>());
+    known_experiments.insert("key_rotation", f::<KeyRotationParams>());
+    known_experiments.insert("storage_contention", f::<StorageContentionParams>());
+    known_experiments.insert("retry_storm", f::<RetryStormParams>());
+    known_experiments.insert("heap_profile_diff", f::<HeapProfileDiffParams>());
+    known_experiments.insert("storage_latency_sweep", f::<StorageLatencySweepParams>());
+    known_experiments.insert("scenario_replay", f::<ScenarioReplayParams>());
+    known_experiments.insert("canary", f::<CanaryParams>());
 
     let builder = known_experiments.get(name).expect("Experiment not found");
     builder(args, cluster)
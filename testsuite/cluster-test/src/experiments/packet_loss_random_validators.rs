@@ -65,6 +65,10 @@ impl ExperimentParam for PacketLossRandomValidatorsParams {
 
 #[async_trait]
 impl Experiment for PacketLossRandomValidators {
+    fn tags(&self) -> &'static [&'static str] {
+        &["network"]
+    }
+
     async fn run(&mut self, _context: &mut Context<'_>) -> anyhow::Result<()> {
         let mut effects: Vec<_> = self
             .instances
@@ -0,0 +1,234 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which repeatedly freezes selected validators with
+/// SIGSTOP under load, with pause durations increasing step by step, to find the longest pause
+/// a validator survives. A pause is survived if the cluster keeps committing during the step
+/// and paused validators catch up with the rest of the cluster after the pauses stop
+use crate::{
+    cluster::Cluster,
+    effects::{self, process_pause::ProcessPause},
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::{self, Instance},
+    tx_emitter::EmitJobRequest,
+};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use futures::future::join_all;
+use libra_logger::{info, warn};
+use std::{
+    collections::HashSet,
+    fmt, mem,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use tokio::time;
+
+#[derive(StructOpt, Debug)]
+pub struct PauseSweepParams {
+    #[structopt(long, default_value = "1", help = "Number of validators to pause")]
+    pub count: usize,
+    #[structopt(
+        long,
+        use_delimiter = true,
+        default_value = "1,2,5,10,20,30,60",
+        help = "Pause durations in secs to try, in order"
+    )]
+    pub pauses_secs: Vec<u64>,
+    #[structopt(
+        long,
+        default_value = "30",
+        help = "Time in secs validators run between pauses"
+    )]
+    pub interval_secs: u64,
+    #[structopt(
+        long,
+        default_value = "120",
+        help = "Duration in secs of each step of the sweep"
+    )]
+    pub step_secs: u64,
+    #[structopt(
+        long,
+        default_value = "60",
+        help = "Time in secs for paused validators to catch up after each step"
+    )]
+    pub catch_up_secs: u64,
+}
+
+pub struct PauseSweep {
+    instances: Vec<Instance>,
+    validators: Vec<Instance>,
+    pauses: Vec<Duration>,
+    interval: Duration,
+    step: Duration,
+    catch_up: Duration,
+}
+
+impl ExperimentParam for PauseSweepParams {
+    type E = PauseSweep;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        let (paused, _) = cluster.split_n_validators_random(self.count);
+        Self::E {
+            instances: paused.into_validator_instances(),
+            validators: cluster.validator_instances().to_vec(),
+            pauses: self
+                .pauses_secs
+                .into_iter()
+                .map(Duration::from_secs)
+                .collect(),
+            interval: Duration::from_secs(self.interval_secs),
+            step: Duration::from_secs(self.step_secs),
+            catch_up: Duration::from_secs(self.catch_up_secs),
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for PauseSweep {
+    fn tags(&self) -> &'static [&'static str] {
+        &["process"]
+    }
+
+    fn affected_validators(&self) -> HashSet<String> {
+        instance::instancelist_to_set(&self.instances)
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        let mut longest_survived = None;
+        let mut text = format!("{}:", self);
+        for pause in self.pauses.clone() {
+            let failure = self.run_step(context, pause).await?;
+            text.push_str(&format!(
+                "\n  {} secs pause: {}",
+                pause.as_secs(),
+                failure.as_deref().unwrap_or("survived")
+            ));
+            match failure {
+                None => longest_survived = Some(pause),
+                Some(failure) => {
+                    warn!(
+                        "{} secs pause was not survived: {}",
+                        pause.as_secs(),
+                        failure
+                    );
+                    break;
+                }
+            }
+        }
+        info!("{}", text);
+        context.report.report_text(text);
+        match longest_survived {
+            Some(pause) => {
+                context.report.report_metric(
+                    &self,
+                    "longest_survived_pause_secs",
+                    pause.as_secs_f64(),
+                );
+                Ok(())
+            }
+            None => bail!("Validators did not survive the shortest pause"),
+        }
+    }
+
+    fn deadline(&self) -> Duration {
+        (self.step + self.catch_up + Duration::from_secs(60)) * self.pauses.len() as u32
+    }
+}
+
+impl PauseSweep {
+    /// Returns reason the pause was not survived, if any
+    async fn run_step(&self, context: &mut Context<'_>, pause: Duration) -> Result<Option<String>> {
+        info!("Pausing validators for {} secs", pause.as_secs());
+        let job = context
+            .tx_emitter
+            .start_job(EmitJobRequest::for_instances(
+                self.validators.clone(),
+                context.global_emit_job_request,
+            ))
+            .await?;
+        let mut pauses = Pauses {
+            effects: self
+                .instances
+                .iter()
+                .map(|instance| ProcessPause::new(instance.clone(), pause, self.interval))
+                .collect(),
+        };
+        let activated = effects::activate_all(&mut pauses.effects).await;
+        if activated.is_ok() {
+            time::delay_for(self.step).await;
+        }
+        let stopped = pauses.stop().await;
+        if let Err(e) = activated.and(stopped) {
+            context.tx_emitter.stop_job(job).await;
+            return Err(e);
+        }
+        let stats = context.tx_emitter.stop_job(job).await;
+        if stats.committed == 0 {
+            return Ok(Some("cluster stopped committing".to_string()));
+        }
+
+        let target = join_all(self.validators.iter().map(Instance::latest_version))
+            .await
+            .into_iter()
+            .filter_map(Result::ok)
+            .max()
+            .unwrap_or(0);
+        let deadline = Instant::now() + self.catch_up;
+        for instance in &self.instances {
+            loop {
+                match instance.latest_version().await {
+                    Ok(version) if version >= target => break,
+                    _ if Instant::now() > deadline => {
+                        return Ok(Some(format!(
+                            "{} did not catch up in {} secs",
+                            instance,
+                            self.catch_up.as_secs()
+                        )));
+                    }
+                    _ => time::delay_for(Duration::from_secs(1)).await,
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Pausing helpers of a step. Nodes stay frozen again and again while helpers run, so when the
+/// run is dropped on deadline before `stop` helpers are stopped by a spawned task
+struct Pauses {
+    effects: Vec<ProcessPause>,
+}
+
+impl Pauses {
+    async fn stop(&mut self) -> Result<()> {
+        let mut effects = mem::take(&mut self.effects);
+        effects::deactivate_all(&mut effects).await
+    }
+}
+
+impl Drop for Pauses {
+    fn drop(&mut self) {
+        if self.effects.is_empty() {
+            return;
+        }
+        let mut effects = mem::take(&mut self.effects);
+        tokio::spawn(async move {
+            if let Err(e) = effects::deactivate_all(&mut effects).await {
+                warn!("Failed to stop process pauses: {}", e);
+            }
+        });
+    }
+}
+
+impl fmt::Display for PauseSweep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Pause sweep on {} validators, every {} secs",
+            self.instances.len(),
+            self.interval.as_secs()
+        )
+    }
+}
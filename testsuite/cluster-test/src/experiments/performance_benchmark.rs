@@ -6,7 +6,8 @@ use crate::{
     experiments::{Context, Experiment, ExperimentParam},
     instance,
     instance::Instance,
-    stats::PrometheusRangeView,
+    latency_breakdown,
+    stats::{self, PrometheusRangeView},
     tx_emitter::{EmitJobRequest, TxStats},
     util::unix_timestamp_now,
 };
@@ -14,12 +15,8 @@ use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use futures::{future::try_join_all, join};
 use libra_logger::{info, warn};
-use libra_trace::{
-    trace::{random_node, trace_node},
-    LibraTraceClient,
-};
+use libra_trace::LibraTraceClient;
 use rand::{rngs::ThreadRng, seq::SliceRandom};
-use serde_json::Value;
 use std::{
     collections::HashSet,
     fmt::{Display, Error, Formatter},
@@ -36,13 +33,21 @@ pub struct PerformanceBenchmarkParams {
         help = "Percent of nodes which should be down"
     )]
     pub percent_nodes_down: usize,
-    #[structopt(long, help = "Whether benchmark should perform trace")]
+    #[structopt(
+        long,
+        help = "Whether benchmark should trace transactions and report median latency of each stage"
+    )]
     pub trace: bool,
     #[structopt(
         long,
         help = "Whether benchmark should perform trace from elastic search logs"
     )]
     pub use_logs_for_trace: bool,
+    #[structopt(
+        long,
+        help = "With --trace, traces one in given number of transactions over the whole measured window instead of all of them for 5 secs"
+    )]
+    pub trace_sample_one_in: Option<u64>,
     #[structopt(
     long,
     default_value = Box::leak(format!("{}", DEFAULT_BENCH_DURATION).into_boxed_str()),
@@ -56,6 +61,18 @@ pub struct PerformanceBenchmarkParams {
         help = "Whether benchmark should pick one node to run DB backup."
     )]
     pub backup: bool,
+    #[structopt(
+        long,
+        default_value = "60",
+        help = "Secs at the start of the run excluded from reported metrics"
+    )]
+    pub warmup_secs: u64,
+    #[structopt(
+        long,
+        default_value = "60",
+        help = "Secs at the end of the run excluded from reported metrics"
+    )]
+    pub cooldown_secs: u64,
 }
 
 pub struct PerformanceBenchmark {
@@ -67,10 +84,23 @@ pub struct PerformanceBenchmark {
     trace: bool,
     tps: Option<u64>,
     use_logs_for_trace: bool,
+    trace_sample_one_in: Option<u64>,
     backup: bool,
+    warmup: Duration,
+    cooldown: Duration,
 }
 
 pub const DEFAULT_BENCH_DURATION: u64 = 120;
+const DEFAULT_WARMUP_SECS: u64 = 60;
+const DEFAULT_COOLDOWN_SECS: u64 = 60;
+/// Sampled trace is broken down per window of this length
+const TRACE_WINDOW: Duration = Duration::from_secs(60);
+/// Time taken by stopping nodes and minting accounts before the load starts
+const SETUP_ESTIMATE: Duration = Duration::from_secs(60);
+/// Trimmed benchmarks still measure this long, with at least `MIN_TRIMMED_MARGIN` of warmup
+/// and cooldown
+const MIN_TRIMMED_DURATION: Duration = Duration::from_secs(30);
+const MIN_TRIMMED_MARGIN: Duration = Duration::from_secs(10);
 
 impl PerformanceBenchmarkParams {
     pub fn new_nodes_down(percent_nodes_down: usize) -> Self {
@@ -80,7 +110,10 @@ impl PerformanceBenchmarkParams {
             trace: false,
             tps: None,
             use_logs_for_trace: false,
+            trace_sample_one_in: None,
             backup: false,
+            warmup_secs: DEFAULT_WARMUP_SECS,
+            cooldown_secs: DEFAULT_COOLDOWN_SECS,
         }
     }
 
@@ -91,7 +124,10 @@ impl PerformanceBenchmarkParams {
             trace: false,
             tps: Some(fixed_tps),
             use_logs_for_trace: false,
+            trace_sample_one_in: None,
             backup: false,
+            warmup_secs: DEFAULT_WARMUP_SECS,
+            cooldown_secs: DEFAULT_COOLDOWN_SECS,
         }
     }
 
@@ -127,13 +163,20 @@ impl ExperimentParam for PerformanceBenchmarkParams {
             trace: self.trace,
             tps: self.tps,
             use_logs_for_trace: self.use_logs_for_trace,
+            trace_sample_one_in: self.trace_sample_one_in,
             backup: self.backup,
+            warmup: Duration::from_secs(self.warmup_secs),
+            cooldown: Duration::from_secs(self.cooldown_secs),
         }
     }
 }
 
 #[async_trait]
 impl Experiment for PerformanceBenchmark {
+    fn tags(&self) -> &'static [&'static str] {
+        &["performance"]
+    }
+
     fn affected_validators(&self) -> HashSet<String> {
         instance::instancelist_to_set(&self.down_validators)
     }
@@ -143,36 +186,58 @@ impl Experiment for PerformanceBenchmark {
         try_join_all(futures).await?;
 
         let backup = self.maybe_start_backup()?;
-        let buffer = Duration::from_secs(60);
-        let window = self.duration + buffer * 2;
         let instances = if context.emit_to_validator {
             self.up_validators.clone()
         } else {
             self.up_fullnodes.clone()
         };
+        let split_traffic = context
+            .global_emit_job_request
+            .as_ref()
+            .map_or(false, |r| r.validator_traffic_percent.is_some());
         let emit_job_request = match self.tps {
             Some(tps) => EmitJobRequest::fixed_tps(instances, tps),
+            None if split_traffic && !self.up_fullnodes.is_empty() => {
+                EmitJobRequest::split_traffic(
+                    self.up_fullnodes.clone(),
+                    self.up_validators.clone(),
+                    context.global_emit_job_request,
+                )
+            }
             None => EmitJobRequest::for_instances(instances, context.global_emit_job_request),
         };
-        let emit_txn = context.tx_emitter.emit_txn_for(window, emit_job_request);
+        let emit_txn = context.tx_emitter.emit_txn_for_steady_state(
+            self.warmup,
+            self.duration,
+            self.cooldown,
+            emit_job_request,
+        );
         let start = chrono::Utc::now();
         let trace_tail = &context.trace_tail;
-        let trace_delay = buffer;
+        let trace_delay = self.warmup;
         let trace = self.trace;
+        let trace_sample_one_in = self.trace_sample_one_in;
+        let trace_duration = self.duration;
         let capture_trace = async move {
-            if trace {
-                tokio::time::delay_for(trace_delay).await;
-                Some(trace_tail.capture_trace(Duration::from_secs(5)).await)
-            } else {
-                None
+            if !trace {
+                return None;
             }
+            tokio::time::delay_for(trace_delay).await;
+            Some(match trace_sample_one_in {
+                Some(one_in) => {
+                    trace_tail
+                        .capture_sampled_trace(trace_duration, one_in)
+                        .await
+                }
+                None => trace_tail.capture_trace(Duration::from_secs(5)).await,
+            })
         };
         let (stats, mut trace) = join!(emit_txn, capture_trace);
 
         // Trace
         let trace_log = self.use_logs_for_trace;
         if trace_log {
-            let start = start + chrono::Duration::seconds(60);
+            let start = start + chrono::Duration::seconds(self.warmup.as_secs() as i64);
             let libra_trace_client = LibraTraceClient::new("elasticsearch-master", 9200);
             trace = match libra_trace_client
                 .get_libra_trace(start, chrono::Duration::seconds(5))
@@ -187,25 +252,36 @@ impl Experiment for PerformanceBenchmark {
         }
         if let Some(trace) = trace {
             info!("Traced {} events", trace.len());
-            let mut events = vec![];
-            for (node, mut event) in trace {
-                // This could be done more elegantly, but for now this will do
-                event
-                    .json
-                    .as_object_mut()
-                    .unwrap()
-                    .insert("peer".to_string(), Value::String(node));
-                events.push(event);
+            let breakdown = latency_breakdown::compute(&trace);
+            for ((stage, median, _), p99) in breakdown.stages.iter().zip(&breakdown.p99) {
+                if let Some(median) = median {
+                    context.report.report_metric(
+                        &self,
+                        format!("{}_median_ms", stage),
+                        *median as f64,
+                    );
+                }
+                if let Some(p99) = p99 {
+                    context
+                        .report
+                        .report_metric(&self, format!("{}_p99_ms", stage), *p99 as f64);
+                }
+            }
+            let mut text = format!("{}: {}", self, breakdown);
+            // Sampled trace covers the whole window, so it shows how stages change over it
+            if self.trace_sample_one_in.is_some() && !trace_log {
+                let windows =
+                    latency_breakdown::compute_per_window(&trace, TRACE_WINDOW.as_millis() as u64);
+                let first = windows.keys().next().copied().unwrap_or(0);
+                for (start, breakdown) in windows {
+                    text.push_str(&format!("\n  +{}s: {}", (start - first) / 1000, breakdown));
+                }
             }
-            events.sort_by_key(|k| k.timestamp);
-            let node =
-                random_node(&events[..], "json-rpc::submit", "txn::").expect("No trace node found");
-            info!("Tracing {}", node);
-            trace_node(&events[..], &node);
+            context.report.report_text(text);
         }
 
         // Report
-        self.report(context, buffer, window, stats?).await?;
+        self.report(context, stats?).await?;
 
         // Clean up
         drop(backup);
@@ -220,7 +296,23 @@ impl Experiment for PerformanceBenchmark {
     }
 
     fn deadline(&self) -> Duration {
-        Duration::from_secs(600) + self.duration
+        Duration::from_secs(480) + self.warmup + self.duration + self.cooldown
+    }
+
+    fn expected_duration(&self) -> Duration {
+        SETUP_ESTIMATE + self.warmup + self.duration + self.cooldown
+    }
+
+    fn trim(&mut self, duration: Duration) {
+        let run = self.warmup + self.duration + self.cooldown;
+        let available = duration.checked_sub(SETUP_ESTIMATE).unwrap_or_default();
+        if available >= run {
+            return;
+        }
+        let scale = available.as_secs_f64() / run.as_secs_f64();
+        self.duration = self.duration.mul_f64(scale).max(MIN_TRIMMED_DURATION);
+        self.warmup = self.warmup.mul_f64(scale).max(MIN_TRIMMED_MARGIN);
+        self.cooldown = self.cooldown.mul_f64(scale).max(MIN_TRIMMED_MARGIN);
     }
 }
 
@@ -257,15 +349,10 @@ impl PerformanceBenchmark {
         })))
     }
 
-    async fn report(
-        &mut self,
-        context: &mut Context<'_>,
-        buffer: Duration,
-        window: Duration,
-        stats: TxStats,
-    ) -> Result<()> {
-        let end = unix_timestamp_now() - buffer;
-        let start = end - window + 2 * buffer;
+    /// `stats` and prometheus metrics only cover steady state between warmup and cooldown
+    async fn report(&mut self, context: &mut Context<'_>, stats: TxStats) -> Result<()> {
+        let end = unix_timestamp_now() - self.cooldown;
+        let start = end - self.duration;
         info!(
             "Link to dashboard : {}",
             context.prometheus.link_to_dashboard(start, end)
@@ -279,9 +366,12 @@ impl PerformanceBenchmark {
                 .report
                 .report_metric(&self, "avg_txns_per_block", avg_txns_per_block);
         }
+        let committed_tps = stats.committed as f64 / self.duration.as_secs_f64();
         context
             .report
-            .report_txn_stats(self.to_string(), stats, window);
+            .report_txn_stats(self.to_string(), stats, self.duration);
+
+        stats::report_range(&mut context.report, &self.to_string(), &pv, committed_tps);
 
         // Backup throughput
         if self.backup {
@@ -33,6 +33,10 @@ impl ExperimentParam for PerformanceBenchmarkThreeRegionSimulationParams {
 
 #[async_trait]
 impl Experiment for PerformanceBenchmarkThreeRegionSimulation {
+    fn tags(&self) -> &'static [&'static str] {
+        &["network", "performance"]
+    }
+
     async fn run(&mut self, context: &mut Context<'_>) -> anyhow::Result<()> {
         let num_nodes = self.cluster.validator_instances().len();
         let split_country_num = ((num_nodes as f64) * 0.8) as usize;
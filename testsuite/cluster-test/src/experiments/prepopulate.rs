@@ -0,0 +1,302 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which grows the ledger to a configurable number of
+/// accounts or state size before benchmarks run, since performance measured on an almost empty
+/// ledger is not representative of a network which has been live for a while. Creation can
+/// pause at ledger size tiers for a benchmark window, to report how throughput and storage
+/// latency degrade as the state tree grows
+use crate::{
+    cluster::Cluster,
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::Instance,
+    stats::PrometheusRangeView,
+    tx_emitter::EmitJobRequest,
+    util::unix_timestamp_now,
+};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use libra_logger::{info, warn};
+use std::{
+    cmp::min,
+    fmt,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+
+/// Slowest account creation rate the deadline accounts for
+const MIN_ACCOUNTS_PER_SEC: u64 = 200;
+/// Time for setting up and draining workload of a tier benchmark
+const TIER_OVERHEAD: Duration = Duration::from_secs(120);
+
+#[derive(StructOpt, Debug)]
+pub struct PrepopulateParams {
+    #[structopt(
+        long,
+        default_value = "1000000",
+        help = "Number of accounts to prepopulate, including ones created by earlier experiments of the suite"
+    )]
+    pub accounts: u64,
+    #[structopt(
+        long,
+        help = "Stop early once state of validators reaches this size in GB"
+    )]
+    pub state_gb: Option<f64>,
+    #[structopt(
+        long,
+        default_value = "100000",
+        help = "Accounts created per round, keys of a round are dropped once it completes"
+    )]
+    pub round_accounts: u64,
+    #[structopt(
+        long,
+        use_delimiter = true,
+        help = "Account counts at which creation pauses to benchmark, e.g. 0,1000000,10000000"
+    )]
+    pub tiers: Vec<u64>,
+    #[structopt(
+        long,
+        default_value = "120",
+        help = "Duration of benchmark at each tier in secs"
+    )]
+    pub tier_duration_secs: u64,
+}
+
+pub struct Prepopulate {
+    accounts: u64,
+    state_bytes: Option<f64>,
+    round_accounts: u64,
+    tiers: Vec<u64>,
+    tier_duration: Duration,
+    instances: Vec<Instance>,
+}
+
+/// Workload results at one ledger size
+struct Tier {
+    accounts: u64,
+    committed_tps: u64,
+    state_bytes: Option<f64>,
+    read_latency: Option<f64>,
+    write_latency: Option<f64>,
+}
+
+impl ExperimentParam for PrepopulateParams {
+    type E = Prepopulate;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        let mut tiers: Vec<_> = self
+            .tiers
+            .into_iter()
+            .filter(|tier| *tier <= self.accounts)
+            .collect();
+        tiers.sort_unstable();
+        tiers.dedup();
+        Self::E {
+            accounts: self.accounts,
+            state_bytes: self.state_gb.map(|gb| gb * 1e9),
+            round_accounts: self.round_accounts,
+            tiers,
+            tier_duration: Duration::from_secs(self.tier_duration_secs),
+            instances: cluster.validator_instances().to_vec(),
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for Prepopulate {
+    fn tags(&self) -> &'static [&'static str] {
+        &["storage", "long"]
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        if self.round_accounts == 0 {
+            bail!("Accounts per round can not be 0");
+        }
+        let request =
+            EmitJobRequest::for_instances(self.instances.clone(), context.global_emit_job_request);
+        let start_version = self.instances[0].latest_version().await?;
+        let start = unix_timestamp_now();
+        let started = Instant::now();
+        // Accounts of earlier experiments are dropped as well, later ones mint on demand
+        context.tx_emitter.clear();
+        let reused = context.warm_state.prepopulated_accounts();
+        if reused > 0 {
+            info!(
+                "Reusing {} accounts prepopulated earlier in the suite",
+                reused
+            );
+        }
+        let mut created = reused;
+        let mut state_bytes = None;
+        // Tiers below reused accounts can not be measured anymore
+        let mut tiers = self
+            .tiers
+            .iter()
+            .skip_while(|tier| **tier < reused)
+            .peekable();
+        let mut results = vec![];
+        loop {
+            while let Some(tier) = tiers.peek() {
+                if **tier > created {
+                    break;
+                }
+                tiers.next();
+                results.push(self.bench_tier(context, &request, created).await?);
+            }
+            if created >= self.accounts {
+                break;
+            }
+            let next_stop = tiers.peek().map_or(self.accounts, |tier| **tier);
+            let round = min(self.round_accounts, next_stop - created);
+            context
+                .tx_emitter
+                .mint_accounts(&request, round as usize)
+                .await?;
+            context.tx_emitter.clear();
+            created += round;
+            context.warm_state.record_prepopulated(created);
+            state_bytes = PrometheusRangeView::new(context.prometheus, start, unix_timestamp_now())
+                .max_state_bytes();
+            info!(
+                "Created {} of {} accounts in {:?}, state size {:?} bytes",
+                created,
+                self.accounts,
+                started.elapsed(),
+                state_bytes
+            );
+            if let (Some(target), Some(size)) = (self.state_bytes, state_bytes) {
+                if size >= target {
+                    info!("Reached target state size of {} bytes", target);
+                    break;
+                }
+            }
+        }
+        let elapsed = started.elapsed();
+        let end_version = self.instances[0].latest_version().await?;
+        context
+            .report
+            .report_metric(&self, "prepopulated_accounts", created as f64);
+        context
+            .report
+            .report_metric(&self, "prepopulate_secs", elapsed.as_secs_f64());
+        context.report.report_metric(
+            &self,
+            "prepopulate_accounts_per_sec",
+            (created - reused) as f64 / elapsed.as_secs_f64(),
+        );
+        context
+            .report
+            .report_metric(&self, "reused_prepopulated_accounts", reused as f64);
+        let mut text = format!(
+            "{}: created {} accounts in {} transactions in {:.0}s",
+            self,
+            created - reused,
+            end_version - start_version,
+            elapsed.as_secs_f64()
+        );
+        if reused > 0 {
+            text.push_str(&format!(", reused {} created earlier", reused));
+        }
+        match state_bytes {
+            Some(size) => {
+                context.report.report_metric(&self, "state_bytes", size);
+                text.push_str(&format!(", state size {:.2} GB", size / 1e9));
+            }
+            None => warn!("State size of validators is not available"),
+        }
+        if !results.is_empty() {
+            text.push_str(
+                "\n  accounts | state GB | committed tps | storage read ms | storage write ms",
+            );
+        }
+        for tier in &results {
+            let metrics = [
+                ("committed_tps", Some(tier.committed_tps as f64)),
+                ("state_bytes", tier.state_bytes),
+                (
+                    "storage_read_latency_ms",
+                    tier.read_latency.map(|l| l * 1000.0),
+                ),
+                (
+                    "storage_write_latency_ms",
+                    tier.write_latency.map(|l| l * 1000.0),
+                ),
+            ];
+            for (metric, value) in metrics.iter() {
+                if let Some(value) = value {
+                    context.report.report_metric(
+                        &self,
+                        format!("tier_{}_{}", tier.accounts, metric),
+                        *value,
+                    );
+                }
+            }
+            text.push_str(&format!(
+                "\n  {} | {} | {} | {} | {}",
+                tier.accounts,
+                format_optional(tier.state_bytes.map(|b| b / 1e9)),
+                tier.committed_tps,
+                format_optional(tier.read_latency.map(|l| l * 1000.0)),
+                format_optional(tier.write_latency.map(|l| l * 1000.0)),
+            ));
+        }
+        context.report.report_text(text);
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(10 * 60 + self.accounts / MIN_ACCOUNTS_PER_SEC)
+            + (self.tier_duration + TIER_OVERHEAD) * self.tiers.len() as u32
+    }
+}
+
+impl Prepopulate {
+    /// Runs workload of the suite at current ledger size, accounts of the workload are dropped
+    /// afterwards like the prepopulated ones
+    async fn bench_tier(
+        &self,
+        context: &mut Context<'_>,
+        request: &EmitJobRequest,
+        accounts: u64,
+    ) -> Result<Tier> {
+        info!(
+            "Benchmarking ledger with {} prepopulated accounts",
+            accounts
+        );
+        let start = unix_timestamp_now();
+        let stats = context
+            .tx_emitter
+            .emit_txn_for(self.tier_duration, request.clone())
+            .await?;
+        let end = unix_timestamp_now();
+        context.tx_emitter.clear();
+        let pv = PrometheusRangeView::new(context.prometheus, start, end);
+        let tier = Tier {
+            accounts,
+            committed_tps: stats.rate(self.tier_duration).committed,
+            state_bytes: pv.max_state_bytes(),
+            read_latency: pv.avg_storage_read_latency(),
+            write_latency: pv.avg_storage_write_latency(),
+        };
+        info!(
+            "Tier of {} accounts: {} committed tps, storage read {:?}s, write {:?}s",
+            accounts, tier.committed_tps, tier.read_latency, tier.write_latency
+        );
+        Ok(tier)
+    }
+}
+
+fn format_optional(value: Option<f64>) -> String {
+    value.map_or_else(|| "-".to_string(), |v| format!("{:.2}", v))
+}
+
+impl fmt::Display for Prepopulate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Prepopulate ledger with {} accounts", self.accounts)?;
+        if let Some(bytes) = self.state_bytes {
+            write!(f, " or {:.2} GB of state", bytes / 1e9)?;
+        }
+        Ok(())
+    }
+}
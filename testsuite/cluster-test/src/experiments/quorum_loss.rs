@@ -0,0 +1,189 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which stops f + 1 validators, so that the cluster loses
+/// liveness, holds the outage while transactions keep being submitted, then restores stopped
+/// validators and measures time to the first commit. Transactions submitted during the outage
+/// are expected to either commit or expire after recovery
+use crate::{
+    cluster::Cluster,
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::{self, Instance},
+    report::ReportSection,
+    tx_emitter::EmitJobRequest,
+};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use futures::future::try_join_all;
+use libra_logger::info;
+use rand::seq::SliceRandom;
+use std::{
+    collections::HashSet,
+    fmt,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use tokio::time;
+
+#[derive(StructOpt, Debug)]
+pub struct QuorumLossParams {
+    #[structopt(
+        long,
+        default_value = "120",
+        help = "Time in secs to keep validators down"
+    )]
+    pub hold_secs: u64,
+    #[structopt(
+        long,
+        default_value = "300",
+        help = "Time in secs for cluster to commit again after validators are restored"
+    )]
+    pub recovery_timeout_secs: u64,
+}
+
+pub struct QuorumLoss {
+    down_validators: Vec<Instance>,
+    up_validators: Vec<Instance>,
+    up_fullnodes: Vec<Instance>,
+    all_validators: Vec<Instance>,
+    hold: Duration,
+    recovery_timeout: Duration,
+}
+
+impl ExperimentParam for QuorumLossParams {
+    type E = QuorumLoss;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        let num_validators = cluster.validator_instances().len();
+        let count = num_validators / 3 + 1;
+        if count >= num_validators {
+            panic!(
+                "Can not stop {} validators and keep any running in cluster with {} validators",
+                count, num_validators
+            );
+        }
+        let mut validators = cluster.validator_instances().to_vec();
+        validators.shuffle(&mut rand::thread_rng());
+        let up_validators = validators.split_off(count);
+        let up_fullnodes = cluster
+            .fullnode_instances()
+            .iter()
+            .filter(|f| {
+                up_validators
+                    .iter()
+                    .any(|v| v.validator_group() == f.validator_group())
+            })
+            .cloned()
+            .collect();
+        Self::E {
+            down_validators: validators,
+            up_validators,
+            up_fullnodes,
+            all_validators: cluster.validator_instances().to_vec(),
+            hold: Duration::from_secs(self.hold_secs),
+            recovery_timeout: Duration::from_secs(self.recovery_timeout_secs),
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for QuorumLoss {
+    fn tags(&self) -> &'static [&'static str] {
+        &["consensus"]
+    }
+
+    fn report_section(&self) -> ReportSection {
+        ReportSection::Critical
+    }
+
+    /// Whole cluster stops committing, so validators which keep running are affected as well
+    fn affected_validators(&self) -> HashSet<String> {
+        instance::instancelist_to_set(&self.all_validators)
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        let instances = if context.emit_to_validator || self.up_fullnodes.is_empty() {
+            self.up_validators.clone()
+        } else {
+            self.up_fullnodes.clone()
+        };
+        let job = context
+            .tx_emitter
+            .start_job(EmitJobRequest::for_instances(
+                instances,
+                context.global_emit_job_request,
+            ))
+            .await?;
+
+        info!("Stopping {} validators", self.down_validators.len());
+        try_join_all(self.down_validators.iter().map(Instance::stop)).await?;
+        time::delay_for(self.hold).await;
+        let version_before_recovery = self.up_validators[0].latest_version().await?;
+
+        info!("Restoring {} validators", self.down_validators.len());
+        let futures = self.down_validators.iter().map(|i| i.start(false));
+        try_join_all(futures).await?;
+        let restored = Instant::now();
+        let deadline = restored + self.recovery_timeout;
+        while self.up_validators[0].latest_version().await.unwrap_or(0) <= version_before_recovery {
+            if Instant::now() > deadline {
+                context.tx_emitter.stop_job(job).await;
+                bail!(
+                    "Cluster did not commit within {} secs after quorum was restored",
+                    self.recovery_timeout.as_secs()
+                );
+            }
+            time::delay_for(Duration::from_secs(1)).await;
+        }
+        let time_to_first_commit = restored.elapsed();
+
+        // Give transactions buffered during the outage time to either commit or expire
+        time::delay_for(Duration::from_secs(30)).await;
+        let stats = context.tx_emitter.stop_job(job).await;
+        let unaccounted = stats
+            .submitted
+            .saturating_sub(stats.committed + stats.expired);
+
+        let msg = format!(
+            "{}: first commit {} secs after restore, {} submitted, {} committed, {} expired",
+            self,
+            time_to_first_commit.as_secs(),
+            stats.submitted,
+            stats.committed,
+            stats.expired
+        );
+        info!("{}", msg);
+        context.report.report_text(msg);
+        context.report.report_metric(
+            &self,
+            "time_to_first_commit",
+            time_to_first_commit.as_secs_f64(),
+        );
+        context
+            .report
+            .report_metric(&self, "unaccounted_txn", unaccounted as f64);
+        if unaccounted > 0 {
+            bail!(
+                "{} transactions submitted during outage neither committed nor expired",
+                unaccounted
+            );
+        }
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(10 * 60) + self.hold + self.recovery_timeout
+    }
+}
+
+impl fmt::Display for QuorumLoss {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Quorum loss of {} validators for {} secs",
+            self.down_validators.len(),
+            self.hold.as_secs()
+        )
+    }
+}
@@ -0,0 +1,250 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which submits transactions to fullnodes while they serve
+/// read queries at increasing multiples of the write rate, as production fullnodes do, and
+/// reports how commit latency changes with the read:write ratio
+use crate::{
+    cluster::Cluster,
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::Instance,
+    query_emitter::{query_sent_events_key, QueryGenerator, QueryJob},
+    tx_emitter::{EmitJobRequest, TxStatsRate},
+};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use libra_json_rpc_client::{JsonRpcAsyncClient, JsonRpcBatch};
+use libra_logger::{info, warn};
+use libra_types::account_config::testnet_dd_account_address;
+use rand::Rng;
+use std::{fmt, sync::Arc, time::Duration};
+use structopt::StructOpt;
+use tokio::time;
+
+/// Page size of get_events queries, wallets usually show a few recent payments
+const EVENTS_LIMIT: u64 = 10;
+
+#[derive(StructOpt, Debug)]
+pub struct ReadWriteRatioParams {
+    #[structopt(
+        long,
+        use_delimiter = true,
+        default_value = "1,10,100",
+        help = "Read queries per committed transaction to try, in order"
+    )]
+    pub ratios: Vec<u64>,
+    #[structopt(
+        long,
+        default_value = "16",
+        help = "Number of concurrent readers per endpoint"
+    )]
+    pub readers: usize,
+    #[structopt(
+        long,
+        default_value = "120",
+        help = "Duration in secs of the baseline and of each step of the sweep"
+    )]
+    pub step_secs: u64,
+}
+
+pub struct ReadWriteRatio {
+    ratios: Vec<u64>,
+    readers: usize,
+    /// Both read queries and transactions are sent to these
+    instances: Vec<Instance>,
+    step: Duration,
+}
+
+/// Read load and transactions of one step of the sweep
+struct Step {
+    ratio: u64,
+    target_qps: u64,
+    queries: u64,
+    errors: u64,
+    read_p99_latency: u64,
+    txn_stats: TxStatsRate,
+}
+
+impl ExperimentParam for ReadWriteRatioParams {
+    type E = ReadWriteRatio;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        let instances = if cluster.fullnode_instances().is_empty() {
+            cluster.validator_instances().to_vec()
+        } else {
+            cluster.fullnode_instances().to_vec()
+        };
+        Self::E {
+            ratios: self.ratios,
+            readers: self.readers.max(1),
+            instances,
+            step: Duration::from_secs(self.step_secs),
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for ReadWriteRatio {
+    fn tags(&self) -> &'static [&'static str] {
+        &["performance", "long"]
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        let request =
+            EmitJobRequest::for_instances(self.instances.clone(), context.global_emit_job_request);
+        info!("Measuring write only baseline");
+        let baseline = context
+            .tx_emitter
+            .emit_txn_for(self.step, request.clone())
+            .await?
+            .rate(self.step);
+        if baseline.committed == 0 {
+            bail!("No transactions were committed without read load");
+        }
+
+        let clients: Vec<_> = self
+            .instances
+            .iter()
+            .map(Instance::json_rpc_client)
+            .collect();
+        // Faucet account sends a payment for every mint, so its event stream is never empty
+        let events_key = query_sent_events_key(&clients[0], testnet_dd_account_address()).await?;
+        let generator = Arc::new(WalletQueries { events_key });
+        let mut steps = vec![];
+        for ratio in self.ratios.clone() {
+            let target_qps = ratio * baseline.committed;
+            info!(
+                "Reading at {} queries/s ({}:1) while writing",
+                target_qps, ratio
+            );
+            let job = context.tx_emitter.start_job(request.clone()).await?;
+            let tasks = self.instances.len() * self.readers;
+            let period = Duration::from_secs_f64(tasks as f64 / target_qps.max(1) as f64);
+            let queries = QueryJob::start(&clients, self.readers, generator.clone(), Some(period));
+            time::delay_for(self.step).await;
+            let query_stats = queries.stop().await;
+            let txn_stats = context.tx_emitter.stop_job(job).await.rate(self.step);
+            let latencies = query_stats.sorted_latencies();
+            steps.push(Step {
+                ratio,
+                target_qps,
+                queries: latencies.len() as u64,
+                errors: query_stats.total_errors(),
+                read_p99_latency: latencies
+                    .get(latencies.len().saturating_sub(1) * 99 / 100)
+                    .copied()
+                    .unwrap_or(0),
+                txn_stats,
+            });
+        }
+
+        self.report(context, &baseline, &steps);
+        if steps.iter().any(|step| step.txn_stats.committed == 0) {
+            bail!("Cluster stopped committing under read load");
+        }
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(5 * 60) + self.step * (self.ratios.len() as u32 + 1)
+    }
+}
+
+impl ReadWriteRatio {
+    fn report(&self, context: &mut Context<'_>, baseline: &TxStatsRate, steps: &[Step]) {
+        let mut text = format!(
+            "{}: write only {} TPS, avg latency {} ms, p99 latency {} ms",
+            self, baseline.committed, baseline.latency, baseline.p99_latency
+        );
+        for step in steps {
+            let read_qps = step.queries / self.step.as_secs().max(1);
+            let reads = [
+                ("read_qps", read_qps as f64),
+                ("read_p99_latency_ms", step.read_p99_latency as f64),
+                ("read_errors", step.errors as f64),
+            ];
+            for (metric, value) in reads.iter() {
+                context.report.report_metric(
+                    &self,
+                    format!("ratio_{}_{}", step.ratio, metric),
+                    *value,
+                );
+            }
+            text.push_str(&format!(
+                "\n  {}:1, {} read queries/s of {} targeted, read p99 latency {} ms, {} read errors:",
+                step.ratio, read_qps, step.target_qps, step.read_p99_latency, step.errors
+            ));
+            let writes = [
+                (
+                    "tps",
+                    baseline.committed as f64,
+                    step.txn_stats.committed as f64,
+                ),
+                (
+                    "avg_latency_ms",
+                    baseline.latency as f64,
+                    step.txn_stats.latency as f64,
+                ),
+                (
+                    "p99_latency_ms",
+                    baseline.p99_latency as f64,
+                    step.txn_stats.p99_latency as f64,
+                ),
+            ];
+            let comparison =
+                context
+                    .report
+                    .report_comparison(&self, &format!("ratio_{}", step.ratio), &writes);
+            text.push_str(&comparison.replace("\n", "\n  "));
+            if read_qps * 10 < step.target_qps * 9 {
+                warn!(
+                    "Readers reached {} of {} queries/s at {}:1, add readers to reach the ratio",
+                    read_qps, step.target_qps, step.ratio
+                );
+            }
+        }
+        info!("{}", text);
+        context.report.report_text(text);
+    }
+}
+
+/// One of the queries wallets issue most
+struct WalletQueries {
+    events_key: String,
+}
+
+#[async_trait]
+impl QueryGenerator for WalletQueries {
+    async fn add_query(
+        &self,
+        _client: &JsonRpcAsyncClient,
+        batch: &mut JsonRpcBatch,
+    ) -> Result<&'static str> {
+        Ok(match rand::thread_rng().gen_range(0, 3) {
+            0 => {
+                batch.add_get_account_request(testnet_dd_account_address());
+                "get_account"
+            }
+            1 => {
+                batch.add_get_metadata_request(None);
+                "get_metadata"
+            }
+            _ => {
+                batch.add_get_events_request(self.events_key.clone(), 0, EVENTS_LIMIT);
+                "get_events"
+            }
+        })
+    }
+}
+
+impl fmt::Display for ReadWriteRatio {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Read:write ratio sweep {:?} on {} endpoints",
+            self.ratios,
+            self.instances.len()
+        )
+    }
+}
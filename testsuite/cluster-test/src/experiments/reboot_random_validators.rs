@@ -12,6 +12,7 @@ use crate::{
     experiments::{Context, Experiment, ExperimentParam},
     instance,
     instance::Instance,
+    report::ReportSection,
 };
 use async_trait::async_trait;
 use futures::future::try_join_all;
@@ -84,6 +85,14 @@ impl ExperimentParam for RebootRandomValidatorsParams {
 
 #[async_trait]
 impl Experiment for RebootRandomValidators {
+    fn tags(&self) -> &'static [&'static str] {
+        &["consensus"]
+    }
+
+    fn report_section(&self) -> ReportSection {
+        ReportSection::Critical
+    }
+
     fn affected_validators(&self) -> HashSet<String> {
         instance::instancelist_to_set(&self.instances)
     }
@@ -99,6 +108,10 @@ impl Experiment for RebootRandomValidators {
     fn deadline(&self) -> Duration {
         Duration::from_secs(20 * 60)
     }
+
+    fn expected_duration(&self) -> Duration {
+        Duration::from_secs(2 * 60)
+    }
 }
 
 impl fmt::Display for RebootRandomValidators {
@@ -46,6 +46,10 @@ impl ExperimentParam for RecoveryTimeParams {
 
 #[async_trait]
 impl Experiment for RecoveryTime {
+    fn tags(&self) -> &'static [&'static str] {
+        &["storage", "state_sync", "long"]
+    }
+
     fn affected_validators(&self) -> HashSet<String> {
         let mut result = HashSet::new();
         result.insert(self.instance.peer_name().clone());
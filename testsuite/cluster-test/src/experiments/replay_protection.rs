@@ -0,0 +1,347 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which submits every signed transaction several times to
+/// different endpoints while the cluster is under load, replays it once more after it was
+/// committed, and verifies through sent payment events of the senders that each transaction
+/// was executed exactly once
+use crate::{
+    cluster::Cluster,
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::Instance,
+    query_emitter::query_sent_events_key,
+    tx_emitter::{AccountData, EmitJobRequest},
+};
+use anyhow::{bail, format_err, Result};
+use async_trait::async_trait;
+use futures::future::join_all;
+use libra_json_rpc_client::{views::EventView, JsonRpcAsyncClient, JsonRpcBatch, JsonRpcResponse};
+use libra_logger::{info, warn};
+use libra_types::{
+    account_config::{coin1_tag, COIN1_NAME},
+    chain_id::ChainId,
+    transaction::{helpers::create_user_txn, SignedTransaction, TransactionPayload},
+};
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use tokio::time;
+use transaction_builder::encode_peer_to_peer_with_metadata_script;
+
+const MAX_GAS_AMOUNT: u64 = 1_000_000;
+const TXN_EXPIRATION_SECONDS: u64 = 60;
+/// Time given to replays after commit before events are checked, so that a replay which was
+/// wrongly accepted would be executed by then
+const SETTLE_TIME: Duration = Duration::from_secs(10);
+const EVENTS_PAGE: u64 = 1000;
+
+#[derive(StructOpt, Debug)]
+pub struct ReplayProtectionParams {
+    #[structopt(
+        long,
+        default_value = "50",
+        help = "Number of accounts, each submits one transaction per round"
+    )]
+    pub accounts: usize,
+    #[structopt(
+        long,
+        default_value = "3",
+        help = "Number of endpoints each signed transaction is submitted to"
+    )]
+    pub copies: usize,
+    #[structopt(long, default_value = "5", help = "Number of rounds")]
+    pub rounds: usize,
+}
+
+pub struct ReplayProtection {
+    accounts: usize,
+    copies: usize,
+    rounds: usize,
+    /// Copies of a transaction go to consecutive instances, validators and fullnodes alike
+    instances: Vec<Instance>,
+    /// Background load is sent to these
+    load_instances: Vec<Instance>,
+}
+
+#[derive(Default)]
+struct ReplayStats {
+    transactions: u64,
+    copies_accepted: u64,
+    copies_rejected: u64,
+    committed: u64,
+    replays_accepted: u64,
+    duplicates: u64,
+}
+
+/// Account of the workload with number of sent payment events seen so far
+struct Sender {
+    account: AccountData,
+    events_key: String,
+    events: u64,
+}
+
+impl ExperimentParam for ReplayProtectionParams {
+    type E = ReplayProtection;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        Self::E {
+            accounts: self.accounts.max(2),
+            copies: self.copies.max(1),
+            rounds: self.rounds,
+            instances: cluster
+                .validator_and_fullnode_instances()
+                .cloned()
+                .collect(),
+            load_instances: cluster.validator_instances().to_vec(),
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for ReplayProtection {
+    fn tags(&self) -> &'static [&'static str] {
+        &["mempool", "correctness"]
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        let request = EmitJobRequest::for_instances(
+            self.load_instances.clone(),
+            context.global_emit_job_request,
+        );
+        let job = context.tx_emitter.start_job(request.clone()).await?;
+        let result = self.replay(context, &request).await;
+        context.tx_emitter.stop_job(job).await;
+        let stats = result?;
+
+        let metrics = [
+            ("transactions", stats.transactions),
+            ("copies_accepted", stats.copies_accepted),
+            ("copies_rejected", stats.copies_rejected),
+            ("committed", stats.committed),
+            ("replays_accepted", stats.replays_accepted),
+            ("duplicates", stats.duplicates),
+        ];
+        for (metric, value) in metrics.iter() {
+            context.report.report_metric(&self, *metric, *value as f64);
+        }
+        let text = format!(
+            "{}: {} of {} transactions committed, {} copies accepted and {} rejected, {} replays after commit accepted, {} duplicate executions",
+            self,
+            stats.committed,
+            stats.transactions,
+            stats.copies_accepted,
+            stats.copies_rejected,
+            stats.replays_accepted,
+            stats.duplicates
+        );
+        info!("{}", text);
+        context.report.report_text(text);
+        if stats.duplicates > 0 {
+            bail!(
+                "{} transactions were executed more than once",
+                stats.duplicates
+            );
+        }
+        if stats.replays_accepted > 0 {
+            bail!(
+                "{} committed transactions were accepted again",
+                stats.replays_accepted
+            );
+        }
+        if stats.committed == 0 {
+            bail!("No transaction was committed");
+        }
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(5 * 60)
+            + (Duration::from_secs(TXN_EXPIRATION_SECONDS) + SETTLE_TIME) * self.rounds as u32
+    }
+}
+
+impl ReplayProtection {
+    async fn replay(
+        &self,
+        context: &mut Context<'_>,
+        request: &EmitJobRequest,
+    ) -> Result<ReplayStats> {
+        context
+            .tx_emitter
+            .mint_accounts(request, self.accounts)
+            .await?;
+        let clients: Vec<_> = self
+            .instances
+            .iter()
+            .map(Instance::json_rpc_client)
+            .collect();
+        let mut senders = vec![];
+        for _ in 0..self.accounts {
+            let account = context.tx_emitter.take_account();
+            let events_key = query_sent_events_key(&clients[0], account.address).await?;
+            // Accounts of the emitter may have sent payments in earlier experiments
+            let events = query_events(&clients[0], &events_key, 0).await?.len() as u64;
+            senders.push(Sender {
+                account,
+                events_key,
+                events,
+            });
+        }
+        let mut stats = ReplayStats::default();
+        for round in 0..self.rounds {
+            let txns = senders
+                .iter()
+                .enumerate()
+                .map(|(i, sender)| {
+                    let receiver = &senders[(i + 1) % senders.len()].account;
+                    sign_payment(&sender.account, receiver)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let submissions = txns.iter().enumerate().flat_map(|(i, txn)| {
+                let clients = &clients;
+                (0..self.copies).map(move |copy| submit(&clients[(i + copy) % clients.len()], txn))
+            });
+            for result in join_all(submissions).await {
+                match result {
+                    Ok(()) => stats.copies_accepted += 1,
+                    Err(e) => {
+                        info!("Copy was rejected: {}", e);
+                        stats.copies_rejected += 1;
+                    }
+                }
+            }
+            stats.transactions += txns.len() as u64;
+
+            let committed = wait_committed(&clients[0], &senders).await?;
+            let replays = txns
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| committed[*i])
+                .map(|(i, txn)| submit(&clients[(i + self.copies) % clients.len()], txn));
+            for result in join_all(replays).await {
+                if result.is_ok() {
+                    stats.replays_accepted += 1;
+                }
+            }
+            time::delay_for(SETTLE_TIME).await;
+
+            for (sender, committed) in senders.iter_mut().zip(committed) {
+                let events = query_events(&clients[0], &sender.events_key, sender.events).await?;
+                let expected = if committed { 1 } else { 0 };
+                if events.len() > expected {
+                    warn!(
+                        "Transaction {} of {} was executed {} times, at versions {:?}",
+                        sender.account.sequence_number,
+                        sender.account.address,
+                        events.len(),
+                        events
+                            .iter()
+                            .map(|e| e.transaction_version)
+                            .collect::<Vec<_>>()
+                    );
+                    stats.duplicates += (events.len() - expected) as u64;
+                }
+                if committed {
+                    stats.committed += 1;
+                    sender.account.sequence_number += 1;
+                }
+                sender.events += events.len() as u64;
+            }
+            info!("Replay round {} of {} done", round + 1, self.rounds);
+        }
+        Ok(stats)
+    }
+}
+
+fn sign_payment(sender: &AccountData, receiver: &AccountData) -> Result<SignedTransaction> {
+    create_user_txn(
+        &sender.key_pair,
+        TransactionPayload::Script(encode_peer_to_peer_with_metadata_script(
+            coin1_tag(),
+            receiver.address,
+            1,
+            vec![],
+            vec![],
+        )),
+        sender.address,
+        sender.sequence_number,
+        MAX_GAS_AMOUNT,
+        0,
+        COIN1_NAME.to_owned(),
+        TXN_EXPIRATION_SECONDS as i64,
+        ChainId::test(),
+    )
+}
+
+async fn submit(client: &JsonRpcAsyncClient, txn: &SignedTransaction) -> Result<()> {
+    client
+        .submit_transaction(txn.clone())
+        .await
+        .map_err(|e| format_err!("[{:?}] {:?}", client, e))
+}
+
+/// Whether transaction of each sender was committed, waits until all of them are or until
+/// they expire
+async fn wait_committed(client: &JsonRpcAsyncClient, senders: &[Sender]) -> Result<Vec<bool>> {
+    let deadline = Instant::now() + Duration::from_secs(TXN_EXPIRATION_SECONDS) + SETTLE_TIME;
+    loop {
+        let mut committed = vec![];
+        for chunk in senders.chunks(100) {
+            let addresses: Vec<_> = chunk.iter().map(|s| s.account.address).collect();
+            let views = client
+                .get_accounts(&addresses)
+                .await
+                .map_err(|e| format_err!("[{:?}] get_accounts failed: {:?}", client, e))?;
+            for (sender, view) in chunk.iter().zip(views) {
+                let view = view.ok_or_else(|| {
+                    format_err!("Account {} does not exist", sender.account.address)
+                })?;
+                committed.push(view.sequence_number > sender.account.sequence_number);
+            }
+        }
+        if committed.iter().all(|c| *c) || Instant::now() > deadline {
+            return Ok(committed);
+        }
+        time::delay_for(Duration::from_secs(1)).await;
+    }
+}
+
+/// All events of `key` from `start`
+async fn query_events(
+    client: &JsonRpcAsyncClient,
+    key: &str,
+    start: u64,
+) -> Result<Vec<EventView>> {
+    let mut events = vec![];
+    loop {
+        let mut batch = JsonRpcBatch::new();
+        batch.add_get_events_request(key.to_string(), start + events.len() as u64, EVENTS_PAGE);
+        let response = client
+            .execute(batch)
+            .await
+            .map_err(|e| format_err!("[{:?}] get_events failed: {:?}", client, e))?
+            .remove(0)?;
+        let mut page = match response {
+            JsonRpcResponse::EventsResponse(page) => page,
+            response => bail!("Unexpected response for get_events: {:?}", response),
+        };
+        let last_page = (page.len() as u64) < EVENTS_PAGE;
+        events.append(&mut page);
+        if last_page {
+            return Ok(events);
+        }
+    }
+}
+
+impl fmt::Display for ReplayProtection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Replay protection with {} accounts, {} copies per transaction",
+            self.accounts, self.copies
+        )
+    }
+}
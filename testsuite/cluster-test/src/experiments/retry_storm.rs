@@ -0,0 +1,188 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment where clients turn impatient: the same load is emitted
+/// first by well behaved clients and then by clients which give up on submissions not answered
+/// within a low threshold and submit them again. Duplicates have to be rejected by mempool and
+/// the amplified load absorbed by back-pressure, so that the cluster keeps its throughput
+use crate::{
+    cluster::Cluster,
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::Instance,
+    tx_emitter::{self, EmitJobRequest, TxStats},
+    util::unix_timestamp_now,
+};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use futures::future::try_join_all;
+use libra_logger::info;
+use std::{fmt, time::Duration};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct RetryStormParams {
+    #[structopt(
+        long,
+        default_value = "120",
+        help = "Duration in secs of load with and without retry storm"
+    )]
+    pub duration_secs: u64,
+    #[structopt(
+        long,
+        default_value = "200",
+        help = "Clients submit again if they get no response within given ms"
+    )]
+    pub threshold_ms: u64,
+    #[structopt(
+        long,
+        default_value = "3",
+        help = "Max number of times clients submit the same transactions again"
+    )]
+    pub max_retries: usize,
+    #[structopt(
+        long,
+        default_value = "50",
+        help = "Fail if committed TPS during the storm drops below given percent of baseline"
+    )]
+    pub min_tps_percent: u64,
+}
+
+pub struct RetryStorm {
+    instances: Vec<Instance>,
+    duration: Duration,
+    storm: tx_emitter::RetryStorm,
+    min_tps_percent: u64,
+}
+
+impl ExperimentParam for RetryStormParams {
+    type E = RetryStorm;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        Self::E {
+            instances: cluster.validator_instances().to_vec(),
+            duration: Duration::from_secs(self.duration_secs),
+            storm: tx_emitter::RetryStorm {
+                threshold: Duration::from_millis(self.threshold_ms),
+                max_retries: self.max_retries,
+            },
+            min_tps_percent: self.min_tps_percent,
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for RetryStorm {
+    fn tags(&self) -> &'static [&'static str] {
+        &["mempool", "performance"]
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        // Mempool of any validator may not grow over the largest capacity deployed
+        let capacity = try_join_all(self.instances.iter().map(Instance::mempool_capacity))
+            .await?
+            .into_iter()
+            .max()
+            .unwrap_or(0) as f64;
+        let request =
+            EmitJobRequest::for_instances(self.instances.clone(), context.global_emit_job_request);
+        let baseline = context
+            .tx_emitter
+            .emit_txn_for(self.duration, request.clone())
+            .await?;
+        let mut storm_request = request;
+        storm_request.thread_params.retry_storm = Some(self.storm);
+        let storm_start = unix_timestamp_now();
+        let storm = context
+            .tx_emitter
+            .emit_txn_for(self.duration, storm_request)
+            .await?;
+        let storm_end = unix_timestamp_now();
+        let max_mempool_size = context
+            .prometheus
+            .query_range_max(
+                format!(
+                    "libra_core_mempool_index_size{{index=\"system_ttl\",{}}}",
+                    context.prometheus.validator_selector()
+                ),
+                &storm_start,
+                &storm_end,
+                10,
+            )
+            .ok();
+
+        let baseline_rate = baseline.rate(self.duration);
+        let storm_rate = storm.rate(self.duration);
+        let amplification = amplification(&storm);
+        let mut metrics = vec![
+            ("baseline_committed_tps", baseline_rate.committed as f64),
+            ("storm_committed_tps", storm_rate.committed as f64),
+            ("baseline_p99_latency", baseline_rate.p99_latency as f64),
+            ("storm_p99_latency", storm_rate.p99_latency as f64),
+            ("amplification", amplification),
+            ("resubmitted_txn", storm.storm_resubmitted as f64),
+            (
+                "duplicates_rejected",
+                storm.storm_duplicates_rejected as f64,
+            ),
+            ("storm_expired_txn", storm.expired as f64),
+        ];
+        if let Some(size) = max_mempool_size {
+            metrics.push(("max_mempool_size", size));
+        }
+        for (metric, value) in metrics {
+            context.report.report_metric(&self, metric, value);
+        }
+        let text = format!(
+            "{}: committed {} -> {} txn/s, p99 latency {} -> {} ms, load amplified {:.2}x by {} resubmitted transactions, {} duplicates rejected, max mempool size {:?}",
+            self,
+            baseline_rate.committed,
+            storm_rate.committed,
+            baseline_rate.p99_latency,
+            storm_rate.p99_latency,
+            amplification,
+            storm.storm_resubmitted,
+            storm.storm_duplicates_rejected,
+            max_mempool_size
+        );
+        info!("{}", text);
+        context.report.report_text(text);
+
+        if storm_rate.committed * 100 < baseline_rate.committed * self.min_tps_percent {
+            bail!(
+                "Committed TPS dropped from {} to {} under retry storm",
+                baseline_rate.committed,
+                storm_rate.committed
+            );
+        }
+        if let Some(size) = max_mempool_size {
+            if size > capacity {
+                bail!("Mempool grew to {} over its capacity of {}", size, capacity);
+            }
+        }
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        self.duration * 2 + Duration::from_secs(5 * 60)
+    }
+}
+
+/// Transactions endpoints got per unique transaction
+fn amplification(stats: &TxStats) -> f64 {
+    if stats.submitted == 0 {
+        return 1.0;
+    }
+    (stats.submitted + stats.storm_resubmitted) as f64 / stats.submitted as f64
+}
+
+impl fmt::Display for RetryStorm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Retry storm with {} ms threshold, up to {} retries",
+            self.storm.threshold.as_millis(),
+            self.storm.max_retries
+        )
+    }
+}
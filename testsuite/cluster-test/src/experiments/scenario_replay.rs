@@ -0,0 +1,244 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which replays a scenario of the incident catalog: faults
+/// of the scenario are applied at their offsets while load is emitted to validators which are
+/// not faulted, and the cluster has to meet expectations of the scenario and faulted
+/// validators have to catch up with the rest afterwards
+use crate::{
+    audit,
+    cluster::Cluster,
+    effects::Effect,
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::{self, Instance},
+    scenarios::{Catalog, Scenario},
+    topology,
+    tx_emitter::EmitJobRequest,
+};
+use anyhow::{bail, format_err, Result};
+use async_trait::async_trait;
+use futures::future::join_all;
+use libra_logger::{info, warn};
+use std::{
+    collections::HashSet,
+    fmt,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use tokio::time::{self, delay_until, Instant as TokioInstant};
+
+#[derive(StructOpt, Debug)]
+pub struct ScenarioReplayParams {
+    #[structopt(long, help = "Name of the scenario to replay")]
+    pub scenario: String,
+    #[structopt(
+        long,
+        help = "Path to yaml scenario catalog, catalog built into cluster test if not set"
+    )]
+    pub catalog: Option<String>,
+}
+
+pub struct ScenarioReplay {
+    scenario: Scenario,
+    validators: Vec<Instance>,
+    faulty: Vec<Instance>,
+    healthy: Vec<Instance>,
+}
+
+impl ExperimentParam for ScenarioReplayParams {
+    type E = ScenarioReplay;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        let catalog = match &self.catalog {
+            Some(path) => Catalog::from_file(path),
+            None => Catalog::builtin(),
+        };
+        let scenario = catalog
+            .and_then(|catalog| catalog.get(&self.scenario).map(Scenario::clone))
+            .unwrap_or_else(|e| panic!("{}", e));
+        let validators = cluster.validator_instances().to_vec();
+        if scenario.validators_needed() > validators.len() {
+            panic!(
+                "Scenario {} needs {} validators, cluster has {}",
+                scenario.name,
+                scenario.validators_needed(),
+                validators.len()
+            );
+        }
+        let faulted = scenario.faulted_validators();
+        let (faulty, healthy): (Vec<_>, Vec<_>) = validators
+            .iter()
+            .cloned()
+            .enumerate()
+            .partition(|(index, _)| faulted.contains(index));
+        Self::E {
+            scenario,
+            faulty: faulty.into_iter().map(|(_, v)| v).collect(),
+            healthy: healthy.into_iter().map(|(_, v)| v).collect(),
+            validators,
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for ScenarioReplay {
+    fn tags(&self) -> &'static [&'static str] {
+        &["scenario"]
+    }
+
+    fn affected_validators(&self) -> HashSet<String> {
+        instance::instancelist_to_set(&self.faulty)
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        let mut text = format!("{}: {}", self, self.scenario.description.trim());
+        if let Some(postmortem) = &self.scenario.postmortem {
+            text.push_str(&format!("\n  postmortem: {}", postmortem));
+        }
+        for fault in &self.scenario.faults {
+            text.push_str(&format!("\n  {}", fault));
+        }
+        info!("{}", text);
+        context.report.report_text(text);
+
+        // Killed validators would fail submissions, so load goes to the others if any
+        let instances = if self.healthy.is_empty() {
+            self.validators.clone()
+        } else {
+            self.healthy.clone()
+        };
+        let job = context
+            .tx_emitter
+            .start_job(EmitJobRequest::for_instances(
+                instances,
+                context.global_emit_job_request,
+            ))
+            .await?;
+
+        let mut effects: Vec<Box<dyn Effect + Send>> = vec![];
+        let mut events = vec![];
+        for fault in &self.scenario.faults {
+            let start = Duration::from_secs(fault.at_secs);
+            let end = start + Duration::from_secs(fault.for_secs);
+            for index in &fault.validators {
+                let i = effects.len();
+                effects.push(
+                    fault
+                        .fault
+                        .effect(self.validators[*index].clone(), &self.validators),
+                );
+                events.push((start, true, i));
+                events.push((end, false, i));
+            }
+        }
+        events.sort_by_key(|(at, activate, _)| (*at, *activate));
+
+        let started = Instant::now();
+        let mut active = HashSet::new();
+        let mut result = Ok(());
+        for (at, activate, i) in events {
+            delay_until(TokioInstant::from_std(started + at)).await;
+            let effect_result = if activate {
+                active.insert(i);
+                audit::record("activate", "effect", &effects[i]);
+                topology::fault_applied(&effects[i]);
+                match effects[i].activate().await {
+                    Ok(()) => effects[i].verify().await,
+                    Err(e) => Err(e),
+                }
+            } else {
+                active.remove(&i);
+                audit::record("deactivate", "effect", &effects[i]);
+                topology::fault_removed(&effects[i]);
+                effects[i].deactivate().await
+            };
+            if let Err(e) = effect_result {
+                result = Err(format_err!("Fault {} failed: {}", effects[i], e));
+                break;
+            }
+        }
+        for i in active {
+            audit::record("deactivate", "effect", &effects[i]);
+            topology::fault_removed(&effects[i]);
+            if let Err(e) = effects[i].deactivate().await {
+                warn!("Failed to deactivate {}: {}", effects[i], e);
+            }
+        }
+        if result.is_ok() {
+            delay_until(TokioInstant::from_std(started + self.scenario.duration())).await;
+        }
+        let stats = context.tx_emitter.stop_job(job).await;
+        let elapsed = started.elapsed();
+        let rate = stats.rate(elapsed);
+        context
+            .report
+            .report_txn_stats(self.to_string(), stats, elapsed);
+        result?;
+
+        let expect = &self.scenario.expect;
+        if rate.committed == 0 {
+            bail!("Cluster stopped committing during the scenario");
+        }
+        if let Some(min_tps) = expect.min_tps {
+            if rate.committed < min_tps {
+                bail!(
+                    "Committed TPS {} is below expected {}",
+                    rate.committed,
+                    min_tps
+                );
+            }
+        }
+        if let Some(max_p99) = expect.max_p99_latency_ms {
+            if rate.p99_latency > max_p99 {
+                bail!(
+                    "p99 latency {} ms is above expected {} ms",
+                    rate.p99_latency,
+                    max_p99
+                );
+            }
+        }
+        self.wait_catch_up(Duration::from_secs(expect.catch_up_secs))
+            .await
+    }
+
+    fn deadline(&self) -> Duration {
+        self.scenario.duration()
+            + Duration::from_secs(self.scenario.expect.catch_up_secs)
+            + Duration::from_secs(5 * 60)
+    }
+}
+
+impl ScenarioReplay {
+    /// Faulted validators have to reach the version the cluster was at after the scenario
+    async fn wait_catch_up(&self, catch_up: Duration) -> Result<()> {
+        let target = join_all(self.validators.iter().map(Instance::latest_version))
+            .await
+            .into_iter()
+            .filter_map(Result::ok)
+            .max()
+            .unwrap_or(0);
+        let deadline = Instant::now() + catch_up;
+        for instance in &self.faulty {
+            loop {
+                match instance.latest_version().await {
+                    Ok(version) if version >= target => break,
+                    _ if Instant::now() > deadline => bail!(
+                        "{} did not catch up to version {} in {} secs",
+                        instance,
+                        target,
+                        catch_up.as_secs()
+                    ),
+                    _ => time::delay_for(Duration::from_secs(1)).await,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ScenarioReplay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Scenario {}", self.scenario.name)
+    }
+}
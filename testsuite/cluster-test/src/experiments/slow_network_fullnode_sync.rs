@@ -0,0 +1,178 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which limits bandwidth on links between fullnodes and
+/// validators (validator to validator links are not affected), keeps the cluster under load and
+/// measures how far behind validators each fullnode falls while syncing. Clusters without
+/// fullnodes get a temporary one
+use crate::{
+    cluster::Cluster,
+    effects::{self, network_bandwidth::NetworkBandwidth},
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::Instance,
+    tx_emitter::EmitJobRequest,
+    util::unix_timestamp_now,
+};
+use anyhow::{bail, format_err, Result};
+use async_trait::async_trait;
+use libra_logger::info;
+use std::{
+    fmt::{Display, Error, Formatter},
+    time::Duration,
+};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct SlowNetworkFullnodeSyncParams {
+    #[structopt(
+        long,
+        default_value = "10",
+        help = "Bandwidth in mbit/s allowed on each fullnode<->validator link"
+    )]
+    pub bandwidth_mbit: u64,
+    #[structopt(
+        long,
+        default_value = "240",
+        help = "Duration in secs for which load is emitted over throttled links"
+    )]
+    pub duration_secs: u64,
+    #[structopt(
+        long,
+        default_value = "15000",
+        help = "Max number of versions any fullnode is allowed to fall behind validators"
+    )]
+    pub max_version_lag: u64,
+}
+
+pub struct SlowNetworkFullnodeSync {
+    validators: Vec<Instance>,
+    fullnodes: Vec<Instance>,
+    bandwidth_mbit: u64,
+    duration: Duration,
+    max_version_lag: u64,
+}
+
+impl ExperimentParam for SlowNetworkFullnodeSyncParams {
+    type E = SlowNetworkFullnodeSync;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        Self::E {
+            validators: cluster.validator_instances().to_vec(),
+            fullnodes: cluster.fullnode_instances().to_vec(),
+            bandwidth_mbit: self.bandwidth_mbit,
+            duration: Duration::from_secs(self.duration_secs),
+            max_version_lag: self.max_version_lag,
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for SlowNetworkFullnodeSync {
+    fn tags(&self) -> &'static [&'static str] {
+        &["network", "state_sync"]
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        let mut fullnodes = self.fullnodes.clone();
+        if fullnodes.is_empty() {
+            // Clusters deployed without fullnodes get one for the duration of the experiment
+            let validator_index = self
+                .validators
+                .first()
+                .ok_or_else(|| format_err!("Slow network fullnode sync requires validators"))?
+                .validator_group()
+                .index;
+            let fullnode = context
+                .temporary_fullnodes
+                .spawn(context.cluster_swarm, context.cluster, validator_index, &[])
+                .await?;
+            fullnodes.push(fullnode);
+        }
+        let mut effects: Vec<_> = self
+            .validators
+            .iter()
+            .map(|v| NetworkBandwidth::new(v.clone(), fullnodes.clone(), self.bandwidth_mbit))
+            .chain(fullnodes.iter().map(|f| {
+                NetworkBandwidth::new(f.clone(), self.validators.clone(), self.bandwidth_mbit)
+            }))
+            .collect();
+        effects::activate_all(&mut effects).await?;
+
+        // Submit to validators so that the submission path itself is not throttled
+        let emit_job_request =
+            EmitJobRequest::for_instances(self.validators.clone(), context.global_emit_job_request);
+        let start = unix_timestamp_now();
+        let emit_result = context
+            .tx_emitter
+            .emit_txn_for(self.duration, emit_job_request)
+            .await;
+        let end = unix_timestamp_now();
+        effects::deactivate_all(&mut effects).await?;
+        let stats = emit_result?;
+
+        info!(
+            "Link to dashboard : {}",
+            context.prometheus.link_to_dashboard(start, end)
+        );
+        let query = "scalar(max(libra_state_sync_committed_version{peer_id=~\"val-.*\"})) - libra_state_sync_committed_version{peer_id=~\"fn-.*\"}".to_string();
+        let lag_response = context
+            .prometheus
+            .query_range(query, &start, &end, 10)
+            .map_err(|e| format_err!("Failed to query fullnode version lag: {}", e))?;
+        let mut max_lag = 0f64;
+        let mut lags: Vec<_> = lag_response.time_series().iter().collect();
+        lags.sort_by(|a, b| a.0.cmp(b.0));
+        for (fullnode, time_series) in lags {
+            let fullnode_max_lag = time_series.max().unwrap_or(0.0);
+            let samples: Vec<_> = time_series
+                .get()
+                .iter()
+                .map(|(_, lag)| format!("{:.0}", lag))
+                .collect();
+            context.report.report_metric(
+                &self,
+                format!("{}_max_version_lag", fullnode),
+                fullnode_max_lag,
+            );
+            context.report.report_text(format!(
+                "{}: {} max version lag {:.0}, over time: [{}]",
+                self,
+                fullnode,
+                fullnode_max_lag,
+                samples.join(", ")
+            ));
+            if fullnode_max_lag > max_lag {
+                max_lag = fullnode_max_lag;
+            }
+        }
+        context
+            .report
+            .report_metric(&self, "max_version_lag", max_lag);
+        context
+            .report
+            .report_txn_stats(self.to_string(), stats, self.duration);
+        if max_lag > self.max_version_lag as f64 {
+            bail!(
+                "Fullnode fell {:.0} versions behind validators, allowed at most {}",
+                max_lag,
+                self.max_version_lag
+            );
+        }
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(600) + self.duration
+    }
+}
+
+impl Display for SlowNetworkFullnodeSync {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(
+            f,
+            "Slow network fullnode sync at {}mbit",
+            self.bandwidth_mbit
+        )
+    }
+}
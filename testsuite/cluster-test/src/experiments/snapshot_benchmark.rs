@@ -0,0 +1,258 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which measures how fast a state snapshot is backed up from
+/// a validator and restored into an empty db with the backup CLI, optionally at several ledger
+/// sizes, and projects time of a full restore of production sized state from the measured speed
+use crate::{
+    cluster::Cluster,
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::Instance,
+    tx_emitter::EmitJobRequest,
+};
+use anyhow::{format_err, Result};
+use async_trait::async_trait;
+use libra_logger::info;
+use std::{cmp::min, fmt, time::Duration};
+use structopt::StructOpt;
+
+/// Slowest account creation rate the deadline accounts for
+const MIN_ACCOUNTS_PER_SEC: u64 = 200;
+/// Time for backing up and restoring snapshot at each ledger size
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(20 * 60);
+
+#[derive(StructOpt, Debug)]
+pub struct SnapshotBenchmarkParams {
+    #[structopt(
+        long,
+        use_delimiter = true,
+        help = "Numbers of prepopulated accounts at which snapshot is measured, e.g. 0,100000,1000000, including ones created by earlier experiments of the suite. Current ledger only by default"
+    )]
+    pub tiers: Vec<u64>,
+    #[structopt(
+        long,
+        default_value = "100000",
+        help = "Accounts created per round when growing ledger to the next tier"
+    )]
+    pub round_accounts: u64,
+    #[structopt(
+        long,
+        default_value = "100",
+        help = "Size of production state in GB, full restore time is projected for it"
+    )]
+    pub production_state_gb: f64,
+}
+
+pub struct SnapshotBenchmark {
+    instance: Instance,
+    validators: Vec<Instance>,
+    tiers: Vec<u64>,
+    round_accounts: u64,
+    production_state_bytes: f64,
+}
+
+impl ExperimentParam for SnapshotBenchmarkParams {
+    type E = SnapshotBenchmark;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        let mut tiers = self.tiers;
+        tiers.sort_unstable();
+        tiers.dedup();
+        Self::E {
+            instance: cluster.validator_instances()[0].clone(),
+            validators: cluster.validator_instances().to_vec(),
+            tiers,
+            round_accounts: self.round_accounts.max(1),
+            production_state_bytes: self.production_state_gb * 1e9,
+        }
+    }
+}
+
+/// Backup and restore of one snapshot
+struct Measurement {
+    /// Accounts created before the snapshot, not set when ledger is not grown
+    accounts: Option<u64>,
+    version: u64,
+    backup_bytes: u64,
+    backup_time: Duration,
+    restore_time: Duration,
+}
+
+impl Measurement {
+    fn backup_mb_per_sec(&self) -> f64 {
+        self.backup_bytes as f64 / 1e6 / self.backup_time.as_secs_f64().max(0.001)
+    }
+
+    fn restore_mb_per_sec(&self) -> f64 {
+        self.backup_bytes as f64 / 1e6 / self.restore_time.as_secs_f64().max(0.001)
+    }
+}
+
+#[async_trait]
+impl Experiment for SnapshotBenchmark {
+    fn tags(&self) -> &'static [&'static str] {
+        &["storage", "long"]
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        let mut measurements = vec![];
+        if self.tiers.is_empty() {
+            measurements.push(self.measure(None).await?);
+        } else {
+            let request = EmitJobRequest::for_instances(
+                self.validators.clone(),
+                context.global_emit_job_request,
+            );
+            // Tiers below accounts prepopulated earlier in the suite can not be measured anymore
+            let reused = context.warm_state.prepopulated_accounts();
+            let mut created = reused;
+            for tier in self.tiers.iter().filter(|tier| **tier >= reused) {
+                while created < *tier {
+                    let round = min(self.round_accounts, tier - created);
+                    context
+                        .tx_emitter
+                        .mint_accounts(&request, round as usize)
+                        .await?;
+                    context.tx_emitter.clear();
+                    created += round;
+                    context.warm_state.record_prepopulated(created);
+                }
+                measurements.push(self.measure(Some(created)).await?);
+            }
+        }
+
+        let mut text = format!(
+            "{}:\n  accounts | version | snapshot MB | backup MB/s | restore MB/s",
+            self
+        );
+        for m in &measurements {
+            let prefix = match m.accounts {
+                Some(accounts) => format!("tier_{}_", accounts),
+                None => String::new(),
+            };
+            let metrics = [
+                ("snapshot_bytes", m.backup_bytes as f64),
+                ("snapshot_backup_mb_per_sec", m.backup_mb_per_sec()),
+                ("snapshot_restore_mb_per_sec", m.restore_mb_per_sec()),
+            ];
+            for (metric, value) in metrics.iter() {
+                context
+                    .report
+                    .report_metric(&self, format!("{}{}", prefix, metric), *value);
+            }
+            text.push_str(&format!(
+                "\n  {} | {} | {:.1} | {:.1} | {:.1}",
+                m.accounts
+                    .map_or_else(|| "-".to_string(), |a| a.to_string()),
+                m.version,
+                m.backup_bytes as f64 / 1e6,
+                m.backup_mb_per_sec(),
+                m.restore_mb_per_sec()
+            ));
+        }
+        // Largest snapshot is closest to production, smaller ones are dominated by overhead
+        let largest = measurements
+            .iter()
+            .max_by_key(|m| m.backup_bytes)
+            .expect("At least one snapshot is measured");
+        let projected_backup = self.production_state_bytes / 1e6 / largest.backup_mb_per_sec();
+        let projected_restore = self.production_state_bytes / 1e6 / largest.restore_mb_per_sec();
+        context
+            .report
+            .report_metric(&self, "projected_full_backup_secs", projected_backup);
+        context
+            .report
+            .report_metric(&self, "projected_full_restore_secs", projected_restore);
+        text.push_str(&format!(
+            "\n  projected for {:.0} GB of state: backup {:.1} h, restore {:.1} h",
+            self.production_state_bytes / 1e9,
+            projected_backup / 3600.0,
+            projected_restore / 3600.0
+        ));
+        info!("{}", text);
+        context.report.report_text(text);
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        let accounts = self.tiers.last().copied().unwrap_or(0);
+        Duration::from_secs(5 * 60 + accounts / MIN_ACCOUNTS_PER_SEC)
+            + SNAPSHOT_TIMEOUT * self.tiers.len().max(1) as u32
+    }
+}
+
+impl SnapshotBenchmark {
+    /// Backs up snapshot of latest committed version into a temporary dir on the validator and
+    /// restores it into an empty db next to it, both are removed afterwards, also when a step
+    /// fails
+    async fn measure(&self, accounts: Option<u64>) -> Result<Measurement> {
+        info!("Backing up state snapshot of {}", self.instance);
+        let output = self
+            .instance
+            .exec_output(
+                "set -e; \
+                dir=$(mktemp -d -t libra_snapshot_XXXXXXXX); \
+                trap '[ $? -eq 0 ] || rm -rf $dir' EXIT; \
+                version=$(/opt/libra/bin/db-backup one-shot query --backup-service-port 7777 --db-state | sed -n 's/.* committed_version: \\([0-9]*\\).*/\\1/p'); \
+                start=$(date +%s%N); \
+                manifest=$(/opt/libra/bin/db-backup one-shot backup --max-chunk-size 1073741824 --backup-service-port 7777 \
+                state-snapshot --state-version $version local-fs --dir $dir | sed -n 's/.*Manifest: //p'); \
+                end=$(date +%s%N); \
+                echo $dir $version $manifest $(( (end - start) / 1000000 )) $(du -sb $dir | cut -f1)",
+            )
+            .await?;
+        let fields: Vec<_> = output.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format_err!("Unexpected output of backup: {}", output));
+        }
+        let (dir, version, manifest) = (fields[0], fields[1], fields[2]);
+        let parse = |field: &str| {
+            field
+                .parse::<u64>()
+                .map_err(|e| format_err!("Failed to parse {} in backup output: {}", field, e))
+        };
+        let backup_time = Duration::from_millis(parse(fields[3])?);
+        let backup_bytes = parse(fields[4])?;
+        info!(
+            "Backed up {} bytes of state at version {} in {:?}, restoring",
+            backup_bytes, version, backup_time
+        );
+
+        let output = self
+            .instance
+            .exec_output(&format!(
+                "set -e; \
+                target=$(mktemp -d -t libra_restore_XXXXXXXX); \
+                trap 'rm -rf $target {dir}' EXIT; \
+                start=$(date +%s%N); \
+                /opt/libra/bin/db-restore --target-db-dir $target --target-version {version} state-snapshot \
+                --state-manifest {manifest} --state-into-version {version} local-fs --dir {dir} > /dev/null; \
+                end=$(date +%s%N); \
+                echo $(( (end - start) / 1000000 ))",
+                manifest = manifest,
+                version = version,
+                dir = dir
+            ))
+            .await?;
+        let restore_time = Duration::from_millis(parse(output.trim())?);
+        info!("Restored snapshot in {:?}", restore_time);
+        Ok(Measurement {
+            accounts,
+            version: parse(version)?,
+            backup_bytes,
+            backup_time,
+            restore_time,
+        })
+    }
+}
+
+impl fmt::Display for SnapshotBenchmark {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "State snapshot benchmark on {}", self.instance)?;
+        if !self.tiers.is_empty() {
+            write!(f, " at {:?} new accounts", self.tiers)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,291 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which contends for the disk of selected validators while
+/// the cluster is under load. A helper next to selected validators rewrites their SST files in
+/// a loop, which is I/O of the size of a full compaction, and a burst of new accounts makes
+/// their RocksDB flush and compact on top of it. Latency of the cluster and storage write
+/// latency of the selected validators before, during and after contention show how much a
+/// slow disk costs
+use crate::{
+    cluster::Cluster,
+    effects::{self, disk_contention::DiskContention},
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::{self, Instance},
+    tx_emitter::{EmitJobRequest, TxStats},
+    util::unix_timestamp_now,
+};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use futures::future::join_all;
+use libra_logger::{info, warn};
+use std::{
+    collections::HashSet,
+    fmt,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use tokio::time;
+
+#[derive(StructOpt, Debug)]
+pub struct StorageContentionParams {
+    #[structopt(
+        long,
+        default_value = "1",
+        help = "Number of validators whose disk is contended for"
+    )]
+    pub count: usize,
+    #[structopt(
+        long,
+        default_value = "180",
+        help = "Duration of the contention window in secs"
+    )]
+    pub duration_secs: u64,
+    #[structopt(
+        long,
+        default_value = "120",
+        help = "Duration of the windows before and after contention in secs"
+    )]
+    pub baseline_secs: u64,
+    #[structopt(
+        long,
+        default_value = "10000",
+        help = "Accounts created at the start of the contention window to force flushes of new state, 0 to only rewrite SST files"
+    )]
+    pub burst_accounts: usize,
+}
+
+pub struct StorageContention {
+    instances: Vec<Instance>,
+    validators: Vec<Instance>,
+    fullnodes: Vec<Instance>,
+    duration: Duration,
+    baseline: Duration,
+    burst_accounts: usize,
+}
+
+/// Phase of the experiment with emitter stats and unix time bounds of its window
+struct Phase {
+    name: &'static str,
+    stats: TxStats,
+    start: Duration,
+    end: Duration,
+}
+
+impl ExperimentParam for StorageContentionParams {
+    type E = StorageContention;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        let (test_cluster, _) = cluster.split_n_validators_random(self.count);
+        Self::E {
+            instances: test_cluster.into_validator_instances(),
+            validators: cluster.validator_instances().to_vec(),
+            fullnodes: cluster.fullnode_instances().to_vec(),
+            duration: Duration::from_secs(self.duration_secs),
+            baseline: Duration::from_secs(self.baseline_secs),
+            burst_accounts: self.burst_accounts,
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for StorageContention {
+    fn tags(&self) -> &'static [&'static str] {
+        &["storage", "performance"]
+    }
+
+    fn affected_validators(&self) -> HashSet<String> {
+        instance::instancelist_to_set(&self.instances)
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        let instances = if context.emit_to_validator || self.fullnodes.is_empty() {
+            self.validators.clone()
+        } else {
+            self.fullnodes.clone()
+        };
+        let request = EmitJobRequest::for_instances(instances, context.global_emit_job_request);
+        let job = context.tx_emitter.start_job(request.clone()).await?;
+        let baseline_start = unix_timestamp_now();
+        time::delay_for(self.baseline).await;
+        let before = context.tx_emitter.peek_job_stats(&job);
+
+        let mut effects: Vec<_> = self
+            .instances
+            .iter()
+            .cloned()
+            .map(DiskContention::new)
+            .collect();
+        let contention_start = unix_timestamp_now();
+        let contention = self.contend(context, &request, &mut effects).await;
+        let during = context.tx_emitter.peek_job_stats(&job);
+        let contention_end = unix_timestamp_now();
+        // Helpers which started before activation of another one failed are still running
+        let deactivated = effects::deactivate_all(&mut effects).await;
+        if let Err(e) = contention.and(deactivated) {
+            context.tx_emitter.stop_job(job).await;
+            return Err(e);
+        }
+        // Node may not serve JSON-RPC right after contention, any later version is progress then
+        let versions: Vec<_> = join_all(self.instances.iter().map(Instance::latest_version))
+            .await
+            .into_iter()
+            .map(|v| v.unwrap_or(0))
+            .collect();
+
+        time::delay_for(self.baseline).await;
+        let after = context.tx_emitter.stop_job(job).await;
+        let recovery_end = unix_timestamp_now();
+
+        let phases = [
+            Phase {
+                name: "before",
+                stats: &before - &TxStats::default(),
+                start: baseline_start,
+                end: contention_start,
+            },
+            Phase {
+                name: "during",
+                stats: &during - &before,
+                start: contention_start,
+                end: contention_end,
+            },
+            Phase {
+                name: "after",
+                stats: &after - &during,
+                start: contention_end,
+                end: recovery_end,
+            },
+        ];
+        let mut text = format!("{}:", self);
+        let mut p99s = vec![];
+        for phase in phases.iter() {
+            let rate = phase.stats.rate(phase.end - phase.start);
+            // Spikes are short, worst minute of the phase shows them better than the average
+            let worst_minute_p99 = phase
+                .stats
+                .minute_latencies
+                .iter()
+                .filter(|latencies| latencies.count() > 0)
+                .map(|latencies| latencies.percentile(99, 100))
+                .max()
+                .unwrap_or(0);
+            let metrics = [
+                ("tps", rate.committed as f64),
+                ("p99_latency", rate.p99_latency as f64),
+                ("worst_minute_p99_latency", worst_minute_p99 as f64),
+            ];
+            for (metric, value) in metrics.iter() {
+                context
+                    .report
+                    .report_metric(&self, format!("{}_{}", phase.name, metric), *value);
+            }
+            text.push_str(&format!(
+                "\n  {} contention: {} TPS, p99 latency {} ms, worst minute p99 {} ms",
+                phase.name, rate.committed, rate.p99_latency, worst_minute_p99
+            ));
+            match self.save_latency_ms(context, phase) {
+                Ok(latency) => {
+                    context.report.report_metric(
+                        &self,
+                        format!("{}_save_transactions_ms", phase.name),
+                        latency,
+                    );
+                    text.push_str(&format!(
+                        ", storage writes of selected validators {:.1} ms",
+                        latency
+                    ));
+                }
+                Err(e) => warn!("Failed to query storage latency: {}", e),
+            }
+            p99s.push(rate.p99_latency);
+        }
+        if p99s[0] > 0 {
+            let spike = p99s[1] as f64 / p99s[0] as f64;
+            context
+                .report
+                .report_metric(&self, "p99_latency_increase", spike);
+            text.push_str(&format!(
+                "\n  p99 latency during contention is {:.2}x of before",
+                spike
+            ));
+        }
+        info!("{}", text);
+        context.report.report_text(text);
+
+        let versions_after = join_all(self.instances.iter().map(Instance::latest_version)).await;
+        for ((instance, before), after) in self.instances.iter().zip(versions).zip(versions_after) {
+            if after.unwrap_or(0) <= before {
+                bail!(
+                    "{} did not make progress after disk contention was removed",
+                    instance
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(10 * 60) + self.duration + self.baseline * 2
+    }
+}
+
+impl StorageContention {
+    /// Contends for the disk of selected validators until the end of the contention window
+    async fn contend(
+        &self,
+        context: &mut Context<'_>,
+        request: &EmitJobRequest,
+        effects: &mut Vec<DiskContention>,
+    ) -> Result<()> {
+        let deadline = Instant::now() + self.duration;
+        effects::activate_all(effects).await?;
+        if self.burst_accounts > 0 {
+            let requested = context.tx_emitter.num_accounts() + self.burst_accounts;
+            let burst = context.tx_emitter.mint_accounts(request, requested);
+            match time::timeout(self.duration, burst).await {
+                Ok(Ok(())) => info!("Created {} accounts", self.burst_accounts),
+                Ok(Err(e)) => warn!("Account burst failed: {}", e),
+                Err(_) => warn!("Account burst did not finish within contention window"),
+            }
+        }
+        let now = Instant::now();
+        if deadline > now {
+            time::delay_for(deadline - now).await;
+        }
+        Ok(())
+    }
+
+    /// Average latency of `save_transactions` of selected validators during `phase`, which
+    /// tells slow disk apart from slowdowns elsewhere
+    fn save_latency_ms(&self, context: &Context<'_>, phase: &Phase) -> Result<f64> {
+        let peers: Vec<_> = self
+            .instances
+            .iter()
+            .map(|instance| instance.peer_name().clone())
+            .collect();
+        let selector = format!(
+            "api_name=\"save_transactions\",peer_id=~\"{}\"",
+            peers.join("|")
+        );
+        let query = format!(
+            "sum(rate(libra_storage_api_latency_seconds_sum{{{0}}}[1m]))/sum(rate(libra_storage_api_latency_seconds_count{{{0}}}[1m]))",
+            selector
+        );
+        let seconds = context
+            .prometheus
+            .query_range_avg(query, &phase.start, &phase.end, 10)?;
+        Ok(seconds * 1000.0)
+    }
+}
+
+impl fmt::Display for StorageContention {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Storage contention on [")?;
+        for instance in self.instances.iter() {
+            write!(f, "{}, ", instance)?;
+        }
+        write!(f, "]")
+    }
+}
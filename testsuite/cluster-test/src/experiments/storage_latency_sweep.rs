@@ -0,0 +1,299 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which delays sync syscalls of selected validators under
+/// load, with the delay increasing step by step, and reports storage write latency percentiles
+/// of the selected validators next to consensus timing of the cluster at each step. This shows
+/// how slow a disk validators can run on before rounds stretch and timeouts start
+use crate::{
+    cluster::Cluster,
+    effects::{self, storage_delay::StorageDelay},
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::{self, Instance},
+    report::ReportSection,
+    stats::PrometheusRangeView,
+    tx_emitter::EmitJobRequest,
+    util::unix_timestamp_now,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use libra_logger::{info, warn};
+use std::{collections::HashSet, fmt, time::Duration};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct StorageLatencySweepParams {
+    #[structopt(
+        long,
+        default_value = "1",
+        help = "Number of validators whose storage is delayed"
+    )]
+    pub count: usize,
+    #[structopt(
+        long,
+        use_delimiter = true,
+        default_value = "0,1,5,10,25,50",
+        help = "Delays in ms added to each sync syscall, in order, 0 measures overhead of tracing"
+    )]
+    pub delays_ms: Vec<u64>,
+    #[structopt(
+        long,
+        default_value = "fsync,fdatasync",
+        help = "Comma separated syscalls to delay, add pwrite64 to delay writes as well"
+    )]
+    pub syscalls: String,
+    #[structopt(
+        long,
+        default_value = "120",
+        help = "Duration in secs of each step of the sweep"
+    )]
+    pub step_secs: u64,
+    #[structopt(
+        long,
+        default_value = "10",
+        help = "Delay is tolerated while committed TPS stays within given percent of the first step"
+    )]
+    pub max_tps_drop_percent: u64,
+}
+
+pub struct StorageLatencySweep {
+    instances: Vec<Instance>,
+    validators: Vec<Instance>,
+    delays: Vec<Duration>,
+    syscalls: String,
+    step: Duration,
+    max_tps_drop_percent: u64,
+}
+
+/// Measurements of one step of the sweep
+struct Step {
+    delay: Duration,
+    committed_tps: u64,
+    p99_latency: u64,
+    round_duration: Option<f64>,
+    timeouts: Option<f64>,
+    save_p50_ms: Option<f64>,
+    save_p99_ms: Option<f64>,
+}
+
+impl ExperimentParam for StorageLatencySweepParams {
+    type E = StorageLatencySweep;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        let (delayed, _) = cluster.split_n_validators_random(self.count);
+        Self::E {
+            instances: delayed.into_validator_instances(),
+            validators: cluster.validator_instances().to_vec(),
+            delays: self
+                .delays_ms
+                .into_iter()
+                .map(Duration::from_millis)
+                .collect(),
+            syscalls: self.syscalls,
+            step: Duration::from_secs(self.step_secs),
+            max_tps_drop_percent: self.max_tps_drop_percent,
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for StorageLatencySweep {
+    fn tags(&self) -> &'static [&'static str] {
+        &["storage", "performance"]
+    }
+
+    fn report_section(&self) -> ReportSection {
+        ReportSection::Informational
+    }
+
+    fn affected_validators(&self) -> HashSet<String> {
+        instance::instancelist_to_set(&self.instances)
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        let mut text = format!(
+            "{}:\n  delay ms | storage p50/p99 ms | TPS | p99 latency ms | round s | timeouts",
+            self
+        );
+        let mut steps = vec![];
+        for delay in self.delays.clone() {
+            let step = self.run_step(context, delay).await?;
+            let prefix = format!("delay_{}ms", delay.as_millis());
+            let mut metrics = vec![
+                ("committed_tps", step.committed_tps as f64),
+                ("p99_latency", step.p99_latency as f64),
+            ];
+            for (metric, value) in [
+                ("round_duration", step.round_duration),
+                ("timeouts", step.timeouts),
+                ("save_transactions_p50_ms", step.save_p50_ms),
+                ("save_transactions_p99_ms", step.save_p99_ms),
+            ]
+            .iter()
+            {
+                if let Some(value) = value {
+                    metrics.push((*metric, *value));
+                }
+            }
+            for (metric, value) in metrics {
+                context
+                    .report
+                    .report_metric(&self, format!("{}_{}", prefix, metric), value);
+            }
+            text.push_str(&format!(
+                "\n  {:>8} | {:>8} / {:<8} | {:>5} | {:>14} | {:>7} | {:>8}",
+                delay.as_millis(),
+                format_opt(step.save_p50_ms, 1),
+                format_opt(step.save_p99_ms, 1),
+                step.committed_tps,
+                step.p99_latency,
+                format_opt(step.round_duration, 2),
+                format_opt(step.timeouts, 0)
+            ));
+            steps.push(step);
+        }
+
+        let tolerated = max_tolerated_delay(&steps, self.max_tps_drop_percent);
+        match tolerated {
+            Some(delay) => {
+                context.report.report_metric(
+                    &self,
+                    "max_tolerated_delay_ms",
+                    delay.as_millis() as f64,
+                );
+                text.push_str(&format!(
+                    "\n  committed TPS stays within {}% up to {} ms delay",
+                    self.max_tps_drop_percent,
+                    delay.as_millis()
+                ));
+            }
+            None => text.push_str(&format!(
+                "\n  committed TPS drops by more than {}% at every delay",
+                self.max_tps_drop_percent
+            )),
+        }
+        info!("{}", text);
+        context.report.report_text(text);
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        (self.step + Duration::from_secs(3 * 60)) * self.delays.len() as u32
+    }
+}
+
+impl StorageLatencySweep {
+    async fn run_step(&self, context: &mut Context<'_>, delay: Duration) -> Result<Step> {
+        info!("Delaying {} by {} ms", self.syscalls, delay.as_millis());
+        let mut effects: Vec<_> = self
+            .instances
+            .iter()
+            .map(|instance| StorageDelay::new(instance.clone(), self.syscalls.clone(), delay))
+            .collect();
+        effects::activate_all(&mut effects).await?;
+        let start = unix_timestamp_now();
+        let stats = context
+            .tx_emitter
+            .emit_txn_for(
+                self.step,
+                EmitJobRequest::for_instances(
+                    self.validators.clone(),
+                    context.global_emit_job_request,
+                ),
+            )
+            .await;
+        let end = unix_timestamp_now();
+        effects::deactivate_all(&mut effects).await?;
+        let rate = stats?.rate(end - start);
+
+        let pv = PrometheusRangeView::new(context.prometheus, start, end);
+        Ok(Step {
+            delay,
+            committed_tps: rate.committed,
+            p99_latency: rate.p99_latency,
+            round_duration: pv.avg_round_duration(),
+            timeouts: self.query_at_end(
+                context,
+                format!(
+                    "sum(increase(libra_consensus_timeout_count{{peer_id=~\"val-.*\"}}[{}s]))",
+                    (end - start).as_secs()
+                ),
+                start,
+                end,
+            ),
+            save_p50_ms: self.save_latency_ms(context, 0.5, start, end),
+            save_p99_ms: self.save_latency_ms(context, 0.99, start, end),
+        })
+    }
+
+    /// Quantile of `save_transactions` latency of delayed validators over the step, which is
+    /// the storage latency the delay amounts to
+    fn save_latency_ms(
+        &self,
+        context: &Context<'_>,
+        quantile: f64,
+        start: Duration,
+        end: Duration,
+    ) -> Option<f64> {
+        let peers: Vec<_> = self
+            .instances
+            .iter()
+            .map(|instance| instance.peer_name().clone())
+            .collect();
+        let query = format!(
+            "histogram_quantile({}, sum by (le) (increase(libra_storage_api_latency_seconds_bucket{{api_name=\"save_transactions\",peer_id=~\"{}\"}}[{}s])))",
+            quantile,
+            peers.join("|"),
+            (end - start).as_secs()
+        );
+        self.query_at_end(context, query, start, end)
+            .map(|seconds| seconds * 1000.0)
+    }
+
+    fn query_at_end(
+        &self,
+        context: &Context<'_>,
+        query: String,
+        start: Duration,
+        end: Duration,
+    ) -> Option<f64> {
+        context
+            .prometheus
+            .query_range_max(query, &end, &end, 10)
+            .map_err(|e| {
+                warn!(
+                    "No data for step {}s-{}s: {}",
+                    start.as_secs(),
+                    end.as_secs(),
+                    e
+                )
+            })
+            .ok()
+    }
+}
+
+/// Largest delay up to which every step commits within `max_drop_percent` of the first one
+fn max_tolerated_delay(steps: &[Step], max_drop_percent: u64) -> Option<Duration> {
+    let baseline = steps.first()?.committed_tps;
+    steps
+        .iter()
+        .take_while(|step| step.committed_tps * 100 >= baseline * (100 - max_drop_percent.min(100)))
+        .last()
+        .map(|step| step.delay)
+}
+
+fn format_opt(value: Option<f64>, precision: usize) -> String {
+    value.map_or_else(|| "-".to_string(), |v| format!("{:.*}", precision, v))
+}
+
+impl fmt::Display for StorageLatencySweep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Storage latency sweep of {} on {} validators",
+            self.syscalls,
+            self.instances.len()
+        )
+    }
+}
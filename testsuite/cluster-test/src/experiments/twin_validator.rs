@@ -59,6 +59,10 @@ impl ExperimentParam for TwinValidatorsParams {
 
 #[async_trait]
 impl Experiment for TwinValidators {
+    fn tags(&self) -> &'static [&'static str] {
+        &["consensus"]
+    }
+
     fn affected_validators(&self) -> HashSet<String> {
         instance::instancelist_to_set(&self.twin_validators)
     }
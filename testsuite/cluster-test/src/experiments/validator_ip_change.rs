@@ -0,0 +1,178 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which moves one validator to another host, so that its ip
+/// address changes, and measures how long it takes for the validator to reconnect to its peers
+/// and catch up with the rest of the cluster. Validator is moved back to its original host at the
+/// end of the experiment
+use crate::{
+    cluster::Cluster,
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::{self, Instance},
+};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use libra_logger::info;
+use rand::Rng;
+use std::{
+    collections::HashSet,
+    fmt,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use tokio::time;
+
+#[derive(StructOpt, Debug)]
+pub struct ValidatorIpChangeParams {
+    #[structopt(
+        long,
+        default_value = "300",
+        help = "Time in secs for moved validator to reconnect to its peers and catch up"
+    )]
+    pub reconnect_timeout_secs: u64,
+}
+
+pub struct ValidatorIpChange {
+    instance: Instance,
+    healthy_instance: Instance,
+    num_validators: usize,
+    reconnect_timeout: Duration,
+}
+
+impl ExperimentParam for ValidatorIpChangeParams {
+    type E = ValidatorIpChange;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        let mut instances = cluster.validator_instances().to_vec();
+        let num_validators = instances.len();
+        if num_validators < 2 {
+            panic!(
+                "Validator ip change needs a validator besides the seed peer, cluster has {}",
+                num_validators
+            );
+        }
+        // Validator 0 is seed peer for the rest of the cluster, so it is never moved
+        let instance = instances.remove(rand::thread_rng().gen_range(1, instances.len()));
+        Self::E {
+            instance,
+            healthy_instance: instances.remove(0),
+            num_validators,
+            reconnect_timeout: Duration::from_secs(self.reconnect_timeout_secs),
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for ValidatorIpChange {
+    fn tags(&self) -> &'static [&'static str] {
+        &["network"]
+    }
+
+    fn affected_validators(&self) -> HashSet<String> {
+        instance::instancelist_to_set(&[self.instance.clone()])
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        if !context.cluster_swarm.supports_moving_instances() {
+            bail!("Validators of this cluster can not be moved to another host");
+        }
+        let original_host = self.instance.k8s_node().clone();
+        let moved_instance = context
+            .cluster_swarm
+            .move_instance(self.instance.instance_config().clone(), None)
+            .await?;
+        if moved_instance.ip() == self.instance.ip() {
+            bail!("Ip address of {} did not change after move", self.instance);
+        }
+        info!(
+            "Moved {} from {} to {}",
+            self.instance,
+            self.instance.ip(),
+            moved_instance.ip()
+        );
+        let result = self.measure_reconnect(&moved_instance, context).await;
+
+        info!("Moving {} back to {}", self.instance, original_host);
+        let restored_instance = context
+            .cluster_swarm
+            .move_instance(self.instance.instance_config().clone(), Some(original_host))
+            .await?;
+        restored_instance
+            .wait_json_rpc(Instant::now() + Duration::from_secs(120))
+            .await?;
+        result
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(10 * 60) + 2 * self.reconnect_timeout
+    }
+}
+
+impl ValidatorIpChange {
+    async fn measure_reconnect(
+        &self,
+        moved_instance: &Instance,
+        context: &mut Context<'_>,
+    ) -> Result<()> {
+        moved_instance
+            .wait_json_rpc(Instant::now() + Duration::from_secs(120))
+            .await?;
+        let started = Instant::now();
+        let deadline = started + self.reconnect_timeout;
+        let expected_peers = (self.num_validators - 1) as f64;
+        loop {
+            let connected_peers = moved_instance
+                .counter("libra_network_peers.validator.connected")
+                .unwrap_or(0.0);
+            if connected_peers >= expected_peers {
+                break;
+            }
+            if Instant::now() > deadline {
+                bail!(
+                    "{} connected to {} of {} peers after ip change",
+                    self.instance,
+                    connected_peers,
+                    expected_peers
+                );
+            }
+            time::delay_for(Duration::from_secs(1)).await;
+        }
+        let reconnect_time = started.elapsed();
+
+        let target_version = self.healthy_instance.latest_version().await?;
+        while moved_instance.latest_version().await.unwrap_or(0) < target_version {
+            if Instant::now() > deadline {
+                bail!(
+                    "{} did not catch up to version {} after ip change",
+                    self.instance,
+                    target_version
+                );
+            }
+            time::delay_for(Duration::from_secs(1)).await;
+        }
+        let catch_up_time = started.elapsed();
+
+        let msg = format!(
+            "{}: reconnected to all peers in {} secs, caught up in {} secs",
+            self,
+            reconnect_time.as_secs(),
+            catch_up_time.as_secs()
+        );
+        info!("{}", msg);
+        context.report.report_text(msg);
+        context
+            .report
+            .report_metric(&self, "reconnect_time", reconnect_time.as_secs_f64());
+        context
+            .report
+            .report_metric(&self, "catch_up_time", catch_up_time.as_secs_f64());
+        Ok(())
+    }
+}
+
+impl fmt::Display for ValidatorIpChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Validator ip change of {}", self.instance)
+    }
+}
@@ -0,0 +1,319 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which removes a validator from the on-chain validator set
+/// under load, checks that it stops proposing without crashing while the rest of the cluster
+/// reconfigures to a smaller set, and then adds it back and waits until it proposes again.
+/// Throughput and latency are reported for the windows after each reconfiguration
+use crate::{
+    cluster::Cluster,
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::{self, Instance},
+    tx_emitter::{execute_and_wait_transactions, EmitJobRequest, TxStatsRate},
+};
+use anyhow::{bail, format_err, Result};
+use async_trait::async_trait;
+use libra_logger::{info, warn};
+use libra_types::{
+    account_config::COIN1_NAME,
+    chain_id::ChainId,
+    transaction::{helpers::create_user_txn, Script, TransactionPayload},
+};
+use rand::Rng;
+use std::{
+    collections::HashSet,
+    fmt,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use tokio::time;
+use transaction_builder::{encode_add_validator_script, encode_remove_validator_script};
+
+const MAX_GAS_AMOUNT: u64 = 1_000_000;
+const TXN_EXPIRATION_SECONDS: i64 = 60;
+const PROPOSALS_COUNTER: &str = "libra_consensus_proposals_count";
+const EPOCH_VALIDATORS_GAUGE: &str = "libra_consensus_current_epoch_validators";
+
+#[derive(StructOpt, Debug)]
+pub struct ValidatorRemovalParams {
+    #[structopt(
+        long,
+        default_value = "120",
+        help = "Duration in secs of load before removal, after removal and after validator is added back"
+    )]
+    pub window_secs: u64,
+    #[structopt(
+        long,
+        default_value = "300",
+        help = "Time in secs for validator added back to catch up and propose again"
+    )]
+    pub rejoin_timeout_secs: u64,
+}
+
+pub struct ValidatorRemoval {
+    instance: Instance,
+    /// Load is sent to these, they stay in the validator set
+    remaining: Vec<Instance>,
+    window: Duration,
+    rejoin_timeout: Duration,
+}
+
+impl ExperimentParam for ValidatorRemovalParams {
+    type E = ValidatorRemoval;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        let mut remaining = cluster.validator_instances().to_vec();
+        // Validator 0 is seed peer for the rest of the cluster, so it is never removed
+        if remaining.len() < 2 {
+            panic!(
+                "Validator removal needs a validator besides the seed peer, cluster has {}",
+                remaining.len()
+            );
+        }
+        let instance = remaining.remove(rand::thread_rng().gen_range(1, remaining.len()));
+        Self::E {
+            instance,
+            remaining,
+            window: Duration::from_secs(self.window_secs),
+            rejoin_timeout: Duration::from_secs(self.rejoin_timeout_secs),
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for ValidatorRemoval {
+    fn tags(&self) -> &'static [&'static str] {
+        &["reconfiguration"]
+    }
+
+    fn affected_validators(&self) -> HashSet<String> {
+        instance::instancelist_to_set(&[self.instance.clone()])
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        let address = self.instance.validator_address().await?;
+        let num_validators = self.remaining.len() + 1;
+        let epoch_validators = self.remaining[0].counter(EPOCH_VALIDATORS_GAUGE)? as usize;
+        if epoch_validators != num_validators {
+            bail!(
+                "Validator set has {} validators, cluster has {}",
+                epoch_validators,
+                num_validators
+            );
+        }
+
+        let job = context
+            .tx_emitter
+            .start_job(EmitJobRequest::for_instances(
+                self.remaining.clone(),
+                context.global_emit_job_request,
+            ))
+            .await?;
+        time::delay_for(self.window).await;
+        let baseline = context.tx_emitter.peek_job_stats(&job);
+
+        info!(
+            "Removing {} ({}) from validator set",
+            self.instance, address
+        );
+        let started = Instant::now();
+        if let Err(e) = self
+            .reconfigure(context, encode_remove_validator_script(address))
+            .await
+        {
+            context.tx_emitter.stop_job(job).await;
+            return Err(e);
+        }
+        let removal_secs = started.elapsed().as_secs_f64();
+        let mut failures = vec![];
+        let proposals = self.instance.counter(PROPOSALS_COUNTER).unwrap_or(0.0);
+        time::delay_for(self.window).await;
+        let after_removal = context.tx_emitter.peek_job_stats(&job);
+        if let Err(e) = self.check_removed(num_validators, proposals).await {
+            failures.push(format!("after removal: {}", e));
+        }
+
+        info!(
+            "Adding {} ({}) back to validator set",
+            self.instance, address
+        );
+        let started = Instant::now();
+        let readd = self
+            .reconfigure(context, encode_add_validator_script(address))
+            .await;
+        let readd_secs = started.elapsed().as_secs_f64();
+        let rejoin = match readd {
+            Ok(()) => self.wait_rejoined(num_validators).await,
+            Err(e) => Err(e),
+        };
+        let rejoin_secs = started.elapsed().as_secs_f64();
+        time::delay_for(self.window).await;
+        let after_readd = context.tx_emitter.stop_job(job).await;
+        if let Err(e) = rejoin {
+            // Rest of the suite would run with a smaller validator set otherwise
+            bail!("{} did not rejoin validator set: {}", self.instance, e);
+        }
+
+        let windows = [
+            ("baseline", baseline.rate(self.window)),
+            (
+                "after_removal",
+                (&after_removal - &baseline).rate(self.window),
+            ),
+            // Covers the time validator took to rejoin as well
+            (
+                "after_readd",
+                (&after_readd - &after_removal)
+                    .rate(self.window + Duration::from_secs_f64(rejoin_secs)),
+            ),
+        ];
+        self.report(context, &windows, removal_secs, readd_secs, rejoin_secs);
+        if windows.iter().any(|(_, rate)| rate.committed == 0) {
+            failures.push("cluster stopped committing".to_string());
+        }
+        if !failures.is_empty() {
+            bail!("{}", failures.join(", "));
+        }
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(5 * 60) + self.window * 3 + self.rejoin_timeout
+    }
+}
+
+impl ValidatorRemoval {
+    /// Runs admin script of libra root account and waits until it is committed
+    async fn reconfigure(&self, context: &mut Context<'_>, script: Script) -> Result<()> {
+        let target = &self.remaining[0];
+        let mut root = context.tx_emitter.load_libra_root_account(target).await?;
+        let txn = create_user_txn(
+            &root.key_pair,
+            TransactionPayload::Script(script),
+            root.address,
+            root.sequence_number,
+            MAX_GAS_AMOUNT,
+            0,
+            COIN1_NAME.to_owned(),
+            TXN_EXPIRATION_SECONDS,
+            ChainId::test(),
+        )
+        .map_err(|e| format_err!("Failed to create signed transaction: {}", e))?;
+        root.sequence_number += 1;
+        execute_and_wait_transactions(&mut target.json_rpc_client(), &mut root, vec![txn]).await
+    }
+
+    /// Removed validator has to keep running without proposing, while the rest of the cluster
+    /// is in an epoch without it
+    async fn check_removed(&self, num_validators: usize, proposals_before: f64) -> Result<()> {
+        let epoch_validators = self.remaining[0].counter(EPOCH_VALIDATORS_GAUGE)? as usize;
+        if epoch_validators != num_validators - 1 {
+            bail!(
+                "{} validators in epoch, expected {}",
+                epoch_validators,
+                num_validators - 1
+            );
+        }
+        self.instance
+            .latest_version()
+            .await
+            .map_err(|e| format_err!("{} stopped responding: {}", self.instance, e))?;
+        let proposals = self.instance.counter(PROPOSALS_COUNTER)?;
+        if proposals > proposals_before {
+            bail!(
+                "{} sent {} proposals after it was removed",
+                self.instance,
+                proposals - proposals_before
+            );
+        }
+        Ok(())
+    }
+
+    /// Validator added back has to reach the epoch with the full validator set, catch up with
+    /// the rest of the cluster and propose again
+    async fn wait_rejoined(&self, num_validators: usize) -> Result<()> {
+        let deadline = Instant::now() + self.rejoin_timeout;
+        let proposals_before = self.instance.counter(PROPOSALS_COUNTER).unwrap_or(0.0);
+        let target = self.remaining[0].latest_version().await?;
+        loop {
+            let epoch_validators = self.instance.counter(EPOCH_VALIDATORS_GAUGE).unwrap_or(0.0);
+            let version = self.instance.latest_version().await.unwrap_or(0);
+            let proposals = self.instance.counter(PROPOSALS_COUNTER).unwrap_or(0.0);
+            if epoch_validators as usize == num_validators
+                && version >= target
+                && proposals > proposals_before
+            {
+                return Ok(());
+            }
+            if Instant::now() > deadline {
+                bail!(
+                    "after {} secs: {} validators in its epoch, version {} of {}, {} proposals",
+                    self.rejoin_timeout.as_secs(),
+                    epoch_validators,
+                    version,
+                    target,
+                    proposals - proposals_before
+                );
+            }
+            time::delay_for(Duration::from_secs(1)).await;
+        }
+    }
+
+    fn report(
+        &self,
+        context: &mut Context<'_>,
+        windows: &[(&str, TxStatsRate)],
+        removal_secs: f64,
+        readd_secs: f64,
+        rejoin_secs: f64,
+    ) {
+        let mut text = format!(
+            "{}: removal committed in {:.1} secs, re-add in {:.1} secs, proposing again after {:.1} secs",
+            self, removal_secs, readd_secs, rejoin_secs
+        );
+        context
+            .report
+            .report_metric(&self, "removal_commit_secs", removal_secs);
+        context
+            .report
+            .report_metric(&self, "readd_commit_secs", readd_secs);
+        context
+            .report
+            .report_metric(&self, "rejoin_secs", rejoin_secs);
+        let baseline_tps = windows[0].1.committed;
+        for (window, rate) in windows {
+            context
+                .report
+                .report_metric(&self, format!("{}_tps", window), rate.committed as f64);
+            context.report.report_metric(
+                &self,
+                format!("{}_p99_latency_ms", window),
+                rate.p99_latency as f64,
+            );
+            text.push_str(&format!(
+                "\n  {}: {} TPS, {} ms p99 latency",
+                window, rate.committed, rate.p99_latency
+            ));
+            if *window != "baseline" && baseline_tps > 0 {
+                let delta =
+                    (rate.committed as f64 - baseline_tps as f64) * 100.0 / baseline_tps as f64;
+                context
+                    .report
+                    .report_metric(&self, format!("{}_delta_pct_tps", window), delta);
+                text.push_str(&format!(" ({:+.1}% TPS)", delta));
+            }
+        }
+        if windows.iter().any(|(_, rate)| rate.expired > 0) {
+            warn!("Transactions expired during reconfiguration");
+        }
+        info!("{}", text);
+        context.report.report_text(text);
+    }
+}
+
+impl fmt::Display for ValidatorRemoval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Validator set removal and re-add of {}", self.instance)
+    }
+}
@@ -0,0 +1,205 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which redeploys the validator set with different sizes in
+/// sequence, runs the same benchmark against each of them and reports throughput and latency
+/// versus number of validators. Original cluster is redeployed with a fresh db afterwards
+use crate::{
+    cluster::Cluster,
+    cost::EXTRA_INSTANCE_HOURS,
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::{
+        self, ApplicationConfig::Validator, Instance, InstanceConfig, ValidatorConfig,
+        ValidatorGroup,
+    },
+    tx_emitter::EmitJobRequest,
+};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use futures::future::try_join_all;
+use libra_logger::info;
+use std::{
+    cmp::max,
+    collections::HashSet,
+    fmt,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct ValidatorSetScalingParams {
+    #[structopt(
+        long,
+        use_delimiter = true,
+        default_value = "30,60,100",
+        help = "Validator set sizes to benchmark, in order"
+    )]
+    pub sizes: Vec<u32>,
+    #[structopt(
+        long,
+        default_value = "180",
+        help = "Duration in secs of the benchmark run against each validator set size"
+    )]
+    pub duration_secs: u64,
+}
+
+pub struct ValidatorSetScaling {
+    sizes: Vec<u32>,
+    duration: Duration,
+    validators: Vec<Instance>,
+    fullnodes: Vec<Instance>,
+}
+
+impl ExperimentParam for ValidatorSetScalingParams {
+    type E = ValidatorSetScaling;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        Self::E {
+            sizes: self.sizes,
+            duration: Duration::from_secs(self.duration_secs),
+            validators: cluster.validator_instances().to_vec(),
+            fullnodes: cluster.fullnode_instances().to_vec(),
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for ValidatorSetScaling {
+    fn tags(&self) -> &'static [&'static str] {
+        &["performance", "long"]
+    }
+
+    fn affected_validators(&self) -> HashSet<String> {
+        instance::instancelist_to_set(&self.validators)
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        let template = match &self.validators[0].instance_config().application_config {
+            Validator(config) if !config.enable_lsr => config.clone(),
+            Validator(_) => bail!("Validator set scaling is not supported with lsr"),
+            _ => bail!("Unexpected application config for validator"),
+        };
+        let original_instances: Vec<_> = self
+            .validators
+            .iter()
+            .chain(self.fullnodes.iter())
+            .cloned()
+            .collect();
+        info!("Stopping original cluster");
+        try_join_all(original_instances.iter().map(Instance::stop)).await?;
+
+        let mut results = vec![];
+        for size in self.sizes.clone() {
+            let result = self.benchmark_set_size(context, &template, size).await;
+            results.push((size, result));
+        }
+
+        info!("Redeploying original cluster");
+        let cluster_swarm = context.cluster_swarm;
+        try_join_all(original_instances.iter().map(|instance| {
+            cluster_swarm.spawn_new_instance(instance.instance_config().clone(), true)
+        }))
+        .await?;
+        let deadline = Instant::now() + Duration::from_secs(5 * 60);
+        try_join_all(
+            original_instances
+                .iter()
+                .map(|instance| instance.wait_json_rpc(deadline)),
+        )
+        .await?;
+        // Accounts minted so far do not exist on the redeployed chain
+        context.tx_emitter.clear();
+        context.warm_state.invalidate();
+
+        let mut curve = vec![];
+        for (size, result) in results {
+            let (tps, p99_latency) = result?;
+            curve.push(format!("{}: {} TPS / {} ms p99", size, tps, p99_latency));
+        }
+        context.report.report_text(format!(
+            "{}: validators vs throughput and latency [{}]",
+            self,
+            curve.join(", ")
+        ));
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(15 * 60)
+            + (Duration::from_secs(15 * 60) + self.duration) * self.sizes.len() as u32
+    }
+}
+
+impl ValidatorSetScaling {
+    /// Deploys validator set of given size with fresh genesis, runs benchmark against it and
+    /// stops it. Returns average TPS and p99 latency
+    async fn benchmark_set_size(
+        &self,
+        context: &mut Context<'_>,
+        template: &ValidatorConfig,
+        size: u32,
+    ) -> Result<(u64, u64)> {
+        info!("Deploying {} validators", size);
+        let started = Instant::now();
+        // Pods of the original cluster keep their hosts, extra validators need new ones
+        let extra_validators = size.saturating_sub(self.validators.len() as u32);
+        let original_count = (self.validators.len() + self.fullnodes.len()) as u32;
+        context
+            .cluster_swarm
+            .ensure_host_count(max(size, original_count + extra_validators))
+            .await?;
+        let cluster_swarm = context.cluster_swarm;
+        let validators = try_join_all((0..size).map(|i| {
+            let config = InstanceConfig {
+                validator_group: ValidatorGroup::new_for_index(i),
+                application_config: Validator(ValidatorConfig {
+                    num_validators: size,
+                    num_fullnodes: 0,
+                    ..template.clone()
+                }),
+            };
+            cluster_swarm.spawn_new_instance(config, true)
+        }))
+        .await?;
+        let deadline = Instant::now() + Duration::from_secs(10 * 60);
+        let result = match try_join_all(validators.iter().map(|v| v.wait_json_rpc(deadline))).await
+        {
+            Ok(_) => {
+                // Each validator set starts with a new genesis
+                context.tx_emitter.clear();
+                context.warm_state.invalidate();
+                let emit_job_request = EmitJobRequest::for_instances(
+                    validators.clone(),
+                    context.global_emit_job_request,
+                );
+                context
+                    .tx_emitter
+                    .emit_txn_for(self.duration, emit_job_request)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        info!("Stopping {} validators", size);
+        try_join_all(validators.iter().map(Instance::stop)).await?;
+        context.report.report_metric(
+            &self,
+            EXTRA_INSTANCE_HOURS,
+            extra_validators as f64 * started.elapsed().as_secs_f64() / 3600.0,
+        );
+        let stats = result?;
+        let rate = stats.rate(self.duration);
+        context.report.report_txn_stats(
+            format!("{} at {} validators", self, size),
+            stats,
+            self.duration,
+        );
+        Ok((rate.committed, rate.p99_latency))
+    }
+}
+
+impl fmt::Display for ValidatorSetScaling {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Validator set scaling {:?}", self.sizes)
+    }
+}
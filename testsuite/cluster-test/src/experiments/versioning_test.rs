@@ -6,10 +6,12 @@
 use crate::{
     cluster::Cluster,
     experiments::{
-        compatibility_test::update_batch_instance, Context, Experiment, ExperimentParam,
+        compatibility_test::{prepull_images, report_rollout_times, update_batch_instance},
+        Context, Experiment, ExperimentParam,
     },
     instance,
     instance::Instance,
+    report::ReportSection,
     tx_emitter::{execute_and_wait_transactions, AccountData, EmitJobRequest},
 };
 use anyhow::format_err;
@@ -37,6 +39,11 @@ pub struct ValidatorVersioningParams {
     pub count: usize,
     #[structopt(long, help = "Image tag of newer validator software")]
     pub updated_image_tag: String,
+    #[structopt(
+        long,
+        help = "Pull images of the newer tag on all hosts before upgrading, so that rollout times do not include download"
+    )]
+    pub prepull_images: bool,
 }
 
 pub struct ValidatorVersioning {
@@ -46,6 +53,7 @@ pub struct ValidatorVersioning {
     second_batch_lsr: Vec<Instance>,
     full_nodes: Vec<Instance>,
     updated_image_tag: String,
+    prepull_images: bool,
 }
 
 impl ExperimentParam for ValidatorVersioningParams {
@@ -75,12 +83,21 @@ impl ExperimentParam for ValidatorVersioningParams {
             second_batch_lsr,
             full_nodes: cluster.fullnode_instances().to_vec(),
             updated_image_tag: self.updated_image_tag,
+            prepull_images: self.prepull_images,
         }
     }
 }
 
 #[async_trait]
 impl Experiment for ValidatorVersioning {
+    fn tags(&self) -> &'static [&'static str] {
+        &["upgrade", "long"]
+    }
+
+    fn report_section(&self) -> ReportSection {
+        ReportSection::Critical
+    }
+
     fn affected_validators(&self) -> HashSet<String> {
         instance::instancelist_to_set(&self.first_batch)
             .union(&instance::instancelist_to_set(&self.second_batch))
@@ -89,6 +106,9 @@ impl Experiment for ValidatorVersioning {
     }
 
     async fn run(&mut self, context: &mut Context<'_>) -> anyhow::Result<()> {
+        if self.prepull_images {
+            prepull_images(context, &self.to_string(), &self.updated_image_tag).await;
+        }
         // Mint a number of accounts
         context
             .tx_emitter
@@ -102,13 +122,20 @@ impl Experiment for ValidatorVersioning {
             .await?;
 
         info!("1. Changing the images for the instances in the first batch");
-        update_batch_instance(
+        let rollout_times = update_batch_instance(
             context,
             &self.first_batch,
             &self.first_batch_lsr,
             self.updated_image_tag.clone(),
         )
         .await?;
+        report_rollout_times(
+            context,
+            &self.to_string(),
+            "first_batch",
+            &self.first_batch,
+            &rollout_times,
+        );
 
         info!("2. Send a transaction to make sure it is not rejected nor cause any fork");
         let full_node = context.cluster.random_fullnode_instance();
@@ -149,13 +176,20 @@ impl Experiment for ValidatorVersioning {
             .await?;
 
         info!("3. Change the rest of the images in the second batch");
-        update_batch_instance(
+        let rollout_times = update_batch_instance(
             context,
             &self.second_batch,
             &self.second_batch_lsr,
             self.updated_image_tag.clone(),
         )
         .await?;
+        report_rollout_times(
+            context,
+            &self.to_string(),
+            "second_batch",
+            &self.second_batch,
+            &rollout_times,
+        );
 
         info!("4. Send a transaction to make sure this feature is still not activated.");
         let txn2 = txn_gen(&mut account_1)?;
@@ -206,13 +240,20 @@ impl Experiment for ValidatorVersioning {
         };
 
         info!("7. Change the images for the full nodes");
-        update_batch_instance(
+        let rollout_times = update_batch_instance(
             context,
             &self.full_nodes,
             &[],
             self.updated_image_tag.clone(),
         )
         .await?;
+        report_rollout_times(
+            context,
+            &self.to_string(),
+            "full_nodes",
+            &self.full_nodes,
+            &rollout_times,
+        );
 
         info!("8. Send a transaction to make sure it gets dropped by the full node mempool.");
 
@@ -0,0 +1,306 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// This module provides an experiment which takes down every validator and fullnode of one
+/// availability zone at once under load, checks that the rest of the cluster keeps committing,
+/// then restores the zone and measures how long its instances take to catch up and how the
+/// re-sync of all of them at once affects throughput of the cluster
+use crate::{
+    cluster::Cluster,
+    experiments::{Context, Experiment, ExperimentParam},
+    instance::{self, Instance},
+    tx_emitter::{EmitJobRequest, TxStatsRate},
+};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use futures::future::{join_all, try_join_all};
+use libra_logger::{info, warn};
+use rand::seq::SliceRandom;
+use std::{
+    collections::HashSet,
+    fmt,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use tokio::time;
+
+#[derive(StructOpt, Debug)]
+pub struct ZoneOutageParams {
+    #[structopt(
+        long,
+        help = "Zone to take down, random zone of validators whose outage does not halt the cluster if not set"
+    )]
+    pub zone: Option<String>,
+    #[structopt(
+        long,
+        default_value = "120",
+        help = "Duration in secs of load before the outage, during it and after the zone caught up"
+    )]
+    pub window_secs: u64,
+    #[structopt(
+        long,
+        default_value = "600",
+        help = "Time in secs for instances of the zone to catch up after it is restored"
+    )]
+    pub catch_up_timeout_secs: u64,
+}
+
+pub struct ZoneOutage {
+    /// Not set if zones of the cluster are unknown
+    zone: Option<String>,
+    down: Cluster,
+    up: Cluster,
+    num_validators: usize,
+    window: Duration,
+    catch_up_timeout: Duration,
+}
+
+impl ExperimentParam for ZoneOutageParams {
+    type E = ZoneOutage;
+    fn build(self, cluster: &Cluster) -> Self::E {
+        let num_validators = cluster.validator_instances().len();
+        let zone = self.zone.or_else(|| {
+            let zones = cluster.zones();
+            if zones.is_empty() {
+                return None;
+            }
+            let safe: Vec<_> = zones
+                .into_iter()
+                .filter(|zone| {
+                    let (_, up) = cluster.split_zone(zone);
+                    keeps_liveness(up.validator_instances().len(), num_validators)
+                })
+                .collect();
+            match safe.choose(&mut rand::thread_rng()) {
+                Some(zone) => Some(zone.clone()),
+                None => {
+                    panic!("Outage of any zone would halt the cluster, set --zone to force one")
+                }
+            }
+        });
+        let (down, up) = match &zone {
+            Some(zone) => cluster.split_zone(zone),
+            None => (
+                Cluster::new(vec![], vec![], vec![], vec![]),
+                cluster.clone(),
+            ),
+        };
+        Self::E {
+            zone,
+            down,
+            up,
+            num_validators,
+            window: Duration::from_secs(self.window_secs),
+            catch_up_timeout: Duration::from_secs(self.catch_up_timeout_secs),
+        }
+    }
+}
+
+#[async_trait]
+impl Experiment for ZoneOutage {
+    fn tags(&self) -> &'static [&'static str] {
+        &["failure_domain", "state_sync", "long"]
+    }
+
+    fn affected_validators(&self) -> HashSet<String> {
+        instance::instancelist_to_set(&self.down_instances())
+    }
+
+    async fn run(&mut self, context: &mut Context<'_>) -> Result<()> {
+        let zone = match &self.zone {
+            Some(zone) => zone.clone(),
+            None => bail!("Zones of validators are unknown, set them in inventory or node labels"),
+        };
+        let down = self.down_instances();
+        if down.is_empty() {
+            bail!("No validators or fullnodes run in zone {}", zone);
+        }
+        let up_validators = self.up.validator_instances().to_vec();
+        if !keeps_liveness(up_validators.len(), self.num_validators) {
+            bail!(
+                "Zone {} has {} of {} validators, outage would halt the cluster",
+                zone,
+                self.num_validators - up_validators.len(),
+                self.num_validators
+            );
+        }
+
+        let job = context
+            .tx_emitter
+            .start_job(EmitJobRequest::for_instances(
+                up_validators.clone(),
+                context.global_emit_job_request,
+            ))
+            .await?;
+        time::delay_for(self.window).await;
+        let baseline = context.tx_emitter.peek_job_stats(&job);
+
+        info!("Taking down {} instances of zone {}", down.len(), zone);
+        if let Err(e) = try_join_all(down.iter().map(Instance::stop)).await {
+            // Instances which were stopped are brought back before failing
+            join_all(down.iter().map(|instance| instance.start(false))).await;
+            context.tx_emitter.stop_job(job).await;
+            return Err(e);
+        }
+        time::delay_for(self.window).await;
+        let outage = context.tx_emitter.peek_job_stats(&job);
+
+        info!("Restoring zone {}", zone);
+        let restored = Instant::now();
+        // Every instance is started even if some fail, so that the zone is not left down
+        let started: Result<Vec<_>> = join_all(down.iter().map(|instance| instance.start(false)))
+            .await
+            .into_iter()
+            .collect();
+        let catch_up = match started {
+            Ok(_) => self.wait_caught_up(&down, &up_validators[0]).await,
+            Err(e) => {
+                context.tx_emitter.stop_job(job).await;
+                return Err(e);
+            }
+        };
+        let resync_window = restored.elapsed();
+        let resync = context.tx_emitter.peek_job_stats(&job);
+        time::delay_for(self.window).await;
+        let recovered = context.tx_emitter.stop_job(job).await;
+
+        let windows = [
+            ("baseline", baseline.rate(self.window)),
+            ("outage", (&outage - &baseline).rate(self.window)),
+            ("resync", (&resync - &outage).rate(resync_window)),
+            ("recovered", (&recovered - &resync).rate(self.window)),
+        ];
+        self.report(context, &zone, &windows, &catch_up);
+        if windows[1].1.committed == 0 {
+            bail!("Cluster stopped committing while zone {} was down", zone);
+        }
+        let lagging: Vec<_> = down
+            .iter()
+            .zip(&catch_up)
+            .filter(|(_, secs)| secs.is_none())
+            .map(|(instance, _)| instance.peer_name().clone())
+            .collect();
+        if !lagging.is_empty() {
+            bail!(
+                "{} did not catch up within {} secs",
+                lagging.join(", "),
+                self.catch_up_timeout.as_secs()
+            );
+        }
+        Ok(())
+    }
+
+    fn deadline(&self) -> Duration {
+        Duration::from_secs(5 * 60) + self.window * 3 + self.catch_up_timeout
+    }
+}
+
+impl ZoneOutage {
+    fn down_instances(&self) -> Vec<Instance> {
+        self.down
+            .validator_and_fullnode_instances()
+            .cloned()
+            .collect()
+    }
+
+    /// Secs each instance took to reach version the cluster had when it was restored, or none
+    /// if it did not reach it before the timeout
+    async fn wait_caught_up(&self, down: &[Instance], up: &Instance) -> Vec<Option<f64>> {
+        let started = Instant::now();
+        let deadline = started + self.catch_up_timeout;
+        let mut caught_up = vec![None; down.len()];
+        let target = match up.latest_version().await {
+            Ok(version) => version,
+            Err(e) => {
+                warn!("Failed to query version of {}: {}", up, e);
+                return caught_up;
+            }
+        };
+        while Instant::now() < deadline && caught_up.iter().any(Option::is_none) {
+            let versions = join_all(down.iter().map(Instance::latest_version)).await;
+            for (secs, version) in caught_up.iter_mut().zip(versions) {
+                if secs.is_none() && version.map_or(false, |v| v >= target) {
+                    *secs = Some(started.elapsed().as_secs_f64());
+                }
+            }
+            time::delay_for(Duration::from_secs(1)).await;
+        }
+        caught_up
+    }
+
+    fn report(
+        &self,
+        context: &mut Context<'_>,
+        zone: &str,
+        windows: &[(&str, TxStatsRate)],
+        catch_up: &[Option<f64>],
+    ) {
+        let mut text = format!("{}:", self);
+        let baseline_tps = windows[0].1.committed;
+        for (window, rate) in windows {
+            context
+                .report
+                .report_metric(&self, format!("{}_tps", window), rate.committed as f64);
+            context.report.report_metric(
+                &self,
+                format!("{}_p99_latency_ms", window),
+                rate.p99_latency as f64,
+            );
+            text.push_str(&format!(
+                "\n  {}: {} TPS, {} ms p99 latency",
+                window, rate.committed, rate.p99_latency
+            ));
+            if *window != "baseline" && baseline_tps > 0 {
+                let delta =
+                    (rate.committed as f64 - baseline_tps as f64) * 100.0 / baseline_tps as f64;
+                context
+                    .report
+                    .report_metric(&self, format!("{}_delta_pct_tps", window), delta);
+                text.push_str(&format!(" ({:+.1}% TPS)", delta));
+            }
+        }
+        let caught_up: Vec<_> = catch_up.iter().filter_map(|secs| *secs).collect();
+        if !caught_up.is_empty() {
+            let max = caught_up.iter().cloned().fold(0.0, f64::max);
+            let avg = caught_up.iter().sum::<f64>() / caught_up.len() as f64;
+            context
+                .report
+                .report_metric(&self, "avg_catch_up_secs", avg);
+            context
+                .report
+                .report_metric(&self, "max_catch_up_secs", max);
+            text.push_str(&format!(
+                "\n  {} of {} instances of zone {} caught up, avg {:.1} secs, max {:.1} secs",
+                caught_up.len(),
+                catch_up.len(),
+                zone,
+                avg,
+                max
+            ));
+        }
+        info!("{}", text);
+        context.report.report_text(text);
+    }
+}
+
+/// Liveness needs more than 2/3 of validators
+fn keeps_liveness(up_validators: usize, num_validators: usize) -> bool {
+    up_validators * 3 > num_validators * 2
+}
+
+impl fmt::Display for ZoneOutage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.zone {
+            Some(zone) => write!(
+                f,
+                "Outage of zone {} ({} validators, {} fullnodes)",
+                zone,
+                self.down.validator_instances().len(),
+                self.down.fullnode_instances().len()
+            ),
+            None => write!(f, "Zone outage"),
+        }
+    }
+}
@@ -0,0 +1,170 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+use crate::{
+    cluster::Cluster,
+    health::{HealthCheck, HealthCheckContext},
+    instance::{self, Instance},
+};
+use anyhow::{bail, format_err, Result};
+use async_trait::async_trait;
+use futures::future::join_all;
+use libra_crypto::{hash::CryptoHash, HashValue};
+use libra_json_rpc_client::{JsonRpcBatch, JsonRpcResponse};
+use libra_logger::{debug, error};
+use libra_types::{
+    account_config::testnet_dd_account_address,
+    proof::{TransactionAccumulatorInternalNode, TransactionAccumulatorProof},
+    transaction::TransactionInfo,
+};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Root hashes are compared at most this often, mismatches found are reported until the
+/// cluster is wiped
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Continuous safety check: transaction accumulator root hash at a version every instance has
+/// committed has to be the same on all validators and fullnodes which respond. Instances which
+/// are down are skipped, a mismatch means that ledger histories diverged
+pub struct AccumulatorHealthCheck {
+    cluster: Cluster,
+    last_check: Option<Instant>,
+    mismatches: HashMap<String, String>,
+}
+
+impl AccumulatorHealthCheck {
+    pub fn new(cluster: Cluster) -> Self {
+        Self {
+            cluster,
+            last_check: None,
+            mismatches: HashMap::new(),
+        }
+    }
+
+    async fn check(&mut self) {
+        let instances: Vec<_> = self
+            .cluster
+            .validator_and_fullnode_instances()
+            .cloned()
+            .collect();
+        let versions = join_all(instances.iter().map(Instance::latest_version)).await;
+        let version = match versions.into_iter().filter_map(Result::ok).min() {
+            Some(version) => version,
+            None => return,
+        };
+        let roots = join_all(
+            instances
+                .iter()
+                .map(|instance| accumulator_root(instance, version)),
+        )
+        .await;
+        let roots: Vec<_> = instances
+            .iter()
+            .zip(roots)
+            .filter_map(|(instance, root)| match root {
+                Ok(root) => Some((instance, root)),
+                Err(e) => {
+                    debug!("No root hash of {} at {}: {}", instance, version, e);
+                    None
+                }
+            })
+            .collect();
+        // Root of most validators is taken as the canonical one
+        let validators = instance::instancelist_to_set(self.cluster.validator_instances());
+        let mut counts: HashMap<HashValue, usize> = HashMap::new();
+        for (instance, root) in &roots {
+            if validators.contains(instance.peer_name()) {
+                *counts.entry(*root).or_default() += 1;
+            }
+        }
+        let canonical = match counts.into_iter().max_by_key(|(_, count)| *count) {
+            Some((root, _)) => root,
+            None => return,
+        };
+        for (instance, root) in roots {
+            if root != canonical {
+                let message = format!(
+                    "{} has accumulator root {:x} at version {}, most validators have {:x}",
+                    instance, root, version, canonical
+                );
+                error!("{}", message);
+                self.mismatches
+                    .insert(format!("val-{}", instance.validator_group().index), message);
+            }
+        }
+    }
+}
+
+/// Root hash of the transaction accumulator of `instance` as of `version`, computed from the
+/// proof of an account which exists on every chain
+async fn accumulator_root(instance: &Instance, version: u64) -> Result<HashValue> {
+    let mut batch = JsonRpcBatch::new();
+    batch.add_get_account_state_with_proof_request(
+        testnet_dd_account_address(),
+        Some(version),
+        Some(version),
+    );
+    let response = instance
+        .json_rpc_client()
+        .execute(batch)
+        .await
+        .map_err(|e| format_err!("get_account_state_with_proof failed: {:?}", e))?
+        .remove(0)?;
+    let view = match response {
+        JsonRpcResponse::AccountStateWithProofResponse(view) => view,
+        response => bail!(
+            "Unexpected response for get_account_state_with_proof: {:?}",
+            response
+        ),
+    };
+    let transaction_info: TransactionInfo =
+        lcs::from_bytes(&view.proof.transaction_info.into_bytes()?)?;
+    let proof: TransactionAccumulatorProof = lcs::from_bytes(
+        &view
+            .proof
+            .ledger_info_to_transaction_info_proof
+            .into_bytes()?,
+    )?;
+    let (root, _) = proof.siblings().iter().fold(
+        (transaction_info.hash(), version),
+        |(hash, index), sibling| {
+            let parent = if index % 2 == 0 {
+                TransactionAccumulatorInternalNode::new(hash, *sibling)
+            } else {
+                TransactionAccumulatorInternalNode::new(*sibling, hash)
+            };
+            (parent.hash(), index / 2)
+        },
+    );
+    Ok(root)
+}
+
+#[async_trait]
+impl HealthCheck for AccumulatorHealthCheck {
+    async fn verify(&mut self, ctx: &mut HealthCheckContext) {
+        let checked_recently = self
+            .last_check
+            .map_or(false, |t| t.elapsed() < CHECK_INTERVAL);
+        if !checked_recently {
+            self.last_check = Some(Instant::now());
+            self.check().await;
+        }
+        for (validator, message) in &self.mismatches {
+            ctx.report_failure(validator.clone(), message.clone());
+        }
+    }
+
+    fn clear(&mut self) {
+        self.mismatches.clear();
+        self.last_check = None;
+    }
+
+    fn name(&self) -> &'static str {
+        "accumulator_check"
+    }
+}
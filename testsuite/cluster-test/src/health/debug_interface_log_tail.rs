@@ -13,9 +13,11 @@ use debug_interface::AsyncNodeDebugClient;
 use libra_logger::{json_log::JsonLogEntry as DebugInterfaceEvent, *};
 use serde_json::{self, value as json};
 use std::{
+    collections::hash_map::DefaultHasher,
     env,
+    hash::{Hash, Hasher},
     sync::{
-        atomic::{AtomicBool, AtomicI64, Ordering},
+        atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
         mpsc, Arc, Mutex,
     },
     time::Duration,
@@ -30,6 +32,7 @@ pub struct DebugPortLogWorker {
     pending_messages: Arc<AtomicI64>,
     trace_sender: mpsc::Sender<(String, DebugInterfaceEvent)>,
     trace_enabled: Arc<AtomicBool>,
+    trace_sample_one_in: Arc<AtomicU64>,
 }
 
 impl DebugPortLogWorker {
@@ -40,6 +43,7 @@ impl DebugPortLogWorker {
         let pending_messages = Arc::new(AtomicI64::new(0));
         let (trace_sender, trace_receiver) = mpsc::channel();
         let trace_enabled = Arc::new(AtomicBool::new(false));
+        let trace_sample_one_in = Arc::new(AtomicU64::new(1));
         for instance in cluster.validator_and_fullnode_instances() {
             let (started_sender, started_receiver) = mpsc::channel();
             started_receivers.push(started_receiver);
@@ -52,6 +56,7 @@ impl DebugPortLogWorker {
                 pending_messages: pending_messages.clone(),
                 trace_sender: trace_sender.clone(),
                 trace_enabled: trace_enabled.clone(),
+                trace_sample_one_in: trace_sample_one_in.clone(),
             };
             runtime.spawn(debug_port_log_worker.run());
         }
@@ -67,6 +72,7 @@ impl DebugPortLogWorker {
             },
             TraceTail {
                 trace_enabled,
+                trace_sample_one_in,
                 trace_receiver: Mutex::new(trace_receiver),
             },
         )
@@ -109,7 +115,7 @@ impl DebugPortLogWorker {
         let e = if event.name == "committed" {
             Self::parse_commit(&event.json)
         } else {
-            if self.trace_enabled.load(Ordering::Relaxed) {
+            if self.trace_enabled.load(Ordering::Relaxed) && self.is_sampled(&event) {
                 let peer = self.instance.peer_name().clone();
                 let _ignore = self.trace_sender.send((peer, event));
             }
@@ -123,6 +129,24 @@ impl DebugPortLogWorker {
         })
     }
 
+    /// Every worker hashes transaction nodes the same way, so that all events of a sampled
+    /// transaction are kept whichever node logged them. Events of blocks are always kept,
+    /// since sampled transactions can end up in any block
+    fn is_sampled(&self, event: &DebugInterfaceEvent) -> bool {
+        let one_in = self.trace_sample_one_in.load(Ordering::Relaxed);
+        if one_in <= 1 {
+            return true;
+        }
+        match event.json.get("node").and_then(|v| v.as_str()) {
+            Some(node) if node.starts_with("txn::") => {
+                let mut hasher = DefaultHasher::new();
+                node.hash(&mut hasher);
+                hasher.finish() % one_in == 0
+            }
+            _ => true,
+        }
+    }
+
     fn parse_commit(json: &json::Value) -> Event {
         Event::Commit(Commit {
             commit: json
@@ -0,0 +1,157 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+use crate::{
+    cluster::Cluster,
+    health::{HealthCheck, HealthCheckContext},
+    prometheus::Prometheus,
+};
+use async_trait::async_trait;
+use libra_logger::{info, warn};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    time::{Duration, Instant},
+};
+
+/// Prometheus is queried at most this often, failures found are reported until next query
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Used when suite deadline is not known, e.g. for a single experiment
+const DEFAULT_HORIZON: Duration = Duration::from_secs(3600);
+
+/// Projects time until data volume of each validator is full from growth of its db over last
+/// 10 minutes. Validators which would run out of disk before the suite ends are reported, and
+/// cleaned up with `DISK_CLEANUP_CMD` if it is set
+pub struct DiskGrowthHealthCheck {
+    cluster: Cluster,
+    prometheus: Prometheus,
+    capacity_bytes: f64,
+    cleanup_cmd: Option<String>,
+    suite_deadline: Option<Instant>,
+    last_check: Option<Instant>,
+    at_risk: HashMap<String, String>,
+    cleaned_up: HashSet<String>,
+}
+
+impl DiskGrowthHealthCheck {
+    /// Enabled by `DISK_CAPACITY_GB`, size of data volume of validators
+    pub fn from_env(cluster: Cluster, prometheus: Prometheus) -> Option<Self> {
+        let capacity_gb: f64 = env::var("DISK_CAPACITY_GB")
+            .ok()?
+            .parse()
+            .expect("Failed to parse DISK_CAPACITY_GB");
+        Some(Self {
+            cluster,
+            prometheus,
+            capacity_bytes: capacity_gb * 1024.0 * 1024.0 * 1024.0,
+            cleanup_cmd: env::var("DISK_CLEANUP_CMD").ok(),
+            suite_deadline: None,
+            last_check: None,
+            at_risk: HashMap::new(),
+            cleaned_up: HashSet::new(),
+        })
+    }
+
+    /// Last value of query for each validator
+    fn query_last(&self, query: &str, now: Duration) -> HashMap<String, f64> {
+        let start = now - Duration::from_secs(120);
+        match self
+            .prometheus
+            .query_range(query.to_string(), &start, &now, 60)
+        {
+            Ok(response) => response
+                .time_series()
+                .iter()
+                .filter_map(|(peer_id, ts)| Some((peer_id.clone(), ts.get().last()?.1)))
+                .collect(),
+            Err(e) => {
+                warn!("Failed to query {}: {}", query, e);
+                HashMap::new()
+            }
+        }
+    }
+
+    async fn cleanup(&mut self, validator: &str) {
+        let cmd = match &self.cleanup_cmd {
+            Some(cmd) => cmd,
+            None => return,
+        };
+        if !self.cleaned_up.insert(validator.to_string()) {
+            return;
+        }
+        let instance = self
+            .cluster
+            .validator_instances()
+            .iter()
+            .find(|i| i.peer_name() == validator);
+        if let Some(instance) = instance {
+            info!("Running disk cleanup on {}", instance);
+            if let Err(e) = instance.exec(cmd, false).await {
+                warn!("Disk cleanup on {} failed: {}", instance, e);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl HealthCheck for DiskGrowthHealthCheck {
+    async fn verify(&mut self, ctx: &mut HealthCheckContext) {
+        let checked_recently = self
+            .last_check
+            .map_or(false, |t| t.elapsed() < CHECK_INTERVAL);
+        if !checked_recently {
+            self.last_check = Some(Instant::now());
+            let used = self.query_last(
+                "sum by (peer_id) (libra_storage_cf_size_bytes{peer_id=~\"val-.*\"})",
+                ctx.now(),
+            );
+            let growth = self.query_last(
+                "sum by (peer_id) (deriv(libra_storage_cf_size_bytes{peer_id=~\"val-.*\"}[10m]))",
+                ctx.now(),
+            );
+            let horizon = self.suite_deadline.map_or(DEFAULT_HORIZON, |deadline| {
+                deadline.saturating_duration_since(Instant::now())
+            });
+            self.at_risk.clear();
+            for (validator, used) in used {
+                let rate = growth.get(&validator).cloned().unwrap_or(0.0);
+                if rate <= 0.0 {
+                    continue;
+                }
+                let time_until_full = (self.capacity_bytes - used).max(0.0) / rate;
+                if time_until_full < horizon.as_secs_f64() {
+                    let message = format!(
+                        "disk predicted to be full in {:.0} secs, {:.1} GB used, growing {:.1} MB/s",
+                        time_until_full,
+                        used / 1024.0 / 1024.0 / 1024.0,
+                        rate / 1024.0 / 1024.0
+                    );
+                    self.at_risk.insert(validator, message);
+                }
+            }
+            let at_risk: Vec<_> = self.at_risk.keys().cloned().collect();
+            for validator in at_risk {
+                self.cleanup(&validator).await;
+            }
+        }
+        for (validator, message) in &self.at_risk {
+            ctx.report_failure(validator.clone(), message.clone());
+        }
+    }
+
+    fn set_suite_deadline(&mut self, deadline: Instant) {
+        self.suite_deadline = Some(deadline);
+    }
+
+    fn clear(&mut self) {
+        self.at_risk.clear();
+        self.cleaned_up.clear();
+        self.last_check = None;
+    }
+
+    fn name(&self) -> &'static str {
+        "disk_growth_check"
+    }
+}
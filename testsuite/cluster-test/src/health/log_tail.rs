@@ -7,7 +7,7 @@ use crate::{health::ValidatorEvent, util::unix_timestamp_now};
 use libra_logger::{json_log::JsonLogEntry as DebugInterfaceEvent, *};
 use std::{
     sync::{
-        atomic::{AtomicBool, AtomicI64, Ordering},
+        atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
         mpsc, Arc, Mutex,
     },
     thread,
@@ -22,6 +22,8 @@ pub struct LogTail {
 pub struct TraceTail {
     pub trace_receiver: Mutex<mpsc::Receiver<(String, DebugInterfaceEvent)>>,
     pub trace_enabled: Arc<AtomicBool>,
+    /// Events of one in this many transactions are captured
+    pub trace_sample_one_in: Arc<AtomicU64>,
 }
 
 impl LogTail {
@@ -67,13 +69,40 @@ impl LogTail {
 
 impl TraceTail {
     pub async fn capture_trace(&self, duration: Duration) -> Vec<(String, DebugInterfaceEvent)> {
+        self.capture_sampled_trace(duration, 1).await
+    }
+
+    /// Captures events of one in `one_in` transactions and all events of blocks, so that the
+    /// trace can cover a long window. Captured events are drained every second instead of
+    /// piling up in the channel
+    pub async fn capture_sampled_trace(
+        &self,
+        duration: Duration,
+        one_in: u64,
+    ) -> Vec<(String, DebugInterfaceEvent)> {
+        let deadline = Instant::now() + duration;
+        let mut events = vec![];
+        self.trace_sample_one_in
+            .store(one_in.max(1), Ordering::Relaxed);
         self.trace_enabled.store(true, Ordering::Relaxed);
-        tokio::time::delay_for(duration).await;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            tokio::time::delay_for(Duration::from_secs(1).min(deadline - now)).await;
+            self.drain_trace(&mut events);
+        }
         self.trace_enabled.store(false, Ordering::Relaxed);
-        let mut events = vec![];
-        while let Ok(event) = self.trace_receiver.lock().unwrap().try_recv() {
+        self.trace_sample_one_in.store(1, Ordering::Relaxed);
+        self.drain_trace(&mut events);
+        events
+    }
+
+    fn drain_trace(&self, events: &mut Vec<(String, DebugInterfaceEvent)>) {
+        let receiver = self.trace_receiver.lock().unwrap();
+        while let Ok(event) = receiver.try_recv() {
             events.push(event);
         }
-        events
     }
 }
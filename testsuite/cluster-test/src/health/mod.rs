@@ -3,23 +3,31 @@
 
 #![forbid(unsafe_code)]
 
+mod accumulator_check;
 mod commit_check;
 mod debug_interface_log_tail;
+mod disk_growth_check;
 mod fullnode_check;
 mod liveness_check;
 mod log_tail;
+mod progress_check;
+mod stale_read_check;
 
-use crate::{cluster::Cluster, util::unix_timestamp_now};
+use crate::{cluster::Cluster, tui::Dashboard, util::unix_timestamp_now};
+pub use accumulator_check::AccumulatorHealthCheck;
 use anyhow::{bail, Result};
 use async_trait::async_trait;
 pub use commit_check::CommitHistoryHealthCheck;
 pub use debug_interface_log_tail::DebugPortLogWorker;
+pub use disk_growth_check::DiskGrowthHealthCheck;
 pub use fullnode_check::FullNodeHealthCheck;
 use itertools::Itertools;
 pub use liveness_check::LivenessHealthCheck;
 pub use log_tail::{LogTail, TraceTail};
+pub use progress_check::ProgressHealthCheck;
+pub use stale_read_check::StaleReadHealthCheck;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
     env, fmt,
     iter::FromIterator,
     time::{Duration, Instant, SystemTime},
@@ -72,6 +80,8 @@ pub trait HealthCheck: Send {
     /// Clean is invoked when cluster is wiped
     /// This means that checks like commit history check should wipe internal state
     fn clear(&mut self) {}
+    /// Time by which currently running suite is expected to complete
+    fn set_suite_deadline(&mut self, _deadline: Instant) {}
 
     fn name(&self) -> &'static str;
 }
@@ -80,6 +90,8 @@ pub struct HealthCheckRunner {
     cluster: Cluster,
     health_checks: Vec<Box<dyn HealthCheck>>,
     debug: bool,
+    /// Shows health of validators instead of printing it
+    dashboard: Option<Dashboard>,
 }
 
 impl HealthCheckRunner {
@@ -88,22 +100,37 @@ impl HealthCheckRunner {
             cluster,
             health_checks,
             debug: env::var("HEALTH_CHECK_DEBUG").is_ok(),
+            dashboard: None,
         }
     }
 
+    pub fn set_dashboard(&mut self, dashboard: Dashboard) {
+        self.dashboard = Some(dashboard);
+    }
+
     pub fn new_all(cluster: Cluster) -> Self {
         let liveness_health_check = LivenessHealthCheck::new(&cluster);
         let fullnode_check = FullNodeHealthCheck::new(cluster.clone());
+        let stale_read_check = StaleReadHealthCheck::new(cluster.clone());
+        let progress_check = ProgressHealthCheck::new(cluster.clone());
+        let accumulator_check = AccumulatorHealthCheck::new(cluster.clone());
         Self::new(
             cluster,
             vec![
                 Box::new(CommitHistoryHealthCheck::new()),
                 Box::new(liveness_health_check),
                 Box::new(fullnode_check),
+                Box::new(stale_read_check),
+                Box::new(progress_check),
+                Box::new(accumulator_check),
             ],
         )
     }
 
+    pub fn add_health_check(&mut self, health_check: Box<dyn HealthCheck>) {
+        self.health_checks.push(health_check);
+    }
+
     /// Takes a list of affected_validators. If there are validators which failed
     /// which were not part of the experiment, then it returns an Err with a string
     /// of all the unexpected failures.
@@ -123,6 +150,7 @@ impl HealthCheckRunner {
 
         let mut context = HealthCheckContext::new();
         for health_check in self.health_checks.iter_mut() {
+            context.check = health_check.name();
             let start = Instant::now();
             for event in events {
                 health_check.on_event(event, &mut context);
@@ -141,11 +169,19 @@ impl HealthCheckRunner {
                 ));
             }
         }
+        let mut failed_checks: HashMap<String, BTreeSet<&'static str>> = HashMap::new();
         for err in context.err_acc {
             node_health.insert(err.validator.clone(), false);
+            failed_checks
+                .entry(err.validator.clone())
+                .or_default()
+                .insert(err.check);
             messages.push(format!("{} {:?}", unix_timestamp_now().as_millis(), err));
         }
 
+        if let Some(dashboard) = &self.dashboard {
+            dashboard.set_health(node_health.clone().into_iter().sorted().collect());
+        }
         let mut failed = vec![];
         let mut validators_message = "".to_string();
         for (i, (node, healthy)) in node_health.into_iter().sorted().enumerate() {
@@ -167,15 +203,23 @@ impl HealthCheckRunner {
         let failed_set: HashSet<&String> = HashSet::from_iter(failed.iter());
         let has_unexpected_failures = !failed_set.is_subset(&affected_validators_set_refs);
 
-        if print_failures.should_print(has_unexpected_failures) {
+        if self.dashboard.is_none() && print_failures.should_print(has_unexpected_failures) {
             messages.iter().for_each(|m| println!("{}", m));
         }
 
         if has_unexpected_failures {
-            let unexpected_failures = failed_set
+            let validators: Vec<_> = failed_set
                 .difference(&affected_validators_set_refs)
-                .join(",");
-            bail!(unexpected_failures);
+                .map(|validator| validator.to_string())
+                .sorted()
+                .collect();
+            let checks = validators
+                .iter()
+                .filter_map(|validator| failed_checks.get(validator))
+                .flatten()
+                .copied()
+                .collect();
+            bail!(UnhealthyValidators { validators, checks });
         }
         Ok(failed)
     }
@@ -191,8 +235,30 @@ impl HealthCheckRunner {
             hc.clear();
         }
     }
+
+    pub fn set_suite_deadline(&mut self, deadline: Instant) {
+        for hc in self.health_checks.iter_mut() {
+            hc.set_suite_deadline(deadline);
+        }
+    }
 }
 
+/// Error of `HealthCheckRunner::run` when validators outside of the experiment failed
+#[derive(Debug)]
+pub struct UnhealthyValidators {
+    pub validators: Vec<String>,
+    /// Names of health checks these validators failed
+    pub checks: BTreeSet<&'static str>,
+}
+
+impl fmt::Display for UnhealthyValidators {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.validators.join(","))
+    }
+}
+
+impl std::error::Error for UnhealthyValidators {}
+
 pub enum PrintFailures {
     None,
     UnexpectedOnly,
@@ -211,12 +277,15 @@ impl PrintFailures {
 
 pub struct HealthCheckContext {
     now: Duration,
+    /// Name of the health check being run, failures are attributed to it
+    check: &'static str,
     err_acc: Vec<HealthCheckError>,
 }
 
 #[derive(Debug)]
 pub struct HealthCheckError {
     pub validator: String,
+    pub check: &'static str,
     pub message: String,
 }
 
@@ -227,6 +296,7 @@ impl HealthCheckContext {
             .expect("Now is behind UNIX_EPOCH");
         Self {
             now,
+            check: "",
             err_acc: vec![],
         }
     }
@@ -236,7 +306,11 @@ impl HealthCheckContext {
     }
 
     pub fn report_failure(&mut self, validator: String, message: String) {
-        self.err_acc.push(HealthCheckError { validator, message })
+        self.err_acc.push(HealthCheckError {
+            validator,
+            check: self.check,
+            message,
+        })
     }
 }
 
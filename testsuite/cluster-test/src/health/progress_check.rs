@@ -0,0 +1,152 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+use crate::{
+    cluster::Cluster,
+    health::{HealthCheck, HealthCheckContext},
+    instance::Instance,
+};
+use async_trait::async_trait;
+use futures::future::join_all;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Validators are polled at most this often, failures found are reported until next poll
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+const MAX_STALL: Duration = Duration::from_secs(120);
+
+/// Committed version from JSON-RPC and current round from debug interface of a validator
+struct Progress {
+    version: u64,
+    round: Option<i64>,
+}
+
+struct ValidatorProgress {
+    /// None until the validator is polled successfully
+    version: Option<u64>,
+    round: Option<i64>,
+    /// Time version last grew
+    since: Instant,
+}
+
+/// Polls committed version of each validator directly, instead of relying on logs or
+/// Prometheus, so that stalled validators are detected even when those pipelines are degraded.
+/// Only committed version counts as progress, rounds also advance on timeouts without commits,
+/// and the round is only reported. Failed polls are failures only once the validator stalled
+/// for too long, so that a single slow response does not fail the run
+pub struct ProgressHealthCheck {
+    cluster: Cluster,
+    last_check: Option<Instant>,
+    progress: HashMap<String, ValidatorProgress>,
+    failures: HashMap<String, String>,
+}
+
+impl ProgressHealthCheck {
+    pub fn new(cluster: Cluster) -> Self {
+        Self {
+            cluster,
+            last_check: None,
+            progress: HashMap::new(),
+            failures: HashMap::new(),
+        }
+    }
+
+    async fn check_progress(&mut self) {
+        let futures = self
+            .cluster
+            .validator_instances()
+            .iter()
+            .map(|instance| async move { (instance.peer_name().clone(), poll(instance).await) });
+        let now = Instant::now();
+        self.failures.clear();
+        for (validator, result) in join_all(futures).await {
+            let last = self
+                .progress
+                .entry(validator.clone())
+                .or_insert(ValidatorProgress {
+                    version: None,
+                    round: None,
+                    since: now,
+                });
+            let error = match result {
+                Ok(progress) => {
+                    last.round = progress.round;
+                    if last
+                        .version
+                        .map_or(true, |version| progress.version > version)
+                    {
+                        last.version = Some(progress.version);
+                        last.since = now;
+                        continue;
+                    }
+                    None
+                }
+                Err(e) => Some(e),
+            };
+            let stalled = now.duration_since(last.since);
+            if stalled <= MAX_STALL {
+                continue;
+            }
+            let mut message = match last.version {
+                Some(version) => format!(
+                    "no progress for {} secs at version {}, round {:?}",
+                    stalled.as_secs(),
+                    version,
+                    last.round
+                ),
+                None => format!("progress not available for {} secs", stalled.as_secs()),
+            };
+            if let Some(e) = error {
+                message.push_str(&format!(", last poll failed: {}", e));
+            }
+            self.failures.insert(validator, message);
+        }
+    }
+}
+
+/// Round is left out when debug interface of the validator is not reachable
+async fn poll(instance: &Instance) -> anyhow::Result<Progress> {
+    let version = instance.latest_version().await?;
+    let round = instance
+        .debug_interface_client()
+        .get_node_metric("libra_consensus_current_round{}")
+        .await
+        .ok()
+        .flatten();
+    Ok(Progress { version, round })
+}
+
+#[async_trait]
+impl HealthCheck for ProgressHealthCheck {
+    async fn verify(&mut self, ctx: &mut HealthCheckContext) {
+        let checked_recently = self
+            .last_check
+            .map_or(false, |t| t.elapsed() < CHECK_INTERVAL);
+        if !checked_recently {
+            self.last_check = Some(Instant::now());
+            self.check_progress().await;
+        }
+        for (validator, message) in &self.failures {
+            ctx.report_failure(validator.clone(), message.clone());
+        }
+    }
+
+    fn invalidate(&mut self, validator: &str) {
+        self.progress.remove(validator);
+        self.failures.remove(validator);
+    }
+
+    fn clear(&mut self) {
+        self.progress.clear();
+        self.failures.clear();
+        self.last_check = None;
+    }
+
+    fn name(&self) -> &'static str {
+        "progress_check"
+    }
+}
@@ -0,0 +1,181 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+use crate::{
+    cluster::Cluster,
+    health::{HealthCheck, HealthCheckContext},
+    instance::Instance,
+};
+use anyhow::{bail, format_err, Result};
+use async_trait::async_trait;
+use futures::future::join_all;
+use libra_json_rpc_types::views::{
+    JSONRPC_LIBRA_LEDGER_TIMESTAMPUSECS, JSONRPC_LIBRA_LEDGER_VERSION,
+};
+use libra_types::account_config::testnet_dd_account_address;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Fullnodes which are further behind validators than this are considered catching up
+const CATCH_UP_LAG: i64 = 100;
+/// Reads are checked at most this often, failures found are reported until next check
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// While a fullnode is catching up, reads it serves must carry ledger version and timestamp,
+/// so that clients can tell how stale they are, and must not claim a version beyond what the
+/// fullnode has actually synced
+pub struct StaleReadHealthCheck {
+    cluster: Cluster,
+    last_check: Option<Instant>,
+    stale: HashMap<String, String>,
+}
+
+impl StaleReadHealthCheck {
+    pub fn new(cluster: Cluster) -> Self {
+        Self {
+            cluster,
+            last_check: None,
+            stale: HashMap::new(),
+        }
+    }
+
+    async fn check_catching_up(&self) -> HashMap<String, String> {
+        let futures = self
+            .cluster
+            .validator_instances()
+            .iter()
+            .map(committed_version);
+        let validator_version = join_all(futures)
+            .await
+            .into_iter()
+            .filter_map(Result::ok)
+            .max()
+            .unwrap_or(0);
+        let futures = self
+            .cluster
+            .fullnode_instances()
+            .iter()
+            .map(|fullnode| async move {
+                match committed_version(fullnode).await {
+                    Ok(version) if validator_version - version > CATCH_UP_LAG => {
+                        Some((fullnode, check_reads(fullnode).await))
+                    }
+                    _ => None,
+                }
+            });
+        let mut stale = HashMap::new();
+        for (fullnode, result) in join_all(futures).await.into_iter().flatten() {
+            if let Err(e) = result {
+                stale.insert(
+                    format!("val-{}", fullnode.validator_group().index),
+                    format!("fullnode {} served stale read: {}", fullnode.peer_name(), e),
+                );
+            }
+        }
+        stale
+    }
+}
+
+async fn committed_version(instance: &Instance) -> Result<i64> {
+    instance
+        .debug_interface_client()
+        .get_node_metric("libra_state_sync_committed_version{}")
+        .await?
+        .ok_or_else(|| format_err!("No committed version metric"))
+}
+
+/// Reads metadata and an account, and validates ledger version of every response against
+/// version committed by the fullnode after the response was served
+async fn check_reads(fullnode: &Instance) -> Result<()> {
+    let request = json!([
+        {"jsonrpc": "2.0", "method": "get_metadata", "params": [], "id": 0},
+        {
+            "jsonrpc": "2.0",
+            "method": "get_account",
+            "params": [testnet_dd_account_address().to_string()],
+            "id": 1
+        },
+    ]);
+    let responses: Vec<Value> = fullnode
+        .json_rpc_http_client()
+        .post(fullnode.json_rpc_url())
+        .json(&request)
+        .send()
+        .await?
+        .json()
+        .await?;
+    let committed = committed_version(fullnode).await?;
+    for response in responses {
+        let method = match response["id"].as_u64() {
+            Some(0) => "get_metadata",
+            _ => "get_account",
+        };
+        let ledger_version = match response[JSONRPC_LIBRA_LEDGER_VERSION].as_i64() {
+            Some(version) => version,
+            None => bail!(
+                "{} response without {}",
+                method,
+                JSONRPC_LIBRA_LEDGER_VERSION
+            ),
+        };
+        if response[JSONRPC_LIBRA_LEDGER_TIMESTAMPUSECS]
+            .as_u64()
+            .is_none()
+        {
+            bail!(
+                "{} response without {}",
+                method,
+                JSONRPC_LIBRA_LEDGER_TIMESTAMPUSECS
+            );
+        }
+        if ledger_version > committed {
+            bail!(
+                "{} response claims ledger version {} while only {} is synced",
+                method,
+                ledger_version,
+                committed
+            );
+        }
+        if let Some(version) = response["result"]["version"].as_i64() {
+            if version != ledger_version {
+                bail!(
+                    "{} returned version {} for ledger version {}",
+                    method,
+                    version,
+                    ledger_version
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl HealthCheck for StaleReadHealthCheck {
+    async fn verify(&mut self, ctx: &mut HealthCheckContext) {
+        let checked_recently = self
+            .last_check
+            .map_or(false, |t| t.elapsed() < CHECK_INTERVAL);
+        if !checked_recently {
+            self.last_check = Some(Instant::now());
+            self.stale = self.check_catching_up().await;
+        }
+        for (validator, message) in &self.stale {
+            ctx.report_failure(validator.clone(), message.clone());
+        }
+    }
+
+    fn clear(&mut self) {
+        self.stale.clear();
+        self.last_check = None;
+    }
+
+    fn name(&self) -> &'static str {
+        "stale_read_check"
+    }
+}
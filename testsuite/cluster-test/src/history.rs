@@ -0,0 +1,189 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// History keeps metrics of every run as a json file in results directory and serves a
+/// dashboard with trends of each metric of each experiment across runs
+use crate::{
+    report::{ReportedMetric, RunMetadata, SuiteReport},
+    util::unix_timestamp_now,
+};
+use anyhow::{format_err, Result};
+use libra_logger::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, net::SocketAddr, path::PathBuf, time::Duration};
+use warp::Filter;
+
+const DASHBOARD_HTML: &str = include_str!("history.html");
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RunRecord {
+    /// Unix timestamp of the end of the run in secs
+    pub timestamp: u64,
+    pub tag: String,
+    /// Not present in records saved before metadata was captured
+    #[serde(default)]
+    pub metadata: Option<RunMetadata>,
+    pub metrics: Vec<ReportedMetric>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Series {
+    pub experiment: String,
+    pub metric: String,
+    pub points: Vec<Point>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Point {
+    pub timestamp: u64,
+    pub tag: String,
+    pub value: f64,
+}
+
+#[derive(Clone)]
+pub struct ResultsStore {
+    dir: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    days: Option<u64>,
+}
+
+impl ResultsStore {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn record(&self, tag: &str, report: &SuiteReport) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format_err!("Failed to create {:?}: {}", self.dir, e))?;
+        let timestamp = unix_timestamp_now().as_secs();
+        let record = RunRecord {
+            timestamp,
+            tag: tag.to_string(),
+            metadata: report.metadata().cloned(),
+            metrics: report.metrics().to_vec(),
+        };
+        let path = self.dir.join(format!("{}-{}.json", timestamp, tag));
+        fs::write(&path, serde_json::to_string(&record)?)
+            .map_err(|e| format_err!("Failed to write {:?}: {}", path, e))?;
+        info!("Saved run results to {:?}", path);
+        Ok(())
+    }
+
+    /// Runs which ended before `since` are skipped, unreadable files are skipped with warning
+    pub fn load(&self, since: Duration) -> Result<Vec<RunRecord>> {
+        let mut records = vec![];
+        let entries = fs::read_dir(&self.dir)
+            .map_err(|e| format_err!("Failed to read {:?}: {}", self.dir, e))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().map_or(true, |e| e != "json") {
+                continue;
+            }
+            let record = fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|s| serde_json::from_str::<RunRecord>(&s).map_err(Into::into));
+            match record {
+                Ok(record) if record.timestamp >= since.as_secs() => records.push(record),
+                Ok(_) => {}
+                Err(e) => warn!("Failed to load {:?}: {}", path, e),
+            }
+        }
+        records.sort_by_key(|r| r.timestamp);
+        Ok(records)
+    }
+
+    /// Serves dashboard at `/` and series of last `days` (30 by default) at `/api/history`
+    pub async fn serve(self, address: SocketAddr) {
+        let index = warp::path::end().map(|| warp::reply::html(DASHBOARD_HTML));
+        let history = warp::path!("api" / "history")
+            .and(warp::query::<HistoryQuery>())
+            .map(move |query: HistoryQuery| {
+                let window = Duration::from_secs(query.days.unwrap_or(30) * 24 * 3600);
+                let since = unix_timestamp_now().checked_sub(window).unwrap_or_default();
+                match self.load(since) {
+                    Ok(records) => warp::reply::json(&series(&records)),
+                    Err(e) => {
+                        warn!("{}", e);
+                        warp::reply::json(&Vec::<Series>::new())
+                    }
+                }
+            });
+        info!("Serving run history at http://{}", address);
+        warp::serve(warp::get().and(index.or(history)))
+            .run(address)
+            .await;
+    }
+}
+
+/// Groups metrics of all runs by experiment and metric name, points are ordered by time
+pub fn series(records: &[RunRecord]) -> Vec<Series> {
+    let mut series: BTreeMap<(&str, &str), Vec<Point>> = BTreeMap::new();
+    for record in records {
+        for metric in &record.metrics {
+            series
+                .entry((&metric.experiment, &metric.metric))
+                .or_default()
+                .push(Point {
+                    timestamp: record.timestamp,
+                    tag: record.tag.clone(),
+                    value: metric.value,
+                });
+        }
+    }
+    series
+        .into_iter()
+        .map(|((experiment, metric), mut points)| {
+            points.sort_by_key(|p| p.timestamp);
+            Series {
+                experiment: experiment.to_string(),
+                metric: metric.to_string(),
+                points,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn metric(experiment: &str, metric: &str, value: f64) -> ReportedMetric {
+        ReportedMetric {
+            experiment: experiment.to_string(),
+            metric: metric.to_string(),
+            value,
+        }
+    }
+
+    #[test]
+    fn test_series() {
+        let records = vec![
+            RunRecord {
+                timestamp: 20,
+                tag: "b".to_string(),
+                metadata: None,
+                metrics: vec![metric("all up", "avg_tps", 900.0)],
+            },
+            RunRecord {
+                timestamp: 10,
+                tag: "a".to_string(),
+                metadata: None,
+                metrics: vec![
+                    metric("all up", "avg_tps", 1000.0),
+                    metric("all up", "p99_latency", 2000.0),
+                ],
+            },
+        ];
+        let series = series(&records);
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0].metric, "avg_tps");
+        let values: Vec<_> = series[0].points.iter().map(|p| p.value).collect();
+        assert_eq!(values, vec![1000.0, 900.0]);
+        assert_eq!(series[1].points.len(), 1);
+    }
+}
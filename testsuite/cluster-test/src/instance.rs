@@ -3,22 +3,36 @@
 
 #![forbid(unsafe_code)]
 
-use crate::cluster_swarm::cluster_swarm_kube::ClusterSwarmKube;
-use anyhow::{format_err, Result};
+use crate::{
+    audit, cluster_swarm::cluster_swarm_kube::ClusterSwarmKube, tx_emitter::EmitTarget,
+    util::unix_timestamp_now,
+};
+use anyhow::{bail, format_err, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use debug_interface::AsyncNodeDebugClient;
 use libra_config::config::NodeConfig;
-use libra_json_rpc_client::{JsonRpcAsyncClient, JsonRpcBatch};
-use reqwest::{Client, Url};
-use serde_json::Value;
+use libra_json_rpc_client::{JsonRpcAsyncClient, JsonRpcBatch, JsonRpcResponse};
+use libra_types::account_address::AccountAddress;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, DATE},
+    Certificate, Client, Identity, Url,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
 use std::{
     collections::HashSet,
-    fmt,
+    fmt, fs,
     process::Stdio,
     str::FromStr,
     time::{Duration, Instant},
 };
+use structopt::StructOpt;
 use tokio::{process::Command, time};
 
+const CLOCK_OFFSET_MAX_PROBES: usize = 40;
+const CLOCK_OFFSET_PROBE_INTERVAL: Duration = Duration::from_millis(50);
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct ValidatorGroup {
     pub index: u32,
@@ -58,6 +72,8 @@ pub struct ValidatorConfig {
     pub config_overrides: Vec<String>,
     pub seed_peer_ip: String,
     pub safety_rules_addr: Option<String>,
+    /// jemalloc options of libra-node, set as MALLOC_CONF, e.g. to enable heap profiling
+    pub malloc_conf: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -78,11 +94,98 @@ pub struct Instance {
     debug_interface_port: Option<u32>,
     http_client: Client,
     backend: InstanceBackend,
+    json_rpc_endpoint: Option<JsonRpcEndpoint>,
+    /// Failure domain, e.g. availability zone, instance is running in if known
+    zone: Option<String>,
+    /// Capacity relative to other instances, e.g. 2 for a host twice as big, if known
+    capacity: Option<u64>,
+}
+
+/// JSON-RPC endpoint which is not plain http on ip:ac_port, e.g. https ingress
+#[derive(Clone)]
+struct JsonRpcEndpoint {
+    url: Url,
+    client: Client,
+}
+
+/// How JSON-RPC endpoints are reached, can be set for all instances with command line flags
+/// or per instance in inventory file
+#[derive(Clone, Debug, Default, Deserialize, StructOpt)]
+pub struct JsonRpcEndpointConfig {
+    /// Full url of the endpoint, only set per instance
+    #[structopt(skip)]
+    #[serde(default)]
+    pub url: Option<String>,
+    #[structopt(long = "json-rpc-https", help = "Use https for JSON-RPC endpoints")]
+    #[serde(default)]
+    pub https: bool,
+    #[structopt(
+        long = "json-rpc-ca-cert",
+        help = "PEM file with CA certificate used to verify JSON-RPC endpoints"
+    )]
+    pub ca_cert: Option<String>,
+    #[structopt(
+        long = "json-rpc-client-identity",
+        help = "PEM file with client certificate and private key for JSON-RPC endpoints"
+    )]
+    pub client_identity: Option<String>,
+    #[structopt(
+        long = "json-rpc-api-key",
+        help = "API key sent with every JSON-RPC request"
+    )]
+    pub api_key: Option<String>,
+    #[structopt(
+        long = "json-rpc-api-key-header",
+        default_value = "x-api-key",
+        help = "Header used to send API key"
+    )]
+    #[serde(default = "default_api_key_header")]
+    pub api_key_header: String,
+}
+
+fn default_api_key_header() -> String {
+    "x-api-key".to_string()
+}
+
+impl JsonRpcEndpointConfig {
+    /// Whether endpoint differs from plain http on ip:ac_port
+    pub fn is_set(&self) -> bool {
+        self.url.is_some()
+            || self.https
+            || self.ca_cert.is_some()
+            || self.client_identity.is_some()
+            || self.api_key.is_some()
+    }
+
+    fn build_client(&self) -> Result<Client> {
+        let mut builder = Client::builder();
+        if let Some(ca_cert) = &self.ca_cert {
+            let pem = fs::read(ca_cert)
+                .map_err(|e| format_err!("Failed to read CA certificate {}: {}", ca_cert, e))?;
+            builder = builder.add_root_certificate(Certificate::from_pem(&pem)?);
+        }
+        if let Some(client_identity) = &self.client_identity {
+            let pem = fs::read(client_identity).map_err(|e| {
+                format_err!("Failed to read client identity {}: {}", client_identity, e)
+            })?;
+            builder = builder.identity(Identity::from_pem(&pem)?);
+        }
+        if let Some(api_key) = &self.api_key {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                HeaderName::from_bytes(self.api_key_header.as_bytes())?,
+                HeaderValue::from_str(api_key)?,
+            );
+            builder = builder.default_headers(headers);
+        }
+        Ok(builder.build()?)
+    }
 }
 
 #[derive(Clone)]
 enum InstanceBackend {
     K8S(K8sInstanceInfo),
+    Ssh(SshInstanceInfo),
     Swarm,
 }
 
@@ -93,6 +196,15 @@ struct K8sInstanceInfo {
     kube: ClusterSwarmKube,
 }
 
+/// Instance managed over ssh, libra-node is expected to run as systemd service on the host
+#[derive(Clone)]
+pub struct SshInstanceInfo {
+    pub ssh_user: String,
+    pub service: String,
+    pub data_dir: String,
+    pub instance_config: InstanceConfig,
+}
+
 impl ValidatorGroup {
     pub fn new_for_index(index: u32) -> ValidatorGroup {
         Self {
@@ -109,6 +221,26 @@ impl ValidatorGroup {
     }
 }
 
+/// Overrides are applied by the node entrypoint script, which splits them on commas and
+/// replaces the value of lines `  KEY:` of the node config, i.e. keys directly under a section.
+/// Keys with a path like `mempool.capacity` are never matched, so they are rejected here
+pub fn validate_config_overrides(overrides: &[String]) -> Result<()> {
+    for o in overrides {
+        let mut parts = o.splitn(2, '=');
+        let key = parts.next().unwrap_or("");
+        if parts.next().is_none() || o.contains(',') {
+            bail!("Config override {} is not in key=value format", o);
+        }
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            bail!(
+                "Config override {} can not be applied, key must be a plain field name",
+                o
+            );
+        }
+    }
+    Ok(())
+}
+
 impl InstanceConfig {
     pub fn replace_tag(&mut self, new_tag: String) -> Result<()> {
         match &mut self.application_config {
@@ -130,6 +262,57 @@ impl InstanceConfig {
         Ok(())
     }
 
+    /// Adds node config overrides in `key=value` format, replacing existing overrides of the
+    /// same keys
+    pub fn add_config_overrides(&mut self, overrides: &[String]) -> Result<()> {
+        validate_config_overrides(overrides)?;
+        let config_overrides = match &mut self.application_config {
+            ApplicationConfig::Validator(c) => &mut c.config_overrides,
+            ApplicationConfig::Fullnode(c) => &mut c.config_overrides,
+            ApplicationConfig::LSR(..) | ApplicationConfig::Vault(..) => {
+                bail!("Config overrides are only supported for validators and fullnodes")
+            }
+        };
+        let key = |o: &str| o.split('=').next().unwrap_or("").to_string();
+        let new_keys: HashSet<_> = overrides.iter().map(|o| key(o)).collect();
+        config_overrides.retain(|o| !new_keys.contains(&key(o)));
+        config_overrides.extend(overrides.iter().cloned());
+        Ok(())
+    }
+
+    pub fn config_overrides(&self) -> &[String] {
+        match &self.application_config {
+            ApplicationConfig::Validator(c) => &c.config_overrides,
+            ApplicationConfig::Fullnode(c) => &c.config_overrides,
+            ApplicationConfig::LSR(..) | ApplicationConfig::Vault(..) => &[],
+        }
+    }
+
+    /// Only validators take jemalloc options
+    pub fn set_malloc_conf(&mut self, malloc_conf: Option<String>) -> Result<()> {
+        match &mut self.application_config {
+            ApplicationConfig::Validator(c) => c.malloc_conf = malloc_conf,
+            _ => bail!("jemalloc options are only supported for validators"),
+        }
+        Ok(())
+    }
+
+    pub fn malloc_conf(&self) -> Option<&str> {
+        match &self.application_config {
+            ApplicationConfig::Validator(c) => c.malloc_conf.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn image_tag(&self) -> Option<&str> {
+        match &self.application_config {
+            ApplicationConfig::Validator(c) => Some(&c.image_tag),
+            ApplicationConfig::Fullnode(c) => Some(&c.image_tag),
+            ApplicationConfig::LSR(c) => Some(&c.image_tag),
+            ApplicationConfig::Vault(..) => None,
+        }
+    }
+
     pub fn pod_name(&self) -> String {
         match &self.application_config {
             ApplicationConfig::Validator(_) => match self.validator_group.twin_index {
@@ -169,6 +352,9 @@ impl Instance {
             backend,
             debug_interface_port,
             http_client,
+            json_rpc_endpoint: None,
+            zone: None,
+            capacity: None,
         }
     }
 
@@ -197,9 +383,70 @@ impl Instance {
             ),
             http_client,
             backend,
+            json_rpc_endpoint: None,
+            zone: None,
+            capacity: None,
         }
     }
 
+    pub fn new_ssh(
+        ip: String,
+        ac_port: u32,
+        debug_interface_port: u32,
+        ssh_info: SshInstanceInfo,
+        http_client: Client,
+    ) -> Instance {
+        Instance {
+            peer_name: ssh_info.instance_config.pod_name(),
+            ip,
+            ac_port,
+            debug_interface_port: Some(debug_interface_port),
+            http_client,
+            backend: InstanceBackend::Ssh(ssh_info),
+            json_rpc_endpoint: None,
+            zone: None,
+            capacity: None,
+        }
+    }
+
+    pub fn with_zone(self, zone: Option<String>) -> Self {
+        Self { zone, ..self }
+    }
+
+    pub fn with_capacity(self, capacity: Option<u64>) -> Self {
+        Self { capacity, ..self }
+    }
+
+    /// Reaches JSON-RPC endpoint of this instance as described by `config`
+    pub fn with_json_rpc_endpoint(self, config: &JsonRpcEndpointConfig) -> Result<Self> {
+        if !config.is_set() {
+            return Ok(self);
+        }
+        let url = match &config.url {
+            Some(url) => url.clone(),
+            None => format!(
+                "{}://{}:{}/v1",
+                if config.https { "https" } else { "http" },
+                self.ip,
+                self.ac_port
+            ),
+        };
+        let json_rpc_endpoint = JsonRpcEndpoint {
+            url: Url::from_str(&url)
+                .map_err(|e| format_err!("Invalid JSON-RPC url {}: {}", url, e))?,
+            client: config.build_client()?,
+        };
+        Ok(Self {
+            json_rpc_endpoint: Some(json_rpc_endpoint),
+            ..self
+        })
+    }
+
+    /// Whether JSON-RPC is reached through an endpoint set with `with_json_rpc_endpoint`
+    pub fn has_json_rpc_endpoint(&self) -> bool {
+        self.json_rpc_endpoint.is_some()
+    }
+
     pub fn counter(&self, counter: &str) -> Result<f64> {
         let response: Value =
             reqwest::blocking::get(format!("http://{}:9101/counters", self.ip).as_str())?.json()?;
@@ -227,6 +474,108 @@ impl Instance {
         Ok(())
     }
 
+    pub async fn try_metrics(&self) -> Result<()> {
+        self.http_client
+            .get(&format!("http://{}:9101/metrics", self.ip))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Checks that libra-node is installed where this instance runs it from
+    pub async fn check_node_binary(&self) -> Result<()> {
+        let command = match self.ssh_backend() {
+            Some(ssh) => format!("systemctl cat {} > /dev/null", shell_quote(&ssh.service)),
+            None => "test -x /opt/libra/bin/libra-node".to_string(),
+        };
+        self.exec(&command, true).await
+    }
+
+    /// Returns latest ledger version known to this instance
+    pub async fn latest_version(&self) -> Result<u64> {
+        let mut batch = JsonRpcBatch::new();
+        batch.add_get_metadata_request(None);
+        let mut responses = self.json_rpc_client().execute(batch).await?;
+        match responses.remove(0)? {
+            JsonRpcResponse::BlockMetadataResponse(metadata) => Ok(metadata.version),
+            other => bail!(
+                "Unexpected response for get_metadata from {}: {:?}",
+                self,
+                other
+            ),
+        }
+    }
+
+    /// Account address of the validator running on this instance, which is its peer id in the
+    /// validator network. Read from node config, so that it is not available for validators
+    /// which keep their identity in secure storage
+    pub async fn validator_address(&self) -> Result<AccountAddress> {
+        let output = self
+            .exec_output(
+                "awk '/^validator_network:/ {v = 1} v && /peer_id:/ {print $2; exit}' /opt/libra/etc/node.yaml",
+            )
+            .await?;
+        let peer_id = output.trim().trim_matches(|c| c == '"' || c == '\'');
+        if peer_id.is_empty() {
+            bail!("Validator peer id of {} is not in its node config", self);
+        }
+        peer_id
+            .parse()
+            .map_err(|e| format_err!("Failed to parse peer id {} of {}: {}", peer_id, self, e))
+    }
+
+    /// Mempool capacity in node config of this instance, which has the default one if the
+    /// config does not set it
+    pub async fn mempool_capacity(&self) -> Result<usize> {
+        let output = self
+            .exec_output(
+                "awk '/^mempool:/ {m = 1; next} m && /^[^ ]/ {exit} m && /^  capacity:/ {print $2; exit}' /opt/libra/etc/node.yaml",
+            )
+            .await?;
+        let capacity = output.trim();
+        if capacity.is_empty() {
+            return Ok(NodeConfig::default().mempool.capacity);
+        }
+        capacity.parse().map_err(|e| {
+            format_err!(
+                "Failed to parse mempool capacity {} of {}: {}",
+                capacity,
+                self,
+                e
+            )
+        })
+    }
+
+    /// Estimates offset of this instance clock from local clock in milliseconds, positive if
+    /// instance clock is ahead. HTTP Date header only has seconds resolution, so JSON-RPC
+    /// endpoint is polled until the second changes and the change is placed between the two
+    /// requests which observed it, as done by htpdate
+    pub async fn estimate_clock_offset(&self) -> Result<i64> {
+        let client = self.json_rpc_http_client();
+        let url = self.json_rpc_url();
+        let mut prev: Option<(i64, i64)> = None;
+        for _ in 0..CLOCK_OFFSET_MAX_PROBES {
+            let sent = unix_timestamp_now().as_millis() as i64;
+            let response = client.post(url.clone()).json(&json!([])).send().await?;
+            let received = unix_timestamp_now().as_millis() as i64;
+            let date = response
+                .headers()
+                .get(DATE)
+                .ok_or_else(|| format_err!("{} did not return Date header", self))?;
+            let server_secs = DateTime::parse_from_rfc2822(date.to_str()?)?.timestamp();
+            let midpoint = (sent + received) / 2;
+            if let Some((prev_secs, prev_midpoint)) = prev {
+                if server_secs > prev_secs {
+                    return Ok(server_secs * 1000 - (prev_midpoint + midpoint) / 2);
+                }
+            }
+            prev = Some((server_secs, midpoint));
+            time::delay_for(CLOCK_OFFSET_PROBE_INTERVAL).await;
+        }
+        bail!("Clock of {} did not advance while probing", self)
+    }
+
     pub async fn wait_json_rpc(&self, deadline: Instant) -> Result<()> {
         while self.try_json_rpc().await.is_err() {
             if Instant::now() > deadline {
@@ -242,7 +591,16 @@ impl Instance {
     }
 
     pub fn validator_group(&self) -> ValidatorGroup {
-        self.k8s_backend().instance_config.validator_group.clone()
+        self.instance_config().validator_group.clone()
+    }
+
+    /// Name of k8s node this instance is running on
+    pub fn k8s_node(&self) -> &String {
+        &self.k8s_backend().k8s_node
+    }
+
+    pub fn zone(&self) -> Option<&str> {
+        self.zone.as_deref()
     }
 
     pub fn ip(&self) -> &String {
@@ -254,6 +612,9 @@ impl Instance {
     }
 
     pub fn json_rpc_url(&self) -> Url {
+        if let Some(endpoint) = &self.json_rpc_endpoint {
+            return endpoint.url.clone();
+        }
         Url::from_str(&format!("http://{}:{}/v1", self.ip(), self.ac_port())).expect("Invalid URL.")
     }
 
@@ -264,21 +625,69 @@ impl Instance {
         panic!("Instance was not started with k8s");
     }
 
+    fn ssh_backend(&self) -> Option<&SshInstanceInfo> {
+        if let InstanceBackend::Ssh(ref ssh) = self.backend {
+            return Some(ssh);
+        }
+        None
+    }
+
     pub fn debug_interface_port(&self) -> Option<u32> {
         self.debug_interface_port
     }
 
     pub fn json_rpc_client(&self) -> JsonRpcAsyncClient {
-        JsonRpcAsyncClient::new_with_client(self.http_client.clone(), self.json_rpc_url())
+        JsonRpcAsyncClient::new_with_client(self.json_rpc_http_client(), self.json_rpc_url())
+    }
+
+    /// Http client for raw requests to `json_rpc_url`, e.g. to inspect fields of responses
+    /// which `JsonRpcAsyncClient` drops
+    pub fn json_rpc_http_client(&self) -> Client {
+        match &self.json_rpc_endpoint {
+            Some(endpoint) => endpoint.client.clone(),
+            None => self.http_client.clone(),
+        }
     }
 
     pub async fn stop(&self) -> Result<()> {
+        audit::record("stop", &self.peer_name, "");
+        if let Some(ssh) = self.ssh_backend() {
+            return self
+                .ssh_cmd(
+                    &format!("sudo systemctl stop {}", shell_quote(&ssh.service)),
+                    false,
+                )
+                .await;
+        }
         let backend = self.k8s_backend();
         backend.kube.delete_node(&backend.instance_config).await
     }
 
     /// Node must be stopped first
     pub async fn start(&self, delete_data: bool) -> Result<()> {
+        audit::record(
+            "start",
+            &self.peer_name,
+            format!("delete_data={}", delete_data),
+        );
+        if let Some(ssh) = self.ssh_backend() {
+            if delete_data {
+                self.ssh_cmd(
+                    &format!(
+                        "sudo find {} -mindepth 1 -delete",
+                        shell_quote(&ssh.data_dir)
+                    ),
+                    false,
+                )
+                .await?;
+            }
+            return self
+                .ssh_cmd(
+                    &format!("sudo systemctl start {}", shell_quote(&ssh.service)),
+                    false,
+                )
+                .await;
+        }
         let backend = self.k8s_backend();
         backend
             .kube
@@ -287,13 +696,46 @@ impl Instance {
             .map(|_| ())
     }
 
+    /// Not known for instances which are not started by cluster test
+    pub fn image_tag(&self) -> Option<&str> {
+        match &self.backend {
+            InstanceBackend::K8S(k8s) => k8s.instance_config.image_tag(),
+            InstanceBackend::Ssh(ssh) => ssh.instance_config.image_tag(),
+            InstanceBackend::Swarm => None,
+        }
+    }
+
+    /// Handle of this instance after it was redeployed with image `tag`
+    pub fn with_image_tag(&self, tag: &str) -> Result<Instance> {
+        let mut instance = self.clone();
+        match &mut instance.backend {
+            InstanceBackend::K8S(k8s) => k8s.instance_config.replace_tag(tag.to_string())?,
+            InstanceBackend::Ssh(ssh) => ssh.instance_config.replace_tag(tag.to_string())?,
+            InstanceBackend::Swarm => bail!("{} has no image tag", self),
+        }
+        Ok(instance)
+    }
+
     pub fn instance_config(&self) -> &InstanceConfig {
+        if let Some(ssh) = self.ssh_backend() {
+            return &ssh.instance_config;
+        }
         let backend = self.k8s_backend();
         &backend.instance_config
     }
 
     /// Runs command on the same host in separate utility container based on cluster-test-util image
+    /// For ssh instances command runs directly on the host as root
     pub async fn util_cmd<S: AsRef<str>>(&self, command: S, job_name: &str) -> Result<()> {
+        audit::record("util_cmd", &self.peer_name, command.as_ref());
+        if self.ssh_backend().is_some() {
+            return self
+                .ssh_cmd(
+                    &format!("sudo sh -c {}", shell_quote(command.as_ref())),
+                    false,
+                )
+                .await;
+        }
         let backend = self.k8s_backend();
         backend
             .kube
@@ -307,7 +749,37 @@ impl Instance {
     }
 
     /// Unlike util_cmd, exec runs command inside the container
+    /// For ssh instances command runs on the host as ssh user
     pub async fn exec(&self, command: &str, mute: bool) -> Result<()> {
+        audit::record("exec", &self.peer_name, command);
+        self.run_cmd(self.exec_command(command), command, mute)
+            .await
+    }
+
+    /// Same as exec, but stdout of the command is returned instead of being printed
+    pub async fn exec_output(&self, command: &str) -> Result<String> {
+        audit::record("exec", &self.peer_name, command);
+        let output = self
+            .exec_command(command)
+            .stderr(Stdio::inherit())
+            .output()
+            .await
+            .map_err(|e| format_err!("Error running {} on {}: {}", command, self.peer_name(), e))?;
+        if !output.status.success() {
+            bail!(
+                "Running {} on {}, exit code {:?}",
+                command,
+                self.peer_name(),
+                output.status.code()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    fn exec_command(&self, command: &str) -> Command {
+        if self.ssh_backend().is_some() {
+            return self.ssh_command(command);
+        }
         let mut cmd = Command::new("kubectl");
         cmd.arg("exec")
             .arg(&self.peer_name)
@@ -318,6 +790,29 @@ impl Instance {
             .arg("-c")
             .arg(command)
             .kill_on_drop(true);
+        cmd
+    }
+
+    async fn ssh_cmd(&self, command: &str, mute: bool) -> Result<()> {
+        self.run_cmd(self.ssh_command(command), command, mute).await
+    }
+
+    fn ssh_command(&self, command: &str) -> Command {
+        let ssh = self
+            .ssh_backend()
+            .expect("Instance was not started with ssh");
+        let mut cmd = Command::new("ssh");
+        cmd.arg("-o")
+            .arg("BatchMode=yes")
+            .arg("-o")
+            .arg("StrictHostKeyChecking=no")
+            .arg(format!("{}@{}", ssh.ssh_user, self.ip))
+            .arg(command)
+            .kill_on_drop(true);
+        cmd
+    }
+
+    async fn run_cmd(&self, mut cmd: Command, command: &str, mute: bool) -> Result<()> {
         if mute {
             cmd.stdout(Stdio::null()).stderr(Stdio::null());
         }
@@ -344,6 +839,68 @@ impl Instance {
         }
     }
 
+    /// Number of times main container of this instance was restarted by k8s
+    pub async fn restart_count(&self) -> Result<u32> {
+        let restart_count = self.main_container_status("restartCount").await?;
+        restart_count.parse().map_err(|e| {
+            format_err!(
+                "Failed to parse restart count {} for {}: {}",
+                restart_count,
+                self.peer_name,
+                e
+            )
+        })
+    }
+
+    /// Time when main container of this instance was last started by k8s
+    pub async fn start_time(&self) -> Result<DateTime<Utc>> {
+        let started_at = self
+            .main_container_status("state.running.startedAt")
+            .await?;
+        started_at.parse().map_err(|e| {
+            format_err!(
+                "Failed to parse start time {} for {}: {}",
+                started_at,
+                self.peer_name,
+                e
+            )
+        })
+    }
+
+    /// Image with digest the main container of this instance actually runs. Differs between
+    /// instances deployed with the same tag if the tag was moved in between
+    pub async fn deployed_image(&self) -> Result<String> {
+        self.main_container_status("imageID").await
+    }
+
+    /// Field of k8s status of main container, given as jsonpath relative to the status
+    async fn main_container_status(&self, path: &str) -> Result<String> {
+        if self.ssh_backend().is_some() {
+            bail!("Pod status is not available for ssh instance {}", self);
+        }
+        let output = Command::new("kubectl")
+            .arg("get")
+            .arg("pod")
+            .arg(&self.peer_name)
+            .arg("-o")
+            .arg(format!(
+                "jsonpath={{.status.containerStatuses[?(@.name==\"main\")].{}}}",
+                path
+            ))
+            .kill_on_drop(true)
+            .output()
+            .await
+            .map_err(|e| format_err!("Failed to get pod status for {}: {}", self.peer_name, e))?;
+        if !output.status.success() {
+            bail!(
+                "Getting pod status for {}, exit code {:?}",
+                self.peer_name,
+                output.status.code()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
     pub fn debug_interface_client(&self) -> AsyncNodeDebugClient {
         AsyncNodeDebugClient::new(
             self.http_client.clone(),
@@ -354,6 +911,25 @@ impl Instance {
     }
 }
 
+#[async_trait]
+impl EmitTarget for Instance {
+    fn name(&self) -> String {
+        self.peer_name.clone()
+    }
+
+    fn json_rpc_client(&self) -> JsonRpcAsyncClient {
+        Instance::json_rpc_client(self)
+    }
+
+    async fn estimate_clock_offset(&self) -> Result<i64> {
+        Instance::estimate_clock_offset(self).await
+    }
+
+    fn capacity(&self) -> u64 {
+        self.capacity.unwrap_or(1)
+    }
+}
+
 impl fmt::Display for Instance {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}({})", self.peer_name, self.ip)
@@ -374,6 +950,12 @@ pub fn instancelist_to_set(instances: &[Instance]) -> HashSet<String> {
     r
 }
 
+/// Quotes `s` as a single word for a POSIX shell, so that the login shell of an ssh host passes
+/// it on without expanding anything in it
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
 pub fn validator_pod_name(index: u32) -> String {
     format!("val-{}", index)
 }
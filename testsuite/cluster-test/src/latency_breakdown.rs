@@ -0,0 +1,275 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Per stage latency of transactions from trace events, aggregated over all sampled transactions
+/// instead of following a single one, either for the whole trace or for each window of time
+/// transactions were submitted in. Timestamps of events of different nodes are compared
+/// directly, so stages which cross nodes include their clock skew
+use libra_logger::json_log::JsonLogEntry;
+use libra_trace::trace::{TRACE_EDGE, TRACE_EVENT};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+/// Stages in order, each one lasts from the end of previous one
+pub const STAGES: [&str; 5] = [
+    "mempool_admission",
+    "block_proposal",
+    "execution",
+    "commit",
+    "fullnode_sync",
+];
+
+pub struct LatencyBreakdown {
+    /// Transactions seen submitted during the trace
+    pub samples: usize,
+    /// Median latency in ms of each stage and number of transactions it was measured for, in
+    /// order of `STAGES`
+    pub stages: Vec<(&'static str, Option<u64>, usize)>,
+    /// p99 latency in ms of each stage, in order of `STAGES`
+    pub p99: Vec<Option<u64>>,
+}
+
+/// Earliest timestamp of each traced node at each stage
+#[derive(Default)]
+struct TraceIndex<'a> {
+    events: HashMap<(&'a str, &'a str), u64>,
+    /// Blocks each transaction was pulled into
+    blocks: HashMap<&'a str, Vec<&'a str>>,
+    /// Last removal of each transaction from mempool of fullnodes, which happens once they sync
+    /// the commit
+    fullnode_removals: HashMap<&'a str, u64>,
+}
+
+impl<'a> TraceIndex<'a> {
+    fn new(events: &'a [(String, JsonLogEntry)]) -> Self {
+        let mut index = Self::default();
+        for (peer, entry) in events {
+            let node = match entry.json.get("node").and_then(|v| v.as_str()) {
+                Some(node) => node,
+                None => continue,
+            };
+            let stage = match entry.json.get("stage").and_then(|v| v.as_str()) {
+                Some(stage) => stage,
+                None => continue,
+            };
+            if entry.name == TRACE_EDGE {
+                if let Some(block) = entry.json.get("node_to").and_then(|v| v.as_str()) {
+                    if stage == "pull_txns" {
+                        index.blocks.entry(node).or_default().push(block);
+                    }
+                }
+                continue;
+            }
+            if entry.name != TRACE_EVENT {
+                continue;
+            }
+            if peer.starts_with("fn-") {
+                if stage == "mempool:remove_transaction" {
+                    let ts = index
+                        .fullnode_removals
+                        .entry(node)
+                        .or_insert(entry.timestamp);
+                    *ts = (*ts).max(entry.timestamp);
+                }
+                continue;
+            }
+            let ts = index.events.entry((node, stage)).or_insert(entry.timestamp);
+            *ts = (*ts).min(entry.timestamp);
+        }
+        index
+    }
+
+    fn get(&self, node: &str, stage: &str) -> Option<u64> {
+        self.events.get(&(node, stage)).copied()
+    }
+
+    /// Timestamps of end of each stage of `txn`, starting with submission
+    fn timeline(&self, txn: &str) -> [Option<u64>; 6] {
+        // Transaction may be pulled into several proposals, only the committed one counts
+        let block = self
+            .blocks
+            .get(txn)
+            .and_then(|blocks| blocks.iter().find(|b| self.get(b, "commit").is_some()));
+        let block_stage = |stage| block.and_then(|b| self.get(b, stage));
+        [
+            self.get(txn, "json-rpc::submit"),
+            self.get(txn, "mempool::add_txn"),
+            block_stage("round_manager::generate_proposal"),
+            block_stage("block_store::execute_block::done"),
+            block_stage("commit"),
+            self.fullnode_removals.get(txn).copied(),
+        ]
+    }
+
+    /// Transactions seen submitted during the trace with their submission timestamp
+    fn submitted(&self) -> HashMap<&'a str, u64> {
+        self.events
+            .iter()
+            .filter(|((node, stage), _)| node.starts_with("txn::") && *stage == "json-rpc::submit")
+            .map(|((node, _), ts)| (*node, *ts))
+            .collect()
+    }
+
+    fn breakdown(&self, txns: impl Iterator<Item = &'a str>) -> LatencyBreakdown {
+        let mut samples = 0;
+        let mut durations = vec![vec![]; STAGES.len()];
+        for txn in txns {
+            samples += 1;
+            let timeline = self.timeline(txn);
+            for (i, window) in timeline.windows(2).enumerate() {
+                if let (Some(start), Some(end)) = (window[0], window[1]) {
+                    durations[i].push(end.saturating_sub(start));
+                }
+            }
+        }
+        for durations in &mut durations {
+            durations.sort_unstable();
+        }
+        LatencyBreakdown {
+            samples,
+            stages: STAGES
+                .iter()
+                .zip(&durations)
+                .map(|(stage, durations)| (*stage, percentile(durations, 50), durations.len()))
+                .collect(),
+            p99: durations
+                .iter()
+                .map(|durations| percentile(durations, 99))
+                .collect(),
+        }
+    }
+}
+
+pub fn compute(events: &[(String, JsonLogEntry)]) -> LatencyBreakdown {
+    let index = TraceIndex::new(events);
+    let txns = index.submitted();
+    index.breakdown(txns.keys().copied())
+}
+
+/// Breakdown of transactions submitted in each window of `window_ms`, keyed by start of the
+/// window, so that degradation during a long trace is not averaged away
+pub fn compute_per_window(
+    events: &[(String, JsonLogEntry)],
+    window_ms: u64,
+) -> BTreeMap<u64, LatencyBreakdown> {
+    let index = TraceIndex::new(events);
+    let mut windows: BTreeMap<u64, Vec<&str>> = BTreeMap::new();
+    for (txn, submitted) in index.submitted() {
+        let start = submitted - submitted % window_ms.max(1);
+        windows.entry(start).or_default().push(txn);
+    }
+    windows
+        .into_iter()
+        .map(|(start, txns)| (start, index.breakdown(txns.into_iter())))
+        .collect()
+}
+
+/// `values` have to be sorted
+fn percentile(values: &[u64], percent: usize) -> Option<u64> {
+    if values.is_empty() {
+        return None;
+    }
+    Some(values[(values.len() * percent / 100).min(values.len() - 1)])
+}
+
+impl fmt::Display for LatencyBreakdown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "median stage latency of {} traced txns:", self.samples)?;
+        for ((stage, median, count), p99) in self.stages.iter().zip(&self.p99) {
+            match (median, p99) {
+                (Some(median), Some(p99)) => write!(
+                    f,
+                    " {} {} ms (p99 {} ms, {} samples),",
+                    stage, median, p99, count
+                )?,
+                _ => write!(f, " {} n/a,", stage)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn event(peer: &str, timestamp: u64, node: &str, stage: &str) -> (String, JsonLogEntry) {
+        (
+            peer.to_string(),
+            JsonLogEntry {
+                name: TRACE_EVENT.to_string(),
+                timestamp,
+                json: json!({"node": node, "stage": stage}),
+            },
+        )
+    }
+
+    #[test]
+    fn test_compute() {
+        let mut events = vec![];
+        for (i, txn) in ["txn::a::0", "txn::b::0", "txn::c::0"].iter().enumerate() {
+            let offset = i as u64 * 10;
+            events.push(event("val-0", 1000, txn, "json-rpc::submit"));
+            events.push(event("val-0", 1005 + offset, txn, "mempool::add_txn"));
+            events.push((
+                "val-0".to_string(),
+                JsonLogEntry {
+                    name: TRACE_EDGE.to_string(),
+                    timestamp: 1100,
+                    json: json!({"node": txn, "node_to": "block::x", "stage": "pull_txns"}),
+                },
+            ));
+        }
+        events.push(event(
+            "val-0",
+            1100,
+            "block::x",
+            "round_manager::generate_proposal",
+        ));
+        events.push(event(
+            "val-1",
+            1300,
+            "block::x",
+            "block_store::execute_block::done",
+        ));
+        events.push(event(
+            "val-0",
+            1250,
+            "block::x",
+            "block_store::execute_block::done",
+        ));
+        events.push(event("val-0", 1400, "block::x", "commit"));
+        events.push(event(
+            "fn-0-0",
+            1900,
+            "txn::a::0",
+            "mempool:remove_transaction",
+        ));
+
+        let breakdown = compute(&events);
+        assert_eq!(breakdown.samples, 3);
+        assert_eq!(
+            breakdown.stages,
+            vec![
+                ("mempool_admission", Some(15), 3),
+                ("block_proposal", Some(85), 3),
+                ("execution", Some(150), 3),
+                ("commit", Some(150), 3),
+                ("fullnode_sync", Some(500), 1),
+            ]
+        );
+        assert_eq!(
+            breakdown.p99,
+            vec![Some(25), Some(95), Some(150), Some(150), Some(500)]
+        );
+
+        let windows = compute_per_window(&events, 1000);
+        assert_eq!(windows.keys().collect::<Vec<_>>(), vec![&1000]);
+        assert_eq!(windows[&1000].samples, 3);
+    }
+}
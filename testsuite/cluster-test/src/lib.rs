@@ -1,22 +1,48 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+pub mod aimd;
 pub mod atomic_histogram;
+pub mod audit;
 pub mod aws;
+pub mod balancer;
+pub mod bisect;
+pub mod checkpoint;
 pub mod cluster;
 pub mod cluster_builder;
 pub mod cluster_swarm;
+pub mod cost;
+pub mod dead_letter;
+pub mod diagnose;
 pub mod effects;
 pub mod experiments;
 pub mod github;
 pub mod health;
+pub mod history;
 pub mod instance;
+pub mod latency_breakdown;
+pub mod metrics_export;
+pub mod preflight;
 pub mod prometheus;
+pub mod pushgateway;
+pub mod query_emitter;
 pub mod report;
+pub mod retrying_client;
+pub mod runner;
+pub mod scenarios;
+pub mod scorecard;
+pub mod significance;
 pub mod slack;
+pub mod slo;
 pub mod stats;
 pub mod suite;
+pub mod timeline;
+pub mod topology;
+pub mod tui;
 pub mod tx_emitter;
+pub mod version_check;
+pub mod warm_state;
+pub mod workload_profile;
 
 pub mod util {
     use std::time::{Duration, SystemTime};
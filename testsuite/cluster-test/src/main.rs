@@ -2,8 +2,11 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::{
-    collections::HashSet,
-    env, fmt, process,
+    collections::{BTreeMap, HashSet},
+    env, fmt, fs,
+    net::SocketAddr,
+    path::Path,
+    process,
     time::{Duration, Instant},
 };
 
@@ -14,30 +17,61 @@ use termion::{color, style};
 
 use anyhow::{bail, format_err, Result};
 use cluster_test::{
-    aws,
+    audit, aws,
+    bisect::{self, BisectParams, Bisection},
+    checkpoint::{Checkpoint, CheckpointStore},
     cluster::Cluster,
     cluster_builder::{ClusterBuilder, ClusterBuilderParams},
-    cluster_swarm::{cluster_swarm_kube::ClusterSwarmKube, ClusterSwarm},
-    experiments::{get_experiment, Context, Experiment},
+    cluster_swarm::{
+        cluster_swarm_kube::{ClusterSwarmKube, CLUSTER_LOCK_DURATION_SECS},
+        cluster_swarm_ssh::ClusterSwarmSsh,
+        ClusterSwarm,
+    },
+    cost::{self, CostRates},
+    diagnose::{self, ClusterState},
+    effects,
+    experiments::{get_experiment, update_batch_instance, Context, Experiment},
     github::GitHub,
-    health::{DebugPortLogWorker, HealthCheckRunner, LogTail, PrintFailures, TraceTail},
-    instance::Instance,
+    health::{
+        DebugPortLogWorker, DiskGrowthHealthCheck, HealthCheckRunner, LogTail, PrintFailures,
+        TraceTail,
+    },
+    history::ResultsStore,
+    instance::{Instance, JsonRpcEndpointConfig},
+    metrics_export::MetricsExport,
+    preflight,
     prometheus::Prometheus,
-    report::SuiteReport,
-    slack::SlackClient,
-    suite::ExperimentSuite,
-    tx_emitter::{AccountData, EmitJobRequest, EmitThreadParams, TxEmitter, TxStats},
+    pushgateway::PushGateway,
+    report::{ReportSection, RunMetadata, SuiteReport},
+    runner,
+    scorecard::Scorecard,
+    slack::{self, SlackClient, SlackThread, SlackUploadTarget},
+    slo::LatencySlo,
+    stats::{self, PrometheusRangeView},
+    suite::{ExperimentSuite, Outcome},
+    timeline, topology,
+    tui::Dashboard,
+    tx_emitter::{AccountData, EmitJobParams, EmitJobRequest, TxEmitter, TxStats},
+    util::unix_timestamp_now,
+    version_check,
+    warm_state::WarmState,
+    workload_profile::WorkloadProfile,
 };
 use futures::{
-    future::{join_all, FutureExt},
-    select,
+    future::{abortable, join_all, try_join_all, AbortHandle, FutureExt},
+    pin_mut, select,
 };
 use itertools::zip;
 use libra_config::config::DEFAULT_JSON_RPC_PORT;
+use libra_json_rpc_client::JsonRpcAsyncClient;
 use std::cmp::min;
-use tokio::time::{delay_for, delay_until, Instant as TokioInstant};
-
-const HEALTH_POLL_INTERVAL: Duration = Duration::from_secs(5);
+use tokio::{
+    signal::{
+        ctrl_c,
+        unix::{signal, SignalKind},
+    },
+    time::delay_for,
+};
 
 #[derive(StructOpt, Debug)]
 #[structopt(group = ArgGroup::with_name("action"))]
@@ -50,6 +84,12 @@ struct Args {
         help = "If set, tries to connect to a libra-swarm instead of aws"
     )]
     swarm: bool,
+    #[structopt(
+        long,
+        conflicts_with = "swarm",
+        help = "Inventory file of cluster not managed by cluster test, instances are controlled over ssh"
+    )]
+    inventory: Option<String>,
 
     #[structopt(long, group = "action")]
     run: Option<String>,
@@ -59,10 +99,85 @@ struct Args {
     emit_tx: bool,
     #[structopt(long, group = "action", requires = "swarm")]
     diag: bool,
+    #[structopt(
+        long,
+        group = "action",
+        help = "Print versions, mempool size and peer counts of every instance as table and json"
+    )]
+    diagnose: bool,
     #[structopt(long, group = "action")]
     suite: Option<String>,
+    #[structopt(
+        long,
+        use_delimiter = true,
+        requires = "suite",
+        help = "Only run experiments of the suite which have any of given tags"
+    )]
+    include_tags: Vec<String>,
+    #[structopt(
+        long,
+        use_delimiter = true,
+        requires = "suite",
+        help = "Skip experiments of the suite which have any of given tags"
+    )]
+    exclude_tags: Vec<String>,
+    #[structopt(
+        long,
+        requires = "suite",
+        help = "Wall-clock budget of the suite in secs, overrides budget of the suite itself. Experiments are trimmed and the least important ones skipped to fit"
+    )]
+    suite_budget_secs: Option<u64>,
     #[structopt(long, group = "action")]
     exec: Option<String>,
+    #[structopt(
+        long,
+        group = "action",
+        help = "Report Prometheus stats of the cluster over a past window starting at given unix timestamp in secs, without running any experiment"
+    )]
+    report_from: Option<u64>,
+    #[structopt(
+        long,
+        requires = "report-from",
+        help = "End of --report-from window as unix timestamp in secs. Defaults to now"
+    )]
+    report_to: Option<u64>,
+    #[structopt(
+        long,
+        group = "action",
+        requires = "record-workload-url",
+        help = "Sample committed transactions of a live network and write their workload profile for --workload-profile to given file"
+    )]
+    record_workload: Option<String>,
+    #[structopt(
+        long,
+        help = "JSON-RPC url of the network sampled by --record-workload"
+    )]
+    record_workload_url: Option<String>,
+    #[structopt(
+        long,
+        default_value = "10000",
+        help = "Number of user transactions sampled by --record-workload"
+    )]
+    record_workload_txns: usize,
+    #[structopt(
+        long,
+        group = "action",
+        help = "Wipe storage of all validators and fullnodes and redeploy genesis, leaving the cluster running. Only clusters listed in CLUSTER_TEST_RESET_ALLOWLIST can be reset"
+    )]
+    reset: bool,
+    #[structopt(
+        long,
+        requires = "reset",
+        help = "Do not ask to confirm --reset by typing the cluster name"
+    )]
+    yes: bool,
+    #[structopt(
+        long,
+        group = "action",
+        requires = "results-dir",
+        help = "Serve dashboard with history of runs from --results-dir on given address"
+    )]
+    serve: Option<SocketAddr>,
 
     #[structopt(last = true)]
     last: Vec<String>,
@@ -73,14 +188,8 @@ struct Args {
     changelog: Option<Vec<String>>,
 
     // emit_tx options
-    #[structopt(long, default_value = "15")]
-    accounts_per_client: usize,
-    #[structopt(long)]
-    workers_per_ac: Option<usize>,
-    #[structopt(long, default_value = "0")]
-    wait_millis: u64,
-    #[structopt(long)]
-    burst: bool,
+    #[structopt(flatten)]
+    emit_job_params: EmitJobParams,
     #[structopt(long, default_value = "mint.key")]
     mint_file: String,
     #[structopt(
@@ -102,21 +211,154 @@ struct Args {
     )]
     pub wait_on_failure: Option<u64>,
 
+    #[structopt(
+        long,
+        help = "Wait for given number of seconds for cluster lock held by another run to be released"
+    )]
+    pub wait_for_lock: Option<u64>,
+    #[structopt(
+        long,
+        help = "Take over cluster lock even if it is held by another run"
+    )]
+    pub steal_lock: bool,
+
+    #[structopt(
+        long,
+        help = "File with hourly instance rates, used to estimate cost of experiments"
+    )]
+    pub cost_rates: Option<String>,
+    #[structopt(
+        long,
+        help = "Directory where metrics of every run are saved, used by --serve"
+    )]
+    pub results_dir: Option<String>,
+    #[structopt(
+        long,
+        help = "Who started the run, recorded in report metadata. Defaults to $USER"
+    )]
+    pub initiator: Option<String>,
+    #[structopt(
+        long,
+        help = "File to record commands run against the cluster in. Defaults to cluster-test-audit-<timestamp>.log"
+    )]
+    pub audit_log: Option<String>,
+    #[structopt(
+        long,
+        help = "Directory where progress of a suite is saved after every experiment, so that it can be resumed"
+    )]
+    pub checkpoint_dir: Option<String>,
+    #[structopt(
+        long,
+        requires_all = &["suite", "checkpoint-dir"],
+        help = "Resume suite of given run id from its checkpoint, skipping experiments which completed"
+    )]
+    pub resume: Option<String>,
+    #[structopt(
+        long,
+        help = "Directory where raw Prometheus data over the window of each experiment is exported to"
+    )]
+    pub export_metrics_dir: Option<String>,
+    #[structopt(
+        long = "export-metric",
+        requires = "export-metrics-dir",
+        help = "Range query to export, can be repeated. Defaults to consensus, mempool, state sync and storage metrics"
+    )]
+    pub export_metrics: Vec<String>,
+    #[structopt(
+        long,
+        default_value = "10",
+        help = "Step in secs of exported range queries"
+    )]
+    pub export_metrics_step: u64,
+    #[structopt(
+        long,
+        help = "Only warn when instances run different images, instead of failing before the suite"
+    )]
+    pub allow_version_skew: bool,
+    #[structopt(
+        long = "latency-slo",
+        default_value = "p99<900",
+        help = "Latency SLO evaluated per minute of each workload, e.g. p99<900, can be repeated"
+    )]
+    pub latency_slos: Vec<LatencySlo>,
+    #[structopt(
+        long,
+        help = "Skip experiments whose target instances fail preflight checks, instead of failing the suite"
+    )]
+    pub skip_failed_preflight: bool,
+    #[structopt(
+        long,
+        help = "Only warn when Prometheus has no metrics of some instances, instead of failing at startup"
+    )]
+    pub allow_missing_metrics: bool,
+    #[structopt(
+        long,
+        default_value = "120",
+        help = "Time in secs to wait for Prometheus to scrape every instance at startup"
+    )]
+    pub prometheus_targets_timeout: u64,
+    #[structopt(
+        long,
+        help = "Show live dashboard of the run in terminal, logs are written to --tui-log instead"
+    )]
+    pub tui: bool,
+    #[structopt(
+        long,
+        requires = "tui",
+        help = "File logs are written to in --tui mode. Defaults to cluster-test-<timestamp>.log"
+    )]
+    pub tui_log: Option<String>,
+
+    #[structopt(flatten)]
+    pub json_rpc_endpoint: JsonRpcEndpointConfig,
+
     #[structopt(flatten)]
     pub cluster_builder_params: ClusterBuilderParams,
+
+    #[structopt(flatten)]
+    pub bisect: BisectParams,
 }
 
 #[tokio::main]
 pub async fn main() {
-    setup_log();
-
     let args = Args::from_args();
 
-    if args.swarm && !(args.emit_tx || args.diag || args.health_check) {
-        panic!("Can only use --emit-tx or --diag or --health-check in --swarm mode");
+    let dashboard = if args.tui && runs_experiments(&args) {
+        let log_file = args
+            .tui_log
+            .clone()
+            .unwrap_or_else(|| format!("cluster-test-{}.log", unix_timestamp_now().as_secs()));
+        let dashboard = Dashboard::new();
+        exit_on_error(dashboard.init_logger(Path::new(&log_file)));
+        Some(dashboard)
+    } else {
+        setup_log();
+        if args.tui {
+            warn!("--tui only applies to experiment runs, output is printed as usual");
+        }
+        None
+    };
+
+    if let Some(address) = args.serve {
+        let results_dir = args.results_dir.as_ref().expect("Checked by structopt");
+        ResultsStore::new(results_dir).serve(address).await;
+        return;
+    }
+
+    if let Some(path) = &args.record_workload {
+        exit_on_error(record_workload(&args, Path::new(path)).await);
+        return;
     }
 
-    if args.diag {
+    if args.swarm && !(args.emit_tx || args.diag || args.health_check || args.diagnose) {
+        panic!("Can only use --emit-tx or --diag or --health-check or --diagnose in --swarm mode");
+    }
+
+    if args.diagnose && args.swarm {
+        let util = BasicSwarmUtil::setup(&args);
+        print_cluster_state(&util.cluster).await;
+        return;
+    } else if args.diag {
         let util = BasicSwarmUtil::setup(&args);
         exit_on_error(util.diag().await);
         return;
@@ -133,6 +375,42 @@ pub async fn main() {
         return;
     }
 
+    let audit_log = args
+        .audit_log
+        .clone()
+        .unwrap_or_else(|| format!("cluster-test-audit-{}.log", unix_timestamp_now().as_secs()));
+    exit_on_error(audit::init(Path::new(&audit_log)));
+    info!("Recording audit log to {}", audit_log);
+
+    if args.bisect.bisect_good.is_some() {
+        exit_on_error(run_bisect(&args).await);
+        return;
+    }
+
+    if args.reset {
+        exit_on_error(reset_cluster(&args).await);
+        return;
+    }
+
+    // Window in the past is reported as it was, whatever state the cluster is in now, so the
+    // cluster is neither locked nor deployed nor torn down
+    if let Some(from) = args.report_from {
+        let to = args
+            .report_to
+            .map(Duration::from_secs)
+            .unwrap_or_else(unix_timestamp_now);
+        let report = exit_on_error(report_window(&args, Duration::from_secs(from), to).await);
+        println!("{}", report);
+        return;
+    }
+
+    // State of the cluster as it is, which setting up the runner would wipe first
+    if args.diagnose {
+        let cluster = exit_on_error(discover_cluster(&args).await);
+        print_cluster_state(&cluster).await;
+        return;
+    }
+
     let wait_on_failure = if let Some(wait_on_failure) = args.wait_on_failure {
         if wait_on_failure > 20 * 60 {
             println!("wait_on_failure can not be more then 1200 seconds on shared cluster");
@@ -157,8 +435,40 @@ pub async fn main() {
             panic!("Failed to setup cluster test runner: {}", e);
         }
     };
+    if let Some(dashboard) = &dashboard {
+        runner.attach_dashboard(dashboard.clone());
+    }
 
-    let result = handle_cluster_test_runner_commands(&args, &mut runner).await;
+    let result = {
+        let commands = handle_cluster_test_runner_commands(&args, &mut runner).fuse();
+        let terminated = wait_for_termination_signal().fuse();
+        pin_mut!(commands, terminated);
+        select! {
+            result = commands => Some(result),
+            signal = terminated => {
+                warn!("Received {}, aborting run", signal);
+                None
+            }
+        }
+    };
+    if let Some(dashboard) = &dashboard {
+        dashboard.stop();
+    }
+    // In-flight experiment is cancelled by dropping it above. Cluster is torn down and its
+    // lock released first, and not healed, since whoever sent the signal wants it back
+    let result = match result {
+        Some(result) => result,
+        None => {
+            tokio::spawn(async {
+                let signal = wait_for_termination_signal().await;
+                warn!("Received {} again, exiting without teardown", signal);
+                process::exit(130);
+            });
+            runner.teardown().await;
+            runner.abort().await;
+            process::exit(130);
+        }
+    };
     if let Err(e) = &result {
         if let Some(wait_on_failure) = wait_on_failure {
             warn!(
@@ -189,6 +499,17 @@ pub async fn main() {
     }
 }
 
+async fn wait_for_termination_signal() -> &'static str {
+    let mut sigterm = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+    let sigint = ctrl_c().fuse();
+    let sigterm = sigterm.recv().fuse();
+    pin_mut!(sigint, sigterm);
+    select! {
+        _ = sigint => "SIGINT",
+        _ = sigterm => "SIGTERM",
+    }
+}
+
 // This function contain handlers for commands that require cluster running for executing them
 async fn handle_cluster_test_runner_commands(
     args: &Args,
@@ -203,7 +524,11 @@ async fn handle_cluster_test_runner_commands(
         let duration = Duration::from_secs(args.duration);
         run_health_check(&runner.logs, &mut runner.health_check_runner, duration).await?
     } else if let Some(suite) = args.suite.as_ref() {
-        perf_msg = Some(runner.run_named_suite(suite).await?);
+        perf_msg = Some(
+            runner
+                .run_named_suite(suite, &args.include_tags, &args.exclude_tags)
+                .await?,
+        );
     } else if let Some(experiment_name) = args.run.as_ref() {
         runner
             .run_and_report(get_experiment(experiment_name, &args.last, &runner.cluster))
@@ -228,6 +553,53 @@ async fn handle_cluster_test_runner_commands(
     Ok(perf_msg)
 }
 
+const RESET_ALLOWLIST_ENV: &str = "CLUSTER_TEST_RESET_ALLOWLIST";
+const K8S_PROMETHEUS_HOST: &str = "libra-testnet-prometheus-server.default.svc.cluster.local";
+
+fn lock_holder() -> String {
+    format!(
+        "{}-{}",
+        env::var("HOSTNAME").unwrap_or_else(|_| "unknown".to_string()),
+        process::id()
+    )
+}
+
+/// Workspace of kube clusters, name of inventory file otherwise
+async fn cluster_name(kube: Option<&ClusterSwarmKube>, inventory: Option<&String>) -> String {
+    match (kube, inventory) {
+        (Some(kube), _) => kube.get_workspace().await.unwrap_or_else(|e| {
+            warn!("Failed to get workspace: {}", e);
+            "unknown".to_string()
+        }),
+        (None, Some(inventory)) => Path::new(inventory)
+            .file_stem()
+            .map_or_else(|| inventory.clone(), |s| s.to_string_lossy().to_string()),
+        (None, None) => "unknown".to_string(),
+    }
+}
+
+async fn renew_lock(cluster_swarm: ClusterSwarmKube, lock_holder: String) {
+    let interval = Duration::from_secs(CLUSTER_LOCK_DURATION_SECS as u64 / 3);
+    loop {
+        delay_for(interval).await;
+        if let Err(e) = cluster_swarm.renew_lock(&lock_holder).await {
+            warn!("Failed to renew cluster lock: {}", e);
+        }
+    }
+}
+
+async fn push_metrics(
+    push_gateway: &Option<PushGateway>,
+    metrics: &[(&str, f64)],
+    labels: &[(&str, &str)],
+) {
+    if let Some(push_gateway) = push_gateway {
+        if let Err(e) = push_gateway.push(metrics, labels).await {
+            warn!("Failed to push metrics: {}", e);
+        }
+    }
+}
+
 fn exit_on_error<T>(r: Result<T>) -> T {
     match r {
         Ok(r) => r,
@@ -238,6 +610,19 @@ fn exit_on_error<T>(r: Result<T>) -> T {
     }
 }
 
+/// Whether experiments are run by `ClusterTestRunner`, which is what the dashboard shows. Other
+/// modes print their results to the terminal
+fn runs_experiments(args: &Args) -> bool {
+    args.serve.is_none()
+        && args.record_workload.is_none()
+        && !args.swarm
+        && !args.diag
+        && args.bisect.bisect_good.is_none()
+        && !args.reset
+        && args.report_from.is_none()
+        && !args.diagnose
+}
+
 fn setup_log() {
     if env::var("RUST_LOG").is_err() {
         env::set_var("RUST_LOG", "info");
@@ -256,14 +641,46 @@ struct ClusterTestRunner {
     health_check_runner: HealthCheckRunner,
     slack: SlackClient,
     slack_changelog_url: Option<Url>,
+    slack_upload: Option<SlackUploadTarget>,
+    /// Thread of the current suite run, results of experiments are posted into it as they
+    /// complete
+    slack_thread: Option<SlackThread>,
+    /// SVG chart of TPS and latency during the last suite
+    timeline_chart: Option<String>,
     tx_emitter: TxEmitter,
+    /// Setup done by experiments which later experiments of the run can reuse
+    warm_state: WarmState,
     prometheus: Prometheus,
     github: GitHub,
     report: SuiteReport,
     global_emit_job_request: EmitJobRequest,
     emit_to_validator: bool,
-    cluster_swarm: ClusterSwarmKube,
+    cluster_swarm: Box<dyn ClusterSwarm>,
+    /// Not set for clusters from inventory, which are not managed by cluster test
+    kube: Option<ClusterSwarmKube>,
     current_tag: String,
+    push_gateway: Option<PushGateway>,
+    lock_holder: String,
+    lock_renewal: Option<AbortHandle>,
+    cost_rates: Option<CostRates>,
+    results_store: Option<ResultsStore>,
+    checkpoints: Option<CheckpointStore>,
+    run_id: String,
+    /// Ids of entries of the current suite which completed or were skipped, including those of
+    /// resumed run
+    completed: Vec<String>,
+    /// Outcomes of entries of the current suite by id, including those of resumed run
+    outcomes: BTreeMap<String, Outcome>,
+    /// Suite of the run being resumed
+    resumed_suite: Option<String>,
+    metrics_export: Option<MetricsExport>,
+    allow_version_skew: bool,
+    skip_failed_preflight: bool,
+    /// Budget given on command line, takes precedence over budget of the suite
+    suite_budget: Option<Duration>,
+    /// No experiment of the current suite runs past this
+    suite_budget_end: Option<Instant>,
+    dashboard: Option<Dashboard>,
 }
 
 fn parse_host_port(s: &str) -> Result<(String, u32, Option<u32>)> {
@@ -287,22 +704,213 @@ fn parse_host_port(s: &str) -> Result<(String, u32, Option<u32>)> {
     Ok((host, port, None))
 }
 
-async fn emit_tx(cluster: &Cluster, args: &Args) -> Result<()> {
-    let accounts_per_client = args.accounts_per_client;
-    let workers_per_ac = args.workers_per_ac;
-    let thread_params = EmitThreadParams {
-        wait_millis: args.wait_millis,
-        wait_committed: !args.burst,
+/// Network is only read over JSON-RPC, it does not have to be a cluster test one
+async fn record_workload(args: &Args, path: &Path) -> Result<()> {
+    let url = args
+        .record_workload_url
+        .as_ref()
+        .expect("Checked by structopt");
+    let url = Url::parse(url).map_err(|e| format_err!("Invalid JSON-RPC url {}: {}", url, e))?;
+    let client = JsonRpcAsyncClient::new(url);
+    let profile = WorkloadProfile::record(&client, args.record_workload_txns).await?;
+    profile.save(path)?;
+    info!("Recorded workload profile to {:?}:\n{}", path, profile);
+    Ok(())
+}
+
+fn print_json_report(report: &SuiteReport) {
+    let json_report =
+        serde_json::to_string_pretty(report).expect("Failed to serialize report to json");
+    info!(
+        "\n====json-report-begin===\n{}\n====json-report-end===",
+        json_report
+    );
+}
+
+/// Prometheus of the cluster, found without taking the lock or deploying anything
+async fn discover_prometheus(args: &Args) -> Result<Prometheus> {
+    match &args.inventory {
+        Some(inventory) => {
+            let cluster_swarm = ClusterSwarmSsh::from_inventory_file(inventory)?;
+            let validator_labels: Vec<_> = cluster_swarm
+                .cluster(&args.mint_file)
+                .validator_instances()
+                .iter()
+                .map(|instance| cluster_swarm.prometheus_labels(instance))
+                .collect();
+            Ok(Prometheus::new(
+                cluster_swarm.prometheus_ip(),
+                cluster_swarm.get_grafana_baseurl().await?,
+            )
+            .with_validator_labels(&validator_labels))
+        }
+        None => {
+            let cluster_swarm = ClusterSwarmKube::new()
+                .await
+                .map_err(|e| format_err!("Failed to initialize ClusterSwarmKube: {}", e))?;
+            Ok(Prometheus::new(
+                K8S_PROMETHEUS_HOST,
+                cluster_swarm.get_grafana_baseurl().await?,
+            ))
+        }
+    }
+}
+
+/// Instances of the running cluster, found without taking the lock or deploying anything
+async fn discover_cluster(args: &Args) -> Result<Cluster> {
+    match &args.inventory {
+        Some(inventory) => {
+            Ok(ClusterSwarmSsh::from_inventory_file(inventory)?.cluster(&args.mint_file))
+        }
+        None => {
+            let cluster_swarm = ClusterSwarmKube::new()
+                .await
+                .map_err(|e| format_err!("Failed to initialize ClusterSwarmKube: {}", e))?;
+            let (validators, fullnodes) = cluster_swarm.running_instances().await?;
+            if validators.is_empty() {
+                bail!("No validator pods are running");
+            }
+            Ok(Cluster::new(validators, fullnodes, vec![], vec![]))
+        }
+    }
+}
+
+/// Standard Prometheus derived report of a past window of the cluster, without running any
+/// experiment. Times are unix timestamps
+async fn report_window(args: &Args, start: Duration, end: Duration) -> Result<String> {
+    if start >= end {
+        bail!("Report window has to start before it ends");
+    }
+    if end > unix_timestamp_now() {
+        bail!("Report window can not end in the future");
+    }
+    let prometheus = discover_prometheus(args).await?;
+    let name = format!("window {}-{}", start.as_secs(), end.as_secs());
+    info!(
+        "Link to dashboard : {}",
+        prometheus.link_to_dashboard(start, end)
+    );
+    let pv = PrometheusRangeView::new(&prometheus, start, end);
+    let committed_tps = pv
+        .avg_committed_tps()
+        .ok_or_else(|| format_err!("Prometheus has no commits of {}", name))?;
+    let mut report = SuiteReport::new();
+    report.start_experiment(name.clone(), ReportSection::Performance);
+    report.report_metric(&name, "committed_tps", committed_tps);
+    let mut text = format!("{}: {:.0} TPS", name, committed_tps);
+    let metrics = [
+        ("avg_txns_per_block", pv.avg_txns_per_block(), 1.0),
+        ("avg_commit_latency_ms", pv.avg_commit_latency(), 1000.0),
+        ("p99_commit_latency_ms", pv.p99_commit_latency(), 1000.0),
+    ];
+    for (metric, value, scale) in metrics.iter() {
+        if let Some(value) = value {
+            let value = value * scale;
+            report.report_metric(&name, metric, value);
+            text.push_str(&format!(", {} {:.0}", metric, value));
+        }
+    }
+    report.report_text(text);
+    stats::report_range(&mut report, &name, &pv, committed_tps);
+    report.end_experiment();
+    print_json_report(&report);
+    Ok(report.to_string())
+}
+
+/// Wipes and redeploys the cluster, which unlike with other actions is not torn down after. For
+/// inventory clusters nodes are all stopped before any of them is started with empty storage,
+/// so that none of them syncs the old chain from a peer
+async fn reset_cluster(args: &Args) -> Result<()> {
+    let kube = match args.inventory {
+        Some(_) => None,
+        None => Some(
+            ClusterSwarmKube::new()
+                .await
+                .map_err(|e| format_err!("Failed to initialize ClusterSwarmKube: {}", e))?,
+        ),
+    };
+    let cluster_name = cluster_name(kube.as_ref(), args.inventory.as_ref()).await;
+    let allowlist = env::var(RESET_ALLOWLIST_ENV).unwrap_or_default();
+    if !allowlist.split(',').any(|name| name.trim() == cluster_name) {
+        bail!(
+            "Cluster {} is not in {}, refusing to reset it",
+            cluster_name,
+            RESET_ALLOWLIST_ENV
+        );
+    }
+    if !args.yes {
+        println!(
+            "Storage of all validators and fullnodes of {} will be wiped. Type the cluster name to confirm:",
+            cluster_name
+        );
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+        if input.trim() != cluster_name {
+            bail!("Reset of {} was not confirmed", cluster_name);
+        }
+    }
+    audit::record("reset", &cluster_name, "");
+    let cluster = match &kube {
+        Some(kube) => {
+            let lock_holder = lock_holder();
+            kube.acquire_lock(
+                &lock_holder,
+                Duration::from_secs(args.wait_for_lock.unwrap_or(0)),
+                args.steal_lock,
+            )
+            .await?;
+            let (renewal, lock_renewal) =
+                abortable(renew_lock(kube.clone(), lock_holder.to_string()));
+            tokio::spawn(renewal);
+            let tag = args.deploy.as_deref().unwrap_or("master");
+            let result = ClusterBuilder::new(tag.to_string(), kube.clone())
+                .setup_cluster(&args.cluster_builder_params)
+                .await;
+            lock_renewal.abort();
+            if let Err(e) = kube.release_lock(&lock_holder).await {
+                warn!("Failed to release cluster lock: {}", e);
+            }
+            result?
+        }
+        None => {
+            let inventory = args
+                .inventory
+                .as_ref()
+                .expect("Kube is only unset with inventory");
+            let cluster = ClusterSwarmSsh::from_inventory_file(inventory)?.cluster(&args.mint_file);
+            let instances: Vec<_> = cluster.validator_and_fullnode_instances().collect();
+            try_join_all(instances.iter().map(|instance| instance.stop())).await?;
+            try_join_all(instances.iter().map(|instance| instance.start(true))).await?;
+            cluster
+        }
     };
+    let deadline = Instant::now() + Duration::from_secs(5 * 60);
+    try_join_all(
+        cluster
+            .validator_and_fullnode_instances()
+            .map(|instance| instance.wait_json_rpc(deadline)),
+    )
+    .await?;
+    info!("Cluster {} was reset", cluster_name);
+    Ok(())
+}
+
+async fn emit_tx(cluster: &Cluster, args: &Args) -> Result<()> {
     let duration = Duration::from_secs(args.duration);
     let mut emitter = TxEmitter::new(cluster);
+    let mut request = args
+        .emit_job_params
+        .emit_job_request(cluster.validator_instances().to_vec())?;
+    if request.validator_traffic_percent.is_some() && !cluster.fullnode_instances().is_empty() {
+        request = EmitJobRequest::split_traffic(
+            cluster.fullnode_instances().to_vec(),
+            cluster.validator_instances().to_vec(),
+            &Some(request),
+        );
+    }
+    let dead_letters = request.dead_letters.clone();
     let job = emitter
-        .start_job(EmitJobRequest {
-            instances: cluster.validator_instances().to_vec(),
-            accounts_per_client,
-            workers_per_ac,
-            thread_params,
-        })
+        .start_job(request)
         .await
         .map_err(|e| format_err!("Failed to start emit job: {}", e))?;
     let deadline = Instant::now() + duration;
@@ -318,9 +926,133 @@ async fn emit_tx(cluster: &Cluster, args: &Args) -> Result<()> {
     let stats = emitter.stop_job(job).await;
     println!("Total stats: {}", stats);
     println!("Average rate: {}", stats.rate(duration));
+    if let Some(dead_letters) = dead_letters {
+        println!(
+            "{} failed transactions, dead letters written to {:?}",
+            dead_letters.entries(),
+            dead_letters.path()
+        );
+    }
     Ok(())
 }
 
+/// Deploys the cluster once and moves it to every tested build in turn, data is kept across
+/// builds
+async fn run_bisect(args: &Args) -> Result<()> {
+    if args.inventory.is_some() {
+        bail!("Bisection needs to deploy builds, which is not possible with --inventory");
+    }
+    let params = &args.bisect;
+    let good = params.bisect_good.as_ref().expect("Checked by structopt");
+    let bad = params.bisect_bad.as_ref().expect("Checked by structopt");
+    let tags = bisect::candidate_tags(&GitHub::new(), good, bad)?;
+    let mut runner = ClusterTestRunner::setup_with_tag(args, good).await?;
+    let result = bisect_builds(args, &mut runner, tags).await;
+    runner.teardown().await;
+    result
+}
+
+/// Finds first bad build among `tags` on the cluster of `runner`
+async fn bisect_builds(
+    args: &Args,
+    runner: &mut ClusterTestRunner,
+    tags: Vec<String>,
+) -> Result<()> {
+    let params = &args.bisect;
+    let good = params.bisect_good.as_ref().expect("Checked by structopt");
+    let bad = params.bisect_bad.as_ref().expect("Checked by structopt");
+    let threshold = params.bisect_threshold.expect("Checked by structopt");
+    // Reference for significance of differences, a single run of it would be noise
+    let good_samples = if params.bisect_repeats > 1 {
+        info!("Measuring {} as reference", good);
+        let samples = measure_build(args, runner, good).await?;
+        info!("{} of {}: {:?}", params.bisect_metric, good, samples);
+        samples
+    } else {
+        vec![]
+    };
+    let mut bisection = Bisection::new(tags);
+    while let Some(index) = bisection.next() {
+        let tag = bisection.tag(index).to_string();
+        info!(
+            "Bisecting {} builds between {} and {}, testing {}",
+            bisection.remaining(),
+            good,
+            bad,
+            tag
+        );
+        match measure_build(args, runner, &tag).await {
+            Ok(samples) => {
+                let regressed = params.is_regression(&samples, threshold, &good_samples);
+                info!(
+                    "{} of {}: {:?}, {}",
+                    params.bisect_metric,
+                    tag,
+                    samples,
+                    if regressed { "bad" } else { "good" }
+                );
+                bisection.record(index, regressed);
+            }
+            Err(e) => {
+                warn!("Skipping {}, failed to measure it: {}", tag, e);
+                bisection.skip(index);
+            }
+        }
+    }
+    let (first_bad, untested) = bisection.result();
+    println!("First bad build: {}", first_bad);
+    if !untested.is_empty() {
+        println!(
+            "Builds which could not be tested and may have caused the regression as well: {}",
+            untested.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Metric of every run of the experiment on the build, the cluster is moved to the build first
+async fn measure_build(args: &Args, runner: &mut ClusterTestRunner, tag: &str) -> Result<Vec<f64>> {
+    let params = &args.bisect;
+    if runner.current_tag != tag {
+        runner.update_tag(tag).await?;
+    }
+    runner
+        .wait_until_all_healthy(Instant::now() + Duration::from_secs(5 * 60))
+        .await?;
+    let mut samples = vec![];
+    for _ in 0..params.bisect_repeats.max(1) {
+        let experiment = get_experiment(&params.bisect_experiment, &args.last, &runner.cluster);
+        runner
+            .run_single_experiment(experiment, Some(runner.global_emit_job_request.clone()))
+            .await?;
+        let value = runner
+            .report
+            .metrics()
+            .iter()
+            .rev()
+            .find(|m| m.metric == params.bisect_metric)
+            .map(|m| m.value)
+            .ok_or_else(|| {
+                format_err!(
+                    "{} did not report {}",
+                    params.bisect_experiment,
+                    params.bisect_metric
+                )
+            })?;
+        samples.push(value);
+    }
+    Ok(samples)
+}
+
+async fn print_cluster_state(cluster: &Cluster) {
+    let state = ClusterState::collect(cluster).await;
+    println!("{}", state);
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&state).expect("Failed to serialize cluster state")
+    );
+}
+
 async fn run_health_check(
     logs: &LogTail,
     health_check_runner: &mut HealthCheckRunner,
@@ -354,7 +1086,9 @@ impl BasicSwarmUtil {
             .map(|peer| parse_host_port(peer).expect("Failed to parse host_port"))
             .collect();
 
-        let cluster = Cluster::from_host_port(parsed_peers, &args.mint_file);
+        let cluster = Cluster::from_host_port(parsed_peers, &args.mint_file)
+            .with_json_rpc_endpoint(&args.json_rpc_endpoint)
+            .expect("Failed to setup JSON-RPC endpoints");
         Self { cluster }
     }
 
@@ -437,12 +1171,18 @@ impl BasicSwarmUtil {
 
 impl ClusterTestRunner {
     pub async fn teardown(&mut self) {
-        self.cluster_swarm.cleanup().await.expect("Cleanup failed");
-        let workspace = self
-            .cluster_swarm
-            .get_workspace()
-            .await
-            .expect("Failed to get workspace");
+        let kube = match &self.kube {
+            Some(kube) => kube,
+            None => return,
+        };
+        kube.cleanup().await.expect("Cleanup failed");
+        if let Some(lock_renewal) = &self.lock_renewal {
+            lock_renewal.abort();
+        }
+        if let Err(e) = kube.release_lock(&self.lock_holder).await {
+            warn!("Failed to release cluster lock: {}", e);
+        }
+        let workspace = kube.get_workspace().await.expect("Failed to get workspace");
         let asg_name = format!("{}-k8s-testnet-validators", workspace);
         aws::set_asg_size(0, 0.0, &asg_name, false, true)
             .await
@@ -451,21 +1191,68 @@ impl ClusterTestRunner {
 
     /// Discovers cluster, setup log, etc
     pub async fn setup(args: &Args) -> Result<Self> {
-        let current_tag = args.deploy.as_deref().unwrap_or("master");
-        let cluster_swarm = ClusterSwarmKube::new()
-            .await
-            .map_err(|e| format_err!("Failed to initialize ClusterSwarmKube: {}", e))?;
-        let prometheus_ip = "libra-testnet-prometheus-server.default.svc.cluster.local";
-        let grafana_base_url = cluster_swarm
-            .get_grafana_baseurl()
-            .await
-            .expect("Failed to discover grafana url in k8s");
-        let prometheus = Prometheus::new(prometheus_ip, grafana_base_url);
-        let cluster_builder = ClusterBuilder::new(current_tag.to_string(), cluster_swarm.clone());
-        let cluster = cluster_builder
-            .setup_cluster(&args.cluster_builder_params)
-            .await
-            .map_err(|e| format_err!("Failed to setup cluster: {}", e))?;
+        Self::setup_with_tag(args, args.deploy.as_deref().unwrap_or("master")).await
+    }
+
+    /// Same as `setup`, but cluster is deployed with given tag instead of --deploy
+    pub async fn setup_with_tag(args: &Args, current_tag: &str) -> Result<Self> {
+        let cost_rates = args
+            .cost_rates
+            .as_ref()
+            .map(|path| CostRates::from_file(path))
+            .transpose()?;
+        let lock_holder = lock_holder();
+        let (cluster, prometheus, cluster_swarm, kube, lock_renewal) =
+            if let Some(inventory) = args.inventory.as_ref() {
+                let cluster_swarm = ClusterSwarmSsh::from_inventory_file(inventory)?;
+                let cluster = cluster_swarm.cluster(&args.mint_file);
+                let prometheus = Prometheus::new(
+                    cluster_swarm.prometheus_ip(),
+                    cluster_swarm.get_grafana_baseurl().await?,
+                );
+                let cluster_swarm: Box<dyn ClusterSwarm> = Box::new(cluster_swarm);
+                (cluster, prometheus, cluster_swarm, None, None)
+            } else {
+                let (cluster, prometheus, kube, lock_renewal) =
+                    Self::setup_kube(args, current_tag, &lock_holder).await?;
+                let cluster_swarm: Box<dyn ClusterSwarm> = Box::new(kube.clone());
+                (
+                    cluster,
+                    prometheus,
+                    cluster_swarm,
+                    Some(kube),
+                    Some(lock_renewal),
+                )
+            };
+        if args.json_rpc_endpoint.is_set()
+            && cluster
+                .validator_and_fullnode_instances()
+                .any(Instance::has_json_rpc_endpoint)
+        {
+            bail!("JSON-RPC endpoints are set both in inventory and with flags, set them in one place");
+        }
+        let cluster = cluster.with_json_rpc_endpoint(&args.json_rpc_endpoint)?;
+        let validator_labels: Vec<_> = cluster
+            .validator_instances()
+            .iter()
+            .map(|instance| cluster_swarm.prometheus_labels(instance))
+            .collect();
+        let prometheus = prometheus.with_validator_labels(&validator_labels);
+        // Queries of instances without metrics return nothing, which experiments report as 0
+        let failures = preflight::check_prometheus_targets(
+            &cluster,
+            cluster_swarm.as_ref(),
+            &prometheus,
+            Duration::from_secs(args.prometheus_targets_timeout),
+        )
+        .await;
+        if !failures.is_empty() {
+            let failures: Vec<_> = failures.iter().map(ToString::to_string).collect();
+            if !args.allow_missing_metrics {
+                bail!("Prometheus has no metrics of: {}", failures.join(", "));
+            }
+            warn!("Prometheus has no metrics of: {}", failures.join(", "));
+        }
         let log_tail_started = Instant::now();
         let (logs, trace_tail) = DebugPortLogWorker::spawn_new(&cluster);
         let log_tail_startup_time = Instant::now() - log_tail_started;
@@ -473,23 +1260,50 @@ impl ClusterTestRunner {
             "Log tail thread started in {} ms",
             log_tail_startup_time.as_millis()
         );
-        let health_check_runner = HealthCheckRunner::new_all(cluster.clone());
+        let mut health_check_runner = HealthCheckRunner::new_all(cluster.clone());
+        if let Some(check) = DiskGrowthHealthCheck::from_env(cluster.clone(), prometheus.clone()) {
+            health_check_runner.add_health_check(Box::new(check));
+        }
         let slack = SlackClient::new();
         let slack_changelog_url = env::var("SLACK_CHANGELOG_URL")
             .map(|u| u.parse().expect("Failed to parse SLACK_CHANGELOG_URL"))
             .ok();
         let tx_emitter = TxEmitter::new(&cluster);
         let github = GitHub::new();
-        let report = SuiteReport::new();
-        let global_emit_job_request = EmitJobRequest {
-            instances: vec![],
-            accounts_per_client: args.accounts_per_client,
-            workers_per_ac: args.workers_per_ac,
-            thread_params: EmitThreadParams {
-                wait_millis: args.wait_millis,
-                wait_committed: !args.burst,
-            },
+        let cluster_name = cluster_name(kube.as_ref(), args.inventory.as_ref()).await;
+        let initiator = args
+            .initiator
+            .clone()
+            .or_else(|| env::var("USER").ok())
+            .unwrap_or_else(|| "unknown".to_string());
+        let mut report = SuiteReport::new();
+        report.set_metadata(RunMetadata::capture(cluster_name, &cluster, initiator));
+        let checkpoints = args.checkpoint_dir.as_ref().map(CheckpointStore::new);
+        let (run_id, completed, outcomes, resumed_suite) = match (&args.resume, &checkpoints) {
+            (Some(run_id), Some(checkpoints)) => {
+                let checkpoint = checkpoints.load(run_id)?;
+                report = checkpoint.report;
+                (
+                    checkpoint.run_id,
+                    checkpoint.completed,
+                    checkpoint.outcomes,
+                    Some(checkpoint.suite),
+                )
+            }
+            _ => (
+                unix_timestamp_now().as_secs().to_string(),
+                vec![],
+                BTreeMap::new(),
+                None,
+            ),
         };
+        report.set_latency_slos(args.latency_slos.clone());
+        if checkpoints.is_some() {
+            info!("Run id is {}, resume it with --resume {}", run_id, run_id);
+        }
+        let global_emit_job_request = args
+            .emit_job_params
+            .emit_job_request(Vec::<Instance>::new())?;
         let emit_to_validator =
             if cluster.fullnode_instances().len() < cluster.validator_instances().len() {
                 true
@@ -503,17 +1317,79 @@ impl ClusterTestRunner {
             health_check_runner,
             slack,
             slack_changelog_url,
+            slack_upload: SlackUploadTarget::from_env(),
+            slack_thread: None,
+            timeline_chart: None,
             tx_emitter,
+            warm_state: WarmState::new(),
             prometheus,
             github,
             report,
             global_emit_job_request,
             emit_to_validator,
             cluster_swarm,
+            kube,
             current_tag: current_tag.to_string(),
+            push_gateway: PushGateway::from_env(),
+            lock_holder,
+            lock_renewal,
+            cost_rates,
+            results_store: args.results_dir.as_ref().map(ResultsStore::new),
+            checkpoints,
+            run_id,
+            completed,
+            outcomes,
+            resumed_suite,
+            metrics_export: args.export_metrics_dir.as_ref().map(|dir| {
+                MetricsExport::new(dir, args.export_metrics.clone(), args.export_metrics_step)
+            }),
+            allow_version_skew: args.allow_version_skew,
+            skip_failed_preflight: args.skip_failed_preflight,
+            suite_budget: args.suite_budget_secs.map(Duration::from_secs),
+            suite_budget_end: None,
+            dashboard: None,
         })
     }
 
+    /// Shows health of validators and experiments run from now on in the dashboard
+    fn attach_dashboard(&mut self, dashboard: Dashboard) {
+        self.health_check_runner.set_dashboard(dashboard.clone());
+        dashboard.start(self.prometheus.clone());
+        self.dashboard = Some(dashboard);
+    }
+
+    /// Acquires cluster lock and deploys cluster in k8s
+    async fn setup_kube(
+        args: &Args,
+        current_tag: &str,
+        lock_holder: &str,
+    ) -> Result<(Cluster, Prometheus, ClusterSwarmKube, AbortHandle)> {
+        let cluster_swarm = ClusterSwarmKube::new()
+            .await
+            .map_err(|e| format_err!("Failed to initialize ClusterSwarmKube: {}", e))?;
+        cluster_swarm
+            .acquire_lock(
+                lock_holder,
+                Duration::from_secs(args.wait_for_lock.unwrap_or(0)),
+                args.steal_lock,
+            )
+            .await?;
+        let (renewal, lock_renewal) =
+            abortable(renew_lock(cluster_swarm.clone(), lock_holder.to_string()));
+        tokio::spawn(renewal);
+        let grafana_base_url = cluster_swarm
+            .get_grafana_baseurl()
+            .await
+            .expect("Failed to discover grafana url in k8s");
+        let prometheus = Prometheus::new(K8S_PROMETHEUS_HOST, grafana_base_url);
+        let cluster_builder = ClusterBuilder::new(current_tag.to_string(), cluster_swarm.clone());
+        let cluster = cluster_builder
+            .setup_cluster(&args.cluster_builder_params)
+            .await
+            .map_err(|e| format_err!("Failed to setup cluster: {}", e))?;
+        Ok((cluster, prometheus, cluster_swarm, lock_renewal))
+    }
+
     pub fn send_changelog_message(
         &self,
         perf_msg: &str,
@@ -526,6 +1402,48 @@ impl ClusterTestRunner {
         );
         let changelog = self.get_changelog(from_commit.as_ref(), &to_commit);
         self.slack_changelog_message(format!("{}\n\n{}", changelog, perf_msg));
+        let thread = self.slack_thread.as_ref();
+        if let (Some(target), Some(chart)) = (&self.slack_upload, &self.timeline_chart) {
+            let title = format!("TPS and latency of {}", to_commit);
+            if let Err(e) = self
+                .slack
+                .upload_file(target, thread, "timeline.svg", &title, chart)
+            {
+                info!("Failed to upload timeline chart: {}", e);
+            }
+        }
+        if let (Some(target), Some(dot)) = (&self.slack_upload, self.report.topology_dot()) {
+            let title = format!("Topology snapshots of {}", to_commit);
+            if let Err(e) = self
+                .slack
+                .upload_file(target, thread, "topology.dot", &title, &dot)
+            {
+                info!("Failed to upload topology snapshots: {}", e);
+            }
+        }
+        if let (Some(target), Some(text)) =
+            (&self.slack_upload, self.report.consensus_snapshots_text())
+        {
+            let title = format!("Consensus snapshots of {}", to_commit);
+            if let Err(e) = self
+                .slack
+                .upload_file(target, thread, "consensus.txt", &title, &text)
+            {
+                info!("Failed to upload consensus snapshots: {}", e);
+            }
+        }
+        if let (Some(target), Some(path)) = (&self.slack_upload, audit::path()) {
+            let title = format!("Audit log of {}", to_commit);
+            let result = fs::read_to_string(&path)
+                .map_err(|e| format_err!("Failed to read {:?}: {}", path, e))
+                .and_then(|log| {
+                    self.slack
+                        .upload_file(target, thread, "audit.log", &title, &log)
+                });
+            if let Err(e) = result {
+                info!("Failed to upload audit log: {}", e);
+            }
+        }
     }
 
     fn get_changelog(&self, prev_commit: Option<&String>, upstream_commit: &str) -> String {
@@ -556,48 +1474,330 @@ impl ClusterTestRunner {
         }
     }
 
-    async fn run_suite(&mut self, suite: ExperimentSuite) -> Result<()> {
+    async fn run_suite(&mut self, name: &str, suite: ExperimentSuite) -> Result<()> {
         info!("Starting suite");
+        let skew_expected = suite.experiments().any(|e| e.expects_version_skew());
+        self.check_version_skew(skew_expected).await?;
         let suite_started = Instant::now();
-        for experiment in suite.experiments {
-            let experiment_name = format!("{}", experiment);
+        let suite_start_timestamp = unix_timestamp_now();
+        let mut suite_deadline = suite
+            .experiments()
+            .fold(suite_started, |deadline, e| deadline + e.deadline());
+        self.suite_budget_end = suite.budget.map(|budget| suite_started + budget);
+        if let Some(budget_end) = self.suite_budget_end {
+            suite_deadline = min(suite_deadline, budget_end);
+        }
+        self.health_check_runner.set_suite_deadline(suite_deadline);
+        self.start_slack_thread(name);
+        let constrained_ids = suite.constrained_ids();
+        let mut failed = vec![];
+        for entry in suite.entries {
+            let experiment_name = format!("{}", entry.experiment);
+            let text_start = self.report.text_len();
+            let over_budget = self.suite_budget_end.map_or(false, |budget_end| {
+                Instant::now() + entry.experiment.expected_duration() > budget_end
+            });
+            let skip_reason = if over_budget {
+                Some("time budget of the suite is used up".to_string())
+            } else {
+                entry.skip_reason(&self.outcomes)
+            };
+            if let Some(reason) = skip_reason {
+                info!("Skipping {}: {}", experiment_name, reason);
+                self.report.report_text(format!(
+                    "(!) {} was not started: {}",
+                    experiment_name, reason
+                ));
+                self.report.report_metric(&experiment_name, "skipped", 1.0);
+                self.finish_entry(
+                    name,
+                    entry.id,
+                    experiment_name,
+                    Outcome::Skipped,
+                    text_start,
+                );
+                continue;
+            }
             let experiment_result = self
-                .run_single_experiment(experiment, None)
+                .run_single_experiment(entry.experiment, Some(self.global_emit_job_request.clone()))
                 .await
-                .map_err(move |e| format_err!("Experiment `{}` failed: `{}`", experiment_name, e));
-            if let Err(e) = experiment_result.as_ref() {
+                .map_err(|e| {
+                    let message = format!("Experiment `{}` failed: `{}`", experiment_name, e);
+                    e.context(message)
+                });
+            if let Err(e) = experiment_result {
                 self.report.report_text(e.to_string());
+                diagnose::attach_on_liveness_failure(
+                    &self.cluster,
+                    &self.prometheus,
+                    &mut self.report,
+                    &experiment_name,
+                    &e,
+                )
+                .await;
+                self.report.report_metric(&experiment_name, "failed", 1.0);
+                self.report.end_experiment();
+                // Entries depending on this one are skipped, the rest of the suite still runs
+                if constrained_ids.contains(&entry.id) {
+                    warn!("{}", e);
+                    failed.push(experiment_name.clone());
+                    self.finish_entry(name, entry.id, experiment_name, Outcome::Failed, text_start);
+                    continue;
+                }
+                // Not recorded as finished, so that a resumed suite runs it again
+                self.post_entry(&experiment_name, Outcome::Failed, text_start);
+                cost::report_suite_cost(&mut self.report);
+                self.report_scorecard().await;
                 self.print_report();
-                experiment_result?;
+                self.save_report();
+                self.render_timeline_chart(suite_start_timestamp);
+                self.finish_slack_thread(slack::outcome_emoji(Outcome::Failed));
+                return Err(e);
             }
+            self.finish_entry(name, entry.id, experiment_name, Outcome::Passed, text_start);
         }
         info!(
             "Suite completed in {:?}",
             Instant::now().duration_since(suite_started)
         );
+        self.report.end_experiment();
+        cost::report_suite_cost(&mut self.report);
+        self.report_scorecard().await;
         self.print_report();
+        self.save_report();
+        self.render_timeline_chart(suite_start_timestamp);
+        let outcome = if failed.is_empty() {
+            Outcome::Passed
+        } else {
+            Outcome::Failed
+        };
+        self.finish_slack_thread(slack::outcome_emoji(outcome));
+        if !failed.is_empty() {
+            bail!("Experiments failed: {}", failed.join(", "));
+        }
         Ok(())
     }
 
+    /// Records outcome of a suite entry which is not run again when the suite is resumed, and
+    /// posts it to the thread of the run
+    fn finish_entry(
+        &mut self,
+        suite: &str,
+        id: String,
+        experiment_name: String,
+        outcome: Outcome,
+        text_start: usize,
+    ) {
+        self.post_entry(&experiment_name, outcome, text_start);
+        self.completed.push(id.clone());
+        self.outcomes.insert(id, outcome);
+        self.save_checkpoint(suite);
+    }
+
+    /// Posts outcome of a suite entry with text reported since `text_start` to the thread of
+    /// the run
+    fn post_entry(&self, experiment_name: &str, outcome: Outcome, text_start: usize) {
+        if let (Some(target), Some(thread)) = (&self.slack_upload, &self.slack_thread) {
+            let mut text = format!("{} *{}*", slack::outcome_emoji(outcome), experiment_name);
+            for line in self.report.text_since(text_start) {
+                text.push('\n');
+                text.push_str(line);
+            }
+            if let Err(e) = self.slack.post_to_thread(target, thread, &text) {
+                info!("Failed to post {} to slack thread: {}", experiment_name, e);
+            }
+        }
+    }
+
+    /// Thread is only started with a bot token, webhooks can not post into threads
+    fn start_slack_thread(&mut self, suite: &str) {
+        let target = match &self.slack_upload {
+            Some(target) => target,
+            None => return,
+        };
+        let title = format!("Cluster test suite {} on {}", suite, self.current_tag);
+        match self.slack.start_thread(target, &title) {
+            Ok(thread) => self.slack_thread = Some(thread),
+            Err(e) => info!("Failed to start slack thread: {}", e),
+        }
+    }
+
+    /// Replaces running status of the thread with `emoji` and summary of the report
+    fn finish_slack_thread(&self, emoji: &str) {
+        if let (Some(target), Some(thread)) = (&self.slack_upload, &self.slack_thread) {
+            let summary = self.report.summary();
+            if let Err(e) = self.slack.finish_thread(target, thread, emoji, &summary) {
+                info!("Failed to update slack thread {}: {}", thread.title(), e);
+            }
+        }
+    }
+
+    /// Fails if instances run different images, unless skew is expected or allowed, then it is
+    /// only recorded in the report
+    async fn check_version_skew(&mut self, expected: bool) -> Result<()> {
+        let images = version_check::deployed_images(&self.cluster).await;
+        let skew = match version_check::describe_skew(&images) {
+            Some(skew) => skew,
+            None => return Ok(()),
+        };
+        if !expected && !self.allow_version_skew {
+            bail!("{}, use --allow-version-skew if this is intended", skew);
+        }
+        warn!("{}", skew);
+        self.report.report_text(format!("(!) {}", skew));
+        Ok(())
+    }
+
+    /// Rendered while prometheus of the cluster is still available, uploaded with changelog
+    fn render_timeline_chart(&mut self, start: Duration) {
+        if self.slack_upload.is_none() {
+            return;
+        }
+        let timelines = timeline::query_timelines(&self.prometheus, start, unix_timestamp_now());
+        match timeline::render_svg(&timelines) {
+            Ok(svg) => self.timeline_chart = Some(svg),
+            Err(e) => warn!("Failed to render timeline chart: {}", e),
+        }
+    }
+
+    /// Reports that the run was aborted. Effects are reverted on inventory clusters, which
+    /// outlive the run, while k8s clusters are already torn down along with them
+    async fn abort(&mut self) {
+        if self.kube.is_none() {
+            let instances: Vec<_> = self
+                .cluster
+                .validator_instances()
+                .iter()
+                .chain(self.cluster.fullnode_instances())
+                .cloned()
+                .collect();
+            join_all(instances.iter().map(|instance| async move {
+                if let Err(e) = effects::revert_all(instance).await {
+                    warn!("Failed to revert effects on {}: {}", instance, e);
+                }
+            }))
+            .await;
+        }
+        self.report.end_experiment();
+        self.report.report_text("Run aborted".to_string());
+        self.report.report_metric("suite", "aborted", 1.0);
+        self.print_report();
+        self.save_report();
+        self.finish_slack_thread(":octagonal_sign:");
+        self.slack_changelog_message(format!(
+            "*Cluster test run {} aborted*\n{}",
+            self.current_tag,
+            self.report.summary()
+        ));
+    }
+
+    async fn report_scorecard(&mut self) {
+        let scorecard = Scorecard::from_metrics(self.report.metrics());
+        info!("{}", scorecard);
+        self.report.report_text(scorecard.to_string());
+        self.report
+            .report_metric("suite", "score", scorecard.score());
+        push_metrics(
+            &self.push_gateway,
+            &[("suite_score", scorecard.score())],
+            &[("tag", &self.current_tag)],
+        )
+        .await;
+    }
+
     pub fn print_report(&self) {
-        let json_report =
-            serde_json::to_string_pretty(&self.report).expect("Failed to serialize report to json");
-        info!(
-            "\n====json-report-begin===\n{}\n====json-report-end===",
-            json_report
-        );
+        print_json_report(&self.report);
+    }
+
+    fn save_report(&self) {
+        if let Some(results_store) = &self.results_store {
+            if let Err(e) = results_store.record(&self.current_tag, &self.report) {
+                warn!("Failed to save run results: {}", e);
+            }
+        }
+    }
+
+    fn save_checkpoint(&self, suite: &str) {
+        if let Some(checkpoints) = &self.checkpoints {
+            let checkpoint = Checkpoint {
+                run_id: self.run_id.clone(),
+                suite: suite.to_string(),
+                completed: self.completed.clone(),
+                outcomes: self.outcomes.clone(),
+                report: self.report.clone(),
+            };
+            if let Err(e) = checkpoints.save(&checkpoint) {
+                warn!("Failed to save checkpoint: {}", e);
+            }
+        }
     }
 
-    pub async fn run_named_suite(&mut self, name: &str) -> Result<String> {
-        let suite = ExperimentSuite::new_by_name(&self.cluster, name)?;
-        self.run_suite(suite).await?;
-        Ok(self.report.to_string())
+    pub async fn run_named_suite(
+        &mut self,
+        name: &str,
+        include_tags: &[String],
+        exclude_tags: &[String],
+    ) -> Result<String> {
+        let mut suite = ExperimentSuite::new_by_name(&self.cluster, name)?;
+        suite.filter_by_tags(include_tags, exclude_tags);
+        if suite.entries.is_empty() {
+            bail!(
+                "No experiments left in suite {} after filtering by tags",
+                name
+            );
+        }
+        // Entries are matched by id, as filtering by tags and ordering change their positions
+        if let Some(resumed_suite) = &self.resumed_suite {
+            if resumed_suite != name {
+                bail!(
+                    "Run {} was started with suite {}, not {}",
+                    self.run_id,
+                    resumed_suite,
+                    name
+                );
+            }
+            info!(
+                "Resuming run {} after {} completed experiments: {:?}",
+                self.run_id,
+                self.completed.len(),
+                self.completed
+            );
+            let completed = &self.completed;
+            suite.entries.retain(|entry| !completed.contains(&entry.id));
+            if suite.entries.is_empty() {
+                bail!("All experiments of run {} already completed", self.run_id);
+            }
+        }
+        suite.budget = self.suite_budget.or(suite.budget);
+        if let Some(budget) = suite.budget {
+            for entry in suite.fit_to_budget(budget)? {
+                let experiment_name = entry.experiment.to_string();
+                self.report.report_text(format!(
+                    "(!) {} was not started: does not fit into time budget of {} secs",
+                    experiment_name,
+                    budget.as_secs()
+                ));
+                self.report.report_metric(&experiment_name, "skipped", 1.0);
+            }
+            info!(
+                "Suite is expected to take {} secs of its {} secs budget",
+                suite.expected_duration().as_secs(),
+                budget.as_secs()
+            );
+        }
+        self.run_suite(name, suite).await?;
+        Ok(self.report.summary())
     }
 
     pub async fn run_and_report(&mut self, experiment: Box<dyn Experiment>) -> Result<()> {
+        self.check_version_skew(experiment.expects_version_skew())
+            .await?;
         self.run_single_experiment(experiment, Some(self.global_emit_job_request.clone()))
             .await?;
+        self.report.end_experiment();
+        cost::report_suite_cost(&mut self.report);
         self.print_report();
+        self.save_report();
         Ok(())
     }
 
@@ -617,6 +1817,9 @@ impl ClusterTestRunner {
                 s
             );
         }
+        if !self.preflight(experiment.as_ref()).await? {
+            return Ok(());
+        }
 
         info!(
             "{}Starting experiment {}{}{}{}",
@@ -627,10 +1830,82 @@ impl ClusterTestRunner {
             Reset {}
         );
 
-        let deadline = Instant::now() + experiment.deadline();
+        let mut deadline = Instant::now() + experiment.deadline();
+        if let Some(budget_end) = self.suite_budget_end {
+            deadline = min(deadline, budget_end);
+        }
+        let experiment_name = experiment.to_string();
+        let experiment_started = Instant::now();
+        self.report
+            .start_experiment(experiment_name.clone(), experiment.report_section());
+        let affected_validators = experiment.affected_validators();
+        self.report.report_topology(
+            topology::snapshot(
+                &self.cluster,
+                &format!("Before {}", experiment_name),
+                &affected_validators,
+            )
+            .await,
+        );
 
-        self.experiment_loop(experiment, global_emit_job_request, deadline)
-            .await?;
+        if let Some(dashboard) = &self.dashboard {
+            dashboard.start_experiment(experiment_name.clone(), deadline);
+        }
+        topology::watch_experiment(
+            &self.cluster,
+            format!("Faults of {}", experiment_name),
+            &affected_validators,
+        );
+        let window_start = unix_timestamp_now();
+        let result = self
+            .experiment_loop(experiment, global_emit_job_request, deadline)
+            .await;
+        if let Some(dashboard) = &self.dashboard {
+            dashboard.end_experiment();
+        }
+        if let Some(snapshot) = topology::unwatch_experiment() {
+            self.report.report_topology(snapshot);
+        }
+        self.report.report_topology(
+            topology::snapshot(
+                &self.cluster,
+                &format!("End of {}", experiment_name),
+                &affected_validators,
+            )
+            .await,
+        );
+        if let Some(metrics_export) = &self.metrics_export {
+            match metrics_export.export(
+                &self.prometheus,
+                &experiment_name,
+                window_start,
+                unix_timestamp_now(),
+            ) {
+                Ok(path) => info!("Exported metrics of experiment to {:?}", path),
+                Err(e) => warn!("Failed to export metrics of experiment: {}", e),
+            }
+        }
+        push_metrics(
+            &self.push_gateway,
+            &[
+                (
+                    "experiment_duration_secs",
+                    experiment_started.elapsed().as_secs_f64(),
+                ),
+                ("experiment_success", if result.is_ok() { 1.0 } else { 0.0 }),
+            ],
+            &[("experiment", &experiment_name)],
+        )
+        .await;
+        if let Some(cost_rates) = &self.cost_rates {
+            cost_rates.report_experiment_cost(
+                &mut self.report,
+                &self.cluster,
+                &experiment_name,
+                experiment_started.elapsed(),
+            );
+        }
+        result?;
 
         info!(
             "{}Experiment finished, waiting until all affected validators recover{}",
@@ -644,6 +1919,33 @@ impl ClusterTestRunner {
         Ok(())
     }
 
+    /// Checks instances affected by the experiment before it starts. Failure is reported under
+    /// the experiment, which is then skipped if --skip-failed-preflight is set. Returns whether
+    /// the experiment should run
+    async fn preflight(&mut self, experiment: &dyn Experiment) -> Result<bool> {
+        let failures = preflight::run(&self.cluster, &experiment.affected_validators()).await;
+        if failures.is_empty() {
+            return Ok(true);
+        }
+        let failures: Vec<_> = failures.iter().map(ToString::to_string).collect();
+        let experiment_name = experiment.to_string();
+        self.report
+            .start_experiment(experiment_name.clone(), experiment.report_section());
+        self.report
+            .report_metric(&experiment_name, "preflight_failed", 1.0);
+        self.report.report_text(format!(
+            "(!) {} : preflight checks failed, experiment was not started: {}",
+            experiment_name,
+            failures.join(", ")
+        ));
+        if !self.skip_failed_preflight {
+            bail!("Preflight checks failed: {}", failures.join(", "));
+        }
+        warn!("Skipping {} after failed preflight checks", experiment_name);
+        self.report.end_experiment();
+        Ok(false)
+    }
+
     // inner poll loop of run_single_experiment
     // do not use this fn, use run_single_experiment to run experiments
     async fn experiment_loop(
@@ -652,7 +1954,6 @@ impl ClusterTestRunner {
         mut global_emit_job_request: Option<EmitJobRequest>,
         deadline: Instant,
     ) -> Result<()> {
-        let affected_validators = experiment.affected_validators();
         let mut context = Context::new(
             &mut self.tx_emitter,
             &mut self.trace_tail,
@@ -661,84 +1962,59 @@ impl ClusterTestRunner {
             &mut self.report,
             &mut global_emit_job_request,
             self.emit_to_validator,
-            &self.cluster_swarm,
+            self.cluster_swarm.as_ref(),
             &self.current_tag[..],
+            &mut self.warm_state,
         );
-        let mut deadline_future = delay_until(TokioInstant::from_std(deadline)).fuse();
-        let mut run_future = experiment.run(&mut context).fuse();
-        loop {
-            select! {
-                delay = deadline_future => {
-                    bail!("Experiment deadline reached");
-                }
-                result = run_future => {
-                    return result.map_err(|e|format_err!("Failed to run experiment: {}", e));
-                }
-                delay = delay_for(HEALTH_POLL_INTERVAL).fuse() => {
-                    let events = self.logs.recv_all();
-                    if let Err(s) = self.health_check_runner.run(
-                        &events,
-                        &affected_validators,
-                        PrintFailures::UnexpectedOnly,
-                    ).await {
-                        bail!("Validators which were not under experiment failed : {}", s);
-                    }
-                }
-            }
-        }
+        runner::experiment_loop(
+            experiment.as_mut(),
+            &mut context,
+            &mut self.logs,
+            &mut self.health_check_runner,
+            self.push_gateway.as_ref(),
+            deadline,
+        )
+        .await
     }
 
-    async fn wait_until_all_healthy(&mut self, deadline: Instant) -> Result<()> {
-        info!("Waiting for all nodes to be healthy");
-        for instance in self.cluster.validator_instances() {
-            self.health_check_runner.invalidate(instance.peer_name());
-        }
-        loop {
-            let now = Instant::now();
-            if now > deadline {
-                bail!("Nodes did not become healthy after deployment");
-            }
-            let deadline = now + HEALTH_POLL_INTERVAL;
-            let events = self.logs.recv_all_until_deadline(deadline);
-            if let Ok(failed_instances) = self
-                .health_check_runner
-                .run(&events, &HashSet::new(), PrintFailures::None)
-                .await
-            {
-                if failed_instances.is_empty() {
-                    break;
-                }
-            }
-        }
-        info!(
-            "All nodes are now healthy. Checking json rpc endpoints of validators and full nodes"
+    /// Redeploys validators, fullnodes and LSRs with `tag`, keeping their data
+    async fn update_tag(&mut self, tag: &str) -> Result<()> {
+        info!("Updating cluster from {} to {}", self.current_tag, tag);
+        let instances: Vec<_> = self
+            .cluster
+            .validator_and_fullnode_instances()
+            .cloned()
+            .collect();
+        let lsrs = self.cluster.lsr_instances().to_vec();
+        let mut global_emit_job_request = None;
+        let mut context = Context::new(
+            &mut self.tx_emitter,
+            &mut self.trace_tail,
+            &self.prometheus,
+            &self.cluster,
+            &mut self.report,
+            &mut global_emit_job_request,
+            self.emit_to_validator,
+            self.cluster_swarm.as_ref(),
+            &self.current_tag[..],
+            &mut self.warm_state,
         );
-        loop {
-            let results = join_all(
-                self.cluster
-                    .validator_and_fullnode_instances()
-                    .map(Instance::try_json_rpc),
-            )
-            .await;
-
-            if results.iter().all(Result::is_ok) {
-                break;
-            }
-            if Instant::now() > deadline {
-                for (instance, result) in
-                    zip(self.cluster.validator_and_fullnode_instances(), results)
-                {
-                    if let Err(err) = result {
-                        warn!("Instance {} still unhealthy: {}", instance, err);
-                    }
-                }
-                bail!("Some json rpc endpoints did not become healthy after deployment");
-            }
-        }
-        info!("All json rpc endpoints are healthy");
+        update_batch_instance(&mut context, &instances, &lsrs, tag.to_string()).await?;
+        self.cluster = self.cluster.with_image_tag(tag)?;
+        self.current_tag = tag.to_string();
         Ok(())
     }
 
+    async fn wait_until_all_healthy(&mut self, deadline: Instant) -> Result<()> {
+        runner::wait_until_all_healthy(
+            &self.cluster,
+            &mut self.logs,
+            &mut self.health_check_runner,
+            deadline,
+        )
+        .await
+    }
+
     fn slack_changelog_message(&self, msg: String) {
         info!("{}", msg);
         if let Some(ref changelog_url) = self.slack_changelog_url {
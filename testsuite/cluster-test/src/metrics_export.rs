@@ -0,0 +1,108 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Raw results of range queries over the window of each experiment are saved compressed, so
+/// that runs can be re-analyzed offline after Prometheus retention expired
+use crate::prometheus::Prometheus;
+use anyhow::{format_err, Result};
+use flate2::{write::GzEncoder, Compression};
+use libra_logger::warn;
+use serde::Serialize;
+use std::{collections::BTreeMap, fs, io::Write, path::PathBuf, time::Duration};
+
+/// Exported when no queries are given
+pub const DEFAULT_EXPORT_QUERIES: &[&str] = &[
+    "rate(libra_consensus_committed_txns_count[1m])",
+    "rate(libra_consensus_committed_blocks_count[1m])",
+    "libra_consensus_current_round",
+    "rate(libra_consensus_timeout_count[1m])",
+    "rate(libra_consensus_creation_to_commit_s_sum[1m])/rate(libra_consensus_creation_to_commit_s_count[1m])",
+    "libra_core_mempool_index_size",
+    "libra_state_sync_committed_version",
+    "libra_storage_latest_transaction_version",
+    "rate(libra_storage_api_latency_seconds_sum[1m])/rate(libra_storage_api_latency_seconds_count[1m])",
+    "libra_storage_cf_size_bytes",
+];
+
+#[derive(Serialize)]
+struct ExperimentMetrics<'a> {
+    experiment: &'a str,
+    start: u64,
+    end: u64,
+    step: u64,
+    /// `data` of Prometheus response by query
+    results: BTreeMap<&'a str, serde_json::Value>,
+}
+
+pub struct MetricsExport {
+    dir: PathBuf,
+    queries: Vec<String>,
+    step: u64,
+}
+
+impl MetricsExport {
+    pub fn new<P: Into<PathBuf>>(dir: P, queries: Vec<String>, step: u64) -> Self {
+        let queries = if queries.is_empty() {
+            DEFAULT_EXPORT_QUERIES
+                .iter()
+                .map(|q| q.to_string())
+                .collect()
+        } else {
+            queries
+        };
+        Self {
+            dir: dir.into(),
+            queries,
+            step,
+        }
+    }
+
+    /// Written to `<dir>/<start>-<experiment>.json.gz`, queries which fail are left out
+    pub fn export(
+        &self,
+        prometheus: &Prometheus,
+        experiment: &str,
+        start: Duration,
+        end: Duration,
+    ) -> Result<PathBuf> {
+        let mut results = BTreeMap::new();
+        for query in &self.queries {
+            match prometheus.query_range_raw(query.clone(), &start, &end, self.step) {
+                Ok(data) => {
+                    results.insert(query.as_str(), data);
+                }
+                Err(e) => warn!("Failed to export {}: {}", query, e),
+            }
+        }
+        let metrics = ExperimentMetrics {
+            experiment,
+            start: start.as_secs(),
+            end: end.as_secs(),
+            step: self.step,
+            results,
+        };
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format_err!("Failed to create {:?}: {}", self.dir, e))?;
+        let path = self.dir.join(format!(
+            "{}-{}.json.gz",
+            start.as_secs(),
+            file_name(experiment)
+        ));
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(serde_json::to_string(&metrics)?.as_bytes())?;
+        fs::write(&path, encoder.finish()?)
+            .map_err(|e| format_err!("Failed to write {:?}: {}", path, e))?;
+        Ok(path)
+    }
+}
+
+/// Experiment names contain spaces and punctuation
+fn file_name(experiment: &str) -> String {
+    experiment
+        .chars()
+        .take(100)
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
@@ -0,0 +1,120 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Checks run on instances an experiment targets before it injects any faults, so that an
+/// unreachable or broken instance stops the experiment with a clear reason instead of failing it
+/// midway with errors of whichever effect hit the instance first
+use crate::{
+    cluster::Cluster, cluster_swarm::ClusterSwarm, instance::Instance, prometheus::Prometheus,
+};
+use anyhow::Result;
+use futures::future::join_all;
+use libra_logger::info;
+use std::{
+    collections::HashSet,
+    fmt,
+    future::Future,
+    time::{Duration, Instant},
+};
+use tokio::time;
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(30);
+/// Prometheus scrapes every 15 secs, series not seen within this window are considered missing
+const METRICS_WINDOW: Duration = Duration::from_secs(60);
+const METRICS_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+pub struct PreflightFailure {
+    pub peer_name: String,
+    pub check: &'static str,
+    pub error: String,
+}
+
+/// Failed checks of instances with given peer names, empty if all of them passed
+pub async fn run(cluster: &Cluster, peer_names: &HashSet<String>) -> Vec<PreflightFailure> {
+    let instances = cluster
+        .validator_and_fullnode_instances()
+        .filter(|instance| peer_names.contains(instance.peer_name()));
+    join_all(instances.map(check_instance))
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Instances whose metrics Prometheus does not have under labels reported by the cluster
+/// backend. Instances which were just deployed may not be scraped yet, so they are checked again
+/// until `timeout`
+pub async fn check_prometheus_targets(
+    cluster: &Cluster,
+    cluster_swarm: &dyn ClusterSwarm,
+    prometheus: &Prometheus,
+    timeout: Duration,
+) -> Vec<PreflightFailure> {
+    let deadline = Instant::now() + timeout;
+    let mut pending: Vec<_> = cluster.validator_and_fullnode_instances().collect();
+    loop {
+        let mut failures = vec![];
+        let mut missing = vec![];
+        for instance in pending {
+            let labels = cluster_swarm.prometheus_labels(instance);
+            let error = match prometheus.has_series(&labels, METRICS_WINDOW) {
+                Ok(true) => continue,
+                Ok(false) => format!("no series with labels {:?}", labels),
+                Err(e) => e.to_string(),
+            };
+            failures.push(PreflightFailure {
+                peer_name: instance.peer_name().clone(),
+                check: "prometheus",
+                error,
+            });
+            missing.push(instance);
+        }
+        if missing.is_empty() || Instant::now() > deadline {
+            return failures;
+        }
+        info!(
+            "Waiting for Prometheus to scrape {} instances",
+            missing.len()
+        );
+        pending = missing;
+        time::delay_for(METRICS_POLL_INTERVAL).await;
+    }
+}
+
+async fn check_instance(instance: &Instance) -> Vec<PreflightFailure> {
+    // Later checks are not meaningful for instance which can not be reached
+    if let Some(failure) = check(instance, "reachable", instance.exec("true", true)).await {
+        return vec![failure];
+    }
+    let checks = vec![
+        check(instance, "node_binary", instance.check_node_binary()).await,
+        check(instance, "metrics", instance.try_metrics()).await,
+        check(instance, "json_rpc", instance.try_json_rpc()).await,
+    ];
+    checks.into_iter().flatten().collect()
+}
+
+async fn check<F: Future<Output = Result<()>>>(
+    instance: &Instance,
+    check: &'static str,
+    future: F,
+) -> Option<PreflightFailure> {
+    let error = match time::timeout(CHECK_TIMEOUT, future).await {
+        Ok(Ok(())) => return None,
+        Ok(Err(e)) => e.to_string(),
+        Err(_) => format!("timed out after {} secs", CHECK_TIMEOUT.as_secs()),
+    };
+    Some(PreflightFailure {
+        peer_name: instance.peer_name().clone(),
+        check,
+        error,
+    })
+}
+
+impl fmt::Display for PreflightFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}: {}", self.peer_name, self.check, self.error)
+    }
+}
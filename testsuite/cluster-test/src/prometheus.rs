@@ -3,16 +3,25 @@
 
 #![forbid(unsafe_code)]
 
+use crate::util::unix_timestamp_now;
 use anyhow::{bail, format_err, Result};
 use reqwest::Url;
 use serde::Deserialize;
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    time::Duration,
+};
+
+/// Selects validators of clusters deployed by cluster test
+const DEFAULT_VALIDATOR_SELECTOR: &str = "peer_id=~\"val-.*\"";
 
 #[derive(Clone)]
 pub struct Prometheus {
     url: Url,
     client: reqwest::blocking::Client,
     grafana_base_url: Url,
+    /// Label matchers which select metrics of validators, e.g. `peer_id=~"val-.*"`
+    validator_selector: String,
 }
 
 pub struct MatrixResponse {
@@ -36,7 +45,20 @@ impl Prometheus {
             url,
             client,
             grafana_base_url,
+            validator_selector: DEFAULT_VALIDATOR_SELECTOR.to_string(),
+        }
+    }
+
+    /// Validators are selected by labels of each of them, e.g. as configured in inventory
+    pub fn with_validator_labels(mut self, labels: &[Vec<(String, String)>]) -> Self {
+        if let Some(selector) = label_selector(labels) {
+            self.validator_selector = selector;
         }
+        self
+    }
+
+    pub fn validator_selector(&self) -> &str {
+        &self.validator_selector
     }
 
     pub fn link_to_dashboard(&self, start: Duration, end: Duration) -> String {
@@ -48,24 +70,14 @@ impl Prometheus {
         )
     }
 
-    fn query_range(
+    pub fn query_range(
         &self,
         query: String,
         start: &Duration,
         end: &Duration,
         step: u64,
     ) -> Result<MatrixResponse> {
-        let url = self
-            .url
-            .join(&format!(
-                "api/v1/query_range?query={}&start={}&end={}&step={}",
-                query,
-                start.as_secs(),
-                end.as_secs(),
-                step
-            ))
-            .expect("Failed to make query_range url");
-
+        let url = self.query_range_url(query, start, end, step);
         let response = self
             .client
             .get(url.clone())
@@ -88,6 +100,63 @@ impl Prometheus {
             ),
         }
     }
+
+    /// `data` of the response as returned by Prometheus, for exporting
+    pub fn query_range_raw(
+        &self,
+        query: String,
+        start: &Duration,
+        end: &Duration,
+        step: u64,
+    ) -> Result<serde_json::Value> {
+        let url = self.query_range_url(query, start, end, step);
+        let mut response: serde_json::Value = self
+            .client
+            .get(url.clone())
+            .send()
+            .map_err(|e| format_err!("Failed to query prometheus: {:?}", e))?
+            .json()
+            .map_err(|e| {
+                format_err!("Failed to parse prometheus response: {:?}. Url: {}", e, url)
+            })?;
+        if response["status"] != "success" {
+            bail!(
+                "Prometheus query failed: {} {}",
+                response["errorType"],
+                response["error"]
+            );
+        }
+        Ok(response["data"].take())
+    }
+
+    /// Whether Prometheus scraped any series with given labels within the last `window`
+    pub fn has_series(&self, labels: &[(String, String)], window: Duration) -> Result<bool> {
+        let selector: Vec<_> = labels
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, quote_label_value(value)))
+            .collect();
+        let end = unix_timestamp_now();
+        let response = self.query_range(
+            format!("count({{{}}})", selector.join(",")),
+            &end.checked_sub(window).unwrap_or_default(),
+            &end,
+            window.as_secs().max(1),
+        )?;
+        Ok(!response.time_series().is_empty())
+    }
+
+    fn query_range_url(&self, query: String, start: &Duration, end: &Duration, step: u64) -> Url {
+        self.url
+            .join(&format!(
+                "api/v1/query_range?query={}&start={}&end={}&step={}",
+                query,
+                start.as_secs(),
+                end.as_secs(),
+                step
+            ))
+            .expect("Failed to make query_range url")
+    }
+
     pub fn query_range_avg(
         &self,
         query: String,
@@ -100,9 +169,60 @@ impl Prometheus {
             .avg()
             .ok_or_else(|| format_err!("Failed to compute avg"))
     }
+
+    pub fn query_range_max(
+        &self,
+        query: String,
+        start: &Duration,
+        end: &Duration,
+        step: u64,
+    ) -> Result<f64> {
+        let response = self.query_range(query, start, end, step)?;
+        response
+            .max()
+            .ok_or_else(|| format_err!("Failed to compute max"))
+    }
+}
+
+/// Label value as PromQL string literal
+fn quote_label_value(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Matchers which select any of the instances with given labels, by labels all of them have.
+/// None if there are no instances or no label they all have
+fn label_selector(labels: &[Vec<(String, String)>]) -> Option<String> {
+    let mut values: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for (name, value) in labels.iter().flatten() {
+        values
+            .entry(name.as_str())
+            .or_default()
+            .insert(value.as_str());
+    }
+    let matchers: Vec<_> = values
+        .into_iter()
+        .filter(|(name, _)| {
+            labels
+                .iter()
+                .all(|instance| instance.iter().any(|(n, _)| n.as_str() == *name))
+        })
+        .map(|(name, values)| {
+            let pattern: Vec<_> = values.into_iter().map(regex::escape).collect();
+            format!("{}=~{}", name, quote_label_value(&pattern.join("|")))
+        })
+        .collect();
+    if labels.is_empty() || matchers.is_empty() {
+        None
+    } else {
+        Some(matchers.join(","))
+    }
 }
 
 impl MatrixResponse {
+    pub fn time_series(&self) -> &HashMap<String, TimeSeries> {
+        &self.inner
+    }
+
     pub fn avg(&self) -> Option<f64> {
         if self.inner.is_empty() {
             return None;
@@ -121,6 +241,17 @@ impl MatrixResponse {
             Some(sum / (count as f64))
         }
     }
+
+    /// Max value across all time series
+    pub fn max(&self) -> Option<f64> {
+        self.inner
+            .values()
+            .filter_map(TimeSeries::max)
+            .fold(None, |max, v| match max {
+                Some(max) if max >= v => Some(max),
+                _ => Some(v),
+            })
+    }
 }
 
 impl TimeSeries {
@@ -148,6 +279,17 @@ impl TimeSeries {
             Some(sum / (count as f64))
         }
     }
+
+    pub fn max(&self) -> Option<f64> {
+        self.inner
+            .iter()
+            .map(|(_, v)| *v)
+            .filter(|v| v.is_finite())
+            .fold(None, |max, v| match max {
+                Some(max) if max >= v => Some(max),
+                _ => Some(v),
+            })
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -175,6 +317,8 @@ struct PrometheusResult {
 #[derive(Debug, Deserialize)]
 struct PrometheusMetric {
     op: Option<String>,
+    /// Missing on aggregated series
+    #[serde(default)]
     peer_id: String,
 }
 
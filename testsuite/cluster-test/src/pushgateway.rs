@@ -0,0 +1,73 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+use anyhow::{bail, format_err, Result};
+use reqwest::{Client, Url};
+use std::env;
+
+/// Pushes metrics produced by cluster test itself (as opposed to metrics scraped from the
+/// cluster) to prometheus pushgateway, so they can be shown on the same dashboards
+#[derive(Clone)]
+pub struct PushGateway {
+    client: Client,
+    url: Url,
+}
+
+impl PushGateway {
+    pub fn new(base_url: Url) -> Self {
+        let url = base_url
+            .join("metrics/job/cluster_test")
+            .expect("Failed to make pushgateway url");
+        Self {
+            client: Client::new(),
+            url,
+        }
+    }
+
+    /// Returns PushGateway if PUSHGATEWAY_URL is set
+    pub fn from_env() -> Option<Self> {
+        env::var("PUSHGATEWAY_URL")
+            .map(|u| Self::new(u.parse().expect("Failed to parse PUSHGATEWAY_URL")))
+            .ok()
+    }
+
+    /// Pushes gauges with given names and values, all labeled with `labels`
+    pub async fn push(&self, metrics: &[(&str, f64)], labels: &[(&str, &str)]) -> Result<()> {
+        let body = format_metrics(metrics, labels);
+        let response = self
+            .client
+            .post(self.url.clone())
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| format_err!("Failed to push metrics: {:?}", e))?;
+        if !response.status().is_success() {
+            bail!("Pushgateway returned error code: {}", response.status())
+        }
+        Ok(())
+    }
+}
+
+fn format_metrics(metrics: &[(&str, f64)], labels: &[(&str, &str)]) -> String {
+    let labels = if labels.is_empty() {
+        "".to_string()
+    } else {
+        let labels: Vec<_> = labels
+            .iter()
+            .map(|(k, v)| format!("{}=\"{}\"", k, v.replace('\\', "\\\\").replace('"', "\\\"")))
+            .collect();
+        format!("{{{}}}", labels.join(","))
+    };
+    let mut body = String::new();
+    for (name, value) in metrics {
+        body.push_str(&format!(
+            "# TYPE cluster_test_{name} gauge\ncluster_test_{name}{labels} {value}\n",
+            name = name,
+            labels = labels,
+            value = value
+        ));
+    }
+    body
+}
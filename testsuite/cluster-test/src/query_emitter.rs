@@ -0,0 +1,221 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Read load of JSON-RPC queries sent by concurrent readers until the job is stopped. Which
+/// queries are sent is up to the experiment, readers only pace them, back off from failing
+/// endpoints and collect latencies per kind of query
+use anyhow::{bail, format_err, Result};
+use async_trait::async_trait;
+use futures::future::join_all;
+use libra_json_rpc_client::{JsonRpcAsyncClient, JsonRpcBatch, JsonRpcResponse};
+use libra_logger::warn;
+use libra_types::account_address::AccountAddress;
+use std::{
+    collections::BTreeMap,
+    mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{task::JoinHandle, time};
+
+/// Delay of a reader after its first failed query, doubled on every next failure in a row
+const MIN_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+#[async_trait]
+pub trait QueryGenerator: Send + Sync {
+    /// Adds next query of a reader to the batch, returns its kind which stats are kept for
+    async fn add_query(
+        &self,
+        client: &JsonRpcAsyncClient,
+        batch: &mut JsonRpcBatch,
+    ) -> Result<&'static str>;
+}
+
+/// Latencies in ms of successful queries and number of failed ones, per query kind
+#[derive(Default)]
+pub struct QueryStats {
+    pub latencies: BTreeMap<&'static str, Vec<u64>>,
+    pub errors: BTreeMap<&'static str, u64>,
+}
+
+impl QueryStats {
+    pub fn merge(&mut self, other: QueryStats) {
+        for (kind, mut latencies) in other.latencies {
+            self.latencies
+                .entry(kind)
+                .or_default()
+                .append(&mut latencies);
+        }
+        for (kind, errors) in other.errors {
+            // Kinds which only failed are reported as well
+            self.latencies.entry(kind).or_default();
+            *self.errors.entry(kind).or_default() += errors;
+        }
+    }
+
+    /// Latencies of queries of all kinds, sorted
+    pub fn sorted_latencies(&self) -> Vec<u64> {
+        let mut latencies: Vec<_> = self.latencies.values().flatten().copied().collect();
+        latencies.sort_unstable();
+        latencies
+    }
+
+    pub fn total_errors(&self) -> u64 {
+        self.errors.values().sum()
+    }
+}
+
+/// Readers are stopped when the job is dropped, so that an experiment failing while the job
+/// runs does not leave them querying
+pub struct QueryJob {
+    stop: Arc<AtomicBool>,
+    readers: Vec<JoinHandle<QueryStats>>,
+}
+
+impl QueryJob {
+    /// Starts `readers` readers per client. Each of them sends a query every `period` if set,
+    /// otherwise the next one as soon as the previous one is answered
+    pub fn start(
+        clients: &[JsonRpcAsyncClient],
+        readers: usize,
+        generator: Arc<dyn QueryGenerator>,
+        period: Option<Duration>,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let readers = clients
+            .iter()
+            .flat_map(|client| (0..readers).map(move |_| client.clone()))
+            .map(|client| spawn_reader(client, generator.clone(), period, stop.clone()))
+            .collect();
+        Self { stop, readers }
+    }
+
+    pub async fn stop(mut self) -> QueryStats {
+        self.stop.store(true, Ordering::Relaxed);
+        let mut stats = QueryStats::default();
+        for reader in join_all(mem::take(&mut self.readers)).await {
+            stats.merge(reader.expect("Reader task failed"));
+        }
+        stats
+    }
+}
+
+impl Drop for QueryJob {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn spawn_reader(
+    client: JsonRpcAsyncClient,
+    generator: Arc<dyn QueryGenerator>,
+    period: Option<Duration>,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<QueryStats> {
+    tokio::spawn(async move {
+        let mut stats = QueryStats::default();
+        let mut interval = period.map(time::interval);
+        let mut backoff = None;
+        while !stop.load(Ordering::Relaxed) {
+            if let Some(delay) = backoff {
+                time::delay_for(delay).await;
+            }
+            if let Some(interval) = interval.as_mut() {
+                interval.tick().await;
+            }
+            let mut batch = JsonRpcBatch::new();
+            let kind = match generator.add_query(&client, &mut batch).await {
+                Ok(kind) => kind,
+                Err(e) => {
+                    warn!("[{:?}] Failed to generate query: {}", client, e);
+                    backoff = Some(next_backoff(backoff));
+                    continue;
+                }
+            };
+            let started = Instant::now();
+            let result = client.execute(batch).await;
+            let error = match result.map(|mut r| r.remove(0)) {
+                Ok(Ok(_)) => {
+                    stats
+                        .latencies
+                        .entry(kind)
+                        .or_default()
+                        .push(started.elapsed().as_millis() as u64);
+                    backoff = None;
+                    continue;
+                }
+                Ok(Err(e)) => format!("{}", e),
+                Err(e) => format!("{:?}", e),
+            };
+            warn!("[{:?}] {} failed: {}", client, kind, error);
+            *stats.errors.entry(kind).or_default() += 1;
+            backoff = Some(next_backoff(backoff));
+        }
+        stats
+    })
+}
+
+fn next_backoff(backoff: Option<Duration>) -> Duration {
+    match backoff {
+        Some(backoff) => (backoff * 2).min(MAX_BACKOFF),
+        None => MIN_BACKOFF,
+    }
+}
+
+/// Key of payments sent by the account
+pub async fn query_sent_events_key(
+    client: &JsonRpcAsyncClient,
+    address: AccountAddress,
+) -> Result<String> {
+    let account = client
+        .get_accounts(&[address])
+        .await
+        .map_err(|e| format_err!("[{:?}] get_accounts failed: {:?}", client, e))?
+        .remove(0)
+        .ok_or_else(|| format_err!("Account {} does not exist", address))?;
+    Ok(account.sent_events_key.0)
+}
+
+pub async fn query_latest_version(client: &JsonRpcAsyncClient) -> Result<u64> {
+    let mut batch = JsonRpcBatch::new();
+    batch.add_get_metadata_request(None);
+    match client.execute(batch).await?.remove(0)? {
+        JsonRpcResponse::BlockMetadataResponse(metadata) => Ok(metadata.version),
+        other => bail!("Unexpected response for get_metadata: {:?}", other),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let mut backoff = None;
+        let mut delays = vec![];
+        for _ in 0..8 {
+            backoff = Some(next_backoff(backoff));
+            delays.push(backoff.unwrap().as_millis());
+        }
+        assert_eq!(delays, vec![100, 200, 400, 800, 1600, 3200, 5000, 5000]);
+    }
+
+    #[test]
+    fn test_merge_keeps_failed_kinds() {
+        let mut stats = QueryStats::default();
+        stats.latencies.insert("get_events", vec![3, 1]);
+        let mut other = QueryStats::default();
+        other.latencies.insert("get_events", vec![2]);
+        other.errors.insert("get_transactions", 4);
+        stats.merge(other);
+        assert_eq!(stats.sorted_latencies(), vec![1, 2, 3]);
+        assert_eq!(stats.total_errors(), 4);
+        assert!(stats.latencies["get_transactions"].is_empty());
+    }
+}
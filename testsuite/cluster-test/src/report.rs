@@ -1,17 +1,73 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::tx_emitter::TxStats;
-use serde::Serialize;
-use std::{fmt, time::Duration};
+use crate::{
+    cluster::Cluster,
+    diagnose::ConsensusSnapshot,
+    slo::LatencySlo,
+    topology::TopologySnapshot,
+    tx_emitter::{TxStats, FULLNODE_GROUP, VALIDATOR_GROUP},
+    util::unix_timestamp_now,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::{self, Write},
+    time::Duration,
+};
 
-#[derive(Default, Debug, Serialize)]
+#[derive(Clone, Default, Debug, Deserialize, Serialize)]
 pub struct SuiteReport {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metadata: Option<RunMetadata>,
+    /// Section of each experiment which was started
+    sections: BTreeMap<String, ReportSection>,
     metrics: Vec<ReportedMetric>,
+    text: Vec<ReportedText>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    topologies: Vec<TopologySnapshot>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    consensus_snapshots: Vec<ConsensusSnapshot>,
+    #[serde(skip)]
+    current_section: Option<ReportSection>,
+    /// Evaluated per minute of every workload reported with `report_txn_stats`
+    #[serde(skip)]
+    latency_slos: Vec<LatencySlo>,
+}
+
+/// Section of the report which metrics and text of an experiment belong to. Critical
+/// experiments guard SLOs, summary leads with pass or fail of each of them
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportSection {
+    Critical,
+    Performance,
+    /// Collapsed in summary
+    Informational,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct ReportedText {
+    /// Not set for text reported by the runner outside of experiments, e.g. scorecard
+    section: Option<ReportSection>,
     text: String,
 }
 
-#[derive(Debug, Serialize)]
+/// What a run was testing and who started it, captured at the start of the run
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RunMetadata {
+    pub cluster: String,
+    /// Image tag of each instance by peer name
+    pub image_tags: BTreeMap<String, String>,
+    /// Commit cluster test was built from, taken from `GIT_REV` set in cluster test image
+    pub runner_commit: Option<String>,
+    pub initiator: String,
+    /// Unix timestamp of the start of the run in secs
+    pub started: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ReportedMetric {
     pub experiment: String,
     pub metric: String,
@@ -36,11 +92,239 @@ impl SuiteReport {
         });
     }
 
+    /// Reports `(metric, baseline, variant)` values as `baseline_{metric}` and
+    /// `{variant}_{metric}` with their difference as `{variant}_delta_pct_{metric}`. Baseline is
+    /// reported once per experiment, so that several variants can be compared to it. Returns
+    /// text lines of the comparison
+    pub fn report_comparison<E: ToString>(
+        &mut self,
+        experiment: E,
+        variant: &str,
+        metrics: &[(&str, f64, f64)],
+    ) -> String {
+        let experiment = experiment.to_string();
+        let mut text = String::new();
+        for (metric, a, b) in metrics {
+            let baseline_metric = format!("baseline_{}", metric);
+            if !self
+                .metrics
+                .iter()
+                .any(|m| m.experiment == experiment && m.metric == baseline_metric)
+            {
+                self.report_metric(&experiment, baseline_metric, *a);
+            }
+            self.report_metric(&experiment, format!("{}_{}", variant, metric), *b);
+            text.push_str(&format!("\n  {}: {:.1} -> {:.1}", metric, a, b));
+            if *a != 0.0 {
+                let delta = (b - a) * 100.0 / a;
+                self.report_metric(
+                    &experiment,
+                    format!("{}_delta_pct_{}", variant, metric),
+                    delta,
+                );
+                text.push_str(&format!(" ({:+.1}%)", delta));
+            }
+        }
+        text
+    }
+
+    pub fn metrics(&self) -> &[ReportedMetric] {
+        &self.metrics
+    }
+
+    pub fn set_metadata(&mut self, metadata: RunMetadata) {
+        self.metadata = Some(metadata);
+    }
+
+    pub fn metadata(&self) -> Option<&RunMetadata> {
+        self.metadata.as_ref()
+    }
+
+    pub fn set_latency_slos(&mut self, slos: Vec<LatencySlo>) {
+        self.latency_slos = slos;
+    }
+
+    /// Text reported until `end_experiment` belongs to the section of this experiment
+    pub fn start_experiment(&mut self, experiment: String, section: ReportSection) {
+        self.sections.insert(experiment, section);
+        self.current_section = Some(section);
+    }
+
+    pub fn end_experiment(&mut self) {
+        self.current_section = None;
+    }
+
     pub fn report_text(&mut self, text: String) {
-        if !self.text.is_empty() {
-            self.text.push_str("\n");
+        self.text.push(ReportedText {
+            section: self.current_section,
+            text,
+        });
+    }
+
+    /// Number of text lines reported so far, marks where text of the next experiment starts
+    pub fn text_len(&self) -> usize {
+        self.text.len()
+    }
+
+    /// Text lines reported since `start` returned by `text_len`
+    pub fn text_since(&self, start: usize) -> Vec<&str> {
+        self.text[start.min(self.text.len())..]
+            .iter()
+            .map(|t| t.text.as_str())
+            .collect()
+    }
+
+    /// Latency added by submitting through fullnodes instead of directly to validators, when
+    /// the same workload was split between both. Ack part is JSON-RPC and mempool admission of
+    /// the fullnode, ack to commit part includes relaying transactions to validators
+    fn report_relay_overhead(&mut self, experiment: &str, stats: &TxStats, window: Duration) {
+        let (fullnodes, validators) = match (
+            stats.groups.get(FULLNODE_GROUP),
+            stats.groups.get(VALIDATOR_GROUP),
+        ) {
+            (Some(fullnodes), Some(validators))
+                if fullnodes.committed > 0 && validators.committed > 0 =>
+            {
+                (fullnodes.rate(window), validators.rate(window))
+            }
+            _ => return,
+        };
+        let overhead = |fullnode: u64, validator: u64| fullnode as i64 - validator as i64;
+        let avg = overhead(fullnodes.latency, validators.latency);
+        let p99 = overhead(fullnodes.p99_latency, validators.p99_latency);
+        let ack = overhead(fullnodes.ack_latency, validators.ack_latency);
+        let ack_to_commit = overhead(
+            fullnodes.ack_to_commit_latency,
+            validators.ack_to_commit_latency,
+        );
+        let metrics = [
+            ("fullnode_overhead_avg_latency", avg),
+            ("fullnode_overhead_p99_latency", p99),
+            ("fullnode_overhead_ack_latency", ack),
+            ("fullnode_overhead_ack_to_commit_latency", ack_to_commit),
+        ];
+        for (metric, value) in metrics.iter() {
+            self.report_metric(experiment, metric, *value as f64);
+        }
+        let percent = if validators.latency > 0 {
+            let percent = avg as f64 * 100.0 / validators.latency as f64;
+            self.report_metric(experiment, "fullnode_overhead_latency_percent", percent);
+            format!(" ({:+.0}%)", percent)
+        } else {
+            String::new()
+        };
+        self.report_text(format!(
+            "{} : submitting through fullnodes adds {:+} ms{} to avg latency and {:+} ms to p99 latency compared to validators, {:+} ms until ack and {:+} ms from ack to commit",
+            experiment, avg, percent, p99, ack, ack_to_commit
+        ));
+    }
+
+    pub fn report_topology(&mut self, snapshot: TopologySnapshot) {
+        self.report_text(format!("Topology snapshot: {}", snapshot.title));
+        self.topologies.push(snapshot);
+    }
+
+    /// Table of the snapshot goes to text of the current experiment as well
+    pub fn report_consensus_snapshot(&mut self, snapshot: ConsensusSnapshot) {
+        self.report_text(snapshot.to_string().trim_end().to_string());
+        self.consensus_snapshots.push(snapshot);
+    }
+
+    /// All consensus snapshots as one text file
+    pub fn consensus_snapshots_text(&self) -> Option<String> {
+        if self.consensus_snapshots.is_empty() {
+            return None;
+        }
+        Some(
+            self.consensus_snapshots
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+    }
+
+    /// All topology snapshots as one DOT file, graphviz renders each graph of it
+    pub fn topology_dot(&self) -> Option<String> {
+        if self.topologies.is_empty() {
+            return None;
         }
-        self.text.push_str(&text);
+        Some(
+            self.topologies
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+    }
+
+    /// Short form of the report for chat, informational sections are collapsed
+    pub fn summary(&self) -> String {
+        self.render(true)
+    }
+
+    fn render(&self, collapse_informational: bool) -> String {
+        let mut out = String::new();
+        if let Some(metadata) = &self.metadata {
+            writeln!(out, "Run: {}", metadata).unwrap();
+        }
+        let critical: Vec<_> = self
+            .sections
+            .iter()
+            .filter(|(_, section)| **section == ReportSection::Critical)
+            .map(|(experiment, _)| (experiment, self.failed(experiment)))
+            .collect();
+        if !critical.is_empty() {
+            let failed = critical.iter().filter(|(_, failed)| *failed).count();
+            writeln!(
+                out,
+                "Critical SLOs: {} passed, {} failed",
+                critical.len() - failed,
+                failed
+            )
+            .unwrap();
+            for (experiment, failed) in &critical {
+                let result = if *failed { "(!) FAIL" } else { "PASS" };
+                writeln!(out, "  {} {}", result, experiment).unwrap();
+            }
+            self.render_text(&mut out, Some(ReportSection::Critical));
+        }
+        self.render_text(&mut out, None);
+        for (section, title) in &[
+            (ReportSection::Performance, "Performance"),
+            (ReportSection::Informational, "Informational"),
+        ] {
+            let lines = self.text_of(Some(*section)).count();
+            if lines == 0 {
+                continue;
+            }
+            if collapse_informational && *section == ReportSection::Informational {
+                writeln!(out, "{}: {} lines, see full report", title, lines).unwrap();
+            } else {
+                writeln!(out, "{}:", title).unwrap();
+                self.render_text(&mut out, Some(*section));
+            }
+        }
+        out.trim_end().to_string()
+    }
+
+    fn render_text(&self, out: &mut String, section: Option<ReportSection>) {
+        for text in self.text_of(section) {
+            writeln!(out, "{}", text).unwrap();
+        }
+    }
+
+    fn text_of(&self, section: Option<ReportSection>) -> impl Iterator<Item = &String> {
+        self.text
+            .iter()
+            .filter(move |t| t.section == section)
+            .map(|t| &t.text)
+    }
+
+    fn failed(&self, experiment: &str) -> bool {
+        self.metrics
+            .iter()
+            .any(|m| m.experiment == experiment && m.metric == "failed" && m.value > 0.0)
     }
 
     pub fn report_txn_stats(&mut self, experiment: String, stats: TxStats, window: Duration) {
@@ -55,9 +339,230 @@ impl SuiteReport {
         let p99_latency = stats.latency_buckets.percentile(99, 100);
         self.report_metric(experiment.clone(), "submitted_txn", submitted_txn as f64);
         self.report_metric(experiment.clone(), "expired_txn", expired_txn as f64);
+        self.report_metric(experiment.clone(), "committed_txn", stats.committed as f64);
         self.report_metric(experiment.clone(), "avg_tps", avg_tps as f64);
         self.report_metric(experiment.clone(), "avg_latency", avg_latency_client as f64);
         self.report_metric(experiment.clone(), "p99_latency", p99_latency as f64);
+        if stats.ack_latency_samples > 0 {
+            // Separates regressions of JSON-RPC and mempool from ones of consensus and execution
+            let rate = stats.rate(window);
+            let metrics = [
+                ("avg_ack_latency", rate.ack_latency),
+                ("p99_ack_latency", rate.p99_ack_latency),
+                ("avg_ack_to_commit_latency", rate.ack_to_commit_latency),
+                ("p99_ack_to_commit_latency", rate.p99_ack_to_commit_latency),
+            ];
+            for (metric, value) in metrics.iter() {
+                self.report_metric(experiment.clone(), *metric, *value as f64);
+            }
+            self.report_text(format!(
+                "{} : submission to ack {} ms, p99 {} ms, ack to commit {} ms, p99 {} ms",
+                experiment,
+                rate.ack_latency,
+                rate.p99_ack_latency,
+                rate.ack_to_commit_latency,
+                rate.p99_ack_to_commit_latency
+            ));
+        }
+        if stats.commit_latency_samples > 0 {
+            self.report_metric(
+                experiment.clone(),
+                "avg_commit_latency",
+                (stats.commit_latency / stats.commit_latency_samples) as f64,
+            );
+        }
+        if !stats.clock_offsets.is_empty() {
+            self.report_metric(
+                experiment.clone(),
+                "max_clock_offset_ms",
+                stats.max_clock_offset() as f64,
+            );
+        }
+        self.report_metric(
+            experiment.clone(),
+            "setup_secs",
+            stats.setup_duration.as_secs_f64(),
+        );
+        self.report_metric(experiment.clone(), "json_rpc_retries", stats.retries as f64);
+        self.report_metric(
+            experiment.clone(),
+            "json_rpc_failovers",
+            stats.failovers as f64,
+        );
+        if stats.admin_submitted > 0 {
+            self.report_metric(
+                experiment.clone(),
+                "admin_submitted_txn",
+                stats.admin_submitted as f64,
+            );
+            self.report_metric(
+                experiment.clone(),
+                "admin_committed_txn",
+                stats.admin_committed as f64,
+            );
+        }
+        if stats.topups > 0 {
+            self.report_metric(
+                experiment.clone(),
+                "topped_up_accounts",
+                stats.topups as f64,
+            );
+        }
+        if stats.storm_resubmitted > 0 {
+            self.report_metric(
+                experiment.clone(),
+                "storm_resubmitted_txn",
+                stats.storm_resubmitted as f64,
+            );
+            self.report_metric(
+                experiment.clone(),
+                "storm_duplicates_rejected",
+                stats.storm_duplicates_rejected as f64,
+            );
+        }
+        // Mempool ordering regressions starve some accounts while aggregate TPS stays the same
+        if let (Some((account, latency)), Some(gini)) = (
+            stats.worst_served_account(),
+            stats.account_throughput_gini(),
+        ) {
+            let starved = stats.starved_accounts();
+            self.report_metric(experiment.clone(), "worst_account_latency", latency as f64);
+            self.report_metric(experiment.clone(), "account_throughput_gini", gini);
+            self.report_metric(experiment.clone(), "starved_accounts", starved as f64);
+            self.report_text(format!(
+                "{} : worst served account {} at {} ms average latency of {} ms overall, gini of per-account throughput {:.3}, {} of {} accounts starved",
+                experiment,
+                account,
+                latency,
+                avg_latency_client,
+                gini,
+                starved,
+                stats.accounts.len()
+            ));
+        }
+        for (txn_type, gas) in &stats.gas {
+            self.report_metric(
+                experiment.clone(),
+                format!("avg_gas_{}", txn_type),
+                gas.avg_gas_used() as f64,
+            );
+        }
+        let total_gas = stats.total_gas();
+        if total_gas.samples > 0 {
+            self.report_metric(
+                experiment.clone(),
+                "avg_gas",
+                total_gas.avg_gas_used() as f64,
+            );
+        }
+        for (group, group_stats) in &stats.groups {
+            let rate = group_stats.rate(window);
+            self.report_metric(
+                experiment.clone(),
+                format!("{}_avg_tps", group),
+                rate.committed as f64,
+            );
+            self.report_metric(
+                experiment.clone(),
+                format!("{}_avg_latency", group),
+                rate.latency as f64,
+            );
+            self.report_metric(
+                experiment.clone(),
+                format!("{}_p99_latency", group),
+                rate.p99_latency as f64,
+            );
+            self.report_metric(
+                experiment.clone(),
+                format!("{}_expired_txn", group),
+                group_stats.expired as f64,
+            );
+            self.report_text(format!(
+                "{} via {} : {} TPS, {} ms latency, {} ms p99 latency, expired {} txns",
+                experiment,
+                group,
+                rate.committed,
+                rate.latency,
+                rate.p99_latency,
+                group_stats.expired
+            ));
+        }
+        self.report_relay_overhead(&experiment, &stats, window);
+        // Spread of accepted load and ack latency over endpoints shows nodes slower than others
+        if stats.endpoints.len() > 1 {
+            let mut lines = vec![];
+            for (endpoint, endpoint_stats) in &stats.endpoints {
+                let accepted_tps = endpoint_stats.accepted / window.as_secs();
+                self.report_metric(
+                    experiment.clone(),
+                    format!("{}_accepted_tps", endpoint),
+                    accepted_tps as f64,
+                );
+                self.report_metric(
+                    experiment.clone(),
+                    format!("{}_avg_ack_latency", endpoint),
+                    endpoint_stats.avg_ack_latency() as f64,
+                );
+                lines.push(format!(
+                    "{} {} TPS accepted, {} ms to ack, {} rejected, {} submit errors",
+                    endpoint,
+                    accepted_tps,
+                    endpoint_stats.avg_ack_latency(),
+                    endpoint_stats.rejected,
+                    endpoint_stats.submit_errors
+                ));
+            }
+            self.report_text(format!(
+                "{} per endpoint : {}",
+                experiment,
+                lines.join("; ")
+            ));
+        }
+        // Under adaptive rate mempool-full rejections are the signal and the rate endpoints
+        // converged to is the capacity of the cluster
+        let rates: Vec<_> = stats
+            .endpoints
+            .iter()
+            .filter_map(|(name, endpoint)| endpoint.rate.map(|rate| (name, endpoint, rate)))
+            .collect();
+        if !rates.is_empty() {
+            let mut lines = vec![];
+            for (endpoint, endpoint_stats, rate) in rates {
+                self.report_metric(
+                    experiment.clone(),
+                    format!("{}_backpressured_txn", endpoint),
+                    endpoint_stats.backpressured as f64,
+                );
+                let converged = match rate.converged_tps {
+                    Some(tps) => {
+                        self.report_metric(
+                            experiment.clone(),
+                            format!("{}_converged_tps", endpoint),
+                            tps,
+                        );
+                        format!("converged to {:.0} TPS", tps)
+                    }
+                    None => format!("(!) no back-pressure up to {:.0} TPS", rate.tps),
+                };
+                lines.push(format!(
+                    "{} {}, {} rejected as mempool full, {} rate cuts",
+                    endpoint, converged, endpoint_stats.backpressured, rate.decreases
+                ));
+            }
+            let capacity = match stats.measured_capacity() {
+                Some(capacity) => {
+                    self.report_metric(experiment.clone(), "measured_capacity_tps", capacity);
+                    format!("{:.0} TPS", capacity)
+                }
+                None => "not reached on every endpoint".to_string(),
+            };
+            self.report_text(format!(
+                "{} adaptive rate : capacity {}, {}",
+                experiment,
+                capacity,
+                lines.join("; ")
+            ));
+        }
         let expired_text = if expired_txn == 0 {
             "no expired txns".to_string()
         } else {
@@ -67,11 +572,196 @@ impl SuiteReport {
             "{} : {:.0} TPS, {:.1} ms latency, {:.1} ms p99 latency, {}",
             experiment, avg_tps, avg_latency_client, p99_latency, expired_text
         ));
+        let slo_results: Vec<_> = self
+            .latency_slos
+            .iter()
+            .map(|slo| slo.evaluate(&stats.minute_latencies, window))
+            .collect();
+        for result in slo_results {
+            let prefix = result.slo.metric_prefix();
+            self.report_metric(
+                experiment.clone(),
+                format!("{}_breached_minutes", prefix),
+                result.breached_minutes as f64,
+            );
+            if let Some((_, latency)) = result.worst_minute {
+                self.report_metric(
+                    experiment.clone(),
+                    format!("{}_worst_minute_ms", prefix),
+                    latency as f64,
+                );
+            }
+            let mark = if result.breached_minutes > 0 {
+                "(!) "
+            } else {
+                ""
+            };
+            self.report_text(format!("{}{} : {}", mark, experiment, result));
+        }
+        if let Some(target_tps) = stats.unreachable_target_tps {
+            self.report_metric(
+                experiment.clone(),
+                "unreachable_target_tps",
+                target_tps as f64,
+            );
+            self.report_text(format!(
+                "(!) {} : target of {} TPS was not reached by emitter",
+                experiment, target_tps
+            ));
+        }
+        if let Some(utilization) = stats.emitter_cpu_utilization() {
+            self.report_metric(
+                experiment.clone(),
+                "emitter_cpu_percent",
+                utilization * 100.0,
+            );
+        }
+        self.report_metric(
+            experiment.clone(),
+            "emitter_submit_errors",
+            stats.submit_errors as f64,
+        );
+        // Numbers limited by the load generator would understate throughput of the cluster
+        let bottlenecks = stats.emitter_bottlenecks();
+        if !bottlenecks.is_empty() {
+            self.report_metric(experiment.clone(), "emitter_bound", 1.0);
+            self.report_text(format!(
+                "(!) {} : results are likely limited by the emitter, not the cluster: {}",
+                experiment,
+                bottlenecks.join(", ")
+            ));
+        }
+    }
+}
+
+impl RunMetadata {
+    pub fn capture(cluster_name: String, cluster: &Cluster, initiator: String) -> Self {
+        let image_tags = cluster
+            .validator_and_fullnode_instances()
+            .filter_map(|i| Some((i.peer_name().clone(), i.image_tag()?.to_string())))
+            .collect();
+        Self {
+            cluster: cluster_name,
+            image_tags,
+            runner_commit: env::var("GIT_REV").ok(),
+            initiator,
+            started: unix_timestamp_now().as_secs(),
+        }
+    }
+}
+
+impl fmt::Display for RunMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Instances usually share few tags, so they are listed with counts instead of per peer
+        let mut tags: BTreeMap<&str, usize> = BTreeMap::new();
+        for tag in self.image_tags.values() {
+            *tags.entry(tag).or_default() += 1;
+        }
+        let tags: Vec<_> = tags
+            .iter()
+            .map(|(tag, count)| format!("{} ({} instances)", tag, count))
+            .collect();
+        write!(
+            f,
+            "cluster {}, images {}, runner {}, started by {}",
+            self.cluster,
+            tags.join(", "),
+            self.runner_commit.as_deref().unwrap_or("unknown"),
+            self.initiator
+        )
     }
 }
 
 impl fmt::Display for SuiteReport {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.text)
+        write!(f, "{}", self.render(false))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let mut report = SuiteReport::new();
+        report.start_experiment("reboot".to_string(), ReportSection::Critical);
+        report.report_text("reboot failed".to_string());
+        report.report_metric("reboot", "failed", 1.0);
+        report.start_experiment("bench".to_string(), ReportSection::Performance);
+        report.report_text("bench: 1000 TPS".to_string());
+        report.start_experiment("flamegraph".to_string(), ReportSection::Informational);
+        report.report_text("flamegraph uploaded".to_string());
+        report.end_experiment();
+        report.report_text("score 80".to_string());
+
+        assert_eq!(
+            report.summary(),
+            "Critical SLOs: 0 passed, 1 failed\n  (!) FAIL reboot\nreboot failed\nscore 80\n\
+             Performance:\nbench: 1000 TPS\nInformational: 1 lines, see full report"
+        );
+        assert!(report
+            .to_string()
+            .ends_with("Informational:\nflamegraph uploaded"));
+    }
+
+    #[test]
+    fn test_report_comparison() {
+        let mut report = SuiteReport::new();
+        let text = report.report_comparison("ab", "low", &[("tps", 100.0, 90.0)]);
+        assert_eq!(text, "\n  tps: 100.0 -> 90.0 (-10.0%)");
+        report.report_comparison("ab", "high", &[("tps", 100.0, 0.0)]);
+        let metrics: Vec<_> = report
+            .metrics()
+            .iter()
+            .map(|m| (m.metric.as_str(), m.value))
+            .collect();
+        assert_eq!(
+            metrics,
+            vec![
+                ("baseline_tps", 100.0),
+                ("low_tps", 90.0),
+                ("low_delta_pct_tps", -10.0),
+                ("high_tps", 0.0),
+                ("high_delta_pct_tps", -100.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_relay_overhead() {
+        let group = |latency: u64| TxStats {
+            committed: 600,
+            latency: 600 * latency,
+            ack_latency: 600 * latency / 10,
+            ack_latency_samples: 600,
+            ack_to_commit_latency: 600 * latency * 9 / 10,
+            ..Default::default()
+        };
+        let mut stats = TxStats::default();
+        stats.groups.insert(FULLNODE_GROUP, group(1500));
+        stats.groups.insert(VALIDATOR_GROUP, group(1000));
+        let mut report = SuiteReport::new();
+        report.report_relay_overhead("bench", &stats, Duration::from_secs(60));
+
+        let metric = |name: &str| {
+            report
+                .metrics()
+                .iter()
+                .find(|m| m.metric == name)
+                .map(|m| m.value)
+        };
+        assert_eq!(metric("fullnode_overhead_avg_latency"), Some(500.0));
+        assert_eq!(metric("fullnode_overhead_ack_latency"), Some(50.0));
+        assert_eq!(
+            metric("fullnode_overhead_ack_to_commit_latency"),
+            Some(450.0)
+        );
+        assert_eq!(metric("fullnode_overhead_latency_percent"), Some(50.0));
+
+        stats.groups.remove(VALIDATOR_GROUP);
+        let mut report = SuiteReport::new();
+        report.report_relay_overhead("bench", &stats, Duration::from_secs(60));
+        assert!(report.metrics().is_empty());
     }
 }
@@ -0,0 +1,273 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// JSON-RPC client layer shared by emitter workers. Failed requests are retried with
+/// exponential backoff and jitter, and go to replicas while own endpoint of the worker is
+/// unavailable. Each endpoint has a circuit breaker shared by all workers using it, so that a
+/// flaky endpoint is skipped instead of stalling every worker submitting to it
+use anyhow::{bail, format_err, Result};
+use libra_json_rpc_client::{
+    views::AccountView, JsonRpcAsyncClient, JsonRpcAsyncClientError, JsonRpcBatch, JsonRpcResponse,
+};
+use libra_logger::warn;
+use libra_types::{account_address::AccountAddress, transaction::SignedTransaction};
+use rand::Rng;
+use std::{
+    cmp::min,
+    error::Error,
+    fmt, io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::time;
+
+const MAX_RETRIES: u32 = 3;
+const BASE_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(2);
+/// Endpoint is skipped for `BREAKER_OPEN_DURATION` after this many consecutive failures
+const BREAKER_THRESHOLD: u64 = 5;
+const BREAKER_OPEN_DURATION: Duration = Duration::from_secs(10);
+/// Errno of the process or the system running out of file descriptors, on Linux
+const EMFILE: i32 = 24;
+const ENFILE: i32 = 23;
+
+#[derive(Default)]
+pub struct RetryStats {
+    pub retries: AtomicU64,
+    /// Requests sent to a replica because own endpoint was unavailable
+    pub failovers: AtomicU64,
+}
+
+pub struct Endpoint {
+    client: JsonRpcAsyncClient,
+    consecutive_failures: AtomicU64,
+    open_until: Mutex<Option<Instant>>,
+}
+
+impl Endpoint {
+    pub fn new(client: JsonRpcAsyncClient) -> Arc<Self> {
+        Arc::new(Self {
+            client,
+            consecutive_failures: AtomicU64::new(0),
+            open_until: Mutex::new(None),
+        })
+    }
+
+    /// After the breaker was open for `BREAKER_OPEN_DURATION` requests are let through again,
+    /// first failure opens it again and first success closes it
+    pub fn is_available(&self) -> bool {
+        match *self.open_until.lock().expect("breaker lock poisoned") {
+            Some(until) => Instant::now() >= until,
+            None => true,
+        }
+    }
+
+    fn on_success(&self) {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+        *self.open_until.lock().expect("breaker lock poisoned") = None;
+    }
+
+    fn on_failure(&self) {
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures < BREAKER_THRESHOLD {
+            return;
+        }
+        let mut open_until = self.open_until.lock().expect("breaker lock poisoned");
+        let now = Instant::now();
+        if open_until.map_or(true, |until| now >= until) {
+            warn!(
+                "[{:?}] {} consecutive failures, skipping endpoint for {} secs",
+                self.client,
+                failures,
+                BREAKER_OPEN_DURATION.as_secs()
+            );
+            *open_until = Some(now + BREAKER_OPEN_DURATION);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RetryingClient {
+    /// Own endpoint first, replicas after it
+    endpoints: Vec<Arc<Endpoint>>,
+    stats: Arc<RetryStats>,
+}
+
+impl RetryingClient {
+    pub fn new(endpoints: Vec<Arc<Endpoint>>, stats: Arc<RetryStats>) -> Self {
+        assert!(!endpoints.is_empty(), "RetryingClient needs an endpoint");
+        Self { endpoints, stats }
+    }
+
+    /// Plain client of own endpoint, for callers which handle retries themselves
+    pub fn primary(&self) -> JsonRpcAsyncClient {
+        self.endpoints[0].client.clone()
+    }
+
+    /// Only transport failures are retried, errors of individual requests of the batch are
+    /// returned as is
+    pub async fn execute(&self, batch: JsonRpcBatch) -> Result<Vec<Result<JsonRpcResponse>>> {
+        let mut attempt = 0;
+        loop {
+            let endpoint = self.pick_endpoint();
+            match endpoint.client.execute(batch.clone()).await {
+                Ok(responses) => {
+                    endpoint.on_success();
+                    return Ok(responses);
+                }
+                Err(e) => {
+                    endpoint.on_failure();
+                    if attempt >= MAX_RETRIES {
+                        let message = format!(
+                            "[{:?}] Request failed after {} retries: {:?}",
+                            endpoint.client, attempt, e
+                        );
+                        if is_local(&e) {
+                            return Err(LocalError(message).into());
+                        }
+                        return Err(format_err!(message));
+                    }
+                    attempt += 1;
+                    self.stats.retries.fetch_add(1, Ordering::Relaxed);
+                    time::delay_for(backoff(attempt)).await;
+                }
+            }
+        }
+    }
+
+    pub async fn submit_transaction(&self, txn: SignedTransaction) -> Result<()> {
+        let mut batch = JsonRpcBatch::new();
+        batch.add_submit_request(txn)?;
+        self.execute(batch).await?.remove(0).map(|_| ())
+    }
+
+    /// Submits all transactions in one batch request, with result of each of them
+    pub async fn submit_transactions(
+        &self,
+        txns: Vec<SignedTransaction>,
+    ) -> Result<Vec<Result<()>>> {
+        let mut batch = JsonRpcBatch::new();
+        for txn in txns {
+            batch.add_submit_request(txn)?;
+        }
+        Ok(self
+            .execute(batch)
+            .await?
+            .into_iter()
+            .map(|response| response.map(|_| ()))
+            .collect())
+    }
+
+    pub async fn get_accounts(
+        &self,
+        addresses: &[AccountAddress],
+    ) -> Result<Vec<Option<AccountView>>> {
+        let mut batch = JsonRpcBatch::new();
+        for address in addresses {
+            batch.add_get_account_request(*address);
+        }
+        self.execute(batch)
+            .await?
+            .into_iter()
+            .map(|response| match response? {
+                JsonRpcResponse::AccountResponse(account) => Ok(account),
+                other => bail!("Unexpected response for get_account: {:?}", other),
+            })
+            .collect()
+    }
+
+    /// First available endpoint, own one if none is available
+    fn pick_endpoint(&self) -> &Endpoint {
+        match self.endpoints.iter().position(|e| e.is_available()) {
+            Some(0) | None => &self.endpoints[0],
+            Some(index) => {
+                self.stats.failovers.fetch_add(1, Ordering::Relaxed);
+                &self.endpoints[index]
+            }
+        }
+    }
+}
+
+/// Request which failed on the emitter host rather than at the endpoint: connection could not
+/// be set up in time, or the host ran out of ports or file descriptors
+#[derive(Debug)]
+pub struct LocalError(String);
+
+impl fmt::Display for LocalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for LocalError {}
+
+fn is_local(error: &JsonRpcAsyncClientError) -> bool {
+    let error = match error {
+        JsonRpcAsyncClientError::ClientError(e) => e,
+        _ => return false,
+    };
+    let mut source = error.source();
+    while let Some(e) = source {
+        if let Some(e) = e.downcast_ref::<io::Error>() {
+            return match e.kind() {
+                io::ErrorKind::TimedOut
+                | io::ErrorKind::AddrNotAvailable
+                | io::ErrorKind::AddrInUse => true,
+                _ => matches!(e.raw_os_error(), Some(EMFILE) | Some(ENFILE)),
+            };
+        }
+        source = e.source();
+    }
+    false
+}
+
+/// Exponential backoff with full jitter
+fn backoff(attempt: u32) -> Duration {
+    let max_delay = min(BASE_BACKOFF * 2u32.pow(attempt), MAX_BACKOFF);
+    max_delay.mul_f64(rand::thread_rng().gen_range(0.0, 1.0))
+}
+
+/// Single endpoint without replicas, retries are not counted in any job stats
+impl From<JsonRpcAsyncClient> for RetryingClient {
+    fn from(client: JsonRpcAsyncClient) -> Self {
+        Self::new(vec![Endpoint::new(client)], Arc::new(RetryStats::default()))
+    }
+}
+
+impl fmt::Debug for RetryingClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.endpoints[0].client)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use reqwest::Url;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let url = Url::parse("http://localhost:8080").unwrap();
+        let endpoint = Endpoint::new(JsonRpcAsyncClient::new(url));
+        for _ in 1..BREAKER_THRESHOLD {
+            endpoint.on_failure();
+        }
+        assert!(endpoint.is_available());
+        endpoint.on_failure();
+        assert!(!endpoint.is_available());
+        endpoint.on_success();
+        assert!(endpoint.is_available());
+    }
+
+    #[test]
+    fn test_backoff() {
+        for attempt in 1..10 {
+            assert!(backoff(attempt) <= MAX_BACKOFF);
+        }
+    }
+}
@@ -0,0 +1,321 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Runs experiments from other binaries, e.g. smoke tests or custom tools, without the cluster
+/// test CLI. Health of the cluster is checked the same way as by the cluster test runner, which
+/// shares the experiment loop with this module
+use crate::{
+    cluster::Cluster,
+    cluster_swarm::ClusterSwarm,
+    diagnose,
+    experiments::{Context, Experiment},
+    health::{
+        DebugPortLogWorker, HealthCheck, HealthCheckRunner, LogTail, PrintFailures, TraceTail,
+        UnhealthyValidators,
+    },
+    instance::Instance,
+    preflight,
+    prometheus::Prometheus,
+    pushgateway::PushGateway,
+    report::SuiteReport,
+    tx_emitter::{EmitJobRequest, TxEmitter},
+    warm_state::WarmState,
+};
+use anyhow::{bail, format_err, Result};
+use futures::{future::join_all, select, FutureExt};
+use libra_logger::{info, warn};
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+use tokio::time::{delay_for, delay_until, Instant as TokioInstant};
+
+pub const HEALTH_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+pub struct ExperimentRunnerBuilder {
+    cluster: Cluster,
+    prometheus: Prometheus,
+    cluster_swarm: Box<dyn ClusterSwarm>,
+    global_emit_job_request: Option<EmitJobRequest>,
+    emit_to_validator: bool,
+    current_tag: String,
+    health_checks: Vec<Box<dyn HealthCheck>>,
+}
+
+impl ExperimentRunnerBuilder {
+    /// Experiments emit to fullnodes with default emit job requests and assume cluster runs
+    /// "master" unless set otherwise
+    pub fn new(
+        cluster: Cluster,
+        prometheus: Prometheus,
+        cluster_swarm: Box<dyn ClusterSwarm>,
+    ) -> Self {
+        Self {
+            cluster,
+            prometheus,
+            cluster_swarm,
+            global_emit_job_request: None,
+            emit_to_validator: false,
+            current_tag: "master".to_string(),
+            health_checks: vec![],
+        }
+    }
+
+    pub fn global_emit_job_request(mut self, request: EmitJobRequest) -> Self {
+        self.global_emit_job_request = Some(request);
+        self
+    }
+
+    pub fn emit_to_validator(mut self, emit_to_validator: bool) -> Self {
+        self.emit_to_validator = emit_to_validator;
+        self
+    }
+
+    pub fn current_tag<S: Into<String>>(mut self, tag: S) -> Self {
+        self.current_tag = tag.into();
+        self
+    }
+
+    /// Run in addition to the default health checks
+    pub fn health_check(mut self, health_check: Box<dyn HealthCheck>) -> Self {
+        self.health_checks.push(health_check);
+        self
+    }
+
+    /// Starts tailing logs of the cluster, so it has to be called within tokio runtime
+    pub fn build(self) -> ExperimentRunner {
+        let (logs, trace_tail) = DebugPortLogWorker::spawn_new(&self.cluster);
+        let mut health_check_runner = HealthCheckRunner::new_all(self.cluster.clone());
+        for health_check in self.health_checks {
+            health_check_runner.add_health_check(health_check);
+        }
+        ExperimentRunner {
+            tx_emitter: TxEmitter::new(&self.cluster),
+            cluster: self.cluster,
+            prometheus: self.prometheus,
+            cluster_swarm: self.cluster_swarm,
+            report: SuiteReport::new(),
+            logs,
+            trace_tail,
+            health_check_runner,
+            global_emit_job_request: self.global_emit_job_request,
+            emit_to_validator: self.emit_to_validator,
+            current_tag: self.current_tag,
+            warm_state: WarmState::new(),
+        }
+    }
+}
+
+pub struct ExperimentRunner {
+    pub cluster: Cluster,
+    pub prometheus: Prometheus,
+    pub cluster_swarm: Box<dyn ClusterSwarm>,
+    pub tx_emitter: TxEmitter,
+    pub report: SuiteReport,
+    logs: LogTail,
+    trace_tail: TraceTail,
+    health_check_runner: HealthCheckRunner,
+    global_emit_job_request: Option<EmitJobRequest>,
+    emit_to_validator: bool,
+    current_tag: String,
+    warm_state: WarmState,
+}
+
+impl ExperimentRunner {
+    /// Context for driving an experiment or parts of one directly, without health checks
+    pub fn context(&mut self) -> Context<'_> {
+        Context::new(
+            &mut self.tx_emitter,
+            &mut self.trace_tail,
+            &self.prometheus,
+            &self.cluster,
+            &mut self.report,
+            &mut self.global_emit_job_request,
+            self.emit_to_validator,
+            self.cluster_swarm.as_ref(),
+            &self.current_tag,
+            &mut self.warm_state,
+        )
+    }
+
+    /// Fails if cluster is unhealthy or instances affected by the experiment fail preflight
+    /// checks before the experiment, validators not affected by it fail while it runs, or the
+    /// cluster does not recover by its deadline
+    pub async fn run(&mut self, mut experiment: Box<dyn Experiment>) -> Result<()> {
+        let events = self.logs.recv_all();
+        if let Err(s) = self
+            .health_check_runner
+            .run(&events, &HashSet::new(), PrintFailures::UnexpectedOnly)
+            .await
+        {
+            bail!(
+                "Some validators are unhealthy before experiment started : {}",
+                s
+            );
+        }
+        let failures = preflight::run(&self.cluster, &experiment.affected_validators()).await;
+        if !failures.is_empty() {
+            let failures: Vec<_> = failures.iter().map(ToString::to_string).collect();
+            bail!("Preflight checks failed: {}", failures.join(", "));
+        }
+        info!("Starting experiment {}", experiment);
+        let deadline = Instant::now() + experiment.deadline();
+        self.report
+            .start_experiment(experiment.to_string(), experiment.report_section());
+        let mut context = Context::new(
+            &mut self.tx_emitter,
+            &mut self.trace_tail,
+            &self.prometheus,
+            &self.cluster,
+            &mut self.report,
+            &mut self.global_emit_job_request,
+            self.emit_to_validator,
+            self.cluster_swarm.as_ref(),
+            &self.current_tag,
+            &mut self.warm_state,
+        );
+        let result = experiment_loop(
+            experiment.as_mut(),
+            &mut context,
+            &mut self.logs,
+            &mut self.health_check_runner,
+            None,
+            deadline,
+        )
+        .await;
+        if let Err(e) = &result {
+            diagnose::attach_on_liveness_failure(
+                &self.cluster,
+                &self.prometheus,
+                &mut self.report,
+                &experiment.to_string(),
+                e,
+            )
+            .await;
+        }
+        self.report.end_experiment();
+        result?;
+        wait_until_all_healthy(
+            &self.cluster,
+            &mut self.logs,
+            &mut self.health_check_runner,
+            deadline,
+        )
+        .await
+    }
+}
+
+/// Runs experiment until it completes or its deadline, validators it does not affect are
+/// checked every `HEALTH_POLL_INTERVAL`. Number of failures is pushed to `push_gateway` if set
+pub async fn experiment_loop(
+    experiment: &mut dyn Experiment,
+    context: &mut Context<'_>,
+    logs: &mut LogTail,
+    health_check_runner: &mut HealthCheckRunner,
+    push_gateway: Option<&PushGateway>,
+    deadline: Instant,
+) -> Result<()> {
+    if !context
+        .warm_state
+        .check_chain(context.cluster.validator_instances())
+        .await
+    {
+        // Accounts of the pool do not exist on the new chain either
+        context.tx_emitter.clear();
+    }
+    let affected_validators = experiment.affected_validators();
+    let experiment_name = experiment.to_string();
+    let mut deadline_future = delay_until(TokioInstant::from_std(deadline)).fuse();
+    let mut run_future = experiment.run(context).fuse();
+    let result = loop {
+        select! {
+            delay = deadline_future => {
+                break Err(format_err!("Experiment deadline reached"));
+            }
+            result = run_future => {
+                break result.map_err(|e|format_err!("Failed to run experiment: {}", e));
+            }
+            delay = delay_for(HEALTH_POLL_INTERVAL).fuse() => {
+                let events = logs.recv_all();
+                if let Err(s) = health_check_runner.run(
+                    &events,
+                    &affected_validators,
+                    PrintFailures::UnexpectedOnly,
+                ).await {
+                    if let Some(push_gateway) = push_gateway {
+                        let failures = s
+                            .downcast_ref::<UnhealthyValidators>()
+                            .map_or(1, |unhealthy| unhealthy.validators.len());
+                        if let Err(e) = push_gateway.push(
+                            &[("health_check_failures", failures as f64)],
+                            &[("experiment", &experiment_name)],
+                        ).await {
+                            warn!("Failed to push metrics: {}", e);
+                        }
+                    }
+                    let message = format!("Validators which were not under experiment failed : {}", s);
+                    break Err(s.context(message));
+                }
+            }
+        }
+    };
+    drop(run_future);
+    context
+        .temporary_fullnodes
+        .teardown(context.cluster_swarm)
+        .await;
+    result
+}
+
+pub async fn wait_until_all_healthy(
+    cluster: &Cluster,
+    logs: &mut LogTail,
+    health_check_runner: &mut HealthCheckRunner,
+    deadline: Instant,
+) -> Result<()> {
+    info!("Waiting for all nodes to be healthy");
+    for instance in cluster.validator_instances() {
+        health_check_runner.invalidate(instance.peer_name());
+    }
+    loop {
+        let now = Instant::now();
+        if now > deadline {
+            bail!("Nodes did not become healthy after deployment");
+        }
+        let deadline = now + HEALTH_POLL_INTERVAL;
+        let events = logs.recv_all_until_deadline(deadline);
+        if let Ok(failed_instances) = health_check_runner
+            .run(&events, &HashSet::new(), PrintFailures::None)
+            .await
+        {
+            if failed_instances.is_empty() {
+                break;
+            }
+        }
+    }
+    info!("All nodes are now healthy. Checking json rpc endpoints of validators and full nodes");
+    loop {
+        let results = join_all(
+            cluster
+                .validator_and_fullnode_instances()
+                .map(Instance::try_json_rpc),
+        )
+        .await;
+
+        if results.iter().all(Result::is_ok) {
+            break;
+        }
+        if Instant::now() > deadline {
+            for (instance, result) in cluster.validator_and_fullnode_instances().zip(results) {
+                if let Err(err) = result {
+                    warn!("Instance {} still unhealthy: {}", instance, err);
+                }
+            }
+            bail!("Some json rpc endpoints did not become healthy after deployment");
+        }
+    }
+    info!("All json rpc endpoints are healthy");
+    Ok(())
+}
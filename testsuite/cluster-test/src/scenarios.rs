@@ -0,0 +1,326 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Catalog of incident scenarios replayed by the scenario_replay experiment. A scenario is a
+/// timed sequence of faults on validators given by index, so that a regression test for an
+/// incident is an entry in scenarios.yaml rather than a new experiment
+use crate::{
+    effects::{
+        clock_skew::ClockSkew, cpu_burn::CpuBurn, cpu_quota::CpuQuota,
+        disk_contention::DiskContention, half_open_connection::HalfOpenConnection,
+        kill_node::KillNode, network_bandwidth::NetworkBandwidth, network_delay::NetworkDelay,
+        packet_loss::PacketLoss, process_pause::ProcessPause, storage_delay::StorageDelay, Effect,
+    },
+    instance::Instance,
+};
+use anyhow::{bail, format_err, Result};
+use serde::Deserialize;
+use std::{collections::HashSet, fmt, fs, time::Duration};
+
+/// Catalog built into the binary, new scenarios from postmortems are added there
+const BUILTIN_CATALOG: &str = include_str!("scenarios.yaml");
+
+/// Example catalog:
+/// ```yaml
+/// - name: leader_flap
+///   postmortem: <link to the postmortem>
+///   description: Validator losing most packets while the rest of the cluster is loaded
+///   duration_secs: 300
+///   faults:
+///     - at_secs: 60
+///       for_secs: 120
+///       validators: [0]
+///       fault: {packet_loss: {percent: 80}}
+///   expect:
+///     min_tps: 100
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+pub struct Catalog(Vec<Scenario>);
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Scenario {
+    /// Unique within the catalog, experiment is run by it
+    pub name: String,
+    /// Link to the postmortem of the incident
+    #[serde(default)]
+    pub postmortem: Option<String>,
+    pub description: String,
+    /// Load runs for the whole duration, faults have to end within it
+    pub duration_secs: u64,
+    pub faults: Vec<ScenarioFault>,
+    #[serde(default)]
+    pub expect: Expectations,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ScenarioFault {
+    /// Offset of the start of the fault from the start of the scenario
+    pub at_secs: u64,
+    pub for_secs: u64,
+    /// Indices of faulted validators in cluster order, each gets its own effect
+    pub validators: Vec<usize>,
+    pub fault: Fault,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Fault {
+    Kill,
+    PacketLoss {
+        percent: f32,
+    },
+    /// Delay and bandwidth limit apply to traffic to all other validators
+    NetworkDelay {
+        delay_ms: u64,
+    },
+    Bandwidth {
+        rate_mbit: u64,
+    },
+    /// Peer is given by index, like faulted validators
+    HalfOpenConnection {
+        peer: usize,
+    },
+    ClockSkew {
+        offset_secs: i64,
+    },
+    CpuBurn {
+        threads: usize,
+    },
+    CpuQuota {
+        millicores: u64,
+    },
+    ProcessPause {
+        pause_secs: u64,
+        interval_secs: u64,
+    },
+    DiskContention,
+    StorageDelay {
+        delay_ms: u64,
+        #[serde(default = "default_syscalls")]
+        syscalls: String,
+    },
+}
+
+/// What the cluster has to keep up during the scenario for the replay to pass
+#[derive(Clone, Debug, Deserialize)]
+pub struct Expectations {
+    /// Committed TPS over the whole scenario
+    #[serde(default)]
+    pub min_tps: Option<u64>,
+    #[serde(default)]
+    pub max_p99_latency_ms: Option<u64>,
+    /// Time for faulted validators to catch up with the rest after the scenario
+    #[serde(default = "default_catch_up_secs")]
+    pub catch_up_secs: u64,
+}
+
+fn default_syscalls() -> String {
+    "fsync,fdatasync".to_string()
+}
+
+fn default_catch_up_secs() -> u64 {
+    120
+}
+
+impl Default for Expectations {
+    fn default() -> Self {
+        Self {
+            min_tps: None,
+            max_p99_latency_ms: None,
+            catch_up_secs: default_catch_up_secs(),
+        }
+    }
+}
+
+impl Catalog {
+    pub fn builtin() -> Result<Self> {
+        Self::parse(BUILTIN_CATALOG, "builtin")
+    }
+
+    pub fn from_file(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format_err!("Failed to read scenario catalog {}: {}", path, e))?;
+        Self::parse(&content, path)
+    }
+
+    fn parse(content: &str, source: &str) -> Result<Self> {
+        let catalog: Self = serde_yaml::from_str(content)
+            .map_err(|e| format_err!("Failed to parse scenario catalog {}: {}", source, e))?;
+        let mut names = HashSet::new();
+        for scenario in &catalog.0 {
+            if !names.insert(&scenario.name) {
+                bail!("Scenario {} is defined twice in {}", scenario.name, source);
+            }
+            scenario
+                .validate()
+                .map_err(|e| format_err!("Scenario {} in {}: {}", scenario.name, source, e))?;
+        }
+        Ok(catalog)
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Scenario> {
+        self.0.iter().find(|s| s.name == name).ok_or_else(|| {
+            format_err!(
+                "Unknown scenario {}, known scenarios: {}",
+                name,
+                self.names().join(", ")
+            )
+        })
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.0.iter().map(|s| s.name.as_str()).collect()
+    }
+}
+
+impl Scenario {
+    /// Faults end within the scenario, and a validator has at most one fault at a time, since
+    /// network faults of one instance would replace each other
+    fn validate(&self) -> Result<()> {
+        let mut windows: Vec<(usize, u64, u64)> = vec![];
+        for fault in &self.faults {
+            if fault.validators.is_empty() {
+                bail!("fault {:?} has no validators", fault.fault);
+            }
+            let end = fault.at_secs + fault.for_secs;
+            if end > self.duration_secs {
+                bail!(
+                    "fault {:?} ends at {}s after the end of the scenario",
+                    fault.fault,
+                    end
+                );
+            }
+            for validator in &fault.validators {
+                if windows
+                    .iter()
+                    .any(|(v, start, stop)| v == validator && fault.at_secs < *stop && *start < end)
+                {
+                    bail!(
+                        "faults on validator {} overlap at {}s",
+                        validator,
+                        fault.at_secs
+                    );
+                }
+                windows.push((*validator, fault.at_secs, end));
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of validators the cluster needs to run the scenario
+    pub fn validators_needed(&self) -> usize {
+        self.faulted_validators()
+            .into_iter()
+            .chain(self.faults.iter().filter_map(|f| match f.fault {
+                Fault::HalfOpenConnection { peer } => Some(peer),
+                _ => None,
+            }))
+            .max()
+            .map_or(0, |index| index + 1)
+    }
+
+    pub fn faulted_validators(&self) -> HashSet<usize> {
+        self.faults
+            .iter()
+            .flat_map(|f| f.validators.iter().cloned())
+            .collect()
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs(self.duration_secs)
+    }
+}
+
+impl Fault {
+    /// Effect of the fault on `instance`, `validators` are all validators of the cluster
+    pub fn effect(&self, instance: Instance, validators: &[Instance]) -> Box<dyn Effect + Send> {
+        let others = || -> Vec<Instance> {
+            validators
+                .iter()
+                .filter(|v| v.peer_name() != instance.peer_name())
+                .cloned()
+                .collect()
+        };
+        match self {
+            Fault::Kill => Box::new(KillNode::new(instance)),
+            Fault::PacketLoss { percent } => Box::new(PacketLoss::new(instance, *percent)),
+            Fault::NetworkDelay { delay_ms } => {
+                let configuration = vec![(others(), Duration::from_millis(*delay_ms))];
+                Box::new(NetworkDelay::new(instance, configuration))
+            }
+            Fault::Bandwidth { rate_mbit } => {
+                let targets = others();
+                Box::new(NetworkBandwidth::new(instance, targets, *rate_mbit))
+            }
+            Fault::HalfOpenConnection { peer } => {
+                Box::new(HalfOpenConnection::new(instance, validators[*peer].clone()))
+            }
+            Fault::ClockSkew { offset_secs } => Box::new(ClockSkew::new(instance, *offset_secs)),
+            Fault::CpuBurn { threads } => Box::new(CpuBurn::new(instance, *threads)),
+            Fault::CpuQuota { millicores } => Box::new(CpuQuota::new(instance, *millicores)),
+            Fault::ProcessPause {
+                pause_secs,
+                interval_secs,
+            } => Box::new(ProcessPause::new(
+                instance,
+                Duration::from_secs(*pause_secs),
+                Duration::from_secs(*interval_secs),
+            )),
+            Fault::DiskContention => Box::new(DiskContention::new(instance)),
+            Fault::StorageDelay { delay_ms, syscalls } => Box::new(StorageDelay::new(
+                instance,
+                syscalls.clone(),
+                Duration::from_millis(*delay_ms),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ScenarioFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "+{}s {:?} on validators {:?} for {}s",
+            self.at_secs, self.fault, self.validators, self.for_secs
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_builtin_catalog() {
+        let catalog = Catalog::builtin().unwrap();
+        assert!(!catalog.names().is_empty());
+        for name in catalog.names() {
+            let scenario = catalog.get(name).unwrap();
+            assert!(scenario.validators_needed() > 0, "{} has no faults", name);
+        }
+        assert!(catalog.get("no_such_scenario").is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let overlapping = r#"
+- name: overlapping
+  description: Two faults on one validator at once
+  duration_secs: 300
+  faults:
+    - {at_secs: 0, for_secs: 120, validators: [0], fault: kill}
+    - {at_secs: 60, for_secs: 60, validators: [1, 0], fault: {cpu_burn: {threads: 4}}}
+"#;
+        assert!(Catalog::parse(overlapping, "test").is_err());
+        let too_long = r#"
+- name: too_long
+  description: Fault outlasting the scenario
+  duration_secs: 100
+  faults:
+    - {at_secs: 60, for_secs: 60, validators: [0], fault: {packet_loss: {percent: 10}}}
+"#;
+        assert!(Catalog::parse(too_long, "test").is_err());
+    }
+}
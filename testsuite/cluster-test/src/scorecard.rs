@@ -0,0 +1,181 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Scorecard combines key metrics of the suite run into single weighted score in [0, 100],
+/// so that overall trend can be tracked across runs without reading the full report
+use crate::report::ReportedMetric;
+use serde::Serialize;
+use std::fmt;
+
+const TARGET_TPS: f64 = 1000.0;
+const TARGET_P99_LATENCY_MS: f64 = 3000.0;
+const TARGET_RECOVERY_SECS: f64 = 60.0;
+
+const THROUGHPUT_WEIGHT: f64 = 0.4;
+const LATENCY_WEIGHT: f64 = 0.3;
+const RECOVERY_WEIGHT: f64 = 0.1;
+const RELIABILITY_WEIGHT: f64 = 0.2;
+
+/// Metrics measuring time to recover from a fault, lower is better
+const RECOVERY_METRICS: &[&str] = &["recovery_time", "catch_up_time", "reconnect_time"];
+
+#[derive(Debug, Serialize)]
+pub struct Scorecard {
+    components: Vec<ScoreComponent>,
+    score: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScoreComponent {
+    name: &'static str,
+    weight: f64,
+    /// Score of this component in [0, 1]
+    score: f64,
+}
+
+impl Scorecard {
+    /// Components for which suite did not report any metrics are not taken into account,
+    /// weights of remaining components are normalized
+    pub fn from_metrics(metrics: &[ReportedMetric]) -> Self {
+        let mut components = vec![];
+        if let Some(tps) = max_metric(metrics, |m| m == "avg_tps") {
+            components.push(ScoreComponent {
+                name: "throughput",
+                weight: THROUGHPUT_WEIGHT,
+                score: (tps / TARGET_TPS).min(1.0),
+            });
+        }
+        if let Some(p99_latency) = max_metric(metrics, |m| m == "p99_latency") {
+            components.push(ScoreComponent {
+                name: "latency",
+                weight: LATENCY_WEIGHT,
+                score: inverse_score(p99_latency, TARGET_P99_LATENCY_MS),
+            });
+        }
+        if let Some(recovery) = max_metric(metrics, |m| RECOVERY_METRICS.contains(&m)) {
+            components.push(ScoreComponent {
+                name: "recovery",
+                weight: RECOVERY_WEIGHT,
+                score: inverse_score(recovery, TARGET_RECOVERY_SECS),
+            });
+        }
+        let submitted = sum_metric(metrics, "submitted_txn");
+        let expired = sum_metric(metrics, "expired_txn");
+        let failed = sum_metric(metrics, "failed");
+        if submitted > 0.0 || failed > 0.0 {
+            let expired_ratio = if submitted > 0.0 {
+                expired / submitted
+            } else {
+                0.0
+            };
+            let score = if failed > 0.0 {
+                0.0
+            } else {
+                1.0 - expired_ratio.min(1.0)
+            };
+            components.push(ScoreComponent {
+                name: "reliability",
+                weight: RELIABILITY_WEIGHT,
+                score,
+            });
+        }
+        let total_weight: f64 = components.iter().map(|c| c.weight).sum();
+        let score = if total_weight > 0.0 {
+            100.0 * components.iter().map(|c| c.weight * c.score).sum::<f64>() / total_weight
+        } else {
+            0.0
+        };
+        Self { components, score }
+    }
+
+    pub fn score(&self) -> f64 {
+        self.score
+    }
+
+    pub fn grade(&self) -> &'static str {
+        match self.score as u64 {
+            90..=100 => "A",
+            80..=89 => "B",
+            70..=79 => "C",
+            60..=69 => "D",
+            _ => "F",
+        }
+    }
+}
+
+fn max_metric<F: Fn(&str) -> bool>(metrics: &[ReportedMetric], filter: F) -> Option<f64> {
+    metrics
+        .iter()
+        .filter(|m| filter(&m.metric))
+        .map(|m| m.value)
+        .fold(None, |max, v| Some(max.map_or(v, |max: f64| max.max(v))))
+}
+
+fn sum_metric(metrics: &[ReportedMetric], name: &str) -> f64 {
+    metrics
+        .iter()
+        .filter(|m| m.metric == name)
+        .map(|m| m.value)
+        .sum()
+}
+
+/// Score for metrics where lower is better, 1.0 when value is at or below target
+fn inverse_score(value: f64, target: f64) -> f64 {
+    if value <= target {
+        1.0
+    } else {
+        target / value
+    }
+}
+
+impl fmt::Display for Scorecard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Score: {:.1} ({})", self.score, self.grade())?;
+        for component in &self.components {
+            write!(
+                f,
+                ", {} {:.0}% (weight {:.1})",
+                component.name,
+                100.0 * component.score,
+                component.weight
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn metric(metric: &str, value: f64) -> ReportedMetric {
+        ReportedMetric {
+            experiment: "test".to_string(),
+            metric: metric.to_string(),
+            value,
+        }
+    }
+
+    #[test]
+    pub fn test_scorecard() {
+        let scorecard = Scorecard::from_metrics(&[]);
+        assert_eq!(scorecard.score(), 0.0);
+
+        let scorecard = Scorecard::from_metrics(&[
+            metric("avg_tps", 500.0),
+            metric("p99_latency", 1000.0),
+            metric("submitted_txn", 100.0),
+            metric("expired_txn", 0.0),
+        ]);
+        // (0.4 * 0.5 + 0.3 * 1.0 + 0.2 * 1.0) / 0.9
+        assert!((scorecard.score() - 77.78).abs() < 0.01);
+        assert_eq!(scorecard.grade(), "C");
+
+        let scorecard =
+            Scorecard::from_metrics(&[metric("avg_tps", 2000.0), metric("failed", 1.0)]);
+        // (0.4 * 1.0 + 0.2 * 0.0) / 0.6
+        assert!((scorecard.score() - 66.67).abs() < 0.01);
+    }
+}
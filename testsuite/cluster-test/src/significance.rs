@@ -0,0 +1,346 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Significance tests over repeated runs of a benchmark, so that a difference between two
+/// builds or configs is only called a regression when it stands out of run to run noise of
+/// cloud instances
+use anyhow::{bail, Result};
+use std::{f64::consts::SQRT_2, fmt, str::FromStr};
+
+/// Iterations and precision of the continued fraction of incomplete beta function
+const MAX_ITERATIONS: usize = 200;
+const EPSILON: f64 = 1e-12;
+const TINY: f64 = 1e-300;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Test {
+    /// Welch's t-test, compares means without assuming equal variances
+    Welch,
+    /// Mann-Whitney U test, compares ranks and is robust to outliers, needs at least 4 runs
+    /// of each side to reach 95% confidence
+    MannWhitney,
+}
+
+impl FromStr for Test {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "welch" => Ok(Test::Welch),
+            "mann-whitney" => Ok(Test::MannWhitney),
+            _ => bail!("Unknown test {}, expected welch or mann-whitney", s),
+        }
+    }
+}
+
+impl fmt::Display for Test {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Test::Welch => write!(f, "welch"),
+            Test::MannWhitney => write!(f, "mann-whitney"),
+        }
+    }
+}
+
+/// Mean of a sample with its confidence interval
+#[derive(Clone, Copy, Debug)]
+pub struct Summary {
+    pub count: usize,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub ci_low: f64,
+    pub ci_high: f64,
+}
+
+/// Candidate sample against baseline sample
+#[derive(Clone, Copy, Debug)]
+pub struct Comparison {
+    pub baseline: Summary,
+    pub candidate: Summary,
+    pub test: Test,
+    pub confidence: f64,
+    /// Two-sided, probability of a difference at least this large if there was none
+    pub p_value: f64,
+}
+
+impl Summary {
+    /// None for fewer than 2 values, which have no variance
+    pub fn new(values: &[f64], confidence: f64) -> Option<Self> {
+        let count = values.len();
+        if count < 2 {
+            return None;
+        }
+        let mean = mean(values);
+        let std_dev = variance(values, mean).sqrt();
+        let half_width =
+            t_critical(confidence, (count - 1) as f64) * std_dev / (count as f64).sqrt();
+        Some(Self {
+            count,
+            mean,
+            std_dev,
+            ci_low: mean - half_width,
+            ci_high: mean + half_width,
+        })
+    }
+}
+
+impl Comparison {
+    /// None unless both samples have at least 2 values
+    pub fn new(baseline: &[f64], candidate: &[f64], test: Test, confidence: f64) -> Option<Self> {
+        let p_value = match test {
+            Test::Welch => welch_p_value(baseline, candidate),
+            Test::MannWhitney => mann_whitney_p_value(baseline, candidate),
+        };
+        Some(Self {
+            baseline: Summary::new(baseline, confidence)?,
+            candidate: Summary::new(candidate, confidence)?,
+            test,
+            confidence,
+            p_value,
+        })
+    }
+
+    pub fn significant(&self) -> bool {
+        self.p_value < 1.0 - self.confidence
+    }
+
+    /// Change of candidate mean relative to baseline mean in percent
+    pub fn delta_pct(&self) -> Option<f64> {
+        if self.baseline.mean == 0.0 {
+            None
+        } else {
+            Some((self.candidate.mean - self.baseline.mean) * 100.0 / self.baseline.mean)
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:.1} [{:.1}, {:.1}]",
+            self.mean, self.ci_low, self.ci_high
+        )
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} -> {}", self.baseline, self.candidate)?;
+        if let Some(delta) = self.delta_pct() {
+            write!(f, " ({:+.1}%)", delta)?;
+        }
+        write!(
+            f,
+            ", {} p={:.3}, {} at {:.0}% confidence",
+            self.test,
+            self.p_value,
+            if self.significant() {
+                "significant"
+            } else {
+                "not significant"
+            },
+            self.confidence * 100.0
+        )
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Unbiased sample variance
+fn variance(values: &[f64], mean: f64) -> f64 {
+    values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64
+}
+
+fn welch_p_value(a: &[f64], b: &[f64]) -> f64 {
+    if a.len() < 2 || b.len() < 2 {
+        return 1.0;
+    }
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let se_a = variance(a, mean_a) / a.len() as f64;
+    let se_b = variance(b, mean_b) / b.len() as f64;
+    let se = se_a + se_b;
+    if se == 0.0 {
+        return if (mean_a - mean_b).abs() < f64::EPSILON {
+            1.0
+        } else {
+            0.0
+        };
+    }
+    let t = (mean_a - mean_b) / se.sqrt();
+    let df =
+        se.powi(2) / (se_a.powi(2) / (a.len() - 1) as f64 + se_b.powi(2) / (b.len() - 1) as f64);
+    t_p_value(t, df)
+}
+
+/// Normal approximation with tie and continuity corrections
+fn mann_whitney_p_value(a: &[f64], b: &[f64]) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 1.0;
+    }
+    let mut values: Vec<_> = a
+        .iter()
+        .map(|v| (*v, true))
+        .chain(b.iter().map(|v| (*v, false)))
+        .collect();
+    values.sort_by(|x, y| x.0.partial_cmp(&y.0).expect("NaN in sample"));
+    let n = values.len() as f64;
+    let mut rank_sum_a = 0.0;
+    let mut tie_correction = 0.0;
+    let mut i = 0;
+    while i < values.len() {
+        let j = (i..values.len())
+            .find(|j| values[*j].0 > values[i].0)
+            .unwrap_or_else(|| values.len());
+        // Tied values share the average of their ranks, which are 1-based
+        let rank = (i + j + 1) as f64 / 2.0;
+        rank_sum_a += rank * values[i..j].iter().filter(|v| v.1).count() as f64;
+        let ties = (j - i) as f64;
+        tie_correction += ties.powi(3) - ties;
+        i = j;
+    }
+    let (n_a, n_b) = (a.len() as f64, b.len() as f64);
+    let u = rank_sum_a - n_a * (n_a + 1.0) / 2.0;
+    let sigma = (n_a * n_b / 12.0 * ((n + 1.0) - tie_correction / (n * (n - 1.0)))).sqrt();
+    if sigma == 0.0 {
+        return 1.0;
+    }
+    let z = ((u - n_a * n_b / 2.0).abs() - 0.5).max(0.0) / sigma;
+    (2.0 * (1.0 - normal_cdf(z))).min(1.0)
+}
+
+/// Two-sided p-value of Student's t statistic
+fn t_p_value(t: f64, df: f64) -> f64 {
+    incomplete_beta(df / (df + t * t), df / 2.0, 0.5)
+}
+
+/// Value of t statistic whose two-sided p-value is 1 - `confidence`
+fn t_critical(confidence: f64, df: f64) -> f64 {
+    let alpha = 1.0 - confidence;
+    let (mut low, mut high) = (0.0, 1e3);
+    for _ in 0..100 {
+        let middle = (low + high) / 2.0;
+        if t_p_value(middle, df) > alpha {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    (low + high) / 2.0
+}
+
+fn normal_cdf(z: f64) -> f64 {
+    0.5 * (1.0 + erf(z / SQRT_2))
+}
+
+/// Abramowitz and Stegun 7.1.26, absolute error below 1.5e-7
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let poly = t
+        * (0.254_829_592
+            + t * (-0.284_496_736
+                + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let y = 1.0 - poly * (-x * x).exp();
+    if x < 0.0 {
+        -y
+    } else {
+        y
+    }
+}
+
+/// Regularized incomplete beta function I_x(a, b)
+fn incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    // Continued fraction converges fast only on one side of the mean, the other side is
+    // taken by symmetry
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_fraction(1.0 - x, b, a) / b
+    }
+}
+
+/// Continued fraction of incomplete beta function by modified Lentz's method
+fn beta_fraction(x: f64, a: f64, b: f64) -> f64 {
+    let clamp = |v: f64| if v.abs() < TINY { TINY } else { v };
+    let mut c = 1.0;
+    let mut d = 1.0 / clamp(1.0 - (a + b) * x / (a + 1.0));
+    let mut h = d;
+    for m in 1..=MAX_ITERATIONS {
+        let m = m as f64;
+        let even = m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m));
+        d = 1.0 / clamp(1.0 + even * d);
+        c = clamp(1.0 + even / c);
+        h *= d * c;
+        let odd = -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0));
+        d = 1.0 / clamp(1.0 + odd * d);
+        c = clamp(1.0 + odd / c);
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < EPSILON {
+            break;
+        }
+    }
+    h
+}
+
+/// Lanczos approximation of ln(Gamma(x)) for x > 0
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_78,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        1.208_650_973_866_179e-3,
+        -5.395_239_384_953e-6,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .fold(1.000_000_000_190_015, |sum, (i, c)| {
+            sum + c / (x + 1.0 + i as f64)
+        });
+    -tmp + (2.506_628_274_631_000_7 * series / x).ln()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_t_distribution() {
+        assert!((t_critical(0.95, 10.0) - 2.228).abs() < 1e-3);
+        assert!((t_critical(0.99, 4.0) - 4.604).abs() < 1e-3);
+        assert!((t_p_value(0.0, 5.0) - 1.0).abs() < 1e-9);
+        assert!((normal_cdf(1.96) - 0.975).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_comparison() {
+        let baseline = [1000.0, 1020.0, 990.0, 1010.0, 1005.0];
+        let noisy = [1015.0, 985.0, 1000.0, 1025.0, 995.0];
+        let regressed = [900.0, 910.0, 895.0, 905.0, 890.0];
+        for test in &[Test::Welch, Test::MannWhitney] {
+            let same = Comparison::new(&baseline, &noisy, *test, 0.95).unwrap();
+            assert!(!same.significant(), "{}", same);
+            let worse = Comparison::new(&baseline, &regressed, *test, 0.95).unwrap();
+            assert!(worse.significant(), "{}", worse);
+            assert!(worse.delta_pct().unwrap() < -9.0);
+        }
+        assert!(Comparison::new(&baseline, &[900.0], Test::Welch, 0.95).is_none());
+        let summary = Summary::new(&baseline, 0.95).unwrap();
+        assert!(summary.ci_low < 1005.0 && 1005.0 < summary.ci_high);
+    }
+}
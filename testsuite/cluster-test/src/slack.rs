@@ -3,14 +3,57 @@
 
 #![forbid(unsafe_code)]
 
+use crate::suite::Outcome;
 use anyhow::{bail, format_err, Result};
 use reqwest::{self, Url};
-use serde_json::{self, json};
+use serde_json::{self, json, Value};
+use std::env;
 
 pub struct SlackClient {
     client: reqwest::blocking::Client,
 }
 
+/// Channel for file uploads and threads, which unlike messages need a bot token instead of a
+/// webhook
+pub struct SlackUploadTarget {
+    pub token: String,
+    pub channel: String,
+}
+
+impl SlackUploadTarget {
+    /// Enabled when both `SLACK_TOKEN` and `SLACK_UPLOAD_CHANNEL` are set
+    pub fn from_env() -> Option<Self> {
+        Some(Self {
+            token: env::var("SLACK_TOKEN").ok()?,
+            channel: env::var("SLACK_UPLOAD_CHANNEL").ok()?,
+        })
+    }
+}
+
+/// Thread of a suite run. Parent message shows status of the run and is edited with the
+/// summary once the run is over, results of experiments are posted into the thread as they
+/// complete
+pub struct SlackThread {
+    /// Channel id, which updates need instead of channel name
+    channel: String,
+    ts: String,
+    title: String,
+}
+
+impl SlackThread {
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+}
+
+pub fn outcome_emoji(outcome: Outcome) -> &'static str {
+    match outcome {
+        Outcome::Passed => ":white_check_mark:",
+        Outcome::Failed => ":x:",
+        Outcome::Skipped => ":fast_forward:",
+    }
+}
+
 impl SlackClient {
     pub fn new() -> Self {
         let client = reqwest::blocking::Client::new();
@@ -30,6 +73,112 @@ impl SlackClient {
         }
         Ok(())
     }
+
+    /// Uploads text file, e.g. svg image, with files.upload API, into `thread` if set
+    pub fn upload_file(
+        &self,
+        target: &SlackUploadTarget,
+        thread: Option<&SlackThread>,
+        filename: &str,
+        title: &str,
+        content: &str,
+    ) -> Result<()> {
+        let mut params = vec![
+            ("token", target.token.as_str()),
+            ("channels", target.channel.as_str()),
+            ("filename", filename),
+            ("title", title),
+            ("content", content),
+        ];
+        if let Some(thread) = thread {
+            params.push(("thread_ts", thread.ts.as_str()));
+        }
+        self.call_api("files.upload", &params)?;
+        Ok(())
+    }
+
+    /// Posts parent message of a new thread, marked as running
+    pub fn start_thread(&self, target: &SlackUploadTarget, title: &str) -> Result<SlackThread> {
+        let text = format!(":hourglass_flowing_sand: {} is running", title);
+        let response = self.call_api(
+            "chat.postMessage",
+            &[
+                ("token", target.token.as_str()),
+                ("channel", target.channel.as_str()),
+                ("text", text.as_str()),
+            ],
+        )?;
+        let field = |name: &str| {
+            response[name]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| format_err!("Slack response has no {}", name))
+        };
+        Ok(SlackThread {
+            channel: field("channel")?,
+            ts: field("ts")?,
+            title: title.to_string(),
+        })
+    }
+
+    pub fn post_to_thread(
+        &self,
+        target: &SlackUploadTarget,
+        thread: &SlackThread,
+        text: &str,
+    ) -> Result<()> {
+        self.call_api(
+            "chat.postMessage",
+            &[
+                ("token", target.token.as_str()),
+                ("channel", thread.channel.as_str()),
+                ("thread_ts", thread.ts.as_str()),
+                ("text", text),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Replaces running status in parent message of the thread with final status and summary
+    pub fn finish_thread(
+        &self,
+        target: &SlackUploadTarget,
+        thread: &SlackThread,
+        emoji: &str,
+        summary: &str,
+    ) -> Result<()> {
+        let text = format!("{} {}\n{}", emoji, thread.title, summary);
+        self.call_api(
+            "chat.update",
+            &[
+                ("token", target.token.as_str()),
+                ("channel", thread.channel.as_str()),
+                ("ts", thread.ts.as_str()),
+                ("text", text.as_str()),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Calls Web API method with form params, returns response of successful call
+    fn call_api(&self, method: &str, params: &[(&str, &str)]) -> Result<Value> {
+        let response = self
+            .client
+            .post(&format!("https://slack.com/api/{}", method))
+            .form(params)
+            .send()
+            .map_err(|e| format_err!("Failed to call slack {}: {:?}", method, e))?;
+        if !response.status().is_success() {
+            bail!("Slack service returned error code: {}", response.status())
+        }
+        let response: Value = response
+            .json()
+            .map_err(|e| format_err!("Failed to parse slack response: {:?}", e))?;
+        if response["ok"] != Value::Bool(true) {
+            bail!("Slack {} failed: {}", method, response["error"])
+        }
+        Ok(response)
+    }
 }
 
 impl Default for SlackClient {
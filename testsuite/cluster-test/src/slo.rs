@@ -0,0 +1,146 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Latency SLOs are evaluated for every minute of a run instead of once over the whole window,
+/// where a percentile of a long run can hide an outage of several minutes
+use crate::atomic_histogram::AtomicHistogramSnapshot;
+use anyhow::{format_err, Error, Result};
+use std::{cmp::max, fmt, str::FromStr, time::Duration};
+
+/// Latency percentile of each minute has to stay below a bound, given as e.g. `p99<900`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LatencySlo {
+    pub percentile: u64,
+    pub max_latency_ms: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SloResult {
+    pub slo: LatencySlo,
+    /// Minutes of the workload window
+    pub minutes: usize,
+    /// Minutes over the latency, including minutes in which nothing was committed
+    pub breached_minutes: usize,
+    pub empty_minutes: usize,
+    /// Minute since start of the job with the highest latency and the latency
+    pub worst_minute: Option<(usize, u64)>,
+}
+
+impl LatencySlo {
+    /// Minutes of `window` without committed transactions are breached, as a stalled cluster
+    /// commits nothing rather than commits slowly
+    pub fn evaluate(
+        &self,
+        minute_latencies: &[AtomicHistogramSnapshot],
+        window: Duration,
+    ) -> SloResult {
+        let minutes = max(minute_latencies.len(), (window.as_secs() / 60) as usize);
+        let latencies: Vec<_> = minute_latencies
+            .iter()
+            .enumerate()
+            .filter(|(_, histogram)| histogram.count() > 0)
+            .map(|(minute, histogram)| (minute, histogram.percentile(self.percentile, 100)))
+            .collect();
+        let empty_minutes = minutes - latencies.len();
+        SloResult {
+            slo: *self,
+            minutes,
+            breached_minutes: empty_minutes
+                + latencies
+                    .iter()
+                    .filter(|(_, latency)| *latency >= self.max_latency_ms)
+                    .count(),
+            empty_minutes,
+            worst_minute: latencies.into_iter().max_by_key(|(_, latency)| *latency),
+        }
+    }
+
+    /// Prefix of metrics reported for this SLO, e.g. `p99_900ms_slo`
+    pub fn metric_prefix(&self) -> String {
+        format!("p{}_{}ms_slo", self.percentile, self.max_latency_ms)
+    }
+}
+
+impl FromStr for LatencySlo {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = || {
+            let mut parts = s.trim().trim_start_matches('p').splitn(2, '<');
+            let percentile = parts.next()?.trim().parse().ok()?;
+            let max_latency_ms = parts.next()?.trim().trim_end_matches("ms").parse().ok()?;
+            Some(Self {
+                percentile,
+                max_latency_ms,
+            })
+        };
+        match parse() {
+            Some(slo) if slo.percentile > 0 && slo.percentile <= 100 => Ok(slo),
+            _ => Err(format_err!(
+                "Invalid latency SLO {}, expected e.g. p99<900",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LatencySlo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "p{} < {} ms", self.percentile, self.max_latency_ms)
+    }
+}
+
+impl fmt::Display for SloResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} breached in {} of {} minutes",
+            self.slo, self.breached_minutes, self.minutes
+        )?;
+        if self.empty_minutes > 0 {
+            write!(f, " ({} without commits)", self.empty_minutes)?;
+        }
+        if let Some((minute, latency)) = self.worst_minute {
+            write!(f, ", worst minute {} at {} ms", minute + 1, latency)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::atomic_histogram::AtomicHistogramAccumulator;
+
+    #[test]
+    fn test_evaluate() {
+        let slo: LatencySlo = "p99<900".parse().unwrap();
+        assert_eq!(
+            slo,
+            LatencySlo {
+                percentile: 99,
+                max_latency_ms: 900
+            }
+        );
+        assert!("p0<900".parse::<LatencySlo>().is_err());
+        assert!("p99".parse::<LatencySlo>().is_err());
+
+        let minutes: Vec<_> = [500, 0, 2000, 1000]
+            .iter()
+            .map(|latency| {
+                let histogram = AtomicHistogramAccumulator::default();
+                if *latency > 0 {
+                    histogram.record_data_point(*latency, 10);
+                }
+                histogram.snapshot()
+            })
+            .collect();
+        let result = slo.evaluate(&minutes, Duration::from_secs(5 * 60));
+        assert_eq!(result.minutes, 5);
+        assert_eq!(result.empty_minutes, 2);
+        assert_eq!(result.breached_minutes, 4);
+        assert_eq!(result.worst_minute, Some((2, 2000)));
+    }
+}
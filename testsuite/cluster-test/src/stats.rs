@@ -1,9 +1,19 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::prometheus::Prometheus;
+use crate::{prometheus::Prometheus, report::SuiteReport};
 use anyhow::format_err;
-use std::time::Duration;
+use libra_logger::warn;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::Duration,
+};
+
+/// Validator is an outlier if its value differs from median of all validators by more than this
+/// fraction of the median
+const OUTLIER_THRESHOLD: f64 = 0.5;
+/// Differences below this are never outliers, so that counts of rare events near zero are ignored
+const OUTLIER_MIN_DELTA: f64 = 2.0;
 
 pub struct PrometheusRangeView<'a> {
     prometheus: &'a Prometheus,
@@ -20,6 +30,41 @@ impl<'a> PrometheusRangeView<'a> {
         }
     }
 
+    /// Transactions committed per second, averaged over validators
+    pub fn avg_committed_tps(&self) -> Option<f64> {
+        self.query_avg(
+            "committed_tps",
+            format!(
+                "avg(rate(libra_consensus_committed_txns_count{{state=\"success\",{selector}}}[1m]))",
+                selector = self.prometheus.validator_selector()
+            ),
+        )
+    }
+
+    /// Average time in seconds from creation of a block to its commit
+    pub fn avg_commit_latency(&self) -> Option<f64> {
+        self.query_avg(
+            "commit_latency",
+            format!(
+                "avg(rate(libra_consensus_creation_to_commit_s_sum{{{selector}}}[1m])/rate(libra_consensus_creation_to_commit_s_count{{{selector}}}[1m]))",
+                selector = self.prometheus.validator_selector()
+            ),
+        )
+    }
+
+    /// 99th percentile of time in seconds from creation of a block to its commit, over the
+    /// whole range rather than averaged per minute
+    pub fn p99_commit_latency(&self) -> Option<f64> {
+        self.query_at_end(
+            "commit_latency",
+            format!(
+                "histogram_quantile(0.99, sum by (le) (increase(libra_consensus_creation_to_commit_s_bucket{{{selector}}}[{}s])))",
+                (self.end - self.start).as_secs(),
+                selector = self.prometheus.validator_selector()
+            ),
+        )
+    }
+
     pub fn avg_txns_per_block(&self) -> Option<f64> {
         self.query_avg(
             "txn_per_block",
@@ -27,12 +72,195 @@ impl<'a> PrometheusRangeView<'a> {
         )
     }
 
+    pub fn max_txns_per_block(&self) -> Option<f64> {
+        self.query_max(
+            "txn_per_block",
+            "irate(libra_consensus_num_txns_per_block_sum[1m])/irate(libra_consensus_num_txns_per_block_count[1m])".to_string(),
+        )
+    }
+
+    /// Average size in bytes of proposals of all validators
+    pub fn avg_proposal_size_bytes(&self) -> Option<f64> {
+        self.query_avg(
+            "proposal_size_bytes",
+            format!(
+                "sum(rate(libra_consensus_proposal_size_bytes_sum{{{selector}}}[1m]))/sum(rate(libra_consensus_proposal_size_bytes_count{{{selector}}}[1m]))",
+                selector = self.prometheus.validator_selector()
+            ),
+        )
+    }
+
+    /// Max over the range and validators of 1 minute average proposal size, in bytes
+    pub fn max_proposal_size_bytes(&self) -> Option<f64> {
+        self.query_at_end(
+            "proposal_size_bytes",
+            format!(
+                "max(max_over_time((rate(libra_consensus_proposal_size_bytes_sum{{{selector}}}[1m])/rate(libra_consensus_proposal_size_bytes_count{{{selector}}}[1m]))[{}s:]))",
+                (self.end - self.start).as_secs(),
+                selector = self.prometheus.validator_selector()
+            ),
+        )
+    }
+
+    /// Average time in seconds between two committed blocks
+    pub fn avg_block_interval(&self) -> Option<f64> {
+        self.query_avg(
+            "block_interval",
+            "1/rate(libra_consensus_committed_blocks_count[1m])".to_string(),
+        )
+    }
+
+    /// Max over the range of 1 minute average block interval, in seconds
+    pub fn max_block_interval(&self) -> Option<f64> {
+        self.query_max(
+            "block_interval",
+            "1/rate(libra_consensus_committed_blocks_count[1m])".to_string(),
+        )
+    }
+
+    /// Average round duration in seconds, both rounds that formed QC and timed out are counted
+    pub fn avg_round_duration(&self) -> Option<f64> {
+        self.query_avg(
+            "round_duration",
+            "1/(rate(libra_consensus_qc_rounds_count[1m])+rate(libra_consensus_timeout_rounds_count[1m]))".to_string(),
+        )
+    }
+
     pub fn avg_backup_bytes_per_second(&self) -> Option<f64> {
         self.query_avg(
             "backup_bytes_per_second",
             "rate(libra_backup_service_sent_bytes[1m])".to_string(),
         )
     }
+
+    /// Average latency in seconds of account state reads by execution
+    pub fn avg_storage_read_latency(&self) -> Option<f64> {
+        self.query_avg(
+            "storage_read_latency",
+            format!(
+                "sum(rate(libra_storage_api_latency_seconds_sum{{api_name=\"get_account_state_with_proof_by_version\",{selector}}}[1m]))/sum(rate(libra_storage_api_latency_seconds_count{{api_name=\"get_account_state_with_proof_by_version\",{selector}}}[1m]))",
+                selector = self.prometheus.validator_selector()
+            ),
+        )
+    }
+
+    /// Average latency in seconds of committing a chunk of transactions to storage
+    pub fn avg_storage_write_latency(&self) -> Option<f64> {
+        self.query_avg(
+            "storage_write_latency",
+            format!(
+                "sum(rate(libra_storage_api_latency_seconds_sum{{api_name=\"save_transactions\",{selector}}}[1m]))/sum(rate(libra_storage_api_latency_seconds_count{{api_name=\"save_transactions\",{selector}}}[1m]))",
+                selector = self.prometheus.validator_selector()
+            ),
+        )
+    }
+
+    /// Size of all column families of the largest validator db
+    pub fn max_state_bytes(&self) -> Option<f64> {
+        self.query_max(
+            "state_bytes",
+            format!(
+                "max(sum by (peer_id) (libra_storage_cf_size_bytes{{{selector}}}))",
+                selector = self.prometheus.validator_selector()
+            ),
+        )
+    }
+
+    /// Network payload bytes received per second by a validator, averaged over validators.
+    /// Framing and encryption overhead of the transport is not included
+    pub fn avg_network_ingress_bytes_per_second(&self) -> Option<f64> {
+        self.query_avg(
+            "network_ingress",
+            network_bytes_rate("received", self.prometheus.validator_selector(), "[1m]"),
+        )
+    }
+
+    /// Network payload bytes sent per second by a validator, averaged over validators
+    pub fn avg_network_egress_bytes_per_second(&self) -> Option<f64> {
+        self.query_avg(
+            "network_egress",
+            network_bytes_rate("sent", self.prometheus.validator_selector(), "[1m]"),
+        )
+    }
+
+    /// Per validator metrics over the range, keyed by metric name and then by peer_id.
+    /// Commits while leader are not exported by consensus, so proposals are the closest proxy
+    pub fn validator_breakdown(&self) -> Vec<(&'static str, HashMap<String, f64>)> {
+        let range = format!("[{}s]", (self.end - self.start).as_secs());
+        let selector = self.prometheus.validator_selector();
+        vec![
+            (
+                "proposals",
+                self.query_per_validator(format!(
+                    "increase(libra_consensus_proposals_count{{{selector}}}{})",
+                    range,
+                    selector = selector
+                )),
+            ),
+            (
+                "nil_votes",
+                self.query_per_validator(format!(
+                    "increase(libra_consensus_vote_nil_count{{{selector}}}{})",
+                    range,
+                    selector = selector
+                )),
+            ),
+            (
+                "timeouts",
+                self.query_per_validator(format!(
+                    "increase(libra_consensus_timeout_count{{{selector}}}{})",
+                    range,
+                    selector = selector
+                )),
+            ),
+            (
+                "network_in_bytes_per_sec",
+                self.query_per_validator(network_bytes_rate(
+                    "received",
+                    self.prometheus.validator_selector(),
+                    &range,
+                )),
+            ),
+            (
+                "network_out_bytes_per_sec",
+                self.query_per_validator(network_bytes_rate(
+                    "sent",
+                    self.prometheus.validator_selector(),
+                    &range,
+                )),
+            ),
+            (
+                "avg_mempool_size",
+                self.query_per_validator(format!(
+                    "avg_over_time(libra_core_mempool_index_size{{index=\"system_ttl\",{selector}}}{})",
+                    range,
+                    selector = selector
+                )),
+            ),
+        ]
+    }
+
+    /// Per validator consensus latencies over the range in ms, keyed like validator_breakdown.
+    /// Votes go to the next leader, so QC latency of a validator is its latency as leader, and
+    /// receival latency tells how late proposals reach it for voting
+    pub fn validator_latencies(&self) -> Vec<(&'static str, HashMap<String, f64>)> {
+        let range = format!("[{}s]", (self.end - self.start).as_secs());
+        let avg_ms = |histogram: &str| {
+            self.query_per_validator(format!(
+                "1000 * increase({0}_sum{{{selector}}}{1}) / increase({0}_count{{{selector}}}{1})",
+                histogram,
+                range,
+                selector = self.prometheus.validator_selector()
+            ))
+        };
+        vec![
+            ("qc_latency_ms", avg_ms("libra_consensus_creation_to_qc_s")),
+            (
+                "proposal_receival_ms",
+                avg_ms("libra_consensus_creation_to_receival_s"),
+            ),
+        ]
+    }
 }
 
 impl<'a> PrometheusRangeView<'a> {
@@ -44,4 +272,224 @@ impl<'a> PrometheusRangeView<'a> {
             .map_err(|e| format_err!("No {} data: {}", name, e))
             .ok()
     }
+
+    fn query_max(&self, name: &str, query: String) -> Option<f64> {
+        self.prometheus
+            .query_range_max(query, &self.start, &self.end, Self::STEP)
+            .map_err(|e| format_err!("No {} data: {}", name, e))
+            .ok()
+    }
+
+    /// Evaluates query over the whole range at the end of the range
+    fn query_at_end(&self, name: &str, query: String) -> Option<f64> {
+        self.prometheus
+            .query_range_max(query, &self.end, &self.end, Self::STEP)
+            .map_err(|e| format_err!("No {} data: {}", name, e))
+            .ok()
+    }
+
+    /// Evaluates range query at the end of the range, validators without data are left out
+    fn query_per_validator(&self, query: String) -> HashMap<String, f64> {
+        self.prometheus
+            .query_range(query, &self.end, &self.end, Self::STEP)
+            .map(|response| {
+                response
+                    .time_series()
+                    .iter()
+                    .filter_map(|(peer_id, ts)| Some((peer_id.clone(), ts.get().last()?.1)))
+                    .filter(|(_, v)| v.is_finite())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Block, storage and network stats of the range along with per validator breakdown.
+/// `committed_tps` is used to report network bytes per transaction
+pub fn report_range(
+    report: &mut SuiteReport,
+    experiment: &str,
+    pv: &PrometheusRangeView,
+    committed_tps: f64,
+) {
+    // Proposal size and round timing, to tell whether throughput comes from bigger blocks
+    // or faster rounds
+    let mut block_stats = vec![];
+    let block_metrics = [
+        ("avg_block_size_bytes", pv.avg_proposal_size_bytes(), 1.0),
+        ("max_block_size_bytes", pv.max_proposal_size_bytes(), 1.0),
+        ("max_txns_per_block", pv.max_txns_per_block(), 1.0),
+        ("avg_block_interval_ms", pv.avg_block_interval(), 1000.0),
+        ("max_block_interval_ms", pv.max_block_interval(), 1000.0),
+        ("avg_round_duration_ms", pv.avg_round_duration(), 1000.0),
+    ];
+    for (metric, value, scale) in block_metrics.iter() {
+        if let Some(value) = value {
+            let value = value * scale;
+            report.report_metric(experiment, metric, value);
+            block_stats.push(format!("{} {:.0}", metric, value));
+        }
+    }
+    if !block_stats.is_empty() {
+        report.report_text(format!("{}: {}", experiment, block_stats.join(", ")));
+    }
+
+    // Storage latency depends on size of the state tree, so ledger size is reported along
+    let mut storage_stats = vec![];
+    let storage_metrics = [
+        (
+            "avg_storage_read_latency_ms",
+            pv.avg_storage_read_latency(),
+            1000.0,
+        ),
+        (
+            "avg_storage_write_latency_ms",
+            pv.avg_storage_write_latency(),
+            1000.0,
+        ),
+        ("state_bytes", pv.max_state_bytes(), 1.0),
+    ];
+    for (metric, value, scale) in storage_metrics.iter() {
+        if let Some(value) = value {
+            let value = value * scale;
+            report.report_metric(experiment, metric, value);
+            storage_stats.push(format!("{} {:.2}", metric, value));
+        }
+    }
+    if !storage_stats.is_empty() {
+        report.report_text(format!("{}: {}", experiment, storage_stats.join(", ")));
+    }
+
+    // Bytes per committed transaction stay flat as TPS grows unless gossip amplifies
+    let mut network_stats = vec![];
+    let network_metrics = [
+        ("network_in", pv.avg_network_ingress_bytes_per_second()),
+        ("network_out", pv.avg_network_egress_bytes_per_second()),
+    ];
+    for (direction, bytes_per_sec) in network_metrics.iter() {
+        if let Some(bytes_per_sec) = bytes_per_sec {
+            report.report_metric(
+                experiment,
+                format!("avg_{}_bytes_per_sec", direction),
+                *bytes_per_sec,
+            );
+            network_stats.push(format!("{} {:.0} B/s", direction, bytes_per_sec));
+            if committed_tps > 0.0 {
+                let bytes_per_txn = bytes_per_sec / committed_tps;
+                report.report_metric(
+                    experiment,
+                    format!("{}_bytes_per_txn", direction),
+                    bytes_per_txn,
+                );
+                network_stats.push(format!("{} {:.0} B/txn", direction, bytes_per_txn));
+            }
+        }
+    }
+    if !network_stats.is_empty() {
+        report.report_text(format!(
+            "{}: network per validator: {}",
+            experiment,
+            network_stats.join(", ")
+        ));
+    }
+
+    report_validator_breakdown(report, experiment, pv);
+}
+
+/// Per validator table, outliers are marked so that a single bad node is easy to tell
+/// from a fleet-wide regression
+fn report_validator_breakdown(
+    report: &mut SuiteReport,
+    experiment: &str,
+    pv: &PrometheusRangeView,
+) {
+    let mut rows: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut outlier_validators = HashSet::new();
+    for (metric, values) in pv.validator_breakdown() {
+        let outliers = outliers(&values);
+        for (peer_id, value) in &values {
+            let marker = if outliers.contains(peer_id) {
+                "(!)"
+            } else {
+                ""
+            };
+            rows.entry(peer_id.clone())
+                .or_default()
+                .push(format!("{} {:.0}{}", metric, value, marker));
+        }
+        outlier_validators.extend(outliers);
+    }
+    if rows.is_empty() {
+        warn!("No per validator metrics available");
+        return;
+    }
+    report.report_metric(
+        experiment,
+        "outlier_validators",
+        outlier_validators.len() as f64,
+    );
+    let mut text = format!(
+        "{}: per validator breakdown, (!) marks outliers",
+        experiment
+    );
+    for (peer_id, row) in rows {
+        text.push_str(&format!("\n  {}: {}", peer_id, row.join(", ")));
+    }
+    report.report_text(text);
+}
+
+/// Per peer_id rate over `range` of RPC and direct send payload bytes of validators selected by
+/// `selector` in given direction, "sent" or "received". Sent direct send messages are observed
+/// both when queued by the network interface and when written by the protocol into the same
+/// series, so only half of it is counted. RPC series carry a `type` label, hence `or` keeps both
+fn network_bytes_rate(state: &str, selector: &str, range: &str) -> String {
+    let direct_send_share = if state == "sent" { " / 2" } else { "" };
+    format!(
+        "sum by (peer_id) (rate(libra_network_rpc_bytes_sum{{state=\"{state}\",{selector}}}{range}) \
+         or rate(libra_network_direct_send_bytes_sum{{state=\"{state}\",{selector}}}{range}){share})",
+        state = state,
+        selector = selector,
+        range = range,
+        share = direct_send_share
+    )
+}
+
+/// Validators which value is far from median of all validators, sorted by peer_id
+pub fn outliers(values: &HashMap<String, f64>) -> Vec<String> {
+    let mut sorted: Vec<_> = values.values().cloned().collect();
+    if sorted.len() < 3 {
+        return vec![];
+    }
+    sorted.sort_by(|a, b| a.partial_cmp(b).expect("Values are finite"));
+    let median = sorted[sorted.len() / 2];
+    let mut outliers: Vec<_> = values
+        .iter()
+        .filter(|(_, v)| (*v - median).abs() > (OUTLIER_THRESHOLD * median).max(OUTLIER_MIN_DELTA))
+        .map(|(peer_id, _)| peer_id.clone())
+        .collect();
+    outliers.sort();
+    outliers
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_outliers() {
+        let values = |v: &[f64]| -> HashMap<String, f64> {
+            v.iter()
+                .enumerate()
+                .map(|(i, v)| (format!("val-{}", i), *v))
+                .collect()
+        };
+        assert!(outliers(&values(&[100.0, 90.0, 110.0, 105.0])).is_empty());
+        assert_eq!(
+            outliers(&values(&[100.0, 10.0, 110.0, 300.0])),
+            vec!["val-1".to_string(), "val-3".to_string()]
+        );
+        // Small counts around zero are not outliers
+        assert!(outliers(&values(&[0.0, 1.0, 0.0, 0.0])).is_empty());
+        assert_eq!(outliers(&values(&[0.0, 5.0, 0.0, 0.0])), vec!["val-1"]);
+    }
 }
@@ -2,89 +2,274 @@
 // SPDX-License-Identifier: Apache-2.0
 
 #![forbid(unsafe_code)]
-use std::{cmp::min, env};
+use std::{
+    cmp::min,
+    collections::{BTreeMap, HashSet},
+    env, fmt,
+    time::Duration,
+};
 
 use crate::{
     cluster::Cluster,
     experiments::{
         CompatiblityTestParams, CpuFlamegraphParams, Experiment, ExperimentParam,
         PerformanceBenchmarkParams, PerformanceBenchmarkThreeRegionSimulationParams,
-        RebootRandomValidatorsParams, RecoveryTimeParams, TwinValidatorsParams,
+        PrepopulateParams, RebootRandomValidatorsParams, RecoveryTimeParams, TwinValidatorsParams,
     },
 };
-use anyhow::{format_err, Result};
+use anyhow::{bail, format_err, Result};
+use libra_logger::{info, warn};
+use serde::{Deserialize, Serialize};
 
+/// Entries run in order they are added, except that an entry always runs after the entries its
+/// constraints refer to
 pub struct ExperimentSuite {
-    pub experiments: Vec<Box<dyn Experiment>>,
+    pub entries: Vec<SuiteEntry>,
+    /// Wall-clock time the suite has to finish in, entries which do not fit are skipped
+    pub budget: Option<Duration>,
+}
+
+/// Time budget of the fast land blocking suite, which gates merges
+const FAST_SUITE_BUDGET: Duration = Duration::from_secs(12 * 60);
+
+pub struct SuiteEntry {
+    /// Unique within the suite, constraints of other entries refer to the entry by it
+    pub id: String,
+    pub experiment: Box<dyn Experiment>,
+    /// Entry is skipped unless all of these passed
+    pub depends_on: Vec<String>,
+    /// Entry is skipped if any of these had given outcome
+    pub skip_if: Vec<(String, Outcome)>,
+    /// Entries of lowest priority are the first skipped to fit the suite into its time budget
+    pub priority: Priority,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Outcome {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+impl SuiteEntry {
+    pub fn new<S: Into<String>>(id: S, experiment: Box<dyn Experiment>) -> Self {
+        Self {
+            id: id.into(),
+            experiment,
+            depends_on: vec![],
+            skip_if: vec![],
+            priority: Priority::Normal,
+        }
+    }
+
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn depends_on<S: Into<String>>(mut self, id: S) -> Self {
+        self.depends_on.push(id.into());
+        self
+    }
+
+    pub fn skip_if<S: Into<String>>(mut self, id: S, outcome: Outcome) -> Self {
+        self.skip_if.push((id.into(), outcome));
+        self
+    }
+
+    /// Ids of entries which have to run before this one
+    fn constraints(&self) -> impl Iterator<Item = &String> {
+        self.depends_on
+            .iter()
+            .chain(self.skip_if.iter().map(|(id, _)| id))
+    }
+
+    /// Reason to skip the entry given outcomes of entries which ran before it, entries missing
+    /// from `outcomes` were filtered out of the suite and do not constrain it
+    pub fn skip_reason(&self, outcomes: &BTreeMap<String, Outcome>) -> Option<String> {
+        for id in &self.depends_on {
+            match outcomes.get(id) {
+                Some(Outcome::Passed) | None => (),
+                Some(outcome) => return Some(format!("{} {}", id, outcome)),
+            }
+        }
+        self.skip_if.iter().find_map(|(id, outcome)| {
+            if outcomes.get(id) == Some(outcome) {
+                Some(format!("{} {}", id, outcome))
+            } else {
+                None
+            }
+        })
+    }
 }
 
 impl ExperimentSuite {
     fn new_pre_release(cluster: &Cluster) -> Self {
-        let mut experiments: Vec<Box<dyn Experiment>> = vec![];
+        let mut entries = vec![];
         if env::var("RECOVERY_EXP").is_ok() {
-            experiments.push(Box::new(
-                RecoveryTimeParams {
-                    num_accounts_to_mint: 100_000,
-                }
-                .build(cluster),
+            entries.push(SuiteEntry::new(
+                "recovery_time",
+                Box::new(
+                    RecoveryTimeParams {
+                        num_accounts_to_mint: 100_000,
+                    }
+                    .build(cluster),
+                ),
             ));
         }
         let count = min(3, cluster.validator_instances().len() / 3);
         // Reboot different sets of 3 validators *100 times
-        for _ in 0..10 {
+        for i in 0..10 {
             let b = Box::new(RebootRandomValidatorsParams::new(count, 0).build(cluster));
-            experiments.push(b);
+            entries.push(SuiteEntry::new(format!("reboot_{}", i), b));
         }
-        experiments.push(Box::new(
-            PerformanceBenchmarkParams::new_nodes_down(0)
-                .enable_db_backup()
-                .build(cluster),
-        ));
-        experiments.push(Box::new(
-            PerformanceBenchmarkParams::new_nodes_down(10)
-                .enable_db_backup()
-                .build(cluster),
-        ));
-        experiments.push(Box::new(
-            PerformanceBenchmarkThreeRegionSimulationParams {}.build(cluster),
-        ));
-        experiments.push(Box::new(
-            PerformanceBenchmarkParams::new_fixed_tps(0, 10)
-                .enable_db_backup()
-                .build(cluster),
+        entries.push(SuiteEntry::new(
+            "perf_baseline",
+            Box::new(
+                PerformanceBenchmarkParams::new_nodes_down(0)
+                    .enable_db_backup()
+                    .build(cluster),
+            ),
         ));
+        // Numbers of experiments below are only meaningful next to a healthy baseline
+        entries.push(
+            SuiteEntry::new(
+                "perf_nodes_down",
+                Box::new(
+                    PerformanceBenchmarkParams::new_nodes_down(10)
+                        .enable_db_backup()
+                        .build(cluster),
+                ),
+            )
+            .depends_on("perf_baseline"),
+        );
+        entries.push(
+            SuiteEntry::new(
+                "perf_three_region",
+                Box::new(PerformanceBenchmarkThreeRegionSimulationParams {}.build(cluster)),
+            )
+            .depends_on("perf_baseline"),
+        );
+        entries.push(
+            SuiteEntry::new(
+                "perf_fixed_tps",
+                Box::new(
+                    PerformanceBenchmarkParams::new_fixed_tps(0, 10)
+                        .enable_db_backup()
+                        .build(cluster),
+                ),
+            )
+            .depends_on("perf_baseline"),
+        );
         if env::var("TWIN_EXPERIMENT").is_ok() {
-            experiments.push(Box::new(TwinValidatorsParams { pair: 1 }.build(cluster)));
+            entries.push(
+                SuiteEntry::new(
+                    "twin_validators",
+                    Box::new(TwinValidatorsParams { pair: 1 }.build(cluster)),
+                )
+                .depends_on("perf_baseline"),
+            );
         }
-        experiments.push(Box::new(
-            CpuFlamegraphParams { duration_secs: 60 }.build(cluster),
+        entries.push(SuiteEntry::new(
+            "cpu_flamegraph",
+            Box::new(
+                CpuFlamegraphParams {
+                    duration_secs: 60,
+                    candidate_image_tag: None,
+                    tps: None,
+                }
+                .build(cluster),
+            ),
         ));
-        Self { experiments }
+        Self {
+            entries,
+            budget: None,
+        }
     }
 
     fn new_perf_suite(cluster: &Cluster) -> Self {
-        let mut experiments: Vec<Box<dyn Experiment>> = vec![];
-        experiments.push(Box::new(
-            PerformanceBenchmarkParams::new_nodes_down(0).build(cluster),
+        let mut entries = vec![];
+        if let Ok(accounts) = env::var("PREPOPULATE_ACCOUNTS") {
+            let accounts = accounts
+                .parse()
+                .expect("PREPOPULATE_ACCOUNTS is not a number");
+            entries.push(SuiteEntry::new(
+                "prepopulate",
+                Box::new(
+                    PrepopulateParams {
+                        accounts,
+                        state_gb: None,
+                        round_accounts: 100_000,
+                        tiers: vec![],
+                        tier_duration_secs: 120,
+                    }
+                    .build(cluster),
+                ),
+            ));
+        }
+        entries.push(SuiteEntry::new(
+            "perf_baseline",
+            Box::new(PerformanceBenchmarkParams::new_nodes_down(0).build(cluster)),
         ));
-        experiments.push(Box::new(
-            PerformanceBenchmarkParams::new_nodes_down(10).build(cluster),
+        entries.push(SuiteEntry::new(
+            "perf_nodes_down",
+            Box::new(PerformanceBenchmarkParams::new_nodes_down(10).build(cluster)),
         ));
-        experiments.push(Box::new(
-            PerformanceBenchmarkThreeRegionSimulationParams {}.build(cluster),
+        entries.push(SuiteEntry::new(
+            "perf_three_region",
+            Box::new(PerformanceBenchmarkThreeRegionSimulationParams {}.build(cluster)),
         ));
-        experiments.push(Box::new(
-            PerformanceBenchmarkParams::new_fixed_tps(0, 10).build(cluster),
+        entries.push(SuiteEntry::new(
+            "perf_fixed_tps",
+            Box::new(PerformanceBenchmarkParams::new_fixed_tps(0, 10).build(cluster)),
         ));
-        Self { experiments }
+        Self {
+            entries,
+            budget: None,
+        }
     }
 
     fn new_land_blocking_suite(cluster: &Cluster) -> Self {
-        let mut experiments: Vec<Box<dyn Experiment>> = vec![];
-        experiments.push(Box::new(
-            PerformanceBenchmarkParams::new_nodes_down(0).build(cluster),
-        ));
-        Self { experiments }
+        let entries = vec![SuiteEntry::new(
+            "perf_baseline",
+            Box::new(PerformanceBenchmarkParams::new_nodes_down(0).build(cluster)),
+        )];
+        Self {
+            entries,
+            budget: None,
+        }
+    }
+
+    /// Land blocking checks which fit into `FAST_SUITE_BUDGET`, experiments are trimmed to
+    /// fit and the least important ones are skipped on slower clusters
+    fn new_land_blocking_fast_suite(cluster: &Cluster) -> Self {
+        let entries = vec![
+            SuiteEntry::new(
+                "perf_baseline",
+                Box::new(PerformanceBenchmarkParams::new_nodes_down(0).build(cluster)),
+            )
+            .priority(Priority::High),
+            SuiteEntry::new(
+                "reboot",
+                Box::new(RebootRandomValidatorsParams::new(1, 0).build(cluster)),
+            ),
+            SuiteEntry::new(
+                "perf_fixed_tps",
+                Box::new(PerformanceBenchmarkParams::new_fixed_tps(0, 10).build(cluster)),
+            )
+            .priority(Priority::Low),
+        ];
+        Self {
+            entries,
+            budget: Some(FAST_SUITE_BUDGET),
+        }
     }
 
     fn new_land_blocking_compat_suite(cluster: &Cluster) -> Result<Self> {
@@ -96,25 +281,279 @@ impl ExperimentSuite {
         };
         let updated_image_tag = env::var("UPDATE_TO_TAG")
             .map_err(|_| format_err!("Expected environment variable UPDATE_TO_TAG"))?;
-        let mut experiments: Vec<Box<dyn Experiment>> = vec![];
-        experiments.push(Box::new(
-            CompatiblityTestParams {
-                count,
-                updated_image_tag,
+        let mut entries = vec![SuiteEntry::new(
+            "compatibility",
+            Box::new(
+                CompatiblityTestParams {
+                    count,
+                    updated_image_tag,
+                    prepull_images: true,
+                }
+                .build(cluster),
+            ),
+        )];
+        entries.extend(Self::new_land_blocking_suite(cluster).entries);
+        Ok(Self {
+            entries,
+            budget: None,
+        })
+    }
+
+    pub fn experiments(&self) -> impl Iterator<Item = &dyn Experiment> {
+        self.entries.iter().map(|entry| entry.experiment.as_ref())
+    }
+
+    /// Ids of entries which constraints of other entries refer to. Failure of such entry is
+    /// handled by the constraints, so it does not stop the suite
+    pub fn constrained_ids(&self) -> HashSet<String> {
+        self.entries
+            .iter()
+            .flat_map(SuiteEntry::constraints)
+            .cloned()
+            .collect()
+    }
+
+    /// Keeps experiments which have at least one of `include` tags (all experiments if `include`
+    /// is empty) and none of `exclude` tags
+    pub fn filter_by_tags(&mut self, include: &[String], exclude: &[String]) {
+        self.entries.retain(|entry| {
+            let tags = entry.experiment.tags();
+            let has_tag = |t: &String| tags.contains(&t.as_str());
+            let keep =
+                (include.is_empty() || include.iter().any(has_tag)) && !exclude.iter().any(has_tag);
+            if !keep {
+                info!("Skipping {} with tags {:?}", entry.experiment, tags);
             }
-            .build(cluster),
-        ));
-        experiments.extend(Self::new_land_blocking_suite(cluster).experiments);
-        Ok(Self { experiments })
+            keep
+        });
+    }
+
+    /// Sum of expected durations of experiments of the suite
+    pub fn expected_duration(&self) -> Duration {
+        self.experiments().map(|e| e.expected_duration()).sum()
+    }
+
+    /// Trims experiments so that the suite is expected to finish within `budget`, then skips
+    /// entries of lowest priority, latest added first, while it still does not fit. Entries
+    /// depending on a skipped entry are skipped with it. Returns skipped entries
+    pub fn fit_to_budget(&mut self, budget: Duration) -> Result<Vec<SuiteEntry>> {
+        let mut skipped = vec![];
+        loop {
+            let expected = self.expected_duration();
+            if expected <= budget {
+                break;
+            }
+            let scale = budget.as_secs_f64() / expected.as_secs_f64();
+            for entry in &mut self.entries {
+                let trimmed = entry.experiment.expected_duration().mul_f64(scale);
+                entry.experiment.trim(trimmed);
+            }
+            if self.expected_duration() <= budget {
+                break;
+            }
+            let lowest = match self.entries.iter().map(|entry| entry.priority).min() {
+                Some(lowest) => lowest,
+                None => break,
+            };
+            let i = self
+                .entries
+                .iter()
+                .rposition(|entry| entry.priority == lowest)
+                .expect("Entry of lowest priority exists");
+            let mut dropped = vec![self.entries.remove(i)];
+            while let Some(j) = self.entries.iter().position(|entry| {
+                entry
+                    .depends_on
+                    .iter()
+                    .any(|id| dropped.iter().any(|d| &d.id == id))
+            }) {
+                dropped.push(self.entries.remove(j));
+            }
+            for entry in &dropped {
+                warn!(
+                    "Skipping {} to fit suite into {} secs",
+                    entry.experiment,
+                    budget.as_secs()
+                );
+            }
+            skipped.extend(dropped);
+        }
+        if self.entries.is_empty() {
+            bail!(
+                "No experiment of the suite fits into time budget of {} secs",
+                budget.as_secs()
+            );
+        }
+        Ok(skipped)
+    }
+
+    /// Orders entries so that each runs after entries its constraints refer to, otherwise
+    /// keeping order they were added in. Fails on unknown or duplicate ids and on cycles
+    fn order(&mut self) -> Result<()> {
+        let constraints: Vec<_> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let constraints: Vec<_> = entry.constraints().map(String::as_str).collect();
+                (entry.id.as_str(), constraints)
+            })
+            .collect();
+        let order = topological_order(&constraints)?;
+        let mut entries: Vec<_> = self.entries.drain(..).map(Some).collect();
+        self.entries = order
+            .into_iter()
+            .map(|i| entries[i].take().expect("Entry is ordered once"))
+            .collect();
+        Ok(())
     }
 
     pub fn new_by_name(cluster: &Cluster, name: &str) -> Result<Self> {
-        match name {
-            "perf" => Ok(Self::new_perf_suite(cluster)),
-            "pre_release" => Ok(Self::new_pre_release(cluster)),
-            "land_blocking" => Ok(Self::new_land_blocking_suite(cluster)),
-            "land_blocking_compat" => Self::new_land_blocking_compat_suite(cluster),
-            other => Err(format_err!("Unknown suite: {}", other)),
+        let mut suite = match name {
+            "perf" => Self::new_perf_suite(cluster),
+            "pre_release" => Self::new_pre_release(cluster),
+            "land_blocking" => Self::new_land_blocking_suite(cluster),
+            "land_blocking_fast" => Self::new_land_blocking_fast_suite(cluster),
+            "land_blocking_compat" => Self::new_land_blocking_compat_suite(cluster)?,
+            other => return Err(format_err!("Unknown suite: {}", other)),
+        };
+        suite.order()?;
+        Ok(suite)
+    }
+}
+
+/// Indices of entries given as ids with ids of entries they run after, earliest added entry
+/// which can run goes first
+fn topological_order(entries: &[(&str, Vec<&str>)]) -> Result<Vec<usize>> {
+    let mut indices = BTreeMap::new();
+    for (i, (id, _)) in entries.iter().enumerate() {
+        if indices.insert(*id, i).is_some() {
+            bail!("Duplicate suite entry {}", id);
+        }
+    }
+    for (id, constraints) in entries {
+        if let Some(unknown) = constraints.iter().find(|c| !indices.contains_key(*c)) {
+            bail!("Suite entry {} refers to unknown entry {}", id, unknown);
+        }
+    }
+    let mut order = Vec::with_capacity(entries.len());
+    let mut done = vec![false; entries.len()];
+    while order.len() < entries.len() {
+        let next = (0..entries.len())
+            .find(|i| !done[*i] && entries[*i].1.iter().all(|c| done[indices[c]]));
+        match next {
+            Some(i) => {
+                done[i] = true;
+                order.push(i);
+            }
+            None => {
+                let cycle: Vec<_> = (0..entries.len())
+                    .filter(|i| !done[*i])
+                    .map(|i| entries[i].0)
+                    .collect();
+                bail!("Suite entries have cyclic constraints: {:?}", cycle);
+            }
+        }
+    }
+    Ok(order)
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Passed => write!(f, "passed"),
+            Outcome::Failed => write!(f, "failed"),
+            Outcome::Skipped => write!(f, "was skipped"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::experiments::Context;
+    use async_trait::async_trait;
+
+    /// Runs for `duration`, which can be trimmed down to `min`
+    struct FakeExperiment {
+        duration: Duration,
+        min: Duration,
+    }
+
+    impl fmt::Display for FakeExperiment {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "Fake({}s)", self.duration.as_secs())
+        }
+    }
+
+    #[async_trait]
+    impl Experiment for FakeExperiment {
+        async fn run(&mut self, _context: &mut Context<'_>) -> Result<()> {
+            Ok(())
+        }
+
+        fn deadline(&self) -> Duration {
+            self.duration
+        }
+
+        fn trim(&mut self, duration: Duration) {
+            self.duration = duration.max(self.min);
         }
     }
+
+    fn entry(id: &str, secs: u64, min_secs: u64) -> SuiteEntry {
+        SuiteEntry::new(
+            id,
+            Box::new(FakeExperiment {
+                duration: Duration::from_secs(secs),
+                min: Duration::from_secs(min_secs),
+            }),
+        )
+    }
+
+    #[test]
+    fn test_fit_to_budget() {
+        let mut suite = ExperimentSuite {
+            entries: vec![
+                entry("baseline", 100, 100).priority(Priority::High),
+                entry("bench", 100, 60),
+                entry("reboot", 100, 100).priority(Priority::Low),
+                entry("recovery", 100, 100).depends_on("reboot"),
+            ],
+            budget: None,
+        };
+        let constrained: Vec<_> = suite.constrained_ids().into_iter().collect();
+        assert_eq!(constrained, vec!["reboot".to_string()]);
+
+        // Trimming only gets the suite to 360 secs, so the low priority entry is skipped along
+        // with the entry depending on it
+        let skipped = suite.fit_to_budget(Duration::from_secs(200)).unwrap();
+        let skipped: Vec<_> = skipped.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(skipped, vec!["reboot", "recovery"]);
+        let ids: Vec<_> = suite.entries.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, vec!["baseline", "bench"]);
+        assert_eq!(suite.expected_duration(), Duration::from_secs(160));
+        assert!(suite.constrained_ids().is_empty());
+
+        assert!(suite
+            .fit_to_budget(Duration::from_secs(200))
+            .unwrap()
+            .is_empty());
+        assert!(suite.fit_to_budget(Duration::from_secs(10)).is_err());
+    }
+
+    #[test]
+    fn test_topological_order() {
+        let entries = vec![
+            ("upgrade", vec!["baseline"]),
+            ("reboot", vec![]),
+            ("baseline", vec![]),
+            ("flamegraph", vec!["upgrade", "reboot"]),
+        ];
+        assert_eq!(topological_order(&entries).unwrap(), vec![1, 2, 0, 3]);
+
+        let unknown = vec![("upgrade", vec!["baseline"])];
+        assert!(topological_order(&unknown).is_err());
+        let cycle = vec![("a", vec!["b"]), ("b", vec!["a"]), ("c", vec![])];
+        assert!(topological_order(&cycle).is_err());
+    }
 }
@@ -0,0 +1,114 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Renders TPS and latency of a run over time into an SVG chart, so that oscillation or
+/// collapse of throughput can be seen at a glance instead of being averaged away
+use crate::prometheus::Prometheus;
+use anyhow::{format_err, Result};
+use libra_logger::warn;
+use plotters::prelude::*;
+use std::time::Duration;
+
+const CHART_WIDTH: u32 = 1000;
+const CHART_HEIGHT: u32 = 300;
+/// Prometheus rejects range queries with more than 11000 points
+const MAX_POINTS: u64 = 10_000;
+
+pub struct Timeline {
+    pub title: &'static str,
+    pub unit: &'static str,
+    /// Secs since start of the run and value
+    pub points: Vec<(f64, f64)>,
+}
+
+/// Per second committed TPS and commit latency averaged across validators
+pub fn query_timelines(prometheus: &Prometheus, start: Duration, end: Duration) -> Vec<Timeline> {
+    let step = max_step(start, end);
+    let queries = [
+        (
+            "Committed TPS",
+            "txn/s",
+            "avg(rate(libra_consensus_committed_txns_count{state=\"success\",peer_id=~\"val-.*\"}[10s]))",
+            1.0,
+        ),
+        (
+            "Block creation to commit latency",
+            "ms",
+            "avg(rate(libra_consensus_creation_to_commit_s_sum{peer_id=~\"val-.*\"}[10s])/rate(libra_consensus_creation_to_commit_s_count{peer_id=~\"val-.*\"}[10s]))",
+            1000.0,
+        ),
+    ];
+    let mut timelines = vec![];
+    for (title, unit, query, scale) in queries.iter() {
+        let response = match prometheus.query_range(query.to_string(), &start, &end, step) {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("Failed to query {}: {}", title, e);
+                continue;
+            }
+        };
+        let points = response
+            .time_series()
+            .values()
+            .flat_map(|ts| ts.get().iter())
+            .filter(|(_, v)| v.is_finite())
+            .map(|(t, v)| ((*t as f64) - start.as_secs_f64(), v * scale))
+            .collect();
+        timelines.push(Timeline {
+            title,
+            unit,
+            points,
+        });
+    }
+    timelines
+}
+
+/// One chart per timeline, stacked vertically
+pub fn render_svg(timelines: &[Timeline]) -> Result<String> {
+    let mut svg = String::new();
+    {
+        let height = CHART_HEIGHT * timelines.len() as u32;
+        let root = SVGBackend::with_string(&mut svg, (CHART_WIDTH, height)).into_drawing_area();
+        root.fill(&WHITE)
+            .map_err(|e| format_err!("Failed to draw chart: {:?}", e))?;
+        for (area, timeline) in root
+            .split_evenly((timelines.len(), 1))
+            .iter()
+            .zip(timelines)
+        {
+            draw_timeline(area, timeline)
+                .map_err(|e| format_err!("Failed to draw {}: {:?}", timeline.title, e))?;
+        }
+        root.present()
+            .map_err(|e| format_err!("Failed to render chart: {:?}", e))?;
+    }
+    Ok(svg)
+}
+
+fn draw_timeline<DB: DrawingBackend>(
+    area: &DrawingArea<DB, plotters::coord::Shift>,
+    timeline: &Timeline,
+) -> std::result::Result<(), DrawingAreaErrorKind<DB::ErrorType>> {
+    let max_x = timeline.points.iter().map(|(x, _)| *x).fold(1.0, f64::max);
+    let max_y = timeline.points.iter().map(|(_, y)| *y).fold(1.0, f64::max);
+    let mut chart = ChartBuilder::on(area)
+        .caption(timeline.title, ("sans-serif", 18))
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(60)
+        .build_ranged(0f64..max_x, 0f64..max_y * 1.1)?;
+    chart
+        .configure_mesh()
+        .x_desc("secs since start")
+        .y_desc(timeline.unit)
+        .draw()?;
+    chart.draw_series(LineSeries::new(timeline.points.iter().cloned(), &BLUE))?;
+    Ok(())
+}
+
+fn max_step(start: Duration, end: Duration) -> u64 {
+    let range = end.as_secs().saturating_sub(start.as_secs());
+    (range / MAX_POINTS).max(1)
+}
@@ -0,0 +1,239 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Graphviz snapshots of the cluster: validators and their fullnodes, which of them are down
+/// or lagging, and effects active on them. Taken around experiments and embedded in the
+/// report, so that reviewers can see what an experiment did to the network
+use crate::{cluster::Cluster, instance::Instance};
+use futures::future::join_all;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fmt::{self, Display, Write},
+    sync::Mutex,
+};
+
+/// Instances further behind the most advanced one are drawn as degraded
+const LAG_THRESHOLD: u64 = 1000;
+
+/// Descriptions of effects which are activated and not deactivated yet
+static ACTIVE_FAULTS: Lazy<Mutex<Vec<String>>> = Lazy::new(|| Mutex::new(vec![]));
+
+pub fn fault_applied(effect: &dyn Display) {
+    ACTIVE_FAULTS
+        .lock()
+        .expect("faults lock poisoned")
+        .push(effect.to_string());
+}
+
+pub fn fault_removed(effect: &dyn Display) {
+    let effect = effect.to_string();
+    let mut faults = ACTIVE_FAULTS.lock().expect("faults lock poisoned");
+    if let Some(index) = faults.iter().position(|f| *f == effect) {
+        faults.remove(index);
+    }
+}
+
+/// Experiment whose snapshot `faults_activated` takes, along with the snapshot once taken
+struct Watched {
+    cluster: Cluster,
+    title: String,
+    affected: HashSet<String>,
+    snapshot: Option<TopologySnapshot>,
+}
+
+static WATCHED: Lazy<Mutex<Option<Watched>>> = Lazy::new(|| Mutex::new(None));
+
+/// Snapshot titled `title` is taken the first time faults are activated until
+/// `unwatch_experiment`, so that the report shows the network while the experiment disrupts it
+pub fn watch_experiment(cluster: &Cluster, title: String, affected: &HashSet<String>) {
+    *WATCHED.lock().expect("watched lock poisoned") = Some(Watched {
+        cluster: cluster.clone(),
+        title,
+        affected: affected.clone(),
+        snapshot: None,
+    });
+}
+
+/// Snapshot taken while faults of the watched experiment were active, if any
+pub fn unwatch_experiment() -> Option<TopologySnapshot> {
+    WATCHED
+        .lock()
+        .expect("watched lock poisoned")
+        .take()
+        .and_then(|watched| watched.snapshot)
+}
+
+/// Called once effects are active, takes snapshot of the watched experiment unless it has one
+pub async fn faults_activated() {
+    let (cluster, title, affected) = match &*WATCHED.lock().expect("watched lock poisoned") {
+        Some(watched) if watched.snapshot.is_none() => (
+            watched.cluster.clone(),
+            watched.title.clone(),
+            watched.affected.clone(),
+        ),
+        _ => return,
+    };
+    let taken = snapshot(&cluster, &title, &affected).await;
+    if let Some(watched) = &mut *WATCHED.lock().expect("watched lock poisoned") {
+        watched.snapshot.get_or_insert(taken);
+    }
+}
+
+/// All effects on instance were reverted
+pub fn faults_reverted(instance: &Instance) {
+    let instance = instance.to_string();
+    ACTIVE_FAULTS
+        .lock()
+        .expect("faults lock poisoned")
+        .retain(|f| !f.contains(&instance));
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TopologySnapshot {
+    pub title: String,
+    pub dot: String,
+}
+
+enum Status {
+    Up(u64),
+    Down,
+}
+
+/// Instances in `affected` are expected to be unhealthy and drawn dashed
+pub async fn snapshot(
+    cluster: &Cluster,
+    title: &str,
+    affected: &HashSet<String>,
+) -> TopologySnapshot {
+    let instances: Vec<_> = cluster.validator_and_fullnode_instances().collect();
+    let statuses: Vec<_> = join_all(instances.iter().map(|instance| async move {
+        match instance.latest_version().await {
+            Ok(version) => Status::Up(version),
+            Err(_) => Status::Down,
+        }
+    }))
+    .await;
+    let max_version = statuses
+        .iter()
+        .filter_map(|status| match status {
+            Status::Up(version) => Some(*version),
+            Status::Down => None,
+        })
+        .max()
+        .unwrap_or(0);
+    let faults = ACTIVE_FAULTS.lock().expect("faults lock poisoned").clone();
+
+    let mut dot = String::new();
+    writeln!(dot, "digraph \"{}\" {{", escape(title)).unwrap();
+    writeln!(dot, "  label=\"{}\";", escape(title)).unwrap();
+    writeln!(dot, "  node [style=filled];").unwrap();
+    writeln!(dot, "  subgraph cluster_validators {{").unwrap();
+    writeln!(dot, "    label=\"validators\";").unwrap();
+    for (instance, status) in instances.iter().zip(&statuses) {
+        let is_validator = cluster
+            .validator_instances()
+            .iter()
+            .any(|v| v.peer_name() == instance.peer_name());
+        if !is_validator {
+            continue;
+        }
+        write_node(
+            &mut dot,
+            instance,
+            status,
+            max_version,
+            affected,
+            &faults,
+            "box",
+        );
+    }
+    writeln!(dot, "  }}").unwrap();
+    for (instance, status) in instances.iter().zip(&statuses) {
+        let is_fullnode = cluster
+            .fullnode_instances()
+            .iter()
+            .any(|f| f.peer_name() == instance.peer_name());
+        if !is_fullnode {
+            continue;
+        }
+        write_node(
+            &mut dot,
+            instance,
+            status,
+            max_version,
+            affected,
+            &faults,
+            "ellipse",
+        );
+        let upstream = cluster
+            .validator_instances()
+            .iter()
+            .find(|v| v.validator_group().index == instance.validator_group().index);
+        if let Some(upstream) = upstream {
+            writeln!(
+                dot,
+                "  \"{}\" -> \"{}\";",
+                instance.peer_name(),
+                upstream.peer_name()
+            )
+            .unwrap();
+        }
+    }
+    writeln!(dot, "}}").unwrap();
+    TopologySnapshot {
+        title: title.to_string(),
+        dot,
+    }
+}
+
+fn write_node(
+    dot: &mut String,
+    instance: &Instance,
+    status: &Status,
+    max_version: u64,
+    affected: &HashSet<String>,
+    faults: &[String],
+    shape: &str,
+) {
+    let (color, state) = match status {
+        Status::Down => ("tomato", "down".to_string()),
+        Status::Up(version) if version + LAG_THRESHOLD < max_version => {
+            ("orange", format!("lagging at {}", version))
+        }
+        Status::Up(version) => ("palegreen", format!("at {}", version)),
+    };
+    let mut label = format!("{}\\n{}", instance.peer_name(), state);
+    let name = instance.to_string();
+    for fault in faults.iter().filter(|f| f.contains(&name)) {
+        label.push_str(&format!("\\n{}", escape(fault)));
+    }
+    let style = if affected.contains(instance.peer_name()) {
+        "filled,dashed"
+    } else {
+        "filled"
+    };
+    writeln!(
+        dot,
+        "    \"{}\" [shape={}, fillcolor={}, style=\"{}\", label=\"{}\"];",
+        instance.peer_name(),
+        shape,
+        color,
+        style,
+        label
+    )
+    .unwrap();
+}
+
+fn escape(s: &str) -> String {
+    s.replace('"', "\\\"")
+}
+
+impl fmt::Display for TopologySnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "// {}\n{}", self.title, self.dot)
+    }
+}
@@ -0,0 +1,302 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Terminal dashboard of a run for `--tui`: current experiment and its remaining time, live TPS
+/// and latency, health of validators and recent warnings. Logs are written to a file instead of
+/// stderr while the dashboard is shown, so that they do not scroll it away
+use crate::{prometheus::Prometheus, timeline, util::unix_timestamp_now};
+use anyhow::{format_err, Result};
+use chrono::Utc;
+use futures::future::{abortable, AbortHandle};
+use libra_logger::log::{self, Level, LevelFilter, Log, Metadata, Record};
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use termion::{clear, color, cursor, screen, style};
+use tokio::{task, time};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+/// Prometheus is not queried on every refresh, it scrapes every 15 secs anyway
+const METRICS_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+const METRICS_WINDOW: Duration = Duration::from_secs(60);
+const MAX_WARNINGS: usize = 10;
+
+#[derive(Clone)]
+pub struct Dashboard {
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    run_started: Instant,
+    experiment: Option<RunningExperiment>,
+    tps: Option<f64>,
+    latency_ms: Option<f64>,
+    /// Peer names and whether the last health check passed
+    health: Vec<(String, bool)>,
+    warnings: VecDeque<String>,
+    log_file: Option<PathBuf>,
+    renderer: Option<AbortHandle>,
+}
+
+struct RunningExperiment {
+    name: String,
+    started: Instant,
+    deadline: Instant,
+}
+
+/// Writes logs to a file and keeps warnings and errors for the dashboard
+struct DashboardLogger {
+    file: Mutex<File>,
+    dashboard: Dashboard,
+}
+
+impl Dashboard {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                run_started: Instant::now(),
+                experiment: None,
+                tps: None,
+                latency_ms: None,
+                health: vec![],
+                warnings: VecDeque::new(),
+                log_file: None,
+                renderer: None,
+            })),
+        }
+    }
+
+    /// Installs logger writing info and above to `log_file`, has to be called instead of the
+    /// default logger setup
+    pub fn init_logger(&self, log_file: &Path) -> Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_file)
+            .map_err(|e| format_err!("Failed to open log file {:?}: {}", log_file, e))?;
+        let logger = DashboardLogger {
+            file: Mutex::new(file),
+            dashboard: self.clone(),
+        };
+        log::set_boxed_logger(Box::new(logger))
+            .map_err(|e| format_err!("Failed to set logger: {}", e))?;
+        log::set_max_level(LevelFilter::Info);
+        self.state().log_file = Some(log_file.to_path_buf());
+        Ok(())
+    }
+
+    /// Switches terminal to the dashboard and redraws it until `stop`
+    pub fn start(&self, prometheus: Prometheus) {
+        let (renderer, handle) = abortable(self.clone().render_loop(prometheus));
+        self.state().renderer = Some(handle);
+        print!("{}{}", screen::ToAlternateScreen, cursor::Hide);
+        tokio::spawn(renderer);
+    }
+
+    /// Switches terminal back, so that output of the run is printed as usual
+    pub fn stop(&self) {
+        let mut state = self.state();
+        if let Some(renderer) = state.renderer.take() {
+            renderer.abort();
+            print!("{}{}", screen::ToMainScreen, cursor::Show);
+            let _ = io::stdout().flush();
+            if let Some(log_file) = &state.log_file {
+                println!("Logs of the run were written to {:?}", log_file);
+            }
+        }
+    }
+
+    pub fn start_experiment(&self, name: String, deadline: Instant) {
+        self.state().experiment = Some(RunningExperiment {
+            name,
+            started: Instant::now(),
+            deadline,
+        });
+    }
+
+    pub fn end_experiment(&self) {
+        self.state().experiment = None;
+    }
+
+    pub fn set_health(&self, health: Vec<(String, bool)>) {
+        self.state().health = health;
+    }
+
+    fn push_warning(&self, warning: String) {
+        let mut state = self.state();
+        if state.warnings.len() == MAX_WARNINGS {
+            state.warnings.pop_front();
+        }
+        state.warnings.push_back(warning);
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("dashboard lock poisoned")
+    }
+
+    async fn render_loop(self, prometheus: Prometheus) {
+        let mut metrics_refreshed: Option<Instant> = None;
+        loop {
+            if metrics_refreshed.map_or(true, |t| t.elapsed() >= METRICS_REFRESH_INTERVAL) {
+                metrics_refreshed = Some(Instant::now());
+                let prometheus = prometheus.clone();
+                // Prometheus client is blocking
+                let timelines = task::spawn_blocking(move || {
+                    let end = unix_timestamp_now();
+                    timeline::query_timelines(&prometheus, end - METRICS_WINDOW, end)
+                })
+                .await
+                .unwrap_or_default();
+                let last = |title: &str| {
+                    timelines
+                        .iter()
+                        .find(|t| t.title == title)
+                        .and_then(|t| t.points.last())
+                        .map(|(_, v)| *v)
+                };
+                let mut state = self.state();
+                state.tps = last("Committed TPS");
+                state.latency_ms = last("Block creation to commit latency");
+            }
+            let mut stdout = io::stdout();
+            let _ = write!(
+                stdout,
+                "{}{}{}",
+                clear::All,
+                cursor::Goto(1, 1),
+                self.render()
+            );
+            let _ = stdout.flush();
+            time::delay_for(REFRESH_INTERVAL).await;
+        }
+    }
+
+    fn render(&self) -> String {
+        let state = self.state();
+        let width = termion::terminal_size().map_or(80, |(w, _)| w as usize);
+        let mut lines = vec![format!(
+            "{}Cluster test{} running for {}",
+            style::Bold,
+            style::Reset,
+            format_duration(state.run_started.elapsed())
+        )];
+        lines.push(String::new());
+        match &state.experiment {
+            Some(experiment) => {
+                lines.push(format!(
+                    "Experiment: {}{}{}",
+                    color::Fg(color::Blue),
+                    experiment.name,
+                    color::Fg(color::Reset)
+                ));
+                lines.push(format!(
+                    "Elapsed {}, deadline in {}",
+                    format_duration(experiment.started.elapsed()),
+                    format_duration(
+                        experiment
+                            .deadline
+                            .saturating_duration_since(Instant::now())
+                    )
+                ));
+            }
+            None => lines.push("No experiment running".to_string()),
+        }
+        lines.push(String::new());
+        let format_metric = |v: Option<f64>| v.map_or("-".to_string(), |v| format!("{:.0}", v));
+        lines.push(format!(
+            "Committed TPS: {}   Block commit latency: {} ms",
+            format_metric(state.tps),
+            format_metric(state.latency_ms)
+        ));
+        lines.push(String::new());
+        lines.push("Validators:".to_string());
+        let mut row = String::new();
+        let mut row_len = 0;
+        for (peer_name, healthy) in &state.health {
+            if row_len + peer_name.len() + 4 > width && row_len > 0 {
+                lines.push(row);
+                row = String::new();
+                row_len = 0;
+            }
+            let fg = if *healthy {
+                color::Fg(color::Green).to_string()
+            } else {
+                color::Fg(color::Red).to_string()
+            };
+            row.push_str(&format!(
+                "{}* {}{}  ",
+                fg,
+                peer_name,
+                color::Fg(color::Reset)
+            ));
+            row_len += peer_name.len() + 4;
+        }
+        if state.health.is_empty() {
+            row.push_str("not checked yet");
+        }
+        lines.push(row);
+        lines.push(String::new());
+        lines.push("Recent warnings:".to_string());
+        for warning in &state.warnings {
+            lines.push(warning.chars().take(width).collect());
+        }
+        lines.join("\r\n")
+    }
+}
+
+impl Default for Dashboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Log for DashboardLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Info
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let time = Utc::now();
+        // Report text spans lines, it is kept as is in the file
+        let message = record.args().to_string();
+        if let Ok(mut file) = self.file.lock() {
+            let _ = writeln!(
+                file,
+                "{} {} {} {}",
+                time.to_rfc3339(),
+                record.level(),
+                record.target(),
+                message
+            );
+        }
+        if record.level() <= Level::Warn {
+            // Each warning takes one line of the dashboard
+            self.dashboard.push_warning(format!(
+                "{} {}",
+                time.format("%H:%M:%S"),
+                message.replace('\n', " ")
+            ));
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.flush();
+        }
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
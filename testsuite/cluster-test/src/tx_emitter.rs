@@ -3,14 +3,28 @@
 
 #![forbid(unsafe_code)]
 
-use crate::{atomic_histogram::*, cluster::Cluster, instance::Instance};
+use crate::{
+    aimd::{self, AimdRate, AimdSnapshot},
+    atomic_histogram::*,
+    balancer::{Balancer, BalancingPolicy, Route},
+    cluster::Cluster,
+    dead_letter::DeadLetters,
+    pushgateway::PushGateway,
+    retrying_client::{Endpoint, LocalError, RetryStats, RetryingClient},
+    util::unix_timestamp_now,
+    workload_profile::WorkloadProfile,
+};
 use std::{
-    fmt, slice,
-    sync::Arc,
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
+    path::Path,
+    slice,
+    sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
 
-use anyhow::{format_err, Result};
+use anyhow::{bail, format_err, Result};
+use async_trait::async_trait;
 use itertools::zip;
 use libra_crypto::{
     ed25519::{Ed25519PrivateKey, Ed25519PublicKey},
@@ -20,7 +34,9 @@ use libra_crypto::{
 use libra_logger::*;
 use libra_types::{
     account_address::AccountAddress,
-    account_config::{self, testnet_dd_account_address, COIN1_NAME},
+    account_config::{
+        self, testnet_dd_account_address, treasury_compliance_account_address, COIN1_NAME,
+    },
     chain_id::ChainId,
     transaction::{
         authenticator::AuthenticationKey, helpers::create_user_txn, Script, TransactionPayload,
@@ -34,27 +50,176 @@ use rand::{
 };
 use tokio::runtime::Handle;
 
-use futures::future::{try_join_all, FutureExt};
-use libra_json_rpc_client::JsonRpcAsyncClient;
-use libra_types::transaction::SignedTransaction;
+use futures::future::{join_all, try_join_all, FutureExt};
+use libra_json_rpc_client::{JsonRpcAsyncClient, JsonRpcBatch, JsonRpcResponse};
+use libra_json_rpc_types::errors::{JsonRpcError, ServerCode};
+use libra_types::{transaction::SignedTransaction, vm_status::StatusCode};
+use reqwest::Url;
 use std::{
     cmp::{max, min},
+    f64::consts::PI,
     ops::Sub,
+    str::FromStr,
     sync::atomic::{AtomicBool, AtomicU64, Ordering},
 };
-use tokio::{task::JoinHandle, time};
+use structopt::StructOpt;
+use tokio::{sync::Mutex as AsyncMutex, task::JoinHandle, time};
 
 const MAX_TXN_BATCH_SIZE: usize = 100; // Max transactions per account in mempool
+const DEFAULT_TARGET_THREADS: usize = 300;
+const PUSH_METRICS_INTERVAL: Duration = Duration::from_secs(10);
+/// In vasp workload one of this many transfers is sent by child account to its own parent
+const VASP_SWEEP_RATIO: u32 = 10;
+/// Fixed TPS jobs get more workers while they submit less than this percent of target
+const AUTOSCALE_TOLERANCE_PERCENT: u64 = 95;
+/// Hard ceiling of submission workers of an autoscaled job
+const MAX_AUTOSCALED_WORKERS: usize = 2000;
+/// Each worker samples gas usage and commit latency of a committed transaction at most this
+/// often, the queries run in the background so that they do not delay submissions
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+/// Emitter is considered the bottleneck when any of these is reached
+const EMITTER_CPU_SATURATION: f64 = 0.9;
+const MAX_SCHEDULING_LAG_MS: u64 = 50;
+const MAX_SUBMIT_ERROR_PERCENT: u64 = 1;
+/// Stats groups of workers when traffic is split between fullnodes and validators
+pub const FULLNODE_GROUP: &str = "fullnodes";
+pub const VALIDATOR_GROUP: &str = "validators";
+
+/// Node transactions are submitted to. Implemented by cluster test `Instance`, other tools can
+/// use `JsonRpcTarget` or implement it for their own node type
+#[async_trait]
+pub trait EmitTarget: fmt::Display + Send + Sync {
+    /// Unique name of the target, per target stats are keyed by it
+    fn name(&self) -> String;
+
+    fn json_rpc_client(&self) -> JsonRpcAsyncClient;
+
+    /// Offset of target clock from local clock in ms, reported with stats of the job
+    async fn estimate_clock_offset(&self) -> Result<i64> {
+        bail!("Clock offset estimation is not supported by {}", self)
+    }
+
+    /// Capacity relative to other targets, weighted balancing sends load proportional to it
+    fn capacity(&self) -> u64 {
+        1
+    }
+}
+
+/// JSON-RPC endpoint not managed by cluster test
+pub struct JsonRpcTarget {
+    name: String,
+    url: Url,
+}
+
+impl JsonRpcTarget {
+    pub fn new<S: Into<String>>(name: S, url: Url) -> Self {
+        Self {
+            name: name.into(),
+            url,
+        }
+    }
+}
+
+impl EmitTarget for JsonRpcTarget {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn json_rpc_client(&self) -> JsonRpcAsyncClient {
+        JsonRpcAsyncClient::new(self.url.clone())
+    }
+}
+
+impl fmt::Display for JsonRpcTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({})", self.name, self.url)
+    }
+}
+
+fn into_targets<T: EmitTarget + 'static>(targets: Vec<T>) -> Vec<Arc<dyn EmitTarget>> {
+    targets
+        .into_iter()
+        .map(|t| Arc::new(t) as Arc<dyn EmitTarget>)
+        .collect()
+}
 
 pub struct TxEmitter {
     accounts: Vec<AccountData>,
+    /// Parent vasp of each minted account
+    account_parents: HashMap<AccountAddress, AccountAddress>,
+    /// Number of parent vasps all accounts of the pool were minted under, if they were minted
+    /// by requests with the same `vasp_parents`
+    pool_vasp_parents: Option<usize>,
     mint_key_pair: KeyPair<Ed25519PrivateKey, Ed25519PublicKey>,
+    push_gateway: Option<PushGateway>,
+    /// Held while transactions of the faucet account are in flight, admin and top-up workers
+    /// send them alongside minting of autoscaled accounts
+    faucet_lock: Arc<AsyncMutex<()>>,
 }
 
 pub struct EmitJob {
     workers: Vec<Worker>,
     stop: Arc<AtomicBool>,
     stats: Arc<StatsAccumulator>,
+    /// Set for fixed TPS jobs
+    autoscale: Option<Autoscale>,
+}
+
+struct Autoscale {
+    target_tps: u64,
+    request: EmitJobRequest,
+    factory: WorkerFactory,
+    num_workers: usize,
+}
+
+/// Everything submission workers of a job share, so that workers can be added to running job
+struct WorkerFactory {
+    all_addresses: Arc<Vec<AccountAddress>>,
+    account_parents: Option<Arc<HashMap<AccountAddress, AccountAddress>>>,
+    endpoints: HashMap<String, Arc<Endpoint>>,
+    replicas: HashMap<Option<&'static str>, Vec<String>>,
+    /// Balancer of each target group, empty with static balancing
+    balancers: HashMap<Option<&'static str>, Arc<Balancer>>,
+    params: EmitThreadParams,
+    stop: Arc<AtomicBool>,
+    stats: Arc<StatsAccumulator>,
+    job_start: Instant,
+    dead_letters: Option<Arc<DeadLetters>>,
+}
+
+impl WorkerFactory {
+    fn spawn(
+        &self,
+        target: Arc<dyn EmitTarget>,
+        group: Option<&'static str>,
+        accounts: Vec<AccountData>,
+    ) -> Worker {
+        let mut endpoints = vec![self.endpoints[&target.name()].clone()];
+        endpoints.extend(
+            self.replicas[&group]
+                .iter()
+                .filter(|name| **name != target.name())
+                .map(|name| self.endpoints[name].clone()),
+        );
+        let worker = SubmissionWorker {
+            accounts,
+            target: target.name(),
+            client: RetryingClient::new(endpoints, self.stats.retry_stats.clone()),
+            balancer: self.balancers.get(&group).cloned(),
+            all_addresses: self.all_addresses.clone(),
+            account_parents: self.account_parents.clone(),
+            stop: self.stop.clone(),
+            params: self.params.clone(),
+            stats: Arc::clone(&self.stats),
+            group_stats: group.map(|group| Arc::clone(&self.stats.groups[group])),
+            job_start: self.job_start,
+            dead_letters: self.dead_letters.clone(),
+            last_sampled: None,
+        };
+        Worker {
+            join_handle: Handle::current().spawn(worker.run().boxed()),
+        }
+    }
 }
 
 #[derive(Default)]
@@ -64,6 +229,73 @@ struct StatsAccumulator {
     expired: AtomicU64,
     latency: AtomicU64,
     latencies: Arc<AtomicHistogramAccumulator>,
+    /// Latencies from submission until the endpoint acknowledged transactions it accepted
+    ack_latency: AtomicU64,
+    ack_latency_samples: AtomicU64,
+    ack_latencies: AckLatencies,
+    /// Latencies from acknowledgement until commit was seen, of committed transactions
+    ack_to_commit_latency: AtomicU64,
+    ack_to_commit_latencies: AtomicHistogramAccumulator,
+    /// Latencies of transactions committed in each minute since the job started
+    minute_latencies: Mutex<Vec<AtomicHistogramAccumulator>>,
+    commit_latency: AtomicU64,
+    commit_latency_samples: AtomicU64,
+    admin_submitted: AtomicU64,
+    admin_committed: AtomicU64,
+    gas: Mutex<BTreeMap<&'static str, GasStats>>,
+    clock_offsets: BTreeMap<String, i64>,
+    /// Accumulators of workers of each target group, updated in addition to this one
+    groups: BTreeMap<&'static str, Arc<StatsAccumulator>>,
+    retry_stats: Arc<RetryStats>,
+    /// Target of fixed TPS job once it can not be reached with `MAX_AUTOSCALED_WORKERS`, 0 if
+    /// it is reached or job has no target
+    unreachable_target_tps: AtomicU64,
+    setup_duration: Duration,
+    /// Transactions of submission requests which failed without response from the endpoint,
+    /// and the part of them which failed on the emitter host, see `LocalError`
+    submit_errors: AtomicU64,
+    local_submit_errors: AtomicU64,
+    /// Submissions to each endpoint by name, only kept for the whole job
+    endpoints: BTreeMap<String, EndpointAccumulator>,
+    /// Sum of delays in ms by which workers woke up later than scheduled
+    scheduling_lag: AtomicU64,
+    scheduling_lag_samples: AtomicU64,
+    /// Accounts of the job topped up by the faucet, not part of submitted or committed
+    topups: AtomicU64,
+    /// Transactions submitted again by retry storm clients and how many of those the endpoint
+    /// rejected
+    storm_resubmitted: AtomicU64,
+    storm_duplicates_rejected: AtomicU64,
+    /// Transactions and latency by sender, which tell starved accounts apart
+    accounts: Mutex<HashMap<AccountAddress, AccountStats>>,
+    /// CPU time of the cluster-test process and time when workers started, not set for groups
+    cpu_start: Option<Duration>,
+    job_start: Option<Instant>,
+}
+
+#[derive(Default)]
+struct EndpointAccumulator {
+    submitted: AtomicU64,
+    accepted: AtomicU64,
+    rejected: AtomicU64,
+    submit_errors: AtomicU64,
+    /// Requests which got a response and sum of their latencies in ms
+    responses: AtomicU64,
+    ack_latency: AtomicU64,
+    /// Rejections because mempool of the endpoint was full, part of `rejected`
+    backpressured: AtomicU64,
+    /// Set if submission rate adapts to back-pressure
+    rate: Option<AimdRate>,
+}
+
+/// Acks take milliseconds rather than the tens of them commits take, so they are kept in 1 ms
+/// steps. Same capacity as the default histogram keeps snapshots comparable with default ones
+struct AckLatencies(AtomicHistogramAccumulator);
+
+impl Default for AckLatencies {
+    fn default() -> Self {
+        Self(AtomicHistogramAccumulator::new(1024, 1))
+    }
 }
 
 #[derive(Debug, Default)]
@@ -73,6 +305,85 @@ pub struct TxStats {
     pub expired: u64,
     pub latency: u64,
     pub latency_buckets: AtomicHistogramSnapshot,
+    /// Part of latency from submission until the endpoint acknowledged transaction, spent in
+    /// JSON-RPC and mempool admission, sampled for accepted transactions
+    pub ack_latency: u64,
+    pub ack_latency_samples: u64,
+    pub ack_latency_buckets: AtomicHistogramSnapshot,
+    /// Part of latency from acknowledgement until commit, spent in consensus and execution,
+    /// summed over committed transactions like `latency`
+    pub ack_to_commit_latency: u64,
+    pub ack_to_commit_latency_buckets: AtomicHistogramSnapshot,
+    /// Latencies of transactions committed in each minute since the job started
+    pub minute_latencies: Vec<AtomicHistogramSnapshot>,
+    /// Sum of latencies from submission to block timestamp of sampled transactions, corrected
+    /// by clock offsets of endpoints
+    pub commit_latency: u64,
+    pub commit_latency_samples: u64,
+    pub admin_submitted: u64,
+    pub admin_committed: u64,
+    /// Gas used by sampled committed transactions, by transaction type
+    pub gas: BTreeMap<&'static str, GasStats>,
+    /// Estimated clock offsets of endpoints from emitter clock in ms, by peer name
+    pub clock_offsets: BTreeMap<String, i64>,
+    /// Stats of workers of each target group when traffic is split between validators and
+    /// fullnodes
+    pub groups: BTreeMap<&'static str, TxStats>,
+    /// JSON-RPC requests of workers which were retried or sent to a replica of the endpoint
+    pub retries: u64,
+    pub failovers: u64,
+    /// Set if job had a fixed target TPS which was not reached by autoscaling workers
+    pub unreachable_target_tps: Option<u64>,
+    /// Time spent minting accounts and estimating clock offsets before workers started, which
+    /// is not part of measured window
+    pub setup_duration: Duration,
+    pub submit_errors: u64,
+    /// Part of `submit_errors` which failed on the emitter host
+    pub local_submit_errors: u64,
+    pub scheduling_lag: u64,
+    pub scheduling_lag_samples: u64,
+    /// CPU time used by the cluster-test process, which runs the workers alongside the rest of
+    /// the test, over `emitter_wall_time` of the job
+    pub emitter_cpu_time: Duration,
+    pub emitter_wall_time: Duration,
+    /// Accounts which ran low on balance and were topped up during the job, these transfers
+    /// are not counted as submitted or committed
+    pub topups: u64,
+    /// Submissions to each endpoint by name, which show how load was balanced between them
+    pub endpoints: BTreeMap<String, EndpointStats>,
+    /// Transactions submitted again by retry storm clients, not counted in `submitted`, and how
+    /// many of these duplicates endpoints rejected
+    pub storm_resubmitted: u64,
+    pub storm_duplicates_rejected: u64,
+    /// Submissions of each account by sender, which aggregate rates hide starvation behind
+    pub accounts: HashMap<AccountAddress, AccountStats>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AccountStats {
+    pub submitted: u64,
+    pub committed: u64,
+    /// Summed over committed transactions like `TxStats::latency`
+    pub latency: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EndpointStats {
+    pub submitted: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+    pub submit_errors: u64,
+    pub responses: u64,
+    pub ack_latency: u64,
+    pub backpressured: u64,
+    /// Submission rate of the endpoint if it adapts to back-pressure
+    pub rate: Option<AimdSnapshot>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GasStats {
+    pub samples: u64,
+    pub gas_used: u64,
 }
 
 #[derive(Debug, Default)]
@@ -82,12 +393,28 @@ pub struct TxStatsRate {
     pub expired: u64,
     pub latency: u64,
     pub p99_latency: u64,
+    pub ack_latency: u64,
+    pub p99_ack_latency: u64,
+    pub ack_to_commit_latency: u64,
+    pub p99_ack_to_commit_latency: u64,
+    pub commit_latency: u64,
+    pub groups: BTreeMap<&'static str, TxStatsRate>,
 }
 
 #[derive(Clone)]
 pub struct EmitThreadParams {
     pub wait_millis: u64,
     pub wait_committed: bool,
+    pub load_profile: LoadProfile,
+    pub transport: SubmissionTransport,
+    pub balancing: BalancingPolicy,
+    /// Set if workers behave like impatient clients
+    pub retry_storm: Option<RetryStorm>,
+    /// Set if workload recorded from a live network is replayed
+    pub workload: Option<Arc<WorkloadProfile>>,
+    /// Submissions to each endpoint are paced by a rate which backs off when its mempool is
+    /// full, see `AimdRate`
+    pub adaptive_rate: bool,
 }
 
 impl Default for EmitThreadParams {
@@ -95,33 +422,317 @@ impl Default for EmitThreadParams {
         Self {
             wait_millis: 0,
             wait_committed: true,
+            load_profile: LoadProfile::Constant,
+            transport: SubmissionTransport::JsonRpc,
+            balancing: BalancingPolicy::Static,
+            retry_storm: None,
+            workload: None,
+            adaptive_rate: false,
+        }
+    }
+}
+
+/// Clients which give up on a submission the endpoint does not answer within `threshold`, or
+/// which fails, and submit the same transactions again, up to `max_retries` times. Abandoned
+/// requests still reach the endpoint, so load it gets is amplified by duplicates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryStorm {
+    pub threshold: Duration,
+    pub max_retries: usize,
+}
+
+/// How workers submit their batches of transactions. Nodes only expose JSON-RPC, so transports
+/// differ in framing of requests, other interfaces can be added here once nodes serve them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SubmissionTransport {
+    /// One JSON-RPC request per transaction
+    JsonRpc,
+    /// All transactions of a batch in a single JSON-RPC batch request
+    JsonRpcBatch,
+}
+
+impl FromStr for SubmissionTransport {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json-rpc" => Ok(SubmissionTransport::JsonRpc),
+            "json-rpc-batch" => Ok(SubmissionTransport::JsonRpcBatch),
+            _ => bail!(
+                "Unknown transport {}, expected json-rpc or json-rpc-batch",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for SubmissionTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubmissionTransport::JsonRpc => write!(f, "json-rpc"),
+            SubmissionTransport::JsonRpcBatch => write!(f, "json-rpc-batch"),
+        }
+    }
+}
+
+/// Shape of load over the duration of a job
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoadProfile {
+    Constant,
+    /// Load follows a cosine wave from `low_percent` of full load at the start of each period
+    /// up to full load in the middle of it
+    Diurnal {
+        period: Duration,
+        low_percent: u64,
+    },
+}
+
+impl LoadProfile {
+    /// Fraction of full load workers should submit at given time since start of the job
+    pub fn load_factor(&self, elapsed: Duration) -> f64 {
+        match self {
+            LoadProfile::Constant => 1.0,
+            LoadProfile::Diurnal {
+                period,
+                low_percent,
+            } => {
+                let low = (*low_percent).max(1).min(100) as f64 / 100.0;
+                let phase = elapsed.as_secs_f64() / period.as_secs_f64() * 2.0 * PI;
+                low + (1.0 - low) * (1.0 - phase.cos()) / 2.0
+            }
         }
     }
 }
 
 #[derive(Clone)]
 pub struct EmitJobRequest {
-    pub instances: Vec<Instance>,
+    pub targets: Vec<Arc<dyn EmitTarget>>,
     pub accounts_per_client: usize,
     pub workers_per_ac: Option<usize>,
     pub thread_params: EmitThreadParams,
+    /// If set, a mint/preburn/burn cycle of administrative transactions is run with this
+    /// interval alongside the regular p2p workload
+    pub admin_txn_interval: Option<Duration>,
+    /// Total number of workers to aim for when workers_per_ac is not set
+    pub target_threads: usize,
+    /// If set, accounts are created as children of this many parent vasps and transfers are
+    /// sent between children of different parents, with some swept to sender's own parent
+    pub vasp_parents: Option<usize>,
+    /// If set, this percent of workers submits to `validator_targets` and the rest to
+    /// `targets`, which are expected to be fullnodes then
+    pub validator_traffic_percent: Option<usize>,
+    pub validator_targets: Vec<Arc<dyn EmitTarget>>,
+    /// If set, workers are added while job submits less than this, see `MAX_AUTOSCALED_WORKERS`
+    pub target_tps: Option<u64>,
+    /// If set, transactions which failed to submit or expired are recorded here
+    pub dead_letters: Option<Arc<DeadLetters>>,
+    /// If set, balances of accounts of the job are checked with this interval and the ones
+    /// below `TOPUP_MIN_BALANCE` are topped up by the faucet, so that long jobs do not run
+    /// out of funds
+    pub topup_interval: Option<Duration>,
+}
+
+/// Command line knobs for the emit job used by --emit-tx and as global emit job request
+/// for experiments
+#[derive(StructOpt, Debug)]
+pub struct EmitJobParams {
+    #[structopt(long, default_value = "15")]
+    pub accounts_per_client: usize,
+    #[structopt(long)]
+    pub workers_per_ac: Option<usize>,
+    #[structopt(
+        long,
+        help = "Total number of workers to aim for when --workers-per-ac is not set [default: 300]"
+    )]
+    pub target_threads: Option<usize>,
+    #[structopt(long, default_value = "0")]
+    pub wait_millis: u64,
+    #[structopt(long, help = "Do not wait for transactions to be committed")]
+    pub burst: bool,
+    #[structopt(
+        long,
+        help = "If set, runs admin mint/preburn/burn transactions every given number of seconds alongside p2p load"
+    )]
+    pub admin_txn_interval_secs: Option<u64>,
+    #[structopt(
+        long,
+        help = "If set, runs vasp workload with given number of parent vasps, transferring between their child accounts"
+    )]
+    pub vasp_parents: Option<usize>,
+    #[structopt(
+        long,
+        help = "If set, given percent of load is submitted to validators and the rest to fullnodes, with stats reported for both"
+    )]
+    pub validator_traffic_percent: Option<usize>,
+    #[structopt(
+        long,
+        help = "If set, load cycles between --diurnal-low-percent and full load with given period in secs"
+    )]
+    pub diurnal_period_secs: Option<u64>,
+    #[structopt(
+        long,
+        default_value = "20",
+        help = "Percent of full load at the low point of diurnal cycle"
+    )]
+    pub diurnal_low_percent: u64,
+    #[structopt(
+        long,
+        default_value = "json-rpc",
+        help = "How transactions are submitted, json-rpc or json-rpc-batch"
+    )]
+    pub transport: SubmissionTransport,
+    #[structopt(
+        long,
+        default_value = "static",
+        help = "How submissions are spread over endpoints: static (each worker keeps its endpoint), round-robin, least-inflight, weighted (by instance capacity) or sticky (per account)"
+    )]
+    pub balancing: BalancingPolicy,
+    #[structopt(
+        long,
+        help = "If set, transactions which failed to submit or expired are written to this file as JSON lines"
+    )]
+    pub dead_letter_file: Option<String>,
+    #[structopt(
+        long,
+        default_value = "10000",
+        help = "Max number of transactions written to --dead-letter-file"
+    )]
+    pub dead_letter_max_entries: usize,
+    #[structopt(
+        long,
+        help = "If set, balances of emitter accounts are checked every given number of seconds and low ones are topped up, for long soaks"
+    )]
+    pub topup_interval_secs: Option<u64>,
+    #[structopt(
+        long,
+        help = "If set, workers act like impatient clients: submissions which get no response within given ms or fail are submitted again, duplicating them"
+    )]
+    pub retry_storm_threshold_ms: Option<u64>,
+    #[structopt(
+        long,
+        default_value = "3",
+        help = "Max number of times a submission is retried with --retry-storm-threshold-ms"
+    )]
+    pub retry_storm_max_retries: usize,
+    #[structopt(
+        long,
+        help = "Replay workload profile recorded with --record-workload: its average TPS, metadata sizes and max gas amounts of transfers and burstiness of arrivals"
+    )]
+    pub workload_profile: Option<String>,
+    #[structopt(
+        long,
+        help = "Treat mempool-full rejections as back-pressure: submissions to each endpoint are paced by a rate which halves on them and grows while they are accepted, and the rate it converges to is reported as capacity"
+    )]
+    pub adaptive_rate: bool,
+}
+
+impl EmitJobParams {
+    /// Fails if params are invalid or dead letter file can not be created
+    pub fn emit_job_request<T: EmitTarget + 'static>(
+        &self,
+        targets: Vec<T>,
+    ) -> Result<EmitJobRequest> {
+        if self.vasp_parents == Some(0) {
+            bail!("--vasp-parents must be positive");
+        }
+        if self.diurnal_period_secs == Some(0) {
+            bail!("--diurnal-period-secs must be positive");
+        }
+        let dead_letters = match &self.dead_letter_file {
+            Some(path) => Some(Arc::new(DeadLetters::create(
+                Path::new(path),
+                self.dead_letter_max_entries,
+            )?)),
+            None => None,
+        };
+        let workload = match &self.workload_profile {
+            Some(path) => {
+                let profile = WorkloadProfile::load(Path::new(path))?;
+                let unsupported = profile.unsupported_share();
+                if unsupported > 0.0 {
+                    warn!(
+                        "{:.1}% of recorded transactions are of types emitter does not generate, they are sent as transfers",
+                        unsupported * 100.0
+                    );
+                }
+                Some(Arc::new(profile))
+            }
+            None => None,
+        };
+        let num_targets = targets.len();
+        let mut request = EmitJobRequest {
+            targets: into_targets(targets),
+            accounts_per_client: self.accounts_per_client,
+            workers_per_ac: self.workers_per_ac,
+            thread_params: EmitThreadParams {
+                wait_millis: self.wait_millis,
+                wait_committed: !self.burst,
+                load_profile: match self.diurnal_period_secs {
+                    Some(period_secs) => LoadProfile::Diurnal {
+                        period: Duration::from_secs(period_secs),
+                        low_percent: self.diurnal_low_percent,
+                    },
+                    None => LoadProfile::Constant,
+                },
+                transport: self.transport,
+                balancing: self.balancing,
+                retry_storm: self
+                    .retry_storm_threshold_ms
+                    .map(|threshold_ms| RetryStorm {
+                        threshold: Duration::from_millis(threshold_ms),
+                        max_retries: self.retry_storm_max_retries,
+                    }),
+                workload: workload.clone(),
+                adaptive_rate: self.adaptive_rate,
+            },
+            admin_txn_interval: self.admin_txn_interval_secs.map(Duration::from_secs),
+            target_threads: self.target_threads.unwrap_or(DEFAULT_TARGET_THREADS),
+            vasp_parents: self.vasp_parents,
+            validator_traffic_percent: self.validator_traffic_percent,
+            validator_targets: vec![],
+            target_tps: None,
+            dead_letters,
+            topup_interval: self.topup_interval_secs.map(Duration::from_secs),
+        };
+        if let Some(workload) = workload {
+            // Observed rate is kept the same way as by fixed_tps, workers send one transaction
+            // per wait, which is varied like the recorded gaps
+            let tps = workload.avg_tps.ceil().max(1.0) as u64;
+            let (num_workers, wait_millis) =
+                EmitJobRequest::fixed_tps_params(num_targets.max(1), tps);
+            request.accounts_per_client = 1;
+            request.workers_per_ac = Some(num_workers);
+            request.thread_params.wait_millis = wait_millis;
+            request.target_tps = Some(tps);
+        }
+        Ok(request)
+    }
 }
 
 impl EmitJobRequest {
-    pub fn for_instances(
-        instances: Vec<Instance>,
+    pub fn for_instances<T: EmitTarget + 'static>(
+        instances: Vec<T>,
         global_emit_job_request: &Option<EmitJobRequest>,
     ) -> Self {
+        let targets = into_targets(instances);
         match global_emit_job_request {
             Some(global_emit_job_request) => EmitJobRequest {
-                instances,
+                targets,
                 ..global_emit_job_request.clone()
             },
             None => Self {
-                instances,
+                targets,
                 accounts_per_client: 15,
                 workers_per_ac: None,
                 thread_params: EmitThreadParams::default(),
+                admin_txn_interval: None,
+                target_threads: DEFAULT_TARGET_THREADS,
+                vasp_parents: None,
+                validator_traffic_percent: None,
+                validator_targets: vec![],
+                target_tps: None,
+                dead_letters: None,
+                topup_interval: None,
             },
         }
     }
@@ -135,26 +746,163 @@ impl EmitJobRequest {
         (num_workers, wait_time)
     }
 
-    pub fn fixed_tps(instances: Vec<Instance>, tps: u64) -> Self {
+    pub fn fixed_tps<T: EmitTarget + 'static>(instances: Vec<T>, tps: u64) -> Self {
         let (num_workers, wait_time) = EmitJobRequest::fixed_tps_params(instances.len(), tps);
         Self {
-            instances,
+            targets: into_targets(instances),
             accounts_per_client: 1,
             workers_per_ac: Some(num_workers),
             thread_params: EmitThreadParams {
                 wait_millis: wait_time,
                 wait_committed: true,
+                load_profile: LoadProfile::Constant,
+                transport: SubmissionTransport::JsonRpc,
+                balancing: BalancingPolicy::Static,
+                retry_storm: None,
+                workload: None,
+                adaptive_rate: false,
             },
+            admin_txn_interval: None,
+            target_threads: DEFAULT_TARGET_THREADS,
+            vasp_parents: None,
+            validator_traffic_percent: None,
+            validator_targets: vec![],
+            target_tps: Some(tps),
+            dead_letters: None,
+            topup_interval: None,
+        }
+    }
+
+    /// Traffic is split between fullnodes and validators by `validator_traffic_percent` of
+    /// global request, all of it goes to fullnodes if that is not set
+    pub fn split_traffic<T: EmitTarget + 'static>(
+        fullnodes: Vec<T>,
+        validators: Vec<T>,
+        global_emit_job_request: &Option<EmitJobRequest>,
+    ) -> Self {
+        Self {
+            validator_targets: into_targets(validators),
+            ..Self::for_instances(fullnodes, global_emit_job_request)
+        }
+    }
+
+    fn is_split(&self) -> bool {
+        self.validator_traffic_percent.is_some() && !self.validator_targets.is_empty()
+    }
+
+    fn all_targets(&self) -> Vec<Arc<dyn EmitTarget>> {
+        let mut targets = self.targets.clone();
+        if self.is_split() {
+            targets.extend(self.validator_targets.iter().cloned());
+        }
+        targets
+    }
+
+    /// Target of each of workers `first..first + num_workers`, with name of its group if traffic
+    /// is split. Workers added to a running job continue cycling targets after existing ones
+    fn assign_workers(
+        &self,
+        first: usize,
+        num_workers: usize,
+    ) -> Vec<(Arc<dyn EmitTarget>, Option<&'static str>)> {
+        if !self.is_split() {
+            return self
+                .targets
+                .iter()
+                .cycle()
+                .skip(first)
+                .take(num_workers)
+                .map(|target| (target.clone(), None))
+                .collect();
         }
+        let percent = min(self.validator_traffic_percent.unwrap_or(0), 100);
+        let validator_workers = |workers: usize| {
+            if self.targets.is_empty() {
+                workers
+            } else {
+                workers * percent / 100
+            }
+        };
+        let end = first + num_workers;
+        let (first_validator, end_validator) = (validator_workers(first), validator_workers(end));
+        let (first_fullnode, end_fullnode) = (first - first_validator, end - end_validator);
+        let fullnode_workers = self
+            .targets
+            .iter()
+            .cycle()
+            .skip(first_fullnode)
+            .take(end_fullnode - first_fullnode)
+            .map(|target| (target.clone(), Some(FULLNODE_GROUP)));
+        let validator_workers = self
+            .validator_targets
+            .iter()
+            .cycle()
+            .skip(first_validator)
+            .take(end_validator - first_validator)
+            .map(|target| (target.clone(), Some(VALIDATOR_GROUP)));
+        fullnode_workers.chain(validator_workers).collect()
+    }
+}
+
+/// Builds emitter which is not tied to cluster test, mint key is required
+#[derive(Default)]
+pub struct TxEmitterBuilder {
+    mint_key_pair: Option<KeyPair<Ed25519PrivateKey, Ed25519PublicKey>>,
+    push_gateway: Option<PushGateway>,
+}
+
+impl TxEmitterBuilder {
+    /// Key of the account which funds accounts created by the emitter
+    pub fn mint_key_pair(
+        mut self,
+        mint_key_pair: KeyPair<Ed25519PrivateKey, Ed25519PublicKey>,
+    ) -> Self {
+        self.mint_key_pair = Some(mint_key_pair);
+        self
+    }
+
+    pub fn mint_key_file(self, mint_file: &str) -> Self {
+        let mint_key: Ed25519PrivateKey = generate_key::load_key(mint_file);
+        self.mint_key_pair(KeyPair::from(mint_key))
+    }
+
+    /// Emitter metrics are pushed to this gateway while `emit_txn_for` runs
+    pub fn push_gateway(mut self, push_gateway: PushGateway) -> Self {
+        self.push_gateway = Some(push_gateway);
+        self
+    }
+
+    pub fn build(self) -> Result<TxEmitter> {
+        Ok(TxEmitter {
+            accounts: vec![],
+            account_parents: HashMap::new(),
+            pool_vasp_parents: None,
+            mint_key_pair: self
+                .mint_key_pair
+                .ok_or_else(|| format_err!("Mint key is not set"))?,
+            push_gateway: self.push_gateway,
+            faucet_lock: Arc::new(AsyncMutex::new(())),
+        })
     }
 }
 
 impl TxEmitter {
+    pub fn builder() -> TxEmitterBuilder {
+        TxEmitterBuilder::default()
+    }
+
+    /// Emitter minting with the cluster mint key, pushing metrics to gateway set in env
     pub fn new(cluster: &Cluster) -> Self {
-        Self {
-            accounts: vec![],
-            mint_key_pair: cluster.mint_key_pair().clone(),
+        let mut builder = Self::builder().mint_key_pair(cluster.mint_key_pair().clone());
+        if let Some(push_gateway) = PushGateway::from_env() {
+            builder = builder.push_gateway(push_gateway);
         }
+        builder.build().expect("Mint key is set")
+    }
+
+    /// Minted accounts in the pool which no job is using
+    pub fn num_accounts(&self) -> usize {
+        self.accounts.len()
     }
 
     pub fn take_account(&mut self) -> AccountData {
@@ -163,25 +911,31 @@ impl TxEmitter {
 
     pub fn clear(&mut self) {
         self.accounts.clear();
+        self.account_parents.clear();
+        self.pool_vasp_parents = None;
     }
 
-    fn pick_mint_instance<'a, 'b>(&'a self, instances: &'b [Instance]) -> &'b Instance {
+    fn pick_mint_target<'a, 'b>(
+        &'a self,
+        targets: &'b [Arc<dyn EmitTarget>],
+    ) -> &'b dyn EmitTarget {
         let mut rng = ThreadRng::default();
-        instances
+        targets
             .choose(&mut rng)
-            .expect("Instances can not be empty")
+            .expect("Targets can not be empty")
+            .as_ref()
     }
 
-    fn pick_mint_client(&self, instances: &[Instance]) -> JsonRpcAsyncClient {
-        self.pick_mint_instance(instances).json_rpc_client()
+    fn pick_mint_client(&self, targets: &[Arc<dyn EmitTarget>]) -> JsonRpcAsyncClient {
+        self.pick_mint_target(targets).json_rpc_client()
     }
 
     pub async fn submit_single_transaction(
         &self,
-        instance: &Instance,
+        target: &dyn EmitTarget,
         account: &mut AccountData,
     ) -> Result<Instant> {
-        let client = instance.json_rpc_client();
+        let client = target.json_rpc_client();
         client
             .submit_transaction(gen_mint_request(account, 10))
             .await?;
@@ -193,63 +947,233 @@ impl TxEmitter {
         let workers_per_ac = match req.workers_per_ac {
             Some(x) => x,
             None => {
-                let target_threads = 300;
+                let target_threads = req.target_threads;
                 // Trying to create somewhere between target_threads/2..target_threads threads
                 // We want to have equal numbers of threads for each AC, so that they are equally loaded
                 // Otherwise things like flamegrap/perf going to show different numbers depending on which AC is chosen
                 // Also limiting number of threads as max 10 per AC for use cases with very small number of nodes or use --peers
-                min(10, max(1, target_threads / req.instances.len()))
+                min(10, max(1, target_threads / req.all_targets().len()))
             }
         };
-        let num_clients = req.instances.len() * workers_per_ac;
+        let num_clients = req.all_targets().len() * workers_per_ac;
         info!(
-            "Will use {} workers per AC with total {} AC clients",
-            workers_per_ac, num_clients
+            "Will use {} workers per AC with total {} AC clients submitting over {}",
+            workers_per_ac, num_clients, req.thread_params.transport
         );
         let num_accounts = req.accounts_per_client * num_clients;
         info!(
             "Will create {} accounts_per_client with total {} accounts",
             req.accounts_per_client, num_accounts
         );
+        let setup_start = Instant::now();
+        if let Some(vasp_parents) = req.vasp_parents {
+            // Accounts minted by earlier vasp jobs of the same shape are reused
+            if self.pool_vasp_parents != req.vasp_parents {
+                info!("Minting new accounts for {} parent vasps", vasp_parents);
+                self.accounts.clear();
+            }
+        }
         self.mint_accounts(&req, num_accounts).await?;
+        let account_parents = if req.vasp_parents.is_some() {
+            Some(Arc::new(self.account_parents.clone()))
+        } else {
+            None
+        };
         let all_accounts = self.accounts.split_off(self.accounts.len() - num_accounts);
         let mut workers = vec![];
         let all_addresses: Vec<_> = all_accounts.iter().map(|d| d.address).collect();
         let all_addresses = Arc::new(all_addresses);
         let mut all_accounts = all_accounts.into_iter();
         let stop = Arc::new(AtomicBool::new(false));
-        let stats = Arc::new(StatsAccumulator::default());
+        let clock_offsets = estimate_clock_offsets(&req.all_targets()).await;
+        let assignments = req.assign_workers(0, num_clients);
+        let groups = assignments
+            .iter()
+            .filter_map(|(_, group)| *group)
+            .map(|group| (group, Arc::new(StatsAccumulator::default())))
+            .collect();
+        let stats = Arc::new(StatsAccumulator {
+            clock_offsets,
+            groups,
+            setup_duration: setup_start.elapsed(),
+            endpoints: req
+                .all_targets()
+                .iter()
+                .map(|target| {
+                    let endpoint = EndpointAccumulator {
+                        rate: if req.thread_params.adaptive_rate {
+                            Some(AimdRate::new())
+                        } else {
+                            None
+                        },
+                        ..Default::default()
+                    };
+                    (target.name(), endpoint)
+                })
+                .collect(),
+            cpu_start: process_cpu_time(),
+            job_start: Some(Instant::now()),
+            ..Default::default()
+        });
         let tokio_handle = Handle::current();
-        for instance in &req.instances {
-            for _ in 0..workers_per_ac {
-                let client = instance.json_rpc_client();
-                let accounts = (&mut all_accounts).take(req.accounts_per_client).collect();
-                let all_addresses = all_addresses.clone();
-                let stop = stop.clone();
-                let params = req.thread_params.clone();
-                let stats = Arc::clone(&stats);
-                let worker = SubmissionWorker {
-                    accounts,
-                    client,
-                    all_addresses,
-                    stop,
-                    params,
-                    stats,
-                };
-                let join_handle = tokio_handle.spawn(worker.run().boxed());
-                workers.push(Worker { join_handle });
+        // Endpoints are shared by workers, so that they share circuit breakers, and workers
+        // fail over to other targets of their group
+        let endpoints: HashMap<_, _> = req
+            .all_targets()
+            .iter()
+            .map(|target| (target.name(), Endpoint::new(target.json_rpc_client())))
+            .collect();
+        let mut replicas: HashMap<Option<&'static str>, Vec<String>> = HashMap::new();
+        for (target, group) in &assignments {
+            let names = replicas.entry(*group).or_default();
+            if !names.contains(&target.name()) {
+                names.push(target.name());
+            }
+        }
+        let mut balancers = HashMap::new();
+        if req.thread_params.balancing != BalancingPolicy::Static {
+            let capacities: HashMap<_, _> = req
+                .all_targets()
+                .iter()
+                .map(|target| (target.name(), target.capacity()))
+                .collect();
+            for (group, names) in &replicas {
+                let group_endpoints = names
+                    .iter()
+                    .map(|name| (name.clone(), endpoints[name].clone(), capacities[name]))
+                    .collect();
+                let balancer = Balancer::new(req.thread_params.balancing, group_endpoints);
+                balancers.insert(*group, Arc::new(balancer));
+            }
+            info!(
+                "Submissions are balanced over endpoints with {} policy",
+                req.thread_params.balancing
+            );
+        }
+        let factory = WorkerFactory {
+            all_addresses,
+            account_parents,
+            endpoints,
+            replicas,
+            balancers,
+            params: req.thread_params.clone(),
+            stop: stop.clone(),
+            stats: stats.clone(),
+            job_start: Instant::now(),
+            dead_letters: req.dead_letters.clone(),
+        };
+        for (target, group) in assignments {
+            let accounts = (&mut all_accounts).take(req.accounts_per_client).collect();
+            workers.push(factory.spawn(target, group, accounts));
+        }
+        if let Some(percent) = req.validator_traffic_percent {
+            if req.is_split() {
+                info!("{}% of workers submit to validators", percent);
+            } else {
+                warn!("No validator targets to split traffic with, all of it goes to targets");
             }
         }
-        info!("Tx emitter workers started");
+        if let Some(interval) = req.admin_txn_interval {
+            let target = self.pick_mint_target(&req.targets);
+            let worker = AdminWorker {
+                client: RetryingClient::new(
+                    vec![Endpoint::new(target.json_rpc_client())],
+                    stats.retry_stats.clone(),
+                ),
+                dd_account: self.load_faucet_account(target).await?,
+                tc_account: self.load_treasury_compliance_account(target).await?,
+                faucet_lock: self.faucet_lock.clone(),
+                interval,
+                stop: stop.clone(),
+                stats: Arc::clone(&stats),
+            };
+            let join_handle = tokio_handle.spawn(worker.run().boxed());
+            workers.push(Worker { join_handle });
+            info!("Admin transaction worker started");
+        }
+        if let Some(interval) = req.topup_interval {
+            let target = self.pick_mint_target(&req.targets);
+            let worker = TopUpWorker {
+                client: RetryingClient::new(
+                    vec![Endpoint::new(target.json_rpc_client())],
+                    stats.retry_stats.clone(),
+                ),
+                faucet_account: self.load_faucet_account(target).await?,
+                faucet_lock: self.faucet_lock.clone(),
+                addresses: factory.all_addresses.clone(),
+                interval,
+                stop: stop.clone(),
+                stats: Arc::clone(&stats),
+            };
+            let join_handle = tokio_handle.spawn(worker.run().boxed());
+            workers.push(Worker { join_handle });
+            info!("Top-up worker started with interval {:?}", interval);
+        }
+        info!(
+            "Tx emitter workers started after {:?} of setup",
+            stats.setup_duration
+        );
         Ok(EmitJob {
             workers,
             stop,
             stats,
+            autoscale: req.target_tps.map(|target_tps| Autoscale {
+                target_tps,
+                request: req,
+                factory,
+                num_workers: num_clients,
+            }),
         })
     }
 
-    pub async fn load_faucet_account(&self, instance: &Instance) -> Result<AccountData> {
-        let client = instance.json_rpc_client();
+    /// Adds workers to a fixed TPS job which submits less than its target, minting accounts for
+    /// them. Returns false once the target can not be reached within `MAX_AUTOSCALED_WORKERS`
+    async fn autoscale(&mut self, job: &mut EmitJob, submitted_tps: u64) -> Result<bool> {
+        let autoscale = match &mut job.autoscale {
+            Some(autoscale) => autoscale,
+            None => return Ok(true),
+        };
+        let target_tps = autoscale.target_tps;
+        if submitted_tps * 100 >= target_tps * AUTOSCALE_TOLERANCE_PERCENT {
+            return Ok(true);
+        }
+        if autoscale.num_workers >= MAX_AUTOSCALED_WORKERS {
+            return Ok(false);
+        }
+        // Scale by throughput of existing workers, at most doubling them at once
+        let per_worker = max(submitted_tps, 1) as f64 / autoscale.num_workers as f64;
+        let missing = ((target_tps - submitted_tps) as f64 / per_worker).ceil() as usize;
+        let new_workers = min(
+            min(missing, autoscale.num_workers),
+            MAX_AUTOSCALED_WORKERS - autoscale.num_workers,
+        )
+        .max(1);
+        info!(
+            "Submitting {} of target {} txn/s, adding {} workers to {}",
+            submitted_tps, target_tps, new_workers, autoscale.num_workers
+        );
+        let num_accounts = new_workers * autoscale.request.accounts_per_client;
+        self.mint_accounts(&autoscale.request, num_accounts).await?;
+        let mut accounts = self
+            .accounts
+            .split_off(self.accounts.len() - num_accounts)
+            .into_iter();
+        for (target, group) in autoscale
+            .request
+            .assign_workers(autoscale.num_workers, new_workers)
+        {
+            let worker_accounts = (&mut accounts)
+                .take(autoscale.request.accounts_per_client)
+                .collect();
+            job.workers
+                .push(autoscale.factory.spawn(target, group, worker_accounts));
+        }
+        autoscale.num_workers += new_workers;
+        Ok(true)
+    }
+
+    pub async fn load_faucet_account(&self, target: &dyn EmitTarget) -> Result<AccountData> {
+        let client = RetryingClient::from(target.json_rpc_client());
         let address = testnet_dd_account_address();
         let sequence_number = query_sequence_numbers(&client, &[address])
             .await
@@ -267,8 +1191,8 @@ impl TxEmitter {
         })
     }
 
-    pub async fn load_libra_root_account(&self, instance: &Instance) -> Result<AccountData> {
-        let client = instance.json_rpc_client();
+    pub async fn load_libra_root_account(&self, target: &dyn EmitTarget) -> Result<AccountData> {
+        let client = RetryingClient::from(target.json_rpc_client());
         let address = account_config::libra_root_address();
         let sequence_number = query_sequence_numbers(&client, &[address])
             .await
@@ -286,6 +1210,28 @@ impl TxEmitter {
         })
     }
 
+    pub async fn load_treasury_compliance_account(
+        &self,
+        target: &dyn EmitTarget,
+    ) -> Result<AccountData> {
+        let client = RetryingClient::from(target.json_rpc_client());
+        let address = treasury_compliance_account_address();
+        let sequence_number = query_sequence_numbers(&client, &[address])
+            .await
+            .map_err(|e| {
+                format_err!(
+                    "query_sequence_numbers on {:?} for treasury compliance account failed: {}",
+                    client,
+                    e
+                )
+            })?[0];
+        Ok(AccountData {
+            address,
+            key_pair: self.mint_key_pair.clone(),
+            sequence_number,
+        })
+    }
+
     pub async fn mint_accounts(
         &mut self,
         req: &EmitJobRequest,
@@ -296,70 +1242,92 @@ impl TxEmitter {
             return Ok(()); // Early return to skip printing 'Minting ...' logs
         }
         let num_accounts = requested_accounts - self.accounts.len(); // Only minting extra accounts
+        self.pool_vasp_parents =
+            if self.accounts.is_empty() || self.pool_vasp_parents == req.vasp_parents {
+                req.vasp_parents
+            } else {
+                None
+            };
         info!("Minting additional {} accounts", num_accounts);
+        let mint_start = Instant::now();
+        let faucet_lock = self.faucet_lock.clone();
+        let faucet_guard = faucet_lock.lock().await;
         let mut faucet_account = self
-            .load_faucet_account(self.pick_mint_instance(&req.instances))
+            .load_faucet_account(self.pick_mint_target(&req.targets))
             .await?;
         let mut libra_root_account = self
-            .load_libra_root_account(self.pick_mint_instance(&req.instances))
+            .load_libra_root_account(self.pick_mint_target(&req.targets))
             .await?;
         let mint_txn = gen_mint_request(
             &mut faucet_account,
             LIBRA_PER_NEW_ACCOUNT * num_accounts as u64,
         );
         execute_and_wait_transactions(
-            &mut self.pick_mint_client(&req.instances),
+            &mut self.pick_mint_client(&req.targets),
             &mut faucet_account,
             vec![mint_txn],
         )
         .await
         .map_err(|e| format_err!("Failed to mint into faucet account: {}", e))?;
+        // Funds fan out from faucet through seed accounts, with enough seeds that each of them
+        // creates its accounts in a single batch, so that setup time hardly grows with accounts
+        let num_seed_accounts = req.vasp_parents.unwrap_or_else(|| {
+            max(
+                req.targets.len(),
+                (num_accounts + MAX_TXN_BATCH_SIZE - 1) / MAX_TXN_BATCH_SIZE,
+            )
+        });
         let seed_accounts = create_seed_accounts(
             &mut libra_root_account,
-            req.instances.len(),
-            100,
-            self.pick_mint_client(&req.instances),
+            num_seed_accounts,
+            MAX_TXN_BATCH_SIZE as u64,
+            self.pick_mint_client(&req.targets),
         )
         .await
         .map_err(|e| format_err!("Failed to create seed accounts: {}", e))?;
         info!("Completed creating seed accounts");
         let libra_per_seed =
-            (LIBRA_PER_NEW_ACCOUNT * num_accounts as u64) / req.instances.len() as u64;
+            (LIBRA_PER_NEW_ACCOUNT * num_accounts as u64) / num_seed_accounts as u64;
         // Create seed accounts with which we can create actual accounts concurrently
         mint_to_new_accounts(
             &mut faucet_account,
             &seed_accounts,
             libra_per_seed,
-            100,
-            self.pick_mint_client(&req.instances),
+            MAX_TXN_BATCH_SIZE as u64,
+            self.pick_mint_client(&req.targets),
         )
         .await
         .map_err(|e| format_err!("Failed to mint seed_accounts: {}", e))?;
+        drop(faucet_guard);
         info!("Completed minting seed accounts");
+        let seed_addresses: Vec<_> = seed_accounts.iter().map(|a| a.address).collect();
         // For each seed account, create a future and transfer libra from that seed account to new accounts
         let account_futures = seed_accounts
             .into_iter()
             .enumerate()
             .map(|(i, seed_account)| {
                 // Spawn new threads
-                let instance = req.instances[i].clone();
-                let num_new_accounts =
-                    (num_accounts + req.instances.len() - 1) / req.instances.len();
-                let client = instance.json_rpc_client();
+                let target = &req.targets[i % req.targets.len()];
+                let num_new_accounts = (num_accounts + num_seed_accounts - 1) / num_seed_accounts;
+                let client = target.json_rpc_client();
                 create_new_accounts(
                     seed_account,
                     num_new_accounts,
                     LIBRA_PER_NEW_ACCOUNT,
-                    20,
+                    MAX_TXN_BATCH_SIZE as u64,
                     client,
                 )
             });
-        let mut minted_accounts = try_join_all(account_futures)
+        let minted_accounts = try_join_all(account_futures)
             .await
-            .map_err(|e| format_err!("Failed to mint accounts {}", e))?
-            .into_iter()
-            .flatten()
-            .collect();
+            .map_err(|e| format_err!("Failed to mint accounts {}", e))?;
+        // Seed accounts are parent vasps of accounts they created
+        for (parent, children) in zip(&seed_addresses, &minted_accounts) {
+            for child in children {
+                self.account_parents.insert(child.address, *parent);
+            }
+        }
+        let mut minted_accounts = minted_accounts.into_iter().flatten().collect();
         self.accounts.append(&mut minted_accounts);
         assert!(
             self.accounts.len() >= num_accounts,
@@ -367,7 +1335,12 @@ impl TxEmitter {
             requested_accounts,
             self.accounts.len()
         );
-        info!("Mint is done");
+        info!(
+            "Minted {} accounts through {} seed accounts in {:?}",
+            num_accounts,
+            num_seed_accounts,
+            mint_start.elapsed()
+        );
         Ok(())
     }
 
@@ -384,7 +1357,15 @@ impl TxEmitter {
                 .expect("TxEmitter worker thread failed");
             self.accounts.append(&mut accounts);
         }
-        job.stats.accumulate()
+        let stats = job.stats.accumulate();
+        let bottlenecks = stats.emitter_bottlenecks();
+        if !bottlenecks.is_empty() {
+            warn!(
+                "Emitter was likely the bottleneck of the job: {}",
+                bottlenecks.join(", ")
+            );
+        }
+        stats
     }
 
     pub async fn emit_txn_for(
@@ -392,18 +1373,101 @@ impl TxEmitter {
         duration: Duration,
         emit_job_request: EmitJobRequest,
     ) -> Result<TxStats> {
-        let job = self.start_job(emit_job_request).await?;
-        tokio::time::delay_for(duration).await;
+        let mut job = self.start_job(emit_job_request).await?;
+        self.wait_job(&mut job, duration).await;
         let stats = self.stop_job(job).await;
         Ok(stats)
     }
 
+    /// Same as `emit_txn_for`, but returned stats only cover `duration` after `warmup`, so that
+    /// ramp-up of accounts and drain of in-flight transactions during `cooldown` are excluded
+    pub async fn emit_txn_for_steady_state(
+        &mut self,
+        warmup: Duration,
+        duration: Duration,
+        cooldown: Duration,
+        emit_job_request: EmitJobRequest,
+    ) -> Result<TxStats> {
+        let mut job = self.start_job(emit_job_request).await?;
+        self.wait_job(&mut job, warmup).await;
+        let start_stats = self.peek_job_stats(&job);
+        self.wait_job(&mut job, duration).await;
+        let end_stats = self.peek_job_stats(&job);
+        self.wait_job(&mut job, cooldown).await;
+        self.stop_job(job).await;
+        Ok(&end_stats - &start_stats)
+    }
+
+    /// Pushes emitter metrics to push gateway while waiting, if it is configured, and
+    /// autoscales workers of fixed TPS jobs
+    async fn wait_job(&mut self, job: &mut EmitJob, duration: Duration) {
+        if self.push_gateway.is_none() && job.autoscale.is_none() {
+            tokio::time::delay_for(duration).await;
+            return;
+        }
+        let deadline = Instant::now() + duration;
+        let mut prev_stats = self.peek_job_stats(job);
+        while Instant::now() < deadline {
+            let window = min(
+                PUSH_METRICS_INTERVAL,
+                deadline.saturating_duration_since(Instant::now()),
+            );
+            tokio::time::delay_for(window).await;
+            if window.as_secs() == 0 {
+                continue;
+            }
+            let stats = self.peek_job_stats(job);
+            let rate = (&stats - &prev_stats).rate(window);
+            prev_stats = stats;
+            if let Some(push_gateway) = &self.push_gateway {
+                if let Err(e) = push_gateway
+                    .push(
+                        &[
+                            ("submitted_tps", rate.submitted as f64),
+                            ("committed_tps", rate.committed as f64),
+                            ("expired_tps", rate.expired as f64),
+                            ("avg_latency_ms", rate.latency as f64),
+                            ("p99_latency_ms", rate.p99_latency as f64),
+                            ("commit_latency_ms", rate.commit_latency as f64),
+                        ],
+                        &[],
+                    )
+                    .await
+                {
+                    warn!("Failed to push emitter metrics: {}", e);
+                }
+            }
+            // Only full windows are representative of submission rate
+            if window < PUSH_METRICS_INTERVAL {
+                continue;
+            }
+            match self.autoscale(job, rate.submitted).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    let target_tps = job.autoscale.as_ref().map_or(0, |a| a.target_tps);
+                    if job
+                        .stats
+                        .unreachable_target_tps
+                        .swap(target_tps, Ordering::Relaxed)
+                        == 0
+                    {
+                        warn!(
+                            "Target {} txn/s is unreachable with {} workers, submitting {} txn/s",
+                            target_tps, MAX_AUTOSCALED_WORKERS, rate.submitted
+                        );
+                    }
+                }
+                Err(e) => warn!("Failed to add emitter workers: {}", e),
+            }
+        }
+    }
+
     pub async fn query_sequence_numbers(
         &self,
-        instance: &Instance,
+        target: &dyn EmitTarget,
         address: &AccountAddress,
     ) -> Result<u64> {
-        let client = instance.json_rpc_client();
+        let client = target.json_rpc_client();
         let resp = client
             .get_accounts(slice::from_ref(address))
             .await
@@ -413,6 +1477,19 @@ impl TxEmitter {
             .ok_or_else(|| format_err!("account does not exist"))?
             .sequence_number)
     }
+
+    /// Sum of sequence numbers of emitter accounts on chain of `target`, after waiting for
+    /// transactions in flight. Accounts are resynced to the chain, so that the difference of
+    /// sums taken before and after a job is the number of its transactions on chain
+    pub async fn sync_sequence_numbers(&mut self, target: &dyn EmitTarget) -> u64 {
+        let client = RetryingClient::from(target.json_rpc_client());
+        // Accounts behind the chain are resynced on deadline too
+        let _ = wait_for_accounts_sequence(&client, &mut self.accounts).await;
+        self.accounts
+            .iter()
+            .map(|account| account.sequence_number)
+            .sum()
+    }
 }
 
 struct Worker {
@@ -421,11 +1498,24 @@ struct Worker {
 
 struct SubmissionWorker {
     accounts: Vec<AccountData>,
-    client: JsonRpcAsyncClient,
+    /// Name of own endpoint of the worker
+    target: String,
+    client: RetryingClient,
+    /// Set unless balancing is static, submissions go to endpoints it picks instead of `client`
+    balancer: Option<Arc<Balancer>>,
     all_addresses: Arc<Vec<AccountAddress>>,
+    /// Set for vasp workload
+    account_parents: Option<Arc<HashMap<AccountAddress, AccountAddress>>>,
     stop: Arc<AtomicBool>,
     params: EmitThreadParams,
     stats: Arc<StatsAccumulator>,
+    /// Set if traffic is split, stats of the group of the endpoint
+    group_stats: Option<Arc<StatsAccumulator>>,
+    /// Load profile is relative to this
+    job_start: Instant,
+    dead_letters: Option<Arc<DeadLetters>>,
+    /// When gas and commit latency were last sampled, see `SAMPLE_INTERVAL`
+    last_sampled: Option<Instant>,
 }
 
 impl SubmissionWorker {
@@ -435,69 +1525,408 @@ impl SubmissionWorker {
         while !self.stop.load(Ordering::Relaxed) {
             let requests = self.gen_requests();
             let num_requests = requests.len();
+            // One transaction of a fully committed batch is sampled every `SAMPLE_INTERVAL`
+            let sampled_txn = requests
+                .choose(&mut ThreadRng::default())
+                .map(|txn| (txn.sender(), txn.sequence_number()));
             let start_time = Instant::now();
-            let wait_util = start_time + wait;
-            let mut tx_offset_time = 0u64;
-            for request in requests {
-                let cur_time = Instant::now();
-                tx_offset_time += (cur_time - start_time).as_millis() as u64;
-                self.stats.submitted.fetch_add(1, Ordering::Relaxed);
-                let resp = self.client.submit_transaction(request).await;
-                if let Err(e) = resp {
-                    warn!("[{:?}] Failed to submit request: {:?}", self.client, e);
+            let batch_submit_time = unix_timestamp_now();
+            let (tx_offset_time, ack_offset_time, sampled_submit_time, dropped) =
+                self.submit(&requests, sampled_txn, start_time).await;
+            if !dropped.is_empty() {
+                // Job stopped while the endpoint pushed back, senders resume from transactions
+                // it never took, and the batch is not waited for
+                for account in self.accounts.iter_mut() {
+                    if let Some((_, sequence_number)) = dropped
+                        .iter()
+                        .find(|(sender, _)| *sender == account.address)
+                    {
+                        account.sequence_number = min(account.sequence_number, *sequence_number);
+                    }
                 }
+                break;
             }
             if self.params.wait_committed {
-                if let Err(uncommitted) =
-                    wait_for_accounts_sequence(&self.client, &mut self.accounts).await
-                {
+                let result = wait_for_accounts_sequence(&self.client, &mut self.accounts).await;
+                let elapsed = self.job_start.elapsed();
+                if let Err(uncommitted) = result {
                     let end_time = (Instant::now() - start_time).as_millis() as u64;
                     let num_committed = (num_requests - uncommitted.len()) as u64;
                     let latency = end_time - tx_offset_time / num_requests as u64;
-                    self.stats
-                        .committed
-                        .fetch_add(num_committed, Ordering::Relaxed);
-                    self.stats
-                        .expired
-                        .fetch_add(uncommitted.len() as u64, Ordering::Relaxed);
-                    self.stats.latency.fetch_add(
-                        // To avoid negative result caused by uncommitted tx occur
-                        // Simplified from:
-                        // end_time * num_committed - (tx_offset_time/num_requests) * num_committed
-                        // to
-                        // (end_time - tx_offset_time / num_requests) * num_committed
-                        latency * num_committed as u64,
-                        Ordering::Relaxed,
-                    );
-                    self.stats
-                        .latencies
-                        .record_data_point(latency, num_committed);
+                    let ack_to_commit =
+                        end_time.saturating_sub(ack_offset_time / num_requests as u64);
+                    self.record(|stats| {
+                        stats.committed.fetch_add(num_committed, Ordering::Relaxed);
+                        stats
+                            .expired
+                            .fetch_add(uncommitted.len() as u64, Ordering::Relaxed);
+                        stats.latency.fetch_add(
+                            // To avoid negative result caused by uncommitted tx occur
+                            // Simplified from:
+                            // end_time * num_committed - (tx_offset_time/num_requests) * num_committed
+                            // to
+                            // (end_time - tx_offset_time / num_requests) * num_committed
+                            latency * num_committed as u64,
+                            Ordering::Relaxed,
+                        );
+                        stats.record_latency(elapsed, latency, num_committed);
+                        stats.record_ack_to_commit_latency(ack_to_commit, num_committed);
+                        stats.record_accounts(&requests, &uncommitted, latency);
+                    });
                     info!(
                         "[{:?}] Transactions were not committed before expiration: {:?}",
                         self.client, uncommitted
                     );
+                    if let Some(dead_letters) = &self.dead_letters {
+                        // Sequence number of each account is the first one not committed
+                        let endpoint = format!("{:?}", self.client);
+                        for txn in requests.iter().filter(|txn| {
+                            uncommitted.iter().any(|(sender, sequence_number)| {
+                                txn.sender() == *sender && txn.sequence_number() >= *sequence_number
+                            })
+                        }) {
+                            dead_letters.record(
+                                "expired",
+                                txn,
+                                &endpoint,
+                                "not committed before expiration",
+                                batch_submit_time,
+                            );
+                        }
+                    }
                 } else {
                     let end_time = (Instant::now() - start_time).as_millis() as u64;
                     let latency = end_time - tx_offset_time / num_requests as u64;
-                    self.stats
-                        .committed
-                        .fetch_add(num_requests as u64, Ordering::Relaxed);
-                    self.stats
-                        .latency
-                        .fetch_add(latency * num_requests as u64, Ordering::Relaxed);
-                    self.stats
-                        .latencies
-                        .record_data_point(latency, num_requests as u64);
+                    let ack_to_commit =
+                        end_time.saturating_sub(ack_offset_time / num_requests as u64);
+                    if let Some((sender, sequence_number)) = sampled_txn {
+                        self.spawn_sample(sender, sequence_number, sampled_submit_time);
+                    }
+                    self.record(|stats| {
+                        stats
+                            .committed
+                            .fetch_add(num_requests as u64, Ordering::Relaxed);
+                        stats
+                            .latency
+                            .fetch_add(latency * num_requests as u64, Ordering::Relaxed);
+                        stats.record_latency(elapsed, latency, num_requests as u64);
+                        stats.record_ack_to_commit_latency(ack_to_commit, num_requests as u64);
+                        stats.record_accounts(&requests, &[], latency);
+                    });
                 }
             }
+            // Below full load each batch cycle is stretched, so that worker submits
+            // proportionally less
             let now = Instant::now();
+            let load_factor = self.params.load_profile.load_factor(now - self.job_start);
+            let wait = match &self.params.workload {
+                Some(workload) => workload.sample_wait(wait, &mut ThreadRng::default()),
+                None => wait,
+            };
+            let wait_util = start_time + max(now - start_time, wait).div_f64(load_factor);
             if wait_util > now {
                 time::delay_for(wait_util - now).await;
+                // Workers wake up late when the emitter runtime is overloaded
+                let lag = Instant::now().saturating_duration_since(wait_util);
+                self.record(|stats| {
+                    stats
+                        .scheduling_lag
+                        .fetch_add(lag.as_millis() as u64, Ordering::Relaxed);
+                    stats.scheduling_lag_samples.fetch_add(1, Ordering::Relaxed);
+                });
             }
         }
         self.accounts
     }
 
+    /// Returns sums of offsets of submission and of acknowledgement of each transaction from
+    /// `start_time` in ms, submission time of the sampled transaction and transactions dropped
+    /// by `submit_routes`. Submission is taken after the pacing delay, as a client holding
+    /// transactions back does not count them as sent
+    async fn submit(
+        &self,
+        requests: &[SignedTransaction],
+        sampled_txn: Option<(AccountAddress, u64)>,
+        start_time: Instant,
+    ) -> (u64, u64, i64, Vec<(AccountAddress, u64)>) {
+        let mut tx_offset_time = 0u64;
+        let mut ack_offset_time = 0u64;
+        let mut sampled_submit_time = 0;
+        let mut dropped = vec![];
+        match self.params.transport {
+            SubmissionTransport::JsonRpc => {
+                for request in requests {
+                    let cur_time = Instant::now();
+                    let submit_time = unix_timestamp_now();
+                    self.record(|stats| {
+                        stats.submitted.fetch_add(1, Ordering::Relaxed);
+                    });
+                    let (paced, request_dropped) = self
+                        .submit_routes(slice::from_ref(request), submit_time)
+                        .await;
+                    tx_offset_time += (cur_time + paced - start_time).as_millis() as u64;
+                    if sampled_txn == Some((request.sender(), request.sequence_number())) {
+                        sampled_submit_time = (submit_time + paced).as_millis() as i64;
+                    }
+                    dropped.extend(request_dropped);
+                    let ack_time = Instant::now();
+                    ack_offset_time += (ack_time - start_time).as_millis() as u64;
+                }
+            }
+            SubmissionTransport::JsonRpcBatch => {
+                let num_requests = requests.len() as u64;
+                let cur_time = Instant::now();
+                let submit_time = unix_timestamp_now();
+                self.record(|stats| {
+                    stats.submitted.fetch_add(num_requests, Ordering::Relaxed);
+                });
+                let (paced, batch_dropped) = self.submit_routes(requests, submit_time).await;
+                tx_offset_time = (cur_time + paced - start_time).as_millis() as u64 * num_requests;
+                if sampled_txn.is_some() {
+                    sampled_submit_time = (submit_time + paced).as_millis() as i64;
+                }
+                dropped = batch_dropped;
+                let ack_time = Instant::now();
+                ack_offset_time = (ack_time - start_time).as_millis() as u64 * num_requests;
+            }
+        }
+        (
+            tx_offset_time,
+            ack_offset_time,
+            sampled_submit_time,
+            dropped,
+        )
+    }
+
+    /// Submits `txns` to own endpoint, or to endpoints the balancer picks in parallel, and
+    /// records how each endpoint responded. Returns the longest pacing delay, which is not part
+    /// of latency, and transactions still pushed back when the job stopped
+    async fn submit_routes(
+        &self,
+        txns: &[SignedTransaction],
+        submit_time: Duration,
+    ) -> (Duration, Vec<(AccountAddress, u64)>) {
+        let mut routes = match &self.balancer {
+            Some(balancer) => balancer.route(txns, &self.stats.retry_stats),
+            None => vec![Route::new(
+                self.target.clone(),
+                self.client.clone(),
+                txns.to_vec(),
+            )],
+        };
+        let submissions = routes
+            .iter_mut()
+            .map(|route| self.submit_route(route, submit_time));
+        let mut max_paced = Duration::from_secs(0);
+        let mut dropped = vec![];
+        for (paced, route_dropped) in join_all(submissions).await {
+            max_paced = max(max_paced, paced);
+            dropped.extend(route_dropped);
+        }
+        (max_paced, dropped)
+    }
+
+    /// Submissions paced by the rate of the endpoint are resubmitted while it pushes them back,
+    /// since later transactions of their senders are only valid once they are taken
+    async fn submit_route(
+        &self,
+        route: &mut Route,
+        submit_time: Duration,
+    ) -> (Duration, Vec<(AccountAddress, u64)>) {
+        let endpoint = self.stats.endpoints.get(&route.name);
+        let rate = endpoint.and_then(|endpoint| endpoint.rate.as_ref());
+        let mut paced = Duration::from_secs(0);
+        loop {
+            if let Some(rate) = rate {
+                let delay = rate.delay(route.txns.len());
+                time::delay_for(delay).await;
+                paced += delay;
+            }
+            let start = Instant::now();
+            let result = match self.params.retry_storm {
+                Some(storm) => self.submit_impatiently(route, storm).await,
+                None => route.submit().await,
+            };
+            let latency = start.elapsed();
+            self.record_ack_latency(&result, latency);
+            if let Some(endpoint) = endpoint {
+                endpoint.record(&result, route.txns.len() as u64, latency);
+            }
+            let backpressured: Vec<_> = match (&result, rate) {
+                (Ok(results), Some(_)) => zip(&route.txns, results)
+                    .filter(|(_, r)| matches!(r, Err(e) if aimd::is_backpressure(e)))
+                    .map(|(txn, _)| txn.clone())
+                    .collect(),
+                _ => vec![],
+            };
+            self.record_submission(&route.client, result, &route.txns, submit_time);
+            if backpressured.is_empty() {
+                return (paced, vec![]);
+            }
+            if self.stop.load(Ordering::Relaxed) {
+                let dropped = backpressured
+                    .iter()
+                    .map(|txn| (txn.sender(), txn.sequence_number()))
+                    .collect();
+                return (paced, dropped);
+            }
+            route.txns = backpressured;
+        }
+    }
+
+    /// Submits `route` again while its submission times out or fails. Last retry waits for the
+    /// response, which is the one recorded
+    async fn submit_impatiently(
+        &self,
+        route: &Route,
+        storm: RetryStorm,
+    ) -> Result<Vec<Result<()>>> {
+        let num_txns = route.txns.len() as u64;
+        let mut retries = 0;
+        let result = loop {
+            if retries == storm.max_retries {
+                break route.submit().await;
+            }
+            // Abandoned submission is not cancelled, it still reaches the endpoint
+            if let Ok(Ok(Ok(results))) = time::timeout(storm.threshold, route.spawn_submit()).await
+            {
+                break Ok(results);
+            }
+            retries += 1;
+            self.record(|stats| {
+                stats
+                    .storm_resubmitted
+                    .fetch_add(num_txns, Ordering::Relaxed);
+            });
+        };
+        if retries > 0 {
+            if let Ok(results) = &result {
+                let rejected = results
+                    .iter()
+                    .filter(|r| matches!(r, Err(e) if is_duplicate(e)))
+                    .count() as u64;
+                self.record(|stats| {
+                    stats
+                        .storm_duplicates_rejected
+                        .fetch_add(rejected, Ordering::Relaxed);
+                });
+            }
+        }
+        result
+    }
+
+    /// Recorded for each transaction of the submission the endpoint accepted
+    fn record_ack_latency(&self, result: &Result<Vec<Result<()>>>, latency: Duration) {
+        let accepted = match result {
+            Ok(results) => results.iter().filter(|r| r.is_ok()).count() as u64,
+            Err(_) => 0,
+        };
+        if accepted > 0 {
+            let latency = latency.as_millis() as u64;
+            self.record(|stats| {
+                stats
+                    .ack_latency
+                    .fetch_add(latency * accepted, Ordering::Relaxed);
+                stats
+                    .ack_latency_samples
+                    .fetch_add(accepted, Ordering::Relaxed);
+                stats.ack_latencies.0.record_data_point(latency, accepted);
+            });
+        }
+    }
+
+    /// Transactions rejected by the endpoint are only logged, while requests which got no
+    /// response are counted as submit errors, since they point to the emitter or its network.
+    /// Both are recorded as dead letters if enabled. Back-pressure is expected under full load,
+    /// so it is not logged as a failure
+    fn record_submission(
+        &self,
+        client: &RetryingClient,
+        result: Result<Vec<Result<()>>>,
+        requests: &[SignedTransaction],
+        submit_time: Duration,
+    ) {
+        let endpoint = || format!("{:?}", client);
+        match result {
+            Ok(results) => {
+                for (request, result) in requests.iter().zip(results) {
+                    if let Err(e) = result {
+                        if aimd::is_backpressure(&e) {
+                            debug!("[{:?}] Mempool is full: {:?}", client, e);
+                        } else {
+                            warn!("[{:?}] Failed to submit request: {:?}", client, e);
+                        }
+                        if let Some(dead_letters) = &self.dead_letters {
+                            let error = format!("{:?}", e);
+                            dead_letters.record(
+                                "rejected",
+                                request,
+                                &endpoint(),
+                                &error,
+                                submit_time,
+                            );
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                let local = e.downcast_ref::<LocalError>().is_some();
+                self.record(|stats| {
+                    stats
+                        .submit_errors
+                        .fetch_add(requests.len() as u64, Ordering::Relaxed);
+                    if local {
+                        stats
+                            .local_submit_errors
+                            .fetch_add(requests.len() as u64, Ordering::Relaxed);
+                    }
+                });
+                warn!("[{:?}] Failed to submit request: {:?}", client, e);
+                if let Some(dead_letters) = &self.dead_letters {
+                    let error = format!("{:?}", e);
+                    for request in requests {
+                        dead_letters.record(
+                            "submit_error",
+                            request,
+                            &endpoint(),
+                            &error,
+                            submit_time,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Updates job stats and stats of the target group of this worker
+    fn record(&self, update: impl Fn(&StatsAccumulator)) {
+        update(&self.stats);
+        if let Some(group_stats) = &self.group_stats {
+            update(group_stats);
+        }
+    }
+
+    /// Samples gas usage and commit latency of the committed transaction in the background,
+    /// unless the last sample is more recent than `SAMPLE_INTERVAL`
+    fn spawn_sample(&mut self, sender: AccountAddress, sequence_number: u64, submit_time: i64) {
+        if let Some(last_sampled) = self.last_sampled {
+            if last_sampled.elapsed() < SAMPLE_INTERVAL {
+                return;
+            }
+        }
+        self.last_sampled = Some(Instant::now());
+        let stats = self.stats.clone();
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            stats
+                .sample_gas(&client, "p2p", sender, sequence_number)
+                .await;
+            stats
+                .sample_commit_latency(&client, sender, sequence_number, submit_time)
+                .await;
+        });
+    }
+
     fn gen_requests(&mut self) -> Vec<SignedTransaction> {
         let mut rng = ThreadRng::default();
         let batch_size = max(MAX_TXN_BATCH_SIZE, self.accounts.len());
@@ -507,19 +1936,257 @@ impl SubmissionWorker {
             .choose_multiple(&mut rng, batch_size);
         let mut requests = Vec::with_capacity(accounts.len());
         for sender in accounts {
-            let receiver = self
-                .all_addresses
-                .choose(&mut rng)
-                .expect("all_addresses can't be empty");
-            let request = gen_transfer_txn_request(sender, receiver, 1);
+            let receiver = match &self.account_parents {
+                Some(account_parents) => pick_vasp_receiver(
+                    &mut rng,
+                    &sender.address,
+                    &self.all_addresses,
+                    account_parents,
+                ),
+                None => *self
+                    .all_addresses
+                    .choose(&mut rng)
+                    .expect("all_addresses can't be empty"),
+            };
+            let (metadata, max_gas_amount) = match &self.params.workload {
+                Some(workload) => (
+                    vec![0; workload.sample_metadata_len(&mut rng)],
+                    workload
+                        .sample_max_gas_amount(&mut rng)
+                        .unwrap_or(MAX_GAS_AMOUNT),
+                ),
+                None => (vec![], MAX_GAS_AMOUNT),
+            };
+            let request = gen_transfer_txn_request(sender, &receiver, 1, metadata, max_gas_amount);
             requests.push(request);
         }
         requests
     }
 }
 
+/// Picks child account of a parent vasp different from sender's one, or sender's own parent
+/// for one in VASP_SWEEP_RATIO transfers
+fn pick_vasp_receiver(
+    rng: &mut ThreadRng,
+    sender: &AccountAddress,
+    all_addresses: &[AccountAddress],
+    account_parents: &HashMap<AccountAddress, AccountAddress>,
+) -> AccountAddress {
+    let sender_parent = account_parents.get(sender);
+    if let Some(sender_parent) = sender_parent {
+        if rng.gen_range(0, VASP_SWEEP_RATIO) == 0 {
+            return *sender_parent;
+        }
+    }
+    let mut receiver = all_addresses
+        .choose(rng)
+        .expect("all_addresses can't be empty");
+    // Few retries are enough as long as there are more than a couple of parents
+    for _ in 0..10 {
+        if account_parents.get(receiver) != sender_parent {
+            break;
+        }
+        receiver = all_addresses
+            .choose(rng)
+            .expect("all_addresses can't be empty");
+    }
+    *receiver
+}
+
+/// Periodically runs administrative flows: tiered mint from treasury compliance account to
+/// designated dealer, preburn by designated dealer and burn by treasury compliance account
+struct AdminWorker {
+    client: RetryingClient,
+    /// Designated dealer is the faucet account
+    dd_account: AccountData,
+    tc_account: AccountData,
+    faucet_lock: Arc<AsyncMutex<()>>,
+    interval: Duration,
+    stop: Arc<AtomicBool>,
+    stats: Arc<StatsAccumulator>,
+}
+
+impl AdminWorker {
+    async fn run(mut self) -> Vec<AccountData> {
+        while !self.stop.load(Ordering::Relaxed) {
+            let wait_util = Instant::now() + self.interval;
+            let faucet_lock = self.faucet_lock.clone();
+            let faucet_guard = faucet_lock.lock().await;
+            // Other users of the faucet moved its sequence number since the last cycle
+            let result = match self.resync_sequence_numbers().await {
+                Ok(()) => self.run_admin_cycle().await,
+                Err(e) => Err(e),
+            };
+            drop(faucet_guard);
+            if let Err(e) = result {
+                warn!("[{:?}] Admin transaction cycle failed: {}", self.client, e);
+            }
+            let now = Instant::now();
+            if wait_util > now {
+                time::delay_for(wait_util - now).await;
+            }
+        }
+        // Admin accounts are not part of the emitter account pool
+        vec![]
+    }
+
+    async fn run_admin_cycle(&mut self) -> Result<()> {
+        let mint_txn = gen_submit_transaction_request(
+            transaction_builder::encode_tiered_mint_script(
+                account_config::coin1_tag(),
+                0,
+                self.dd_account.address,
+                ADMIN_TXN_AMOUNT,
+                0,
+            ),
+            &mut self.tc_account,
+        );
+        self.execute_as_tc(mint_txn, "tiered_mint").await?;
+        let preburn_txn = gen_submit_transaction_request(
+            transaction_builder::encode_preburn_script(
+                account_config::coin1_tag(),
+                ADMIN_TXN_AMOUNT,
+            ),
+            &mut self.dd_account,
+        );
+        self.stats.admin_submitted.fetch_add(1, Ordering::Relaxed);
+        execute_and_wait_retrying(&self.client, &mut self.dd_account, vec![preburn_txn]).await?;
+        self.stats.admin_committed.fetch_add(1, Ordering::Relaxed);
+        self.stats
+            .sample_gas(
+                &self.client,
+                "preburn",
+                self.dd_account.address,
+                self.dd_account.sequence_number - 1,
+            )
+            .await;
+        let burn_txn = gen_submit_transaction_request(
+            transaction_builder::encode_burn_script(
+                account_config::coin1_tag(),
+                0,
+                self.dd_account.address,
+            ),
+            &mut self.tc_account,
+        );
+        self.execute_as_tc(burn_txn, "burn").await
+    }
+
+    async fn execute_as_tc(
+        &mut self,
+        txn: SignedTransaction,
+        txn_type: &'static str,
+    ) -> Result<()> {
+        self.stats.admin_submitted.fetch_add(1, Ordering::Relaxed);
+        execute_and_wait_retrying(&self.client, &mut self.tc_account, vec![txn]).await?;
+        self.stats.admin_committed.fetch_add(1, Ordering::Relaxed);
+        self.stats
+            .sample_gas(
+                &self.client,
+                txn_type,
+                self.tc_account.address,
+                self.tc_account.sequence_number - 1,
+            )
+            .await;
+        Ok(())
+    }
+
+    async fn resync_sequence_numbers(&mut self) -> Result<()> {
+        let sequence_numbers = query_sequence_numbers(
+            &self.client,
+            &[self.dd_account.address, self.tc_account.address],
+        )
+        .await?;
+        self.dd_account.sequence_number = sequence_numbers[0];
+        self.tc_account.sequence_number = sequence_numbers[1];
+        Ok(())
+    }
+}
+
+/// Tops up accounts of a job which ran low on balance, transfers of the workload are random
+/// so some accounts drain over long jobs while others pile up funds
+struct TopUpWorker {
+    client: RetryingClient,
+    /// Shared with admin worker and minting of autoscaled accounts, so top-ups hold
+    /// `faucet_lock` and query its sequence number first
+    faucet_account: AccountData,
+    faucet_lock: Arc<AsyncMutex<()>>,
+    addresses: Arc<Vec<AccountAddress>>,
+    interval: Duration,
+    stop: Arc<AtomicBool>,
+    stats: Arc<StatsAccumulator>,
+}
+
+impl TopUpWorker {
+    async fn run(mut self) -> Vec<AccountData> {
+        while !self.stop.load(Ordering::Relaxed) {
+            let wait_util = Instant::now() + self.interval;
+            match self.top_up().await {
+                Ok(0) => {}
+                Ok(topped_up) => info!("[{:?}] Topped up {} accounts", self.client, topped_up),
+                Err(e) => warn!("[{:?}] Failed to top up accounts: {}", self.client, e),
+            }
+            // Checked often, so that job does not outlive its stop by a whole interval
+            while Instant::now() < wait_util && !self.stop.load(Ordering::Relaxed) {
+                let remaining = wait_util.saturating_duration_since(Instant::now());
+                time::delay_for(min(remaining, Duration::from_secs(1))).await;
+            }
+        }
+        // Faucet account is not part of the emitter account pool
+        vec![]
+    }
+
+    /// Returns number of accounts topped up
+    async fn top_up(&mut self) -> Result<usize> {
+        let balances = query_balances(&self.client, &self.addresses).await?;
+        let low: Vec<_> = zip(self.addresses.iter(), balances)
+            .filter(|(_, balance)| *balance < TOPUP_MIN_BALANCE)
+            .map(|(address, balance)| (*address, LIBRA_PER_NEW_ACCOUNT - balance))
+            .collect();
+        if low.is_empty() {
+            return Ok(0);
+        }
+        let faucet_lock = self.faucet_lock.clone();
+        let _faucet_guard = faucet_lock.lock().await;
+        self.faucet_account.sequence_number =
+            query_sequence_numbers(&self.client, &[self.faucet_account.address]).await?[0];
+        let total = low.iter().map(|(_, amount)| amount).sum();
+        let mint_txn = gen_mint_request(&mut self.faucet_account, total);
+        execute_and_wait_retrying(&self.client, &mut self.faucet_account, vec![mint_txn])
+            .await
+            .map_err(|e| format_err!("Failed to mint into faucet account: {}", e))?;
+        for batch in low.chunks(MAX_TXN_BATCH_SIZE) {
+            let requests = batch
+                .iter()
+                .map(|(address, amount)| {
+                    gen_mint_txn_request(&mut self.faucet_account, address, *amount)
+                })
+                .collect();
+            execute_and_wait_retrying(&self.client, &mut self.faucet_account, requests).await?;
+            self.stats
+                .topups
+                .fetch_add(batch.len() as u64, Ordering::Relaxed);
+        }
+        Ok(low.len())
+    }
+}
+
+/// Like `execute_and_wait_transactions`, but failed requests are retried and go to replicas
+/// of the endpoint of `client`
+async fn execute_and_wait_retrying(
+    client: &RetryingClient,
+    account: &mut AccountData,
+    txns: Vec<SignedTransaction>,
+) -> Result<()> {
+    for result in client.submit_transactions(txns).await? {
+        result?;
+    }
+    wait_for_accounts_sequence(client, slice::from_mut(account))
+        .await
+        .map_err(|_| format_err!("Transactions were not committed before expiration"))
+}
+
 async fn wait_for_accounts_sequence(
-    client: &JsonRpcAsyncClient,
+    client: &RetryingClient,
     accounts: &mut [AccountData],
 ) -> Result<(), Vec<(AccountAddress, u64)>> {
     let deadline = Instant::now() + TXN_MAX_WAIT;
@@ -555,6 +2222,15 @@ async fn wait_for_accounts_sequence(
     Ok(())
 }
 
+/// Rejections of a transaction mempool already has, or which was already committed
+fn is_duplicate(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<JsonRpcError>().map_or(false, |e| {
+        e.code == ServerCode::MempoolInvalidUpdate as i16
+            || e.code == ServerCode::MempoolInvalidSeqNumber as i16
+            || e.as_status_code() == Some(StatusCode::SEQUENCE_NUMBER_TOO_OLD)
+    })
+}
+
 fn is_sequence_equal(accounts: &[AccountData], sequence_numbers: &[u64]) -> bool {
     for (account, sequence_number) in zip(accounts, sequence_numbers) {
         if *sequence_number != account.sequence_number {
@@ -564,8 +2240,72 @@ fn is_sequence_equal(accounts: &[AccountData], sequence_numbers: &[u64]) -> bool
     true
 }
 
+async fn query_gas_used(
+    client: &RetryingClient,
+    sender: AccountAddress,
+    sequence_number: u64,
+) -> Result<u64> {
+    let mut batch = JsonRpcBatch::new();
+    batch.add_get_account_transaction_request(sender, sequence_number, false);
+    let mut responses = client.execute(batch).await?;
+    match responses.remove(0)? {
+        JsonRpcResponse::AccountTransactionResponse(Some(txn)) => Ok(txn.gas_used),
+        other => bail!(
+            "Unexpected response for get_account_transaction {}::{}: {:?}",
+            sender,
+            sequence_number,
+            other
+        ),
+    }
+}
+
+/// Returns timestamp in ms of the block which committed given transaction
+async fn query_commit_timestamp(
+    client: &RetryingClient,
+    sender: AccountAddress,
+    sequence_number: u64,
+) -> Result<i64> {
+    let mut batch = JsonRpcBatch::new();
+    batch.add_get_account_transaction_request(sender, sequence_number, false);
+    let version = match client.execute(batch).await?.remove(0)? {
+        JsonRpcResponse::AccountTransactionResponse(Some(txn)) => txn.version,
+        other => bail!(
+            "Unexpected response for get_account_transaction {}::{}: {:?}",
+            sender,
+            sequence_number,
+            other
+        ),
+    };
+    let mut batch = JsonRpcBatch::new();
+    batch.add_get_metadata_request(Some(version));
+    match client.execute(batch).await?.remove(0)? {
+        JsonRpcResponse::BlockMetadataResponse(metadata) => Ok((metadata.timestamp / 1000) as i64),
+        other => bail!(
+            "Unexpected response for get_metadata {}: {:?}",
+            version,
+            other
+        ),
+    }
+}
+
+/// Endpoints which clock offset could not be estimated are left out
+async fn estimate_clock_offsets(targets: &[Arc<dyn EmitTarget>]) -> BTreeMap<String, i64> {
+    let offsets = join_all(targets.iter().map(|t| t.estimate_clock_offset())).await;
+    let mut result = BTreeMap::new();
+    for (target, offset) in zip(targets, offsets) {
+        match offset {
+            Ok(offset) => {
+                debug!("Clock offset of {} is {} ms", target, offset);
+                result.insert(target.name(), offset);
+            }
+            Err(e) => warn!("Failed to estimate clock offset of {}: {}", target, e),
+        }
+    }
+    result
+}
+
 async fn query_sequence_numbers(
-    client: &JsonRpcAsyncClient,
+    client: &RetryingClient,
     addresses: &[AccountAddress],
 ) -> Result<Vec<u64>> {
     let mut result = vec![];
@@ -585,23 +2325,55 @@ async fn query_sequence_numbers(
     Ok(result)
 }
 
+/// Balances in the gas currency, accounts without one have balance 0
+async fn query_balances(client: &RetryingClient, addresses: &[AccountAddress]) -> Result<Vec<u64>> {
+    let mut result = vec![];
+    for addresses_batch in addresses.chunks(20) {
+        let resp = client
+            .get_accounts(addresses_batch)
+            .await
+            .map_err(|e| format_err!("[{:?}] get_accounts failed: {:?} ", client, e))?;
+        for item in resp.into_iter() {
+            let balance = item
+                .ok_or_else(|| format_err!("account does not exist"))?
+                .balances
+                .iter()
+                .find(|amount| amount.currency == GAS_CURRENCY_CODE)
+                .map_or(0, |amount| amount.amount);
+            result.push(balance);
+        }
+    }
+    Ok(result)
+}
+
 const MAX_GAS_AMOUNT: u64 = 1_000_000;
 const GAS_UNIT_PRICE: u64 = 0;
 const GAS_CURRENCY_CODE: &str = COIN1_NAME;
 const TXN_EXPIRATION_SECONDS: i64 = 50;
 const TXN_MAX_WAIT: Duration = Duration::from_secs(TXN_EXPIRATION_SECONDS as u64 + 30);
 const LIBRA_PER_NEW_ACCOUNT: u64 = 1_000_000;
+/// Accounts below this are topped up back to `LIBRA_PER_NEW_ACCOUNT` if job has top-ups
+const TOPUP_MIN_BALANCE: u64 = LIBRA_PER_NEW_ACCOUNT / 10;
+const ADMIN_TXN_AMOUNT: u64 = 1_000;
 
 fn gen_submit_transaction_request(
     script: Script,
     sender_account: &mut AccountData,
+) -> SignedTransaction {
+    gen_submit_transaction_request_with_gas(script, sender_account, MAX_GAS_AMOUNT)
+}
+
+fn gen_submit_transaction_request_with_gas(
+    script: Script,
+    sender_account: &mut AccountData,
+    max_gas_amount: u64,
 ) -> SignedTransaction {
     let transaction = create_user_txn(
         &sender_account.key_pair,
         TransactionPayload::Script(script),
         sender_account.address,
         sender_account.sequence_number,
-        MAX_GAS_AMOUNT,
+        max_gas_amount,
         GAS_UNIT_PRICE,
         GAS_CURRENCY_CODE.to_owned(),
         TXN_EXPIRATION_SECONDS,
@@ -628,16 +2400,19 @@ fn gen_transfer_txn_request(
     sender: &mut AccountData,
     receiver: &AccountAddress,
     num_coins: u64,
+    metadata: Vec<u8>,
+    max_gas_amount: u64,
 ) -> SignedTransaction {
-    gen_submit_transaction_request(
+    gen_submit_transaction_request_with_gas(
         transaction_builder::encode_peer_to_peer_with_metadata_script(
             account_config::coin1_tag(),
             *receiver,
             num_coins,
-            vec![],
+            metadata,
             vec![],
         ),
         sender,
+        max_gas_amount,
     )
 }
 
@@ -765,8 +2540,11 @@ pub async fn execute_and_wait_transactions(
         account.sequence_number,
         account.address
     );
-    for request in txn {
-        libra_retrier::retry_async(libra_retrier::fixed_retry_strategy(5_000, 20), || {
+    // Mempool parks transactions with gaps in sequence numbers, so whole batch is submitted
+    // at once instead of one round trip per transaction
+    let client = &*client;
+    let submissions = txn.into_iter().map(|request| {
+        libra_retrier::retry_async(libra_retrier::fixed_retry_strategy(5_000, 20), move || {
             let request = request.clone();
             let c = client.clone();
             let client_name = format!("{:?}", client);
@@ -779,11 +2557,14 @@ pub async fn execute_and_wait_transactions(
                 resp.map_err(|e| format_err!("[{}] Failed to submit request: {:?}", client_name, e))
             })
         })
-        .await?;
-    }
-    let r = wait_for_accounts_sequence(client, slice::from_mut(account))
-        .await
-        .map_err(|_| format_err!("Mint transactions were not committed before expiration"));
+    });
+    try_join_all(submissions).await?;
+    let r = wait_for_accounts_sequence(
+        &RetryingClient::from(client.clone()),
+        slice::from_mut(account),
+    )
+    .await
+    .map_err(|_| format_err!("Mint transactions were not committed before expiration"));
     debug!(
         "[{:?}] Account {} is at sequence number {} now",
         client, account.address, account.sequence_number
@@ -846,20 +2627,10 @@ async fn mint_to_new_accounts(
     max_num_accounts_per_batch: u64,
     mut client: JsonRpcAsyncClient,
 ) -> Result<()> {
-    let mut left = accounts;
-    let mut i = 0;
-    let num_accounts = accounts.len();
-    while !left.is_empty() {
-        let batch_size = OsRng.gen::<usize>()
-            % min(
-                max_num_accounts_per_batch as usize,
-                min(MAX_TXN_BATCH_SIZE, num_accounts - i),
-            );
-        let (to_batch, rest) = left.split_at(batch_size + 1);
+    let batch_size = min(max_num_accounts_per_batch as usize, MAX_TXN_BATCH_SIZE);
+    for to_batch in accounts.chunks(batch_size) {
         let mint_requests = gen_mint_txn_requests(minting_account, to_batch, libra_per_new_account);
         execute_and_wait_transactions(&mut client, minting_account, mint_requests).await?;
-        i += to_batch.len();
-        left = rest;
     }
     Ok(())
 }
@@ -887,10 +2658,335 @@ impl StatsAccumulator {
             expired: self.expired.load(Ordering::Relaxed),
             latency: self.latency.load(Ordering::Relaxed),
             latency_buckets: self.latencies.snapshot(),
+            ack_latency: self.ack_latency.load(Ordering::Relaxed),
+            ack_latency_samples: self.ack_latency_samples.load(Ordering::Relaxed),
+            ack_latency_buckets: self.ack_latencies.0.snapshot(),
+            ack_to_commit_latency: self.ack_to_commit_latency.load(Ordering::Relaxed),
+            ack_to_commit_latency_buckets: self.ack_to_commit_latencies.snapshot(),
+            minute_latencies: self
+                .minute_latencies
+                .lock()
+                .expect("minute latencies lock poisoned")
+                .iter()
+                .map(AtomicHistogramAccumulator::snapshot)
+                .collect(),
+            commit_latency: self.commit_latency.load(Ordering::Relaxed),
+            commit_latency_samples: self.commit_latency_samples.load(Ordering::Relaxed),
+            admin_submitted: self.admin_submitted.load(Ordering::Relaxed),
+            admin_committed: self.admin_committed.load(Ordering::Relaxed),
+            gas: self.gas.lock().expect("gas stats lock poisoned").clone(),
+            clock_offsets: self.clock_offsets.clone(),
+            groups: self
+                .groups
+                .iter()
+                .map(|(group, stats)| (*group, stats.accumulate()))
+                .collect(),
+            retries: self.retry_stats.retries.load(Ordering::Relaxed),
+            failovers: self.retry_stats.failovers.load(Ordering::Relaxed),
+            unreachable_target_tps: match self.unreachable_target_tps.load(Ordering::Relaxed) {
+                0 => None,
+                tps => Some(tps),
+            },
+            setup_duration: self.setup_duration,
+            submit_errors: self.submit_errors.load(Ordering::Relaxed),
+            local_submit_errors: self.local_submit_errors.load(Ordering::Relaxed),
+            scheduling_lag: self.scheduling_lag.load(Ordering::Relaxed),
+            scheduling_lag_samples: self.scheduling_lag_samples.load(Ordering::Relaxed),
+            emitter_cpu_time: match (process_cpu_time(), self.cpu_start) {
+                (Some(now), Some(start)) => now.checked_sub(start).unwrap_or_default(),
+                _ => Duration::default(),
+            },
+            emitter_wall_time: self
+                .job_start
+                .map_or_else(Duration::default, |t| t.elapsed()),
+            topups: self.topups.load(Ordering::Relaxed),
+            endpoints: self
+                .endpoints
+                .iter()
+                .map(|(name, endpoint)| (name.clone(), endpoint.accumulate()))
+                .collect(),
+            storm_resubmitted: self.storm_resubmitted.load(Ordering::Relaxed),
+            storm_duplicates_rejected: self.storm_duplicates_rejected.load(Ordering::Relaxed),
+            accounts: self
+                .accounts
+                .lock()
+                .expect("account stats lock poisoned")
+                .clone(),
+        }
+    }
+
+    /// Recorded for the whole job and for the minute `elapsed` since start of the job falls in
+    fn record_latency(&self, elapsed: Duration, latency: u64, num_committed: u64) {
+        self.latencies.record_data_point(latency, num_committed);
+        let minute = (elapsed.as_secs() / 60) as usize;
+        let mut minutes = self
+            .minute_latencies
+            .lock()
+            .expect("minute latencies lock poisoned");
+        if minutes.len() <= minute {
+            minutes.resize_with(minute + 1, Default::default);
+        }
+        minutes[minute].record_data_point(latency, num_committed);
+    }
+
+    /// Transactions of `requests` are committed unless their sender is `uncommitted` from the
+    /// given sequence number
+    fn record_accounts(
+        &self,
+        requests: &[SignedTransaction],
+        uncommitted: &[(AccountAddress, u64)],
+        latency: u64,
+    ) {
+        let mut accounts = self.accounts.lock().expect("account stats lock poisoned");
+        for txn in requests {
+            let stats = accounts.entry(txn.sender()).or_default();
+            stats.submitted += 1;
+            if !uncommitted.iter().any(|(sender, sequence_number)| {
+                txn.sender() == *sender && txn.sequence_number() >= *sequence_number
+            }) {
+                stats.committed += 1;
+                stats.latency += latency;
+            }
+        }
+    }
+
+    fn record_ack_to_commit_latency(&self, latency: u64, num_committed: u64) {
+        self.ack_to_commit_latency
+            .fetch_add(latency * num_committed, Ordering::Relaxed);
+        self.ack_to_commit_latencies
+            .record_data_point(latency, num_committed);
+    }
+
+    async fn sample_gas(
+        &self,
+        client: &RetryingClient,
+        txn_type: &'static str,
+        sender: AccountAddress,
+        sequence_number: u64,
+    ) {
+        match query_gas_used(client, sender, sequence_number).await {
+            Ok(gas_used) => {
+                let mut gas = self.gas.lock().expect("gas stats lock poisoned");
+                let stats = gas.entry(txn_type).or_default();
+                stats.samples += 1;
+                stats.gas_used += gas_used;
+            }
+            Err(e) => debug!("[{:?}] Failed to sample gas usage: {}", client, e),
+        }
+    }
+
+    /// Commit latency is taken from block timestamp, which comes from the clock of the block
+    /// proposer. JSON-RPC does not tell the proposer, so latency is not corrected, and clock
+    /// offsets of endpoints are reported instead as an estimate of the error
+    async fn sample_commit_latency(
+        &self,
+        client: &RetryingClient,
+        sender: AccountAddress,
+        sequence_number: u64,
+        submit_time: i64,
+    ) {
+        match query_commit_timestamp(client, sender, sequence_number).await {
+            Ok(commit_timestamp) => {
+                let latency = commit_timestamp - submit_time;
+                self.commit_latency
+                    .fetch_add(max(latency, 0) as u64, Ordering::Relaxed);
+                self.commit_latency_samples.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) => debug!("[{:?}] Failed to query commit timestamp: {}", client, e),
+        }
+    }
+}
+
+impl EndpointAccumulator {
+    /// Transactions of the request are rejected one by one, or all fail without response
+    fn record(&self, result: &Result<Vec<Result<()>>>, num_txns: u64, latency: Duration) {
+        self.submitted.fetch_add(num_txns, Ordering::Relaxed);
+        match result {
+            Ok(results) => {
+                let accepted = results.iter().filter(|r| r.is_ok()).count() as u64;
+                let backpressured = results
+                    .iter()
+                    .filter(|r| matches!(r, Err(e) if aimd::is_backpressure(e)))
+                    .count() as u64;
+                self.backpressured
+                    .fetch_add(backpressured, Ordering::Relaxed);
+                if let Some(rate) = &self.rate {
+                    rate.on_response(accepted, backpressured);
+                }
+                self.accepted.fetch_add(accepted, Ordering::Relaxed);
+                self.rejected
+                    .fetch_add(results.len() as u64 - accepted, Ordering::Relaxed);
+                self.responses.fetch_add(1, Ordering::Relaxed);
+                self.ack_latency
+                    .fetch_add(latency.as_millis() as u64, Ordering::Relaxed);
+            }
+            Err(_) => {
+                self.submit_errors.fetch_add(num_txns, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn accumulate(&self) -> EndpointStats {
+        EndpointStats {
+            submitted: self.submitted.load(Ordering::Relaxed),
+            accepted: self.accepted.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            submit_errors: self.submit_errors.load(Ordering::Relaxed),
+            responses: self.responses.load(Ordering::Relaxed),
+            ack_latency: self.ack_latency.load(Ordering::Relaxed),
+            backpressured: self.backpressured.load(Ordering::Relaxed),
+            rate: self.rate.as_ref().map(AimdRate::snapshot),
+        }
+    }
+}
+
+impl EndpointStats {
+    /// Average time in ms until the endpoint answered requests which got a response
+    pub fn avg_ack_latency(&self) -> u64 {
+        if self.responses == 0 {
+            0
+        } else {
+            self.ack_latency / self.responses
+        }
+    }
+}
+
+impl GasStats {
+    pub fn avg_gas_used(&self) -> u64 {
+        if self.samples == 0 {
+            0
+        } else {
+            self.gas_used / self.samples
         }
     }
 }
 
+impl TxStats {
+    /// Gas usage across all sampled transactions regardless of their type
+    pub fn total_gas(&self) -> GasStats {
+        self.gas
+            .values()
+            .fold(GasStats::default(), |acc, g| GasStats {
+                samples: acc.samples + g.samples,
+                gas_used: acc.gas_used + g.gas_used,
+            })
+    }
+
+    /// Largest absolute clock offset of endpoints in ms
+    pub fn max_clock_offset(&self) -> u64 {
+        self.clock_offsets
+            .values()
+            .map(|offset| offset.abs() as u64)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Fraction of all cores of the emitter host used by the cluster-test process. Workers
+    /// compete with the rest of the process for its CPU, so saturation starves them either way
+    pub fn emitter_cpu_utilization(&self) -> Option<f64> {
+        if self.emitter_wall_time.as_millis() == 0 || self.emitter_cpu_time.as_millis() == 0 {
+            return None;
+        }
+        Some(
+            self.emitter_cpu_time.as_secs_f64()
+                / (self.emitter_wall_time.as_secs_f64() * num_cpus::get() as f64),
+        )
+    }
+
+    pub fn avg_scheduling_lag(&self) -> u64 {
+        if self.scheduling_lag_samples == 0 {
+            0
+        } else {
+            self.scheduling_lag / self.scheduling_lag_samples
+        }
+    }
+
+    /// Reasons to believe that throughput was limited by the emitter rather than the cluster,
+    /// empty if there are none
+    pub fn emitter_bottlenecks(&self) -> Vec<String> {
+        let mut reasons = vec![];
+        if let Some(utilization) = self.emitter_cpu_utilization() {
+            if utilization >= EMITTER_CPU_SATURATION {
+                reasons.push(format!(
+                    "cluster-test process CPU at {:.0}%",
+                    utilization * 100.0
+                ));
+            }
+        }
+        if self.avg_scheduling_lag() >= MAX_SCHEDULING_LAG_MS {
+            reasons.push(format!(
+                "workers wake up {} ms late on average",
+                self.avg_scheduling_lag()
+            ));
+        }
+        // Errors at the endpoint point to the cluster, only local ones to the emitter
+        if self.submitted > 0
+            && self.local_submit_errors * 100 >= self.submitted * MAX_SUBMIT_ERROR_PERCENT
+        {
+            reasons.push(format!(
+                "{} of {} submissions failed on the emitter host",
+                self.local_submit_errors, self.submitted
+            ));
+        }
+        reasons
+    }
+
+    /// Sum of rates endpoints converged to under back-pressure, None unless submissions adapted
+    /// to it and every endpoint pushed back, since capacity of the others was not reached
+    pub fn measured_capacity(&self) -> Option<f64> {
+        let rates: Vec<_> = self.endpoints.values().filter_map(|e| e.rate).collect();
+        if rates.is_empty() {
+            return None;
+        }
+        rates.iter().map(|rate| rate.converged_tps).sum()
+    }
+
+    /// Account with the highest average latency in ms among accounts which got transactions
+    /// committed
+    pub fn worst_served_account(&self) -> Option<(AccountAddress, u64)> {
+        self.accounts
+            .iter()
+            .filter(|(_, account)| account.committed > 0)
+            .map(|(address, account)| (*address, account.latency / account.committed))
+            .max_by_key(|(_, latency)| *latency)
+    }
+
+    /// Accounts which submitted transactions but got none of them committed
+    pub fn starved_accounts(&self) -> usize {
+        self.accounts
+            .values()
+            .filter(|account| account.submitted > 0 && account.committed == 0)
+            .count()
+    }
+
+    /// Gini coefficient of committed transactions of accounts which submitted any, 0 if all
+    /// of them were served equally and close to 1 if a few of them got all the throughput
+    pub fn account_throughput_gini(&self) -> Option<f64> {
+        let committed: Vec<_> = self
+            .accounts
+            .values()
+            .filter(|account| account.submitted > 0)
+            .map(|account| account.committed)
+            .collect();
+        gini(committed)
+    }
+}
+
+fn gini(mut values: Vec<u64>) -> Option<f64> {
+    let total: u64 = values.iter().sum();
+    if total == 0 {
+        return None;
+    }
+    values.sort_unstable();
+    let n = values.len() as f64;
+    let weighted: f64 = values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| (i + 1) as f64 * *value as f64)
+        .sum();
+    Some(2.0 * weighted / (n * total as f64) - (n + 1.0) / n)
+}
+
 impl TxStats {
     pub fn rate(&self, window: Duration) -> TxStatsRate {
         TxStatsRate {
@@ -903,6 +2999,28 @@ impl TxStats {
                 self.latency / self.committed
             },
             p99_latency: self.latency_buckets.percentile(99, 100),
+            ack_latency: if self.ack_latency_samples == 0 {
+                0u64
+            } else {
+                self.ack_latency / self.ack_latency_samples
+            },
+            p99_ack_latency: self.ack_latency_buckets.percentile(99, 100),
+            ack_to_commit_latency: if self.committed == 0 {
+                0u64
+            } else {
+                self.ack_to_commit_latency / self.committed
+            },
+            p99_ack_to_commit_latency: self.ack_to_commit_latency_buckets.percentile(99, 100),
+            commit_latency: if self.commit_latency_samples == 0 {
+                0u64
+            } else {
+                self.commit_latency / self.commit_latency_samples
+            },
+            groups: self
+                .groups
+                .iter()
+                .map(|(group, stats)| (*group, stats.rate(window)))
+                .collect(),
         }
     }
 }
@@ -917,6 +3035,102 @@ impl Sub for &TxStats {
             expired: self.expired - other.expired,
             latency: self.latency - other.latency,
             latency_buckets: &self.latency_buckets - &other.latency_buckets,
+            ack_latency: self.ack_latency - other.ack_latency,
+            ack_latency_samples: self.ack_latency_samples - other.ack_latency_samples,
+            ack_latency_buckets: &self.ack_latency_buckets - &other.ack_latency_buckets,
+            ack_to_commit_latency: self.ack_to_commit_latency - other.ack_to_commit_latency,
+            ack_to_commit_latency_buckets: &self.ack_to_commit_latency_buckets
+                - &other.ack_to_commit_latency_buckets,
+            minute_latencies: self
+                .minute_latencies
+                .iter()
+                .enumerate()
+                .map(
+                    |(minute, latencies)| match other.minute_latencies.get(minute) {
+                        Some(other) => latencies - other,
+                        None => latencies - &AtomicHistogramSnapshot::default(),
+                    },
+                )
+                .collect(),
+            commit_latency: self.commit_latency - other.commit_latency,
+            commit_latency_samples: self.commit_latency_samples - other.commit_latency_samples,
+            admin_submitted: self.admin_submitted - other.admin_submitted,
+            admin_committed: self.admin_committed - other.admin_committed,
+            gas: self
+                .gas
+                .iter()
+                .map(|(txn_type, gas)| {
+                    let other = other.gas.get(txn_type).copied().unwrap_or_default();
+                    let delta = GasStats {
+                        samples: gas.samples - other.samples,
+                        gas_used: gas.gas_used - other.gas_used,
+                    };
+                    (*txn_type, delta)
+                })
+                .collect(),
+            clock_offsets: self.clock_offsets.clone(),
+            groups: self
+                .groups
+                .iter()
+                .map(|(group, stats)| {
+                    let delta = match other.groups.get(group) {
+                        Some(other) => stats - other,
+                        None => stats - &TxStats::default(),
+                    };
+                    (*group, delta)
+                })
+                .collect(),
+            retries: self.retries - other.retries,
+            failovers: self.failovers - other.failovers,
+            unreachable_target_tps: self.unreachable_target_tps,
+            setup_duration: self.setup_duration,
+            submit_errors: self.submit_errors - other.submit_errors,
+            local_submit_errors: self.local_submit_errors - other.local_submit_errors,
+            scheduling_lag: self.scheduling_lag - other.scheduling_lag,
+            scheduling_lag_samples: self.scheduling_lag_samples - other.scheduling_lag_samples,
+            emitter_cpu_time: self
+                .emitter_cpu_time
+                .checked_sub(other.emitter_cpu_time)
+                .unwrap_or_default(),
+            emitter_wall_time: self
+                .emitter_wall_time
+                .checked_sub(other.emitter_wall_time)
+                .unwrap_or_default(),
+            topups: self.topups - other.topups,
+            endpoints: self
+                .endpoints
+                .iter()
+                .map(|(name, endpoint)| {
+                    let other = other.endpoints.get(name).copied().unwrap_or_default();
+                    let delta = EndpointStats {
+                        submitted: endpoint.submitted - other.submitted,
+                        accepted: endpoint.accepted - other.accepted,
+                        rejected: endpoint.rejected - other.rejected,
+                        submit_errors: endpoint.submit_errors - other.submit_errors,
+                        responses: endpoint.responses - other.responses,
+                        ack_latency: endpoint.ack_latency - other.ack_latency,
+                        backpressured: endpoint.backpressured - other.backpressured,
+                        rate: endpoint.rate,
+                    };
+                    (name.clone(), delta)
+                })
+                .collect(),
+            storm_resubmitted: self.storm_resubmitted - other.storm_resubmitted,
+            storm_duplicates_rejected: self.storm_duplicates_rejected
+                - other.storm_duplicates_rejected,
+            accounts: self
+                .accounts
+                .iter()
+                .map(|(address, account)| {
+                    let other = other.accounts.get(address).copied().unwrap_or_default();
+                    let delta = AccountStats {
+                        submitted: account.submitted - other.submitted,
+                        committed: account.committed - other.committed,
+                        latency: account.latency - other.latency,
+                    };
+                    (*address, delta)
+                })
+                .collect(),
         }
     }
 }
@@ -927,7 +3141,57 @@ impl fmt::Display for TxStats {
             f,
             "submitted: {}, committed: {}, expired: {}",
             self.submitted, self.committed, self.expired,
-        )
+        )?;
+        if self.admin_submitted > 0 {
+            write!(
+                f,
+                ", admin submitted: {}, admin committed: {}",
+                self.admin_submitted, self.admin_committed,
+            )?;
+        }
+        for (txn_type, gas) in &self.gas {
+            write!(f, ", {} avg gas: {}", txn_type, gas.avg_gas_used())?;
+        }
+        if !self.clock_offsets.is_empty() {
+            write!(f, ", max clock offset: {} ms", self.max_clock_offset())?;
+        }
+        if self.retries > 0 || self.failovers > 0 {
+            write!(
+                f,
+                ", retries: {}, failovers: {}",
+                self.retries, self.failovers
+            )?;
+        }
+        if let Some(target_tps) = self.unreachable_target_tps {
+            write!(f, ", target {} txn/s not reached", target_tps)?;
+        }
+        if self.submit_errors > 0 {
+            write!(f, ", submit errors: {}", self.submit_errors)?;
+        }
+        if self.topups > 0 {
+            write!(f, ", accounts topped up: {}", self.topups)?;
+        }
+        if self.storm_resubmitted > 0 {
+            write!(
+                f,
+                ", resubmitted by retry storm: {}, duplicates rejected: {}",
+                self.storm_resubmitted, self.storm_duplicates_rejected
+            )?;
+        }
+        if let Some(utilization) = self.emitter_cpu_utilization() {
+            write!(f, ", emitter cpu: {:.0}%", utilization * 100.0)?;
+        }
+        if let Some(capacity) = self.measured_capacity() {
+            write!(f, ", measured capacity: {:.0} txn/s", capacity)?;
+        }
+        for (group, stats) in &self.groups {
+            write!(
+                f,
+                ", {} submitted: {}, committed: {}, expired: {}",
+                group, stats.submitted, stats.committed, stats.expired,
+            )?;
+        }
+        Ok(())
     }
 }
 
@@ -935,15 +3199,42 @@ impl fmt::Display for TxStatsRate {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "submitted: {} txn/s, committed: {} txn/s, expired: {} txn/s, latency: {} ms, p99 latency: {} ms",
-            self.submitted, self.committed, self.expired, self.latency, self.p99_latency,
-        )
+            "submitted: {} txn/s, committed: {} txn/s, expired: {} txn/s, latency: {} ms, p99 latency: {} ms, commit latency: {} ms",
+            self.submitted, self.committed, self.expired, self.latency, self.p99_latency, self.commit_latency,
+        )?;
+        write!(
+            f,
+            ", to ack: {} ms (p99 {} ms), ack to commit: {} ms (p99 {} ms)",
+            self.ack_latency,
+            self.p99_ack_latency,
+            self.ack_to_commit_latency,
+            self.p99_ack_to_commit_latency,
+        )?;
+        for (group, rate) in &self.groups {
+            write!(
+                f,
+                ", {} committed: {} txn/s, latency: {} ms",
+                group, rate.committed, rate.latency,
+            )?;
+        }
+        Ok(())
     }
 }
 
+/// CPU time used by this process so far, from /proc/self/stat which is in clock ticks of 10ms
+fn process_cpu_time() -> Option<Duration> {
+    let stat = fs::read_to_string("/proc/self/stat").ok()?;
+    // Command name may contain spaces, fields after it are separated by single spaces
+    let fields: Vec<_> = stat.rsplit(')').next()?.split_whitespace().collect();
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some(Duration::from_millis((utime + stime) * 10))
+}
+
 #[cfg(test)]
 mod test {
-    use crate::tx_emitter::EmitJobRequest;
+    use crate::tx_emitter::{gini, EmitJobRequest, LoadProfile, TxStats};
+    use std::time::Duration;
 
     #[test]
     pub fn test_fixed_tps_params() {
@@ -957,4 +3248,46 @@ mod test {
         assert_eq!(num_workers, 2usize);
         assert_eq!(wait_time, 2000u64);
     }
+
+    #[test]
+    pub fn test_diurnal_load_factor() {
+        let profile = LoadProfile::Diurnal {
+            period: Duration::from_secs(600),
+            low_percent: 20,
+        };
+        let factor = |secs| profile.load_factor(Duration::from_secs(secs));
+        assert!((factor(0) - 0.2).abs() < 1e-9);
+        assert!((factor(150) - 0.6).abs() < 1e-9);
+        assert!((factor(300) - 1.0).abs() < 1e-9);
+        assert!((factor(600) - 0.2).abs() < 1e-9);
+        assert!((LoadProfile::Constant.load_factor(Duration::from_secs(150)) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    pub fn test_emitter_bottlenecks() {
+        let stats = TxStats {
+            submitted: 1000,
+            submit_errors: 50,
+            local_submit_errors: 5,
+            scheduling_lag: 1000,
+            scheduling_lag_samples: 100,
+            ..Default::default()
+        };
+        assert!(stats.emitter_bottlenecks().is_empty());
+        let stats = TxStats {
+            local_submit_errors: 50,
+            scheduling_lag_samples: 10,
+            ..stats
+        };
+        assert_eq!(stats.emitter_bottlenecks().len(), 2);
+    }
+
+    #[test]
+    pub fn test_gini() {
+        assert_eq!(gini(vec![]), None);
+        assert_eq!(gini(vec![0, 0]), None);
+        assert!(gini(vec![5, 5, 5, 5]).unwrap().abs() < 1e-9);
+        assert!((gini(vec![0, 0, 0, 8]).unwrap() - 0.75).abs() < 1e-9);
+        assert!((gini(vec![3, 1]).unwrap() - 0.25).abs() < 1e-9);
+    }
 }
@@ -0,0 +1,54 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Images are pulled on every pod start, so instances deployed with the same tag run different
+/// builds once the tag moves. Mixed versions have silently invalidated benchmark results before,
+/// so images actually deployed are compared before a suite starts
+use crate::cluster::Cluster;
+use futures::future::join_all;
+use libra_logger::{debug, warn};
+use std::collections::BTreeMap;
+
+/// Peer names by deployed image, instances whose image is not known are left out
+pub async fn deployed_images(cluster: &Cluster) -> BTreeMap<String, Vec<String>> {
+    let instances: Vec<_> = cluster.validator_and_fullnode_instances().collect();
+    let images = join_all(instances.iter().map(|i| i.deployed_image())).await;
+    let mut result: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (instance, image) in instances.iter().zip(images) {
+        match image {
+            Ok(image) => result
+                .entry(image)
+                .or_default()
+                .push(instance.peer_name().clone()),
+            Err(e) => debug!("Deployed image of {} is not known: {}", instance, e),
+        }
+    }
+    if result.is_empty() {
+        warn!("Deployed images are not known, version skew is not checked");
+    }
+    result
+}
+
+/// None if all instances run the same image
+pub fn describe_skew(images: &BTreeMap<String, Vec<String>>) -> Option<String> {
+    if images.len() < 2 {
+        return None;
+    }
+    let images: Vec<_> = images
+        .iter()
+        .map(|(image, peers)| {
+            format!(
+                "{} on {} instances ({})",
+                image.trim_start_matches("docker-pullable://"),
+                peers.len(),
+                peers.join(", ")
+            )
+        })
+        .collect();
+    Some(format!(
+        "cluster runs mixed versions: {}",
+        images.join("; ")
+    ))
+}
@@ -0,0 +1,77 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Setup which is expensive to redo and stays valid for the rest of a suite once done, i.e.
+/// accounts prepopulated on the ledger. Experiments record it here and later ones build on it
+/// instead of repeating it. The emitter account pool is kept by `TxEmitter` itself, but it is
+/// dropped together with this state once the chain is reset
+use crate::instance::Instance;
+use futures::future::join_all;
+use libra_logger::{info, warn};
+
+#[derive(Default)]
+pub struct WarmState {
+    /// Highest ledger version seen, a chain with lower version was reset since
+    version: Option<u64>,
+    prepopulated_accounts: u64,
+}
+
+impl WarmState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accounts created on the ledger by earlier experiments, whose keys are not kept
+    pub fn prepopulated_accounts(&self) -> u64 {
+        self.prepopulated_accounts
+    }
+
+    pub fn record_prepopulated(&mut self, accounts: u64) {
+        self.prepopulated_accounts = accounts;
+    }
+
+    /// Has to be called by experiments which redeploy the cluster from a new genesis
+    pub fn invalidate(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Forgets everything if the most advanced ledger of `instances` is behind the highest
+    /// version seen, which means the chain was reset. A single lagging or restarted instance
+    /// does not drop the state. Returns whether state was kept
+    pub async fn check_chain(&mut self, instances: &[Instance]) -> bool {
+        let mut version = None;
+        for (instance, result) in instances
+            .iter()
+            .zip(join_all(instances.iter().map(Instance::latest_version)).await)
+        {
+            match result {
+                Ok(v) => version = version.max(Some(v)),
+                Err(e) => warn!("Failed to get ledger version of {}: {}", instance, e),
+            }
+        }
+        let version = match version {
+            Some(version) => version,
+            None => {
+                warn!("Failed to check whether chain was reset, no instance answered");
+                return true;
+            }
+        };
+        match self.version {
+            Some(seen) if version < seen => {
+                info!(
+                    "Ledger is at version {}, below {} seen before, dropping warm state",
+                    version, seen
+                );
+                self.invalidate();
+                self.version = Some(version);
+                false
+            }
+            _ => {
+                self.version = Some(version);
+                true
+            }
+        }
+    }
+}
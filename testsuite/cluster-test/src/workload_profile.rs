@@ -0,0 +1,372 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#![forbid(unsafe_code)]
+
+/// Workload observed on a live network, recorded from a sample of its latest committed
+/// transactions. Emitter replays it with the observed rate, metadata sizes, max gas amounts and
+/// burstiness of arrivals, so that benchmarks see a real traffic mix instead of uniform
+/// transfers. Gas used depends on execution, so it is only recorded to compare against
+use anyhow::{bail, format_err, Result};
+use libra_json_rpc_client::{
+    views::{ScriptView, TransactionDataView, TransactionView},
+    JsonRpcAsyncClient, JsonRpcBatch, JsonRpcResponse,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{cmp::min, collections::BTreeMap, fmt, fs, path::Path, time::Duration};
+
+/// Max page size of get_transactions
+const PAGE_SIZE: u64 = 1000;
+/// Distributions are kept as this many percentiles, p0 to p100
+const PERCENTILES: usize = 21;
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct WorkloadProfile {
+    /// Number of user transactions sampled
+    pub sampled: usize,
+    /// Block time between first and last sampled transaction
+    pub window_secs: f64,
+    /// Share of each script type, e.g. `peer_to_peer_transaction`
+    pub script_types: BTreeMap<String, f64>,
+    /// Metadata of transfers, which is the only part of them that varies in size
+    pub metadata_bytes: Distribution,
+    pub gas_used: Distribution,
+    pub max_gas_amount: Distribution,
+    /// Gaps between transactions in ms, transactions of a block are spread evenly over the
+    /// time since the previous block with user transactions
+    pub inter_arrival_ms: Distribution,
+    pub avg_tps: f64,
+    /// Busiest second of the window
+    pub peak_tps: f64,
+}
+
+/// Percentiles of samples, evenly spaced from p0 to p100
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Distribution {
+    pub percentiles: Vec<f64>,
+}
+
+impl Distribution {
+    fn from_samples(mut samples: Vec<f64>) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort_by(|a, b| a.partial_cmp(b).expect("Samples are not NaN"));
+        let last = samples.len() - 1;
+        let percentiles = (0..PERCENTILES)
+            .map(|i| samples[i * last / (PERCENTILES - 1)])
+            .collect();
+        Self { percentiles }
+    }
+
+    /// `p` in 0..=100, rounded down to the nearest kept percentile
+    pub fn percentile(&self, p: usize) -> f64 {
+        if self.percentiles.is_empty() {
+            return 0.0;
+        }
+        let index = min(p, 100) * (self.percentiles.len() - 1) / 100;
+        self.percentiles[index]
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.percentiles.is_empty() {
+            return 0.0;
+        }
+        self.percentiles.iter().sum::<f64>() / self.percentiles.len() as f64
+    }
+
+    /// Random value between two adjacent percentiles, which follows the distribution
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> f64 {
+        match self.percentiles.len() {
+            0 => 0.0,
+            1 => self.percentiles[0],
+            len => {
+                let i = rng.gen_range(0, len - 1);
+                let (low, high) = (self.percentiles[i], self.percentiles[i + 1]);
+                low + (high - low) * rng.gen::<f64>()
+            }
+        }
+    }
+}
+
+impl WorkloadProfile {
+    /// Samples at least `count` latest user transactions unless the chain is shorter, going
+    /// back page by page
+    pub async fn record(client: &JsonRpcAsyncClient, count: usize) -> Result<Self> {
+        let mut batch = JsonRpcBatch::new();
+        batch.add_get_metadata_request(None);
+        let latest = match client.execute(batch).await?.remove(0)? {
+            JsonRpcResponse::BlockMetadataResponse(metadata) => metadata.version,
+            other => bail!("Unexpected response for get_metadata: {:?}", other),
+        };
+        let mut pages = vec![];
+        let mut user_txns = 0;
+        let mut end = latest + 1;
+        while user_txns < count && end > 0 {
+            let start = end.saturating_sub(PAGE_SIZE);
+            let mut batch = JsonRpcBatch::new();
+            batch.add_get_transactions_request(start, end - start, false);
+            let txns = match client.execute(batch).await?.remove(0)? {
+                JsonRpcResponse::TransactionsResponse(txns) => txns,
+                other => bail!("Unexpected response for get_transactions: {:?}", other),
+            };
+            user_txns += txns.iter().filter(|t| is_user(t)).count();
+            pages.push(txns);
+            end = start;
+        }
+        let txns: Vec<_> = pages.into_iter().rev().flatten().collect();
+        Self::from_transactions(&txns)
+    }
+
+    /// `txns` are consecutive, user transactions before the first block metadata are skipped
+    /// since their block time is not known
+    pub fn from_transactions(txns: &[TransactionView]) -> Result<Self> {
+        let mut script_types: BTreeMap<String, f64> = BTreeMap::new();
+        let mut metadata_bytes = vec![];
+        let mut gas_used = vec![];
+        let mut max_gas_amount = vec![];
+        let mut per_second: BTreeMap<u64, u64> = BTreeMap::new();
+        // Block time and number of user transactions of every block
+        let mut blocks: Vec<(u64, u64)> = vec![];
+        let mut sampled = 0;
+        for txn in txns {
+            match &txn.transaction {
+                TransactionDataView::BlockMetadata { timestamp_usecs } => {
+                    blocks.push((*timestamp_usecs, 0));
+                }
+                TransactionDataView::UserTransaction {
+                    max_gas_amount: max_gas,
+                    script,
+                    ..
+                } => {
+                    let (time, block_txns) = match blocks.last_mut() {
+                        Some(block) => block,
+                        None => continue,
+                    };
+                    sampled += 1;
+                    *block_txns += 1;
+                    *per_second.entry(*time / 1_000_000).or_default() += 1;
+                    *script_types.entry(script_type(script)).or_default() += 1.0;
+                    if let ScriptView::PeerToPeer { metadata, .. } = script {
+                        metadata_bytes.push((metadata.0.len() / 2) as f64);
+                    }
+                    gas_used.push(txn.gas_used as f64);
+                    max_gas_amount.push(*max_gas as f64);
+                }
+                _ => {}
+            }
+        }
+        if sampled == 0 {
+            bail!("No user transactions with known block time were found");
+        }
+        for share in script_types.values_mut() {
+            *share /= sampled as f64;
+        }
+        // Empty blocks in between are not arrivals, gap of a block is measured from the last
+        // one which had user transactions
+        let mut inter_arrival_ms = vec![];
+        let mut prev_time = None;
+        for (time, block_txns) in blocks.iter().filter(|(_, block_txns)| *block_txns > 0) {
+            if let Some(prev_time) = prev_time {
+                let gap_ms = time.saturating_sub(prev_time) as f64 / 1000.0;
+                for _ in 0..*block_txns {
+                    inter_arrival_ms.push(gap_ms / *block_txns as f64);
+                }
+            }
+            prev_time = Some(*time);
+        }
+        let mut with_txns = blocks.iter().filter(|(_, block_txns)| *block_txns > 0);
+        let first = with_txns.next().map_or(0, |(time, _)| *time);
+        let last = with_txns.last().map_or(first, |(time, _)| *time);
+        let window_secs = (last - first) as f64 / 1_000_000.0;
+        let avg_tps = if window_secs > 0.0 {
+            sampled as f64 / window_secs
+        } else {
+            sampled as f64
+        };
+        Ok(Self {
+            sampled,
+            window_secs,
+            script_types,
+            metadata_bytes: Distribution::from_samples(metadata_bytes),
+            gas_used: Distribution::from_samples(gas_used),
+            max_gas_amount: Distribution::from_samples(max_gas_amount),
+            inter_arrival_ms: Distribution::from_samples(inter_arrival_ms),
+            avg_tps,
+            peak_tps: per_second.values().cloned().max().unwrap_or(0) as f64,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format_err!("Failed to read workload profile {:?}: {}", path, e))?;
+        serde_json::from_str(&content)
+            .map_err(|e| format_err!("Failed to parse workload profile {:?}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .map_err(|e| format_err!("Failed to write workload profile {:?}: {}", path, e))
+    }
+
+    /// Share of transactions of types emitter can not generate, they are sent as transfers
+    pub fn unsupported_share(&self) -> f64 {
+        self.script_types
+            .iter()
+            .filter(|(script_type, _)| script_type.as_str() != "peer_to_peer_transaction")
+            .map(|(_, share)| share)
+            .sum()
+    }
+
+    /// Wait between batches of a worker, varied around `wait` like gaps between observed
+    /// transactions vary around their mean
+    pub fn sample_wait<R: Rng>(&self, wait: Duration, rng: &mut R) -> Duration {
+        let mean = self.inter_arrival_ms.mean();
+        if mean <= 0.0 {
+            return wait;
+        }
+        wait.mul_f64(self.inter_arrival_ms.sample(rng) / mean)
+    }
+
+    pub fn sample_metadata_len<R: Rng>(&self, rng: &mut R) -> usize {
+        self.metadata_bytes.sample(rng).round() as usize
+    }
+
+    /// None if no max gas amounts were recorded. Never below the most gas a sampled
+    /// transaction used, so that replayed transfers do not run out of gas
+    pub fn sample_max_gas_amount<R: Rng>(&self, rng: &mut R) -> Option<u64> {
+        if self.max_gas_amount.percentiles.is_empty() {
+            return None;
+        }
+        let max_gas = self
+            .max_gas_amount
+            .sample(rng)
+            .max(self.gas_used.percentile(100));
+        Some(max_gas.round() as u64)
+    }
+}
+
+fn is_user(txn: &TransactionView) -> bool {
+    matches!(txn.transaction, TransactionDataView::UserTransaction { .. })
+}
+
+fn script_type(script: &ScriptView) -> String {
+    match script {
+        ScriptView::PeerToPeer { .. } => "peer_to_peer_transaction",
+        ScriptView::Mint { .. } => "mint_transaction",
+        ScriptView::Unknown {} => "unknown_transaction",
+    }
+    .to_string()
+}
+
+impl fmt::Display for WorkloadProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} transactions over {:.0} secs, avg {:.1} txn/s, peak {:.0} txn/s",
+            self.sampled, self.window_secs, self.avg_tps, self.peak_tps
+        )?;
+        for (script_type, share) in &self.script_types {
+            writeln!(f, "  {}: {:.1}%", script_type, share * 100.0)?;
+        }
+        for (name, distribution) in &[
+            ("metadata bytes", &self.metadata_bytes),
+            ("gas used", &self.gas_used),
+            ("max gas amount", &self.max_gas_amount),
+            ("inter-arrival ms", &self.inter_arrival_ms),
+        ] {
+            writeln!(
+                f,
+                "  {}: p50 {:.1}, p90 {:.1}, p99 {:.1}",
+                name,
+                distribution.percentile(50),
+                distribution.percentile(90),
+                distribution.percentile(99)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libra_json_rpc_client::views::{BytesView, VMStatusView};
+
+    fn txn(transaction: TransactionDataView) -> TransactionView {
+        TransactionView {
+            version: 0,
+            transaction,
+            hash: String::new(),
+            events: vec![],
+            vm_status: VMStatusView::Executed,
+            gas_used: 100,
+        }
+    }
+
+    fn block(secs: u64) -> TransactionView {
+        txn(TransactionDataView::BlockMetadata {
+            timestamp_usecs: secs * 1_000_000,
+        })
+    }
+
+    fn user(script: ScriptView) -> TransactionView {
+        txn(TransactionDataView::UserTransaction {
+            sender: String::new(),
+            signature_scheme: String::new(),
+            signature: String::new(),
+            public_key: String::new(),
+            sequence_number: 0,
+            chain_id: 0,
+            max_gas_amount: 1_000_000,
+            gas_unit_price: 0,
+            gas_currency: String::new(),
+            expiration_timestamp_secs: 0,
+            script_hash: String::new(),
+            script,
+        })
+    }
+
+    fn transfer(metadata_len: usize) -> TransactionView {
+        user(ScriptView::PeerToPeer {
+            receiver: String::new(),
+            amount: 1,
+            currency: String::new(),
+            metadata: BytesView::from(&vec![0u8; metadata_len][..]),
+            metadata_signature: BytesView::from(&[0u8; 0][..]),
+        })
+    }
+
+    #[test]
+    fn test_from_transactions() {
+        let txns = vec![
+            transfer(100),
+            block(10),
+            block(11),
+            transfer(0),
+            transfer(16),
+            block(12),
+            block(14),
+            user(ScriptView::Unknown {}),
+            transfer(16),
+            block(15),
+            transfer(16),
+        ];
+        let profile = WorkloadProfile::from_transactions(&txns).unwrap();
+        assert_eq!(profile.sampled, 5);
+        assert_eq!(profile.window_secs, 4.0);
+        assert_eq!(profile.peak_tps, 2.0);
+        assert_eq!(profile.script_types["peer_to_peer_transaction"], 0.8);
+        assert!((profile.unsupported_share() - 0.2).abs() < 1e-9);
+        assert_eq!(profile.metadata_bytes.percentile(0), 0.0);
+        assert_eq!(profile.metadata_bytes.percentile(100), 16.0);
+        // Empty block at 12 secs does not shorten the gap of the block at 14 secs
+        assert_eq!(profile.inter_arrival_ms.percentile(0), 1000.0);
+        assert_eq!(profile.inter_arrival_ms.percentile(100), 1500.0);
+        assert_eq!(
+            profile.sample_max_gas_amount(&mut rand::thread_rng()),
+            Some(1_000_000)
+        );
+    }
+}